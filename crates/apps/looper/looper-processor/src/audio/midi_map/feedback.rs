@@ -0,0 +1,178 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Sends the values of mapped entities back to controllers, so LEDs, encoder rings and motorised
+//! faders reflect the looper's state.
+//!
+//! * Control-change mappings send the value scaled back into 0-127
+//! * Note mappings send note-on with velocity 127 when the value is past half of the mapping's
+//!   range and velocity 0 otherwise
+//! * Program-change mappings can't be displayed and are skipped
+//!
+//! The record button is considered on while the active looper is recording or overdubbing.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use basedrop::Shared;
+
+use crate::audio::processor::handle::LooperState;
+use crate::parameters::EntityId;
+use crate::MultiTrackLooperHandle;
+
+use super::{MidiMapping, MidiMappingKey, MidiMessageKind};
+
+pub trait MidiFeedbackOutput {
+    fn send_feedback(&mut self, message: &[u8]);
+}
+
+impl MidiFeedbackOutput for midir::MidiOutputConnection {
+    fn send_feedback(&mut self, message: &[u8]) {
+        if let Err(err) = self.send(message) {
+            log::warn!("Failed to send MIDI feedback {}", err);
+        }
+    }
+}
+
+/// Build the feedback message for a mapping, `None` if it can't be displayed
+pub fn feedback_message(
+    handle: &MultiTrackLooperHandle,
+    key: &MidiMappingKey,
+    mapping: &MidiMapping,
+) -> Option<[u8; 3]> {
+    let value = match &mapping.entity_id {
+        EntityId::EntityIdLooperParameter(looper_id, parameter_id) => {
+            let value = handle.get_parameter(*looper_id, parameter_id)?;
+            mapping.unscale(value.to_f32())
+        }
+        EntityId::EntityIdRecordButton => {
+            let state = handle.get_looper_state(handle.active_looper());
+            if matches!(state, LooperState::Recording | LooperState::Overdubbing) {
+                127
+            } else {
+                0
+            }
+        }
+    };
+    let channel = key.channel.unwrap_or(0) & 0x0F;
+
+    match key.kind {
+        MidiMessageKind::ControlChange => Some([0xB0 | channel, key.number, value]),
+        MidiMessageKind::Note => Some([
+            0x90 | channel,
+            key.number,
+            if value >= 64 { 127 } else { 0 },
+        ]),
+        MidiMessageKind::ProgramChange => None,
+    }
+}
+
+/// Tracks the feedback sent to a controller, so only changes are sent
+#[derive(Default)]
+pub struct MidiFeedback {
+    sent: HashMap<MidiMappingKey, [u8; 3]>,
+}
+
+impl MidiFeedback {
+    pub fn tick(&mut self, handle: &MultiTrackLooperHandle, output: &mut dyn MidiFeedbackOutput) {
+        let store = handle.midi().midi_map().store().get();
+        for (key, mapping) in store.iter() {
+            if let Some(message) = feedback_message(handle, key, mapping) {
+                if self.sent.get(key) != Some(&message) {
+                    output.send_feedback(&message);
+                    self.sent.insert(*key, message);
+                }
+            }
+        }
+        self.sent.retain(|key, _| store.contains_key(key));
+    }
+}
+
+/// Send feedback to `output` every `interval` until `is_running` is unset
+pub fn spawn_midi_feedback_thread(
+    handle: Shared<MultiTrackLooperHandle>,
+    mut output: impl MidiFeedbackOutput + Send + 'static,
+    is_running: Shared<AtomicBool>,
+    interval: Duration,
+) -> std::io::Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name(String::from("midi-feedback-thread"))
+        .spawn(move || {
+            let mut feedback = MidiFeedback::default();
+            while is_running.load(Ordering::Relaxed) {
+                feedback.tick(&handle, &mut output);
+                std::thread::sleep(interval);
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parameters::SourceParameter;
+    use crate::{LooperId, MultiTrackLooper};
+
+    use super::*;
+
+    impl MidiFeedbackOutput for Vec<Vec<u8>> {
+        fn send_feedback(&mut self, message: &[u8]) {
+            self.push(message.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_feedback_sends_changes() {
+        let looper = MultiTrackLooper::new(Default::default(), 2);
+        let handle = looper.handle();
+        let midi_map = handle.midi().midi_map();
+        midi_map.add_mapping(
+            MidiMappingKey::control_change(Some(1), 20),
+            MidiMapping::new(EntityId::EntityIdLooperParameter(
+                LooperId(1),
+                SourceParameter::Speed.into(),
+            ))
+            .with_range(0.0, 2.0),
+        );
+        midi_map.add_mapping(
+            MidiMappingKey::note(None, 60),
+            EntityId::EntityIdRecordButton.into(),
+        );
+        midi_map.add_mapping(
+            MidiMappingKey::program_change(None, 1),
+            EntityId::EntityIdRecordButton.into(),
+        );
+
+        let mut feedback = MidiFeedback::default();
+        let mut output: Vec<Vec<u8>> = vec![];
+        feedback.tick(handle, &mut output);
+        output.sort();
+        assert_eq!(output, vec![vec![0x90, 60, 0], vec![0xB1, 20, 64]]);
+
+        output.clear();
+        feedback.tick(handle, &mut output);
+        assert!(output.is_empty());
+
+        handle.set_source_parameter(LooperId(1), SourceParameter::Speed, 2.0);
+        feedback.tick(handle, &mut output);
+        assert_eq!(output, vec![vec![0xB1, 20, 127]]);
+    }
+}
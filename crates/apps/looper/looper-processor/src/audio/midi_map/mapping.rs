@@ -0,0 +1,345 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use serde::{Deserialize, Serialize};
+
+use augmented_atomics::AtomicF32;
+
+use crate::parameters::EntityId;
+
+/// Fraction of a mapping's range within which a soft-takeover controller picks up the parameter
+const SOFT_TAKEOVER_TOLERANCE: f32 = 2.0 / 127.0;
+
+/// How control-change values are interpreted
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MidiEncoderMode {
+    /// The value is the controller's position
+    #[default]
+    Absolute = 0,
+    /// Endless encoders sending 1-63 to increment and 65-127 to decrement, where 127 is -1
+    RelativeTwosComplement = 1,
+    /// Endless encoders sending values above 64 to increment and below 64 to decrement
+    RelativeBinaryOffset = 2,
+    /// Endless encoders where bit 6 is the sign, 1-63 increment and 65-127 decrement, where 65
+    /// is -1
+    RelativeSignedBit = 3,
+}
+
+impl MidiEncoderMode {
+    /// Number of steps a relative encoder moved. `None` for absolute controllers.
+    pub fn delta(&self, value: u8) -> Option<i32> {
+        let value = (value & 0x7F) as i32;
+        match self {
+            MidiEncoderMode::Absolute => None,
+            MidiEncoderMode::RelativeTwosComplement => {
+                Some(if value < 64 { value } else { value - 128 })
+            }
+            MidiEncoderMode::RelativeBinaryOffset => Some(value - 64),
+            MidiEncoderMode::RelativeSignedBit => {
+                Some(if value < 64 { value } else { -(value - 64) })
+            }
+        }
+    }
+}
+
+/// A MIDI event, as seen by a mapping
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MidiMappingInput {
+    ControlChange(u8),
+    NoteOn(u8),
+    NoteOff,
+    ProgramChange,
+}
+
+/// Soft-takeover state. This isn't persisted.
+#[derive(Debug)]
+struct MidiMappingState {
+    /// Last value this mapping set, NaN if none
+    last_sent: AtomicF32,
+    /// Last value the controller was at, NaN if none
+    last_input: AtomicF32,
+}
+
+impl Default for MidiMappingState {
+    fn default() -> Self {
+        Self {
+            last_sent: AtomicF32::new(f32::NAN),
+            last_input: AtomicF32::new(f32::NAN),
+        }
+    }
+}
+
+/// Maps a MIDI message onto an [`EntityId`].
+///
+/// * Control-changes set the value within `min` and `max`, either as absolute positions or as
+///   relative steps, depending on `encoder_mode`
+/// * Note-on sets `max` and note-off sets `min`, so notes work as momentary buttons
+/// * Program-changes set `max`
+///
+/// `min` may be greater than `max` to invert the controller.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub entity_id: EntityId,
+    /// How control-change values are interpreted. Defaults to absolute.
+    pub encoder_mode: MidiEncoderMode,
+    /// Value when the controller is at 0. Defaults to 0.
+    pub min: f32,
+    /// Value when the controller is at 127. Defaults to 1.
+    pub max: f32,
+    /// If set, absolute controllers are ignored until they reach the parameter's current value,
+    /// so parameters changed elsewhere don't jump when the controller is moved. Defaults to
+    /// false.
+    pub soft_takeover: bool,
+    #[serde(skip)]
+    state: MidiMappingState,
+}
+
+impl Clone for MidiMapping {
+    fn clone(&self) -> Self {
+        Self {
+            entity_id: self.entity_id.clone(),
+            encoder_mode: self.encoder_mode,
+            min: self.min,
+            max: self.max,
+            soft_takeover: self.soft_takeover,
+            state: MidiMappingState {
+                last_sent: self.state.last_sent.get().into(),
+                last_input: self.state.last_input.get().into(),
+            },
+        }
+    }
+}
+
+impl PartialEq for MidiMapping {
+    fn eq(&self, other: &Self) -> bool {
+        self.entity_id == other.entity_id
+            && self.encoder_mode == other.encoder_mode
+            && self.min == other.min
+            && self.max == other.max
+            && self.soft_takeover == other.soft_takeover
+    }
+}
+
+impl From<EntityId> for MidiMapping {
+    fn from(entity_id: EntityId) -> Self {
+        Self::new(entity_id)
+    }
+}
+
+impl MidiMapping {
+    pub fn new(entity_id: EntityId) -> Self {
+        Self {
+            entity_id,
+            encoder_mode: MidiEncoderMode::default(),
+            min: 0.0,
+            max: 1.0,
+            soft_takeover: false,
+            state: MidiMappingState::default(),
+        }
+    }
+
+    pub fn with_encoder_mode(mut self, encoder_mode: MidiEncoderMode) -> Self {
+        self.encoder_mode = encoder_mode;
+        self
+    }
+
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub fn with_soft_takeover(mut self, soft_takeover: bool) -> Self {
+        self.soft_takeover = soft_takeover;
+        self
+    }
+
+    /// Compute the value an input sets, given the entity's `current` value. Returns `None` if the
+    /// input should be ignored.
+    pub fn value(&self, input: MidiMappingInput, current: Option<f32>) -> Option<f32> {
+        match input {
+            MidiMappingInput::ControlChange(value) => match self.encoder_mode.delta(value) {
+                Some(delta) => {
+                    let step = (self.max - self.min) / 127.0;
+                    let current = current.unwrap_or(self.min);
+                    Some(self.clamp(current + delta as f32 * step))
+                }
+                None => {
+                    let target = self.scale(value);
+                    if self.soft_takeover && !self.soft_takeover_accepts(current, target) {
+                        return None;
+                    }
+                    self.state.last_sent.set(target);
+                    Some(target)
+                }
+            },
+            MidiMappingInput::NoteOn(_) | MidiMappingInput::ProgramChange => Some(self.max),
+            MidiMappingInput::NoteOff => Some(self.min),
+        }
+    }
+
+    /// Scale a 0-127 controller value into the mapping's range
+    pub fn scale(&self, value: u8) -> f32 {
+        self.min + (value.min(127) as f32 / 127.0) * (self.max - self.min)
+    }
+
+    /// Convert a value within the mapping's range back into 0-127, for controller feedback
+    pub fn unscale(&self, value: f32) -> u8 {
+        let range = self.max - self.min;
+        if range == 0.0 {
+            return 0;
+        }
+        let normalized = ((value - self.min) / range).clamp(0.0, 1.0);
+        (normalized * 127.0).round() as u8
+    }
+
+    fn clamp(&self, value: f32) -> f32 {
+        value.clamp(self.min.min(self.max), self.min.max(self.max))
+    }
+
+    /// The controller picks up the parameter if the parameter hasn't changed since the mapping
+    /// last set it, if the controller is close enough to the parameter or if the controller
+    /// crossed the parameter's value since the last input.
+    fn soft_takeover_accepts(&self, current: Option<f32>, target: f32) -> bool {
+        let last_input = self.state.last_input.get();
+        self.state.last_input.set(target);
+
+        let current = match current {
+            Some(current) => current,
+            None => return true,
+        };
+        let tolerance = (self.max - self.min).abs() * SOFT_TAKEOVER_TOLERANCE;
+        let last_sent = self.state.last_sent.get();
+
+        (current - last_sent).abs() <= tolerance
+            || (target - current).abs() <= tolerance
+            || (!last_input.is_nan() && (last_input - current) * (target - current) <= 0.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
+
+    use crate::parameters::SourceParameter;
+    use crate::LooperId;
+
+    use super::*;
+
+    fn mapping() -> MidiMapping {
+        MidiMapping::new(EntityId::EntityIdLooperParameter(
+            LooperId(0),
+            SourceParameter::Speed.into(),
+        ))
+    }
+
+    #[test]
+    fn test_relative_encoder_deltas() {
+        assert_eq!(MidiEncoderMode::Absolute.delta(10), None);
+        assert_eq!(MidiEncoderMode::RelativeTwosComplement.delta(1), Some(1));
+        assert_eq!(MidiEncoderMode::RelativeTwosComplement.delta(127), Some(-1));
+        assert_eq!(MidiEncoderMode::RelativeBinaryOffset.delta(65), Some(1));
+        assert_eq!(MidiEncoderMode::RelativeBinaryOffset.delta(63), Some(-1));
+        assert_eq!(MidiEncoderMode::RelativeSignedBit.delta(3), Some(3));
+        assert_eq!(MidiEncoderMode::RelativeSignedBit.delta(65), Some(-1));
+    }
+
+    #[test]
+    fn test_absolute_values_are_scaled() {
+        let mapping = mapping().with_range(0.5, 2.0);
+        assert_eq!(
+            mapping.value(MidiMappingInput::ControlChange(0), None),
+            Some(0.5)
+        );
+        assert_eq!(
+            mapping.value(MidiMappingInput::ControlChange(127), None),
+            Some(2.0)
+        );
+        assert_eq!(mapping.unscale(2.0), 127);
+        assert_eq!(mapping.unscale(0.0), 0);
+
+        let inverted = mapping.with_range(1.0, 0.0);
+        assert_eq!(
+            inverted.value(MidiMappingInput::ControlChange(127), None),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn test_relative_values_are_clamped() {
+        let mapping = mapping().with_encoder_mode(MidiEncoderMode::RelativeTwosComplement);
+        let value = mapping
+            .value(MidiMappingInput::ControlChange(2), Some(0.5))
+            .unwrap();
+        assert!((value - (0.5 + 2.0 / 127.0)).abs() < f32::EPSILON);
+        assert_eq!(
+            mapping.value(MidiMappingInput::ControlChange(127), Some(0.0)),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn test_notes_and_programs() {
+        let mapping = mapping().with_range(0.2, 0.8);
+        assert_eq!(
+            mapping.value(MidiMappingInput::NoteOn(100), None),
+            Some(0.8)
+        );
+        assert_eq!(mapping.value(MidiMappingInput::NoteOff, None), Some(0.2));
+        assert_eq!(
+            mapping.value(MidiMappingInput::ProgramChange, None),
+            Some(0.8)
+        );
+    }
+
+    #[test]
+    fn test_soft_takeover_waits_for_the_controller_to_cross_the_value() {
+        let mapping = mapping().with_soft_takeover(true);
+        assert_realtime_safe(|| {
+            // Parameter is at 0.5, the controller at 0
+            assert_eq!(
+                mapping.value(MidiMappingInput::ControlChange(0), Some(0.5)),
+                None
+            );
+            assert_eq!(
+                mapping.value(MidiMappingInput::ControlChange(30), Some(0.5)),
+                None
+            );
+            // The controller crossed the parameter's value
+            let value = mapping
+                .value(MidiMappingInput::ControlChange(70), Some(0.5))
+                .unwrap();
+            assert_eq!(value, mapping.scale(70));
+            // Picked up
+            assert_eq!(
+                mapping.value(MidiMappingInput::ControlChange(10), Some(value)),
+                Some(mapping.scale(10))
+            );
+            // The parameter was changed elsewhere, so it has to be picked up again
+            assert_eq!(
+                mapping.value(MidiMappingInput::ControlChange(11), Some(1.0)),
+                None
+            );
+        });
+    }
+}
@@ -0,0 +1,484 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Maps MIDI messages onto looper entities.
+//!
+//! * [`MidiMappingKey`] identifies a control-change, note or program-change message, optionally
+//!   on a specific channel
+//! * [`MidiMapping`] is what a key controls and how its values are scaled
+//! * [`MidiMap`] holds the mappings and MIDI learn state, it is read from the audio-thread
+//! * [`feedback`] sends the mapped values back to controllers
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use audio_garbage_collector::{Handle, Shared, SharedCell};
+
+use crate::parameters::EntityId;
+
+pub use self::mapping::{MidiEncoderMode, MidiMapping, MidiMappingInput};
+
+pub mod feedback;
+mod mapping;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MidiControllerNumber {
+    controller_number: u8,
+}
+
+impl MidiControllerNumber {
+    #[allow(dead_code)]
+    pub fn new(number: u8) -> Self {
+        MidiControllerNumber {
+            controller_number: number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_midi_spec {
+    use super::*;
+
+    #[test]
+    fn test_create_midi_spec() {
+        let spec = MidiControllerNumber::new(88);
+        assert_eq!(spec.controller_number, 88);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MidiMessageKind {
+    ControlChange = 0,
+    Note = 1,
+    ProgramChange = 2,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MidiMappingKey {
+    pub kind: MidiMessageKind,
+    /// Channel the message must be sent on, `None` matches any channel
+    pub channel: Option<u8>,
+    /// Controller, note or program number
+    pub number: u8,
+}
+
+impl MidiMappingKey {
+    pub fn control_change(channel: Option<u8>, controller_number: u8) -> Self {
+        Self {
+            kind: MidiMessageKind::ControlChange,
+            channel,
+            number: controller_number,
+        }
+    }
+
+    pub fn note(channel: Option<u8>, note: u8) -> Self {
+        Self {
+            kind: MidiMessageKind::Note,
+            channel,
+            number: note,
+        }
+    }
+
+    pub fn program_change(channel: Option<u8>, program_number: u8) -> Self {
+        Self {
+            kind: MidiMessageKind::ProgramChange,
+            channel,
+            number: program_number,
+        }
+    }
+
+    /// The same key, matching any channel
+    pub fn omni(&self) -> Self {
+        Self {
+            channel: None,
+            ..*self
+        }
+    }
+}
+
+impl From<MidiControllerNumber> for MidiMappingKey {
+    fn from(spec: MidiControllerNumber) -> Self {
+        Self::control_change(None, spec.controller_number)
+    }
+}
+
+pub type MidiMapStorePersist = HashMap<MidiMappingKey, MidiMapping>;
+pub type MidiMapStore = SharedCell<MidiMapStorePersist>;
+
+/// Project files used to map bare controller numbers onto entities
+type LegacyMidiMapStorePersist = HashMap<MidiControllerNumber, EntityId>;
+
+/// Deserialize a [`MidiMapStorePersist`], converting mappings from older project files
+pub fn deserialize_midi_map_store<'de, D>(deserializer: D) -> Result<MidiMapStorePersist, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Format {
+        Current(MidiMapStorePersist),
+        Legacy(LegacyMidiMapStorePersist),
+    }
+
+    Ok(match Format::deserialize(deserializer)? {
+        Format::Current(store) => store,
+        Format::Legacy(store) => store
+            .into_iter()
+            .map(|(spec, entity_id)| (spec.into(), entity_id.into()))
+            .collect(),
+    })
+}
+
+/// Find the mapping for a message received on a specific channel. Mappings on the message's
+/// channel take precedence over mappings on any channel.
+pub fn find_mapping<'a>(
+    store: &'a MidiMapStorePersist,
+    key: &MidiMappingKey,
+) -> Option<&'a MidiMapping> {
+    store.get(key).or_else(|| store.get(&key.omni()))
+}
+
+/// MIDI learn state.
+///
+/// The audio-thread only touches the atomics, recording the first message it receives while
+/// learning. The mapping is created when [`MidiMap::poll_learn`] is called from another thread.
+#[derive(Default)]
+struct MidiLearnState {
+    is_learning: AtomicBool,
+    /// Packed [`MidiMappingKey`], 0 if nothing was received
+    learned_key: AtomicU32,
+    target: Mutex<Option<MidiMapping>>,
+}
+
+const LEARNED_KEY_FLAG: u32 = 1 << 31;
+
+fn pack_learned_key(key: &MidiMappingKey) -> u32 {
+    LEARNED_KEY_FLAG
+        | (key.kind as u32) << 16
+        | (key.channel.unwrap_or(0) as u32) << 8
+        | key.number as u32
+}
+
+fn unpack_learned_key(packed: u32) -> Option<MidiMappingKey> {
+    if packed & LEARNED_KEY_FLAG == 0 {
+        return None;
+    }
+    let kind = match (packed >> 16) & 0xFF {
+        0 => MidiMessageKind::ControlChange,
+        1 => MidiMessageKind::Note,
+        _ => MidiMessageKind::ProgramChange,
+    };
+    Some(MidiMappingKey {
+        kind,
+        channel: Some(((packed >> 8) & 0xFF) as u8),
+        number: (packed & 0xFF) as u8,
+    })
+}
+
+pub struct MidiMap {
+    #[allow(dead_code)]
+    store: MidiMapStore,
+    handle: Handle,
+    learn: MidiLearnState,
+}
+
+impl Default for MidiMap {
+    fn default() -> Self {
+        Self::new_with_handle(audio_garbage_collector::handle())
+    }
+}
+
+impl MidiMap {
+    #[allow(dead_code)]
+    pub fn new(handle: &Handle, store: SharedCell<MidiMapStorePersist>) -> Self {
+        MidiMap {
+            handle: handle.clone(),
+            store,
+            learn: MidiLearnState::default(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn new_with_handle(handle: &Handle) -> Self {
+        MidiMap {
+            handle: handle.clone(),
+            store: SharedCell::new(Shared::new(handle, Default::default())),
+            learn: MidiLearnState::default(),
+        }
+    }
+
+    /// Map a controller number, on any channel, onto an entity
+    #[allow(dead_code)]
+    pub fn add(&self, spec: MidiControllerNumber, action: EntityId) {
+        self.add_mapping(spec.into(), action.into());
+    }
+
+    pub fn add_mapping(&self, key: MidiMappingKey, mapping: MidiMapping) {
+        let mut current = (*self.store.get()).clone();
+        current.insert(key, mapping);
+        self.store.set(Shared::new(&self.handle, current));
+    }
+
+    pub fn remove_mapping(&self, key: &MidiMappingKey) {
+        let mut current = (*self.store.get()).clone();
+        current.remove(key);
+        self.store.set(Shared::new(&self.handle, current));
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, spec: &MidiControllerNumber) -> Option<EntityId> {
+        self.get_mapping(&(*spec).into())
+            .map(|mapping| mapping.entity_id)
+    }
+
+    pub fn get_mapping(&self, key: &MidiMappingKey) -> Option<MidiMapping> {
+        find_mapping(self.store.get().deref(), key).cloned()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.store.get().deref().is_empty()
+    }
+
+    pub fn store(&self) -> &MidiMapStore {
+        &self.store
+    }
+
+    // MARK: MIDI learn
+
+    /// Map the next MIDI message received onto `mapping`. Learning ends once
+    /// [`MidiMap::poll_learn`] picks up the message.
+    pub fn start_learn(&self, mapping: MidiMapping) {
+        *self.learn.target.lock().unwrap() = Some(mapping);
+        self.learn.learned_key.store(0, Ordering::Release);
+        self.learn.is_learning.store(true, Ordering::Release);
+    }
+
+    pub fn cancel_learn(&self) {
+        self.learn.is_learning.store(false, Ordering::Release);
+        self.learn.learned_key.store(0, Ordering::Release);
+        *self.learn.target.lock().unwrap() = None;
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learn.is_learning.load(Ordering::Acquire)
+            || self.learn.learned_key.load(Ordering::Acquire) != 0
+    }
+
+    /// Called from the audio-thread with every incoming message. While learning, records the
+    /// message and returns true, in which case it shouldn't trigger any mappings.
+    pub fn learn(&self, key: &MidiMappingKey) -> bool {
+        if !self.learn.is_learning.load(Ordering::Acquire) {
+            return false;
+        }
+        self.learn
+            .learned_key
+            .store(pack_learned_key(key), Ordering::Release);
+        self.learn.is_learning.store(false, Ordering::Release);
+        true
+    }
+
+    /// If a message was received while learning, map it and return its key. Existing mappings
+    /// for the same entity are replaced.
+    pub fn poll_learn(&self) -> Option<MidiMappingKey> {
+        let key = unpack_learned_key(self.learn.learned_key.swap(0, Ordering::AcqRel))?;
+        let mapping = self.learn.target.lock().unwrap().take()?;
+
+        log::info!(
+            "Learned MIDI mapping key={:?} entity_id={:?}",
+            key,
+            mapping.entity_id
+        );
+        let mut current = (*self.store.get()).clone();
+        current.retain(|_, existing| existing.entity_id != mapping.entity_id);
+        current.insert(key, mapping);
+        self.store.set(Shared::new(&self.handle, current));
+
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod test_midi_map {
+    use audio_garbage_collector::Shared;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
+    use serde::Serialize;
+
+    use crate::parameters::SourceParameter;
+    use crate::LooperId;
+
+    use super::*;
+
+    #[test]
+    fn test_create_midi_map_with_handle() {
+        let midi_map = MidiMap::new_with_handle(audio_garbage_collector::handle());
+        assert!(midi_map.is_empty());
+    }
+
+    #[test]
+    fn test_create_midi_map() {
+        let store = SharedCell::new(Shared::new(
+            audio_garbage_collector::handle(),
+            Default::default(),
+        ));
+        let midi_map = MidiMap::new(audio_garbage_collector::handle(), store);
+        assert!(midi_map.is_empty());
+    }
+
+    #[test]
+    fn test_add_entry() {
+        let store = SharedCell::new(Shared::new(
+            audio_garbage_collector::handle(),
+            Default::default(),
+        ));
+        let midi_map = MidiMap::new(audio_garbage_collector::handle(), store);
+
+        assert!(midi_map.is_empty());
+        let spec = MidiControllerNumber::new(88);
+        midi_map.add(
+            spec,
+            EntityId::EntityIdLooperParameter(LooperId(0), SourceParameter::Start.into()),
+        );
+        assert!(!midi_map.is_empty());
+        assert!(midi_map.get(&spec).is_some());
+        assert_eq!(
+            midi_map.get(&spec).unwrap(),
+            EntityId::EntityIdLooperParameter(LooperId(0), SourceParameter::Start.into())
+        );
+    }
+
+    #[test]
+    fn test_channel_mappings_take_precedence() {
+        let midi_map = MidiMap::default();
+        let start = EntityId::EntityIdLooperParameter(LooperId(0), SourceParameter::Start.into());
+        let end = EntityId::EntityIdLooperParameter(LooperId(0), SourceParameter::End.into());
+        midi_map.add_mapping(
+            MidiMappingKey::control_change(None, 10),
+            start.clone().into(),
+        );
+        midi_map.add_mapping(
+            MidiMappingKey::control_change(Some(2), 10),
+            end.clone().into(),
+        );
+
+        let entity_on_channel = |channel| {
+            midi_map
+                .get_mapping(&MidiMappingKey::control_change(Some(channel), 10))
+                .map(|mapping| mapping.entity_id)
+        };
+        assert_eq!(entity_on_channel(0), Some(start));
+        assert_eq!(entity_on_channel(2), Some(end));
+        assert!(midi_map
+            .get_mapping(&MidiMappingKey::note(Some(0), 10))
+            .is_none());
+
+        midi_map.remove_mapping(&MidiMappingKey::control_change(Some(2), 10));
+        assert_eq!(midi_map.store().get().len(), 1);
+    }
+
+    #[test]
+    fn test_learn() {
+        let midi_map = MidiMap::default();
+        let start = EntityId::EntityIdLooperParameter(LooperId(0), SourceParameter::Start.into());
+        midi_map.add_mapping(
+            MidiMappingKey::control_change(None, 1),
+            start.clone().into(),
+        );
+        assert!(!midi_map.learn(&MidiMappingKey::control_change(Some(0), 2)));
+
+        midi_map.start_learn(MidiMapping::new(start.clone()).with_soft_takeover(true));
+        assert!(midi_map.is_learning());
+        assert!(midi_map.poll_learn().is_none());
+
+        let key = MidiMappingKey::note(Some(3), 60);
+        assert_realtime_safe(|| {
+            assert!(midi_map.learn(&key));
+            assert!(!midi_map.learn(&MidiMappingKey::note(Some(3), 61)));
+        });
+        assert_eq!(midi_map.poll_learn(), Some(key));
+        assert!(!midi_map.is_learning());
+
+        let store = midi_map.store().get();
+        assert_eq!(store.len(), 1);
+        assert_eq!(
+            store.get(&key),
+            Some(&MidiMapping::new(start).with_soft_takeover(true))
+        );
+    }
+
+    #[test]
+    fn test_cancel_learn() {
+        let midi_map = MidiMap::default();
+        midi_map.start_learn(MidiMapping::new(EntityId::EntityIdRecordButton));
+        midi_map.cancel_learn();
+        assert!(!midi_map.learn(&MidiMappingKey::note(Some(0), 60)));
+        assert!(midi_map.poll_learn().is_none());
+        assert!(midi_map.is_empty());
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Project {
+        #[serde(deserialize_with = "deserialize_midi_map_store")]
+        midi_map: MidiMapStorePersist,
+    }
+
+    #[test]
+    fn test_persistence_round_trip() {
+        let mut store = MidiMapStorePersist::default();
+        store.insert(
+            MidiMappingKey::program_change(Some(1), 5),
+            MidiMapping::new(EntityId::EntityIdRecordButton).with_range(1.0, 0.0),
+        );
+        let bytes = rmp_serde::to_vec(&Project {
+            midi_map: store.clone(),
+        })
+        .unwrap();
+        let project: Project = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(project.midi_map, store);
+    }
+
+    #[test]
+    fn test_load_legacy_projects() {
+        #[derive(Serialize)]
+        struct LegacyProject {
+            midi_map: LegacyMidiMapStorePersist,
+        }
+
+        let mut legacy = LegacyMidiMapStorePersist::default();
+        legacy.insert(
+            MidiControllerNumber::new(20),
+            EntityId::EntityIdRecordButton,
+        );
+        let bytes = rmp_serde::to_vec(&LegacyProject { midi_map: legacy }).unwrap();
+        let project: Project = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(
+            project
+                .midi_map
+                .get(&MidiMappingKey::control_change(None, 20)),
+            Some(&MidiMapping::new(EntityId::EntityIdRecordButton))
+        );
+    }
+}
@@ -26,6 +26,15 @@
 /// This is disabled on iOS for now, because due to variable buffer sizes the graph processor will
 /// always allocate on its first run (TODO: we should figure out how to estimate the maximum size or
 /// avoid having to resize)
-#[cfg(all(debug_assertions, not(target_os = "ios"), not(target_os = "linux")))]
+#[cfg(all(
+    debug_assertions,
+    not(test),
+    not(target_os = "ios"),
+    not(target_os = "linux")
+))]
 #[global_allocator]
 static A: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
+
+// Tests check real-time sections with the testing helpers' allocator instead, on every platform
+#[cfg(test)]
+audio_processor_testing_helpers::install_realtime_allocator!();
@@ -0,0 +1,262 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use basedrop::Shared;
+
+use audio_garbage_collector::make_shared;
+use audio_processor_traits::parameters::{
+    make_handle_ref, AudioProcessorHandle, AudioProcessorHandleProvider, AudioProcessorHandleRef,
+    FloatType, ParameterSpec, ParameterType, ParameterValue,
+};
+use audio_processor_traits::simple_processor::MonoAudioProcessor;
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+use augmented_atomics::AtomicF32;
+use augmented_dsp_filters::rbj::{FilterProcessor, FilterType};
+
+const LOW_SHELF_FREQUENCY: f32 = 250.0;
+const HIGH_SHELF_FREQUENCY: f32 = 4000.0;
+
+pub struct EqualizerHandle {
+    low_gain_db: AtomicF32,
+    mid_gain_db: AtomicF32,
+    mid_frequency: AtomicF32,
+    high_gain_db: AtomicF32,
+}
+
+impl Default for EqualizerHandle {
+    fn default() -> Self {
+        Self {
+            low_gain_db: 0.0.into(),
+            mid_gain_db: 0.0.into(),
+            mid_frequency: 1000.0.into(),
+            high_gain_db: 0.0.into(),
+        }
+    }
+}
+
+impl EqualizerHandle {
+    /// Gain of the low shelf. Defaults to 0dB
+    pub fn low_gain_db(&self) -> f32 {
+        self.low_gain_db.get()
+    }
+
+    pub fn set_low_gain_db(&self, value: f32) {
+        self.low_gain_db.set(value);
+    }
+
+    /// Gain of the mid band. Defaults to 0dB
+    pub fn mid_gain_db(&self) -> f32 {
+        self.mid_gain_db.get()
+    }
+
+    pub fn set_mid_gain_db(&self, value: f32) {
+        self.mid_gain_db.set(value);
+    }
+
+    /// Center of the mid band. Defaults to 1kHz
+    pub fn mid_frequency(&self) -> f32 {
+        self.mid_frequency.get()
+    }
+
+    pub fn set_mid_frequency(&self, value: f32) {
+        self.mid_frequency.set(value);
+    }
+
+    /// Gain of the high shelf. Defaults to 0dB
+    pub fn high_gain_db(&self) -> f32 {
+        self.high_gain_db.get()
+    }
+
+    pub fn set_high_gain_db(&self, value: f32) {
+        self.high_gain_db.set(value);
+    }
+
+    fn values(&self) -> [f32; 4] {
+        [
+            self.low_gain_db(),
+            self.mid_gain_db(),
+            self.mid_frequency(),
+            self.high_gain_db(),
+        ]
+    }
+}
+
+/// Low-shelf, mid-band & high-shelf filters for one channel
+struct EqualizerBands {
+    low: FilterProcessor<f32>,
+    mid: FilterProcessor<f32>,
+    high: FilterProcessor<f32>,
+}
+
+impl Default for EqualizerBands {
+    fn default() -> Self {
+        let mut low = FilterProcessor::new(FilterType::LowShelf);
+        low.set_cutoff(LOW_SHELF_FREQUENCY);
+        let mut mid = FilterProcessor::new(FilterType::BandShelf);
+        mid.set_band_width(1.0);
+        let mut high = FilterProcessor::new(FilterType::HighShelf);
+        high.set_cutoff(HIGH_SHELF_FREQUENCY);
+        Self { low, mid, high }
+    }
+}
+
+impl EqualizerBands {
+    fn set_values(&mut self, [low_gain_db, mid_gain_db, mid_frequency, high_gain_db]: [f32; 4]) {
+        self.low.set_gain_db(low_gain_db);
+        self.mid.set_gain_db(mid_gain_db);
+        self.mid.set_center_frequency(mid_frequency);
+        self.high.set_gain_db(high_gain_db);
+    }
+}
+
+/// A three band equalizer built from RBJ shelving filters
+pub struct EqualizerProcessor {
+    handle: Shared<EqualizerHandle>,
+    bands: Vec<EqualizerBands>,
+    /// Values the filters were last set-up with
+    values: [f32; 4],
+}
+
+impl Default for EqualizerProcessor {
+    fn default() -> Self {
+        Self {
+            handle: make_shared(EqualizerHandle::default()),
+            bands: vec![],
+            values: [0.0; 4],
+        }
+    }
+}
+
+impl AudioProcessorHandleProvider for EqualizerProcessor {
+    fn generic_handle(&self) -> AudioProcessorHandleRef {
+        make_handle_ref(GenericHandle(self.handle.clone()))
+    }
+}
+
+impl AudioProcessor for EqualizerProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.values = self.handle.values();
+        self.bands
+            .resize_with(context.settings.output_channels(), Default::default);
+        for bands in &mut self.bands {
+            bands.low.m_prepare(context);
+            bands.mid.m_prepare(context);
+            bands.high.m_prepare(context);
+            bands.set_values(self.values);
+        }
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        let values = self.handle.values();
+        if values != self.values {
+            self.values = values;
+            for bands in &mut self.bands {
+                bands.set_values(values);
+            }
+        }
+
+        for (channel, bands) in data.channels_mut().iter_mut().zip(self.bands.iter_mut()) {
+            for sample in channel.iter_mut() {
+                let output = bands.low.m_process(context, *sample);
+                let output = bands.mid.m_process(context, output);
+                *sample = bands.high.m_process(context, output);
+            }
+        }
+    }
+}
+
+struct GenericHandle(Shared<EqualizerHandle>);
+
+impl AudioProcessorHandle for GenericHandle {
+    fn name(&self) -> String {
+        "EQ".to_string()
+    }
+
+    fn parameter_count(&self) -> usize {
+        4
+    }
+
+    fn get_parameter_spec(&self, index: usize) -> ParameterSpec {
+        let specs: [(&str, (f32, f32)); 4] = [
+            ("Low", (-24.0, 24.0)),
+            ("Mid", (-24.0, 24.0)),
+            ("Mid frequency", (100.0, 10000.0)),
+            ("High", (-24.0, 24.0)),
+        ];
+        let (name, range) = specs[index];
+        ParameterSpec::new(
+            name.into(),
+            ParameterType::Float(FloatType { range, step: None }),
+        )
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<ParameterValue> {
+        self.0.values().get(index).map(|value| (*value).into())
+    }
+
+    fn set_parameter(&self, index: usize, request: ParameterValue) {
+        let ParameterValue::Float { value } = request;
+        match index {
+            0 => self.0.set_low_gain_db(value),
+            1 => self.0.set_mid_gain_db(value),
+            2 => self.0.set_mid_frequency(value),
+            3 => self.0.set_high_gain_db(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::*;
+
+    fn process_dc(processor: &mut EqualizerProcessor) -> f32 {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        processor.prepare(&mut context);
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 4096);
+        for sample in buffer.slice_mut() {
+            *sample = 0.5;
+        }
+        processor.process(&mut context, &mut buffer);
+        *buffer.get(0, 4095)
+    }
+
+    #[test]
+    fn test_flat_equalizer_passes_through() {
+        let mut processor = EqualizerProcessor::default();
+        let output = process_dc(&mut processor);
+        assert!((output - 0.5).abs() < 0.01, "output={}", output);
+    }
+
+    #[test]
+    fn test_low_shelf_boosts_low_frequencies() {
+        let mut processor = EqualizerProcessor::default();
+        processor.handle.set_low_gain_db(12.0);
+        let output = process_dc(&mut processor);
+        assert!(output > 1.5, "output={}", output);
+    }
+}
@@ -24,94 +24,199 @@
 use std::ops::Deref;
 
 use basedrop::{Shared, SharedCell};
+use serde::{Deserialize, Serialize};
 
 use audio_garbage_collector::{make_shared, make_shared_cell};
-use audio_processor_bitcrusher::BitCrusherProcessor;
 use audio_processor_graph::{AudioProcessorGraph, AudioProcessorGraphHandle, NodeIndex, NodeType};
-use audio_processor_time::FreeverbProcessor;
-use audio_processor_time::MonoDelayProcessor;
-use audio_processor_traits::parameters::{AudioProcessorHandleProvider, AudioProcessorHandleRef};
-use audio_processor_traits::simple_processor::MonoCopyProcessor;
-use audio_processor_traits::{
-    simple_processor, AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings,
-};
-use augmented_dsp_filters::rbj::{FilterProcessor, FilterType};
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
+
+pub use registry::{build_effect, factory_presets, EffectPreset, EFFECT_TYPES};
+pub use slot::EffectSlotHandle;
+use slot::EffectSlotProcessor;
+
+use super::parameters::{ParameterId, ParameterValue};
+use super::parameters_map::ParametersMap;
+
+pub mod equalizer;
+pub mod registry;
+pub mod slot;
+pub mod utility;
+
+/// Maximum number of effects in one chain
+pub const MAX_EFFECT_SLOTS: usize = 8;
+/// Maximum number of parameters of an effect which can be locked or modulated
+pub const MAX_EFFECT_PARAMETERS: usize = 8;
 
 type SomeEffectProcessor = Box<dyn AudioProcessor<SampleType = f32> + Send + 'static>;
-type SomeHandle = AudioProcessorHandleRef;
 
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[allow(clippy::enum_variant_names)]
 pub enum EffectType {
     EffectTypeReverb = 0,
     EffectTypeDelay = 1,
     EffectTypeFilter = 2,
     EffectTypeBitCrusher = 3,
+    EffectTypeCompressor = 4,
+    EffectTypeChorus = 5,
+    EffectTypePitchShifter = 6,
+    EffectTypeGainPan = 7,
+    EffectTypeEQ = 8,
+    EffectTypeModReverb = 9,
 }
 
 #[derive(Clone)]
 pub struct EffectNodeState {
     node_index: NodeIndex,
-    #[allow(unused)]
-    handle: AudioProcessorHandleRef,
+    slot: Shared<EffectSlotHandle>,
 }
 
 pub struct EffectsProcessorHandle {
     graph_handle: Shared<AudioProcessorGraphHandle>,
     effects: SharedCell<Vec<EffectNodeState>>,
     settings: SharedCell<AudioProcessorSettings>,
+    /// The owning track's parameters, effect parameters are seeded here so they can be locked
+    /// and modulated. Chains which aren't on a track (such as mixer buses) don't have one.
+    parameter_values: Option<Shared<ParametersMap>>,
 }
 
 impl EffectsProcessorHandle {
-    pub fn add_effect(&self, effect: EffectType) {
-        let (mut processor, handle): (SomeEffectProcessor, AudioProcessorHandleRef) = {
-            use EffectType::*;
-
-            let (effect, handle): (SomeEffectProcessor, SomeHandle) = match effect {
-                EffectTypeReverb => {
-                    let processor = FreeverbProcessor::default();
-                    let handle = processor.generic_handle();
-                    (Box::new(processor), handle)
-                }
-                EffectTypeDelay => {
-                    let mono_delay_processor = MonoDelayProcessor::default();
-                    let handle = mono_delay_processor.generic_handle();
-                    (
-                        Box::new(simple_processor::MonoCopyProcessor::new(
-                            mono_delay_processor,
-                        )),
-                        handle,
-                    )
-                }
-                EffectTypeFilter => {
-                    let processor = FilterProcessor::new(FilterType::LowPass);
-                    let handle = processor.generic_handle();
-                    (Box::new(MonoCopyProcessor::new(processor)), handle)
-                }
-                EffectTypeBitCrusher => {
-                    let processor = BitCrusherProcessor::default();
-                    processor.handle().set_sample_rate(100.0);
-                    let handle = AudioProcessorHandleProvider::generic_handle(&processor);
-                    (Box::new(processor), handle)
-                }
-            };
+    /// The types of the effects in this chain, in processing order
+    pub fn effect_types(&self) -> Vec<EffectType> {
+        self.effects
+            .get()
+            .iter()
+            .map(|effect| effect.slot.effect_type().clone())
+            .collect()
+    }
 
-            (effect, handle)
-        };
+    /// The slots in this chain, in processing order
+    pub fn slots(&self) -> Vec<Shared<EffectSlotHandle>> {
+        self.effects
+            .get()
+            .iter()
+            .map(|effect| effect.slot.clone())
+            .collect()
+    }
+
+    pub fn slot(&self, slot_id: usize) -> Option<Shared<EffectSlotHandle>> {
+        self.effects
+            .get()
+            .iter()
+            .find(|effect| effect.slot.id() == slot_id)
+            .map(|effect| effect.slot.clone())
+    }
+
+    /// Append an effect to the end of the chain. Returns its slot ID or `None` if the chain is
+    /// full.
+    pub fn add_effect(&self, effect: EffectType) -> Option<usize> {
+        let effects = self.effects.get();
+        let slot_id = (0..MAX_EFFECT_SLOTS)
+            .find(|slot_id| !effects.iter().any(|effect| effect.slot.id() == *slot_id))?;
+        self.add_effect_at_slot(slot_id, effect)
+    }
+
+    /// Append an effect to the end of the chain with a given slot ID. Returns `None` if the slot
+    /// is out of range or taken.
+    pub fn add_effect_at_slot(&self, slot_id: usize, effect: EffectType) -> Option<usize> {
+        if slot_id >= MAX_EFFECT_SLOTS || self.slot(slot_id).is_some() {
+            return None;
+        }
+
+        let (processor, handle) = build_effect(&effect);
+        let slot = make_shared(EffectSlotHandle::new(slot_id, effect, handle));
+        let mut processor = EffectSlotProcessor::new(slot.clone(), processor);
 
         let settings = *self.settings.get().deref();
         let mut context = AudioContext::from(settings);
         processor.prepare(&mut context);
-        let node_idx = self.graph_handle.add_node(NodeType::Simple(processor));
+        let node_idx = self
+            .graph_handle
+            .add_node(NodeType::Simple(Box::new(processor)));
         let state = EffectNodeState {
-            handle,
             node_index: node_idx,
+            slot,
         };
         let mut effects: Vec<EffectNodeState> = (*self.effects.get().deref()).clone();
         effects.push(state);
         self.effects.set(make_shared(effects));
         self.update_graph();
+        self.seed_parameters(slot_id);
+
+        Some(slot_id)
+    }
+
+    /// Remove the effect on `slot_id`. Returns false if there's no such slot.
+    pub fn remove_effect(&self, slot_id: usize) -> bool {
+        let mut effects: Vec<EffectNodeState> = (*self.effects.get().deref()).clone();
+        let position = effects
+            .iter()
+            .position(|effect| effect.slot.id() == slot_id);
+        if let Some(position) = position {
+            let state = effects.remove(position);
+            self.effects.set(make_shared(effects));
+            self.graph_handle.remove_node(state.node_index);
+            self.update_graph();
+            if let Some(parameter_values) = &self.parameter_values {
+                for parameter in 0..MAX_EFFECT_PARAMETERS {
+                    parameter_values.unset(ParameterId::ParameterIdEffect(slot_id, parameter));
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move the effect at position `from` in the chain to position `to`. Slot IDs are kept.
+    pub fn move_effect(&self, from: usize, to: usize) {
+        let mut effects: Vec<EffectNodeState> = (*self.effects.get().deref()).clone();
+        if from >= effects.len() {
+            return;
+        }
+        let state = effects.remove(from);
+        let to = to.min(effects.len());
+        effects.insert(to, state);
+        self.effects.set(make_shared(effects));
+        self.update_graph();
+    }
+
+    /// Set a parameter of the effect on `slot_id`. Doesn't allocate, so may be called from the
+    /// audio-thread.
+    pub fn set_parameter(&self, slot_id: usize, parameter: usize, value: f32) {
+        self.with_slot(slot_id, |slot| slot.set_parameter(parameter, value));
+    }
+
+    pub fn set_bypassed(&self, slot_id: usize, bypassed: bool) {
+        self.with_slot(slot_id, |slot| slot.set_bypassed(bypassed));
+    }
+
+    pub fn set_mix(&self, slot_id: usize, mix: f32) {
+        self.with_slot(slot_id, |slot| slot.set_mix(mix));
+    }
+
+    pub fn apply_preset(&self, slot_id: usize, preset: &EffectPreset) {
+        self.with_slot(slot_id, |slot| slot.apply_preset(preset));
+        self.seed_parameters(slot_id);
+    }
+
+    /// Copy the parameters of the effect on `slot_id` into the track's parameters
+    fn seed_parameters(&self, slot_id: usize) {
+        if let (Some(parameter_values), Some(slot)) = (&self.parameter_values, self.slot(slot_id)) {
+            for (parameter, value) in slot.parameters().into_iter().enumerate() {
+                parameter_values.set(
+                    ParameterId::ParameterIdEffect(slot_id, parameter),
+                    ParameterValue::Float(value.into()),
+                );
+            }
+        }
+    }
+
+    fn with_slot(&self, slot_id: usize, f: impl FnOnce(&EffectSlotHandle)) {
+        let effects = self.effects.get();
+        if let Some(effect) = effects.iter().find(|effect| effect.slot.id() == slot_id) {
+            f(&effect.slot);
+        }
     }
 
     fn update_graph(&self) {
@@ -147,6 +252,15 @@ pub struct EffectsProcessor {
 
 impl EffectsProcessor {
     pub fn new() -> Self {
+        Self::build(None)
+    }
+
+    /// Create a chain for a track, which seeds effect parameters onto `parameter_values`
+    pub fn with_parameters(parameter_values: Shared<ParametersMap>) -> Self {
+        Self::build(Some(parameter_values))
+    }
+
+    fn build(parameter_values: Option<Shared<ParametersMap>>) -> Self {
         let mut graph = AudioProcessorGraph::default();
         let graph_handle = graph.handle().clone();
 
@@ -160,6 +274,7 @@ impl EffectsProcessor {
                 graph_handle,
                 effects: make_shared_cell(vec![]),
                 settings: make_shared_cell(Default::default()),
+                parameter_values,
             }),
         }
     }
@@ -187,3 +302,87 @@ impl AudioProcessor for EffectsProcessor {
         self.graph.process(context, data)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::assert_f_eq;
+
+    use super::*;
+
+    fn process(processor: &mut EffectsProcessor) -> f32 {
+        let mut buffer = AudioBuffer::from_interleaved(2, &[1.0, 1.0]);
+        processor.process(&mut AudioContext::default(), &mut buffer);
+        *buffer.get(0, 0)
+    }
+
+    #[test]
+    fn test_add_effects_assigns_free_slots() {
+        let processor = EffectsProcessor::new();
+        let handle = processor.handle();
+        assert_eq!(handle.add_effect(EffectType::EffectTypeReverb), Some(0));
+        assert_eq!(handle.add_effect(EffectType::EffectTypeChorus), Some(1));
+        assert!(handle.remove_effect(0));
+        assert_eq!(handle.add_effect(EffectType::EffectTypeEQ), Some(0));
+        assert_eq!(
+            handle.effect_types(),
+            vec![EffectType::EffectTypeChorus, EffectType::EffectTypeEQ]
+        );
+        assert_eq!(
+            handle.add_effect_at_slot(1, EffectType::EffectTypeDelay),
+            None
+        );
+    }
+
+    #[test]
+    fn test_chain_is_full() {
+        let processor = EffectsProcessor::new();
+        let handle = processor.handle();
+        for _ in 0..MAX_EFFECT_SLOTS {
+            assert!(handle.add_effect(EffectType::EffectTypeGainPan).is_some());
+        }
+        assert_eq!(handle.add_effect(EffectType::EffectTypeGainPan), None);
+    }
+
+    #[test]
+    fn test_move_effect_keeps_slot_ids() {
+        let processor = EffectsProcessor::new();
+        let handle = processor.handle();
+        handle.add_effect(EffectType::EffectTypeReverb);
+        handle.add_effect(EffectType::EffectTypeDelay);
+        handle.add_effect(EffectType::EffectTypeFilter);
+        handle.move_effect(2, 0);
+        let slot_ids: Vec<usize> = handle.slots().iter().map(|slot| slot.id()).collect();
+        assert_eq!(slot_ids, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn test_add_effect_seeds_track_parameters() {
+        let parameter_values = make_shared(ParametersMap::new());
+        let processor = EffectsProcessor::with_parameters(parameter_values.clone());
+        let handle = processor.handle();
+        let slot_id = handle.add_effect(EffectType::EffectTypeGainPan).unwrap();
+        let parameter_id = ParameterId::ParameterIdEffect(slot_id, 0);
+        assert!(parameter_values.has_value(parameter_id.clone()));
+
+        assert!(handle.remove_effect(slot_id));
+        assert!(!parameter_values.has_value(parameter_id));
+    }
+
+    #[test]
+    fn test_process_through_slots() {
+        let mut processor = EffectsProcessor::new();
+        processor.prepare(&mut AudioContext::default());
+        let handle = processor.handle().clone();
+        let first = handle.add_effect(EffectType::EffectTypeGainPan).unwrap();
+        let second = handle.add_effect(EffectType::EffectTypeGainPan).unwrap();
+        handle.set_parameter(first, 0, 0.5);
+        handle.set_parameter(second, 0, 0.5);
+        assert_f_eq!(process(&mut processor), 0.25);
+
+        handle.set_bypassed(second, true);
+        assert_f_eq!(process(&mut processor), 0.5);
+
+        assert!(handle.remove_effect(first));
+        assert_f_eq!(process(&mut processor), 1.0);
+    }
+}
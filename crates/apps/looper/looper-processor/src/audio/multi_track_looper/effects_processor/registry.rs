@@ -0,0 +1,215 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Effects which can be inserted into a chain & their factory presets.
+
+use serde::{Deserialize, Serialize};
+
+use audio_processor_bitcrusher::BitCrusherProcessor;
+use audio_processor_dynamics::CompressorProcessor;
+use audio_processor_pitch_shifter::MultiChannelPitchShifterProcessor;
+use audio_processor_time::chorus::ChorusProcessor;
+use audio_processor_time::mod_reverb::ModReverbProcessor;
+use audio_processor_time::{FreeverbProcessor, MonoDelayProcessor};
+use audio_processor_traits::parameters::{AudioProcessorHandleProvider, AudioProcessorHandleRef};
+use audio_processor_traits::simple_processor::MonoCopyProcessor;
+use augmented_dsp_filters::rbj::{FilterProcessor, FilterType};
+
+use super::equalizer::EqualizerProcessor;
+use super::utility::UtilityProcessor;
+use super::{EffectType, SomeEffectProcessor};
+
+/// Every effect type, in the order they're listed to users
+pub const EFFECT_TYPES: [EffectType; 10] = [
+    EffectType::EffectTypeReverb,
+    EffectType::EffectTypeDelay,
+    EffectType::EffectTypeBitCrusher,
+    EffectType::EffectTypeFilter,
+    EffectType::EffectTypeCompressor,
+    EffectType::EffectTypeChorus,
+    EffectType::EffectTypePitchShifter,
+    EffectType::EffectTypeGainPan,
+    EffectType::EffectTypeEQ,
+    EffectType::EffectTypeModReverb,
+];
+
+/// Build a processor for `effect_type` & a generic handle to its parameters
+pub fn build_effect(effect_type: &EffectType) -> (SomeEffectProcessor, AudioProcessorHandleRef) {
+    use EffectType::*;
+
+    match effect_type {
+        EffectTypeReverb => {
+            let processor = FreeverbProcessor::default();
+            let handle = processor.generic_handle();
+            (Box::new(processor), handle)
+        }
+        EffectTypeDelay => {
+            let mono_delay_processor = MonoDelayProcessor::default();
+            let handle = mono_delay_processor.generic_handle();
+            (
+                Box::new(MonoCopyProcessor::new(mono_delay_processor)),
+                handle,
+            )
+        }
+        EffectTypeFilter => {
+            let processor = FilterProcessor::new(FilterType::LowPass);
+            let handle = processor.generic_handle();
+            (Box::new(MonoCopyProcessor::new(processor)), handle)
+        }
+        EffectTypeBitCrusher => {
+            let processor = BitCrusherProcessor::default();
+            processor.handle().set_sample_rate(100.0);
+            let handle = AudioProcessorHandleProvider::generic_handle(&processor);
+            (Box::new(processor), handle)
+        }
+        EffectTypeCompressor => {
+            let processor = CompressorProcessor::new();
+            let handle = processor.generic_handle();
+            (Box::new(processor), handle)
+        }
+        EffectTypeChorus => {
+            let processor = ChorusProcessor::default();
+            let handle = processor.generic_handle();
+            (Box::new(processor), handle)
+        }
+        EffectTypePitchShifter => {
+            let processor = MultiChannelPitchShifterProcessor::default();
+            let handle = processor.generic_handle();
+            (Box::new(processor), handle)
+        }
+        EffectTypeGainPan => {
+            let processor = UtilityProcessor::default();
+            let handle = processor.generic_handle();
+            (Box::new(processor), handle)
+        }
+        EffectTypeEQ => {
+            let processor = EqualizerProcessor::default();
+            let handle = processor.generic_handle();
+            (Box::new(processor), handle)
+        }
+        EffectTypeModReverb => {
+            let processor = ModReverbProcessor::default();
+            let handle = processor.generic_handle();
+            (Box::new(processor), handle)
+        }
+    }
+}
+
+/// A named set of parameter values for one effect type
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EffectPreset {
+    pub name: String,
+    pub effect_type: EffectType,
+    pub mix: f32,
+    /// Values for the effect's parameters, in order
+    pub parameters: Vec<f32>,
+}
+
+impl EffectPreset {
+    fn new(name: &str, effect_type: EffectType, mix: f32, parameters: &[f32]) -> Self {
+        Self {
+            name: name.to_string(),
+            effect_type,
+            mix,
+            parameters: parameters.to_vec(),
+        }
+    }
+}
+
+/// Presets shipped for `effect_type`. Parameter values follow the order of the effect's
+/// parameters, as listed by `EffectsService::get_effects`.
+pub fn factory_presets(effect_type: &EffectType) -> Vec<EffectPreset> {
+    use EffectType::*;
+
+    let presets: &[(&str, f32, &[f32])] = match effect_type {
+        // Dry, Room size, Damp, Wet
+        EffectTypeReverb => &[
+            ("Room", 1.0, &[0.8, 0.4, 0.5, 0.3]),
+            ("Hall", 1.0, &[0.7, 0.85, 0.3, 0.5]),
+        ],
+        // Delay, Feedback
+        EffectTypeDelay => &[
+            ("Slapback", 0.4, &[0.12, 0.1]),
+            ("Echo", 0.5, &[0.375, 0.45]),
+        ],
+        // Bit rate
+        EffectTypeBitCrusher => &[("Lo-fi", 1.0, &[4000.0]), ("Crushed", 1.0, &[1000.0])],
+        // Threshold, Ratio, Attack, Release, Make-up gain, Knee
+        EffectTypeCompressor => &[
+            ("Gentle", 1.0, &[-18.0, 2.0, 10.0, 100.0, 2.0, 6.0]),
+            ("Squash", 1.0, &[-30.0, 8.0, 1.0, 50.0, 8.0, 2.0]),
+        ],
+        // Rate, Depth, Mix
+        EffectTypeChorus => &[
+            ("Subtle", 1.0, &[0.5, 0.002, 0.3]),
+            ("Wide", 1.0, &[1.5, 0.005, 0.6]),
+        ],
+        // Ratio
+        EffectTypePitchShifter => &[
+            ("Octave up", 0.5, &[2.0]),
+            ("Octave down", 0.5, &[0.5]),
+            ("Fifth up", 0.5, &[1.5]),
+        ],
+        // Low, Mid, Mid frequency, High
+        EffectTypeEQ => &[
+            ("Warm", 1.0, &[3.0, -2.0, 500.0, -3.0]),
+            ("Bright", 1.0, &[-2.0, 0.0, 1000.0, 4.0]),
+            ("Telephone", 1.0, &[-24.0, 6.0, 1500.0, -24.0]),
+        ],
+        EffectTypeFilter | EffectTypeGainPan | EffectTypeModReverb => &[],
+    };
+
+    presets
+        .iter()
+        .map(|(name, mix, parameters)| {
+            EffectPreset::new(name, effect_type.clone(), *mix, parameters)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_every_effect() {
+        for effect_type in EFFECT_TYPES.iter() {
+            let (_processor, handle) = build_effect(effect_type);
+            assert!(!handle.name().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_factory_presets_match_their_effects_parameters() {
+        for effect_type in EFFECT_TYPES.iter() {
+            let (_processor, handle) = build_effect(effect_type);
+            for preset in factory_presets(effect_type) {
+                assert_eq!(
+                    preset.parameters.len(),
+                    handle.parameter_count(),
+                    "{}",
+                    preset.name
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::sync::atomic::AtomicBool;
+
+use basedrop::Shared;
+
+use audio_processor_traits::parameters::{AudioProcessorHandleRef, ParameterValue};
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+use augmented_atomics::{AtomicF32, AtomicValue};
+
+use super::registry::EffectPreset;
+use super::{EffectType, SomeEffectProcessor, MAX_EFFECT_PARAMETERS};
+
+/// State of one position in an effects chain; the effect it holds, bypass & dry/wet.
+///
+/// Slot IDs are stable while effects are re-ordered, so they are used to target effect
+/// parameters from `ParameterId::ParameterIdEffect`.
+pub struct EffectSlotHandle {
+    id: usize,
+    effect_type: EffectType,
+    bypassed: AtomicBool,
+    mix: AtomicF32,
+    parameters: AudioProcessorHandleRef,
+}
+
+impl EffectSlotHandle {
+    pub fn new(id: usize, effect_type: EffectType, parameters: AudioProcessorHandleRef) -> Self {
+        Self {
+            id,
+            effect_type,
+            bypassed: false.into(),
+            mix: 1.0.into(),
+            parameters,
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn effect_type(&self) -> &EffectType {
+        &self.effect_type
+    }
+
+    /// Bypassed slots pass their input through untouched. Defaults to false
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed.get()
+    }
+
+    pub fn set_bypassed(&self, value: bool) {
+        self.bypassed.set(value);
+    }
+
+    /// Dry/wet balance between 0 (dry) and 1 (wet). Defaults to 1
+    pub fn mix(&self) -> f32 {
+        self.mix.get()
+    }
+
+    pub fn set_mix(&self, value: f32) {
+        self.mix.set(value.clamp(0.0, 1.0));
+    }
+
+    /// Number of parameters on this effect which can be set, locked or modulated
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.parameter_count().min(MAX_EFFECT_PARAMETERS)
+    }
+
+    pub fn parameter(&self, parameter: usize) -> Option<f32> {
+        if parameter >= self.parameter_count() {
+            return None;
+        }
+        self.parameters
+            .get_parameter(parameter)
+            .map(|ParameterValue::Float { value }| value)
+    }
+
+    pub fn set_parameter(&self, parameter: usize, value: f32) {
+        if parameter < self.parameter_count() {
+            self.parameters.set_parameter(parameter, value.into());
+        }
+    }
+
+    /// Current value of every parameter, in order
+    pub fn parameters(&self) -> Vec<f32> {
+        (0..self.parameter_count())
+            .map(|parameter| self.parameter(parameter).unwrap_or(0.0))
+            .collect()
+    }
+
+    /// Set mix & parameters from a preset. Presets for other effect types are ignored.
+    pub fn apply_preset(&self, preset: &EffectPreset) {
+        if preset.effect_type != self.effect_type {
+            return;
+        }
+        self.set_mix(preset.mix);
+        for (parameter, value) in preset.parameters.iter().enumerate() {
+            self.set_parameter(parameter, *value);
+        }
+    }
+}
+
+/// Runs an effect, applying its slot's bypass & dry/wet
+pub struct EffectSlotProcessor {
+    slot: Shared<EffectSlotHandle>,
+    processor: SomeEffectProcessor,
+    dry_buffer: AudioBuffer<f32>,
+}
+
+impl EffectSlotProcessor {
+    pub fn new(slot: Shared<EffectSlotHandle>, processor: SomeEffectProcessor) -> Self {
+        Self {
+            slot,
+            processor,
+            dry_buffer: AudioBuffer::empty(),
+        }
+    }
+}
+
+impl AudioProcessor for EffectSlotProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.dry_buffer.resize(
+            context.settings.output_channels(),
+            context.settings.block_size(),
+        );
+        self.processor.prepare(context);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        if self.slot.is_bypassed() {
+            return;
+        }
+
+        let mix = self.slot.mix();
+        if mix >= 1.0 {
+            self.processor.process(context, data);
+            return;
+        }
+
+        self.dry_buffer
+            .resize(data.num_channels(), data.num_samples());
+        self.dry_buffer.copy_from(data);
+        self.processor.process(context, data);
+
+        for (wet_channel, dry_channel) in data
+            .channels_mut()
+            .iter_mut()
+            .zip(self.dry_buffer.channels())
+        {
+            for (wet, dry) in wet_channel.iter_mut().zip(dry_channel) {
+                *wet = dry + mix * (*wet - dry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_garbage_collector::make_shared;
+    use audio_processor_testing_helpers::assert_f_eq;
+    use audio_processor_traits::parameters::AudioProcessorHandleProvider;
+
+    use super::super::utility::UtilityProcessor;
+    use super::*;
+
+    fn make_slot() -> EffectSlotProcessor {
+        let processor = UtilityProcessor::default();
+        let handle = processor.generic_handle();
+        handle.set_parameter(0, 0.0.into());
+        let slot = make_shared(EffectSlotHandle::new(
+            0,
+            EffectType::EffectTypeGainPan,
+            handle,
+        ));
+        EffectSlotProcessor::new(slot, Box::new(processor))
+    }
+
+    fn process(processor: &mut EffectSlotProcessor) -> f32 {
+        let mut buffer = AudioBuffer::from_interleaved(2, &[1.0, 1.0]);
+        processor.process(&mut AudioContext::default(), &mut buffer);
+        *buffer.get(0, 0)
+    }
+
+    #[test]
+    fn test_slot_is_fully_wet_by_default() {
+        let mut processor = make_slot();
+        assert_f_eq!(process(&mut processor), 0.0);
+    }
+
+    #[test]
+    fn test_slot_dry_wet() {
+        let mut processor = make_slot();
+        processor.slot.set_mix(0.25);
+        assert_f_eq!(process(&mut processor), 0.75);
+    }
+
+    #[test]
+    fn test_bypassed_slot_passes_through() {
+        let mut processor = make_slot();
+        processor.slot.set_bypassed(true);
+        assert_f_eq!(process(&mut processor), 1.0);
+    }
+}
@@ -0,0 +1,175 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use basedrop::Shared;
+
+use audio_garbage_collector::make_shared;
+use audio_processor_traits::parameters::{
+    make_handle_ref, AudioProcessorHandle, AudioProcessorHandleProvider, AudioProcessorHandleRef,
+    FloatType, ParameterSpec, ParameterType, ParameterValue,
+};
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+use augmented_atomics::AtomicF32;
+
+pub struct UtilityHandle {
+    gain: AtomicF32,
+    pan: AtomicF32,
+}
+
+impl Default for UtilityHandle {
+    fn default() -> Self {
+        Self {
+            gain: 1.0.into(),
+            pan: 0.0.into(),
+        }
+    }
+}
+
+impl UtilityHandle {
+    /// Linear gain. Defaults to 1.0
+    pub fn gain(&self) -> f32 {
+        self.gain.get()
+    }
+
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.set(gain.max(0.0));
+    }
+
+    /// Balance between -1 (left) and 1 (right). Defaults to 0.0
+    pub fn pan(&self) -> f32 {
+        self.pan.get()
+    }
+
+    pub fn set_pan(&self, pan: f32) {
+        self.pan.set(pan.clamp(-1.0, 1.0));
+    }
+}
+
+/// Gain & balance on a single insert, for trimming or placing a track before other effects
+pub struct UtilityProcessor {
+    handle: Shared<UtilityHandle>,
+}
+
+impl Default for UtilityProcessor {
+    fn default() -> Self {
+        Self {
+            handle: make_shared(UtilityHandle::default()),
+        }
+    }
+}
+
+impl AudioProcessorHandleProvider for UtilityProcessor {
+    fn generic_handle(&self) -> AudioProcessorHandleRef {
+        make_handle_ref(GenericHandle(self.handle.clone()))
+    }
+}
+
+impl AudioProcessor for UtilityProcessor {
+    type SampleType = f32;
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        let gain = self.handle.gain();
+        let pan = self.handle.pan();
+        let num_channels = data.num_channels();
+        for (channel_num, channel) in data.channels_mut().iter_mut().enumerate() {
+            let balance = if num_channels < 2 {
+                1.0
+            } else if channel_num == 0 {
+                (1.0 - pan).min(1.0)
+            } else {
+                (1.0 + pan).min(1.0)
+            };
+            for sample in channel.iter_mut() {
+                *sample *= gain * balance;
+            }
+        }
+    }
+}
+
+struct GenericHandle(Shared<UtilityHandle>);
+
+impl AudioProcessorHandle for GenericHandle {
+    fn name(&self) -> String {
+        "Gain/Pan".to_string()
+    }
+
+    fn parameter_count(&self) -> usize {
+        2
+    }
+
+    fn get_parameter_spec(&self, index: usize) -> ParameterSpec {
+        let specs = [
+            ParameterSpec::new(
+                "Gain".into(),
+                ParameterType::Float(FloatType {
+                    range: (0.0, 2.0),
+                    step: None,
+                }),
+            ),
+            ParameterSpec::new(
+                "Pan".into(),
+                ParameterType::Float(FloatType {
+                    range: (-1.0, 1.0),
+                    step: None,
+                }),
+            ),
+        ];
+        specs[index].clone()
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<ParameterValue> {
+        match index {
+            0 => Some(self.0.gain().into()),
+            1 => Some(self.0.pan().into()),
+            _ => None,
+        }
+    }
+
+    fn set_parameter(&self, index: usize, request: ParameterValue) {
+        let ParameterValue::Float { value } = request;
+        match index {
+            0 => self.0.set_gain(value),
+            1 => self.0.set_pan(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::assert_f_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_utility_applies_gain_and_pan() {
+        let mut processor = UtilityProcessor::default();
+        let handle = processor.generic_handle();
+        handle.set_parameter(0, 0.5.into());
+        handle.set_parameter(1, 1.0.into());
+
+        let mut buffer = AudioBuffer::from_interleaved(2, &[1.0, 1.0]);
+        processor.process(&mut AudioContext::default(), &mut buffer);
+        assert_f_eq!(*buffer.get(0, 0), 0.0);
+        assert_f_eq!(*buffer.get(1, 0), 0.5);
+    }
+}
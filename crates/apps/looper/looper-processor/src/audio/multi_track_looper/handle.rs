@@ -30,6 +30,7 @@ use num::ToPrimitive;
 use audio_garbage_collector::{make_shared, make_shared_cell, Shared};
 use audio_processor_analysis::running_rms_processor::RunningRMSProcessorHandle;
 use audio_processor_metronome::MetronomeProcessorHandle;
+use audio_processor_standalone_midi::clock::{MidiClockEvent, MidiClockFollower};
 use audio_processor_traits::{AudioBuffer, AudioProcessorSettings};
 use augmented_atomics::{AtomicF32, AtomicValue};
 
@@ -37,17 +38,23 @@ use crate::audio::multi_track_looper::midi_store::MidiStoreHandle;
 use crate::audio::multi_track_looper::scene_state::SceneHandle;
 use crate::audio::multi_track_looper::track_events_worker::TrackEventsBus;
 use crate::audio::processor::handle::{LooperHandleThread, LooperState, ToggleRecordingResult};
+use crate::audio::time_info_provider::ClockSource;
 use crate::parameters::LFOMode;
 use crate::{QuantizeMode, TimeInfoProvider, TimeInfoProviderImpl};
 
+use super::effects_processor::{EffectPreset, EffectType, MAX_EFFECT_PARAMETERS};
 use super::looper_voice::LooperVoice;
 use super::metrics::audio_processor_metrics::{AudioProcessorMetrics, AudioProcessorMetricsHandle};
+use super::mixer::MixerHandle;
 use super::parameters::{
     CQuantizeMode, EnvelopeParameter, LFOParameter, LooperId, ParameterId, ParameterValue,
     QuantizationParameter, SceneId, SourceParameter, TempoControl,
 };
+use super::routing::RoutingHandle;
 use super::slice_worker::{SliceResult, SliceWorker};
-use super::tempo_estimation::estimate_tempo;
+use super::tempo_estimation::worker::TempoEstimationWorker;
+use super::trigger_model::sequencer::{SequencerHandle, SongEntry};
+use super::trigger_model::{Trigger, TriggerCondition};
 
 pub struct MultiTrackLooperHandle {
     voices: Vec<LooperVoice>,
@@ -56,10 +63,14 @@ pub struct MultiTrackLooperHandle {
     metronome_handle: Shared<MetronomeProcessorHandle>,
     input_meter_handle: Shared<RunningRMSProcessorHandle>,
     slice_worker: SliceWorker,
+    tempo_estimation_worker: TempoEstimationWorker,
     track_events: Shared<TrackEventsBus>,
     settings: SharedCell<AudioProcessorSettings>,
     metrics_handle: Shared<AudioProcessorMetricsHandle>,
     midi_store: Shared<MidiStoreHandle>,
+    mixer: Shared<MixerHandle>,
+    routing: Shared<RoutingHandle>,
+    sequencer: Shared<SequencerHandle>,
     active_looper: AtomicUsize,
 }
 
@@ -70,9 +81,15 @@ impl MultiTrackLooperHandle {
         input_meter_handle: Shared<RunningRMSProcessorHandle>,
         metrics: &AudioProcessorMetrics,
         voices: Vec<LooperVoice>,
+        mixer: Shared<MixerHandle>,
+        routing: Shared<RoutingHandle>,
     ) -> Self {
         MultiTrackLooperHandle {
             voices,
+            tempo_estimation_worker: TempoEstimationWorker::new(
+                time_info_provider.clone(),
+                metronome_handle.clone(),
+            ),
             time_info_provider,
             scene_handle: SceneHandle::new(8, 2),
             metronome_handle,
@@ -82,6 +99,9 @@ impl MultiTrackLooperHandle {
             track_events: make_shared(TrackEventsBus::new()),
             metrics_handle: metrics.handle(),
             midi_store: make_shared(MidiStoreHandle::default()),
+            mixer,
+            routing,
+            sequencer: make_shared(SequencerHandle::default()),
             active_looper: AtomicUsize::new(0),
         }
     }
@@ -141,6 +161,10 @@ impl MultiTrackLooperHandle {
         self.active_looper.set(looper_id.0);
     }
 
+    pub fn active_looper(&self) -> LooperId {
+        LooperId(self.active_looper.get())
+    }
+
     pub fn toggle_recording(&self, looper_id: LooperId, thread: LooperHandleThread) {
         if let Some(handle) = self.voices.get(looper_id.0) {
             let was_empty = self.all_loopers_empty_other_than(looper_id);
@@ -166,25 +190,8 @@ impl MultiTrackLooperHandle {
                     && tempo_control.as_enum()
                         == TempoControl::TempoControlSetGlobalTempo.to_usize().unwrap()
                 {
-                    let settings = self.settings.get();
-                    let estimated_tempo = estimate_tempo(
-                        Default::default(),
-                        settings.sample_rate(),
-                        handle.looper().num_samples(),
-                    )
-                    .tempo;
-                    if estimated_tempo > 300.0 {
-                        log::warn!(
-                            "This loop is too short tempo is ignored {}",
-                            estimated_tempo
-                        );
-                        return;
-                    }
-                    log::info!("Setting global tempo to {}", estimated_tempo);
-                    self.time_info_provider.set_tempo(estimated_tempo);
-                    self.metronome_handle.set_tempo(estimated_tempo);
-                    self.metronome_handle.set_is_playing(true);
-                    self.time_info_provider.play();
+                    self.tempo_estimation_worker
+                        .add_job(self.settings.get().sample_rate(), handle.looper().clone());
                 }
             }
         }
@@ -326,6 +333,13 @@ impl MultiTrackLooperHandle {
                 EnvelopeParameter::Sustain => voice.envelope().adsr_envelope.set_sustain(value),
                 _ => {}
             },
+            // Parameters are seeded when effects are added; until then the effect keeps its own
+            // values
+            ParameterId::ParameterIdEffect(slot_id, parameter)
+                if voice.user_parameters().has_value(parameter_id.clone()) =>
+            {
+                voice.effects().set_parameter(slot_id, parameter, value);
+            }
             _ => {}
         }
     }
@@ -566,6 +580,63 @@ impl MultiTrackLooperHandle {
             .remove_lock(position_beats, parameter_id);
     }
 
+    /// Append an effect to a track's chain & seed its parameters so they can be locked and
+    /// modulated. Returns the slot ID or `None` if the chain is full.
+    pub fn add_effect(&self, looper_id: LooperId, effect_type: EffectType) -> Option<usize> {
+        let voice = self.voices.get(looper_id.0)?;
+        voice.effects().add_effect(effect_type)
+    }
+
+    /// Same as `add_effect`, with a given slot ID
+    pub fn add_effect_at_slot(
+        &self,
+        looper_id: LooperId,
+        slot_id: usize,
+        effect_type: EffectType,
+    ) -> Option<usize> {
+        let voice = self.voices.get(looper_id.0)?;
+        voice.effects().add_effect_at_slot(slot_id, effect_type)
+    }
+
+    pub fn remove_effect(&self, looper_id: LooperId, slot_id: usize) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            voice.effects().remove_effect(slot_id);
+        }
+    }
+
+    /// Move the effect at position `from` of a track's chain to position `to`
+    pub fn move_effect(&self, looper_id: LooperId, from: usize, to: usize) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            voice.effects().move_effect(from, to);
+        }
+    }
+
+    pub fn set_effect_parameter(
+        &self,
+        looper_id: LooperId,
+        slot_id: usize,
+        parameter: usize,
+        value: f32,
+    ) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            if voice.effects().slot(slot_id).is_none() || parameter >= MAX_EFFECT_PARAMETERS {
+                return;
+            }
+            voice.effects().set_parameter(slot_id, parameter, value);
+            Self::update_parameter_table(
+                voice,
+                ParameterId::ParameterIdEffect(slot_id, parameter),
+                ParameterValue::Float(value.into()),
+            );
+        }
+    }
+
+    pub fn apply_effect_preset(&self, looper_id: LooperId, slot_id: usize, preset: &EffectPreset) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            voice.effects().apply_preset(slot_id, preset);
+        }
+    }
+
     pub fn trigger(&self, looper_id: LooperId) {
         if let Some(voice) = self.voices().get(looper_id.0) {
             voice.looper().trigger();
@@ -579,6 +650,81 @@ impl MultiTrackLooperHandle {
             .toggle_trigger(position_beats);
     }
 
+    pub fn set_pattern_length(&self, looper_id: LooperId, pattern_length: usize) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            voice.trigger_model().set_pattern_length(pattern_length);
+        }
+    }
+
+    pub fn set_pattern_step_beats(&self, looper_id: LooperId, pattern_step_beats: f64) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            voice
+                .trigger_model()
+                .set_pattern_step_beats(pattern_step_beats);
+        }
+    }
+
+    /// Override the global swing for a track, `None` goes back to following it
+    pub fn set_track_swing(&self, looper_id: LooperId, swing: Option<f32>) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            voice.trigger_model().set_swing(swing);
+        }
+    }
+
+    pub fn set_swing(&self, swing: f32) {
+        self.sequencer.set_swing(swing);
+    }
+
+    pub fn set_fill(&self, is_fill: bool) {
+        self.sequencer.set_fill(is_fill);
+    }
+
+    pub fn set_trigger_probability(&self, looper_id: LooperId, step: usize, probability: f32) {
+        self.update_trigger(looper_id, step, |trigger| {
+            trigger.set_probability(probability)
+        });
+    }
+
+    pub fn set_trigger_condition(
+        &self,
+        looper_id: LooperId,
+        step: usize,
+        condition: TriggerCondition,
+    ) {
+        self.update_trigger(looper_id, step, |trigger| trigger.set_condition(condition));
+    }
+
+    pub fn set_trigger_micro_timing(&self, looper_id: LooperId, step: usize, micro_timing: f32) {
+        self.update_trigger(looper_id, step, |trigger| {
+            trigger.set_micro_timing(micro_timing)
+        });
+    }
+
+    pub fn set_trigger_ratchets(&self, looper_id: LooperId, step: usize, ratchets: usize) {
+        self.update_trigger(looper_id, step, |trigger| trigger.set_ratchets(ratchets));
+    }
+
+    fn update_trigger(&self, looper_id: LooperId, step: usize, f: impl FnOnce(&mut Trigger)) {
+        if let Some(voice) = self.voices.get(looper_id.0) {
+            voice.trigger_model().update_trigger(step, f);
+        }
+    }
+
+    /// Select the pattern to edit & play on all tracks
+    pub fn select_pattern(&self, pattern: usize) {
+        for voice in self.voices.iter() {
+            voice.trigger_model().select_pattern(pattern);
+        }
+    }
+
+    pub fn set_song(&self, song: Vec<SongEntry>) {
+        self.sequencer.set_song(song);
+    }
+
+    pub fn set_song_enabled(&self, song_enabled: bool) {
+        self.sequencer.set_song_enabled(song_enabled);
+    }
+
     pub fn get_position_percent(&self, looper_id: LooperId) -> f32 {
         if let Some(voice) = self.voices.get(looper_id.0) {
             let playhead = voice.looper().playhead() as f32;
@@ -611,6 +757,33 @@ impl MultiTrackLooperHandle {
         self.time_info_provider.stop();
     }
 
+    pub fn clock_source(&self) -> ClockSource {
+        self.time_info_provider.clock_source()
+    }
+
+    pub fn set_clock_source(&self, clock_source: ClockSource) {
+        self.time_info_provider.set_clock_source(clock_source);
+    }
+
+    /// Follow a MIDI clock event with the play-head & metronome, when the clock source is MIDI
+    /// clock. Called on the audio-thread.
+    pub(crate) fn on_midi_clock_event(&self, event: MidiClockEvent, follower: &MidiClockFollower) {
+        if self.clock_source() != ClockSource::ClockSourceMidiClock {
+            return;
+        }
+
+        self.time_info_provider.apply_midi_clock(event, follower);
+        match event {
+            MidiClockEvent::Start | MidiClockEvent::Continue => {
+                self.metronome_handle.set_is_playing(true);
+            }
+            MidiClockEvent::Stop => {
+                self.metronome_handle.set_is_playing(false);
+            }
+            _ => {}
+        }
+    }
+
     pub fn toggle_playback(&self, looper_id: LooperId) {
         if let Some(handle) = self.voices.get(looper_id.0) {
             // TODO: We might want to stop the playhead
@@ -629,6 +802,29 @@ impl MultiTrackLooperHandle {
         &self.metronome_handle
     }
 
+    /// Pan, mute/solo, sends, aux buses & master bus
+    pub fn mixer(&self) -> &Shared<MixerHandle> {
+        &self.mixer
+    }
+
+    /// Per-track input channels & output buses
+    pub fn routing(&self) -> &Shared<RoutingHandle> {
+        &self.routing
+    }
+
+    pub fn sequencer(&self) -> &Shared<SequencerHandle> {
+        &self.sequencer
+    }
+
+    /// Route every track in the mixer's exclusive `group` into `output_bus`
+    pub fn set_group_output_bus(&self, group: usize, output_bus: usize) {
+        for (index, track) in self.mixer.tracks().iter().enumerate() {
+            if track.group() == Some(group) {
+                self.routing.set_output_bus(LooperId(index), output_bus);
+            }
+        }
+    }
+
     pub fn set_metronome_volume(&self, volume: f32) {
         self.metronome_handle.set_volume(volume);
     }
@@ -649,6 +845,38 @@ impl MultiTrackLooperHandle {
         }
     }
 
+    /// Undo the last overdub layer of a looper on the next audio block. Returns false if there was
+    /// nothing to undo.
+    pub fn undo_overdub(&self, looper_id: LooperId) -> bool {
+        self.voices
+            .get(looper_id.0)
+            .map(|handle| handle.looper().undo_overdub())
+            .unwrap_or(false)
+    }
+
+    /// Redo the last undone overdub layer of a looper on the next audio block. Returns false if
+    /// there was nothing to redo.
+    pub fn redo_overdub(&self, looper_id: LooperId) -> bool {
+        self.voices
+            .get(looper_id.0)
+            .map(|handle| handle.looper().redo_overdub())
+            .unwrap_or(false)
+    }
+
+    pub fn get_overdub_undo_count(&self, looper_id: LooperId) -> usize {
+        self.voices
+            .get(looper_id.0)
+            .map(|handle| handle.looper().overdub_undo_count())
+            .unwrap_or(0)
+    }
+
+    pub fn get_overdub_redo_count(&self, looper_id: LooperId) -> usize {
+        self.voices
+            .get(looper_id.0)
+            .map(|handle| handle.looper().overdub_redo_count())
+            .unwrap_or(0)
+    }
+
     pub fn num_voices(&self) -> usize {
         self.voices.len()
     }
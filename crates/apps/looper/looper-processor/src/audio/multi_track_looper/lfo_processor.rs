@@ -34,8 +34,13 @@ pub struct LFOHandleMap {
 }
 
 impl LFOHandleMap {
+    /// Modulation amount for `id`. Maps loaded from older projects may not know about every
+    /// parameter; those aren't modulated.
     pub fn get(&self, id: &ParameterId) -> f32 {
-        self.values[self.indexes[id]].get()
+        self.indexes
+            .get(id)
+            .map(|index| self.values[*index].get())
+            .unwrap_or(0.0)
     }
 }
 
@@ -125,6 +130,16 @@ mod test {
         assert_f_eq!(handle.frequency(), 44.44);
     }
 
+    #[test]
+    fn test_map_get_unknown_parameter() {
+        // Simulates a map saved before effect parameters existed
+        let handle = LFOHandle::default();
+        let mut map = handle.map().clone();
+        map.indexes
+            .retain(|id, _| !matches!(id, ParameterId::ParameterIdEffect(_, _)));
+        assert_f_eq!(map.get(&ParameterId::ParameterIdEffect(0, 0)), 0.0);
+    }
+
     #[test]
     fn test_add_modulation() {
         let handle = LFOHandle::default();
@@ -22,6 +22,7 @@
 // THE SOFTWARE.
 use basedrop::Shared;
 
+use audio_garbage_collector::make_shared;
 use audio_processor_pitch_shifter::{
     MultiChannelPitchShifterProcessor, MultiChannelPitchShifterProcessorHandle,
 };
@@ -42,7 +43,7 @@ pub type ParameterValues = ParametersMap;
 
 pub struct LooperVoice {
     pub id: usize,
-    parameter_values: ParameterValues,
+    parameter_values: Shared<ParameterValues>,
     parameter_ids: Vec<ParameterId>,
     triggers: Shared<TrackTriggerModel>,
     looper_handle: Shared<LooperProcessorHandle>,
@@ -102,11 +103,11 @@ pub struct VoiceProcessors {
     pub pitch_shifter: MultiChannelPitchShifterProcessor,
     pub envelope: EnvelopeProcessor,
     pub effects_processor: EffectsProcessor,
+    /// Shared with the effects chain, which seeds its effects' parameters
+    pub parameter_values: Shared<ParameterValues>,
 }
 
 pub fn build_voice_handle(id: usize, voice_processors: &VoiceProcessors) -> LooperVoice {
-    use audio_garbage_collector::make_shared;
-
     use super::parameters::build_default_parameters;
 
     let VoiceProcessors {
@@ -114,6 +115,7 @@ pub fn build_voice_handle(id: usize, voice_processors: &VoiceProcessors) -> Loop
         pitch_shifter,
         envelope,
         effects_processor,
+        parameter_values,
     } = voice_processors;
     let looper_handle = looper.handle().clone();
     let sequencer_handle = looper.sequencer_handle().clone();
@@ -123,7 +125,7 @@ pub fn build_voice_handle(id: usize, voice_processors: &VoiceProcessors) -> Loop
     LooperVoice {
         id,
         parameter_ids,
-        parameter_values: ParametersMap::new(),
+        parameter_values: parameter_values.clone(),
         looper_handle,
         sequencer_handle,
         triggers,
@@ -152,6 +154,7 @@ pub fn from_handle(handle: &LooperVoice) -> VoiceProcessors {
         effects_processor,
         envelope,
         pitch_shifter,
+        parameter_values: handle.parameter_values.clone(),
     }
 }
 
@@ -159,7 +162,8 @@ pub fn build_voice_processor(
     options: &LooperOptions,
     time_info_provider: &Shared<TimeInfoProviderImpl>,
 ) -> VoiceProcessors {
-    let effects_processor = EffectsProcessor::new();
+    let parameter_values = make_shared(ParametersMap::new());
+    let effects_processor = EffectsProcessor::with_parameters(parameter_values.clone());
     let looper = LooperProcessor::new(options.clone(), time_info_provider.clone());
     looper
         .handle()
@@ -175,5 +179,6 @@ pub fn build_voice_processor(
         pitch_shifter,
         envelope,
         effects_processor,
+        parameter_values,
     }
 }
@@ -29,10 +29,10 @@ use itertools::Itertools;
 use atomic_queue::Queue;
 use audio_garbage_collector::make_shared;
 use audio_processor_traits::MidiMessageLike;
-use augmented_atomics::{AtomicF32, AtomicOption};
+use augmented_atomics::AtomicOption;
 use augmented_midi::{parse_midi_event, MIDIMessage, ParserState};
 
-use crate::audio::midi_map::{MidiControllerNumber, MidiMap};
+use crate::audio::midi_map::{find_mapping, MidiMap, MidiMappingInput, MidiMappingKey};
 use crate::audio::multi_track_looper::parameters::{EntityId, ParameterValue};
 use crate::MultiTrackLooper;
 use augmented_longbackoff::LongBackoff;
@@ -121,27 +121,62 @@ impl MidiStoreHandle {
     ) -> Option<()> {
         let bytes = message.bytes()?;
         let (_, message) = parse_midi_event::<&[u8]>(bytes, &mut ParserState::default()).ok()?;
-        if let MIDIMessage::ControlChange {
-            controller_number,
-            value,
-            ..
-        } = message
-        {
-            let entity_id = self
-                .midi_map
-                .get(&MidiControllerNumber::new(controller_number))?;
-            match entity_id {
-                EntityId::EntityIdLooperParameter(looper_id, parameter_id) => {
-                    looper.handle.set_parameter(
-                        looper_id,
-                        parameter_id,
-                        ParameterValue::Float(AtomicF32::new(value as f32 / 127.0)),
-                    );
-                }
-                EntityId::EntityIdRecordButton => {
-                    looper.on_record_button_click_midi(value);
-                }
+        let (key, input) = match message {
+            MIDIMessage::ControlChange {
+                channel,
+                controller_number,
+                value,
+            } => (
+                MidiMappingKey::control_change(Some(channel), controller_number),
+                MidiMappingInput::ControlChange(value),
+            ),
+            MIDIMessage::NoteOn(note) if note.velocity > 0 => (
+                MidiMappingKey::note(Some(note.channel), note.note),
+                MidiMappingInput::NoteOn(note.velocity),
+            ),
+            MIDIMessage::NoteOn(note) | MIDIMessage::NoteOff(note) => (
+                MidiMappingKey::note(Some(note.channel), note.note),
+                MidiMappingInput::NoteOff,
+            ),
+            MIDIMessage::ProgramChange {
+                channel,
+                program_number,
+            } => (
+                MidiMappingKey::program_change(Some(channel), program_number),
+                MidiMappingInput::ProgramChange,
+            ),
+            _ => return None,
+        };
+
+        if input != MidiMappingInput::NoteOff && self.midi_map.learn(&key) {
+            return Some(());
+        }
+
+        let store = self.midi_map.store().get();
+        let mapping = find_mapping(&store, &key)?;
+        match &mapping.entity_id {
+            EntityId::EntityIdLooperParameter(looper_id, parameter_id) => {
+                let current = looper
+                    .handle
+                    .get_parameter(*looper_id, parameter_id)
+                    .map(|value| value.to_f32());
+                let value = mapping.value(input, current)?;
+                looper.handle.set_parameter(
+                    *looper_id,
+                    parameter_id.clone(),
+                    ParameterValue::from_f32(parameter_id, value),
+                );
             }
+            EntityId::EntityIdRecordButton => match input {
+                MidiMappingInput::ControlChange(value) => looper.on_record_button_click_midi(value),
+                MidiMappingInput::NoteOn(_) => looper.on_record_button_click_midi(127),
+                MidiMappingInput::NoteOff => looper.on_record_button_click_midi(0),
+                // Program changes have no release, so they're a full press
+                MidiMappingInput::ProgramChange => {
+                    looper.on_record_button_click_midi(127);
+                    looper.on_record_button_click_midi(0);
+                }
+            },
         }
 
         Some(())
@@ -234,13 +269,16 @@ pub trait MidiStoreActorDelegate {
 
 #[cfg(test)]
 mod test {
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
     use basedrop::Owned;
     use itertools::Itertools;
 
     use audio_garbage_collector::{handle, make_shared};
     use audio_processor_standalone_midi::host::{MidiMessageEntry, MidiMessageWrapper};
 
+    use crate::audio::midi_map::MidiMapping;
+    use crate::audio::multi_track_looper::parameters::{LooperId, ParameterId, SourceParameter};
+
     use super::*;
 
     #[test]
@@ -260,7 +298,7 @@ mod test {
             },
         ));
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             store.push_event_to_queues(&message);
         });
 
@@ -302,7 +340,7 @@ mod test {
 
         let mut looper = MultiTrackLooper::default();
         let events = [make_message(), make_message(), make_message()];
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             store.process_midi_events(&events, &mut looper);
         });
 
@@ -330,6 +368,95 @@ mod test {
         }
     }
 
+    fn midi_message(message_data: [u8; 3]) -> MidiMessageEntry {
+        MidiMessageEntry(Owned::new(
+            handle(),
+            MidiMessageWrapper {
+                message_data,
+                timestamp: 0,
+            },
+        ))
+    }
+
+    #[test]
+    fn test_channel_and_note_mappings() {
+        let store = MidiStoreHandle::default();
+        let speed = ParameterId::ParameterIdSource(SourceParameter::Speed);
+        let loop_enabled = ParameterId::ParameterIdSource(SourceParameter::LoopEnabled);
+        store.midi_map().add_mapping(
+            MidiMappingKey::control_change(Some(1), 20),
+            MidiMapping::new(EntityId::EntityIdLooperParameter(
+                LooperId(0),
+                speed.clone(),
+            ))
+            .with_range(0.0, 2.0),
+        );
+        store.midi_map().add_mapping(
+            MidiMappingKey::note(None, 60),
+            MidiMapping::new(EntityId::EntityIdLooperParameter(
+                LooperId(0),
+                loop_enabled.clone(),
+            ))
+            .with_range(1.0, 0.0),
+        );
+
+        let mut looper = MultiTrackLooper::default();
+        let get = |looper: &MultiTrackLooper, parameter_id| {
+            looper
+                .handle()
+                .get_parameter(LooperId(0), parameter_id)
+                .unwrap()
+        };
+
+        let events = [midi_message([0xB0, 20, 127]), midi_message([0x93, 60, 100])];
+        assert_realtime_safe(|| {
+            store.process_midi_events(&events, &mut looper);
+        });
+        assert_eq!(get(&looper, &speed), ParameterValue::Float(1.0.into()));
+        assert_eq!(
+            get(&looper, &loop_enabled),
+            ParameterValue::Bool(false.into())
+        );
+
+        let events = [midi_message([0xB1, 20, 127]), midi_message([0x83, 60, 0])];
+        assert_realtime_safe(|| {
+            store.process_midi_events(&events, &mut looper);
+        });
+        assert_eq!(get(&looper, &speed), ParameterValue::Float(2.0.into()));
+        assert_eq!(
+            get(&looper, &loop_enabled),
+            ParameterValue::Bool(true.into())
+        );
+    }
+
+    #[test]
+    fn test_midi_learn() {
+        let store = MidiStoreHandle::default();
+        let speed = ParameterId::ParameterIdSource(SourceParameter::Speed);
+        store
+            .midi_map()
+            .start_learn(MidiMapping::new(EntityId::EntityIdLooperParameter(
+                LooperId(0),
+                speed.clone(),
+            )));
+
+        let mut looper = MultiTrackLooper::default();
+        let events = [midi_message([0xB2, 7, 0])];
+        assert_realtime_safe(|| {
+            store.process_midi_events(&events, &mut looper);
+        });
+        let value = looper.handle().get_parameter(LooperId(0), &speed);
+        assert_eq!(value, Some(ParameterValue::Float(1.0.into())));
+        assert_eq!(
+            store.midi_map().poll_learn(),
+            Some(MidiMappingKey::control_change(Some(2), 7))
+        );
+
+        store.process_midi_events(&events, &mut looper);
+        let value = looper.handle().get_parameter(LooperId(0), &speed);
+        assert_eq!(value, Some(ParameterValue::Float(0.0.into())));
+    }
+
     #[test]
     fn test_actor_state() {
         let queue = make_shared(Queue::new(100));
@@ -346,13 +473,13 @@ mod test {
                 timestamp: 0,
             },
         ));
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             store.push_event_to_queues(&message);
         });
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             store.push_event_to_queues(&message);
         });
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             store.push_event_to_queues(&message);
         });
 
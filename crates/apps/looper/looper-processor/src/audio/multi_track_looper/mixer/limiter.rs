@@ -0,0 +1,97 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Peak limiter used on the master bus.
+//!
+//! The envelope follows peaks instantly and releases exponentially. Gain is `ceiling / envelope`
+//! when the envelope is over the ceiling; since the envelope is never below the current peak the
+//! output never exceeds the ceiling. There's no look-ahead, so hard transients are clamped within
+//! a sample, which is preferred over clipping the output.
+
+pub struct Limiter {
+    envelope: f32,
+    release_mult: f32,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self {
+            envelope: 0.0,
+            release_mult: 0.0,
+        }
+    }
+}
+
+impl Limiter {
+    pub fn prepare(&mut self, sample_rate: f32, release_ms: f32) {
+        let release_samples = (release_ms * 0.001 * sample_rate).max(1.0);
+        self.release_mult = (-1.0 / release_samples).exp();
+    }
+
+    /// Gain to apply to a frame with absolute peak `peak`, given a linear `ceiling`
+    pub fn process_frame(&mut self, peak: f32, ceiling: f32) -> f32 {
+        self.envelope = peak.max(self.envelope * self.release_mult);
+        if self.envelope > ceiling {
+            ceiling / self.envelope
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quiet_signals_are_untouched() {
+        let mut limiter = Limiter::default();
+        limiter.prepare(44100.0, 100.0);
+        for _ in 0..100 {
+            assert!((limiter.process_frame(0.5, 1.0) - 1.0).abs() < f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_output_never_exceeds_ceiling() {
+        let mut limiter = Limiter::default();
+        limiter.prepare(44100.0, 100.0);
+        for i in 0..1000 {
+            let peak = if i % 100 == 0 { 4.0 } else { 1.5 };
+            let gain = limiter.process_frame(peak, 0.9);
+            assert!(peak * gain <= 0.9 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_gain_recovers_after_release() {
+        let mut limiter = Limiter::default();
+        limiter.prepare(1000.0, 10.0);
+        assert!(limiter.process_frame(2.0, 1.0) < 0.6);
+        let mut gain = 0.0;
+        for _ in 0..200 {
+            gain = limiter.process_frame(0.1, 1.0);
+        }
+        assert!((gain - 1.0).abs() < f32::EPSILON);
+    }
+}
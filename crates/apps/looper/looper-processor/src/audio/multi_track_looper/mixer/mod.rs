@@ -0,0 +1,483 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Mixer for the looper tracks.
+//!
+//! Every track goes through a [`TrackMixerProcessor`] applying pan and mute/solo, then into the
+//! master bus and each of the aux send buses. Aux buses run an [`EffectsProcessor`] chain (an
+//! `audio-processor-graph`) and are summed back into the master bus, which has a volume and a
+//! peak limiter. Tracks, aux buses & the master bus are metered.
+//!
+//! Tracks may be put into exclusive groups. Un-muting a track mutes every other track in its group
+//! and soloing a track un-solos every other track in its group, so a group holds alternative
+//! parts (e.g. two variations of a bass-line) of which a single one plays.
+//!
+//! All state lives in a [`MixerHandle`] shared between the audio-thread and other threads.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::time::Duration;
+
+use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_analysis::running_rms_processor::{
+    RunningRMSProcessor, RunningRMSProcessorHandle,
+};
+use augmented_atomics::{AtomicF32, AtomicValue};
+
+use super::effects_processor::{EffectsProcessor, EffectsProcessorHandle};
+use super::parameters::LooperId;
+
+pub use self::processors::{
+    AuxBusProcessor, MasterBusProcessor, SendProcessor, TrackMixerProcessor,
+};
+
+pub mod limiter;
+mod processors;
+
+/// Number of aux send buses the looper is built with
+pub const NUM_AUX_BUSES: usize = 2;
+
+fn make_meter() -> RunningRMSProcessor {
+    RunningRMSProcessor::new_with_duration(
+        audio_garbage_collector::handle(),
+        Duration::from_millis(50),
+    )
+}
+
+/// Mean RMS of the first two channels
+fn meter_level(meter: &RunningRMSProcessorHandle) -> f32 {
+    (meter.calculate_rms(0) + meter.calculate_rms(1)) / 2.0
+}
+
+pub struct TrackMixerHandle {
+    /// -1 is hard left, 1 is hard right
+    pan: AtomicF32,
+    is_muted: AtomicBool,
+    is_soloed: AtomicBool,
+    /// Exclusive group + 1, 0 is no group
+    group: AtomicUsize,
+    send_levels: Vec<AtomicF32>,
+    meter: Shared<RunningRMSProcessorHandle>,
+}
+
+impl TrackMixerHandle {
+    fn new(num_aux_buses: usize, meter: Shared<RunningRMSProcessorHandle>) -> Self {
+        Self {
+            pan: AtomicF32::new(0.0),
+            is_muted: AtomicBool::new(false),
+            is_soloed: AtomicBool::new(false),
+            group: AtomicUsize::new(0),
+            send_levels: (0..num_aux_buses).map(|_| AtomicF32::new(0.0)).collect(),
+            meter,
+        }
+    }
+
+    pub fn pan(&self) -> f32 {
+        self.pan.get()
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.is_muted.get()
+    }
+
+    pub fn is_soloed(&self) -> bool {
+        self.is_soloed.get()
+    }
+
+    pub fn group(&self) -> Option<usize> {
+        self.group.get().checked_sub(1)
+    }
+
+    /// Level sent into aux bus `bus`, 0 if there's no such bus
+    pub fn send_level(&self, bus: usize) -> f32 {
+        self.send_levels
+            .get(bus)
+            .map(|level| level.get())
+            .unwrap_or(0.0)
+    }
+
+    pub fn num_sends(&self) -> usize {
+        self.send_levels.len()
+    }
+
+    pub fn meter(&self) -> &Shared<RunningRMSProcessorHandle> {
+        &self.meter
+    }
+
+    /// Post-fader RMS level
+    pub fn level(&self) -> f32 {
+        meter_level(&self.meter)
+    }
+}
+
+pub struct AuxBusHandle {
+    effects: Shared<EffectsProcessorHandle>,
+    return_level: AtomicF32,
+    meter: Shared<RunningRMSProcessorHandle>,
+}
+
+impl AuxBusHandle {
+    pub fn effects(&self) -> &Shared<EffectsProcessorHandle> {
+        &self.effects
+    }
+
+    pub fn return_level(&self) -> f32 {
+        self.return_level.get()
+    }
+
+    pub fn set_return_level(&self, level: f32) {
+        self.return_level.set(level.max(0.0));
+    }
+
+    pub fn level(&self) -> f32 {
+        meter_level(&self.meter)
+    }
+}
+
+pub struct MasterBusHandle {
+    volume: AtomicF32,
+    limiter_enabled: AtomicBool,
+    limiter_ceiling_db: AtomicF32,
+    limiter_release_ms: AtomicF32,
+    /// Lowest limiter gain on the last block
+    limiter_gain: AtomicF32,
+    meter: Shared<RunningRMSProcessorHandle>,
+}
+
+impl MasterBusHandle {
+    pub fn volume(&self) -> f32 {
+        self.volume.get()
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.set(volume.max(0.0));
+    }
+
+    /// Defaults to false, so the mix isn't altered until the limiter is switched on
+    pub fn is_limiter_enabled(&self) -> bool {
+        self.limiter_enabled.get()
+    }
+
+    pub fn set_limiter_enabled(&self, enabled: bool) {
+        self.limiter_enabled.set(enabled);
+    }
+
+    /// Peak ceiling of the limiter in dBFS. Defaults to -0.3dB
+    pub fn limiter_ceiling_db(&self) -> f32 {
+        self.limiter_ceiling_db.get()
+    }
+
+    pub fn set_limiter_ceiling_db(&self, ceiling_db: f32) {
+        self.limiter_ceiling_db.set(ceiling_db.min(0.0));
+    }
+
+    /// Defaults to 100ms
+    pub fn limiter_release_ms(&self) -> f32 {
+        self.limiter_release_ms.get()
+    }
+
+    pub fn set_limiter_release_ms(&self, release_ms: f32) {
+        self.limiter_release_ms.set(release_ms.max(1.0));
+    }
+
+    /// Gain reduction applied by the limiter on the last block, in dB (0 or negative)
+    pub fn limiter_gain_reduction_db(&self) -> f32 {
+        20.0 * self.limiter_gain.get().max(1e-6).log10()
+    }
+
+    pub fn level(&self) -> f32 {
+        meter_level(&self.meter)
+    }
+}
+
+pub struct MixerHandle {
+    tracks: Vec<Shared<TrackMixerHandle>>,
+    aux_buses: Vec<Shared<AuxBusHandle>>,
+    master: Shared<MasterBusHandle>,
+}
+
+impl MixerHandle {
+    pub fn tracks(&self) -> &[Shared<TrackMixerHandle>] {
+        &self.tracks
+    }
+
+    pub fn track(&self, looper_id: LooperId) -> Option<&Shared<TrackMixerHandle>> {
+        self.tracks.get(looper_id.0)
+    }
+
+    pub fn aux_buses(&self) -> &[Shared<AuxBusHandle>] {
+        &self.aux_buses
+    }
+
+    pub fn aux_bus(&self, bus: usize) -> Option<&Shared<AuxBusHandle>> {
+        self.aux_buses.get(bus)
+    }
+
+    pub fn master(&self) -> &Shared<MasterBusHandle> {
+        &self.master
+    }
+
+    pub fn set_pan(&self, looper_id: LooperId, pan: f32) {
+        if let Some(track) = self.track(looper_id) {
+            track.pan.set(pan.clamp(-1.0, 1.0));
+        }
+    }
+
+    /// Mute or un-mute a track. Un-muting a track mutes the others in its group.
+    pub fn set_muted(&self, looper_id: LooperId, is_muted: bool) {
+        if let Some(track) = self.track(looper_id) {
+            track.is_muted.set(is_muted);
+            if !is_muted {
+                for other in self.other_group_members(looper_id) {
+                    other.is_muted.set(true);
+                }
+            }
+        }
+    }
+
+    /// Solo or un-solo a track. Soloing a track un-solos the others in its group.
+    pub fn set_soloed(&self, looper_id: LooperId, is_soloed: bool) {
+        if let Some(track) = self.track(looper_id) {
+            track.is_soloed.set(is_soloed);
+            if is_soloed {
+                for other in self.other_group_members(looper_id) {
+                    other.is_soloed.set(false);
+                }
+            }
+        }
+    }
+
+    /// Put a track in an exclusive group, or remove it from its group with `None`
+    pub fn set_group(&self, looper_id: LooperId, group: Option<usize>) {
+        if let Some(track) = self.track(looper_id) {
+            track.group.set(group.map(|group| group + 1).unwrap_or(0));
+        }
+    }
+
+    pub fn set_send_level(&self, looper_id: LooperId, bus: usize, level: f32) {
+        if let Some(level_ref) = self
+            .track(looper_id)
+            .and_then(|track| track.send_levels.get(bus))
+        {
+            level_ref.set(level.max(0.0));
+        }
+    }
+
+    pub fn is_any_soloed(&self) -> bool {
+        self.tracks.iter().any(|track| track.is_soloed())
+    }
+
+    /// Whether a track is heard given mute & solo states
+    pub fn is_audible(&self, looper_id: LooperId) -> bool {
+        match self.track(looper_id) {
+            Some(track) => !track.is_muted() && (track.is_soloed() || !self.is_any_soloed()),
+            None => false,
+        }
+    }
+
+    fn other_group_members(
+        &self,
+        looper_id: LooperId,
+    ) -> impl Iterator<Item = &Shared<TrackMixerHandle>> {
+        let group = self.track(looper_id).and_then(|track| track.group());
+        self.tracks
+            .iter()
+            .enumerate()
+            .filter(move |(index, track)| {
+                group.is_some() && *index != looper_id.0 && track.group() == group
+            })
+            .map(|(_, track)| track)
+    }
+}
+
+/// Audio processors of the mixer, which are moved into the looper's graph
+pub struct MixerProcessors {
+    pub tracks: Vec<TrackMixerProcessor>,
+    /// `sends[track][bus]`
+    pub sends: Vec<Vec<SendProcessor>>,
+    pub aux_buses: Vec<AuxBusProcessor>,
+    pub master: MasterBusProcessor,
+}
+
+impl MixerProcessors {
+    pub fn new(num_tracks: usize, num_aux_buses: usize) -> (Self, Shared<MixerHandle>) {
+        let track_meters: Vec<RunningRMSProcessor> =
+            (0..num_tracks).map(|_| make_meter()).collect();
+        let aux_buses: Vec<(EffectsProcessor, RunningRMSProcessor)> = (0..num_aux_buses)
+            .map(|_| (EffectsProcessor::new(), make_meter()))
+            .collect();
+        let master_meter = make_meter();
+
+        let handle = make_shared(MixerHandle {
+            tracks: track_meters
+                .iter()
+                .map(|meter| {
+                    make_shared(TrackMixerHandle::new(num_aux_buses, meter.handle().clone()))
+                })
+                .collect(),
+            aux_buses: aux_buses
+                .iter()
+                .map(|(effects, meter)| {
+                    make_shared(AuxBusHandle {
+                        effects: effects.handle().clone(),
+                        return_level: AtomicF32::new(1.0),
+                        meter: meter.handle().clone(),
+                    })
+                })
+                .collect(),
+            master: make_shared(MasterBusHandle {
+                volume: AtomicF32::new(1.0),
+                limiter_enabled: AtomicBool::new(false),
+                limiter_ceiling_db: AtomicF32::new(-0.3),
+                limiter_release_ms: AtomicF32::new(100.0),
+                limiter_gain: AtomicF32::new(1.0),
+                meter: master_meter.handle().clone(),
+            }),
+        });
+
+        let processors = Self::build(&handle, track_meters, aux_buses, master_meter);
+        (processors, handle)
+    }
+
+    pub fn from_handle(handle: &Shared<MixerHandle>) -> Self {
+        let track_meters = handle
+            .tracks
+            .iter()
+            .map(|track| RunningRMSProcessor::from_handle(track.meter.clone()))
+            .collect();
+        let aux_buses = handle
+            .aux_buses
+            .iter()
+            .map(|bus| {
+                (
+                    EffectsProcessor::from_handle(bus.effects.clone()),
+                    RunningRMSProcessor::from_handle(bus.meter.clone()),
+                )
+            })
+            .collect();
+        let master_meter = RunningRMSProcessor::from_handle(handle.master.meter.clone());
+        Self::build(handle, track_meters, aux_buses, master_meter)
+    }
+
+    fn build(
+        handle: &Shared<MixerHandle>,
+        track_meters: Vec<RunningRMSProcessor>,
+        aux_buses: Vec<(EffectsProcessor, RunningRMSProcessor)>,
+        master_meter: RunningRMSProcessor,
+    ) -> Self {
+        Self {
+            tracks: track_meters
+                .into_iter()
+                .enumerate()
+                .map(|(index, meter)| {
+                    TrackMixerProcessor::new(handle.clone(), LooperId(index), meter)
+                })
+                .collect(),
+            sends: handle
+                .tracks
+                .iter()
+                .map(|track| {
+                    (0..handle.aux_buses.len())
+                        .map(|bus| SendProcessor::new(track.clone(), bus))
+                        .collect()
+                })
+                .collect(),
+            aux_buses: aux_buses
+                .into_iter()
+                .zip(&handle.aux_buses)
+                .map(|((effects, meter), bus)| AuxBusProcessor::new(bus.clone(), effects, meter))
+                .collect(),
+            master: MasterBusProcessor::new(handle.master.clone(), master_meter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mute_and_solo() {
+        let (_, mixer) = MixerProcessors::new(3, NUM_AUX_BUSES);
+        assert!(mixer.is_audible(LooperId(0)));
+        mixer.set_muted(LooperId(0), true);
+        assert!(!mixer.is_audible(LooperId(0)));
+        assert!(mixer.is_audible(LooperId(1)));
+
+        mixer.set_soloed(LooperId(1), true);
+        assert!(mixer.is_audible(LooperId(1)));
+        assert!(!mixer.is_audible(LooperId(2)));
+        // Mute wins over solo
+        mixer.set_soloed(LooperId(0), true);
+        assert!(!mixer.is_audible(LooperId(0)));
+
+        mixer.set_soloed(LooperId(0), false);
+        mixer.set_soloed(LooperId(1), false);
+        assert!(mixer.is_audible(LooperId(2)));
+        assert!(!mixer.is_audible(LooperId(5)));
+    }
+
+    #[test]
+    fn test_exclusive_groups() {
+        let (_, mixer) = MixerProcessors::new(4, NUM_AUX_BUSES);
+        mixer.set_group(LooperId(0), Some(1));
+        mixer.set_group(LooperId(1), Some(1));
+        mixer.set_group(LooperId(2), Some(2));
+        assert_eq!(mixer.track(LooperId(0)).unwrap().group(), Some(1));
+        assert_eq!(mixer.track(LooperId(3)).unwrap().group(), None);
+
+        mixer.set_muted(LooperId(0), false);
+        assert!(mixer.is_audible(LooperId(0)));
+        assert!(!mixer.is_audible(LooperId(1)));
+        assert!(mixer.is_audible(LooperId(2)));
+        assert!(mixer.is_audible(LooperId(3)));
+
+        mixer.set_muted(LooperId(1), false);
+        assert!(!mixer.is_audible(LooperId(0)));
+        assert!(mixer.is_audible(LooperId(1)));
+
+        mixer.set_soloed(LooperId(0), true);
+        mixer.set_soloed(LooperId(1), true);
+        assert!(!mixer.track(LooperId(0)).unwrap().is_soloed());
+        assert!(mixer.track(LooperId(1)).unwrap().is_soloed());
+
+        // Tracks outside of groups aren't affected
+        mixer.set_group(LooperId(1), None);
+        mixer.set_muted(LooperId(0), false);
+        assert!(mixer.is_audible(LooperId(1)));
+    }
+
+    #[test]
+    fn test_send_levels() {
+        let (processors, mixer) = MixerProcessors::new(2, 3);
+        assert_eq!(processors.sends.len(), 2);
+        assert_eq!(processors.sends[0].len(), 3);
+        assert_eq!(mixer.aux_buses().len(), 3);
+
+        mixer.set_send_level(LooperId(1), 2, 0.5);
+        assert!((mixer.track(LooperId(1)).unwrap().send_level(2) - 0.5).abs() < f32::EPSILON);
+        assert!(mixer.track(LooperId(1)).unwrap().send_level(0).abs() < f32::EPSILON);
+        // Out of range is ignored
+        mixer.set_send_level(LooperId(1), 3, 0.5);
+        assert!(mixer.track(LooperId(1)).unwrap().send_level(3).abs() < f32::EPSILON);
+    }
+}
@@ -0,0 +1,305 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use audio_garbage_collector::Shared;
+use audio_processor_analysis::running_rms_processor::RunningRMSProcessor;
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+
+use super::limiter::Limiter;
+use super::{AuxBusHandle, MasterBusHandle, MixerHandle, TrackMixerHandle};
+use crate::audio::multi_track_looper::effects_processor::EffectsProcessor;
+use crate::audio::multi_track_looper::parameters::LooperId;
+
+/// Multiply `channel` by a gain ramping linearly from `from` to `to` over the block, so gain
+/// changes don't click
+fn apply_gain_ramp(data: &mut AudioBuffer<f32>, channel: usize, from: f32, to: f32) {
+    let samples = data.channel_mut(channel);
+    let step = (to - from) / samples.len().max(1) as f32;
+    for (index, sample) in samples.iter_mut().enumerate() {
+        *sample *= from + step * (index + 1) as f32;
+    }
+}
+
+/// Applies pan & mute/solo to a track, then meters it
+pub struct TrackMixerProcessor {
+    mixer: Shared<MixerHandle>,
+    looper_id: LooperId,
+    /// Left & right gains applied on the last block
+    gains: [f32; 2],
+    meter: RunningRMSProcessor,
+}
+
+impl TrackMixerProcessor {
+    pub fn new(
+        mixer: Shared<MixerHandle>,
+        looper_id: LooperId,
+        meter: RunningRMSProcessor,
+    ) -> Self {
+        Self {
+            mixer,
+            looper_id,
+            gains: [1.0, 1.0],
+            meter,
+        }
+    }
+
+    /// Target left & right gains. Pan is a balance control, so centered tracks are at unity
+    fn target_gains(&self) -> [f32; 2] {
+        let track = match self.mixer.track(self.looper_id) {
+            Some(track) => track,
+            None => return [0.0, 0.0],
+        };
+        if !self.mixer.is_audible(self.looper_id) {
+            return [0.0, 0.0];
+        }
+
+        let pan = track.pan();
+        [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)]
+    }
+}
+
+impl AudioProcessor for TrackMixerProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.meter.prepare(context);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        let target = self.target_gains();
+        if data.num_channels() == 1 {
+            // Mono output can't be panned
+            let to = target[0].max(target[1]);
+            apply_gain_ramp(data, 0, self.gains[0].max(self.gains[1]), to);
+        } else {
+            for channel in 0..data.num_channels() {
+                let side = channel.min(1);
+                apply_gain_ramp(data, channel, self.gains[side], target[side]);
+            }
+        }
+        self.gains = target;
+
+        self.meter.process(context, data);
+    }
+}
+
+/// Scales a track by its send level into an aux bus
+pub struct SendProcessor {
+    track: Shared<TrackMixerHandle>,
+    bus: usize,
+    level: f32,
+}
+
+impl SendProcessor {
+    pub fn new(track: Shared<TrackMixerHandle>, bus: usize) -> Self {
+        let level = track.send_level(bus);
+        Self { track, bus, level }
+    }
+}
+
+impl AudioProcessor for SendProcessor {
+    type SampleType = f32;
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        let target = self.track.send_level(self.bus);
+        for channel in 0..data.num_channels() {
+            apply_gain_ramp(data, channel, self.level, target);
+        }
+        self.level = target;
+    }
+}
+
+/// Runs an aux bus effects chain & applies its return level
+pub struct AuxBusProcessor {
+    handle: Shared<AuxBusHandle>,
+    effects: EffectsProcessor,
+    meter: RunningRMSProcessor,
+    level: f32,
+}
+
+impl AuxBusProcessor {
+    pub fn new(
+        handle: Shared<AuxBusHandle>,
+        effects: EffectsProcessor,
+        meter: RunningRMSProcessor,
+    ) -> Self {
+        let level = handle.return_level();
+        Self {
+            handle,
+            effects,
+            meter,
+            level,
+        }
+    }
+}
+
+impl AudioProcessor for AuxBusProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.effects.prepare(context);
+        self.meter.prepare(context);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        self.effects.process(context, data);
+
+        let target = self.handle.return_level();
+        for channel in 0..data.num_channels() {
+            apply_gain_ramp(data, channel, self.level, target);
+        }
+        self.level = target;
+
+        self.meter.process(context, data);
+    }
+}
+
+/// Master volume & peak limiter
+pub struct MasterBusProcessor {
+    handle: Shared<MasterBusHandle>,
+    limiter: Limiter,
+    meter: RunningRMSProcessor,
+    volume: f32,
+    sample_rate: f32,
+}
+
+impl MasterBusProcessor {
+    pub fn new(handle: Shared<MasterBusHandle>, meter: RunningRMSProcessor) -> Self {
+        let volume = handle.volume();
+        Self {
+            handle,
+            limiter: Limiter::default(),
+            meter,
+            volume,
+            sample_rate: 44100.0,
+        }
+    }
+}
+
+impl AudioProcessor for MasterBusProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.sample_rate = context.settings.sample_rate();
+        self.meter.prepare(context);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        let target = self.handle.volume();
+        for channel in 0..data.num_channels() {
+            apply_gain_ramp(data, channel, self.volume, target);
+        }
+        self.volume = target;
+
+        let mut minimum_gain = 1.0;
+        if self.handle.is_limiter_enabled() {
+            self.limiter
+                .prepare(self.sample_rate, self.handle.limiter_release_ms());
+            let ceiling = 10.0_f32.powf(self.handle.limiter_ceiling_db() / 20.0);
+            for sample_num in 0..data.num_samples() {
+                let peak = (0..data.num_channels())
+                    .map(|channel| data.get(channel, sample_num).abs())
+                    .fold(0.0, f32::max);
+                let gain = self.limiter.process_frame(peak, ceiling);
+                for channel in 0..data.num_channels() {
+                    let sample = *data.get(channel, sample_num);
+                    data.set(channel, sample_num, sample * gain);
+                }
+                minimum_gain = f32::min(minimum_gain, gain);
+            }
+        }
+        self.handle.limiter_gain.set(minimum_gain);
+
+        self.meter.process(context, data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::super::MixerProcessors;
+    use super::*;
+
+    fn process(processor: &mut impl AudioProcessor<SampleType = f32>, value: f32) -> Vec<f32> {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        processor.prepare(&mut context);
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 64);
+        // Run twice so gain ramps settle
+        for _ in 0..2 {
+            for sample in buffer.slice_mut() {
+                *sample = value;
+            }
+            processor.process(&mut context, &mut buffer);
+        }
+        vec![*buffer.get(0, 63), *buffer.get(1, 63)]
+    }
+
+    #[test]
+    fn test_apply_gain_ramp() {
+        let mut buffer = AudioBuffer::from_interleaved(1, &[1.0, 1.0, 1.0, 1.0]);
+        apply_gain_ramp(&mut buffer, 0, 0.0, 1.0);
+        assert_eq!(buffer.channel(0), &[0.25, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn test_track_pan_and_mute() {
+        let (mut processors, mixer) = MixerProcessors::new(2, 2);
+        let track = &mut processors.tracks[0];
+        assert_eq!(process(track, 1.0), vec![1.0, 1.0]);
+        assert!(mixer.track(LooperId(0)).unwrap().level() > 0.0);
+
+        mixer.set_pan(LooperId(0), 0.5);
+        assert_eq!(process(track, 1.0), vec![0.5, 1.0]);
+        mixer.set_pan(LooperId(0), -1.0);
+        assert_eq!(process(track, 1.0), vec![1.0, 0.0]);
+
+        mixer.set_soloed(LooperId(1), true);
+        assert_eq!(process(track, 1.0), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_send_level() {
+        let (mut processors, mixer) = MixerProcessors::new(1, 2);
+        let send = &mut processors.sends[0][1];
+        assert_eq!(process(send, 1.0), vec![0.0, 0.0]);
+        mixer.set_send_level(LooperId(0), 1, 0.25);
+        assert_eq!(process(send, 1.0), vec![0.25, 0.25]);
+    }
+
+    #[test]
+    fn test_master_limiter() {
+        let (mut processors, mixer) = MixerProcessors::new(1, 2);
+        mixer.master().set_limiter_enabled(true);
+        let output = process(&mut processors.master, 2.0);
+        let ceiling = 10.0_f32.powf(-0.3 / 20.0);
+        assert!(output.iter().all(|sample| *sample <= ceiling + 1e-6));
+        assert!(mixer.master().limiter_gain_reduction_db() < -6.0);
+
+        mixer.master().set_limiter_enabled(false);
+        mixer.master().set_volume(0.5);
+        assert_eq!(process(&mut processors.master, 2.0), vec![1.0, 1.0]);
+        assert!(mixer.master().limiter_gain_reduction_db().abs() < f32::EPSILON);
+    }
+}
@@ -24,15 +24,18 @@
 //! This module provides a `MultiTrackLooperProcessor`, which can sequence and loop 8 looper tracks
 //! with shared tempo.
 use std::convert::TryFrom;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use assert_no_alloc::assert_no_alloc;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rustc_hash::FxHashMap as HashMap;
 
 use audio_garbage_collector::{make_shared, Shared};
 use audio_processor_analysis::running_rms_processor::RunningRMSProcessor;
 use audio_processor_graph::{AudioProcessorGraph, NodeType};
 use audio_processor_metronome::MetronomeProcessor;
+use audio_processor_standalone_midi::clock::{is_midi_clock_message, MidiClockFollower};
 use audio_processor_traits::{
     AudioBuffer, AudioContext, AudioProcessor, MidiEventHandler, MidiMessageLike,
 };
@@ -49,10 +52,11 @@ use self::looper_voice::{LooperVoice, VoiceProcessors};
 use self::metrics::audio_processor_metrics::AudioProcessorMetrics;
 use self::midi_button::{MIDIButton, MIDIButtonEvent};
 use self::midi_store::MidiStoreHandle;
+use self::mixer::{MixerProcessors, NUM_AUX_BUSES};
 use self::parameters::{LFOParameter, LooperId, ParameterId, ParameterValue};
 pub use self::parameters_map::ParametersMap;
-use self::trigger_model::step_tracker::StepTracker;
-use self::trigger_model::{find_current_beat_trigger, find_running_beat_trigger};
+use self::routing::{RoutingProcessors, StereoAdapter};
+use self::trigger_model::sequencer::TrackSequencer;
 
 pub(crate) mod allocator;
 mod copy_paste;
@@ -64,8 +68,10 @@ pub(crate) mod looper_voice;
 pub(crate) mod metrics;
 mod midi_button;
 pub(crate) mod midi_store;
+pub(crate) mod mixer;
 pub mod parameters;
 mod parameters_map;
+pub(crate) mod routing;
 pub(crate) mod scene_state;
 pub(crate) mod slice_worker;
 mod tempo_estimation;
@@ -86,35 +92,54 @@ type ParametersScratchIndexes = HashMap<ParameterId, usize>;
 /// graph TD
 ///    A{Input}
 ///
-///    A-->E[Looper 1]
+///    A-->C1{Input select 1}
+///     -->E[Looper 1]
 ///     -->G[Pitch-shifter 1]
 ///     -->F[Envelope 1]
 ///     -->H[Effects 1]
-///     -->X{Gain 1}
+///     -->X{Pan/Mute/Solo 1}
+///     -->B1{Output bus 1}
+///     -->Z[Master bus]
 ///     -->I{Output}
 ///
-///    A -->M[Looper 2]
+///    A -->C2{Input select 2}
+///     -->M[Looper 2]
 ///     -->K[Pitch-shifter 2]
 ///     -->J[Envelope 2]
 ///     -->L[Effects 2]
-///     -->Y{Gain 2}
-///     -->I{Output}
-///    A -->O[Looper ...]
+///     -->Y{Pan/Mute/Solo 2}
+///     -->B2{Output bus 2}
+///     -->Z
+///    A -->C3{Input select ...}
+///     -->O[Looper ...]
 ///     -->P[Pitch-shifter ...]
 ///     -->Q[Envelope ...]
 ///     -->R[Effects ...]
-///     -->S{Gain ...}
-///     -->I{Output}
+///     -->S{Pan/Mute/Solo ...}
+///     -->B3{Output bus ...}
+///     -->Z
+///
+///    X -->T1{Send 1/A}-->U[Aux bus A]-->Z
+///    X -->T2{Send 1/B}-->V[Aux bus B]-->Z
+///    Y -->T3{Send 2/A}-->U
+///    Y -->T4{Send 2/B}-->V
 /// ```
 pub struct MultiTrackLooper {
     graph: AudioProcessorGraph,
     handle: Shared<MultiTrackLooperHandle>,
-    step_trackers: Vec<StepTracker>,
+    sequencers: Vec<TrackSequencer>,
+    /// Rolls trigger probabilities, seeded off the audio-thread
+    rng: StdRng,
+    /// Holds the samples between two sequencer events when a callback is split
+    chunk_buffer: AudioBuffer<f32>,
     lfos: Vec<(Oscillator<f32>, Oscillator<f32>)>,
     metrics: AudioProcessorMetrics,
     parameters_scratch: ParametersScratch,
     parameter_scratch_indexes: ParametersScratchIndexes,
     record_midi_button: MIDIButton,
+    midi_clock: MidiClockFollower,
+    /// Time base for MIDI messages without host timestamps
+    midi_clock_origin: Instant,
 }
 
 impl Default for MultiTrackLooper {
@@ -135,6 +160,8 @@ impl MultiTrackLooper {
 
         let (processors, voices) =
             Self::build_voices(&options, num_voices, &time_info_provider, None);
+        let (mixer_processors, mixer_handle) = MixerProcessors::new(num_voices, NUM_AUX_BUSES);
+        let (routing_processors, routing_handle) = RoutingProcessors::new(num_voices);
 
         let (parameters_scratch, parameter_scratch_indexes) =
             Self::make_parameters_scratch(&voices);
@@ -151,25 +178,40 @@ impl MultiTrackLooper {
             input_meter_processor.handle().clone(),
             &metrics,
             voices,
+            mixer_handle,
+            routing_handle,
         ));
 
-        let step_trackers = processors.iter().map(|_| StepTracker::default()).collect();
+        let sequencers = processors
+            .iter()
+            .map(|_| TrackSequencer::default())
+            .collect();
         let lfos = processors
             .iter()
             .map(|_| (Oscillator::sine(44100.0), Oscillator::sine(44100.0)))
             .collect();
 
-        let graph = Self::build_audio_graph(input_meter_processor, processors, metronome);
+        let graph = Self::build_audio_graph(
+            input_meter_processor,
+            processors,
+            mixer_processors,
+            routing_processors,
+            metronome,
+        );
 
         Self {
             graph,
             handle,
-            step_trackers,
+            sequencers,
+            rng: StdRng::from_entropy(),
+            chunk_buffer: AudioBuffer::empty(),
             parameters_scratch,
             parameter_scratch_indexes,
             lfos,
             metrics,
             record_midi_button: MIDIButton::new(),
+            midi_clock: MidiClockFollower::default(),
+            midi_clock_origin: Instant::now(),
         }
     }
 
@@ -215,22 +257,37 @@ impl MultiTrackLooper {
         let (parameters_scratch, parameter_scratch_indexes) =
             Self::make_parameters_scratch(&voices);
         let metrics = AudioProcessorMetrics::from_handle(handle.metrics_handle().clone());
-        let step_trackers = processors.iter().map(|_| StepTracker::default()).collect();
+        let mixer_processors = MixerProcessors::from_handle(handle.mixer());
+        let routing_processors = RoutingProcessors::from_handle(handle.routing());
+        let sequencers = processors
+            .iter()
+            .map(|_| TrackSequencer::default())
+            .collect();
         let lfos = processors
             .iter()
             .map(|_| (Oscillator::sine(44100.0), Oscillator::sine(44100.0)))
             .collect();
-        let graph = Self::build_audio_graph(input_meter_processor, processors, metronome);
+        let graph = Self::build_audio_graph(
+            input_meter_processor,
+            processors,
+            mixer_processors,
+            routing_processors,
+            metronome,
+        );
 
         Self {
             graph,
             handle,
-            step_trackers,
+            sequencers,
+            rng: StdRng::from_entropy(),
+            chunk_buffer: AudioBuffer::empty(),
             lfos,
             metrics,
             parameters_scratch,
             parameter_scratch_indexes,
             record_midi_button: MIDIButton::new(),
+            midi_clock: MidiClockFollower::default(),
+            midi_clock_origin: Instant::now(),
         }
     }
 
@@ -265,10 +322,13 @@ impl MultiTrackLooper {
     fn build_audio_graph(
         input_meter: RunningRMSProcessor,
         processors: Vec<VoiceProcessors>,
+        mixer_processors: MixerProcessors,
+        routing_processors: RoutingProcessors,
         metronome: MetronomeProcessor<TimeInfoMetronomePlayhead>,
     ) -> AudioProcessorGraph {
         let mut graph = AudioProcessorGraph::default();
-        let metronome_idx = graph.add_node(NodeType::Simple(Box::new(metronome)));
+        let metronome_idx =
+            graph.add_node(NodeType::Simple(Box::new(StereoAdapter::new(metronome))));
         let input_meter_node_idx = graph.add_node(NodeType::Simple(Box::new(input_meter)));
         graph
             .add_connection(graph.input(), input_meter_node_idx)
@@ -280,20 +340,67 @@ impl MultiTrackLooper {
             .add_connection(metronome_idx, graph.output())
             .expect("Shouldn't produce loop");
 
-        for VoiceProcessors {
-            looper,
-            pitch_shifter,
-            envelope,
-            effects_processor,
-        } in processors
+        let MixerProcessors {
+            tracks,
+            sends,
+            aux_buses,
+            master,
+        } = mixer_processors;
+        // Master volume & limiting only apply to the main output, tracks routed to other output
+        // buses are passed through
+        let master_idx =
+            graph.add_node(NodeType::Simple(Box::new(StereoAdapter::main_bus(master))));
+        graph
+            .add_connection(master_idx, graph.output())
+            .expect("Shouldn't produce loop");
+        let aux_bus_indexes: Vec<_> = aux_buses
+            .into_iter()
+            .map(|aux_bus| {
+                let aux_bus_idx =
+                    graph.add_node(NodeType::Simple(Box::new(StereoAdapter::new(aux_bus))));
+                graph
+                    .add_connection(aux_bus_idx, master_idx)
+                    .expect("Shouldn't produce loop");
+                aux_bus_idx
+            })
+            .collect();
+
+        let RoutingProcessors { inputs, outputs } = routing_processors;
+
+        for (
+            (
+                VoiceProcessors {
+                    looper,
+                    pitch_shifter,
+                    envelope,
+                    effects_processor,
+                    ..
+                },
+                (track_mixer, track_sends),
+            ),
+            (input_select, output_router),
+        ) in processors
+            .into_iter()
+            .zip(tracks.into_iter().zip(sends))
+            .zip(inputs.into_iter().zip(outputs))
         {
-            let looper_idx = graph.add_node(NodeType::Simple(Box::new(looper)));
-            let pitch_shifter_idx = graph.add_node(NodeType::Simple(Box::new(pitch_shifter)));
-            let envelope_idx = graph.add_node(NodeType::Simple(Box::new(envelope)));
-            let effects_idx = graph.add_node(NodeType::Simple(Box::new(effects_processor)));
+            // Track processors are stereo regardless of how many channels the device has
+            let input_select_idx = graph.add_node(NodeType::Simple(Box::new(input_select)));
+            let looper_idx = graph.add_node(NodeType::Simple(Box::new(StereoAdapter::new(looper))));
+            let pitch_shifter_idx = graph.add_node(NodeType::Simple(Box::new(StereoAdapter::new(
+                pitch_shifter,
+            ))));
+            let envelope_idx =
+                graph.add_node(NodeType::Simple(Box::new(StereoAdapter::new(envelope))));
+            let effects_idx = graph.add_node(NodeType::Simple(Box::new(StereoAdapter::new(
+                effects_processor,
+            ))));
 
             graph
-                .add_connection(graph.input(), looper_idx)
+                .add_connection(graph.input(), input_select_idx)
+                .expect("Shouldn't produce loop");
+            graph
+                .add_connection(input_select_idx, looper_idx)
                 .expect("Shouldn't produce loop");
             graph
                 .add_connection(looper_idx, pitch_shifter_idx)
@@ -304,72 +411,179 @@ impl MultiTrackLooper {
             graph
                 .add_connection(envelope_idx, effects_idx)
                 .expect("Shouldn't produce loop");
+
+            let track_mixer_idx = graph.add_node(NodeType::Simple(Box::new(track_mixer)));
+            graph
+                .add_connection(effects_idx, track_mixer_idx)
+                .expect("Shouldn't produce loop");
+            let output_router_idx = graph.add_node(NodeType::Simple(Box::new(output_router)));
             graph
-                .add_connection(effects_idx, graph.output())
+                .add_connection(track_mixer_idx, output_router_idx)
                 .expect("Shouldn't produce loop");
+            graph
+                .add_connection(output_router_idx, master_idx)
+                .expect("Shouldn't produce loop");
+            for (send, aux_bus_idx) in track_sends.into_iter().zip(&aux_bus_indexes) {
+                let send_idx = graph.add_node(NodeType::Simple(Box::new(send)));
+                graph
+                    .add_connection(track_mixer_idx, send_idx)
+                    .expect("Shouldn't produce loop");
+                graph
+                    .add_connection(send_idx, *aux_bus_idx)
+                    .expect("Shouldn't produce loop");
+            }
         }
 
         graph
     }
 }
 
-// Sequencer parameter-locks handling
+// Sequencer handling
+//
+// Callbacks are split wherever a trigger fires or ends, so triggers and their parameter-locks
+// apply on the exact sample they fall on.
 impl MultiTrackLooper {
-    fn process_triggers(&mut self) {
-        if let Some(position_beats) = self
-            .handle
-            .time_info_provider()
-            .get_time_info()
-            .position_beats()
-        {
-            let step_trackers = &mut self.step_trackers;
-            let parameters_scratch = &mut self.parameters_scratch;
-            let parameters_scratch_indexes = &self.parameter_scratch_indexes;
-
-            for (voice, step_tracker) in self.handle.voices().iter().zip(step_trackers) {
-                Self::process_triggers_for_voice(
-                    parameters_scratch_indexes,
-                    parameters_scratch,
-                    voice,
-                    step_tracker,
-                    position_beats,
-                )
+    /// Block-start position in beats & beats per sample, if the tempo is known
+    fn sequencer_transport(&self, sample_rate: f32) -> Option<(f64, f64)> {
+        let time_info = self.handle.time_info_provider().get_time_info();
+        let position_beats = time_info.position_beats()?;
+        let tempo = time_info.tempo()?;
+        let beats_per_sample = if time_info.is_playing() {
+            tempo / 60.0 / sample_rate as f64
+        } else {
+            0.0
+        };
+        // Events fire on the sample closest to them, so the sequencer runs half a sample ahead
+        Some((position_beats + 0.5 * beats_per_sample, beats_per_sample))
+    }
+
+    fn process_triggers(&mut self, position_beats: Option<f64>) {
+        let position_beats = match position_beats {
+            Some(position_beats) => position_beats,
+            None => {
+                for sequencer in self.sequencers.iter_mut() {
+                    sequencer.reset();
+                }
+                return;
             }
+        };
+
+        let beats_per_bar = self.handle.metronome_handle().beats_per_bar() as f64;
+        let sequencer_handle = self.handle.sequencer();
+        let parameters_scratch = &mut self.parameters_scratch;
+        let parameters_scratch_indexes = &self.parameter_scratch_indexes;
+        let rng = &mut self.rng;
+
+        for (voice, sequencer) in self.handle.voices().iter().zip(&mut self.sequencers) {
+            let trigger_model = voice.trigger_model();
+            if sequencer.advance(
+                trigger_model,
+                sequencer_handle,
+                beats_per_bar,
+                position_beats,
+                rng,
+            ) > 0
+            {
+                voice.looper().trigger();
+                voice.envelope().adsr_envelope.note_on();
+            }
+
+            Self::process_trigger_locks_for_voice(
+                parameters_scratch_indexes,
+                parameters_scratch,
+                voice,
+                sequencer,
+                position_beats,
+            );
         }
     }
 
-    fn process_triggers_for_voice(
+    fn process_trigger_locks_for_voice(
         parameters_scratch_indexes: &ParametersScratchIndexes,
         parameters_scratch: &mut ParametersScratch,
         voice: &LooperVoice,
-        step_tracker: &mut StepTracker,
+        sequencer: &TrackSequencer,
         position_beats: f64,
     ) {
-        let triggers = voice.trigger_model();
-
-        let triggers_vec = triggers.triggers();
-        if let Some(_trigger) =
-            find_current_beat_trigger(triggers, &triggers_vec, step_tracker, position_beats)
-        {
-            voice.looper().trigger();
-            voice.envelope().adsr_envelope.note_on();
-        }
-
-        let index = voice.id;
-        let parameters_scratch = &mut parameters_scratch[index];
+        let (pattern, step) = match sequencer.active_step(position_beats) {
+            Some(active_step) => active_step,
+            None => {
+                voice.envelope().adsr_envelope.note_off();
+                return;
+            }
+        };
 
-        let triggers = find_running_beat_trigger(triggers, &triggers_vec, position_beats);
-        let mut has_triggers = false;
-        for trigger in triggers {
-            has_triggers = true;
+        let trigger_model = voice.trigger_model();
+        let triggers = trigger_model.pattern_triggers(pattern);
+        let parameters_scratch = &mut parameters_scratch[voice.id];
+        if let Some(trigger) = trigger_model.find_step(&triggers, step) {
             for (parameter_id, lock) in trigger.locks() {
                 let parameter_idx = parameters_scratch_indexes[parameter_id];
                 parameters_scratch[parameter_idx] = ParameterValue::Float(lock.value().into());
             }
         }
+    }
 
-        if !has_triggers {
-            voice.envelope().adsr_envelope.note_off();
+    /// Number of samples until the next trigger fires or ends, at most `remaining`
+    fn next_chunk_length(
+        &self,
+        position_beats: f64,
+        beats_per_sample: f64,
+        remaining: usize,
+    ) -> usize {
+        if beats_per_sample <= 0.0 {
+            return remaining;
+        }
+
+        let beats_per_bar = self.handle.metronome_handle().beats_per_bar() as f64;
+        let to = position_beats + (remaining - 1) as f64 * beats_per_sample;
+        let next_boundary = self
+            .handle
+            .voices()
+            .iter()
+            .zip(&self.sequencers)
+            .filter_map(|(voice, sequencer)| {
+                sequencer.next_boundary(
+                    voice.trigger_model(),
+                    self.handle.sequencer(),
+                    beats_per_bar,
+                    position_beats,
+                    to,
+                )
+            })
+            .fold(None, |next: Option<f64>, boundary| {
+                Some(next.map_or(boundary, |next| next.min(boundary)))
+            });
+
+        match next_boundary {
+            Some(boundary) => (((boundary - position_beats) / beats_per_sample).ceil() as usize)
+                .clamp(1, remaining),
+            None => remaining,
+        }
+    }
+
+    /// Process `length` samples starting at `offset` through the graph
+    fn process_chunk(
+        &mut self,
+        context: &mut AudioContext,
+        data: &mut AudioBuffer<f32>,
+        offset: usize,
+        length: usize,
+    ) {
+        for (chunk, channel) in self
+            .chunk_buffer
+            .channels_mut()
+            .iter_mut()
+            .zip(data.channels())
+        {
+            chunk.resize(length, 0.0);
+            chunk.copy_from_slice(&channel[offset..offset + length]);
+        }
+
+        self.graph.process(context, &mut self.chunk_buffer);
+
+        for (chunk, channel) in self.chunk_buffer.channels().iter().zip(data.channels_mut()) {
+            channel[offset..offset + length].copy_from_slice(chunk);
         }
     }
 }
@@ -545,9 +759,14 @@ impl AudioProcessor for MultiTrackLooper {
 
     fn prepare(&mut self, context: &mut AudioContext) {
         let settings = context.settings;
+        self.handle
+            .routing()
+            .prepare(settings.input_channels(), settings.output_channels());
         self.graph.prepare(context);
         self.handle.metrics_handle().prepare(settings);
         self.handle.set_settings(make_shared(settings));
+        self.chunk_buffer
+            .resize(settings.output_channels(), settings.block_size());
 
         for (o1, o2) in self.lfos.iter_mut() {
             o1.set_sample_rate(settings.sample_rate());
@@ -559,27 +778,76 @@ impl AudioProcessor for MultiTrackLooper {
         assert_no_alloc(|| {
             self.metrics.on_process_start();
 
-            self.process_scenes();
-            self.process_triggers();
-            self.process_lfos();
-            self.flush_parameters();
-
-            self.graph.process(context, data);
+            let num_samples = data.num_samples();
+            let transport = self.sequencer_transport(context.settings.sample_rate());
+            // Splitting needs the chunk buffer to match the callback's layout, otherwise triggers
+            // fall on the start of the block
+            let can_split = data.num_channels() == self.chunk_buffer.num_channels()
+                && num_samples
+                    <= self
+                        .chunk_buffer
+                        .channels()
+                        .first()
+                        .map_or(0, Vec::capacity);
+
+            let mut offset = 0;
+            while offset < num_samples {
+                let remaining = num_samples - offset;
+                let position_beats = transport.map(|(position_beats, beats_per_sample)| {
+                    position_beats + offset as f64 * beats_per_sample
+                });
+
+                self.process_scenes();
+                self.process_triggers(position_beats);
+                self.process_lfos();
+                self.flush_parameters();
+
+                let length = match (transport, position_beats) {
+                    (Some((_, beats_per_sample)), Some(position_beats)) if can_split => {
+                        self.next_chunk_length(position_beats, beats_per_sample, remaining)
+                    }
+                    _ => remaining,
+                };
+                if length == num_samples {
+                    self.graph.process(context, data);
+                } else {
+                    self.process_chunk(context, data, offset, length);
+                }
 
-            // Ideally this wouldn't be in bulk. Perhaps this is the wrong approach and we should
-            // be just reading time from somewhere
-            self.tick_lfos(data.num_samples() as f32);
-            self.handle
-                .time_info_provider()
-                .tick_n(data.num_samples() as u32);
+                // Ideally this wouldn't be in bulk. Perhaps this is the wrong approach and we
+                // should be just reading time from somewhere
+                self.tick_lfos(length as f32);
+                self.handle.time_info_provider().tick_n(length as u32);
+                offset += length;
+            }
 
             self.metrics.on_process_end();
         });
     }
 }
 
+impl MultiTrackLooper {
+    fn process_midi_clock<Message: MidiMessageLike>(&mut self, midi_messages: &[Message]) {
+        for message in midi_messages {
+            let bytes = match message.bytes() {
+                Some(bytes) if is_midi_clock_message(bytes) => bytes,
+                _ => continue,
+            };
+            let timestamp = message
+                .timestamp()
+                .unwrap_or_else(|| self.midi_clock_origin.elapsed().as_micros() as u64);
+
+            if let Some(event) = self.midi_clock.accept(timestamp, bytes) {
+                self.handle.on_midi_clock_event(event, &self.midi_clock);
+            }
+        }
+    }
+}
+
 impl MidiEventHandler for MultiTrackLooper {
     fn process_midi_events<Message: MidiMessageLike>(&mut self, midi_messages: &[Message]) {
+        self.process_midi_clock(midi_messages);
+
         let midi_store = self.handle.midi().clone();
         MidiStoreHandle::process_midi_events(&midi_store, midi_messages, self);
 
@@ -590,7 +858,7 @@ impl MidiEventHandler for MultiTrackLooper {
 
 #[cfg(test)]
 mod test {
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
     use audio_processor_testing_helpers::{assert_f_eq, sine_buffer};
     use basedrop::Owned;
@@ -599,11 +867,14 @@ mod test {
     use audio_processor_traits::AudioProcessorSettings;
 
     use crate::audio::midi_map::MidiControllerNumber;
+    use crate::audio::multi_track_looper::effects_processor::EffectType;
     use crate::audio::multi_track_looper::parameters::EntityId;
     use crate::audio::processor::handle::LooperState;
     use crate::parameters::SourceParameter;
     use crate::LooperHandleThread;
 
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
+
     use super::*;
 
     #[test]
@@ -623,7 +894,7 @@ mod test {
         assert_eq!(value.clone(), ParameterValue::Float(2.0.into()));
 
         looper.handle.set_scene_value(0.5);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process_scenes();
         });
         assert_eq!(
@@ -636,7 +907,7 @@ mod test {
     fn test_scenes_dont_allocate() {
         let mut looper = MultiTrackLooper::default();
         let handle = std::thread::spawn(move || {
-            assert_no_alloc(|| {
+            assert_realtime_safe(|| {
                 looper.process_scenes();
                 looper.flush_parameters();
             });
@@ -652,7 +923,7 @@ mod test {
             .handle
             .set_source_parameter(LooperId(0), SourceParameter::Speed, 2.0);
         looper.process_scenes();
-        looper.process_triggers();
+        looper.process_triggers(None);
         looper.process_lfos();
         looper.flush_parameters();
         assert_f_eq!(looper.handle.voices()[0].looper().speed(), 2.0);
@@ -669,13 +940,13 @@ mod test {
             ParameterId::ParameterIdSource(SourceParameter::Speed),
             2.0,
         );
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process_scenes();
             looper.flush_parameters();
         });
         assert_f_eq!(looper.handle.voices()[0].looper().speed(), 1.0);
         looper.handle.set_scene_value(0.5);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process_scenes();
         });
         assert_eq!(
@@ -683,12 +954,53 @@ mod test {
                 [&ParameterId::ParameterIdSource(SourceParameter::Speed)]],
             ParameterValue::Float(1.5.into())
         );
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.flush_parameters();
         });
         assert_f_eq!(looper.handle.voices()[0].looper().speed(), 1.5);
     }
 
+    #[test]
+    fn test_scenes_lock_effect_parameters() {
+        let mut looper = MultiTrackLooper::default();
+        let slot_id = looper
+            .handle
+            .add_effect(LooperId(0), EffectType::EffectTypeGainPan)
+            .unwrap();
+        let parameter_id = ParameterId::ParameterIdEffect(slot_id, 0);
+
+        looper.handle.set_scene_value(0.0);
+        looper
+            .handle
+            .add_scene_parameter_lock(1, LooperId(0), parameter_id, 0.0);
+        looper.handle.set_scene_value(0.5);
+        assert_realtime_safe(|| {
+            looper.process_scenes();
+            looper.flush_parameters();
+        });
+
+        let slot = looper.handle.voices()[0].effects().slot(slot_id).unwrap();
+        assert_f_eq!(slot.parameter(0).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn test_effects_added_on_a_voice_can_be_locked() {
+        let mut looper = MultiTrackLooper::default();
+        let effects = looper.handle.voices()[0].effects().clone();
+        let slot_id = effects.add_effect(EffectType::EffectTypeGainPan).unwrap();
+        let parameter_id = ParameterId::ParameterIdEffect(slot_id, 0);
+
+        looper.handle.set_scene_value(0.0);
+        looper
+            .handle
+            .add_scene_parameter_lock(1, LooperId(0), parameter_id, 0.0);
+        looper.handle.set_scene_value(0.5);
+        looper.process_scenes();
+        looper.flush_parameters();
+
+        assert_f_eq!(effects.slot(slot_id).unwrap().parameter(0).unwrap(), 0.5);
+    }
+
     #[test]
     fn test_build_parameters_table() {
         use itertools::Itertools;
@@ -771,6 +1083,67 @@ mod test {
         assert_eq!(buffer.channel(0), [1.0, 2.0, 3.0, 4.0])
     }
 
+    #[test]
+    fn test_muted_tracks_are_silent_on_the_output() {
+        let mut processor = MultiTrackLooper::new(Default::default(), 2);
+        let mut settings = AudioProcessorSettings::default();
+        settings.sample_rate = 100.0;
+        settings.input_channels = 1;
+        settings.output_channels = 1;
+        let mut context = AudioContext::from(settings);
+        processor.prepare(&mut context);
+
+        let looper = processor.handle().voices()[0].looper().clone();
+        let looper_buffer = AudioBuffer::from_interleaved(1, &[1.0, 2.0, 3.0, 4.0]);
+        looper.set_looper_buffer(&looper_buffer);
+        looper.play();
+        processor.handle().mixer().set_muted(LooperId(0), true);
+
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize_with(1, 4, || 0.0);
+        // The first block fades out
+        processor.process(&mut context, &mut buffer);
+        for sample in buffer.slice_mut() {
+            *sample = 0.0;
+        }
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_tracks_play_into_their_output_bus() {
+        let mut processor = MultiTrackLooper::new(Default::default(), 2);
+        let mut settings = AudioProcessorSettings::default();
+        settings.sample_rate = 100.0;
+        settings.input_channels = 4;
+        settings.output_channels = 4;
+        let mut context = AudioContext::from(settings);
+        processor.prepare(&mut context);
+        assert_eq!(processor.handle().routing().num_output_buses(), 2);
+
+        for (index, value) in [1.0, 10.0].iter().enumerate() {
+            let looper = processor.handle().voices()[index].looper().clone();
+            let looper_buffer = AudioBuffer::new(vec![vec![*value; 4], vec![*value; 4]]);
+            looper.set_looper_buffer(&looper_buffer);
+            looper.play();
+        }
+        processor.handle().routing().set_output_bus(LooperId(1), 1);
+        processor.handle().mixer().master().set_volume(0.5);
+
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize_with(4, 4, || 0.0);
+        // The first block ramps the master volume
+        processor.process(&mut context, &mut buffer);
+        for sample in buffer.slice_mut() {
+            *sample = 0.0;
+        }
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [0.5, 0.5, 0.5, 0.5]);
+        assert_eq!(buffer.channel(1), [0.5, 0.5, 0.5, 0.5]);
+        assert_eq!(buffer.channel(2), [10.0, 10.0, 10.0, 10.0]);
+        assert_eq!(buffer.channel(3), [10.0, 10.0, 10.0, 10.0]);
+    }
+
     #[test]
     fn test_we_can_set_start_on_a_looper() {
         let mut processor = MultiTrackLooper::default();
@@ -824,6 +1197,7 @@ mod test {
         processor
             .handle()
             .toggle_recording(LooperId(0), LooperHandleThread::OtherThread);
+        wait_for_tempo_estimation(&processor);
         let mut buffer = AudioBuffer::empty();
         buffer.resize_with(1, num_samples, || 0.0);
         processor.process(&mut context, &mut buffer);
@@ -858,6 +1232,40 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_stopping_recording_on_the_audio_thread_estimates_tempo_in_the_background() {
+        let mut processor = MultiTrackLooper::default();
+        let mut settings = AudioProcessorSettings {
+            sample_rate: 10.0, // 10 samples per sec
+            ..AudioProcessorSettings::default()
+        };
+        settings.input_channels = 1;
+        settings.output_channels = 1;
+        let mut context = AudioContext::from(settings);
+        processor.prepare(&mut context);
+
+        processor
+            .handle()
+            .toggle_recording(LooperId(0), LooperHandleThread::AudioThread);
+        let mut buffer = range_buffer(40);
+        processor.process(&mut context, &mut buffer);
+        let handle = processor.handle().clone();
+        assert_realtime_safe(|| {
+            handle.toggle_recording(LooperId(0), LooperHandleThread::AudioThread);
+        });
+
+        wait_for_tempo_estimation(&processor);
+        assert!(processor.handle().metronome_handle().is_playing());
+        assert_eq!(
+            processor
+                .handle()
+                .time_info_provider()
+                .get_time_info()
+                .tempo(),
+            Some(processor.handle().metronome_handle().tempo() as f64)
+        );
+    }
+
     #[test]
     fn test_record_into_two_synced_loopers() {
         let mut processor = MultiTrackLooper::default();
@@ -884,6 +1292,7 @@ mod test {
         processor
             .handle()
             .toggle_recording(LooperId(0), LooperHandleThread::OtherThread);
+        wait_for_tempo_estimation(&processor);
 
         // Advance by 10 samples
         let mut buffer = AudioBuffer::empty();
@@ -961,6 +1370,23 @@ mod test {
         );
     }
 
+    /// Tempo is estimated on a background thread, which starts the play-head once it's done
+    fn wait_for_tempo_estimation(processor: &MultiTrackLooper) {
+        let start = Instant::now();
+        while !processor
+            .handle()
+            .time_info_provider()
+            .get_time_info()
+            .is_playing()
+        {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Timed-out waiting for tempo estimation"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
     fn range_buffer(num_samples: usize) -> AudioBuffer<f32> {
         let mut buffer = AudioBuffer::empty();
         buffer.resize(1, num_samples);
@@ -984,6 +1410,76 @@ mod test {
         looper.process_scenes();
     }
 
+    fn build_sequenced_looper() -> (MultiTrackLooper, AudioContext) {
+        let mut processor = MultiTrackLooper::new(Default::default(), 1);
+        let mut settings = AudioProcessorSettings::default();
+        settings.sample_rate = 100.0;
+        settings.input_channels = 1;
+        settings.output_channels = 1;
+        settings.block_size = 64;
+        let mut context = AudioContext::from(settings);
+        processor.prepare(&mut context);
+
+        let clip: Vec<f32> = (1..=100).map(|i| i as f32).collect();
+        let looper = processor.handle().voices()[0].looper().clone();
+        looper.set_looper_buffer(&AudioBuffer::from_interleaved(1, &clip));
+        looper.play();
+        processor.handle().metronome_handle().set_volume(0.0);
+        processor.handle().set_tempo(120.0);
+        (processor, context)
+    }
+
+    #[test]
+    fn test_triggers_fire_on_their_exact_sample() {
+        let (mut processor, mut context) = build_sequenced_looper();
+        // At 120bpm and 100Hz, step 2 (0.5 beats) falls on sample 25
+        processor.handle().toggle_trigger(LooperId(0), 2);
+        processor.handle().play();
+
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, 64);
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0)[24], 25.0);
+        assert_eq!(buffer.channel(0)[25], 1.0);
+        assert_eq!(buffer.channel(0)[26], 2.0);
+    }
+
+    #[test]
+    fn test_ratchets_retrigger_within_a_step() {
+        let (mut processor, mut context) = build_sequenced_looper();
+        processor.handle().toggle_trigger(LooperId(0), 2);
+        processor.handle().set_trigger_ratchets(LooperId(0), 2, 2);
+        processor.handle().play();
+
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, 64);
+        processor.process(&mut context, &mut buffer);
+        // The second ratchet falls half a step (6.25 samples) after the first
+        assert_eq!(buffer.channel(0)[30], 6.0);
+        assert_eq!(buffer.channel(0)[31], 1.0);
+    }
+
+    #[test]
+    fn test_sequencer_doesnt_alloc() {
+        let (mut processor, mut context) = build_sequenced_looper();
+        for step in 0..16 {
+            processor.handle().toggle_trigger(LooperId(0), step);
+        }
+        processor.handle().set_swing(0.5);
+        processor.handle().play();
+
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, 64);
+        let handle = std::thread::spawn(move || {
+            for _ in 0..10 {
+                assert_realtime_safe(|| {
+                    processor.process(&mut context, &mut buffer);
+                });
+            }
+        });
+        handle.join().unwrap();
+    }
+
     #[test]
     fn test_slicing_sets_offset() {
         let mut looper = MultiTrackLooper::new(Default::default(), 1);
@@ -1000,7 +1496,7 @@ mod test {
 
         looper.process_scenes();
         looper.process_lfos();
-        looper.process_triggers();
+        looper.process_triggers(None);
         let parameter = &looper.parameters_scratch[0][looper.parameter_scratch_indexes
             [&ParameterId::ParameterIdSource(SourceParameter::SliceId)]];
         let parameter: i32 = parameter.as_int();
@@ -1069,4 +1565,50 @@ mod test {
                 < 0.01 // MIDI has ~0.008 step-size (range: 0-127, resolution: 1 / 127)
         );
     }
+
+    #[test]
+    fn test_midi_clock_drives_the_playhead() {
+        use crate::audio::time_info_provider::ClockSource;
+
+        let mut looper = MultiTrackLooper::default();
+        looper
+            .handle()
+            .set_clock_source(ClockSource::ClockSourceMidiClock);
+
+        let message = |message_data: [u8; 3], timestamp: u64| {
+            MidiMessageEntry(Owned::new(
+                audio_garbage_collector::handle(),
+                MidiMessageWrapper {
+                    message_data,
+                    timestamp,
+                },
+            ))
+        };
+        let mut messages: Vec<MidiMessageEntry> =
+            (0..48).map(|i| message([0xF8, 0, 0], i * 20_833)).collect();
+        messages.push(message([0xFA, 0, 0], 48 * 20_833));
+        looper.process_midi_events(&messages);
+
+        let time_info = looper.handle().time_info_provider().get_time_info();
+        assert!(time_info.is_playing());
+        assert!((time_info.tempo().unwrap() - 120.0).abs() < 0.01);
+        assert!(looper.handle().metronome_handle().is_playing());
+
+        looper.process_midi_events(&[message([0xFC, 0, 0], 49 * 20_833)]);
+        assert!(!looper
+            .handle()
+            .time_info_provider()
+            .get_time_info()
+            .is_playing());
+        assert!(!looper.handle().metronome_handle().is_playing());
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            MultiTrackLooper::default(),
+            Default::default(),
+            10,
+        );
+    }
 }
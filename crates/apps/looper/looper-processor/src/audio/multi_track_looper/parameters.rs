@@ -30,6 +30,9 @@ use strum_macros::{EnumDiscriminants, EnumIter, EnumProperty};
 
 use augmented_atomics::{AtomicF32, AtomicValue};
 
+use crate::audio::multi_track_looper::effects_processor::{
+    MAX_EFFECT_PARAMETERS, MAX_EFFECT_SLOTS,
+};
 use crate::QuantizeMode;
 
 #[repr(transparent)]
@@ -63,6 +66,9 @@ pub enum ParameterId {
     ParameterIdLFO(usize, LFOParameter),
     #[serde(rename = "Q")]
     ParameterIdQuantization(QuantizationParameter),
+    /// A parameter of the effect on an effects chain slot; `(slot_id, parameter)`
+    #[serde(rename = "FX")]
+    ParameterIdEffect(usize, usize),
 }
 
 impl From<EnvelopeParameter> for ParameterId {
@@ -90,6 +96,12 @@ impl EnumProperty for ParameterId {
             ParameterId::ParameterIdEnvelope(parameter) => parameter.get_str(prop),
             ParameterId::ParameterIdLFO(_, parameter) => parameter.get_str(prop),
             ParameterId::ParameterIdQuantization(parameter) => parameter.get_str(prop),
+            // Effect parameters are all floats; their actual defaults are set by the effect
+            ParameterId::ParameterIdEffect(_, _) => match prop {
+                "type" => Some("float"),
+                "default" => Some("0.0"),
+                _ => None,
+            },
         }
     }
 }
@@ -363,6 +375,33 @@ impl ParameterValue {
             0
         }
     }
+
+    /// Numeric representation of any value, booleans are 0 or 1
+    pub fn to_f32(&self) -> f32 {
+        match self {
+            ParameterValue::Float(inner) => inner.get(),
+            ParameterValue::Bool(inner) => {
+                if inner.get() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ParameterValue::Enum(inner) => inner.get() as f32,
+            ParameterValue::Int(inner) => inner.get() as f32,
+        }
+    }
+
+    /// Build a value of the type declared for `parameter_id` from a number. Booleans are true
+    /// from 0.5 and integers are rounded.
+    pub fn from_f32(parameter_id: &ParameterId, value: f32) -> Self {
+        match parameter_id.get_str("type") {
+            Some("bool") => ParameterValue::Bool((value >= 0.5).into()),
+            Some("enum") => ParameterValue::Enum((value.round().max(0.0) as usize).into()),
+            Some("int") => ParameterValue::Int((value.round() as i32).into()),
+            _ => ParameterValue::Float(value.into()),
+        }
+    }
 }
 
 pub fn build_parameter_indexes(parameter_ids: &[ParameterId]) -> FxHashMap<ParameterId, usize> {
@@ -395,12 +434,19 @@ pub fn build_parameter_ids() -> Vec<ParameterId> {
     let quantization_parameters: Vec<ParameterId> = QuantizationParameter::iter()
         .map(ParameterId::ParameterIdQuantization)
         .collect();
+    let effect_parameters: Vec<ParameterId> = (0..MAX_EFFECT_SLOTS)
+        .flat_map(|slot_id| {
+            (0..MAX_EFFECT_PARAMETERS)
+                .map(move |parameter| ParameterId::ParameterIdEffect(slot_id, parameter))
+        })
+        .collect();
 
     source_parameters
         .iter()
         .chain(envelope_parameters.iter())
         .chain(lfo_parameters.iter())
         .chain(quantization_parameters.iter())
+        .chain(effect_parameters.iter())
         .cloned()
         .collect()
 }
@@ -80,12 +80,15 @@ impl ParametersMap {
         }
     }
 
-    /// Returns true if the parameter has been set after the default
+    /// Returns true if the parameter has been set after the default. Maps loaded from older
+    /// projects may not know about every parameter; those are never set.
     #[inline]
     pub fn has_value(&self, id: impl Into<ParameterId>) -> bool {
         let id: ParameterId = id.into();
-        let index: usize = self.indexes[&id];
-        self.has_value[index].get()
+        self.indexes
+            .get(&id)
+            .map(|index| self.has_value[*index].get())
+            .unwrap_or(false)
     }
 
     #[inline]
@@ -147,4 +150,15 @@ mod test {
         ps.set(SourceParameter::Start, 0.5);
         assert_eq!(ps.get(SourceParameter::Start).as_float(), 0.5_f32);
     }
+
+    #[test]
+    fn test_has_value_is_false_for_unknown_parameters() {
+        // Simulates a map saved before effect parameters existed
+        let mut ps = ParametersMap::new();
+        ps.indexes
+            .retain(|id, _| !matches!(id, ParameterId::ParameterIdEffect(_, _)));
+        ps.set(SourceParameter::Start, 0.5);
+        assert!(ps.has_value(SourceParameter::Start));
+        assert!(!ps.has_value(ParameterId::ParameterIdEffect(0, 0)));
+    }
 }
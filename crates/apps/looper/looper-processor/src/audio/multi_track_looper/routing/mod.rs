@@ -0,0 +1,189 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Routing of looper tracks to & from device channels.
+//!
+//! The looper's tracks are stereo. When running on a device (or in a plug-in host) with more
+//! channels, every track picks the input channels it records from and the output bus it plays
+//! into. Output bus 0 is the main mix, bus `n` is the channel pair `2n, 2n + 1`. Tracks routed to
+//! the same bus are summed, so a group of tracks can share an output.
+//!
+//! Aux buses & the metronome always play on the main mix, the master bus processes every output
+//! channel.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+
+use audio_garbage_collector::{make_shared, Shared};
+use augmented_atomics::AtomicValue;
+
+use super::parameters::LooperId;
+
+pub use self::processors::{InputSelectProcessor, OutputRouterProcessor, StereoAdapter};
+
+mod processors;
+
+pub struct TrackRoutingHandle {
+    input_channel: AtomicUsize,
+    input_is_mono: AtomicBool,
+    output_bus: AtomicUsize,
+}
+
+impl Default for TrackRoutingHandle {
+    fn default() -> Self {
+        Self {
+            input_channel: AtomicUsize::new(0),
+            input_is_mono: AtomicBool::new(false),
+            output_bus: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl TrackRoutingHandle {
+    /// First device channel this track records from. Defaults to 0
+    pub fn input_channel(&self) -> usize {
+        self.input_channel.get()
+    }
+
+    /// If true the track records `input_channel` onto both of its channels, otherwise it records
+    /// `input_channel` & the channel after it. Defaults to false
+    pub fn input_is_mono(&self) -> bool {
+        self.input_is_mono.get()
+    }
+
+    /// Output bus this track plays into. Defaults to 0, the main mix
+    pub fn output_bus(&self) -> usize {
+        self.output_bus.get()
+    }
+}
+
+pub struct RoutingHandle {
+    tracks: Vec<Shared<TrackRoutingHandle>>,
+    num_input_channels: AtomicUsize,
+    num_output_channels: AtomicUsize,
+}
+
+impl RoutingHandle {
+    pub fn tracks(&self) -> &[Shared<TrackRoutingHandle>] {
+        &self.tracks
+    }
+
+    pub fn track(&self, looper_id: LooperId) -> Option<&Shared<TrackRoutingHandle>> {
+        self.tracks.get(looper_id.0)
+    }
+
+    pub fn set_input(&self, looper_id: LooperId, input_channel: usize, is_mono: bool) {
+        if let Some(track) = self.track(looper_id) {
+            track.input_channel.set(input_channel);
+            track.input_is_mono.set(is_mono);
+        }
+    }
+
+    /// Route a track into `output_bus`. Buses the device doesn't have fall back to the main mix.
+    pub fn set_output_bus(&self, looper_id: LooperId, output_bus: usize) {
+        if let Some(track) = self.track(looper_id) {
+            track.output_bus.set(output_bus);
+        }
+    }
+
+    /// Number of input channels the looper was prepared with
+    pub fn num_input_channels(&self) -> usize {
+        self.num_input_channels.get()
+    }
+
+    /// Number of output channels the looper was prepared with
+    pub fn num_output_channels(&self) -> usize {
+        self.num_output_channels.get()
+    }
+
+    /// Number of stereo output buses available, including the main mix
+    pub fn num_output_buses(&self) -> usize {
+        (self.num_output_channels() / 2).max(1)
+    }
+
+    pub(crate) fn prepare(&self, num_input_channels: usize, num_output_channels: usize) {
+        self.num_input_channels.set(num_input_channels);
+        self.num_output_channels.set(num_output_channels);
+    }
+}
+
+/// Audio processors of the routing, which are moved into the looper's graph
+pub struct RoutingProcessors {
+    pub inputs: Vec<InputSelectProcessor>,
+    pub outputs: Vec<OutputRouterProcessor>,
+}
+
+impl RoutingProcessors {
+    pub fn new(num_tracks: usize) -> (Self, Shared<RoutingHandle>) {
+        let handle = make_shared(RoutingHandle {
+            tracks: (0..num_tracks)
+                .map(|_| make_shared(TrackRoutingHandle::default()))
+                .collect(),
+            num_input_channels: AtomicUsize::new(2),
+            num_output_channels: AtomicUsize::new(2),
+        });
+        (Self::from_handle(&handle), handle)
+    }
+
+    pub fn from_handle(handle: &Shared<RoutingHandle>) -> Self {
+        Self {
+            inputs: handle
+                .tracks
+                .iter()
+                .map(|track| InputSelectProcessor::new(track.clone()))
+                .collect(),
+            outputs: handle
+                .tracks
+                .iter()
+                .map(|track| OutputRouterProcessor::new(track.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_routing_handle() {
+        let (processors, routing) = RoutingProcessors::new(4);
+        assert_eq!(processors.inputs.len(), 4);
+        assert_eq!(processors.outputs.len(), 4);
+
+        routing.set_input(LooperId(1), 3, true);
+        routing.set_output_bus(LooperId(2), 2);
+        routing.set_output_bus(LooperId(10), 2);
+        let track = routing.track(LooperId(1)).unwrap();
+        assert_eq!(track.input_channel(), 3);
+        assert!(track.input_is_mono());
+        assert_eq!(track.output_bus(), 0);
+        assert_eq!(routing.track(LooperId(2)).unwrap().output_bus(), 2);
+
+        assert_eq!(routing.num_output_buses(), 1);
+        routing.prepare(8, 10);
+        assert_eq!(routing.num_input_channels(), 8);
+        assert_eq!(routing.num_output_buses(), 5);
+        routing.prepare(1, 1);
+        assert_eq!(routing.num_output_buses(), 1);
+    }
+}
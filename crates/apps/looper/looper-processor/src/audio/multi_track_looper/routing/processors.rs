@@ -0,0 +1,324 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use audio_garbage_collector::Shared;
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+
+use super::TrackRoutingHandle;
+
+/// Copies a track's selected input channels onto its first two channels & silences the rest
+pub struct InputSelectProcessor {
+    track: Shared<TrackRoutingHandle>,
+}
+
+impl InputSelectProcessor {
+    pub fn new(track: Shared<TrackRoutingHandle>) -> Self {
+        Self { track }
+    }
+}
+
+impl AudioProcessor for InputSelectProcessor {
+    type SampleType = f32;
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        let num_channels = data.num_channels();
+        if num_channels < 2 {
+            return;
+        }
+
+        let left = self.track.input_channel();
+        let right = if self.track.input_is_mono() {
+            left
+        } else {
+            left + 1
+        };
+        let read = |data: &AudioBuffer<f32>, channel: usize, sample_num: usize| {
+            if channel < num_channels {
+                *data.get(channel, sample_num)
+            } else {
+                0.0
+            }
+        };
+
+        for sample_num in 0..data.num_samples() {
+            let left_sample = read(data, left, sample_num);
+            let right_sample = read(data, right, sample_num);
+            data.set(0, sample_num, left_sample);
+            data.set(1, sample_num, right_sample);
+            for channel in 2..num_channels {
+                data.set(channel, sample_num, 0.0);
+            }
+        }
+    }
+}
+
+/// Moves a track from the first two channels onto the channel pair of its output bus
+pub struct OutputRouterProcessor {
+    track: Shared<TrackRoutingHandle>,
+}
+
+impl OutputRouterProcessor {
+    pub fn new(track: Shared<TrackRoutingHandle>) -> Self {
+        Self { track }
+    }
+}
+
+impl AudioProcessor for OutputRouterProcessor {
+    type SampleType = f32;
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        let num_channels = data.num_channels();
+        let first_channel = self.track.output_bus() * 2;
+        if first_channel == 0 || first_channel >= num_channels {
+            return;
+        }
+
+        let (main, rest) = data.channels_mut().split_at_mut(2);
+        // On odd channel counts the last bus is mono
+        let last_channel = (first_channel + 1).min(num_channels - 1);
+        for target in first_channel..=last_channel {
+            for sample in rest[target - 2].iter_mut() {
+                *sample = 0.0;
+            }
+        }
+        for (channel, source) in main.iter_mut().enumerate() {
+            let target = &mut rest[(first_channel + channel).min(last_channel) - 2];
+            for (target_sample, source_sample) in target.iter_mut().zip(source.iter_mut()) {
+                *target_sample += *source_sample;
+                *source_sample = 0.0;
+            }
+        }
+    }
+}
+
+/// Runs a stereo processor on the first two channels of buffers with more channels & silences the
+/// rest, so multi-channel devices don't change how (or how much memory) the processor runs with.
+pub struct StereoAdapter<P> {
+    processor: P,
+    context: AudioContext,
+    buffer: AudioBuffer<f32>,
+    pass_through_other_channels: bool,
+}
+
+impl<P> StereoAdapter<P> {
+    pub fn new(processor: P) -> Self {
+        Self {
+            processor,
+            context: AudioContext::default(),
+            buffer: AudioBuffer::empty(),
+            pass_through_other_channels: false,
+        }
+    }
+
+    /// Like [`StereoAdapter::new`], but channels past the first two are left untouched. Used for
+    /// the master bus, so tracks routed onto other output buses skip it.
+    pub fn main_bus(processor: P) -> Self {
+        Self {
+            pass_through_other_channels: true,
+            ..Self::new(processor)
+        }
+    }
+}
+
+impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for StereoAdapter<P> {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        let mut settings = context.settings;
+        settings.set_input_channels(settings.input_channels().min(2));
+        settings.set_output_channels(settings.output_channels().min(2));
+        self.buffer
+            .resize(settings.output_channels(), settings.block_size());
+        self.context = AudioContext::from(settings);
+        self.processor.prepare(&mut self.context);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+        if data.num_channels() <= 2 {
+            self.processor.process(context, data);
+            return;
+        }
+
+        self.buffer.resize(2, data.num_samples());
+        for channel in 0..2 {
+            self.buffer
+                .channel_mut(channel)
+                .copy_from_slice(data.channel(channel));
+        }
+        self.processor.process(&mut self.context, &mut self.buffer);
+        for channel in 0..2 {
+            data.channel_mut(channel)
+                .copy_from_slice(self.buffer.channel(channel));
+        }
+        if self.pass_through_other_channels {
+            return;
+        }
+        for channel in 2..data.num_channels() {
+            for sample in data.channel_mut(channel) {
+                *sample = 0.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::super::RoutingProcessors;
+    use super::*;
+    use crate::audio::multi_track_looper::parameters::LooperId;
+
+    fn four_channel_buffer() -> AudioBuffer<f32> {
+        AudioBuffer::new(vec![
+            vec![1.0, 1.0],
+            vec![2.0, 2.0],
+            vec![3.0, 3.0],
+            vec![4.0, 4.0],
+        ])
+    }
+
+    #[test]
+    fn test_input_select() {
+        let (mut processors, routing) = RoutingProcessors::new(1);
+        let processor = &mut processors.inputs[0];
+        let mut context = AudioContext::default();
+
+        let mut buffer = four_channel_buffer();
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [1.0, 1.0]);
+        assert_eq!(buffer.channel(1), [2.0, 2.0]);
+        assert_eq!(buffer.channel(2), [0.0, 0.0]);
+        assert_eq!(buffer.channel(3), [0.0, 0.0]);
+
+        routing.set_input(LooperId(0), 2, false);
+        let mut buffer = four_channel_buffer();
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [3.0, 3.0]);
+        assert_eq!(buffer.channel(1), [4.0, 4.0]);
+
+        routing.set_input(LooperId(0), 3, true);
+        let mut buffer = four_channel_buffer();
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [4.0, 4.0]);
+        assert_eq!(buffer.channel(1), [4.0, 4.0]);
+
+        // Missing channels are silent
+        routing.set_input(LooperId(0), 3, false);
+        let mut buffer = four_channel_buffer();
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [4.0, 4.0]);
+        assert_eq!(buffer.channel(1), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_output_router() {
+        let (mut processors, routing) = RoutingProcessors::new(1);
+        let processor = &mut processors.outputs[0];
+        let mut context = AudioContext::default();
+
+        let mut buffer = four_channel_buffer();
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channels(), four_channel_buffer().channels());
+
+        routing.set_output_bus(LooperId(0), 1);
+        let mut buffer = four_channel_buffer();
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [0.0, 0.0]);
+        assert_eq!(buffer.channel(1), [0.0, 0.0]);
+        assert_eq!(buffer.channel(2), [1.0, 1.0]);
+        assert_eq!(buffer.channel(3), [2.0, 2.0]);
+
+        // Buses the device doesn't have play on the main mix
+        routing.set_output_bus(LooperId(0), 2);
+        let mut buffer = four_channel_buffer();
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channels(), four_channel_buffer().channels());
+    }
+
+    #[test]
+    fn test_stereo_adapter() {
+        struct ChannelCountProcessor {
+            prepared_channels: usize,
+        }
+        impl AudioProcessor for ChannelCountProcessor {
+            type SampleType = f32;
+
+            fn prepare(&mut self, context: &mut AudioContext) {
+                self.prepared_channels = context.settings.output_channels();
+            }
+
+            fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+                assert_eq!(data.num_channels(), self.prepared_channels);
+                for sample in data.slice_mut() {
+                    *sample *= 2.0;
+                }
+            }
+        }
+
+        let mut adapter = StereoAdapter::new(ChannelCountProcessor {
+            prepared_channels: 0,
+        });
+        let mut settings = AudioProcessorSettings::default();
+        settings.set_output_channels(4);
+        settings.set_block_size(2);
+        let mut context = AudioContext::from(settings);
+        adapter.prepare(&mut context);
+        assert_eq!(adapter.processor.prepared_channels, 2);
+
+        let mut buffer = four_channel_buffer();
+        adapter.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [2.0, 2.0]);
+        assert_eq!(buffer.channel(1), [4.0, 4.0]);
+        assert_eq!(buffer.channel(2), [0.0, 0.0]);
+        assert_eq!(buffer.channel(3), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_main_bus_stereo_adapter_passes_other_channels_through() {
+        struct DoubleProcessor;
+        impl AudioProcessor for DoubleProcessor {
+            type SampleType = f32;
+
+            fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+                for sample in data.slice_mut() {
+                    *sample *= 2.0;
+                }
+            }
+        }
+
+        let mut adapter = StereoAdapter::main_bus(DoubleProcessor);
+        let mut settings = AudioProcessorSettings::default();
+        settings.set_output_channels(4);
+        settings.set_block_size(2);
+        let mut context = AudioContext::from(settings);
+        adapter.prepare(&mut context);
+
+        let mut buffer = four_channel_buffer();
+        adapter.process(&mut context, &mut buffer);
+        assert_eq!(buffer.channel(0), [2.0, 2.0]);
+        assert_eq!(buffer.channel(1), [4.0, 4.0]);
+        assert_eq!(buffer.channel(2), [3.0, 3.0]);
+        assert_eq!(buffer.channel(3), [4.0, 4.0]);
+    }
+}
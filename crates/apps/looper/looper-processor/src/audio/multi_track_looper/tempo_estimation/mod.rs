@@ -20,6 +20,11 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
+use audio_processor_analysis::beat_tracking::{track_beats, BeatTrackerOptions};
+use audio_processor_traits::AudioBuffer;
+
+pub mod worker;
+
 pub struct TimeSignature {
     beats_per_bar: usize,
 }
@@ -32,10 +37,15 @@ impl Default for TimeSignature {
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct TempoEstimate {
+    /// Number of whole bars in the loop
     pub num_bars: usize,
+    pub num_beats: usize,
     pub tempo: f32,
 }
 
+/// Beat tracking results with a lower tempo confidence are ignored
+const MINIMUM_TEMPO_CONFIDENCE: f32 = 0.2;
+
 /// Performs tempo estimation into a `TimeSignature` by trying to divide a certain audio length by
 /// a power of 2 number of bars between 1 and 256.
 ///
@@ -54,6 +64,7 @@ pub fn estimate_tempo(
     let mut tempo_candidate = TempoEstimate {
         tempo: 0.0,
         num_bars: 0,
+        num_beats: 0,
     };
     for i in 0..8 {
         let num_bars = 2u32.pow(i);
@@ -64,6 +75,7 @@ pub fn estimate_tempo(
         tempo_candidate = TempoEstimate {
             tempo: (1.0 / secs_per_beat) * 60.0,
             num_bars: num_bars as usize,
+            num_beats: num_bars as usize * time_signature.beats_per_bar,
         };
         if tempo_candidate.tempo >= 80.0 && tempo_candidate.tempo <= 160.0 {
             return tempo_candidate;
@@ -73,6 +85,53 @@ pub fn estimate_tempo(
     tempo_candidate
 }
 
+/// Performs tempo estimation on the audio of a recorded loop.
+///
+/// Beats are tracked over `clip` (see [`audio_processor_analysis::beat_tracking`]), then the
+/// tempo is adjusted so the loop holds a whole number of beats, since it'll be repeated. Unlike
+/// [`estimate_tempo`] this works for loops which aren't a power of 2 number of bars long, such as
+/// 3 bars or 6 beats.
+///
+/// Falls back to [`estimate_tempo`] on the clip length if no clear beat was found.
+pub fn estimate_tempo_from_audio(
+    time_signature: TimeSignature,
+    sample_rate: f32,
+    clip: &AudioBuffer<f32>,
+) -> TempoEstimate {
+    let length_samples = clip.num_samples();
+    let options = BeatTrackerOptions {
+        beats_per_bar: time_signature.beats_per_bar,
+        ..BeatTrackerOptions::default()
+    };
+    let beat_tracking = track_beats(sample_rate, options, clip).filter(|result| {
+        result.tempo_confidence >= MINIMUM_TEMPO_CONFIDENCE && result.beats.len() >= 2
+    });
+
+    match beat_tracking {
+        Some(result) => {
+            let samples_per_beat = sample_rate * 60.0 / result.tempo;
+            let num_beats = (length_samples as f32 / samples_per_beat).round().max(1.0);
+            log::info!(
+                "Tracked beats on loop tempo={} num_beats={} downbeat={:?} downbeat_confidence={}",
+                result.tempo,
+                num_beats,
+                result.first_downbeat(),
+                result.downbeat_confidence
+            );
+
+            TempoEstimate {
+                tempo: num_beats * sample_rate * 60.0 / length_samples as f32,
+                num_bars: num_beats as usize / time_signature.beats_per_bar,
+                num_beats: num_beats as usize,
+            }
+        }
+        None => {
+            log::info!("No beat found on loop, estimating tempo from its length");
+            estimate_tempo(time_signature, sample_rate, length_samples)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -103,6 +162,56 @@ mod test {
 
         let result = estimate_tempo(Default::default(), sample_rate, length_samples as usize);
         assert_eq!(result.num_bars, 4);
+        assert_eq!(result.num_beats, 16);
         assert_eq!(result.tempo, 120.0);
     }
+
+    /// Decaying 1kHz bursts on every beat
+    fn click_loop(sample_rate: f32, tempo: f32, num_beats: usize) -> AudioBuffer<f32> {
+        let samples_per_beat = sample_rate * 60.0 / tempo;
+        let length = (samples_per_beat * num_beats as f32) as usize;
+        let mut samples = vec![0.0; length];
+        for beat in 0..num_beats {
+            let start = (beat as f32 * samples_per_beat) as usize;
+            for i in 0..2000.min(length - start) {
+                let time = i as f32 / sample_rate;
+                samples[start + i] = (2.0 * std::f32::consts::PI * 1000.0 * time).sin()
+                    * (-(i as f32) / 300.0).exp();
+            }
+        }
+        AudioBuffer::from_interleaved(1, &samples)
+    }
+
+    #[test]
+    fn test_tempo_estimation_from_audio() {
+        let sample_rate = 44100.0;
+        let clip = click_loop(sample_rate, 120.0, 16);
+        let result = estimate_tempo_from_audio(Default::default(), sample_rate, &clip);
+        assert_eq!(result.num_bars, 4);
+        assert_eq!(result.num_beats, 16);
+        assert!((result.tempo - 120.0).abs() < 0.01, "{:?}", result);
+    }
+
+    #[test]
+    fn test_tempo_estimation_from_audio_on_loop_which_is_not_power_of_two_bars() {
+        let sample_rate = 44100.0;
+        let clip = click_loop(sample_rate, 100.0, 6);
+        // Length based estimation would have this as 1 bar of 66bpm
+        let result = estimate_tempo_from_audio(Default::default(), sample_rate, &clip);
+        assert_eq!(result.num_beats, 6);
+        assert_eq!(result.num_bars, 1);
+        assert!((result.tempo - 100.0).abs() < 0.01, "{:?}", result);
+    }
+
+    #[test]
+    fn test_tempo_estimation_from_silent_audio_falls_back_to_length() {
+        let sample_rate = 44100.0;
+        let length_samples = (sample_rate * 0.5 * 16.0) as usize;
+        let clip = AudioBuffer::from_interleaved(1, &vec![0.0; length_samples]);
+        let result = estimate_tempo_from_audio(Default::default(), sample_rate, &clip);
+        assert_eq!(
+            result,
+            estimate_tempo(Default::default(), sample_rate, length_samples)
+        );
+    }
 }
@@ -0,0 +1,147 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Contains a background-thread worker which estimates the tempo of a recorded loop & sets it as
+//! the global tempo. Beat tracking allocates & is too slow to run on the thread that stopped the
+//! recording, which may be the audio-thread.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use basedrop::Shared;
+
+use atomic_queue::Queue;
+use audio_garbage_collector::make_shared;
+use audio_processor_metronome::MetronomeProcessorHandle;
+
+use crate::audio::processor::handle::{looper_clip_copy_to_vec_buffer, LooperHandle};
+use crate::{TimeInfoProvider, TimeInfoProviderImpl};
+
+use super::{estimate_tempo_from_audio, TempoEstimate};
+
+/// Estimates over this tempo are ignored, the loop is likely too short
+const MAXIMUM_TEMPO: f32 = 300.0;
+
+struct TempoEstimationJob {
+    sample_rate: f32,
+    looper: Shared<LooperHandle>,
+}
+
+struct TempoEstimationThread {
+    job_queue: Shared<Queue<TempoEstimationJob>>,
+    is_running: Shared<AtomicBool>,
+    time_info_provider: Shared<TimeInfoProviderImpl>,
+    metronome_handle: Shared<MetronomeProcessorHandle>,
+}
+
+impl TempoEstimationThread {
+    fn run(&self) {
+        while self.is_running.load(Ordering::Relaxed) {
+            if let Some(job) = self.job_queue.pop() {
+                self.process_job(job);
+            }
+
+            std::thread::sleep(Duration::from_millis(10))
+        }
+    }
+
+    fn process_job(&self, job: TempoEstimationJob) {
+        let clip = looper_clip_copy_to_vec_buffer(&job.looper.looper_clip());
+        let estimate = estimate_tempo_from_audio(Default::default(), job.sample_rate, &clip);
+        self.apply_estimate(&job, estimate);
+    }
+
+    fn apply_estimate(&self, job: &TempoEstimationJob, estimate: TempoEstimate) {
+        if estimate.tempo > MAXIMUM_TEMPO {
+            log::warn!("This loop is too short tempo is ignored {}", estimate.tempo);
+            return;
+        }
+
+        log::info!("Setting global tempo to {}", estimate.tempo);
+        self.time_info_provider.set_tempo(estimate.tempo);
+        self.metronome_handle.set_tempo(estimate.tempo);
+        // The loop kept playing while its tempo was estimated, so the play-head starts where the
+        // loop is rather than at the beginning
+        self.time_info_provider
+            .playhead()
+            .set_position_seconds(job.looper.playhead() as f32 / job.sample_rate);
+        self.metronome_handle.set_is_playing(true);
+        self.time_info_provider.play();
+    }
+}
+
+pub struct TempoEstimationWorker {
+    job_queue: Shared<Queue<TempoEstimationJob>>,
+    is_running: Shared<AtomicBool>,
+}
+
+impl TempoEstimationWorker {
+    pub fn new(
+        time_info_provider: Shared<TimeInfoProviderImpl>,
+        metronome_handle: Shared<MetronomeProcessorHandle>,
+    ) -> Self {
+        let job_queue = make_shared(Queue::new(10));
+        let is_running = make_shared(AtomicBool::new(true));
+
+        let thread = TempoEstimationThread {
+            job_queue: job_queue.clone(),
+            is_running: is_running.clone(),
+            time_info_provider,
+            metronome_handle,
+        };
+        std::thread::Builder::new()
+            .name(String::from("looper_tempo_estimation_worker"))
+            .spawn(move || {
+                log::info!("Tempo estimation thread started");
+                thread.run();
+                log::info!("Tempo estimation thread exiting");
+            })
+            .unwrap();
+
+        Self {
+            job_queue,
+            is_running,
+        }
+    }
+
+    /// Estimate the tempo of the loop recorded on `looper` & set it as the global tempo. Doesn't
+    /// allocate, so it may be called from the audio-thread. The job is dropped if the queue is
+    /// full.
+    pub fn add_job(&self, sample_rate: f32, looper: Shared<LooperHandle>) {
+        let was_queued = self.job_queue.push(TempoEstimationJob {
+            sample_rate,
+            looper,
+        });
+        if !was_queued {
+            log::warn!("Tempo estimation queue is full, the loop's tempo won't be estimated");
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for TempoEstimationWorker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
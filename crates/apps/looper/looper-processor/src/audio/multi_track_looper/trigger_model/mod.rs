@@ -24,59 +24,31 @@
 //!
 //! [`TrackTriggerModel`] is an object containing step-sequencer state. In particular it holds:
 //!
-//! * A list of [`Trigger`] objects, which should have some position within the sequencer
-//!   ([`TriggerPosition`]) and optionally have any number of [`TriggerLock`]s associated to
-//!   different [`ParameterId`]s
-//! * The sequencer options, such as number of steps (length) and step-size in beats
+//! * A list of [`Trigger`] objects for each of its patterns, which should have some position
+//!   within the sequencer ([`TriggerPosition`]) and optionally have any number of
+//!   [`TriggerLock`]s associated to different [`ParameterId`]s
+//! * The sequencer options, such as number of steps (length), step-size in beats and swing
+//!
+//! Triggers are turned into sample-accurate events by [`sequencer`].
 use std::collections::HashMap;
-use std::ops::Deref;
 use std::sync::atomic::AtomicUsize;
 
 use basedrop::{Shared, SharedCell};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use audio_garbage_collector::{make_shared, make_shared_cell};
-use augmented_atomics::{AtomicF32, AtomicValue};
-use step_tracker::StepTracker;
+use augmented_atomics::{AtomicF32, AtomicF64, AtomicOption, AtomicValue};
 
 use crate::audio::multi_track_looper::parameters::ParameterId;
 
-pub mod step_tracker;
-
-/// Return an iterator for all the triggers that are currently playing
-pub fn find_running_beat_trigger<'a>(
-    track_trigger_model: &TrackTriggerModel,
-    triggers: &'a Shared<Vec<Trigger>>,
-    position_beats: f64,
-) -> impl Iterator<Item = &'a Trigger> + 'a {
-    let position_beats = position_beats
-        % (track_trigger_model.pattern_length as f64 * track_trigger_model.pattern_step_beats);
-    let step_length_beats = track_trigger_model.pattern_step_beats;
-    let current_step = (position_beats / step_length_beats) as usize;
-
-    triggers
-        .deref()
-        .iter()
-        .filter(move |trigger| trigger.position.step.get() == current_step)
-}
+pub mod sequencer;
 
-/// If a trigger has just been fired, return a reference to it.
-/// This mutates the `StepTracker`.
-pub fn find_current_beat_trigger<'a>(
-    track_trigger_model: &'a TrackTriggerModel,
-    triggers: &'a Shared<Vec<Trigger>>,
-    step_tracker: &mut StepTracker,
-    position_beats: f64,
-) -> Option<&'a Trigger> {
-    step_tracker
-        .accept(
-            track_trigger_model.pattern_step_beats,
-            position_beats
-                % (track_trigger_model.pattern_length as f64
-                    * track_trigger_model.pattern_step_beats),
-        )
-        .and_then(|step| track_trigger_model.find_step(triggers, step))
-}
+/// Number of patterns each track holds
+pub const NUM_PATTERNS: usize = 16;
+/// Maximum number of steps in a pattern
+pub const MAX_PATTERN_LENGTH: usize = 64;
+/// Maximum number of times a trigger may re-fire within its step
+pub const MAX_RATCHETS: usize = 8;
 
 #[derive(Default, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct TriggerLock {
@@ -135,10 +107,107 @@ impl Clone for TriggerPosition {
     }
 }
 
+/// When a trigger fires, evaluated against the number of times its pattern has looped
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum TriggerCondition {
+    #[default]
+    Always,
+    /// Fire on iteration `offset` of every `every` iterations of the pattern, e.g. `every: 4,
+    /// offset: 3` fires on the 4th, 8th, 12th... time the pattern plays
+    Every { every: usize, offset: usize },
+    /// Fire only while fill mode is on
+    Fill,
+    /// Fire only while fill mode is off
+    NotFill,
+    /// Fire only on the first time the pattern plays
+    First,
+    /// Fire on every time the pattern plays except the first
+    NotFirst,
+}
+
+impl TriggerCondition {
+    pub fn is_met(&self, iteration: usize, is_fill: bool) -> bool {
+        match *self {
+            TriggerCondition::Always => true,
+            TriggerCondition::Every { every, offset } => {
+                let every = every.max(1);
+                iteration % every == offset % every
+            }
+            TriggerCondition::Fill => is_fill,
+            TriggerCondition::NotFill => !is_fill,
+            TriggerCondition::First => iteration == 0,
+            TriggerCondition::NotFirst => iteration != 0,
+        }
+    }
+}
+
+/// C API counterpart of [`TriggerCondition`]. `every` and `offset` are only read for
+/// `CTriggerConditionEvery`
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum CTriggerCondition {
+    CTriggerConditionAlways = 0,
+    CTriggerConditionEvery = 1,
+    CTriggerConditionFill = 2,
+    CTriggerConditionNotFill = 3,
+    CTriggerConditionFirst = 4,
+    CTriggerConditionNotFirst = 5,
+}
+
+impl CTriggerCondition {
+    pub fn into_condition(self, every: usize, offset: usize) -> TriggerCondition {
+        match self {
+            CTriggerCondition::CTriggerConditionAlways => TriggerCondition::Always,
+            CTriggerCondition::CTriggerConditionEvery => TriggerCondition::Every { every, offset },
+            CTriggerCondition::CTriggerConditionFill => TriggerCondition::Fill,
+            CTriggerCondition::CTriggerConditionNotFill => TriggerCondition::NotFill,
+            CTriggerCondition::CTriggerConditionFirst => TriggerCondition::First,
+            CTriggerCondition::CTriggerConditionNotFirst => TriggerCondition::NotFirst,
+        }
+    }
+}
+
+fn default_probability() -> f32 {
+    1.0
+}
+
+fn default_ratchets() -> usize {
+    1
+}
+
+// Project files may hold values the setters would have rejected, so they're clamped on load too
+
+fn deserialize_probability<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    Ok(f32::deserialize(deserializer)?.clamp(0.0, 1.0))
+}
+
+fn deserialize_micro_timing<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    Ok(f32::deserialize(deserializer)?.clamp(-0.5, 0.5))
+}
+
+fn deserialize_ratchets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    Ok(usize::deserialize(deserializer)?.clamp(1, MAX_RATCHETS))
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Trigger {
     inner: TriggerInner,
     position: TriggerPosition,
+    #[serde(
+        default = "default_probability",
+        deserialize_with = "deserialize_probability"
+    )]
+    probability: f32,
+    #[serde(default)]
+    condition: TriggerCondition,
+    #[serde(default, deserialize_with = "deserialize_micro_timing")]
+    micro_timing: f32,
+    #[serde(
+        default = "default_ratchets",
+        deserialize_with = "deserialize_ratchets"
+    )]
+    ratchets: usize,
 }
 
 impl Default for Trigger {
@@ -146,6 +215,10 @@ impl Default for Trigger {
         Trigger {
             inner: TriggerInner::LoopTrigger(LoopTrigger::default()),
             position: TriggerPosition::default(),
+            probability: default_probability(),
+            condition: TriggerCondition::default(),
+            micro_timing: 0.0,
+            ratchets: default_ratchets(),
         }
     }
 }
@@ -163,6 +236,41 @@ impl Trigger {
         self.position = position;
     }
 
+    /// Chance between 0 and 1 of this trigger firing when its condition is met. Defaults to 1
+    pub fn probability(&self) -> f32 {
+        self.probability
+    }
+
+    pub fn set_probability(&mut self, probability: f32) {
+        self.probability = probability.clamp(0.0, 1.0);
+    }
+
+    pub fn condition(&self) -> TriggerCondition {
+        self.condition
+    }
+
+    pub fn set_condition(&mut self, condition: TriggerCondition) {
+        self.condition = condition;
+    }
+
+    /// Offset of this trigger from its step, as a fraction of a step between -0.5 and 0.5
+    pub fn micro_timing(&self) -> f32 {
+        self.micro_timing
+    }
+
+    pub fn set_micro_timing(&mut self, micro_timing: f32) {
+        self.micro_timing = micro_timing.clamp(-0.5, 0.5);
+    }
+
+    /// Number of times this trigger fires, evenly spaced within its step. Defaults to 1
+    pub fn ratchets(&self) -> usize {
+        self.ratchets
+    }
+
+    pub fn set_ratchets(&mut self, ratchets: usize) {
+        self.ratchets = ratchets.clamp(1, MAX_RATCHETS);
+    }
+
     pub fn add_lock(&mut self, parameter_id: ParameterId, value: f32) {
         let TriggerInner::LoopTrigger(loop_trigger) = &mut self.inner;
         loop_trigger
@@ -182,32 +290,69 @@ impl Trigger {
 }
 
 pub struct TrackTriggerModel {
-    pattern_length: usize,
-    pattern_step_beats: f64,
-    triggers: SharedCell<Vec<Trigger>>,
+    pattern_length: AtomicUsize,
+    pattern_step_beats: AtomicF64,
+    swing: AtomicOption<AtomicF32>,
+    selected_pattern: AtomicUsize,
+    patterns: Vec<SharedCell<Vec<Trigger>>>,
 }
 
 impl Default for TrackTriggerModel {
     fn default() -> Self {
         Self {
-            pattern_length: 16,
-            pattern_step_beats: 0.25,
-            triggers: make_shared_cell(Vec::default()),
+            pattern_length: 16.into(),
+            pattern_step_beats: 0.25.into(),
+            swing: AtomicOption::empty(),
+            selected_pattern: 0.into(),
+            patterns: (0..NUM_PATTERNS)
+                .map(|_| make_shared_cell(Vec::default()))
+                .collect(),
         }
     }
 }
 
 impl TrackTriggerModel {
+    /// Number of steps in this track's patterns. Defaults to 16
     pub fn pattern_length(&self) -> usize {
+        self.pattern_length.get()
+    }
+
+    pub fn set_pattern_length(&self, pattern_length: usize) {
         self.pattern_length
+            .set(pattern_length.clamp(1, MAX_PATTERN_LENGTH));
     }
 
+    /// Length of each step in beats. Defaults to 0.25 (1/16th notes)
     pub fn pattern_step_beats(&self) -> f64 {
-        self.pattern_step_beats
+        self.pattern_step_beats.get()
+    }
+
+    pub fn set_pattern_step_beats(&self, pattern_step_beats: f64) {
+        if pattern_step_beats > 0.0 {
+            self.pattern_step_beats.set(pattern_step_beats);
+        }
+    }
+
+    /// Swing for this track, overriding the global swing if set
+    pub fn swing(&self) -> Option<f32> {
+        self.swing.inner()
+    }
+
+    pub fn set_swing(&self, swing: Option<f32>) {
+        self.swing.set(swing.map(|swing| swing.clamp(0.0, 1.0)));
+    }
+
+    /// The pattern which is edited and played, unless song mode is on
+    pub fn selected_pattern(&self) -> usize {
+        self.selected_pattern.get()
+    }
+
+    pub fn select_pattern(&self, pattern: usize) {
+        self.selected_pattern.set(pattern.min(NUM_PATTERNS - 1));
     }
 
     pub fn num_triggers(&self) -> usize {
-        self.triggers.get().len()
+        self.selected().get().len()
     }
 
     pub fn find_step<'a>(
@@ -221,31 +366,30 @@ impl TrackTriggerModel {
     }
 
     pub fn add_lock(&self, position_beats: usize, parameter_id: ParameterId, value: f32) {
-        let triggers = self.triggers.get();
-        let mut triggers: Vec<Trigger> = (*triggers).clone();
-        if let Some(trigger) = triggers
-            .iter_mut()
-            .find(|trigger| trigger.position.step.get() == position_beats)
-        {
-            trigger.add_lock(parameter_id, value);
-        }
-        self.triggers.set(make_shared(triggers));
+        self.update_trigger(position_beats, |trigger| {
+            trigger.add_lock(parameter_id, value)
+        });
     }
 
     pub fn remove_lock(&self, position_beats: usize, parameter_id: ParameterId) {
-        let triggers = self.triggers.get();
+        self.update_trigger(position_beats, |trigger| trigger.remove_lock(parameter_id));
+    }
+
+    /// Change the trigger on `position_step` of the selected pattern, if there's one
+    pub fn update_trigger(&self, position_step: usize, f: impl FnOnce(&mut Trigger)) {
+        let triggers = self.selected().get();
         let mut triggers: Vec<Trigger> = (*triggers).clone();
         if let Some(trigger) = triggers
             .iter_mut()
-            .find(|trigger| trigger.position.step.get() == position_beats)
+            .find(|trigger| trigger.position.step.get() == position_step)
         {
-            trigger.remove_lock(parameter_id);
+            f(trigger);
         }
-        self.triggers.set(make_shared(triggers));
+        self.selected().set(make_shared(triggers));
     }
 
     pub fn toggle_trigger(&self, position_step: usize) {
-        let triggers = self.triggers.get();
+        let triggers = self.selected().get();
         let mut triggers: Vec<Trigger> = (*triggers).clone();
 
         let indexes: Vec<usize> = triggers
@@ -258,7 +402,7 @@ impl TrackTriggerModel {
             for index in indexes {
                 triggers.remove(index);
             }
-            self.triggers.set(make_shared(triggers));
+            self.selected().set(make_shared(triggers));
         } else {
             let mut trigger = Trigger::default();
             trigger.set_position(TriggerPosition {
@@ -270,14 +414,8 @@ impl TrackTriggerModel {
     }
 
     pub fn remove_trigger(&self, position_step: usize) {
-        let triggers = self.triggers.get();
+        let triggers = self.selected().get();
         let mut triggers: Vec<Trigger> = (*triggers).clone();
-        let _indexes: Vec<usize> = triggers
-            .iter()
-            .enumerate()
-            .filter(|(_idx, trigger)| trigger.position.step.get() == position_step)
-            .map(|(idx, _)| idx)
-            .collect();
         let indexes: Vec<usize> = triggers
             .iter()
             .enumerate()
@@ -288,35 +426,51 @@ impl TrackTriggerModel {
             for index in indexes {
                 triggers.remove(index);
             }
-            self.triggers.set(make_shared(triggers));
+            self.selected().set(make_shared(triggers));
         }
     }
 
     pub fn add_trigger(&self, trigger: Trigger) {
-        let triggers = self.triggers.get();
+        let triggers = self.selected().get();
         let mut triggers: Vec<Trigger> = (*triggers).clone();
         triggers.push(trigger);
         log::info!("Track triggers={:?}", triggers);
-        self.triggers.set(make_shared(triggers));
+        self.selected().set(make_shared(triggers));
     }
 
     pub fn add_triggers(&self, triggers: &[Trigger]) {
-        let triggers: Vec<Trigger> = Vec::from(triggers);
-        self.triggers.set(make_shared(triggers));
+        self.set_pattern_triggers(self.selected_pattern(), triggers);
+    }
+
+    /// Replace the triggers of `pattern`
+    pub fn set_pattern_triggers(&self, pattern: usize, triggers: &[Trigger]) {
+        if let Some(cell) = self.patterns.get(pattern) {
+            cell.set(make_shared(Vec::from(triggers)));
+        }
     }
 
     pub fn clear(&self) {
-        self.triggers.set(make_shared(vec![]));
+        self.selected().set(make_shared(vec![]));
     }
 
+    /// Triggers of the selected pattern
     pub fn triggers(&self) -> Shared<Vec<Trigger>> {
-        self.triggers.get()
+        self.selected().get()
+    }
+
+    /// Triggers of `pattern`, which may not be the selected one
+    pub fn pattern_triggers(&self, pattern: usize) -> Shared<Vec<Trigger>> {
+        self.patterns[pattern.min(NUM_PATTERNS - 1)].get()
+    }
+
+    fn selected(&self) -> &SharedCell<Vec<Trigger>> {
+        &self.patterns[self.selected_pattern()]
     }
 }
 
 #[cfg(test)]
 mod test {
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
 
     use super::*;
 
@@ -325,7 +479,7 @@ mod test {
         let trigger_model = TrackTriggerModel::default();
         trigger_model.toggle_trigger(10);
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             for trigger in trigger_model.triggers().iter() {
                 for (_id, lock) in trigger.locks() {
                     assert!(lock.value > f32::NEG_INFINITY);
@@ -377,6 +531,71 @@ mod test {
         assert_eq!(triggers.len(), 0);
     }
 
+    #[test]
+    fn test_patterns_are_independent() {
+        let trigger_model = TrackTriggerModel::default();
+        trigger_model.toggle_trigger(2);
+        trigger_model.select_pattern(1);
+        assert_eq!(trigger_model.num_triggers(), 0);
+        trigger_model.toggle_trigger(4);
+        trigger_model.toggle_trigger(5);
+        assert_eq!(trigger_model.pattern_triggers(0).len(), 1);
+        assert_eq!(trigger_model.pattern_triggers(1).len(), 2);
+    }
+
+    #[test]
+    fn test_update_trigger() {
+        let trigger_model = TrackTriggerModel::default();
+        trigger_model.toggle_trigger(3);
+        trigger_model.update_trigger(3, |trigger| {
+            trigger.set_probability(2.0);
+            trigger.set_ratchets(0);
+            trigger.set_micro_timing(-0.25);
+        });
+        let triggers = trigger_model.triggers();
+        assert_eq!(triggers[0].probability(), 1.0);
+        assert_eq!(triggers[0].ratchets(), 1);
+        assert_eq!(triggers[0].micro_timing(), -0.25);
+    }
+
+    #[test]
+    fn test_out_of_range_trigger_fields_are_clamped_on_load() {
+        let trigger = Trigger {
+            probability: 2.0,
+            micro_timing: -3.0,
+            ratchets: 0,
+            ..Trigger::default()
+        };
+        let serialized = rmp_serde::to_vec_named(&trigger).unwrap();
+        let trigger: Trigger = rmp_serde::from_slice(&serialized).unwrap();
+        assert_eq!(trigger.probability(), 1.0);
+        assert_eq!(trigger.micro_timing(), -0.5);
+        assert_eq!(trigger.ratchets(), 1);
+
+        let trigger = Trigger {
+            ratchets: MAX_RATCHETS + 1,
+            ..Trigger::default()
+        };
+        let serialized = rmp_serde::to_vec_named(&trigger).unwrap();
+        let trigger: Trigger = rmp_serde::from_slice(&serialized).unwrap();
+        assert_eq!(trigger.ratchets(), MAX_RATCHETS);
+    }
+
+    #[test]
+    fn test_trigger_conditions() {
+        let every = TriggerCondition::Every {
+            every: 4,
+            offset: 3,
+        };
+        let fired: Vec<usize> = (0..9).filter(|i| every.is_met(*i, false)).collect();
+        assert_eq!(fired, vec![3, 7]);
+        assert!(TriggerCondition::First.is_met(0, false));
+        assert!(!TriggerCondition::First.is_met(1, false));
+        assert!(TriggerCondition::NotFirst.is_met(1, false));
+        assert!(TriggerCondition::Fill.is_met(5, true));
+        assert!(!TriggerCondition::NotFill.is_met(5, true));
+    }
+
     #[test]
     fn test_bulk_add_triggers() {
         let trigger_model = TrackTriggerModel::default();
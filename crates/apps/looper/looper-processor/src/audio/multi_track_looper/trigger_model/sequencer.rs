@@ -0,0 +1,541 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Turns [`TrackTriggerModel`]s into events at exact positions in beats.
+//!
+//! Events are found on windows of time, so the processor can split its buffer exactly where the
+//! next event falls. This module handles:
+//!
+//! * Global and per-track swing, which delays every odd step
+//! * Micro-timing offsets and ratchets of each [`Trigger`]
+//! * Song mode, which chains patterns for a number of bars each and loops
+//!
+//! Trigger conditions and probability are rolled by [`TrackSequencer`] when events fire.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use basedrop::{Shared, SharedCell};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use audio_garbage_collector::{make_shared, make_shared_cell};
+use augmented_atomics::AtomicF32;
+
+use super::{TrackTriggerModel, Trigger};
+
+/// Playhead jumps longer than this many beats reset a [`TrackSequencer`] instead of firing every
+/// event in between
+const MAX_JUMP_BEATS: f64 = 1.0;
+
+/// Plays `pattern` for `bars` bars when song mode is on
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SongEntry {
+    pub pattern: usize,
+    pub bars: usize,
+}
+
+/// Sequencer state shared between all tracks
+pub struct SequencerHandle {
+    swing: AtomicF32,
+    is_fill: AtomicBool,
+    song_enabled: AtomicBool,
+    song: SharedCell<Vec<SongEntry>>,
+}
+
+impl Default for SequencerHandle {
+    fn default() -> Self {
+        Self {
+            swing: 0.0.into(),
+            is_fill: AtomicBool::new(false),
+            song_enabled: AtomicBool::new(false),
+            song: make_shared_cell(vec![]),
+        }
+    }
+}
+
+impl SequencerHandle {
+    /// Swing between 0 and 1 for tracks that don't set their own. At 1, odd steps are delayed by
+    /// half a step
+    pub fn swing(&self) -> f32 {
+        self.swing.get()
+    }
+
+    pub fn set_swing(&self, swing: f32) {
+        self.swing.set(swing.clamp(0.0, 1.0));
+    }
+
+    pub fn is_fill(&self) -> bool {
+        self.is_fill.load(Ordering::Relaxed)
+    }
+
+    pub fn set_fill(&self, is_fill: bool) {
+        self.is_fill.store(is_fill, Ordering::Relaxed);
+    }
+
+    pub fn song_enabled(&self) -> bool {
+        self.song_enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_song_enabled(&self, song_enabled: bool) {
+        self.song_enabled.store(song_enabled, Ordering::Relaxed);
+    }
+
+    pub fn song(&self) -> Shared<Vec<SongEntry>> {
+        self.song.get()
+    }
+
+    pub fn set_song(&self, song: Vec<SongEntry>) {
+        self.song.set(make_shared(song));
+    }
+}
+
+/// A trigger firing at `beats`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SequencerEvent {
+    pub pattern: usize,
+    pub step: usize,
+    /// Number of times the pattern looped before this event, counted from the start of the
+    /// transport or of the song entry playing it
+    pub iteration: usize,
+    pub ratchet: usize,
+    pub beats: f64,
+    /// Parameter locks and the envelope are held until this position
+    pub until_beats: f64,
+}
+
+/// Call `f` for every event in the `(from, to]` window
+pub fn for_each_event(
+    model: &TrackTriggerModel,
+    handle: &SequencerHandle,
+    beats_per_bar: f64,
+    from: f64,
+    to: f64,
+    mut f: impl FnMut(&Trigger, SequencerEvent),
+) {
+    if to <= from {
+        return;
+    }
+
+    let song = handle.song();
+    let song_beats: f64 = song
+        .iter()
+        .map(|entry| entry.bars as f64 * beats_per_bar)
+        .sum();
+    if !handle.song_enabled() || song_beats <= 0.0 {
+        pattern_events(
+            model,
+            handle,
+            model.selected_pattern(),
+            (0.0, f64::INFINITY),
+            (from, to),
+            &mut f,
+        );
+        return;
+    }
+
+    let first_cycle = (from / song_beats).floor().max(0.0) as usize;
+    let last_cycle = (to / song_beats).floor() as usize;
+    for cycle in first_cycle..=last_cycle {
+        let mut start = cycle as f64 * song_beats;
+        for entry in song.iter() {
+            let end = start + entry.bars as f64 * beats_per_bar;
+            if end > from && start <= to {
+                pattern_events(
+                    model,
+                    handle,
+                    entry.pattern,
+                    (start, end),
+                    (from, to),
+                    &mut f,
+                );
+            }
+            start = end;
+        }
+    }
+}
+
+/// Position of the first event in the `(from, to]` window
+pub fn next_event_beats(
+    model: &TrackTriggerModel,
+    handle: &SequencerHandle,
+    beats_per_bar: f64,
+    from: f64,
+    to: f64,
+) -> Option<f64> {
+    let mut next: Option<f64> = None;
+    for_each_event(model, handle, beats_per_bar, from, to, |_, event| {
+        next = Some(next.map_or(event.beats, |next| next.min(event.beats)));
+    });
+    next
+}
+
+/// Events of `pattern` looping from the start of `segment`, which fall both within the segment
+/// and the `(from, to]` window
+fn pattern_events(
+    model: &TrackTriggerModel,
+    handle: &SequencerHandle,
+    pattern: usize,
+    (start, end): (f64, f64),
+    (from, to): (f64, f64),
+    f: &mut impl FnMut(&Trigger, SequencerEvent),
+) {
+    let triggers = model.pattern_triggers(pattern);
+    if triggers.is_empty() {
+        return;
+    }
+
+    let length = model.pattern_length();
+    let step_beats = model.pattern_step_beats();
+    let pattern_beats = length as f64 * step_beats;
+    let swing = model.swing().unwrap_or_else(|| handle.swing()) as f64;
+
+    // Micro-timing and swing move events less than a step, so looking one iteration around the
+    // window is enough
+    let first_iteration = ((from.max(start) - start) / pattern_beats).floor() as i64 - 1;
+    let last_iteration = ((to.min(end) - start) / pattern_beats).floor() as i64 + 1;
+
+    for iteration in first_iteration.max(0)..=last_iteration {
+        let origin = start + iteration as f64 * pattern_beats;
+        for trigger in triggers.iter() {
+            let step = trigger.step();
+            if step >= length {
+                continue;
+            }
+
+            let mut step_start =
+                origin + (step as f64 + trigger.micro_timing() as f64) * step_beats;
+            if step % 2 == 1 {
+                step_start += swing * 0.5 * step_beats;
+            }
+
+            let ratchets = trigger.ratchets();
+            for ratchet in 0..ratchets {
+                let beats = step_start + ratchet as f64 * step_beats / ratchets as f64;
+                if beats > from && beats <= to && beats >= start && beats < end {
+                    f(
+                        trigger,
+                        SequencerEvent {
+                            pattern,
+                            step,
+                            iteration: iteration as usize,
+                            ratchet,
+                            beats,
+                            until_beats: step_start + step_beats,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct ActiveStep {
+    pattern: usize,
+    step: usize,
+    until_beats: f64,
+}
+
+/// Per-track audio-thread state, which remembers where the playhead was so events fire exactly
+/// once
+#[derive(Default)]
+pub struct TrackSequencer {
+    last_position_beats: Option<f64>,
+    active: Option<ActiveStep>,
+    /// Trigger iteration which failed its condition or probability roll, so its ratchets are
+    /// skipped too
+    skipped: Option<(usize, usize, usize)>,
+}
+
+impl TrackSequencer {
+    /// Forget the last position, the next call to `advance` fires events on the current position
+    pub fn reset(&mut self) {
+        self.last_position_beats = None;
+        self.active = None;
+        self.skipped = None;
+    }
+
+    /// Fire events up to `position_beats` since the last call. Returns the number of triggers
+    /// that should play.
+    pub fn advance(
+        &mut self,
+        model: &TrackTriggerModel,
+        handle: &SequencerHandle,
+        beats_per_bar: f64,
+        position_beats: f64,
+        rng: &mut impl Rng,
+    ) -> usize {
+        let from = match self.last_position_beats {
+            Some(last) if last <= position_beats && position_beats - last <= MAX_JUMP_BEATS => last,
+            _ => {
+                self.active = None;
+                self.skipped = None;
+                position_beats - f64::EPSILON.max(position_beats.abs() * f64::EPSILON)
+            }
+        };
+        self.last_position_beats = Some(position_beats);
+
+        let is_fill = handle.is_fill();
+        let mut fired = 0;
+        let active = &mut self.active;
+        let skipped = &mut self.skipped;
+        for_each_event(
+            model,
+            handle,
+            beats_per_bar,
+            from,
+            position_beats,
+            |trigger, event| {
+                let key = (event.pattern, event.step, event.iteration);
+                if event.ratchet == 0 {
+                    let probability = trigger.probability();
+                    let plays = trigger.condition().is_met(event.iteration, is_fill)
+                        && (probability >= 1.0 || rng.gen::<f32>() < probability);
+                    *skipped = if plays { None } else { Some(key) };
+                }
+                if *skipped == Some(key) {
+                    return;
+                }
+
+                fired += 1;
+                let is_latest = match active {
+                    Some(active) => active.until_beats <= event.until_beats,
+                    None => true,
+                };
+                if is_latest {
+                    *active = Some(ActiveStep {
+                        pattern: event.pattern,
+                        step: event.step,
+                        until_beats: event.until_beats,
+                    });
+                }
+            },
+        );
+        fired
+    }
+
+    /// Pattern and step of the trigger holding its locks at `position_beats`
+    pub fn active_step(&self, position_beats: f64) -> Option<(usize, usize)> {
+        self.active
+            .filter(|active| position_beats < active.until_beats)
+            .map(|active| (active.pattern, active.step))
+    }
+
+    /// Next position in the `(from, to]` window where an event fires or the active trigger ends
+    pub fn next_boundary(
+        &self,
+        model: &TrackTriggerModel,
+        handle: &SequencerHandle,
+        beats_per_bar: f64,
+        from: f64,
+        to: f64,
+    ) -> Option<f64> {
+        let next_event = next_event_beats(model, handle, beats_per_bar, from, to);
+        let active_end = self
+            .active
+            .map(|active| active.until_beats)
+            .filter(|until| *until > from && *until <= to);
+        match (next_event, active_end) {
+            (Some(event), Some(end)) => Some(event.min(end)),
+            (event, end) => event.or(end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::super::TriggerCondition;
+    use super::*;
+
+    fn events(
+        model: &TrackTriggerModel,
+        handle: &SequencerHandle,
+        from: f64,
+        to: f64,
+    ) -> Vec<SequencerEvent> {
+        let mut result = vec![];
+        for_each_event(model, handle, 4.0, from, to, |_, event| result.push(event));
+        result.sort_by(|a, b| a.beats.partial_cmp(&b.beats).unwrap());
+        result
+    }
+
+    fn beats(events: &[SequencerEvent]) -> Vec<f64> {
+        events.iter().map(|event| event.beats).collect()
+    }
+
+    #[test]
+    fn test_events_loop_with_the_pattern() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        model.toggle_trigger(0);
+        model.toggle_trigger(4);
+
+        let result = events(&model, &handle, -0.1, 7.9);
+        assert_eq!(beats(&result), vec![0.0, 1.0, 4.0, 5.0]);
+        assert_eq!(result[2].iteration, 1);
+    }
+
+    #[test]
+    fn test_pattern_length_and_step_size() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        model.set_pattern_length(3);
+        model.set_pattern_step_beats(0.5);
+        model.toggle_trigger(1);
+        model.toggle_trigger(5);
+
+        assert_eq!(beats(&events(&model, &handle, 0.0, 3.0)), vec![0.5, 2.0]);
+    }
+
+    #[test]
+    fn test_swing_delays_odd_steps() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        handle.set_swing(0.5);
+        model.toggle_trigger(0);
+        model.toggle_trigger(1);
+        assert_eq!(
+            beats(&events(&model, &handle, -0.1, 1.0)),
+            vec![0.0, 0.25 + 0.0625]
+        );
+
+        model.set_swing(Some(0.0));
+        assert_eq!(beats(&events(&model, &handle, -0.1, 1.0)), vec![0.0, 0.25]);
+    }
+
+    #[test]
+    fn test_micro_timing_and_ratchets() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        model.toggle_trigger(2);
+        model.update_trigger(2, |trigger| {
+            trigger.set_micro_timing(0.5);
+            trigger.set_ratchets(2);
+        });
+
+        let result = events(&model, &handle, 0.0, 1.0);
+        assert_eq!(beats(&result), vec![0.625, 0.75]);
+        assert_eq!(result[1].ratchet, 1);
+        assert_eq!(result[1].until_beats, 0.875);
+    }
+
+    #[test]
+    fn test_song_mode_chains_patterns() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        model.toggle_trigger(0);
+        model.select_pattern(1);
+        model.toggle_trigger(2);
+        handle.set_song(vec![
+            SongEntry {
+                pattern: 0,
+                bars: 2,
+            },
+            SongEntry {
+                pattern: 1,
+                bars: 1,
+            },
+        ]);
+        handle.set_song_enabled(true);
+
+        let result = events(&model, &handle, -0.1, 13.0);
+        assert_eq!(beats(&result), vec![0.0, 4.0, 8.5, 12.0]);
+        assert_eq!(
+            result.iter().map(|event| event.pattern).collect::<Vec<_>>(),
+            vec![0, 0, 1, 0]
+        );
+        assert_eq!(result[3].iteration, 0);
+    }
+
+    #[test]
+    fn test_track_sequencer_fires_events_once() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        for step in 0..16 {
+            model.toggle_trigger(step);
+        }
+
+        let mut sequencer = TrackSequencer::default();
+        let mut fired = 0;
+        let mut position = 0.0;
+        while position < 8.0 {
+            fired += sequencer.advance(&model, &handle, 4.0, position, &mut rng);
+            position += 0.01;
+        }
+        assert_eq!(fired, 32);
+    }
+
+    #[test]
+    fn test_track_sequencer_active_step() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        model.toggle_trigger(1);
+
+        let mut sequencer = TrackSequencer::default();
+        assert_eq!(sequencer.advance(&model, &handle, 4.0, 0.0, &mut rng), 0);
+        assert_eq!(
+            sequencer.next_boundary(&model, &handle, 4.0, 0.0, 1.0),
+            Some(0.25)
+        );
+        assert_eq!(sequencer.advance(&model, &handle, 4.0, 0.25, &mut rng), 1);
+        assert_eq!(sequencer.active_step(0.3), Some((0, 1)));
+        assert_eq!(
+            sequencer.next_boundary(&model, &handle, 4.0, 0.25, 1.0),
+            Some(0.5)
+        );
+        assert_eq!(sequencer.active_step(0.5), None);
+    }
+
+    #[test]
+    fn test_track_sequencer_conditions_and_probability() {
+        let model = TrackTriggerModel::default();
+        let handle = SequencerHandle::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        model.toggle_trigger(0);
+        model.toggle_trigger(1);
+        model.update_trigger(0, |trigger| {
+            trigger.set_condition(TriggerCondition::Every {
+                every: 2,
+                offset: 1,
+            })
+        });
+        model.update_trigger(1, |trigger| {
+            trigger.set_probability(0.0);
+            trigger.set_ratchets(4);
+        });
+
+        let mut sequencer = TrackSequencer::default();
+        let mut fired = vec![];
+        let mut position = 0.0;
+        while position < 16.0 {
+            if sequencer.advance(&model, &handle, 4.0, position, &mut rng) > 0 {
+                fired.push(position);
+            }
+            position += 0.125;
+        }
+        assert_eq!(fired, vec![4.0, 12.0]);
+    }
+}
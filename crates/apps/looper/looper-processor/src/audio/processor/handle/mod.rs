@@ -26,6 +26,7 @@ use std::ops::Deref;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::time::Duration;
 
+use atomic_queue::Queue;
 use atomic_refcell::AtomicRefCell;
 use basedrop::{Shared, SharedCell};
 use num_derive::{FromPrimitive, ToPrimitive};
@@ -36,12 +37,14 @@ use augmented_atomics::{AtomicEnum, AtomicValue};
 pub use quantize_mode::{QuantizeMode, QuantizeOptions};
 use utils::CopyLoopClipParams;
 
+use crate::audio::processor::handle::overdub_history::OverdubHistory;
 use crate::audio::processor::handle::scratch_pad::ScratchPad;
 use crate::audio::{
     loop_quantization::{LoopQuantizer, QuantizeInput},
     time_info_provider::{HostCallback, TimeInfoProvider, TimeInfoProviderImpl},
 };
 
+mod overdub_history;
 mod quantize_mode;
 mod scratch_pad;
 mod utils;
@@ -87,6 +90,9 @@ pub enum LooperState {
 pub struct LooperOptions {
     pub max_loop_length: Duration,
     pub host_callback: Option<HostCallback>,
+    /// Number of overdub layers that can be undone. Each layer pre-allocates a buffer of
+    /// `max_loop_length`. Defaults to 2.
+    pub max_overdub_layers: usize,
 }
 
 pub type LooperClip = SharedCell<AtomicRefCell<AudioBuffer<AtomicF32>>>;
@@ -115,6 +121,7 @@ impl Default for LooperOptions {
         Self {
             max_loop_length: Duration::from_secs(crate::MAX_LOOP_LENGTH_SECS as u64),
             host_callback: None,
+            max_overdub_layers: 2,
         }
     }
 }
@@ -151,6 +158,10 @@ pub struct LooperHandle {
     looper_clip: LooperClip,
     /// Temporary swap buffer
     looper_clip1: LooperClip,
+    /// Overdub layers that can be undone or redone
+    overdub_history: AtomicRefCell<OverdubHistory>,
+    /// Undo/redo requests, applied by the audio-thread at the start of a block
+    overdub_commands: Queue<OverdubCommand>,
     /// Where playback is within the looped clip buffer
     cursor: AtomicF32,
     /// Provides time information
@@ -175,6 +186,13 @@ pub enum ToggleRecordingResult {
     StopRecordingScheduled,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OverdubCommand {
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LooperHandleThread {
     AudioThread,
     OtherThread,
@@ -200,6 +218,8 @@ impl LooperHandle {
             scratch_pad: AtomicRefCell::new(ScratchPad::new(AudioBuffer::empty())),
             looper_clip: make_shared_cell(AtomicRefCell::new(AudioBuffer::empty())),
             looper_clip1: make_shared_cell(AtomicRefCell::new(AudioBuffer::empty())),
+            overdub_history: AtomicRefCell::new(OverdubHistory::default()),
+            overdub_commands: Queue::new(16),
             scheduled_playback: AtomicUsize::new(0),
             cursor: AtomicF32::new(0.0),
             time_info_provider,
@@ -304,6 +324,16 @@ impl LooperHandle {
     pub fn playhead(&self) -> usize {
         self.cursor.get() as usize
     }
+
+    /// Number of overdub layers that can be undone
+    pub fn overdub_undo_count(&self) -> usize {
+        self.overdub_history.borrow().undo_count()
+    }
+
+    /// Number of undone overdub layers that can be redone
+    pub fn overdub_redo_count(&self) -> usize {
+        self.overdub_history.borrow().redo_count()
+    }
 }
 
 // MARK: Computed properties
@@ -412,6 +442,7 @@ impl LooperHandle {
             if self.tick_time.get() {
                 self.time_info_provider.play();
             }
+            self.overdub_history.borrow().begin_layer();
             self.state.set(LooperState::Overdubbing);
         }
 
@@ -420,6 +451,7 @@ impl LooperHandle {
 
     pub fn clear(&self) {
         self.state.set(LooperState::Empty);
+        self.overdub_history.borrow().reset();
         // Clear the looper clip in case playback re-starts
         let clip = self.looper_clip.get();
         let clip = clip.deref().borrow();
@@ -491,6 +523,7 @@ impl LooperHandle {
                 );
                 self.looper_clip
                     .set(make_shared(AtomicRefCell::new(new_buffer)));
+                self.overdub_history.borrow().reset();
                 if self.tick_time.get() {
                     self.time_info_provider.play();
                 }
@@ -498,6 +531,7 @@ impl LooperHandle {
                 self.cursor.set(0.0);
             }
         } else if old_state == LooperState::Overdubbing {
+            self.overdub_history.borrow().end_layer();
             self.state.set(LooperState::Playing);
         }
     }
@@ -544,12 +578,56 @@ impl LooperHandle {
             }
             self.looper_clip1.set(self.looper_clip.get());
             self.looper_clip.set(shared_buffer);
+            self.overdub_history.borrow().reset();
         } else if old_state == LooperState::Overdubbing {
+            self.overdub_history.borrow().end_layer();
             self.state.set(LooperState::Playing);
         }
     }
 }
 
+/// MARK: Overdub history
+impl LooperHandle {
+    /// Remove the last overdub layer from the loop, stopping the overdub if it's in progress.
+    /// Returns false if there was nothing to undo or too many requests are pending.
+    ///
+    /// The loop is only modified by the audio-thread, so the undo is applied at the start of the
+    /// next block.
+    pub fn undo_overdub(&self) -> bool {
+        self.overdub_undo_count() > 0 && self.overdub_commands.push(OverdubCommand::Undo)
+    }
+
+    /// Add the last undone overdub layer back onto the loop. Returns false if there was nothing
+    /// to redo or too many requests are pending.
+    ///
+    /// Like [`LooperHandle::undo_overdub`], this is applied at the start of the next block.
+    pub fn redo_overdub(&self) -> bool {
+        self.overdub_redo_count() > 0 && self.overdub_commands.push(OverdubCommand::Redo)
+    }
+
+    /// Apply pending undo/redo requests. Doesn't allocate; the cost is a pass over the loop
+    /// length for each request.
+    fn process_overdub_commands(&self) {
+        while let Some(command) = self.overdub_commands.pop() {
+            let clip = self.looper_clip.get();
+            let clip = clip.deref().borrow();
+            let history = self.overdub_history.borrow();
+            let length = self.length.load(Ordering::Relaxed);
+            match command {
+                OverdubCommand::Undo => {
+                    if self.state.get() == LooperState::Overdubbing {
+                        self.state.set(LooperState::Playing);
+                    }
+                    history.undo(&clip, length);
+                }
+                OverdubCommand::Redo => {
+                    history.redo(&clip, length);
+                }
+            }
+        }
+    }
+}
+
 /// MARK: Buffer override
 impl LooperHandle {
     /// Override the looper memory buffer.
@@ -569,6 +647,7 @@ impl LooperHandle {
 
         let new_length = new_buffer.num_samples();
         self.looper_clip.set(make_shared(new_buffer.into()));
+        self.overdub_history.borrow().reset();
         self.length.set(new_length);
         self.state.set(LooperState::Paused);
         self.cursor.set(self.get_start_samples());
@@ -604,6 +683,11 @@ impl LooperHandle {
                     num_channels,
                     max_loop_length_samples,
                 ))));
+            *self.overdub_history.borrow_mut() = OverdubHistory::new(
+                self.options.max_overdub_layers,
+                num_channels,
+                max_loop_length_samples,
+            );
         }
 
         self.time_info_provider
@@ -612,6 +696,12 @@ impl LooperHandle {
         self.settings.set(make_shared(settings));
     }
 
+    /// Must be called by the audio-thread before each block is processed
+    #[inline]
+    pub(crate) fn before_process(&self) {
+        self.process_overdub_commands();
+    }
+
     pub fn state(&self) -> LooperState {
         self.state.get()
     }
@@ -641,10 +731,12 @@ impl LooperHandle {
                 let clip = clip.deref().borrow();
                 // TODO - There should be separate read/write cursors (?)
                 let cursor = self.cursor.get() as usize;
-                let clip_sample = clip.get(channel, cursor % clip.num_samples());
+                let index = cursor % clip.num_samples();
+                let clip_sample = clip.get(channel, index);
                 let clip_out = clip_sample.get();
 
                 clip_sample.set(clip_out + sample);
+                self.overdub_history.borrow().record(channel, index, sample);
                 self.apply_wet_volume(clip_out)
             }
             _ => 0.0,
@@ -779,8 +871,8 @@ fn calculate_fade_volume(fade_perc: f32, length: usize, cursor: f32) -> f32 {
 
 #[cfg(test)]
 mod test {
-    use assert_no_alloc::assert_no_alloc;
     use audio_processor_testing_helpers::assert_f_eq;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
 
     use audio_processor_traits::{AudioContext, AudioProcessor};
 
@@ -1050,11 +1142,63 @@ mod test {
             handle.after_process();
         }
         assert_eq!(handle.state.get(), LooperState::Recording);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             handle.stop_recording_audio_thread_only();
         });
     }
 
+    #[test]
+    fn test_undo_and_redo_overdub() {
+        let handle = LooperHandle::default();
+        handle.prepare(AudioProcessorSettings {
+            sample_rate: 100.0,
+            input_channels: 1,
+            output_channels: 1,
+            ..AudioProcessorSettings::default()
+        });
+        let process = |samples: &[f32]| {
+            for sample in samples {
+                handle.process(0, *sample);
+                handle.after_process();
+            }
+        };
+        let clip_values = || -> Vec<f32> {
+            let clip = handle.looper_clip();
+            let clip = clip.borrow();
+            clip.channel(0)
+                .iter()
+                .take(handle.num_samples())
+                .map(|sample| sample.get())
+                .collect()
+        };
+
+        handle.start_recording();
+        process(&[1.0, 2.0, 3.0, 4.0]);
+        handle.stop_recording(LooperHandleThread::AudioThread);
+        assert_eq!(clip_values(), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(handle.overdub_undo_count(), 0);
+
+        assert_eq!(handle.start_recording(), LooperState::Overdubbing);
+        process(&[10.0, 10.0, 10.0, 10.0]);
+        handle.stop_recording(LooperHandleThread::AudioThread);
+        assert_eq!(clip_values(), vec![11.0, 12.0, 13.0, 14.0]);
+        assert_eq!(handle.overdub_undo_count(), 1);
+
+        assert!(handle.undo_overdub());
+        assert_eq!(clip_values(), vec![11.0, 12.0, 13.0, 14.0]);
+        assert_realtime_safe(|| handle.before_process());
+        assert_eq!(clip_values(), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(handle.overdub_redo_count(), 1);
+        assert!(!handle.undo_overdub());
+
+        assert!(handle.redo_overdub());
+        assert_realtime_safe(|| handle.before_process());
+        assert_eq!(clip_values(), vec![11.0, 12.0, 13.0, 14.0]);
+
+        handle.clear();
+        assert_eq!(handle.overdub_undo_count(), 0);
+    }
+
     mod get_offset {
         use super::*;
 
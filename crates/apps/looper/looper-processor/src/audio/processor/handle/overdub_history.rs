@@ -0,0 +1,374 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use audio_processor_traits::{AtomicF32, AudioBuffer};
+
+use super::utils;
+
+/// Pre-allocated pool of overdub layers, used to undo & redo overdubs.
+///
+/// Each overdub pass records what it added to the looper clip onto its own layer. Undoing
+/// subtracts the newest layer from the clip and redoing adds it back, so nothing is allocated
+/// while performing. Layers are a ring, once the pool is full starting a new overdub commits the
+/// oldest layer, which can't be undone anymore.
+#[derive(Default)]
+pub struct OverdubHistory {
+    layers: Vec<OverdubLayer>,
+    /// Index of the oldest layer that can be undone
+    start: AtomicUsize,
+    /// Number of layers that can be undone, the newest one is the last
+    undo_count: AtomicUsize,
+    /// Number of undone layers that can be redone, following the undo layers
+    redo_count: AtomicUsize,
+    is_recording: AtomicBool,
+}
+
+impl OverdubHistory {
+    pub fn new(num_layers: usize, num_channels: usize, num_samples: usize) -> Self {
+        OverdubHistory {
+            layers: (0..num_layers)
+                .map(|_| OverdubLayer::new(num_channels, num_samples))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn max_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.undo_count.load(Ordering::Relaxed)
+    }
+
+    pub fn redo_count(&self) -> usize {
+        self.redo_count.load(Ordering::Relaxed)
+    }
+
+    /// Start recording onto a new layer. Discards the redo history.
+    ///
+    /// The layer is cleared lazily, a block at a time, as it's recorded onto, so this may be
+    /// called from the audio-thread.
+    pub fn begin_layer(&self) {
+        let num_layers = self.max_layers();
+        if num_layers == 0 {
+            return;
+        }
+
+        self.redo_count.store(0, Ordering::Relaxed);
+        let mut undo_count = self.undo_count();
+        if undo_count == num_layers {
+            self.start.store(
+                (self.start.load(Ordering::Relaxed) + 1) % num_layers,
+                Ordering::Relaxed,
+            );
+            undo_count -= 1;
+        }
+
+        self.layers[self.layer_index(undo_count)].clear();
+
+        self.undo_count.store(undo_count + 1, Ordering::Relaxed);
+        self.is_recording.store(true, Ordering::Relaxed);
+    }
+
+    /// Stop recording onto the current layer
+    pub fn end_layer(&self) {
+        self.is_recording.store(false, Ordering::Relaxed);
+    }
+
+    /// Add `sample` to the layer being recorded
+    #[inline]
+    pub fn record(&self, channel: usize, index: usize, sample: f32) {
+        if !self.is_recording.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let undo_count = self.undo_count();
+        if undo_count == 0 {
+            return;
+        }
+        self.layers[self.layer_index(undo_count - 1)].add(channel, index, sample);
+    }
+
+    /// Remove the newest layer from `clip`. Returns false if there's nothing to undo.
+    pub fn undo(&self, clip: &AudioBuffer<AtomicF32>, length: usize) -> bool {
+        let undo_count = self.undo_count();
+        if undo_count == 0 {
+            return false;
+        }
+
+        self.end_layer();
+        let layer = &self.layers[self.layer_index(undo_count - 1)];
+        mix_layer(clip, layer, length, -1.0);
+        self.undo_count.store(undo_count - 1, Ordering::Relaxed);
+        self.redo_count.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Add the last undone layer back onto `clip`. Returns false if there's nothing to redo.
+    pub fn redo(&self, clip: &AudioBuffer<AtomicF32>, length: usize) -> bool {
+        let redo_count = self.redo_count();
+        if redo_count == 0 {
+            return false;
+        }
+
+        let undo_count = self.undo_count();
+        let layer = &self.layers[self.layer_index(undo_count)];
+        mix_layer(clip, layer, length, 1.0);
+        self.undo_count.store(undo_count + 1, Ordering::Relaxed);
+        self.redo_count.store(redo_count - 1, Ordering::Relaxed);
+        true
+    }
+
+    /// Forget all layers, for example when the clip is replaced
+    pub fn reset(&self) {
+        self.end_layer();
+        self.undo_count.store(0, Ordering::Relaxed);
+        self.redo_count.store(0, Ordering::Relaxed);
+    }
+
+    fn layer_index(&self, offset: usize) -> usize {
+        (self.start.load(Ordering::Relaxed) + offset) % self.max_layers()
+    }
+}
+
+/// Number of samples that share a generation stamp
+const BLOCK_SIZE: usize = 256;
+
+/// A layer of the pool. Each block of samples is stamped with the layer generation it was
+/// recorded on, so clearing the layer only needs to start a new generation. Blocks from older
+/// generations read as silence & are cleared the first time they're recorded onto.
+#[derive(Default)]
+struct OverdubLayer {
+    buffer: AudioBuffer<AtomicF32>,
+    /// Generation of each block of `BLOCK_SIZE` samples, across all channels
+    generations: Vec<AtomicU32>,
+    generation: AtomicU32,
+}
+
+impl OverdubLayer {
+    fn new(num_channels: usize, num_samples: usize) -> Self {
+        let num_blocks = num_samples.div_ceil(BLOCK_SIZE);
+        OverdubLayer {
+            buffer: utils::empty_buffer(num_channels, num_samples),
+            generations: (0..num_blocks).map(|_| AtomicU32::new(0)).collect(),
+            generation: AtomicU32::new(0),
+        }
+    }
+
+    fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn is_block_current(&self, block: usize) -> bool {
+        self.generations[block].load(Ordering::Relaxed) == self.generation.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    fn get(&self, channel: usize, index: usize) -> f32 {
+        if self.is_block_current(index / BLOCK_SIZE) {
+            self.buffer.get(channel, index).get()
+        } else {
+            0.0
+        }
+    }
+
+    #[inline]
+    fn add(&self, channel: usize, index: usize, sample: f32) {
+        if channel >= self.buffer.num_channels() || index >= self.buffer.num_samples() {
+            return;
+        }
+
+        let block = index / BLOCK_SIZE;
+        if !self.is_block_current(block) {
+            let block_end = ((block + 1) * BLOCK_SIZE).min(self.buffer.num_samples());
+            for channel in self.buffer.channels() {
+                for layer_sample in &channel[block * BLOCK_SIZE..block_end] {
+                    layer_sample.set(0.0);
+                }
+            }
+            self.generations[block]
+                .store(self.generation.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+
+        let layer_sample = self.buffer.get(channel, index);
+        layer_sample.set(layer_sample.get() + sample);
+    }
+}
+
+fn mix_layer(clip: &AudioBuffer<AtomicF32>, layer: &OverdubLayer, length: usize, gain: f32) {
+    let num_channels = clip.num_channels().min(layer.buffer.num_channels());
+    let length = length
+        .min(clip.num_samples())
+        .min(layer.buffer.num_samples());
+    for channel in 0..num_channels {
+        for index in 0..length {
+            let sample = clip.get(channel, index);
+            sample.set(sample.get() + gain * layer.get(channel, index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
+
+    use super::*;
+
+    fn clip_values(clip: &AudioBuffer<AtomicF32>) -> Vec<f32> {
+        clip.channel(0).iter().map(|sample| sample.get()).collect()
+    }
+
+    fn overdub(history: &OverdubHistory, clip: &AudioBuffer<AtomicF32>, value: f32) {
+        history.begin_layer();
+        for index in 0..clip.num_samples() {
+            let sample = clip.get(0, index);
+            sample.set(sample.get() + value);
+            history.record(0, index, value);
+        }
+        history.end_layer();
+    }
+
+    #[test]
+    fn test_undo_redo_single_layer() {
+        let history = OverdubHistory::new(2, 1, 4);
+        let clip = utils::empty_buffer(1, 4);
+        overdub(&history, &clip, 1.0);
+        assert_eq!(clip_values(&clip), vec![1.0; 4]);
+        assert_eq!(history.undo_count(), 1);
+
+        assert!(history.undo(&clip, 4));
+        assert_eq!(clip_values(&clip), vec![0.0; 4]);
+        assert_eq!(history.undo_count(), 0);
+        assert_eq!(history.redo_count(), 1);
+        assert!(!history.undo(&clip, 4));
+
+        assert!(history.redo(&clip, 4));
+        assert_eq!(clip_values(&clip), vec![1.0; 4]);
+        assert!(!history.redo(&clip, 4));
+    }
+
+    #[test]
+    fn test_undo_multiple_layers_in_order() {
+        let history = OverdubHistory::new(3, 1, 4);
+        let clip = utils::empty_buffer(1, 4);
+        overdub(&history, &clip, 1.0);
+        overdub(&history, &clip, 2.0);
+        overdub(&history, &clip, 4.0);
+        assert_eq!(clip_values(&clip), vec![7.0; 4]);
+
+        history.undo(&clip, 4);
+        assert_eq!(clip_values(&clip), vec![3.0; 4]);
+        history.undo(&clip, 4);
+        assert_eq!(clip_values(&clip), vec![1.0; 4]);
+        history.redo(&clip, 4);
+        assert_eq!(clip_values(&clip), vec![3.0; 4]);
+    }
+
+    #[test]
+    fn test_new_overdub_discards_redo() {
+        let history = OverdubHistory::new(3, 1, 4);
+        let clip = utils::empty_buffer(1, 4);
+        overdub(&history, &clip, 1.0);
+        overdub(&history, &clip, 2.0);
+        history.undo(&clip, 4);
+        overdub(&history, &clip, 4.0);
+        assert_eq!(clip_values(&clip), vec![5.0; 4]);
+        assert_eq!(history.redo_count(), 0);
+        assert!(!history.redo(&clip, 4));
+
+        history.undo(&clip, 4);
+        assert_eq!(clip_values(&clip), vec![1.0; 4]);
+    }
+
+    #[test]
+    fn test_oldest_layer_is_committed_when_the_pool_is_full() {
+        let history = OverdubHistory::new(2, 1, 4);
+        let clip = utils::empty_buffer(1, 4);
+        overdub(&history, &clip, 1.0);
+        overdub(&history, &clip, 2.0);
+        overdub(&history, &clip, 4.0);
+        assert_eq!(history.undo_count(), 2);
+
+        assert!(history.undo(&clip, 4));
+        assert!(history.undo(&clip, 4));
+        assert!(!history.undo(&clip, 4));
+        assert_eq!(clip_values(&clip), vec![1.0; 4]);
+    }
+
+    #[test]
+    fn test_reused_layer_does_not_leak_old_samples() {
+        let history = OverdubHistory::new(1, 1, 4);
+        let clip = utils::empty_buffer(1, 4);
+        overdub(&history, &clip, 1.0);
+        overdub(&history, &clip, 2.0);
+
+        history.begin_layer();
+        history.record(0, 1, 4.0);
+        history.record(0, 1, 4.0);
+        history.end_layer();
+        clip.get(0, 1).set(clip.get(0, 1).get() + 8.0);
+        assert_eq!(clip_values(&clip), vec![3.0, 11.0, 3.0, 3.0]);
+
+        assert!(history.undo(&clip, 4));
+        assert_eq!(clip_values(&clip), vec![3.0; 4]);
+    }
+
+    #[test]
+    fn test_reused_layer_only_keeps_recorded_blocks() {
+        let num_samples = BLOCK_SIZE * 3;
+        let history = OverdubHistory::new(1, 1, num_samples);
+        let clip = utils::empty_buffer(1, num_samples);
+        overdub(&history, &clip, 1.0);
+
+        history.begin_layer();
+        history.record(0, BLOCK_SIZE + 1, 2.0);
+        history.end_layer();
+        clip.get(0, BLOCK_SIZE + 1).set(3.0);
+
+        assert!(history.undo(&clip, num_samples));
+        assert_eq!(clip_values(&clip), vec![1.0; num_samples]);
+    }
+
+    #[test]
+    fn test_begin_layer_is_realtime_safe() {
+        let history = OverdubHistory::new(2, 2, 1024);
+        assert_realtime_safe(|| {
+            history.begin_layer();
+            history.record(1, 512, 1.0);
+            history.end_layer();
+        });
+    }
+
+    #[test]
+    fn test_empty_history_is_a_no_op() {
+        let history = OverdubHistory::default();
+        let clip = utils::empty_buffer(1, 4);
+        history.begin_layer();
+        history.record(0, 0, 1.0);
+        assert!(!history.undo(&clip, 4));
+        assert_eq!(history.max_layers(), 0);
+    }
+}
@@ -93,6 +93,7 @@ impl AudioProcessor for LooperProcessor {
 
     fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
         let handle = &*self.handle;
+        handle.before_process();
         for sample_num in 0..data.num_samples() {
             for channel_num in 0..data.num_channels() {
                 let input = data.get(channel_num, sample_num);
@@ -116,7 +117,7 @@ impl MidiEventHandler for LooperProcessor {
 mod test {
     use std::time::Duration;
 
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
     use audio_processor_testing_helpers::sine_buffer;
     use audio_processor_testing_helpers::test_level_equivalence;
     use audio_processor_testing_helpers::{assert_f_eq, rms_level};
@@ -403,7 +404,7 @@ mod test {
     fn test_looper_is_silent_for(looper: &mut LooperProcessor, num_samples: usize) {
         let mut output = make_silent_buffer(num_samples);
         let mut context = AudioContext::default();
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut output);
         });
         let silent_buffer = make_silent_buffer(num_samples);
@@ -434,7 +435,7 @@ mod test {
             let buffer: Vec<f32> = (0..10).map(|_i| 1.0).collect();
             let mut buffer = AudioBuffer::from_interleaved(1, &buffer);
             looper.handle.start_recording();
-            assert_no_alloc(|| {
+            assert_realtime_safe(|| {
                 looper.process(&mut context, &mut buffer);
             });
             looper
@@ -443,7 +444,7 @@ mod test {
 
             let buffer: Vec<f32> = (0..10).map(|_i| 0.0).collect();
             let mut buffer = AudioBuffer::from_interleaved(1, &buffer);
-            assert_no_alloc(|| {
+            assert_realtime_safe(|| {
                 looper.process(&mut context, &mut buffer);
             });
             let output_vec = buffer.channel(0).to_vec();
@@ -456,7 +457,7 @@ mod test {
             let buffer: Vec<f32> = (0..10).map(|_i| 1.0).collect();
             let mut buffer = AudioBuffer::from_interleaved(1, &buffer);
             looper.handle.start_recording();
-            assert_no_alloc(|| {
+            assert_realtime_safe(|| {
                 looper.process(&mut context, &mut buffer);
             });
             looper
@@ -468,12 +469,23 @@ mod test {
         let buffer: Vec<f32> = (0..10).map(|_i| 0.0).collect();
         let mut buffer = AudioBuffer::from_interleaved(1, &buffer);
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut buffer);
         });
         let output_vec = buffer.channel(0).to_vec();
         let sample_vec: Vec<f32> = (0..10).map(|_i| 2.0).collect();
         assert_eq!(output_vec, sample_vec, "Overdub didn't work");
+
+        // Undo is applied by the next block
+        assert!(looper.handle.undo_overdub());
+        let buffer: Vec<f32> = (0..10).map(|_i| 0.0).collect();
+        let mut buffer = AudioBuffer::from_interleaved(1, &buffer);
+        assert_realtime_safe(|| {
+            looper.process(&mut context, &mut buffer);
+        });
+        let output_vec = buffer.channel(0).to_vec();
+        let sample_vec: Vec<f32> = (0..10).map(|_i| 1.0).collect();
+        assert_eq!(output_vec, sample_vec, "Undo didn't work");
     }
 
     #[test]
@@ -487,7 +499,7 @@ mod test {
         let num_samples = (MAX_LOOP_LENGTH_SECS * settings.sample_rate) as usize - 30;
         let sample_buffer: Vec<f32> = (0..num_samples).map(|i| i as f32).collect();
         let mut sample_buffer = AudioBuffer::from_interleaved(1, &sample_buffer);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut sample_buffer);
         });
 
@@ -499,7 +511,7 @@ mod test {
         let mut sample_buffer = AudioBuffer::from_interleaved(1, &sample_buffer);
 
         looper.handle.start_recording();
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut sample_buffer);
         });
         looper
@@ -517,7 +529,7 @@ mod test {
         let output_buffer: Vec<f32> = (0..10).map(|_i| 0.0).collect();
         let mut output_buffer = AudioBuffer::from_interleaved(1, &output_buffer);
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut output_buffer);
         });
         let output_vec = output_buffer.channel(0).to_vec();
@@ -529,7 +541,7 @@ mod test {
 
         audio_buffer::clear(&mut output_buffer);
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut output_buffer);
         });
         let output_vec = output_buffer.channel(0).to_vec();
@@ -541,7 +553,7 @@ mod test {
 
         // Stop looper
         looper.handle().pause();
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut sample_buffer);
         });
         let empty_buffer: Vec<f32> = (0..10).map(|_i| 0.0).collect();
@@ -578,7 +590,7 @@ mod test {
             let sample_buffer: Vec<f32> = (0..100).map(|i| i as f32).collect();
             let mut sample_buffer = AudioBuffer::from_interleaved(1, &sample_buffer);
             // We process 1s of audio; which is 1 beat
-            assert_no_alloc(|| {
+            assert_realtime_safe(|| {
                 looper.process(&mut context, &mut sample_buffer);
             });
         }
@@ -598,7 +610,7 @@ mod test {
         // We record some audio in
         let recorded_buffer: Vec<f32> = (0..400).map(|i| i as f32).collect();
         let mut recorded_buffer = AudioBuffer::from_interleaved(1, &recorded_buffer);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut recorded_buffer);
         });
         let position_beats = get_position_beats(&mut looper);
@@ -611,7 +623,7 @@ mod test {
         // We expect audio to be played back now
         let output_buffer: Vec<f32> = (0..200).map(|_i| 0.0).collect();
         let mut output_buffer = AudioBuffer::from_interleaved(1, &output_buffer);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             looper.process(&mut context, &mut output_buffer);
         });
         assert_eq!(looper.handle.state(), LooperState::Playing);
@@ -632,4 +644,13 @@ mod test {
             .position_beats()
             .unwrap()
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            LooperProcessor::default(),
+            Default::default(),
+            10,
+        );
+    }
 }
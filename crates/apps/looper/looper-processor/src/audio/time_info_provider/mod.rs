@@ -25,16 +25,19 @@
 //!
 //! * hosted-mode - The DAW provides play-head & tempo
 //! * standalone-mode - The [`TimeInfoProviderImpl`] object provides play-head & tempo
+//!   - With [`ClockSource::ClockSourceMidiClock`] the play-head follows an external MIDI clock
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use mockall::automock;
+use num_derive::{FromPrimitive, ToPrimitive};
 
 use audio_processor_metronome::MetronomePlayhead;
 #[cfg(not(target_os = "ios"))]
 use audio_processor_standalone::standalone_vst::vst;
 #[cfg(not(target_os = "ios"))]
 pub use audio_processor_standalone::standalone_vst::vst::plugin::HostCallback;
-use augmented_atomics::AtomicValue;
+use audio_processor_standalone_midi::clock::{MidiClockEvent, MidiClockFollower};
+use augmented_atomics::{AtomicEnum, AtomicValue};
 use augmented_playhead::{PlayHead, PlayHeadOptions};
 
 #[cfg(target_os = "ios")]
@@ -90,6 +93,20 @@ pub trait TimeInfoProvider {
     fn pause(&self);
 }
 
+/// Where the stand-alone play-head gets its tempo & transport from
+#[repr(C)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, FromPrimitive, ToPrimitive)]
+pub enum ClockSource {
+    /// Tempo & transport are controlled by the looper
+    ClockSourceInternal = 0,
+    /// Tempo & transport follow an incoming MIDI clock
+    ClockSourceMidiClock = 1,
+}
+
+/// Drift between the play-head and the MIDI clock which is tolerated before the play-head is
+/// moved, in beats. This is 2 clock ticks; clock messages are only seen once per audio block.
+const MIDI_CLOCK_TOLERANCE_BEATS: f64 = 2.0 / 24.0;
+
 /// Concrete [`TimeInfoProvider`] implementation that supports both a "hosted" mode for VST where
 /// the playhead state is queried from the VST Host and a "stand-alone" mode, where the playhead
 /// state is managed internally by using [`PlayHead`].
@@ -104,6 +121,7 @@ pub struct TimeInfoProviderImpl {
     host_callback: Option<HostCallback>,
     playhead: PlayHead,
     is_playing: AtomicBool,
+    clock_source: AtomicEnum<ClockSource>,
 }
 
 impl TimeInfoProvider for TimeInfoProviderImpl {
@@ -146,6 +164,7 @@ impl TimeInfoProviderImpl {
             host_callback,
             playhead: PlayHead::new(PlayHeadOptions::new(None, None, None)),
             is_playing: AtomicBool::new(false),
+            clock_source: AtomicEnum::new(ClockSource::ClockSourceInternal),
         }
     }
 
@@ -161,6 +180,48 @@ impl TimeInfoProviderImpl {
         self.playhead.set_sample_rate(sample_rate);
     }
 
+    pub fn clock_source(&self) -> ClockSource {
+        self.clock_source.get()
+    }
+
+    pub fn set_clock_source(&self, clock_source: ClockSource) {
+        self.clock_source.set(clock_source);
+    }
+
+    /// Follow a MIDI clock `event`, received by `follower`. This is a no-op unless the clock
+    /// source is [`ClockSource::ClockSourceMidiClock`].
+    ///
+    /// The play-head keeps moving on [`TimeInfoProvider::tick_n`] at the estimated clock tempo and
+    /// is moved onto the clock position if it drifts.
+    pub fn apply_midi_clock(&self, event: MidiClockEvent, follower: &MidiClockFollower) {
+        if self.clock_source() != ClockSource::ClockSourceMidiClock {
+            return;
+        }
+
+        if let Some(tempo) = follower.tempo() {
+            self.playhead.set_tempo(tempo as f32);
+        }
+
+        match event {
+            MidiClockEvent::Start | MidiClockEvent::Continue => {
+                self.playhead.set_position_beats(follower.position_beats());
+                self.play();
+            }
+            MidiClockEvent::Stop => {
+                self.pause();
+            }
+            MidiClockEvent::SongPosition { beats } => {
+                self.playhead.set_position_beats(beats);
+            }
+            MidiClockEvent::Tick => {
+                let drift = (self.playhead.position_beats() - follower.position_beats()).abs();
+                if follower.is_running() && drift > MIDI_CLOCK_TOLERANCE_BEATS {
+                    self.playhead.set_position_beats(follower.position_beats());
+                }
+            }
+        }
+    }
+
     /// Same as [`get_host_time_info`] for standalone mode
     fn get_playhead_time_info(&self) -> TimeInfo {
         TimeInfo {
@@ -362,4 +423,84 @@ mod test {
         assert_f_eq!(result.position_samples(), 1000.0);
         assert_eq!(result.is_playing(), true);
     }
+
+    fn clock_follower(messages: &[&[u8]]) -> MidiClockFollower {
+        let mut follower = MidiClockFollower::default();
+        let mut time = 0;
+        for message in messages {
+            follower.accept(time, message);
+            time += 20_833;
+        }
+        follower
+    }
+
+    #[test]
+    fn test_midi_clock_is_ignored_with_internal_clock_source() {
+        let time_info_provider = TimeInfoProviderImpl::new(None);
+        assert_eq!(
+            time_info_provider.clock_source(),
+            ClockSource::ClockSourceInternal
+        );
+        let follower = clock_follower(&[&[0xFA]]);
+        time_info_provider.apply_midi_clock(MidiClockEvent::Start, &follower);
+        assert!(!time_info_provider.get_time_info().is_playing());
+    }
+
+    #[test]
+    fn test_midi_clock_drives_the_transport() {
+        let time_info_provider = TimeInfoProviderImpl::new(None);
+        time_info_provider.set_clock_source(ClockSource::ClockSourceMidiClock);
+        time_info_provider.set_sample_rate(1000.0);
+
+        let mut messages: Vec<&[u8]> = vec![&[0xF8]; 48];
+        messages.push(&[0xFA]);
+        let follower = clock_follower(&messages);
+        time_info_provider.apply_midi_clock(MidiClockEvent::Start, &follower);
+        let time_info = time_info_provider.get_time_info();
+        assert!(time_info.is_playing());
+        assert!((time_info.tempo().unwrap() - 120.0).abs() < 0.01);
+        assert_f_eq!(time_info.position_beats().unwrap(), 0.0);
+
+        time_info_provider.apply_midi_clock(MidiClockEvent::Stop, &follower);
+        assert!(!time_info_provider.get_time_info().is_playing());
+
+        time_info_provider.apply_midi_clock(MidiClockEvent::SongPosition { beats: 8.0 }, &follower);
+        assert_f_eq!(
+            time_info_provider.get_time_info().position_beats().unwrap(),
+            8.0
+        );
+    }
+
+    #[test]
+    fn test_midi_clock_corrects_drift() {
+        let time_info_provider = TimeInfoProviderImpl::new(None);
+        time_info_provider.set_clock_source(ClockSource::ClockSourceMidiClock);
+        time_info_provider.set_sample_rate(1000.0);
+
+        let mut messages: Vec<&[u8]> = vec![&[0xF8]; 48];
+        messages.push(&[0xFA]);
+        messages.extend(vec![&[0xF8][..]; 25]);
+        let follower = clock_follower(&messages);
+        assert_f_eq!(follower.position_beats(), 1.0);
+
+        time_info_provider.play();
+        time_info_provider.apply_midi_clock(MidiClockEvent::Tick, &follower);
+        assert_f_eq!(
+            time_info_provider.get_time_info().position_beats().unwrap(),
+            1.0
+        );
+
+        // Small drift is tolerated
+        time_info_provider.tick_n(20);
+        time_info_provider.apply_midi_clock(MidiClockEvent::Tick, &follower);
+        let position_beats = time_info_provider.get_time_info().position_beats().unwrap();
+        assert!((position_beats - 1.04).abs() < 1e-4);
+
+        time_info_provider.tick_n(500);
+        time_info_provider.apply_midi_clock(MidiClockEvent::Tick, &follower);
+        assert_f_eq!(
+            time_info_provider.get_time_info().position_beats().unwrap(),
+            1.0
+        );
+    }
 }
@@ -137,6 +137,27 @@ pub unsafe extern "C" fn audio_io_settings_controller__set_output_device(
     controller.set_output_device(device);
 }
 
+/// Re-open the input device with `channels` channels. Devices with fewer channels use all of them.
+#[no_mangle]
+pub unsafe extern "C" fn audio_io_settings_controller__set_input_channels(
+    engine: *const LooperEngine,
+    channels: usize,
+) {
+    let controller = (*engine).audio_io_settings_controller();
+    controller.set_input_channels(channels);
+}
+
+/// Re-open the output device with `channels` channels. Devices with fewer channels use all of
+/// them.
+#[no_mangle]
+pub unsafe extern "C" fn audio_io_settings_controller__set_output_channels(
+    engine: *const LooperEngine,
+    channels: usize,
+) {
+    let controller = (*engine).audio_io_settings_controller();
+    controller.set_output_channels(channels);
+}
+
 fn into_c_model(devices: anyhow::Result<Vec<AudioDevice>>) -> CAudioDeviceList {
     match devices {
         Ok(device) => {
@@ -24,7 +24,9 @@
 use std::ffi::CString;
 use std::os::raw::c_char;
 
+use crate::audio::multi_track_looper::effects_processor::factory_presets;
 use crate::c_api::into_ptr;
+use crate::parameters::LooperId;
 use crate::services::effects_service::{EffectDefinition, EffectParameterModel, EffectsService};
 use crate::LooperEngine;
 
@@ -190,16 +192,154 @@ pub unsafe extern "C" fn looper_engine__get_effect_definitions() -> *mut CEffect
     })
 }
 
-/// Add an effect of type `EffectType` into the track with `LooperId`.
+#[no_mangle]
+pub unsafe extern "C" fn effect_definition__presets_count(
+    definition: *mut EffectDefinition,
+) -> usize {
+    (*definition).presets.len()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn effect_definition__preset_name(
+    definition: *mut EffectDefinition,
+    index: usize,
+) -> *mut c_char {
+    let definition = &(*definition);
+    CString::new(definition.presets[index].name.clone())
+        .unwrap_or_else(|_| CString::new("unknown").unwrap())
+        .into_raw()
+}
+
+/// Add an effect of type `EffectType` into the track with `LooperId`. Returns the slot ID of the
+/// new effect or -1 if the chain is full.
 #[no_mangle]
 pub unsafe extern "C" fn looper_engine__add_effect(
     engine: *const LooperEngine,
     looper_id: usize,
     effect_type: usize,
-) {
+) -> i32 {
     let handle = (*engine).handle();
     let definitions = EffectsService::get_effects();
     let effect_type = definitions[effect_type].ty.clone();
 
-    handle.voices()[looper_id].effects().add_effect(effect_type);
+    handle
+        .add_effect(LooperId(looper_id), effect_type)
+        .map(|slot_id| slot_id as i32)
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__remove_effect(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    slot_id: usize,
+) {
+    (*engine)
+        .handle()
+        .remove_effect(LooperId(looper_id), slot_id);
+}
+
+/// Move the effect at position `from` in the track's chain to position `to`
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__move_effect(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    from: usize,
+    to: usize,
+) {
+    (*engine)
+        .handle()
+        .move_effect(LooperId(looper_id), from, to);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_effect_slots_count(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> usize {
+    (*engine).handle().voices()[looper_id]
+        .effects()
+        .slots()
+        .len()
+}
+
+/// Slot ID of the effect at `position` in the track's chain
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_effect_slot_id(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    position: usize,
+) -> usize {
+    (*engine).handle().voices()[looper_id].effects().slots()[position].id()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_effect_bypassed(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    slot_id: usize,
+    bypassed: bool,
+) {
+    (*engine).handle().voices()[looper_id]
+        .effects()
+        .set_bypassed(slot_id, bypassed);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_effect_mix(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    slot_id: usize,
+    mix: f32,
+) {
+    (*engine).handle().voices()[looper_id]
+        .effects()
+        .set_mix(slot_id, mix);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_effect_parameter(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    slot_id: usize,
+    parameter: usize,
+    value: f32,
+) {
+    (*engine)
+        .handle()
+        .set_effect_parameter(LooperId(looper_id), slot_id, parameter, value);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_effect_parameter(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    slot_id: usize,
+    parameter: usize,
+) -> f32 {
+    (*engine).handle().voices()[looper_id]
+        .effects()
+        .slot(slot_id)
+        .and_then(|slot| slot.parameter(parameter))
+        .unwrap_or(0.0)
+}
+
+/// Apply the `preset_index`th factory preset of the effect on `slot_id`, as listed by
+/// `effect_definition__preset_name`
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__apply_effect_preset(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    slot_id: usize,
+    preset_index: usize,
+) {
+    let handle = (*engine).handle();
+    let slot = handle.voices()[looper_id].effects().slot(slot_id);
+    if let Some(preset) = slot.and_then(|slot| {
+        factory_presets(slot.effect_type())
+            .into_iter()
+            .nth(preset_index)
+    }) {
+        handle.apply_effect_preset(LooperId(looper_id), slot_id, &preset);
+    }
 }
@@ -63,6 +63,44 @@ pub unsafe extern "C" fn looper_engine__clear(engine: *const LooperEngine, loope
     (*engine).handle().clear(LooperId(looper_id));
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__undo_overdub(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> bool {
+    log::info!("looper_engine - Undo overdub {}", looper_id);
+    (*engine).handle().undo_overdub(LooperId(looper_id))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__redo_overdub(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> bool {
+    log::info!("looper_engine - Redo overdub {}", looper_id);
+    (*engine).handle().redo_overdub(LooperId(looper_id))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_overdub_undo_count(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> usize {
+    (*engine)
+        .handle()
+        .get_overdub_undo_count(LooperId(looper_id))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_overdub_redo_count(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> usize {
+    (*engine)
+        .handle()
+        .get_overdub_redo_count(LooperId(looper_id))
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn looper_engine__trigger_looper(
     engine: *const LooperEngine,
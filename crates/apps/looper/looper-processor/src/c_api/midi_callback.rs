@@ -20,11 +20,16 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::os::raw::c_char;
 use std::sync::atomic::AtomicBool;
 
 use audio_garbage_collector::make_shared;
 
-use crate::audio::midi_map::MidiControllerNumber;
+use crate::audio::midi_map::{
+    MidiControllerNumber, MidiEncoderMode, MidiMapping, MidiMappingKey, MidiMessageKind,
+};
 pub use crate::audio::multi_track_looper::midi_store::MidiEvent;
 use crate::audio::multi_track_looper::midi_store::MidiStoreActor;
 use crate::audio::multi_track_looper::parameters::EntityId;
@@ -51,6 +56,111 @@ pub unsafe extern "C" fn looper_engine__add_midi_mapping(
     );
 }
 
+fn midi_mapping_key(kind: MidiMessageKind, channel: i32, number: u8) -> MidiMappingKey {
+    MidiMappingKey {
+        kind,
+        channel: u8::try_from(channel).ok(),
+        number,
+    }
+}
+
+/// Map a MIDI message onto an entity. A negative `channel` matches messages on any channel.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn looper_engine__add_midi_mapping_with_options(
+    engine: *const LooperEngine,
+    kind: MidiMessageKind,
+    channel: i32,
+    number: u8,
+    entity_id: EntityId,
+    encoder_mode: MidiEncoderMode,
+    min: f32,
+    max: f32,
+    soft_takeover: bool,
+) {
+    let key = midi_mapping_key(kind, channel, number);
+    log::info!(
+        "Adding MIDI mapping key={:?} parameter_id={:?}",
+        key,
+        entity_id
+    );
+    let mapping = MidiMapping::new(entity_id)
+        .with_encoder_mode(encoder_mode)
+        .with_range(min, max)
+        .with_soft_takeover(soft_takeover);
+    (*engine).midi_store().midi_map().add_mapping(key, mapping);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__remove_midi_mapping(
+    engine: *const LooperEngine,
+    kind: MidiMessageKind,
+    channel: i32,
+    number: u8,
+) {
+    (*engine)
+        .midi_store()
+        .midi_map()
+        .remove_mapping(&midi_mapping_key(kind, channel, number));
+}
+
+/// Map the next MIDI message received onto `entity_id`.
+/// `looper_engine__poll_midi_learn` must be called until it returns true to create the mapping.
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__start_midi_learn(
+    engine: *const LooperEngine,
+    entity_id: EntityId,
+) {
+    (*engine)
+        .midi_store()
+        .midi_map()
+        .start_learn(MidiMapping::new(entity_id));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__cancel_midi_learn(engine: *const LooperEngine) {
+    (*engine).midi_store().midi_map().cancel_learn();
+}
+
+/// Returns true once a mapping was learned
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__poll_midi_learn(engine: *const LooperEngine) -> bool {
+    (*engine).midi_store().midi_map().poll_learn().is_some()
+}
+
+/// Send MIDI feedback to the first output port whose name contains `port_name`. Returns false
+/// if no port was found or the connection failed. Does nothing if feedback is already being sent.
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__start_midi_feedback(
+    engine: *const LooperEngine,
+    port_name: *const c_char,
+) -> bool {
+    let port_name = CStr::from_ptr(port_name).to_str().unwrap_or("");
+    (*engine).start_midi_feedback(port_name)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__stop_midi_feedback(engine: *const LooperEngine) {
+    (*engine).stop_midi_feedback();
+}
+
+/// Send MIDI clock & transport messages following the looper play-head to the first output port
+/// whose name contains `port_name`. Returns false if no port was found or the connection failed.
+/// Does nothing if clock is already being sent.
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__start_midi_clock_output(
+    engine: *const LooperEngine,
+    port_name: *const c_char,
+) -> bool {
+    let port_name = CStr::from_ptr(port_name).to_str().unwrap_or("");
+    (*engine).start_midi_clock_output(port_name)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__stop_midi_clock_output(engine: *const LooperEngine) {
+    (*engine).stop_midi_clock_output();
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn looper_engine__register_midi_callback(
     engine: *const LooperEngine,
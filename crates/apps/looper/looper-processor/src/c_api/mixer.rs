@@ -0,0 +1,225 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::services::effects_service::EffectsService;
+use crate::{LooperEngine, LooperId};
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_pan(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    pan: f32,
+) {
+    (*engine).handle().mixer().set_pan(LooperId(looper_id), pan);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_muted(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    is_muted: bool,
+) {
+    (*engine)
+        .handle()
+        .mixer()
+        .set_muted(LooperId(looper_id), is_muted);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__is_track_muted(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> bool {
+    (*engine)
+        .handle()
+        .mixer()
+        .track(LooperId(looper_id))
+        .map(|track| track.is_muted())
+        .unwrap_or(false)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_soloed(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    is_soloed: bool,
+) {
+    (*engine)
+        .handle()
+        .mixer()
+        .set_soloed(LooperId(looper_id), is_soloed);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__is_track_soloed(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> bool {
+    (*engine)
+        .handle()
+        .mixer()
+        .track(LooperId(looper_id))
+        .map(|track| track.is_soloed())
+        .unwrap_or(false)
+}
+
+/// Put the track in an exclusive mute/solo group. A negative `group` removes the track from its
+/// group.
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_group(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    group: i32,
+) {
+    let group = if group < 0 {
+        None
+    } else {
+        Some(group as usize)
+    };
+    (*engine)
+        .handle()
+        .mixer()
+        .set_group(LooperId(looper_id), group);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_send_level(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    bus: usize,
+    level: f32,
+) {
+    (*engine)
+        .handle()
+        .mixer()
+        .set_send_level(LooperId(looper_id), bus, level);
+}
+
+/// RMS level of the track after pan & mute/solo
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_track_level(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> f32 {
+    (*engine)
+        .handle()
+        .mixer()
+        .track(LooperId(looper_id))
+        .map(|track| track.level())
+        .unwrap_or(0.0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__num_aux_buses(engine: *const LooperEngine) -> usize {
+    (*engine).handle().mixer().aux_buses().len()
+}
+
+/// Add an effect of type `EffectType` into the aux bus at `bus`.
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__add_aux_effect(
+    engine: *const LooperEngine,
+    bus: usize,
+    effect_type: usize,
+) {
+    let handle = (*engine).handle();
+    let definitions = EffectsService::get_effects();
+    let effect_type = definitions[effect_type].ty.clone();
+
+    if let Some(aux_bus) = handle.mixer().aux_bus(bus) {
+        aux_bus.effects().add_effect(effect_type);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_aux_return_level(
+    engine: *const LooperEngine,
+    bus: usize,
+    level: f32,
+) {
+    if let Some(aux_bus) = (*engine).handle().mixer().aux_bus(bus) {
+        aux_bus.set_return_level(level);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_aux_level(
+    engine: *const LooperEngine,
+    bus: usize,
+) -> f32 {
+    (*engine)
+        .handle()
+        .mixer()
+        .aux_bus(bus)
+        .map(|aux_bus| aux_bus.level())
+        .unwrap_or(0.0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_master_volume(
+    engine: *const LooperEngine,
+    volume: f32,
+) {
+    (*engine).handle().mixer().master().set_volume(volume);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_master_limiter_enabled(
+    engine: *const LooperEngine,
+    enabled: bool,
+) {
+    (*engine)
+        .handle()
+        .mixer()
+        .master()
+        .set_limiter_enabled(enabled);
+}
+
+/// Set the master limiter ceiling in dBFS
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_master_limiter_ceiling(
+    engine: *const LooperEngine,
+    ceiling_db: f32,
+) {
+    (*engine)
+        .handle()
+        .mixer()
+        .master()
+        .set_limiter_ceiling_db(ceiling_db);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_master_level(engine: *const LooperEngine) -> f32 {
+    (*engine).handle().mixer().master().level()
+}
+
+/// Current gain reduction of the master limiter in dB, 0 when it isn't limiting
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_master_gain_reduction(
+    engine: *const LooperEngine,
+) -> f32 {
+    (*engine)
+        .handle()
+        .mixer()
+        .master()
+        .limiter_gain_reduction_db()
+}
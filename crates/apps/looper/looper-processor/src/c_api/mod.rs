@@ -35,7 +35,10 @@ use crate::audio::multi_track_looper::parameters::ParameterValue;
 use crate::audio::multi_track_looper::parameters::{
     CQuantizeMode, EnvelopeParameter, LFOParameter, LooperId, SourceParameter, TempoControl,
 };
+use crate::audio::multi_track_looper::trigger_model::sequencer::SongEntry;
+use crate::audio::multi_track_looper::trigger_model::CTriggerCondition;
 pub use crate::engine::LooperEngine;
+use crate::ClockSource;
 use crate::TimeInfoProvider;
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -49,6 +52,8 @@ pub use self::lfos::*;
 pub use self::looper::*;
 pub use self::metrics::*;
 pub use self::midi_callback::*;
+pub use self::mixer::*;
+pub use self::routing::*;
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 mod analytics;
@@ -62,6 +67,8 @@ mod lfos;
 mod looper;
 mod metrics;
 mod midi_callback;
+mod mixer;
+mod routing;
 
 fn into_ptr<T>(value: T) -> *mut T {
     Box::into_raw(Box::new(value))
@@ -134,6 +141,137 @@ pub unsafe extern "C" fn looper_engine__toggle_trigger(
         .toggle_trigger(LooperId(looper_id), position_beats)
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_pattern_length(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    pattern_length: usize,
+) {
+    (*engine)
+        .handle()
+        .set_pattern_length(LooperId(looper_id), pattern_length)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_pattern_step_beats(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    pattern_step_beats: f64,
+) {
+    (*engine)
+        .handle()
+        .set_pattern_step_beats(LooperId(looper_id), pattern_step_beats)
+}
+
+/// A negative swing makes the track follow the global swing
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_swing(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    swing: f32,
+) {
+    let swing = if swing < 0.0 { None } else { Some(swing) };
+    (*engine)
+        .handle()
+        .set_track_swing(LooperId(looper_id), swing)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_swing(engine: *const LooperEngine, swing: f32) {
+    (*engine).handle().set_swing(swing)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_fill(engine: *const LooperEngine, is_fill: bool) {
+    (*engine).handle().set_fill(is_fill)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_trigger_probability(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    step: usize,
+    probability: f32,
+) {
+    (*engine)
+        .handle()
+        .set_trigger_probability(LooperId(looper_id), step, probability)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_trigger_condition(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    step: usize,
+    condition: CTriggerCondition,
+    every: usize,
+    offset: usize,
+) {
+    (*engine).handle().set_trigger_condition(
+        LooperId(looper_id),
+        step,
+        condition.into_condition(every, offset),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_trigger_micro_timing(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    step: usize,
+    micro_timing: f32,
+) {
+    (*engine)
+        .handle()
+        .set_trigger_micro_timing(LooperId(looper_id), step, micro_timing)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_trigger_ratchets(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    step: usize,
+    ratchets: usize,
+) {
+    (*engine)
+        .handle()
+        .set_trigger_ratchets(LooperId(looper_id), step, ratchets)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__select_pattern(
+    engine: *const LooperEngine,
+    pattern: usize,
+) {
+    (*engine).handle().select_pattern(pattern)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__clear_song(engine: *const LooperEngine) {
+    (*engine).handle().set_song(vec![])
+}
+
+/// Append `pattern` for `bars` bars to the song
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__add_song_entry(
+    engine: *const LooperEngine,
+    pattern: usize,
+    bars: usize,
+) {
+    let handle = (*engine).handle();
+    let mut song = handle.sequencer().song().to_vec();
+    song.push(SongEntry { pattern, bars });
+    handle.set_song(song)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_song_enabled(
+    engine: *const LooperEngine,
+    song_enabled: bool,
+) {
+    (*engine).handle().set_song_enabled(song_enabled)
+}
+
 #[no_mangle]
 pub extern "C" fn looper_engine__source_parameter_id(parameter: SourceParameter) -> ParameterId {
     ParameterId::ParameterIdSource(parameter)
@@ -154,6 +292,16 @@ pub extern "C" fn looper_engine__lfo_parameter_id(
     ParameterId::ParameterIdLFO(lfo, parameter)
 }
 
+/// Parameter ID of the `parameter`th parameter of the effect on slot `slot_id`, to be used for
+/// parameter locks & LFO mappings
+#[no_mangle]
+pub extern "C" fn looper_engine__effect_parameter_id(
+    slot_id: usize,
+    parameter: usize,
+) -> ParameterId {
+    ParameterId::ParameterIdEffect(slot_id, parameter)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn looper_engine__set_boolean_parameter(
     engine: *const LooperEngine,
@@ -313,6 +461,35 @@ pub unsafe extern "C" fn looper_engine__set_tempo(engine: *const LooperEngine, t
     handle.set_tempo(tempo);
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_clock_source(
+    engine: *const LooperEngine,
+    clock_source: ClockSource,
+) {
+    (*engine).handle().set_clock_source(clock_source);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__start_tempo_sync(engine: *const LooperEngine) -> bool {
+    match (*engine).start_tempo_sync() {
+        Ok(()) => true,
+        Err(err) => {
+            log::error!("Failed to start tempo sync {}", err);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__stop_tempo_sync(engine: *const LooperEngine) {
+    (*engine).stop_tempo_sync();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__is_tempo_sync_enabled(engine: *const LooperEngine) -> bool {
+    (*engine).is_tempo_sync_enabled()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn looper_engine__get_playhead_position(
     engine: *const LooperEngine,
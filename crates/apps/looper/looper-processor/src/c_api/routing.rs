@@ -0,0 +1,88 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::{LooperEngine, LooperId};
+
+/// Record the track from device input `channel`. If `is_mono` is true this channel is recorded
+/// onto both sides of the track, otherwise `channel` & `channel + 1` are recorded.
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_input(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    channel: usize,
+    is_mono: bool,
+) {
+    (*engine)
+        .handle()
+        .routing()
+        .set_input(LooperId(looper_id), channel, is_mono);
+}
+
+/// Play the track into the stereo output bus `bus`. Bus 0 is the main mix, bus `n` is the device
+/// channel pair `2n, 2n + 1`.
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_track_output_bus(
+    engine: *const LooperEngine,
+    looper_id: usize,
+    bus: usize,
+) {
+    (*engine)
+        .handle()
+        .routing()
+        .set_output_bus(LooperId(looper_id), bus);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__get_track_output_bus(
+    engine: *const LooperEngine,
+    looper_id: usize,
+) -> usize {
+    (*engine)
+        .handle()
+        .routing()
+        .track(LooperId(looper_id))
+        .map(|track| track.output_bus())
+        .unwrap_or(0)
+}
+
+/// Play every track in the mixer's exclusive `group` into the stereo output bus `bus`
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__set_group_output_bus(
+    engine: *const LooperEngine,
+    group: usize,
+    bus: usize,
+) {
+    (*engine).handle().set_group_output_bus(group, bus);
+}
+
+/// Number of stereo output buses on the current device, including the main mix
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__num_output_buses(engine: *const LooperEngine) -> usize {
+    (*engine).handle().routing().num_output_buses()
+}
+
+/// Number of input channels on the current device
+#[no_mangle]
+pub unsafe extern "C" fn looper_engine__num_input_channels(engine: *const LooperEngine) -> usize {
+    (*engine).handle().routing().num_input_channels()
+}
@@ -66,6 +66,30 @@ impl AudioIOSettingsController {
         });
     }
 
+    /// Re-open the input device with `channels` channels
+    pub fn set_input_channels(&self, channels: usize) {
+        let addr = self.audio_state_controller.clone();
+        ActorSystem::current().spawn(async move {
+            addr.send(SetOptions::InputChannels(channels))
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to set input channels {}", err);
+                });
+        });
+    }
+
+    /// Re-open the output device with `channels` channels
+    pub fn set_output_channels(&self, channels: usize) {
+        let addr = self.audio_state_controller.clone();
+        ActorSystem::current().spawn(async move {
+            addr.send(SetOptions::OutputChannels(channels))
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to set output channels {}", err);
+                });
+        });
+    }
+
     pub fn input_device(&self) -> String {
         let addr = self.audio_state_controller.clone();
         let options = ActorSystem::current()
@@ -60,6 +60,10 @@ impl AudioStateController {
                 .and_then(|s| s.as_str().map(|s| s.to_string())),
             output_device: defaults_service::get("output-device")
                 .and_then(|s| s.as_str().map(|s| s.to_string())),
+            input_channels: defaults_service::get("input-channels")
+                .and_then(|v| v.as_integer().map(|i| i as usize)),
+            output_channels: defaults_service::get("output-channels")
+                .and_then(|v| v.as_integer().map(|i| i as usize)),
             ..StandaloneOptions::default()
         };
         let handle = processor.handle().clone();
@@ -83,15 +87,26 @@ impl AudioStateController {
                 defaults_service::set(key, defaults_service::Value::String(str.clone()));
             }
         };
+        let insert_channels = |key, maybe_channels: Option<usize>| {
+            if let Some(channels) = maybe_channels {
+                defaults_service::set(key, defaults_service::Value::Integer(channels as i64));
+            }
+        };
         insert_some("input-device", &options.input_device);
         insert_some("output-device", &options.output_device);
+        insert_channels("input-channels", options.input_channels);
+        insert_channels("output-channels", options.output_channels);
         if options.input_device == current_options.input_device
             && options.output_device == current_options.output_device
+            && options.input_channels == current_options.input_channels
+            && options.output_channels == current_options.output_channels
         {
             log::warn!(
-                "Ignoring noop IO options update input={:?} output={:?}",
+                "Ignoring noop IO options update input={:?} output={:?} input_channels={:?} output_channels={:?}",
                 options.input_device,
-                options.output_device
+                options.output_device,
+                options.input_channels,
+                options.output_channels
             );
             return;
         }
@@ -116,20 +131,19 @@ impl AudioStateController {
 fn setup_audio_state(options: StandaloneOptions, processor: MultiTrackLooper) -> AudioState {
     let standalone_processor = StandaloneProcessorImpl::new_with(processor, options);
     let handles = audio_processor_standalone::standalone_start_for_env!(standalone_processor);
+    let configuration = handles.configuration();
     let options = StandaloneOptions {
         accepts_input: true,
-        input_device: handles
-            .configuration()
+        input_device: configuration
             .input_configuration()
             .as_ref()
             .map(|config| config.name().to_string()),
-        output_device: Some(
-            handles
-                .configuration()
-                .output_configuration()
-                .name()
-                .to_string(),
-        ),
+        output_device: Some(configuration.output_configuration().name().to_string()),
+        input_channels: configuration
+            .input_configuration()
+            .as_ref()
+            .map(|config| config.num_channels() as usize),
+        output_channels: Some(configuration.output_configuration().num_channels() as usize),
         handle: None,
     };
 
@@ -146,6 +160,8 @@ pub enum SetOptions {
     StandaloneOptions(StandaloneOptions),
     InputDevice(String),
     OutputDevice(String),
+    InputChannels(usize),
+    OutputChannels(usize),
 }
 
 impl Handler<SetOptions> for AudioStateController {
@@ -165,6 +181,14 @@ impl Handler<SetOptions> for AudioStateController {
                 output_device: Some(output_device),
                 ..options
             }),
+            SetOptions::InputChannels(input_channels) => self.set_options(StandaloneOptions {
+                input_channels: Some(input_channels),
+                ..options
+            }),
+            SetOptions::OutputChannels(output_channels) => self.set_options(StandaloneOptions {
+                output_channels: Some(output_channels),
+                ..options
+            }),
         }
     }
 }
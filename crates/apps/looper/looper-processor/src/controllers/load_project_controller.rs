@@ -24,11 +24,20 @@ use actix::Addr;
 use basedrop::Shared;
 
 use crate::audio::multi_track_looper::looper_voice::LooperVoice;
-use crate::audio::multi_track_looper::parameters::{build_default_parameters, ParameterId};
+use crate::audio::multi_track_looper::mixer::MixerHandle;
+use crate::audio::multi_track_looper::parameters::{
+    build_default_parameters, LooperId, ParameterId,
+};
+use crate::audio::multi_track_looper::routing::RoutingHandle;
+use crate::audio::multi_track_looper::trigger_model::sequencer::SequencerHandle;
+use crate::audio::multi_track_looper::trigger_model::TrackTriggerModel;
 use crate::audio::multi_track_looper::ParametersMap;
 use crate::controllers::events_controller::{ApplicationEvent, BroadcastMessage, EventsController};
 use crate::services::audio_clip_manager::{AudioClipManager, AudioClipModelRef, LoadClipMessage};
-use crate::services::project_manager::model::{LooperVoicePersist, Project};
+use crate::services::project_manager::model::{
+    EffectChainPersist, LooperVoicePersist, MixerPersist, Project, RoutingPersist,
+    SequencerPersist, TrackTriggerModelPersist,
+};
 use crate::services::project_manager::{LoadLatestProjectMessage, ProjectManager};
 use crate::MultiTrackLooperHandle;
 use actix_system_threads::ActorSystem;
@@ -64,8 +73,8 @@ async fn run_load_latest_project(context: LoadContext) -> anyhow::Result<()> {
     log::info!("Loaded previous project, hydrating...");
     {
         // MIDI in-place copy
-        for (spec, action) in latest_project.midi_map.iter() {
-            handle.midi().midi_map().add(*spec, action.clone());
+        for (key, mapping) in latest_project.midi_map.iter() {
+            handle.midi().midi_map().add_mapping(*key, mapping.clone());
         }
     }
     {
@@ -97,11 +106,26 @@ async fn run_load_latest_project(context: LoadContext) -> anyhow::Result<()> {
                 &source_voice.parameter_values,
                 destination_voice.user_parameters(),
             );
-            let trigger_model = destination_voice.trigger_model();
-            trigger_model.add_triggers(&source_voice.triggers.triggers);
+            copy_triggers(&source_voice.triggers, destination_voice.trigger_model());
             copy_lfo(&parameter_ids, source_voice, destination_voice)
         }
     }
+    if let Some(mixer) = &latest_project.mixer {
+        // mixer in-place
+        copy_mixer(mixer, handle.mixer());
+    }
+    if let Some(routing) = &latest_project.routing {
+        // routing in-place
+        copy_routing(routing, handle.routing());
+    }
+    if let Some(effects) = &latest_project.effects {
+        // effects in-place
+        copy_effects(effects, &handle);
+    }
+    if let Some(sequencer) = &latest_project.sequencer {
+        // sequencer in-place
+        copy_sequencer(sequencer, handle.sequencer());
+    }
     {
         // clips in-place
         copy_clips(
@@ -165,6 +189,92 @@ fn copy_lfo(
     }
 }
 
+fn copy_triggers(source: &TrackTriggerModelPersist, destination: &TrackTriggerModel) {
+    destination.set_pattern_length(source.pattern_length);
+    destination.set_pattern_step_beats(source.pattern_step_beats);
+    destination.set_swing(source.swing);
+    destination.select_pattern(source.selected_pattern);
+    for (pattern, triggers) in source.patterns.iter().enumerate() {
+        destination.set_pattern_triggers(pattern, triggers);
+    }
+    destination.add_triggers(&source.triggers);
+}
+
+fn copy_sequencer(source: &SequencerPersist, destination: &SequencerHandle) {
+    destination.set_swing(source.swing);
+    destination.set_song(source.song.clone());
+    destination.set_song_enabled(source.song_enabled);
+}
+
+fn copy_mixer(source: &MixerPersist, destination: &MixerHandle) {
+    for (index, track) in source.tracks.iter().enumerate() {
+        let looper_id = LooperId(index);
+        destination.set_pan(looper_id, track.pan);
+        destination.set_muted(looper_id, track.is_muted);
+        destination.set_soloed(looper_id, track.is_soloed);
+        for (bus, level) in track.send_levels.iter().enumerate() {
+            destination.set_send_level(looper_id, bus, *level);
+        }
+    }
+    // Groups are set after mute/solo states so restoring them doesn't trigger the exclusive
+    // group behaviour
+    for (index, track) in source.tracks.iter().enumerate() {
+        destination.set_group(LooperId(index), track.group);
+    }
+
+    for (source_aux_bus, destination_aux_bus) in
+        source.aux_buses.iter().zip(destination.aux_buses())
+    {
+        destination_aux_bus.set_return_level(source_aux_bus.return_level);
+        let effects = destination_aux_bus.effects();
+        if effects.effect_types().is_empty() {
+            for effect in &source_aux_bus.effects {
+                effects.add_effect(effect.clone());
+            }
+        }
+    }
+
+    let master = destination.master();
+    master.set_volume(source.master.volume);
+    master.set_limiter_enabled(source.master.limiter_enabled);
+    master.set_limiter_ceiling_db(source.master.limiter_ceiling_db);
+    master.set_limiter_release_ms(source.master.limiter_release_ms);
+}
+
+fn copy_routing(source: &RoutingPersist, destination: &RoutingHandle) {
+    for (index, track) in source.tracks.iter().enumerate() {
+        let looper_id = LooperId(index);
+        destination.set_input(looper_id, track.input_channel, track.input_is_mono);
+        destination.set_output_bus(looper_id, track.output_bus);
+    }
+}
+
+fn copy_effects(source: &[EffectChainPersist], destination: &MultiTrackLooperHandle) {
+    for (index, chain) in source.iter().enumerate() {
+        let looper_id = LooperId(index);
+        let is_empty = destination
+            .get(looper_id)
+            .map(|voice| voice.effects().effect_types().is_empty())
+            .unwrap_or(false);
+        if !is_empty {
+            continue;
+        }
+
+        for slot in &chain.slots {
+            let slot_id =
+                destination.add_effect_at_slot(looper_id, slot.slot_id, slot.effect_type.clone());
+            if let Some(slot_id) = slot_id {
+                let effects = destination.voices()[index].effects();
+                effects.set_bypassed(slot_id, slot.bypassed);
+                effects.set_mix(slot_id, slot.mix);
+                for (parameter, value) in slot.parameters.iter().enumerate() {
+                    destination.set_effect_parameter(looper_id, slot_id, parameter, *value);
+                }
+            }
+        }
+    }
+}
+
 fn copy_parameters(
     parameter_ids: &[ParameterId],
     source_map: &ParametersMap,
@@ -182,9 +292,19 @@ fn copy_parameters(
 mod test {
     use audio_processor_testing_helpers::assert_f_eq;
 
+    use crate::audio::multi_track_looper::effects_processor::EffectType;
+    use crate::audio::multi_track_looper::mixer::MixerProcessors;
+    use crate::audio::multi_track_looper::trigger_model::sequencer::SongEntry;
+    use crate::audio::multi_track_looper::trigger_model::TrackTriggerModel;
     use crate::audio::multi_track_looper::ParametersMap;
-    use crate::controllers::load_project_controller::copy_parameters;
-    use crate::parameters::{build_parameter_ids, SourceParameter};
+    use crate::controllers::load_project_controller::{
+        copy_effects, copy_mixer, copy_parameters, copy_sequencer, copy_triggers,
+    };
+    use crate::parameters::{build_parameter_ids, LooperId, ParameterId, SourceParameter};
+    use crate::services::project_manager::model::{
+        EffectChainPersist, MixerPersist, SequencerPersist, TrackTriggerModelPersist,
+    };
+    use crate::MultiTrackLooper;
 
     #[test]
     fn test_copy_parameters() {
@@ -201,4 +321,144 @@ mod test {
         assert!(!parameters_map1.has_value(SourceParameter::End));
         assert!(!parameters_map2.has_value(SourceParameter::End));
     }
+
+    #[test]
+    fn test_copy_mixer() {
+        let (_, source) = MixerProcessors::new(4, 2);
+        source.set_group(LooperId(0), Some(0));
+        source.set_group(LooperId(1), Some(0));
+        source.set_muted(LooperId(0), true);
+        source.set_muted(LooperId(1), false);
+        source.set_pan(LooperId(2), -0.5);
+        source.set_send_level(LooperId(3), 1, 0.25);
+        source.aux_bus(0).unwrap().set_return_level(0.5);
+        source
+            .aux_bus(0)
+            .unwrap()
+            .effects()
+            .add_effect(EffectType::EffectTypeReverb);
+        source.master().set_volume(0.75);
+        source.master().set_limiter_ceiling_db(-1.0);
+        let persist = MixerPersist::from(&*source);
+
+        let (_, destination) = MixerProcessors::new(4, 2);
+        copy_mixer(&persist, &destination);
+
+        assert!(destination.track(LooperId(0)).unwrap().is_muted());
+        assert!(!destination.track(LooperId(1)).unwrap().is_muted());
+        assert_eq!(destination.track(LooperId(1)).unwrap().group(), Some(0));
+        assert_eq!(destination.track(LooperId(2)).unwrap().group(), None);
+        assert_f_eq!(destination.track(LooperId(2)).unwrap().pan(), -0.5);
+        assert_f_eq!(destination.track(LooperId(3)).unwrap().send_level(1), 0.25);
+        assert_f_eq!(destination.aux_bus(0).unwrap().return_level(), 0.5);
+        assert_eq!(
+            destination.aux_bus(0).unwrap().effects().effect_types(),
+            vec![EffectType::EffectTypeReverb]
+        );
+        assert_f_eq!(destination.master().volume(), 0.75);
+        assert_f_eq!(destination.master().limiter_ceiling_db(), -1.0);
+    }
+
+    #[test]
+    fn test_copy_effects() {
+        let source = MultiTrackLooper::default();
+        let source = source.handle();
+        source.add_effect(LooperId(0), EffectType::EffectTypeReverb);
+        let slot_id = source
+            .add_effect(LooperId(0), EffectType::EffectTypeGainPan)
+            .unwrap();
+        source.move_effect(LooperId(0), 1, 0);
+        source.set_effect_parameter(LooperId(0), slot_id, 0, 0.5);
+        source.voices()[0].effects().set_mix(slot_id, 0.75);
+        source.voices()[0].effects().set_bypassed(slot_id, true);
+        let persist: Vec<EffectChainPersist> = source
+            .voices()
+            .iter()
+            .map(|voice| EffectChainPersist::from(&**voice.effects()))
+            .collect();
+
+        let destination = MultiTrackLooper::default();
+        let destination = destination.handle();
+        copy_effects(&persist, destination);
+
+        let effects = destination.voices()[0].effects();
+        assert_eq!(
+            effects.effect_types(),
+            vec![EffectType::EffectTypeGainPan, EffectType::EffectTypeReverb]
+        );
+        let slot = effects.slot(slot_id).unwrap();
+        assert!(slot.is_bypassed());
+        assert_f_eq!(slot.mix(), 0.75);
+        assert_f_eq!(slot.parameter(0).unwrap(), 0.5);
+        assert_f_eq!(
+            destination.voices()[0]
+                .user_parameters()
+                .get(ParameterId::ParameterIdEffect(slot_id, 0))
+                .as_float(),
+            0.5
+        );
+        assert!(destination.voices()[1].effects().effect_types().is_empty());
+    }
+
+    #[test]
+    fn test_copy_triggers() {
+        let source = TrackTriggerModel::default();
+        source.set_pattern_length(12);
+        source.set_pattern_step_beats(0.5);
+        source.set_swing(Some(0.25));
+        source.toggle_trigger(1);
+        source.select_pattern(3);
+        source.toggle_trigger(2);
+        source.update_trigger(2, |trigger| trigger.set_ratchets(3));
+        let persist = TrackTriggerModelPersist::from(&source);
+
+        let destination = TrackTriggerModel::default();
+        copy_triggers(&persist, &destination);
+
+        assert_eq!(destination.pattern_length(), 12);
+        assert_f_eq!(destination.pattern_step_beats(), 0.5);
+        assert_eq!(destination.swing(), Some(0.25));
+        assert_eq!(destination.selected_pattern(), 3);
+        assert_eq!(destination.pattern_triggers(0)[0].step(), 1);
+        assert_eq!(destination.triggers()[0].ratchets(), 3);
+    }
+
+    #[test]
+    fn test_copy_triggers_from_a_project_without_patterns() {
+        let source = TrackTriggerModel::default();
+        source.toggle_trigger(4);
+        let mut persist = TrackTriggerModelPersist::from(&source);
+        persist.patterns = vec![];
+
+        let destination = TrackTriggerModel::default();
+        copy_triggers(&persist, &destination);
+        assert_eq!(destination.triggers()[0].step(), 4);
+    }
+
+    #[test]
+    fn test_copy_sequencer() {
+        let source = MultiTrackLooper::default();
+        let source = source.handle();
+        source.set_swing(0.5);
+        source.set_song(vec![SongEntry {
+            pattern: 2,
+            bars: 4,
+        }]);
+        source.set_song_enabled(true);
+        let persist = SequencerPersist::from(&**source.sequencer());
+
+        let destination = MultiTrackLooper::default();
+        let destination = destination.handle();
+        copy_sequencer(&persist, destination.sequencer());
+
+        assert_f_eq!(destination.sequencer().swing(), 0.5);
+        assert!(destination.sequencer().song_enabled());
+        assert_eq!(
+            destination.sequencer().song().to_vec(),
+            vec![SongEntry {
+                pattern: 2,
+                bars: 4,
+            }]
+        );
+    }
 }
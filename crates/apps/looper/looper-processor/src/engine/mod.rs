@@ -39,7 +39,9 @@ use crate::controllers::events_controller::EventsController;
 use crate::controllers::load_project_controller;
 use crate::controllers::load_project_controller::LoadContext;
 use crate::services::audio_clip_manager::AudioClipManager;
+use crate::services::midi_output::MidiOutputThread;
 use crate::services::project_manager::ProjectManager;
+use crate::services::tempo_sync::{LooperTempoSync, LooperTempoSyncOptions};
 #[cfg(any(target_os = "ios", target_os = "macos"))]
 use crate::services::{
     analytics::AnalyticsService,
@@ -87,6 +89,9 @@ pub struct LooperEngine {
     #[cfg(any(target_os = "ios", target_os = "macos"))]
     audio_wave_rendering_controller: Option<AudioWaveRenderingController>,
     _autosave_controller: Option<AutosaveController>,
+    tempo_sync: Mutex<Option<LooperTempoSync>>,
+    midi_feedback: Mutex<Option<MidiOutputThread>>,
+    midi_clock_output: Mutex<Option<MidiOutputThread>>,
 }
 
 impl Default for LooperEngine {
@@ -108,6 +113,7 @@ impl LooperEngine {
                 } else {
                     None
                 },
+                ..Default::default()
             },
             8,
         );
@@ -177,6 +183,9 @@ impl LooperEngine {
             #[cfg(any(target_os = "ios", target_os = "macos"))]
             audio_wave_rendering_controller,
             _autosave_controller: autosave_controller,
+            tempo_sync: Mutex::new(None),
+            midi_feedback: Mutex::new(None),
+            midi_clock_output: Mutex::new(None),
         }
     }
 
@@ -227,6 +236,55 @@ impl LooperEngine {
     pub fn project_manager(&self) -> &Addr<ProjectManager> {
         &self.project_manager
     }
+
+    /// Join the network tempo sync session, if not already in one
+    pub fn start_tempo_sync(&self) -> std::io::Result<()> {
+        let mut tempo_sync = self.tempo_sync.lock().unwrap();
+        if tempo_sync.is_none() {
+            *tempo_sync = Some(LooperTempoSync::start(
+                self.handle.clone(),
+                LooperTempoSyncOptions::default(),
+            )?);
+        }
+        Ok(())
+    }
+
+    pub fn stop_tempo_sync(&self) {
+        self.tempo_sync.lock().unwrap().take();
+    }
+
+    pub fn is_tempo_sync_enabled(&self) -> bool {
+        self.tempo_sync.lock().unwrap().is_some()
+    }
+
+    /// Send MIDI feedback to the first output port whose name contains `port_name`, if not
+    /// already sending it. Returns false if no port was found or the connection failed.
+    pub fn start_midi_feedback(&self, port_name: &str) -> bool {
+        let mut midi_feedback = self.midi_feedback.lock().unwrap();
+        if midi_feedback.is_none() {
+            *midi_feedback = MidiOutputThread::start_feedback(self.handle.clone(), port_name);
+        }
+        midi_feedback.is_some()
+    }
+
+    pub fn stop_midi_feedback(&self) {
+        self.midi_feedback.lock().unwrap().take();
+    }
+
+    /// Send MIDI clock to the first output port whose name contains `port_name`, if not already
+    /// sending it. Returns false if no port was found or the connection failed.
+    pub fn start_midi_clock_output(&self, port_name: &str) -> bool {
+        let mut midi_clock_output = self.midi_clock_output.lock().unwrap();
+        if midi_clock_output.is_none() {
+            *midi_clock_output =
+                MidiOutputThread::start_clock_output(self.handle.clone(), port_name);
+        }
+        midi_clock_output.is_some()
+    }
+
+    pub fn stop_midi_clock_output(&self) {
+        self.midi_clock_output.lock().unwrap().take();
+    }
 }
 
 pub async fn save_project(
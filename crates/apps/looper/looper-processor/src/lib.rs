@@ -54,9 +54,13 @@ pub use self::audio::processor::handle::QuantizeOptions;
 pub use self::audio::processor::LooperProcessor;
 pub use self::audio::shuffler::LoopShufflerParams;
 pub use self::audio::shuffler::LoopShufflerProcessorHandle;
-pub use self::audio::time_info_provider::{TimeInfo, TimeInfoProvider, TimeInfoProviderImpl};
+pub use self::audio::time_info_provider::{
+    ClockSource, TimeInfo, TimeInfoProvider, TimeInfoProviderImpl,
+};
 pub use self::c_api::*;
-pub use self::services::osc_server::setup_osc_server;
+pub use self::services::osc_server::{
+    setup_osc_server, setup_osc_server_with_options, LooperOscOptions,
+};
 
 pub mod audio;
 #[allow(clippy::missing_safety_doc)]
@@ -28,8 +28,8 @@ use basedrop::Shared;
 use bytesize::ByteSize;
 
 use audio_garbage_collector::make_shared;
-use audio_processor_file::file_io::AudioFileError;
-use audio_processor_file::OutputAudioFileProcessor;
+use audio_processor_file::file_io::{AudioFileError, AudioFileLoop, AudioFileMetadata};
+use audio_processor_file::{OutputAudioFileProcessor, OutputFileSettings, RecordingMetadata};
 use audio_processor_traits::{AudioBuffer, AudioProcessorSettings};
 
 use crate::audio::processor::handle::{looper_clip_copy_to_vec_buffer, LooperClipRef};
@@ -42,12 +42,18 @@ pub struct AudioClipModel {
     #[allow(unused)]
     path: PathBuf,
     contents: AudioBuffer<f32>,
+    metadata: AudioFileMetadata,
 }
 
 impl AudioClipModel {
     pub fn contents(&self) -> &AudioBuffer<f32> {
         &self.contents
     }
+
+    /// Markers, loop points & tempo read from the file
+    pub fn metadata(&self) -> &AudioFileMetadata {
+        &self.metadata
+    }
 }
 
 pub type AudioClipModelRef = Shared<AudioClipModel>;
@@ -82,10 +88,20 @@ impl AudioClipManager {
         let rms = sum / audio_file.num_samples() as f32;
         log::info!("RMS level rms={}", rms);
 
+        let metadata =
+            audio_processor_file::file_io::read_audio_file_metadata(path.to_str().unwrap())?;
+        log::info!(
+            "File metadata tempo={:?} markers={} loops={}",
+            metadata.tempo(),
+            metadata.markers.len(),
+            metadata.loops.len()
+        );
+
         let clip_model = make_shared(AudioClipModel {
             id: AudioClipId(self.audio_clips.len()),
             path: path.into(),
             contents: audio_file,
+            metadata,
         });
         self.audio_clips.push(clip_model.clone());
         Ok(clip_model)
@@ -110,14 +126,30 @@ impl Handler<LoadClipMessage> for AudioClipManager {
     }
 }
 
-pub fn write_looper_clip(settings: AudioProcessorSettings, clip_path: &Path, clip: &LooperClipRef) {
+/// Write a clip into a WAV file, with a loop point over the whole clip and its tempo, if known
+pub fn write_looper_clip(
+    settings: AudioProcessorSettings,
+    clip_path: &Path,
+    clip: &LooperClipRef,
+    tempo: Option<f32>,
+) {
     log::info!("Writing audio into {:?}", clip_path);
 
-    let mut output_processor =
-        OutputAudioFileProcessor::from_path(settings, clip_path.to_str().unwrap());
+    let mut clip_buffer = looper_clip_copy_to_vec_buffer(clip);
+    let mut output_file_settings = OutputFileSettings::new(clip_path.to_str().unwrap());
+    output_file_settings.metadata = Some(RecordingMetadata {
+        originator: String::from("looper-processor"),
+        tempo,
+        loops: vec![AudioFileLoop {
+            start: 0,
+            end: clip_buffer.num_samples() as u64,
+            play_count: 0,
+        }],
+        ..RecordingMetadata::default()
+    });
+    let mut output_processor = OutputAudioFileProcessor::new(settings, output_file_settings);
     output_processor.prepare(settings);
 
-    let mut clip_buffer = looper_clip_copy_to_vec_buffer(clip);
     if let Err(err) = output_processor.process(&mut clip_buffer) {
         log::error!("Failed to write file: {}", err);
     }
@@ -161,6 +193,34 @@ mod test {
         assert!(level > 0.1);
     }
 
+    #[test]
+    fn test_clip_loop_points_and_tempo_are_written() {
+        wisual_logger::init_from_env();
+        let data_path = tempdir::TempDir::new("looper_processor__audio_clip_manager").unwrap();
+        let clip_path = data_path.path().join("clip.wav");
+
+        let mut input_buffer = AudioBuffer::empty();
+        input_buffer.resize(2, 100);
+        let looper = MultiTrackLooper::new(LooperOptions::default(), 1);
+        let voice: &LooperVoice = &looper.handle().voices()[0];
+        voice.looper().set_looper_buffer(&input_buffer);
+        let clip = voice.looper().looper_clip();
+        write_looper_clip(Default::default(), &clip_path, &clip, Some(120.0));
+
+        let mut manager = AudioClipManager::default();
+        let clip = manager.load_at_path(&clip_path).unwrap();
+        let metadata = clip.metadata();
+        assert_eq!(metadata.tempo(), Some(120.0));
+        assert_eq!(
+            metadata.loops,
+            vec![AudioFileLoop {
+                start: 0,
+                end: 100,
+                play_count: 0
+            }]
+        );
+    }
+
     #[test]
     fn test_roundtrip_to_file() {
         wisual_logger::init_from_env();
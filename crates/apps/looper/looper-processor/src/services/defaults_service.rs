@@ -38,6 +38,14 @@ impl Value {
             None
         }
     }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        if let Value::Integer(i) = &self {
+            Some(*i)
+        } else {
+            None
+        }
+    }
 }
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -20,18 +20,15 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use audio_processor_bitcrusher::BitCrusherProcessor;
-use audio_processor_time::{FreeverbProcessor, MonoDelayProcessor};
-use audio_processor_traits::parameters::{
-    AudioProcessorHandleProvider, AudioProcessorHandleRef, ParameterSpec,
-};
-use augmented_dsp_filters::rbj::FilterProcessor;
+use audio_processor_traits::parameters::{AudioProcessorHandleRef, ParameterSpec};
 
-use crate::audio::multi_track_looper::effects_processor::EffectType;
+use crate::audio::multi_track_looper::effects_processor::{
+    build_effect, factory_presets, EffectPreset, EffectType, EFFECT_TYPES, MAX_EFFECT_PARAMETERS,
+};
 
 fn build_parameters_model(ty: EffectType, handle: AudioProcessorHandleRef) -> EffectDefinition {
     let name = handle.name();
-    let num_parameters = handle.parameter_count();
+    let num_parameters = handle.parameter_count().min(MAX_EFFECT_PARAMETERS);
 
     let mut parameters = Vec::with_capacity(num_parameters);
     for id in 0..num_parameters {
@@ -43,6 +40,7 @@ fn build_parameters_model(ty: EffectType, handle: AudioProcessorHandleRef) -> Ef
     EffectDefinition {
         name,
         parameters,
+        presets: factory_presets(&ty),
         ty,
     }
 }
@@ -57,6 +55,7 @@ pub struct EffectParameterModel {
 pub struct EffectDefinition {
     pub name: String,
     pub parameters: Vec<EffectParameterModel>,
+    pub presets: Vec<EffectPreset>,
     pub ty: EffectType,
 }
 
@@ -65,26 +64,13 @@ pub struct EffectsService {}
 
 impl EffectsService {
     pub fn get_effects() -> Vec<EffectDefinition> {
-        [
-            (
-                EffectType::EffectTypeReverb,
-                FreeverbProcessor::default().generic_handle(),
-            ),
-            (
-                EffectType::EffectTypeDelay,
-                MonoDelayProcessor::<f32>::default().generic_handle(),
-            ),
-            (
-                EffectType::EffectTypeBitCrusher,
-                BitCrusherProcessor::default().generic_handle(),
-            ),
-            (
-                EffectType::EffectTypeFilter,
-                FilterProcessor::default().generic_handle(),
-            ),
-        ]
-        .map(|(ty, handle)| build_parameters_model(ty, handle))
-        .to_vec()
+        EFFECT_TYPES
+            .iter()
+            .map(|ty| {
+                let (_processor, handle) = build_effect(ty);
+                build_parameters_model(ty.clone(), handle)
+            })
+            .collect()
     }
 }
 
@@ -99,6 +85,22 @@ mod test {
         assert_eq!(effects[1].name, "Delay");
         assert_eq!(effects[2].name, "Bit-crusher");
         assert_eq!(effects[3].name, "Filter");
+        assert_eq!(effects[4].name, "Compressor");
+        assert_eq!(effects[5].name, "Chorus");
+        assert_eq!(effects[6].name, "Pitch shifter");
+        assert_eq!(effects[7].name, "Gain/Pan");
+        assert_eq!(effects[8].name, "EQ");
+        assert_eq!(effects[9].name, "Mod reverb");
+    }
+
+    #[test]
+    fn test_effect_definitions_include_presets() {
+        let effects = EffectsService::get_effects();
+        let pitch_shifter = effects
+            .iter()
+            .find(|effect| effect.ty == EffectType::EffectTypePitchShifter)
+            .unwrap();
+        assert_eq!(pitch_shifter.presets[0].name, "Octave up");
     }
 
     #[test]
@@ -0,0 +1,136 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Threads which send MIDI to an output port. They're stopped when dropped.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use basedrop::Shared;
+
+use audio_garbage_collector::make_shared;
+use audio_processor_standalone_midi::clock::{spawn_midi_clock_thread, MidiTransportState};
+
+use crate::audio::midi_map::feedback::spawn_midi_feedback_thread;
+use crate::{MultiTrackLooperHandle, TimeInfoProvider};
+
+/// Connect to the first MIDI output port whose name contains `port_name`
+pub fn connect_midi_output(
+    port_name: &str,
+    connection_name: &str,
+) -> Option<midir::MidiOutputConnection> {
+    let output = match midir::MidiOutput::new("Continuous") {
+        Ok(output) => output,
+        Err(err) => {
+            log::error!("Failed to create MIDI output {}", err);
+            return None;
+        }
+    };
+    let ports = output.ports();
+    let port = ports.iter().find(|port| {
+        output
+            .port_name(port)
+            .map(|name| name.contains(port_name))
+            .unwrap_or(false)
+    });
+    let port = match port {
+        Some(port) => port,
+        None => {
+            log::error!("MIDI output port not found {}", port_name);
+            return None;
+        }
+    };
+
+    match output.connect(port, connection_name) {
+        Ok(connection) => Some(connection),
+        Err(err) => {
+            log::error!("Failed to connect to MIDI output {}", err);
+            None
+        }
+    }
+}
+
+pub struct MidiOutputThread {
+    is_running: Shared<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MidiOutputThread {
+    /// Send MIDI feedback to the first output port whose name contains `port_name`. Returns
+    /// `None` if no port was found or the connection failed.
+    pub fn start_feedback(handle: Shared<MultiTrackLooperHandle>, port_name: &str) -> Option<Self> {
+        let connection = connect_midi_output(port_name, "continuous-feedback")?;
+        let is_running = make_shared(AtomicBool::new(true));
+        let thread = spawn_midi_feedback_thread(
+            handle,
+            connection,
+            is_running.clone(),
+            Duration::from_millis(50),
+        )
+        .map_err(|err| log::error!("Failed to start MIDI feedback thread {}", err))
+        .ok()?;
+
+        Some(Self {
+            is_running,
+            thread: Some(thread),
+        })
+    }
+
+    /// Send MIDI clock & transport messages following the looper play-head to the first output
+    /// port whose name contains `port_name`. Returns `None` if no port was found or the connection
+    /// failed.
+    pub fn start_clock_output(
+        handle: Shared<MultiTrackLooperHandle>,
+        port_name: &str,
+    ) -> Option<Self> {
+        let connection = connect_midi_output(port_name, "continuous-clock")?;
+        let is_running = make_shared(AtomicBool::new(true));
+        let thread = spawn_midi_clock_thread(
+            move || {
+                let time_info = handle.time_info_provider().get_time_info();
+                MidiTransportState {
+                    tempo: time_info.tempo(),
+                    is_playing: time_info.is_playing(),
+                    position_beats: time_info.position_beats().unwrap_or(0.0),
+                }
+            },
+            connection,
+            is_running.clone(),
+        )
+        .map_err(|err| log::error!("Failed to start MIDI clock thread {}", err))
+        .ok()?;
+
+        Some(Self {
+            is_running,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for MidiOutputThread {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -27,5 +27,7 @@ pub mod analytics;
 pub mod audio_clip_manager;
 pub mod defaults_service;
 pub mod effects_service;
+pub mod midi_output;
 pub mod osc_server;
 pub mod project_manager;
+pub mod tempo_sync;
@@ -0,0 +1,220 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Sends looper state back to registered OSC clients.
+//!
+//! Every tick a snapshot of the looper is built as a list of OSC messages. Only messages whose
+//! arguments changed since the last tick are sent, except for newly registered clients which
+//! receive the full snapshot.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use audio_processor_standalone_osc::{OscMessage, OscPacket, OscSender, OscType};
+use num::ToPrimitive;
+
+use crate::audio::multi_track_looper::parameters::{build_parameter_ids, LooperId};
+use crate::{MultiTrackLooperHandle, TimeInfoProvider};
+
+use super::namespace::{looper_address, parameter_address, parameter_value_to_osc};
+
+/// Build the current feedback snapshot
+pub fn feedback_messages(handle: &MultiTrackLooperHandle) -> Vec<OscMessage> {
+    let mut messages = vec![];
+    let mut push = |addr: String, args: Vec<OscType>| messages.push(OscMessage { addr, args });
+
+    let time_info = handle.time_info_provider().get_time_info();
+    push(
+        "/transport/playing".to_string(),
+        vec![OscType::Bool(time_info.is_playing())],
+    );
+    if let Some(tempo) = time_info.tempo() {
+        push("/tempo".to_string(), vec![OscType::Float(tempo as f32)]);
+    }
+    if let Some(beats) = time_info.position_beats() {
+        push(
+            "/transport/beats".to_string(),
+            vec![OscType::Float(beats as f32)],
+        );
+    }
+
+    let input_meter = handle.input_meter_handle();
+    push(
+        "/meters/input".to_string(),
+        vec![
+            OscType::Float(input_meter.calculate_rms(0)),
+            OscType::Float(input_meter.calculate_rms(1)),
+        ],
+    );
+
+    let parameter_ids = build_parameter_ids();
+    for looper_id in (0..handle.num_voices()).map(LooperId) {
+        let state = handle.get_looper_state(looper_id);
+        push(
+            looper_address(looper_id, "state"),
+            vec![
+                OscType::Int(state.to_i32().unwrap_or(0)),
+                OscType::String(format!("{:?}", state)),
+            ],
+        );
+        push(
+            looper_address(looper_id, "position"),
+            vec![OscType::Float(handle.get_position_percent(looper_id))],
+        );
+
+        for parameter_id in &parameter_ids {
+            if let Some(value) = handle.get_parameter(looper_id, parameter_id) {
+                push(
+                    parameter_address(looper_id, parameter_id),
+                    vec![parameter_value_to_osc(&value)],
+                );
+            }
+        }
+    }
+
+    messages
+}
+
+#[derive(Default)]
+pub struct FeedbackState {
+    sent: HashMap<String, Vec<OscType>>,
+    clients: Vec<SocketAddr>,
+}
+
+impl FeedbackState {
+    /// Send changed values to all clients and the full snapshot to new clients
+    pub fn tick(&mut self, handle: &MultiTrackLooperHandle, sender: &OscSender) {
+        let clients = sender.clients();
+        if clients.is_empty() {
+            self.clients.clear();
+            return;
+        }
+
+        let messages = feedback_messages(handle);
+        for client in &clients {
+            if !self.clients.contains(client) {
+                for message in &messages {
+                    Self::send_to(sender, *client, message);
+                }
+            }
+        }
+
+        for message in messages {
+            if self.sent.get(&message.addr) == Some(&message.args) {
+                continue;
+            }
+            for client in clients
+                .iter()
+                .filter(|client| self.clients.contains(client))
+            {
+                Self::send_to(sender, *client, &message);
+            }
+            self.sent.insert(message.addr.clone(), message.args);
+        }
+
+        self.clients = clients;
+    }
+
+    fn send_to(sender: &OscSender, client: SocketAddr, message: &OscMessage) {
+        if let Err(err) = sender.send_to(client, &OscPacket::Message(message.clone())) {
+            log::debug!("Failed to send OSC feedback to {} {}", client, err);
+        }
+    }
+}
+
+pub fn run_feedback_loop(
+    handle: &MultiTrackLooperHandle,
+    sender: &OscSender,
+    interval: Duration,
+) -> ! {
+    let mut state = FeedbackState::default();
+    loop {
+        state.tick(handle, sender);
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+
+    use crate::audio::multi_track_looper::parameters::SourceParameter;
+    use crate::MultiTrackLooper;
+
+    use super::*;
+
+    fn receive_all(socket: &UdpSocket) -> Vec<OscMessage> {
+        let mut buf = [0u8; audio_processor_standalone_osc::rosc::decoder::MTU];
+        let mut messages = vec![];
+        while let Ok((size, _)) = socket.recv_from(&mut buf) {
+            if let Ok(OscPacket::Message(message)) =
+                audio_processor_standalone_osc::rosc::decoder::decode(&buf[..size])
+            {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn test_feedback_messages_cover_all_loopers() {
+        let looper = MultiTrackLooper::new(Default::default(), 2);
+        let messages = feedback_messages(looper.handle());
+        let addresses: Vec<&str> = messages.iter().map(|msg| msg.addr.as_str()).collect();
+        assert!(addresses.contains(&"/transport/playing"));
+        assert!(addresses.contains(&"/meters/input"));
+        assert!(addresses.contains(&"/looper/0/state"));
+        assert!(addresses.contains(&"/looper/1/position"));
+        assert!(addresses.contains(&"/looper/1/source/speed"));
+    }
+
+    #[test]
+    fn test_tick_sends_snapshot_then_changes() {
+        let looper = MultiTrackLooper::new(Default::default(), 2);
+        let handle = looper.handle();
+        let client = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let sender = OscSender::bind().unwrap();
+        sender.register_client(client.local_addr().unwrap());
+
+        let mut state = FeedbackState::default();
+        state.tick(handle, &sender);
+        let snapshot = receive_all(&client);
+        assert_eq!(snapshot.len(), feedback_messages(handle).len());
+
+        state.tick(handle, &sender);
+        assert!(receive_all(&client).is_empty());
+
+        handle.set_source_parameter(LooperId(1), SourceParameter::Speed, 2.0);
+        state.tick(handle, &sender);
+        let changes = receive_all(&client);
+        assert_eq!(
+            changes,
+            vec![OscMessage {
+                addr: "/looper/1/source/speed".to_string(),
+                args: vec![OscType::Float(2.0)]
+            }]
+        );
+    }
+}
@@ -0,0 +1,516 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! OSC control surface for [`MultiTrackLooperHandle`].
+//!
+//! The server listens on UDP port 1449 by default. `<n>` is the 0-based looper index, `<lfo>`
+//! is 0 or 1 and `<s>` is a 0-based scene index.
+//!
+//! # Tracks
+//!
+//! | Address                    | Arguments | Action                                        |
+//! |----------------------------|-----------|-----------------------------------------------|
+//! | `/looper/<n>/record`       |           | Toggle recording/overdubbing                  |
+//! | `/looper/<n>/play`         |           | Toggle playback                               |
+//! | `/looper/<n>/stop`         |           | Stop the looper                               |
+//! | `/looper/<n>/clear`        |           | Clear the looper                              |
+//! | `/looper/<n>/trigger`      |           | Restart the looper and its envelope           |
+//! | `/looper/<n>/undo`         |           | Undo the last overdub layer                   |
+//! | `/looper/<n>/redo`         |           | Redo the last undone overdub layer            |
+//! | `/looper/<n>/volume`       | float     | Set the looper volume                         |
+//! | `/looper/<n>/select`       |           | Make this the active looper                   |
+//! | `/looper/record`           |           | `record` on the active looper                 |
+//! | `/looper/play`             |           | `play` on the active looper                   |
+//! | `/looper/stop`             |           | `stop` on the active looper                   |
+//! | `/looper/clear`            |           | `clear` on the active looper                  |
+//!
+//! # Parameters
+//!
+//! | Address                                                  | Arguments                       |
+//! |----------------------------------------------------------|---------------------------------|
+//! | `/looper/<n>/source/{start,end,fade_start,fade_end}`     | float                           |
+//! | `/looper/<n>/source/{pitch,speed}`                       | float                           |
+//! | `/looper/<n>/source/{loop_enabled,slice_enabled}`        | bool                            |
+//! | `/looper/<n>/source/slice_id`                            | int                             |
+//! | `/looper/<n>/envelope/{attack,decay,release,sustain}`    | float                           |
+//! | `/looper/<n>/envelope/enabled`                           | bool                            |
+//! | `/looper/<n>/lfo/<lfo>/{frequency,amount}`               | float                           |
+//! | `/looper/<n>/lfo/<lfo>/mode`                             | int: 0 sine, 1 square, 2 saw    |
+//! | `/looper/<n>/quantization/mode`                          | int: 0 closest, 1 next, 2 none  |
+//! | `/looper/<n>/quantization/tempo_control`                 | int: 0 set global tempo, 1 none |
+//!
+//! Numeric arguments are converted to the parameter's type, so surfaces which only send floats
+//! can control every parameter. Numbers at least 0.5 are `true`.
+//!
+//! # Scenes, tempo & transport
+//!
+//! | Address                                   | Arguments | Action                              |
+//! |-------------------------------------------|-----------|-------------------------------------|
+//! | `/scene/slider`                           | float     | Interpolate between scenes (0-1)    |
+//! | `/scene/<s>`                              |           | Jump the slider to a scene          |
+//! | `/scene/<s>/looper/<n>/<parameter path>`  | float     | Lock a parameter value in a scene   |
+//! | `/scene/<s>/looper/<n>/<parameter path>`  |           | Remove the parameter lock           |
+//! | `/transport/play`                         |           | Start the playhead                  |
+//! | `/transport/stop`                         |           | Stop the playhead                   |
+//! | `/tempo`                                  | float     | Set the tempo in BPM                |
+//! | `/metronome/volume`                       | float     | Set the metronome volume            |
+//!
+//! `<parameter path>` is a parameter address without the `/looper/<n>/` prefix, e.g.
+//! `source/speed`.
+//!
+//! # Feedback
+//!
+//! Clients register with `/feedback/register` and stop receiving feedback with
+//! `/feedback/unregister`. Both take an optional int port, otherwise the port the message was
+//! sent from is used. Registered clients receive:
+//!
+//! | Address                  | Arguments                    |
+//! |--------------------------|------------------------------|
+//! | `/transport/playing`     | bool                         |
+//! | `/transport/beats`       | float                        |
+//! | `/tempo`                 | float                        |
+//! | `/meters/input`          | float, float (RMS per channel) |
+//! | `/looper/<n>/state`      | int, string                  |
+//! | `/looper/<n>/position`   | float (0-1)                  |
+//! | Every parameter address  | The parameter's value        |
+//!
+//! A full snapshot is sent on registration, afterwards only changed values are sent.
+//!
+//! # Bundles
+//!
+//! Messages in bundles are dispatched in order, once the bundle's timetag is reached.
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use basedrop::Shared;
+
+use audio_processor_standalone_osc::{OscMap, OscSender, OscServer, OscServerOptions, OscType};
+
+use crate::audio::multi_track_looper::parameters::{build_parameter_ids, LooperId};
+use crate::{LooperHandleThread, MultiTrackLooperHandle};
+
+use self::feedback::run_feedback_loop;
+use self::namespace::{arg_float, arg_int, looper_address, parameter_address, scene_lock_address};
+
+pub mod feedback;
+pub mod namespace;
+
+pub struct LooperOscOptions {
+    /// UDP port the OSC server listens on. Defaults to 1449.
+    pub port: u16,
+    /// How often feedback is sent to registered clients. Defaults to 50ms.
+    pub feedback_interval: Duration,
+}
+
+impl Default for LooperOscOptions {
+    fn default() -> Self {
+        Self {
+            port: 1449,
+            feedback_interval: Duration::from_millis(50),
+        }
+    }
+}
+
+pub struct LooperOscContext {
+    handle: Shared<MultiTrackLooperHandle>,
+    sender: OscSender,
+}
+
+pub fn setup_osc_server(handle: Shared<MultiTrackLooperHandle>) {
+    setup_osc_server_with_options(handle, LooperOscOptions::default())
+}
+
+pub fn setup_osc_server_with_options(
+    handle: Shared<MultiTrackLooperHandle>,
+    options: LooperOscOptions,
+) {
+    let sender = match OscSender::bind() {
+        Ok(sender) => sender,
+        Err(err) => {
+            log::error!("Failed to open OSC feedback socket {}", err);
+            return;
+        }
+    };
+
+    let osc_map = build_osc_map(&handle);
+    let context = LooperOscContext {
+        handle: handle.clone(),
+        sender: sender.clone(),
+    };
+    let osc_server = OscServer::with_options(
+        context,
+        osc_map,
+        OscServerOptions {
+            port: options.port,
+            ..Default::default()
+        },
+    );
+    let _ = std::thread::Builder::new()
+        .name(String::from("looper_osc_server"))
+        .spawn(move || {
+            if let Err(err) = osc_server.start() {
+                log::error!("OscServer has exited with {}", err);
+            }
+        });
+
+    let _ = std::thread::Builder::new()
+        .name(String::from("looper_osc_feedback"))
+        .spawn(move || run_feedback_loop(&handle, &sender, options.feedback_interval));
+}
+
+type LooperAction = fn(&MultiTrackLooperHandle, LooperId);
+
+/// Actions available on every looper, also available on the active looper through
+/// `/looper/<action>` if `on_active_looper` is set
+const LOOPER_ACTIONS: [(&str, LooperAction, bool); 8] = [
+    ("record", toggle_recording, true),
+    ("play", toggle_playback, true),
+    ("stop", stop, true),
+    ("clear", clear, true),
+    ("trigger", trigger, false),
+    ("undo", undo_overdub, false),
+    ("redo", redo_overdub, false),
+    ("select", select, false),
+];
+
+fn toggle_recording(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    log::info!("Toggle recording looper={:?}", looper_id);
+    handle.toggle_recording(looper_id, LooperHandleThread::OtherThread)
+}
+
+fn toggle_playback(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    log::info!("Toggle playback looper={:?}", looper_id);
+    handle.toggle_playback(looper_id)
+}
+
+fn stop(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    if let Some(voice) = handle.get(looper_id) {
+        voice.looper().stop();
+    }
+}
+
+fn clear(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    log::info!("Clear looper={:?}", looper_id);
+    handle.clear(looper_id)
+}
+
+fn trigger(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    handle.trigger(looper_id)
+}
+
+fn undo_overdub(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    handle.undo_overdub(looper_id);
+}
+
+fn redo_overdub(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    handle.redo_overdub(looper_id);
+}
+
+fn select(handle: &MultiTrackLooperHandle, looper_id: LooperId) {
+    handle.set_active_looper(looper_id)
+}
+
+/// Build the handlers for every address in the namespace. Handlers are registered for each
+/// looper & scene, since addresses are matched exactly.
+pub fn build_osc_map(handle: &MultiTrackLooperHandle) -> OscMap<LooperOscContext> {
+    let mut osc_map: OscMap<LooperOscContext> = OscMap::default();
+    let parameter_ids = build_parameter_ids();
+    let num_scenes = handle.scene_handle().scenes().len();
+
+    for (name, action, on_active_looper) in LOOPER_ACTIONS {
+        if on_active_looper {
+            osc_map.add(
+                format!("/looper/{}", name),
+                Box::new(move |context, _msg| {
+                    action(&context.handle, context.handle.active_looper())
+                }),
+            );
+        }
+    }
+
+    for looper_id in (0..handle.num_voices()).map(LooperId) {
+        for (name, action, _) in LOOPER_ACTIONS {
+            osc_map.add(
+                looper_address(looper_id, name),
+                Box::new(move |context, _msg| action(&context.handle, looper_id)),
+            );
+        }
+
+        osc_map.add(
+            looper_address(looper_id, "volume"),
+            Box::new(move |context, msg| {
+                if let Some(volume) = arg_float(&msg.args) {
+                    context.handle.set_volume(looper_id, volume);
+                }
+            }),
+        );
+
+        for parameter_id in &parameter_ids {
+            osc_map.add(parameter_address(looper_id, parameter_id), {
+                let parameter_id = parameter_id.clone();
+                Box::new(move |context, msg| {
+                    let result = namespace::set_parameter(
+                        &context.handle,
+                        looper_id,
+                        &parameter_id,
+                        &msg.args,
+                    );
+                    if result.is_none() {
+                        log::warn!("Invalid OSC arguments {} {:?}", msg.addr, msg.args);
+                    }
+                })
+            });
+
+            for scene_id in 0..num_scenes {
+                let parameter_id = parameter_id.clone();
+                osc_map.add(
+                    scene_lock_address(scene_id, looper_id, &parameter_id),
+                    Box::new(move |context, msg| match arg_float(&msg.args) {
+                        Some(value) => context.handle.add_scene_parameter_lock(
+                            scene_id,
+                            looper_id,
+                            parameter_id.clone(),
+                            value,
+                        ),
+                        None => context.handle.remove_scene_parameter_lock(
+                            scene_id,
+                            looper_id,
+                            parameter_id.clone(),
+                        ),
+                    }),
+                );
+            }
+        }
+    }
+
+    for scene_id in 0..num_scenes {
+        let value = scene_id as f32 / (num_scenes.max(2) - 1) as f32;
+        osc_map.add(
+            format!("/scene/{}", scene_id),
+            Box::new(move |context, _msg| context.handle.set_scene_value(value)),
+        );
+    }
+    osc_map.add(
+        "/scene/slider",
+        Box::new(|context, msg| {
+            if let Some(value) = arg_float(&msg.args) {
+                context.handle.set_scene_value(value);
+            }
+        }),
+    );
+
+    osc_map.add(
+        "/transport/play",
+        Box::new(|context, _msg| context.handle.play()),
+    );
+    osc_map.add(
+        "/transport/stop",
+        Box::new(|context, _msg| context.handle.stop()),
+    );
+    osc_map.add(
+        "/tempo",
+        Box::new(|context, msg| {
+            if let Some(tempo) = arg_float(&msg.args) {
+                context.handle.set_tempo(tempo);
+            }
+        }),
+    );
+    osc_map.add(
+        "/metronome/volume",
+        Box::new(|context, msg| {
+            if let Some(volume) = arg_float(&msg.args) {
+                context.handle.set_metronome_volume(volume);
+            }
+        }),
+    );
+
+    osc_map.add_with_source(
+        "/feedback/register",
+        Box::new(|context, msg, source| {
+            context
+                .sender
+                .register_client(feedback_client_address(&msg.args, source));
+        }),
+    );
+    osc_map.add_with_source(
+        "/feedback/unregister",
+        Box::new(|context, msg, source| {
+            context
+                .sender
+                .unregister_client(&feedback_client_address(&msg.args, source));
+        }),
+    );
+
+    osc_map
+}
+
+fn feedback_client_address(args: &[OscType], source: SocketAddr) -> SocketAddr {
+    let port = arg_int(args)
+        .and_then(|port| u16::try_from(port).ok())
+        .unwrap_or_else(|| source.port());
+    SocketAddr::new(source.ip(), port)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use audio_processor_standalone_osc::OscMessage;
+
+    use crate::audio::multi_track_looper::parameters::{
+        ParameterId, ParameterValue, SourceParameter,
+    };
+    use crate::{MultiTrackLooper, TimeInfoProvider};
+
+    use super::*;
+
+    fn dispatch(
+        osc_map: &OscMap<LooperOscContext>,
+        context: &LooperOscContext,
+        addr: &str,
+        args: Vec<OscType>,
+    ) {
+        let handler = osc_map.get(addr).expect("Missing OSC address");
+        let source = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9000));
+        handler(
+            context,
+            OscMessage {
+                addr: addr.to_string(),
+                args,
+            },
+            source,
+        );
+    }
+
+    fn setup() -> (OscMap<LooperOscContext>, LooperOscContext) {
+        let looper = MultiTrackLooper::new(Default::default(), 2);
+        let handle = looper.handle().clone();
+        let osc_map = build_osc_map(&handle);
+        let context = LooperOscContext {
+            handle,
+            sender: OscSender::bind().unwrap(),
+        };
+        (osc_map, context)
+    }
+
+    #[test]
+    fn test_set_parameters() {
+        let (osc_map, context) = setup();
+        let speed = ParameterId::ParameterIdSource(SourceParameter::Speed);
+        let loop_enabled = ParameterId::ParameterIdSource(SourceParameter::LoopEnabled);
+
+        dispatch(
+            &osc_map,
+            &context,
+            "/looper/1/source/speed",
+            vec![OscType::Float(2.0)],
+        );
+        dispatch(
+            &osc_map,
+            &context,
+            "/looper/1/source/loop_enabled",
+            vec![OscType::Int(0)],
+        );
+
+        let handle = &context.handle;
+        assert_eq!(
+            handle.get_parameter(LooperId(1), &speed),
+            Some(ParameterValue::Float(2.0.into()))
+        );
+        assert_eq!(
+            handle.get_parameter(LooperId(0), &speed),
+            Some(ParameterValue::Float(1.0.into()))
+        );
+        assert_eq!(
+            handle.get_parameter(LooperId(1), &loop_enabled),
+            Some(ParameterValue::Bool(false.into()))
+        );
+    }
+
+    #[test]
+    fn test_active_looper_actions() {
+        let (osc_map, context) = setup();
+        dispatch(&osc_map, &context, "/looper/1/select", vec![]);
+        assert_eq!(context.handle.active_looper(), LooperId(1));
+        assert!(osc_map.get("/looper/record").is_some());
+        assert!(osc_map.get("/looper/trigger").is_none());
+    }
+
+    #[test]
+    fn test_scenes_and_tempo() {
+        let (osc_map, context) = setup();
+        let speed = ParameterId::ParameterIdSource(SourceParameter::Speed);
+
+        dispatch(&osc_map, &context, "/scene/1", vec![]);
+        assert_eq!(context.handle.scene_handle().get_slider(), 1.0);
+        dispatch(
+            &osc_map,
+            &context,
+            "/scene/slider",
+            vec![OscType::Float(0.25)],
+        );
+        assert_eq!(context.handle.scene_handle().get_slider(), 0.25);
+
+        dispatch(
+            &osc_map,
+            &context,
+            "/scene/1/looper/0/source/speed",
+            vec![OscType::Float(2.0)],
+        );
+        assert!(context
+            .handle
+            .scene_handle()
+            .get_right(LooperId(0), speed.clone())
+            .is_some());
+        dispatch(&osc_map, &context, "/scene/1/looper/0/source/speed", vec![]);
+        assert!(context
+            .handle
+            .scene_handle()
+            .get_right(LooperId(0), speed)
+            .is_none());
+
+        dispatch(&osc_map, &context, "/tempo", vec![OscType::Float(90.0)]);
+        let tempo = context.handle.time_info_provider().get_time_info().tempo();
+        assert_eq!(tempo, Some(90.0));
+    }
+
+    #[test]
+    fn test_feedback_registration() {
+        let (osc_map, context) = setup();
+        dispatch(
+            &osc_map,
+            &context,
+            "/feedback/register",
+            vec![OscType::Int(9001)],
+        );
+        let client = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9001));
+        assert_eq!(context.sender.clients(), vec![client]);
+        dispatch(
+            &osc_map,
+            &context,
+            "/feedback/unregister",
+            vec![OscType::Int(9001)],
+        );
+        assert!(!context.sender.has_clients());
+    }
+}
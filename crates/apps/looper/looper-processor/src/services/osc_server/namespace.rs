@@ -0,0 +1,242 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Mapping between OSC addresses & arguments and looper parameters.
+use std::convert::TryFrom;
+
+use audio_processor_standalone_osc::OscType;
+use num::FromPrimitive;
+use strum::EnumProperty;
+
+use crate::audio::multi_track_looper::parameters::{
+    CQuantizeMode, EnvelopeParameter, LFOMode, LFOParameter, LooperId, ParameterId, ParameterValue,
+    QuantizationParameter, SourceParameter, TempoControl,
+};
+use crate::MultiTrackLooperHandle;
+
+pub fn looper_address(looper_id: LooperId, path: &str) -> String {
+    format!("/looper/{}/{}", looper_id.0, path)
+}
+
+pub fn parameter_address(looper_id: LooperId, parameter_id: &ParameterId) -> String {
+    looper_address(looper_id, &parameter_path(parameter_id))
+}
+
+pub fn scene_lock_address(
+    scene_id: usize,
+    looper_id: LooperId,
+    parameter_id: &ParameterId,
+) -> String {
+    format!(
+        "/scene/{}/looper/{}/{}",
+        scene_id,
+        looper_id.0,
+        parameter_path(parameter_id)
+    )
+}
+
+/// Path of a parameter relative to its looper, e.g. `source/speed`, `lfo/1/frequency` or
+/// `effects/0/2`
+pub fn parameter_path(parameter_id: &ParameterId) -> String {
+    match parameter_id {
+        ParameterId::ParameterIdSource(parameter) => {
+            format!("source/{}", source_parameter_name(parameter))
+        }
+        ParameterId::ParameterIdEnvelope(parameter) => {
+            format!("envelope/{}", envelope_parameter_name(parameter))
+        }
+        ParameterId::ParameterIdLFO(lfo, parameter) => {
+            format!("lfo/{}/{}", lfo, lfo_parameter_name(parameter))
+        }
+        ParameterId::ParameterIdQuantization(parameter) => {
+            format!("quantization/{}", quantization_parameter_name(parameter))
+        }
+        ParameterId::ParameterIdEffect(slot_id, parameter) => {
+            format!("effects/{}/{}", slot_id, parameter)
+        }
+    }
+}
+
+fn source_parameter_name(parameter: &SourceParameter) -> &'static str {
+    match parameter {
+        SourceParameter::Start => "start",
+        SourceParameter::End => "end",
+        SourceParameter::FadeStart => "fade_start",
+        SourceParameter::FadeEnd => "fade_end",
+        SourceParameter::Pitch => "pitch",
+        SourceParameter::Speed => "speed",
+        SourceParameter::LoopEnabled => "loop_enabled",
+        SourceParameter::SliceId => "slice_id",
+        SourceParameter::SliceEnabled => "slice_enabled",
+    }
+}
+
+fn envelope_parameter_name(parameter: &EnvelopeParameter) -> &'static str {
+    match parameter {
+        EnvelopeParameter::Attack => "attack",
+        EnvelopeParameter::Decay => "decay",
+        EnvelopeParameter::Release => "release",
+        EnvelopeParameter::Sustain => "sustain",
+        EnvelopeParameter::EnvelopeEnabled => "enabled",
+    }
+}
+
+fn lfo_parameter_name(parameter: &LFOParameter) -> &'static str {
+    match parameter {
+        LFOParameter::LFOParameterFrequency => "frequency",
+        LFOParameter::LFOParameterAmount => "amount",
+        LFOParameter::LFOParameterMode => "mode",
+    }
+}
+
+fn quantization_parameter_name(parameter: &QuantizationParameter) -> &'static str {
+    match parameter {
+        QuantizationParameter::QuantizationParameterQuantizeMode => "mode",
+        QuantizationParameter::QuantizationParameterTempoControl => "tempo_control",
+    }
+}
+
+// MARK: Argument conversion
+
+/// Read a float argument. Integers are converted so surfaces that only send ints still work.
+pub fn arg_float(args: &[OscType]) -> Option<f32> {
+    match args.first()? {
+        OscType::Float(value) => Some(*value),
+        OscType::Double(value) => Some(*value as f32),
+        OscType::Int(value) => Some(*value as f32),
+        OscType::Long(value) => Some(*value as f32),
+        OscType::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+/// Read an integer argument. Floats are rounded.
+pub fn arg_int(args: &[OscType]) -> Option<i32> {
+    match args.first()? {
+        OscType::Int(value) => Some(*value),
+        OscType::Long(value) => Some(*value as i32),
+        OscType::Bool(value) => Some(*value as i32),
+        _ => arg_float(args).map(|value| value.round() as i32),
+    }
+}
+
+/// Read a boolean argument. Numbers are true when they're at least 0.5, so buttons sending 0/1
+/// either as ints or floats work.
+pub fn arg_bool(args: &[OscType]) -> Option<bool> {
+    match args.first()? {
+        OscType::Bool(value) => Some(*value),
+        _ => arg_float(args).map(|value| value >= 0.5),
+    }
+}
+
+pub fn parameter_value_to_osc(value: &ParameterValue) -> OscType {
+    match value {
+        ParameterValue::Float(_) => OscType::Float(value.as_float()),
+        ParameterValue::Bool(_) => OscType::Bool(value.as_bool()),
+        ParameterValue::Enum(_) => OscType::Int(value.as_enum() as i32),
+        ParameterValue::Int(_) => OscType::Int(value.as_int()),
+    }
+}
+
+/// Set a looper parameter from OSC arguments, converting them into the parameter's declared
+/// type. Returns `None` if the arguments can't be converted.
+pub fn set_parameter(
+    handle: &MultiTrackLooperHandle,
+    looper_id: LooperId,
+    parameter_id: &ParameterId,
+    args: &[OscType],
+) -> Option<()> {
+    match parameter_id.get_str("type")? {
+        "float" => {
+            let value = arg_float(args)?;
+            match parameter_id {
+                ParameterId::ParameterIdSource(parameter) => {
+                    handle.set_source_parameter(looper_id, parameter.clone(), value)
+                }
+                ParameterId::ParameterIdEnvelope(parameter) => {
+                    handle.set_envelope_parameter(looper_id, parameter.clone(), value)
+                }
+                ParameterId::ParameterIdLFO(lfo, parameter) => {
+                    handle.set_lfo_parameter(looper_id, *lfo, parameter.clone(), value)
+                }
+                ParameterId::ParameterIdQuantization(_) => {
+                    handle.set_parameter(looper_id, parameter_id.clone(), value.into())
+                }
+                ParameterId::ParameterIdEffect(slot_id, parameter) => {
+                    handle.set_effect_parameter(looper_id, *slot_id, *parameter, value)
+                }
+            }
+        }
+        "bool" => handle.set_boolean_parameter(looper_id, parameter_id.clone(), arg_bool(args)?),
+        "int" => handle.set_int_parameter(looper_id, parameter_id.clone(), arg_int(args)?),
+        "enum" => {
+            let value = usize::try_from(arg_int(args)?).ok()?;
+            match parameter_id {
+                ParameterId::ParameterIdLFO(lfo, LFOParameter::LFOParameterMode) => {
+                    handle.set_lfo_mode(looper_id, *lfo, LFOMode::from_usize(value)?)
+                }
+                ParameterId::ParameterIdQuantization(
+                    QuantizationParameter::QuantizationParameterQuantizeMode,
+                ) => handle.set_quantization_mode(looper_id, CQuantizeMode::from_usize(value)?),
+                ParameterId::ParameterIdQuantization(
+                    QuantizationParameter::QuantizationParameterTempoControl,
+                ) => handle.set_tempo_control(looper_id, TempoControl::from_usize(value)?),
+                _ => handle.set_parameter(looper_id, parameter_id.clone(), value.into()),
+            }
+        }
+        _ => return None,
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::audio::multi_track_looper::parameters::build_parameter_ids;
+
+    use super::*;
+
+    #[test]
+    fn test_parameter_addresses_are_unique() {
+        let parameter_ids = build_parameter_ids();
+        let mut addresses: Vec<String> = parameter_ids
+            .iter()
+            .map(|parameter_id| parameter_address(LooperId(3), parameter_id))
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        assert_eq!(addresses.len(), parameter_ids.len());
+        assert!(addresses.contains(&"/looper/3/source/speed".to_string()));
+        assert!(addresses.contains(&"/looper/3/lfo/1/frequency".to_string()));
+        assert!(addresses.contains(&"/looper/3/quantization/tempo_control".to_string()));
+    }
+
+    #[test]
+    fn test_argument_conversion() {
+        assert_eq!(arg_float(&[OscType::Int(2)]), Some(2.0));
+        assert_eq!(arg_float(&[OscType::String("1".into())]), None);
+        assert_eq!(arg_float(&[]), None);
+        assert_eq!(arg_int(&[OscType::Float(1.6)]), Some(2));
+        assert_eq!(arg_bool(&[OscType::Float(1.0)]), Some(true));
+        assert_eq!(arg_bool(&[OscType::Int(0)]), Some(false));
+    }
+}
@@ -33,10 +33,12 @@ use basedrop::Shared;
 use audio_garbage_collector::make_shared;
 
 use crate::services::audio_clip_manager::write_looper_clip;
-use crate::{MultiTrackLooper, MultiTrackLooperHandle};
+use crate::{MultiTrackLooper, MultiTrackLooperHandle, TimeInfoProvider};
 
-use self::model::LooperVoicePersist;
 use self::model::Project;
+use self::model::{
+    EffectChainPersist, LooperVoicePersist, MixerPersist, RoutingPersist, SequencerPersist,
+};
 
 pub mod model;
 
@@ -210,8 +212,13 @@ fn persist_handle_clips(
             let settings = *handle.settings().deref();
             let clip_path = project_path.join(format!("looper_{}.wav", voice.id));
             let clip = voice.looper().looper_clip();
+            let tempo = handle
+                .time_info_provider()
+                .get_time_info()
+                .tempo()
+                .map(|tempo| tempo as f32);
 
-            write_looper_clip(settings, &clip_path, &clip);
+            write_looper_clip(settings, &clip_path, &clip, tempo);
 
             Some(clip_path)
         })
@@ -243,6 +250,16 @@ fn project_from_handle(
             .get()
             .deref()
             .clone(),
+        mixer: Some(MixerPersist::from(looper_handle.mixer().deref())),
+        routing: Some(RoutingPersist::from(looper_handle.routing().deref())),
+        effects: Some(
+            looper_handle
+                .voices()
+                .iter()
+                .map(|voice| EffectChainPersist::from(voice.effects().deref()))
+                .collect(),
+        ),
+        sequencer: Some(SequencerPersist::from(looper_handle.sequencer().deref())),
     }
 }
 
@@ -25,17 +25,33 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
-use crate::audio::midi_map::MidiMapStorePersist;
+use crate::audio::midi_map::{deserialize_midi_map_store, MidiMapStorePersist};
+use crate::audio::multi_track_looper::effects_processor::{
+    EffectSlotHandle, EffectType, EffectsProcessorHandle,
+};
 use crate::audio::multi_track_looper::lfo_processor::LFOHandleMap;
 use crate::audio::multi_track_looper::looper_voice::{LooperVoice, ParameterValues};
+use crate::audio::multi_track_looper::mixer::{
+    AuxBusHandle, MasterBusHandle, MixerHandle, TrackMixerHandle,
+};
+use crate::audio::multi_track_looper::routing::{RoutingHandle, TrackRoutingHandle};
 use crate::audio::multi_track_looper::scene_state::SceneHandle;
-use crate::audio::multi_track_looper::trigger_model::{TrackTriggerModel, Trigger};
+use crate::audio::multi_track_looper::trigger_model::sequencer::{SequencerHandle, SongEntry};
+use crate::audio::multi_track_looper::trigger_model::{TrackTriggerModel, Trigger, NUM_PATTERNS};
 
+/// `triggers` holds the selected pattern. Projects saved before pattern banks existed only have
+/// those and deserialize with no other `patterns`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TrackTriggerModelPersist {
     pub pattern_length: usize,
     pub pattern_step_beats: f64,
     pub triggers: Vec<Trigger>,
+    #[serde(default)]
+    pub swing: Option<f32>,
+    #[serde(default)]
+    pub selected_pattern: usize,
+    #[serde(default)]
+    pub patterns: Vec<Vec<Trigger>>,
 }
 
 impl From<&TrackTriggerModel> for TrackTriggerModelPersist {
@@ -44,6 +60,30 @@ impl From<&TrackTriggerModel> for TrackTriggerModelPersist {
             pattern_length: model.pattern_length(),
             pattern_step_beats: model.pattern_step_beats(),
             triggers: model.triggers().deref().to_vec(),
+            swing: model.swing(),
+            selected_pattern: model.selected_pattern(),
+            patterns: (0..NUM_PATTERNS)
+                .map(|pattern| model.pattern_triggers(pattern).deref().to_vec())
+                .collect(),
+        }
+    }
+}
+
+/// Sequencer state shared by all tracks. Projects saved before song mode existed deserialize to
+/// `None` and keep the engine's defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SequencerPersist {
+    pub swing: f32,
+    pub song_enabled: bool,
+    pub song: Vec<SongEntry>,
+}
+
+impl From<&SequencerHandle> for SequencerPersist {
+    fn from(sequencer: &SequencerHandle) -> Self {
+        Self {
+            swing: sequencer.swing(),
+            song_enabled: sequencer.song_enabled(),
+            song: sequencer.song().deref().clone(),
         }
     }
 }
@@ -69,10 +109,179 @@ impl From<&LooperVoice> for LooperVoicePersist {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackMixerPersist {
+    pub pan: f32,
+    pub is_muted: bool,
+    pub is_soloed: bool,
+    pub group: Option<usize>,
+    pub send_levels: Vec<f32>,
+}
+
+impl From<&TrackMixerHandle> for TrackMixerPersist {
+    fn from(track: &TrackMixerHandle) -> Self {
+        Self {
+            pan: track.pan(),
+            is_muted: track.is_muted(),
+            is_soloed: track.is_soloed(),
+            group: track.group(),
+            send_levels: (0..track.num_sends())
+                .map(|bus| track.send_level(bus))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuxBusPersist {
+    pub return_level: f32,
+    pub effects: Vec<EffectType>,
+}
+
+impl From<&AuxBusHandle> for AuxBusPersist {
+    fn from(aux_bus: &AuxBusHandle) -> Self {
+        Self {
+            return_level: aux_bus.return_level(),
+            effects: aux_bus.effects().effect_types(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MasterBusPersist {
+    pub volume: f32,
+    pub limiter_enabled: bool,
+    pub limiter_ceiling_db: f32,
+    pub limiter_release_ms: f32,
+}
+
+impl From<&MasterBusHandle> for MasterBusPersist {
+    fn from(master: &MasterBusHandle) -> Self {
+        Self {
+            volume: master.volume(),
+            limiter_enabled: master.is_limiter_enabled(),
+            limiter_ceiling_db: master.limiter_ceiling_db(),
+            limiter_release_ms: master.limiter_release_ms(),
+        }
+    }
+}
+
+/// Mixer state. Projects saved before the mixer existed deserialize to `None` and keep the
+/// engine's defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MixerPersist {
+    pub tracks: Vec<TrackMixerPersist>,
+    pub aux_buses: Vec<AuxBusPersist>,
+    pub master: MasterBusPersist,
+}
+
+impl From<&MixerHandle> for MixerPersist {
+    fn from(mixer: &MixerHandle) -> Self {
+        Self {
+            tracks: mixer
+                .tracks()
+                .iter()
+                .map(|track| TrackMixerPersist::from(track.deref()))
+                .collect(),
+            aux_buses: mixer
+                .aux_buses()
+                .iter()
+                .map(|aux_bus| AuxBusPersist::from(aux_bus.deref()))
+                .collect(),
+            master: MasterBusPersist::from(mixer.master().deref()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackRoutingPersist {
+    pub input_channel: usize,
+    pub input_is_mono: bool,
+    pub output_bus: usize,
+}
+
+impl From<&TrackRoutingHandle> for TrackRoutingPersist {
+    fn from(track: &TrackRoutingHandle) -> Self {
+        Self {
+            input_channel: track.input_channel(),
+            input_is_mono: track.input_is_mono(),
+            output_bus: track.output_bus(),
+        }
+    }
+}
+
+/// Track input/output routing. Projects saved before routing existed deserialize to `None` and
+/// keep the engine's defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoutingPersist {
+    pub tracks: Vec<TrackRoutingPersist>,
+}
+
+impl From<&RoutingHandle> for RoutingPersist {
+    fn from(routing: &RoutingHandle) -> Self {
+        Self {
+            tracks: routing
+                .tracks()
+                .iter()
+                .map(|track| TrackRoutingPersist::from(track.deref()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EffectSlotPersist {
+    pub slot_id: usize,
+    pub effect_type: EffectType,
+    pub bypassed: bool,
+    pub mix: f32,
+    pub parameters: Vec<f32>,
+}
+
+impl From<&EffectSlotHandle> for EffectSlotPersist {
+    fn from(slot: &EffectSlotHandle) -> Self {
+        Self {
+            slot_id: slot.id(),
+            effect_type: slot.effect_type().clone(),
+            bypassed: slot.is_bypassed(),
+            mix: slot.mix(),
+            parameters: slot.parameters(),
+        }
+    }
+}
+
+/// A track's effects chain, in processing order. Projects saved before effect slots existed
+/// deserialize to `None` and keep empty chains.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EffectChainPersist {
+    pub slots: Vec<EffectSlotPersist>,
+}
+
+impl From<&EffectsProcessorHandle> for EffectChainPersist {
+    fn from(effects: &EffectsProcessorHandle) -> Self {
+        Self {
+            slots: effects
+                .slots()
+                .iter()
+                .map(|slot| EffectSlotPersist::from(slot.deref()))
+                .collect(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Project {
     pub voices: Vec<LooperVoicePersist>,
     pub looper_clips: Vec<Option<PathBuf>>,
+    #[serde(deserialize_with = "deserialize_midi_map_store")]
     pub midi_map: MidiMapStorePersist,
     pub scene_state: SceneHandle,
+    #[serde(default)]
+    pub mixer: Option<MixerPersist>,
+    #[serde(default)]
+    pub routing: Option<RoutingPersist>,
+    #[serde(default)]
+    pub effects: Option<Vec<EffectChainPersist>>,
+    #[serde(default)]
+    pub sequencer: Option<SequencerPersist>,
 }
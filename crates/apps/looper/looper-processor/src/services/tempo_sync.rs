@@ -0,0 +1,290 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Keeps the looper tempo & bar phase in sync with other looper/metronome instances on the local
+//! network. See [`augmented_tempo_sync`].
+//!
+//! * Tempo changes made locally (UI, tempo estimation, OSC) are pushed to the session
+//! * Tempo changes made on other peers are applied onto the [`MultiTrackLooperHandle`], which
+//!   also updates the metronome
+//! * While playing, the play-head is nudged into phase with the session every `interval`. The
+//!   metronome follows the looper play-head, so it stays in phase as well
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use basedrop::Shared;
+
+use augmented_tempo_sync::{
+    SystemClock, TempoSyncOptions, TempoSyncReader, TempoSyncSession, TempoSyncThread,
+    UdpMulticastTransport,
+};
+
+use crate::{MultiTrackLooperHandle, TimeInfoProvider};
+
+pub struct LooperTempoSyncOptions {
+    /// Network session options. The session tempo defaults to the current looper tempo.
+    pub session: TempoSyncOptions,
+    /// How often tempo & phase are checked. Defaults to 10ms.
+    pub interval: Duration,
+    /// Phase drift, in beats, which is tolerated before the play-head is moved. Defaults to 0.01.
+    pub tolerance_beats: f64,
+}
+
+impl Default for LooperTempoSyncOptions {
+    fn default() -> Self {
+        Self {
+            session: TempoSyncOptions::default(),
+            interval: Duration::from_millis(10),
+            tolerance_beats: 0.01,
+        }
+    }
+}
+
+/// Synchronises the looper with a [`TempoSyncSession`] each time [`TempoSync::tick`] is called.
+pub struct TempoSync {
+    handle: Shared<MultiTrackLooperHandle>,
+    session: Arc<TempoSyncSession>,
+    reader: TempoSyncReader,
+    tolerance_beats: f64,
+    /// Last tempo we've seen locally, used to tell local changes apart
+    last_tempo: Option<f32>,
+}
+
+impl TempoSync {
+    pub fn new(
+        handle: Shared<MultiTrackLooperHandle>,
+        session: Arc<TempoSyncSession>,
+        tolerance_beats: f64,
+    ) -> Self {
+        let reader = session.reader();
+        Self {
+            handle,
+            session,
+            reader,
+            tolerance_beats,
+            last_tempo: None,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.sync_tempo();
+        self.sync_phase();
+    }
+
+    fn sync_tempo(&mut self) {
+        let local_tempo = self.local_tempo();
+        let session_tempo = self.session.tempo() as f32;
+
+        match (local_tempo, self.last_tempo) {
+            (Some(local_tempo), Some(last_tempo)) if local_tempo != last_tempo => {
+                log::info!("Sending tempo {} to the sync session", local_tempo);
+                self.session.set_tempo(local_tempo as f64);
+                self.last_tempo = Some(local_tempo);
+            }
+            _ if local_tempo != Some(session_tempo) => {
+                log::info!("Applying sync session tempo {}", session_tempo);
+                self.handle.set_tempo(session_tempo);
+                self.last_tempo = Some(session_tempo);
+            }
+            _ => {}
+        }
+    }
+
+    fn sync_phase(&self) {
+        let time_info_provider = self.handle.time_info_provider();
+        let time_info = time_info_provider.get_time_info();
+        if !time_info.is_playing() {
+            return;
+        }
+
+        if let Some(position_beats) = time_info.position_beats() {
+            let mut aligned_beats = self.reader.aligned_beat(position_beats);
+            // The play-head can't go before the start, use the next bar instead
+            if aligned_beats < 0.0 {
+                aligned_beats += self.reader.quantum();
+            }
+            if (aligned_beats - position_beats).abs() > self.tolerance_beats {
+                time_info_provider
+                    .playhead()
+                    .set_position_beats(aligned_beats);
+            }
+        }
+    }
+
+    fn local_tempo(&self) -> Option<f32> {
+        self.handle
+            .time_info_provider()
+            .get_time_info()
+            .tempo()
+            .map(|tempo| tempo as f32)
+    }
+}
+
+/// Running tempo sync. Leaves the session when dropped.
+pub struct LooperTempoSync {
+    session: Arc<TempoSyncSession>,
+    _session_thread: TempoSyncThread,
+    is_running: Arc<AtomicBool>,
+    sync_thread: Option<JoinHandle<()>>,
+}
+
+impl LooperTempoSync {
+    /// Join the UDP multicast tempo sync session
+    pub fn start(
+        handle: Shared<MultiTrackLooperHandle>,
+        mut options: LooperTempoSyncOptions,
+    ) -> std::io::Result<Self> {
+        if let Some(tempo) = handle.time_info_provider().get_time_info().tempo() {
+            options.session.tempo = tempo;
+        }
+        let transport = UdpMulticastTransport::bind(Default::default())?;
+        let session = TempoSyncSession::new(
+            transport,
+            Arc::new(SystemClock::default()),
+            std::mem::take(&mut options.session),
+        );
+        Self::start_with_session(handle, Arc::new(session), options)
+    }
+
+    pub fn start_with_session(
+        handle: Shared<MultiTrackLooperHandle>,
+        session: Arc<TempoSyncSession>,
+        options: LooperTempoSyncOptions,
+    ) -> std::io::Result<Self> {
+        let session_thread = session.clone().start()?;
+        let is_running = Arc::new(AtomicBool::new(true));
+        let sync_thread = std::thread::Builder::new()
+            .name(String::from("looper-tempo-sync"))
+            .spawn({
+                let is_running = is_running.clone();
+                let mut tempo_sync =
+                    TempoSync::new(handle, session.clone(), options.tolerance_beats);
+                move || {
+                    while is_running.load(Ordering::Relaxed) {
+                        tempo_sync.tick();
+                        std::thread::sleep(options.interval);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            session,
+            _session_thread: session_thread,
+            is_running,
+            sync_thread: Some(sync_thread),
+        })
+    }
+
+    pub fn session(&self) -> &Arc<TempoSyncSession> {
+        &self.session
+    }
+}
+
+impl Drop for LooperTempoSync {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        if let Some(sync_thread) = self.sync_thread.take() {
+            let _ = sync_thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::assert_f_eq;
+    use augmented_tempo_sync::{LocalNetwork, ManualClock};
+
+    use crate::MultiTrackLooper;
+
+    use super::*;
+
+    fn setup(tempo: f64) -> (Arc<ManualClock>, Arc<TempoSyncSession>) {
+        let clock = Arc::new(ManualClock::new(0));
+        let session = TempoSyncSession::new(
+            LocalNetwork::new().connect(),
+            clock.clone(),
+            TempoSyncOptions {
+                tempo,
+                ..TempoSyncOptions::default()
+            },
+        );
+        (clock, Arc::new(session))
+    }
+
+    #[test]
+    fn test_tempo_is_synchronised_both_ways() {
+        let looper = MultiTrackLooper::new(Default::default(), 1);
+        let handle = looper.handle().clone();
+        handle.set_tempo(120.0);
+        let (_clock, session) = setup(90.0);
+        let mut tempo_sync = TempoSync::new(handle.clone(), session.clone(), 0.01);
+
+        // The session tempo wins when joining
+        tempo_sync.tick();
+        assert_eq!(handle.metronome_handle().tempo(), 90.0);
+        assert_f_eq!(
+            handle.time_info_provider().get_time_info().tempo().unwrap(),
+            90.0
+        );
+
+        // Local changes are sent to the session
+        handle.set_tempo(140.0);
+        tempo_sync.tick();
+        assert_f_eq!(session.tempo(), 140.0);
+
+        // Session changes are applied
+        session.set_tempo(100.0);
+        tempo_sync.tick();
+        assert_eq!(handle.metronome_handle().tempo(), 100.0);
+        tempo_sync.tick();
+        assert_f_eq!(session.tempo(), 100.0);
+    }
+
+    #[test]
+    fn test_playhead_is_moved_into_phase() {
+        let looper = MultiTrackLooper::new(Default::default(), 1);
+        let handle = looper.handle().clone();
+        let (clock, session) = setup(60.0);
+        let mut tempo_sync = TempoSync::new(handle.clone(), session, 0.01);
+        let time_info_provider = handle.time_info_provider();
+        time_info_provider.set_sample_rate(1000.0);
+
+        clock.advance(Duration::from_millis(6500));
+        tempo_sync.tick();
+        // Stopped play-heads aren't moved
+        assert_f_eq!(
+            time_info_provider.get_time_info().position_beats().unwrap(),
+            0.0
+        );
+
+        handle.play();
+        time_info_provider.tick_n(200);
+        tempo_sync.tick();
+        // Session is at beat 6.5, phase 2.5, the closest beat in phase with it would be -1.5
+        assert_f_eq!(
+            time_info_provider.get_time_info().position_beats().unwrap(),
+            2.5
+        );
+    }
+}
@@ -23,6 +23,7 @@
 use std::sync::Arc;
 
 use vst::api::Events;
+use vst::channels::{ChannelInfo, SpeakerArrangementType, StereoChannel, StereoConfig};
 use vst::editor::Editor;
 use vst::plugin::{Category, HostCallback, Info, Plugin, PluginParameters};
 use vst::plugin_main;
@@ -41,6 +42,14 @@ pub mod ui;
 
 pub static BUNDLE_IDENTIFIER: &str = "com.beijaflor.Loopi";
 
+/// Number of looper tracks in the plug-in. The editor only controls the first track, so there's a
+/// single track & a single track output bus until it supports more.
+const NUM_LOOPERS: usize = 1;
+/// Stereo output buses, the main mix and one for each track. Tracks play on the main mix unless
+/// routed into their own bus.
+const NUM_OUTPUT_BUSES: usize = 1 + NUM_LOOPERS;
+const NUM_OUTPUT_CHANNELS: usize = NUM_OUTPUT_BUSES * 2;
+
 pub struct LoopiPlugin {
     parameters: Arc<ParameterStore>,
     processor: MultiTrackLooper,
@@ -56,6 +65,8 @@ impl Plugin for LoopiPlugin {
             vendor: "Beijaflor Software".to_string(),
             unique_id: 2504, // Used by hosts to differentiate between plugins.
             parameters: 0,
+            inputs: 2,
+            outputs: NUM_OUTPUT_CHANNELS as i32,
             ..Default::default()
         }
     }
@@ -70,17 +81,43 @@ impl Plugin for LoopiPlugin {
             LooperOptions {
                 ..Default::default()
             },
-            1,
+            NUM_LOOPERS,
         );
 
+        let mut settings = AudioProcessorSettings::default();
+        settings.set_output_channels(NUM_OUTPUT_CHANNELS);
+
         LoopiPlugin {
             processor,
             parameters: Arc::new(ParameterStore::default()),
-            settings: AudioProcessorSettings::default(),
-            buffer_handler: Default::default(),
+            settings,
+            buffer_handler: VSTBufferHandler::new_with_num_channels(NUM_OUTPUT_CHANNELS),
         }
     }
 
+    fn get_output_info(&self, output: i32) -> ChannelInfo {
+        let bus = output as usize / 2;
+        let (side, stereo_channel) = if output % 2 == 0 {
+            ("L", StereoChannel::Left)
+        } else {
+            ("R", StereoChannel::Right)
+        };
+        let name = if bus == 0 {
+            format!("Main {}", side)
+        } else {
+            format!("Track {} {}", bus, side)
+        };
+        ChannelInfo::new(
+            name.clone(),
+            Some(name),
+            true,
+            Some(SpeakerArrangementType::Stereo(
+                StereoConfig::L_R,
+                stereo_channel,
+            )),
+        )
+    }
+
     fn set_sample_rate(&mut self, rate: f32) {
         self.settings.set_sample_rate(rate);
         let mut context = AudioContext::from(self.settings);
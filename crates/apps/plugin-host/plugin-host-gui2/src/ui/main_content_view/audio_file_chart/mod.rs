@@ -21,15 +21,20 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use audio_processor_traits::AudioBuffer;
+use std::ops::Range;
+
 use iced::{
     widget::{
-        canvas::{Cursor, Geometry, Program},
+        canvas::{self, Cursor, Frame, Geometry, Program},
         Canvas,
     },
-    Element, Rectangle,
+    Element, Length, Point, Rectangle, Size,
 };
 
+use audio_processor_iced_design_system::colors::Colors;
+use augmented::audio::processor::file::peaks::{
+    PeakPyramid, PeakPyramidLoader, PeakPyramidOptions,
+};
 use augmented::gui::iced_baseview::renderer::Theme;
 
 pub enum Message {
@@ -37,21 +42,67 @@ pub enum Message {
     None,
 }
 
+/// Waveform overview of the input file. Peaks are built on a background thread & cached next to
+/// the file, so drawing only reads as many peaks as there are pixels.
 pub struct AudioFileModel {
-    #[allow(dead_code)]
-    audio_file: AudioBuffer<f32>,
+    loader: Option<PeakPyramidLoader>,
+    pyramid: Option<PeakPyramid>,
+    /// Visible range of frames, defaults to the whole file
+    visible_range: Option<Range<usize>>,
 }
 
 impl AudioFileModel {
     pub fn empty() -> Self {
         Self {
-            audio_file: AudioBuffer::empty(),
+            loader: None,
+            pyramid: None,
+            visible_range: None,
+        }
+    }
+
+    pub fn load(&mut self, path: &str) {
+        self.pyramid = None;
+        self.visible_range = None;
+        self.loader = Some(PeakPyramidLoader::load_or_build(
+            path,
+            PeakPyramidOptions::default(),
+        ));
+    }
+
+    /// Check whether the background peak generation finished
+    pub fn update(&mut self) {
+        let result = match &self.loader {
+            Some(loader) => loader.poll(),
+            None => None,
+        };
+        match result {
+            Some(Ok(pyramid)) => {
+                self.pyramid = Some(pyramid);
+                self.loader = None;
+            }
+            Some(Err(err)) => {
+                log::error!("Failed to build audio file overview: {}", err);
+                self.loader = None;
+            }
+            None => {}
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_visible_range(&mut self, range: Range<usize>) {
+        self.visible_range = Some(range);
+    }
+
+    fn visible_range(&self) -> Range<usize> {
+        match (&self.visible_range, &self.pyramid) {
+            (Some(range), _) => range.clone(),
+            (None, Some(pyramid)) => 0..pyramid.num_frames(),
+            (None, None) => 0..0,
         }
     }
 }
 
 pub struct View<'a> {
-    #[allow(dead_code)]
     model: &'a AudioFileModel,
 }
 
@@ -61,7 +112,10 @@ impl<'a> View<'a> {
     }
 
     pub fn view(self) -> Element<'a, Message> {
-        Canvas::new(self).into()
+        Canvas::new(self)
+            .height(Length::Fill)
+            .width(Length::Fill)
+            .into()
     }
 }
 
@@ -72,9 +126,47 @@ impl<'a> Program<Message> for View<'a> {
         &self,
         _state: &Self::State,
         _theme: &Theme,
-        _bounds: Rectangle,
+        bounds: Rectangle,
         _cursor: Cursor,
     ) -> Vec<Geometry> {
-        vec![]
+        let mut frame = Frame::new(bounds.size());
+
+        let pyramid = match &self.model.pyramid {
+            Some(pyramid) => pyramid,
+            None => {
+                if let Some(loader) = &self.model.loader {
+                    let progress = canvas::Path::rectangle(
+                        Point::new(0.0, frame.height() / 2.0 - 1.0),
+                        Size::new(frame.width() * loader.progress(), 2.0),
+                    );
+                    frame.fill(&progress, Colors::border_color());
+                }
+                return vec![frame.into_geometry()];
+            }
+        };
+
+        let num_pixels = frame.width().max(1.0) as usize;
+        let num_channels = pyramid.num_channels();
+        let channel_height = frame.height() / num_channels.max(1) as f32;
+        let range = self.model.visible_range();
+
+        for channel in 0..num_channels {
+            let peaks = pyramid.peaks(channel, range.clone(), num_pixels);
+            let center = channel_height * (channel as f32 + 0.5);
+            let mut path = canvas::path::Builder::new();
+            for (x, peak) in peaks.iter().enumerate() {
+                let x = x as f32;
+                path.move_to(Point::new(x, center - peak.max * channel_height / 2.0));
+                path.line_to(Point::new(x, center - peak.min * channel_height / 2.0));
+            }
+            frame.stroke(
+                &path.build(),
+                canvas::Stroke::default()
+                    .with_width(1.0)
+                    .with_color(Colors::active_border_color()),
+            );
+        }
+
+        vec![frame.into_geometry()]
     }
 }
@@ -27,6 +27,7 @@ use derivative::Derivative;
 use thiserror::Error;
 
 use augmented::audio::gc::Shared;
+use augmented::audio::processor::analysis::loudness::LoudnessMeterHandle;
 use augmented::gui::iced::{Command, Element, Subscription};
 use plugin_host_lib::audio_io::{
     LoadPluginMessage, ReloadPluginMessage, SetAudioFilePathMessage, StartMessage, StopMessage,
@@ -81,6 +82,8 @@ pub struct MainContentView {
     /// RMS processor handle
     /// This should not be optional & it might break if the host restarts processors for some reason
     rms_processor_handle: Option<Shared<RunningRMSProcessorHandle>>,
+    /// EBU R128 loudness meter handle
+    loudness_handle: Option<Shared<LoudnessMeterHandle>>,
     /// Amplitude over time chart
     audio_chart: Option<audio_chart::AudioChart>,
     /// Unused
@@ -142,6 +145,10 @@ impl MainContentView {
         let (audio_io_settings, audio_io_settings_command) =
             audio_io_settings::Controller::new(audio_io_service);
         let editor_controller = plugin_editor_window::EditorController::new(plugin_host.clone());
+        let mut audio_file_model = AudioFileModel::empty();
+        if let Some(path) = &host_state.audio_input_file_path {
+            audio_file_model.load(path);
+        }
 
         let command = Command::batch(vec![
             command,
@@ -160,8 +167,9 @@ impl MainContentView {
                 status_message: StatusBar::new("Starting audio thread", status_bar::State::Warning),
                 volume_handle: None,
                 rms_processor_handle: None,
+                loudness_handle: None,
                 audio_chart: None,
-                audio_file_model: AudioFileModel::empty(),
+                audio_file_model,
                 volume_meter_state: volume_meter::VolumeMeter::default(),
                 start_stop_button_state: view::StartStopViewModel::default(),
                 route: view::Route::Development,
@@ -176,8 +184,11 @@ impl MainContentView {
         if let Some(chart) = &mut self.audio_chart {
             chart.update();
         }
+        self.audio_file_model.update();
         self.volume_meter_state
             .set_volume_info((&self.volume_handle).into());
+        self.volume_meter_state
+            .set_loudness_info((&self.loudness_handle).into());
         match message {
             Message::AudioIOSettings(msg) => self.update_audio_io_settings(msg),
             Message::PluginContent(msg) => self.update_plugin_content(msg),
@@ -371,12 +382,19 @@ impl MainContentView {
                 self.audio_chart = Some(audio_chart::AudioChart::new(buffer));
             }
         }
+        if self.loudness_handle.is_none() {
+            if let Some(loudness_handle) = ProcessorHandleRegistry::current().get("loudness-meter")
+            {
+                self.loudness_handle = Some(loudness_handle);
+            }
+        }
     }
 
     fn reset_handles(&mut self) {
         self.audio_chart = None;
         self.rms_processor_handle = None;
         self.volume_handle = None;
+        self.loudness_handle = None;
     }
 }
 
@@ -406,6 +424,7 @@ impl MainContentView {
 
     fn on_set_input_file_response(&mut self, input_file: String) {
         self.reset_handles();
+        self.audio_file_model.load(&input_file);
         self.host_state.audio_input_file_path = Some(input_file);
         self.host_options_service
             .store(&self.host_state)
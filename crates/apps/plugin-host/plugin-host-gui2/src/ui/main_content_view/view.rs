@@ -82,13 +82,15 @@ pub fn main_content_view(view_model: MainContentViewModel) -> Element<Message> {
         transport_controls,
         status_message,
         start_stop_button_state,
-        audio_file_model: _,
+        audio_file_model,
     } = view_model;
 
     let content_view = match route {
         Route::Development => Row::with_children(vec![Column::with_children(vec![
             plugin_content_container(plugin_content),
             Rule::horizontal(1).style(style::Rule).into(),
+            audio_file_visualization(audio_file_model),
+            Rule::horizontal(1).style(style::Rule).into(),
             bottom_visualisation_content_container(BottomVisualisationViewModel {
                 audio_chart,
                 volume_meter_state,
@@ -121,7 +123,6 @@ struct BottomVisualisationViewModel<'a> {
     volume_meter_state: &'a volume_meter::VolumeMeter,
 }
 
-#[allow(dead_code)]
 fn audio_file_visualization(audio_file_model: &AudioFileModel) -> Element<Message> {
     Container::new(
         audio_file_chart::View::new(audio_file_model)
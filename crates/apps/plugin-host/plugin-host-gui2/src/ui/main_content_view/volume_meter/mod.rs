@@ -27,12 +27,14 @@ use iced::widget::canvas::event::Status;
 use iced::widget::canvas::Fill;
 use iced::widget::canvas::{Cache, Cursor, Event, Frame, Geometry, Program, Stroke};
 use iced::{
-    widget::Canvas, widget::Container, Command, Element, Length, Point, Rectangle, Size, Vector,
+    widget::Canvas, widget::Column, widget::Container, widget::Text, Command, Element, Length,
+    Point, Rectangle, Size, Vector,
 };
 
 use audio_garbage_collector::Shared;
 use audio_processor_iced_design_system::colors::{darken_color, Colors};
 use audio_processor_iced_design_system::spacing::Spacing;
+use augmented::audio::processor::analysis::loudness::LoudnessMeterHandle;
 use augmented_audio_volume::{Amplitude, Decibels};
 use plugin_host_lib::audio_io::processor_handle_registry::ProcessorHandleRegistry;
 use plugin_host_lib::processors::test_host_processor::TestHostProcessorHandle;
@@ -60,6 +62,37 @@ impl From<&Option<Shared<VolumeMeterProcessorHandle>>> for VolumeInfo {
     }
 }
 
+/// Broadcast loudness readings, displayed as text below the meter
+#[derive(Default, Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct LoudnessInfo {
+    momentary: f32,
+    short_term: f32,
+    integrated: f32,
+    loudness_range: f32,
+    true_peak: f32,
+}
+
+impl From<&Option<Shared<LoudnessMeterHandle>>> for LoudnessInfo {
+    fn from(handle: &Option<Shared<LoudnessMeterHandle>>) -> Self {
+        match handle {
+            None => LoudnessInfo {
+                momentary: f32::NEG_INFINITY,
+                short_term: f32::NEG_INFINITY,
+                integrated: f32::NEG_INFINITY,
+                loudness_range: 0.0,
+                true_peak: f32::NEG_INFINITY,
+            },
+            Some(handle) => LoudnessInfo {
+                momentary: handle.momentary_loudness(),
+                short_term: handle.short_term_loudness(),
+                integrated: handle.integrated_loudness(),
+                loudness_range: handle.loudness_range(),
+                true_peak: handle.true_peak(),
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Message {
     DragStart,
@@ -89,6 +122,7 @@ struct MouseState {
 
 pub struct VolumeMeter {
     volume_info: VolumeInfo,
+    loudness_info: LoudnessInfo,
     state: State,
     // frame: RefCell<Frame>,
     left_cache: Cache,
@@ -105,6 +139,7 @@ impl VolumeMeter {
     pub fn new() -> Self {
         Self {
             volume_info: VolumeInfo::default(),
+            loudness_info: LoudnessInfo::from(&None),
             state: State::default(),
             // frame: RefCell::new(Frame::new(Size::new(100., 100.))),
             left_cache: Default::default(),
@@ -116,16 +151,38 @@ impl VolumeMeter {
         self.volume_info = volume_info;
     }
 
+    pub fn set_loudness_info(&mut self, loudness_info: LoudnessInfo) {
+        self.loudness_info = loudness_info;
+    }
+
     pub fn set_volume_handle(&mut self, value: Decibels) {
         self.state.volume = value;
     }
 
     pub fn view(&self) -> Element<Message> {
-        Container::new(Canvas::new(self).width(Length::Fill).height(Length::Fill))
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .padding(Spacing::medium_spacing())
-            .into()
+        let loudness_info = self.loudness_info;
+        let loudness_text = format!(
+            "M {}\nS {}\nI {}\nLRA {:.1} LU\nTP {}",
+            format_lufs(loudness_info.momentary),
+            format_lufs(loudness_info.short_term),
+            format_lufs(loudness_info.integrated),
+            loudness_info.loudness_range,
+            format_dbtp(loudness_info.true_peak),
+        );
+
+        Container::new(Column::with_children(vec![
+            Canvas::new(self)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into(),
+            Text::new(loudness_text)
+                .size(Spacing::small_font_size())
+                .into(),
+        ]))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .padding(Spacing::medium_spacing())
+        .into()
     }
 
     /// True if the cursor is currently dragging the volume meter handle
@@ -411,6 +468,22 @@ impl VolumeMeter {
     }
 }
 
+fn format_lufs(value: f32) -> String {
+    if value.is_finite() {
+        format!("{:.1} LUFS", value)
+    } else {
+        "-inf LUFS".to_string()
+    }
+}
+
+fn format_dbtp(value: f32) -> String {
+    if value.is_finite() {
+        format!("{:.1} dBTP", value)
+    } else {
+        "-inf dBTP".to_string()
+    }
+}
+
 /// Convert a number in decibels to the rendering range.
 /// This has no meaning other than to be a logarithmic scaled float that fits nicely within the UI.
 fn db_to_render(db: f32) -> f32 {
@@ -43,6 +43,7 @@ use crate::audio_io::audio_thread::options::AudioThreadOptions;
 use crate::audio_io::audio_thread::{AudioThread, AudioThreadProcessor};
 use crate::processors::audio_file_processor::file_io::{default_read_audio_file, AudioFileError};
 use crate::processors::audio_file_processor::InMemoryAudioFile;
+use crate::processors::output_file_processor::{OutputFileSettings, RecordingMetadata};
 use crate::processors::running_rms_processor::RunningRMSProcessorHandle;
 use crate::processors::shared_processor::SharedProcessor;
 use crate::processors::test_host_processor::TestHostProcessor;
@@ -328,6 +329,30 @@ impl TestPluginHost {
         }
     }
 
+    /// Start recording the output into a file. Returns false if already recording.
+    pub fn start_recording(&self, path: &Path) -> bool {
+        match self.host_processor() {
+            Some(processor) => {
+                log::info!("Recording output path={:?}", path);
+                let mut settings = OutputFileSettings::new(&path.to_string_lossy());
+                settings.metadata = Some(RecordingMetadata {
+                    originator: String::from("plugin-host"),
+                    ..RecordingMetadata::default()
+                });
+                processor.recorder_handle().start(settings)
+            }
+            None => false,
+        }
+    }
+
+    /// Stop recording, the file is finalized in the background
+    pub fn stop_recording(&self) {
+        if let Some(processor) = self.host_processor() {
+            log::info!("Stopping recording processor_id={}", processor.id());
+            processor.recorder_handle().stop();
+        }
+    }
+
     pub fn plugin_instance(&mut self) -> Option<SharedProcessor<PluginInstance>> {
         self.vst_plugin_instance.clone()
     }
@@ -28,6 +28,7 @@ use vst::host::PluginInstance;
 use vst::plugin::Plugin;
 
 use audio_garbage_collector::{Handle, Shared};
+use audio_processor_analysis::loudness::{LoudnessMeterHandle, LoudnessMeterProcessor};
 use audio_processor_standalone_midi::host::MidiMessageEntry;
 use audio_processor_standalone_midi::vst::MidiVSTConverter;
 use audio_processor_traits::{
@@ -36,7 +37,10 @@ use audio_processor_traits::{
 
 use crate::audio_io::cpal_vst_buffer_handler::CpalVstBufferHandler;
 use crate::audio_io::processor_handle_registry::ProcessorHandleRegistry;
-use crate::processors::audio_file_processor::{AudioFileProcessor, InMemoryAudioFile};
+use crate::processors::audio_file_processor::{
+    AudioFileProcessor, InMemoryAudioFile, StreamingOptions,
+};
+use crate::processors::output_file_processor::{AudioFileRecorder, AudioFileRecorderHandle};
 use crate::processors::running_rms_processor::{RunningRMSProcessor, RunningRMSProcessorHandle};
 use crate::processors::shared_processor::SharedProcessor;
 use crate::processors::volume_meter_processor::{VolumeMeterProcessor, VolumeMeterProcessorHandle};
@@ -63,6 +67,8 @@ pub struct TestHostProcessorImpl<PluginInstanceT: Plugin + 'static> {
     maybe_audio_file_processor: Option<AudioFileProcessor>,
     volume_meter_processor: VolumeMeterProcessor,
     running_rms_processor: RunningRMSProcessor,
+    loudness_meter_processor: LoudnessMeterProcessor,
+    recorder: AudioFileRecorder,
     midi_converter: MidiVSTConverter,
     mono_input: Option<usize>,
 }
@@ -99,8 +105,21 @@ impl<PluginInstanceT: Plugin> TestHostProcessorImpl<PluginInstanceT> {
         ProcessorHandleRegistry::current()
             .register("rms-processor", running_rms_processor.handle().clone());
 
+        let loudness_meter_processor = LoudnessMeterProcessor::default();
+        ProcessorHandleRegistry::current()
+            .register("loudness-meter", loudness_meter_processor.handle().clone());
+
+        let recorder = AudioFileRecorder::default();
+        ProcessorHandleRegistry::current().register("recorder", recorder.handle().clone());
+
+        // Files are streamed from disk so long files don't need to fit in memory
         let maybe_audio_file_processor = maybe_audio_file_settings.map(|audio_file_settings| {
-            AudioFileProcessor::new(handle, audio_file_settings, audio_settings)
+            AudioFileProcessor::new_streaming(
+                handle,
+                audio_file_settings,
+                audio_settings,
+                StreamingOptions::default(),
+            )
         });
 
         if let Some(audio_file_processor) = &maybe_audio_file_processor {
@@ -117,6 +136,8 @@ impl<PluginInstanceT: Plugin> TestHostProcessorImpl<PluginInstanceT> {
             maybe_audio_file_processor,
             volume_meter_processor,
             running_rms_processor,
+            loudness_meter_processor,
+            recorder,
             midi_converter: MidiVSTConverter::default(),
             mono_input,
         }
@@ -172,6 +193,14 @@ impl<PluginInstanceT: Plugin> TestHostProcessorImpl<PluginInstanceT> {
         self.running_rms_processor.handle()
     }
 
+    pub fn loudness_meter_handle(&self) -> &Shared<LoudnessMeterHandle> {
+        self.loudness_meter_processor.handle()
+    }
+
+    pub fn recorder_handle(&self) -> &Shared<AudioFileRecorderHandle> {
+        self.recorder.handle()
+    }
+
     pub fn set_volume(&self, volume: f32) {
         self.handle.set_volume(volume);
     }
@@ -202,6 +231,8 @@ impl<PluginInstanceT: Plugin> AudioProcessor for TestHostProcessorImpl<PluginIns
         }
         self.volume_meter_processor.prepare(context);
         self.running_rms_processor.prepare(context);
+        self.loudness_meter_processor.prepare(context);
+        self.recorder.prepare(context);
     }
 
     fn process(&mut self, context: &mut AudioContext, output: &mut AudioBuffer<Self::SampleType>) {
@@ -236,6 +267,10 @@ impl<PluginInstanceT: Plugin> AudioProcessor for TestHostProcessorImpl<PluginIns
         // Volume meter
         self.volume_meter_processor.process(context, output);
         self.running_rms_processor.process(context, output);
+        self.loudness_meter_processor.process(context, output);
+
+        // Record what's being heard
+        self.recorder.process(context, output);
     }
 }
 
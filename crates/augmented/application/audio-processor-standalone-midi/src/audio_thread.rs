@@ -75,7 +75,7 @@ impl MidiAudioThreadHandler {
 
 #[cfg(test)]
 mod test {
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
     use basedrop::{Collector, Owned};
 
     use audio_processor_traits::MidiMessageLike;
@@ -129,7 +129,7 @@ mod test {
         let mut midi_audio_thread_handler = MidiAudioThreadHandler::default();
 
         let num_messages =
-            assert_no_alloc(|| midi_audio_thread_handler.collect_midi_messages(&queue));
+            assert_realtime_safe(|| midi_audio_thread_handler.collect_midi_messages(&queue));
 
         assert_eq!(num_messages, 3);
         let buffer = midi_audio_thread_handler.buffer();
@@ -157,7 +157,7 @@ mod test {
 
         let mut midi_audio_thread_handler = MidiAudioThreadHandler::default();
         let num_messages =
-            assert_no_alloc(|| midi_audio_thread_handler.collect_midi_messages(&queue));
+            assert_realtime_safe(|| midi_audio_thread_handler.collect_midi_messages(&queue));
         assert_eq!(num_messages, 1);
         let buffer = midi_audio_thread_handler.buffer();
         assert_eq!(buffer.len(), 1);
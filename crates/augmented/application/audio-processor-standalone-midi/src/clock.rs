@@ -0,0 +1,520 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! MIDI clock slave & master support.
+//!
+//! * [`MidiClockFollower`] follows an incoming MIDI clock. It's real-time safe and meant to be fed
+//!   MIDI messages on the audio-thread. Tempo is estimated from the average of recent clock
+//!   intervals, with outliers removed, and smoothed, so it's stable against timing jitter from
+//!   USB/drivers
+//! * [`MidiClockGenerator`] decides when clock & transport messages should be sent to follow a
+//!   play-head
+//! * [`spawn_midi_clock_thread`] drives a generator on a background thread and sends messages out
+//!   over a [`MidiClockOutput`], such as a `midir` connection
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use basedrop::Shared;
+
+/// MIDI clock ticks per quarter note
+pub const MIDI_CLOCK_PPQN: u32 = 24;
+
+pub const MIDI_CLOCK_TICK: u8 = 0xF8;
+pub const MIDI_CLOCK_START: u8 = 0xFA;
+pub const MIDI_CLOCK_CONTINUE: u8 = 0xFB;
+pub const MIDI_CLOCK_STOP: u8 = 0xFC;
+pub const MIDI_SONG_POSITION_POINTER: u8 = 0xF2;
+
+/// Number of tick intervals the tempo is estimated from
+const TEMPO_WINDOW: usize = 24;
+/// Tempo range accepted, clock intervals outside of it are ignored
+const MIN_TEMPO: f64 = 20.0;
+const MAX_TEMPO: f64 = 400.0;
+/// Relative tempo change considered a real change, rather than jitter
+const TEMPO_JUMP_THRESHOLD: f64 = 0.05;
+const TEMPO_SMOOTHING: f64 = 0.1;
+/// Relative distance from the median interval after which an interval is ignored
+const OUTLIER_THRESHOLD: f64 = 0.3;
+
+/// Returns true for messages [`MidiClockFollower`] is interested in
+pub fn is_midi_clock_message(bytes: &[u8]) -> bool {
+    matches!(
+        bytes.first(),
+        Some(&MIDI_CLOCK_TICK)
+            | Some(&MIDI_CLOCK_START)
+            | Some(&MIDI_CLOCK_CONTINUE)
+            | Some(&MIDI_CLOCK_STOP)
+            | Some(&MIDI_SONG_POSITION_POINTER)
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiClockEvent {
+    Tick,
+    Start,
+    Continue,
+    Stop,
+    SongPosition { beats: f64 },
+}
+
+/// Follows an incoming MIDI clock, see the module docs.
+pub struct MidiClockFollower {
+    intervals: [f64; TEMPO_WINDOW],
+    num_intervals: usize,
+    next_interval: usize,
+    last_tick_at: Option<u64>,
+    tempo: Option<f64>,
+    is_running: bool,
+    /// The first tick after `Start`/`Continue` is at the current position
+    is_waiting_first_tick: bool,
+    position_ticks: u64,
+}
+
+impl Default for MidiClockFollower {
+    fn default() -> Self {
+        Self {
+            intervals: [0.0; TEMPO_WINDOW],
+            num_intervals: 0,
+            next_interval: 0,
+            last_tick_at: None,
+            tempo: None,
+            is_running: false,
+            is_waiting_first_tick: false,
+            position_ticks: 0,
+        }
+    }
+}
+
+impl MidiClockFollower {
+    /// Handle a MIDI message received at `timestamp` micro-seconds. Returns the clock event it
+    /// represents, if any.
+    pub fn accept(&mut self, timestamp: u64, bytes: &[u8]) -> Option<MidiClockEvent> {
+        match *bytes.first()? {
+            MIDI_CLOCK_TICK => {
+                if let Some(last_tick_at) = self.last_tick_at {
+                    self.push_interval(timestamp.saturating_sub(last_tick_at) as f64);
+                }
+                self.last_tick_at = Some(timestamp);
+                if self.is_running && !self.is_waiting_first_tick {
+                    self.position_ticks += 1;
+                }
+                self.is_waiting_first_tick = false;
+                Some(MidiClockEvent::Tick)
+            }
+            MIDI_CLOCK_START => {
+                self.is_running = true;
+                self.is_waiting_first_tick = true;
+                self.position_ticks = 0;
+                Some(MidiClockEvent::Start)
+            }
+            MIDI_CLOCK_CONTINUE => {
+                self.is_running = true;
+                self.is_waiting_first_tick = true;
+                Some(MidiClockEvent::Continue)
+            }
+            MIDI_CLOCK_STOP => {
+                self.is_running = false;
+                Some(MidiClockEvent::Stop)
+            }
+            MIDI_SONG_POSITION_POINTER if bytes.len() >= 3 => {
+                // Song position is in 16th notes, which are 6 clock ticks
+                let sixteenths = (bytes[1] as u64 & 0x7F) | ((bytes[2] as u64 & 0x7F) << 7);
+                self.position_ticks = sixteenths * 6;
+                Some(MidiClockEvent::SongPosition {
+                    beats: self.position_beats(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Estimated tempo, once enough clock ticks have been received
+    pub fn tempo(&self) -> Option<f64> {
+        self.tempo
+    }
+
+    /// Whether the clock source is playing, between `Start`/`Continue` and `Stop`
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    /// Position of the last clock tick in beats
+    pub fn position_beats(&self) -> f64 {
+        self.position_ticks as f64 / MIDI_CLOCK_PPQN as f64
+    }
+
+    fn push_interval(&mut self, interval: f64) {
+        let min_interval = 60_000_000.0 / (MAX_TEMPO * MIDI_CLOCK_PPQN as f64);
+        let max_interval = 60_000_000.0 / (MIN_TEMPO * MIDI_CLOCK_PPQN as f64);
+        if interval < min_interval || interval > max_interval {
+            return;
+        }
+
+        self.intervals[self.next_interval] = interval;
+        self.next_interval = (self.next_interval + 1) % TEMPO_WINDOW;
+        self.num_intervals = (self.num_intervals + 1).min(TEMPO_WINDOW);
+        // Wait for a few ticks before guessing
+        if self.num_intervals < MIDI_CLOCK_PPQN as usize / 4 {
+            return;
+        }
+
+        // Average intervals close to the median, so dropped or doubled ticks are ignored. Jitter
+        // cancels out over the window.
+        let mut window = self.intervals;
+        let window = &mut window[..self.num_intervals];
+        window.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+        let median = window[window.len() / 2];
+        let (sum, count) = window
+            .iter()
+            .filter(|interval| ((*interval - median) / median).abs() < OUTLIER_THRESHOLD)
+            .fold((0.0, 0), |(sum, count), interval| {
+                (sum + interval, count + 1)
+            });
+        let estimate = 60_000_000.0 / ((sum / count as f64) * MIDI_CLOCK_PPQN as f64);
+
+        self.tempo = Some(match self.tempo {
+            Some(tempo) if ((estimate - tempo) / tempo).abs() < TEMPO_JUMP_THRESHOLD => {
+                tempo + (estimate - tempo) * TEMPO_SMOOTHING
+            }
+            _ => estimate,
+        });
+    }
+}
+
+/// Snapshot of the play-head a MIDI clock is generated for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiTransportState {
+    pub tempo: Option<f64>,
+    pub is_playing: bool,
+    pub position_beats: f64,
+}
+
+/// Decides which clock & transport messages to send for a play-head, see
+/// [`MidiClockGenerator::poll`].
+#[derive(Default)]
+pub struct MidiClockGenerator {
+    was_playing: bool,
+    next_tick_at: Option<u64>,
+}
+
+impl MidiClockGenerator {
+    /// Emit all messages due at `now` micro-seconds, for the play-head `state`. Returns when this
+    /// should be called again.
+    ///
+    /// Clock ticks are sent continuously while there's a tempo, so followers can lock onto it before
+    /// the transport starts. Starting from the beginning sends `Start`, starting elsewhere sends
+    /// the song position and `Continue`.
+    pub fn poll(
+        &mut self,
+        now: u64,
+        state: MidiTransportState,
+        mut emit: impl FnMut(&[u8]),
+    ) -> Option<u64> {
+        if state.is_playing != self.was_playing {
+            self.was_playing = state.is_playing;
+            if state.is_playing {
+                let sixteenths = (state.position_beats.max(0.0) * 4.0).round() as u64;
+                if sixteenths == 0 {
+                    emit(&[MIDI_CLOCK_START]);
+                } else {
+                    let sixteenths = sixteenths.min(0x3FFF);
+                    emit(&[
+                        MIDI_SONG_POSITION_POINTER,
+                        (sixteenths & 0x7F) as u8,
+                        ((sixteenths >> 7) & 0x7F) as u8,
+                    ]);
+                    emit(&[MIDI_CLOCK_CONTINUE]);
+                }
+                // Ticks are restarted in phase with the transport
+                self.next_tick_at = Some(now);
+            } else {
+                emit(&[MIDI_CLOCK_STOP]);
+            }
+        }
+
+        let tempo = match state.tempo {
+            Some(tempo) if tempo > 0.0 => tempo,
+            _ => {
+                self.next_tick_at = None;
+                return None;
+            }
+        };
+        let tick_interval = (60_000_000.0 / (tempo * MIDI_CLOCK_PPQN as f64)) as u64;
+
+        let mut next_tick_at = self.next_tick_at.unwrap_or(now);
+        while next_tick_at <= now {
+            emit(&[MIDI_CLOCK_TICK]);
+            next_tick_at += tick_interval;
+        }
+        self.next_tick_at = Some(next_tick_at);
+        Some(next_tick_at)
+    }
+}
+
+/// Destination of generated MIDI clock messages
+pub trait MidiClockOutput {
+    fn send_clock(&mut self, message: &[u8]);
+}
+
+impl MidiClockOutput for midir::MidiOutputConnection {
+    fn send_clock(&mut self, message: &[u8]) {
+        if let Err(err) = self.send(message) {
+            log::error!("Failed to send MIDI clock message {}", err);
+        }
+    }
+}
+
+/// Send MIDI clock following the play-head `state` returns until `is_running` is unset.
+///
+/// This uses its own thread rather than the audio-thread, so sending is not bound to the audio
+/// buffer size. Ticks are scheduled against absolute deadlines, so sleep inaccuracy doesn't add up.
+pub fn spawn_midi_clock_thread(
+    state: impl Fn() -> MidiTransportState + Send + 'static,
+    mut output: impl MidiClockOutput + Send + 'static,
+    is_running: Shared<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name(String::from("midi-clock-thread"))
+        .spawn(move || {
+            let start = Instant::now();
+            let mut generator = MidiClockGenerator::default();
+            // Transport state is polled at least this often
+            let max_sleep = Duration::from_millis(5);
+
+            while is_running.load(Ordering::Relaxed) {
+                let now = start.elapsed().as_micros() as u64;
+                let next_deadline =
+                    generator.poll(now, state(), |message| output.send_clock(message));
+
+                let sleep = next_deadline
+                    .map(|deadline| Duration::from_micros(deadline.saturating_sub(now)))
+                    .unwrap_or(max_sleep)
+                    .min(max_sleep);
+                std::thread::sleep(sleep);
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed_ticks(follower: &mut MidiClockFollower, start: u64, interval: u64, count: u64) -> u64 {
+        let mut time = start;
+        for _ in 0..count {
+            follower.accept(time, &[MIDI_CLOCK_TICK]);
+            time += interval;
+        }
+        time
+    }
+
+    #[test]
+    fn test_is_midi_clock_message() {
+        assert!(is_midi_clock_message(&[MIDI_CLOCK_TICK]));
+        assert!(is_midi_clock_message(&[MIDI_SONG_POSITION_POINTER, 0, 0]));
+        assert!(!is_midi_clock_message(&[0x90, 60, 127]));
+        assert!(!is_midi_clock_message(&[]));
+    }
+
+    #[test]
+    fn test_follower_estimates_tempo() {
+        let mut follower = MidiClockFollower::default();
+        assert_eq!(follower.tempo(), None);
+        // 120bpm is 2 beats per second, 48 ticks per second
+        feed_ticks(&mut follower, 0, 20_833, 48);
+        assert!((follower.tempo().unwrap() - 120.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_follower_filters_jitter() {
+        let mut follower = MidiClockFollower::default();
+        let mut time = 0;
+        for i in 0..200 {
+            // Up to 2ms of jitter on each tick
+            let jitter = [0, 2_000, -1_500, 500, -2_000, 1_000][i % 6];
+            follower.accept((time as i64 + jitter) as u64, &[MIDI_CLOCK_TICK]);
+            time += 20_833;
+        }
+        assert!((follower.tempo().unwrap() - 120.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_follower_follows_tempo_changes() {
+        let mut follower = MidiClockFollower::default();
+        let time = feed_ticks(&mut follower, 0, 20_833, 48);
+        // 90bpm
+        feed_ticks(&mut follower, time, 27_778, 96);
+        assert!((follower.tempo().unwrap() - 90.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_follower_ignores_gaps() {
+        let mut follower = MidiClockFollower::default();
+        let time = feed_ticks(&mut follower, 0, 20_833, 48);
+        follower.accept(time + 10_000_000, &[MIDI_CLOCK_TICK]);
+        assert!((follower.tempo().unwrap() - 120.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_follower_ignores_dropped_ticks() {
+        let mut follower = MidiClockFollower::default();
+        let time = feed_ticks(&mut follower, 0, 20_833, 48);
+        // One tick got lost
+        feed_ticks(&mut follower, time + 20_833, 20_833, 4);
+        assert!((follower.tempo().unwrap() - 120.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_follower_transport_and_position() {
+        let mut follower = MidiClockFollower::default();
+        assert_eq!(
+            follower.accept(0, &[MIDI_CLOCK_START]),
+            Some(MidiClockEvent::Start)
+        );
+        assert!(follower.is_running());
+        // The first tick is beat 0
+        feed_ticks(&mut follower, 0, 20_833, 1);
+        assert!(follower.position_beats().abs() < f64::EPSILON);
+        feed_ticks(&mut follower, 0, 20_833, 36);
+        assert!((follower.position_beats() - 1.5).abs() < f64::EPSILON);
+
+        assert_eq!(
+            follower.accept(0, &[MIDI_CLOCK_STOP]),
+            Some(MidiClockEvent::Stop)
+        );
+        assert!(!follower.is_running());
+        feed_ticks(&mut follower, 0, 20_833, 24);
+        assert!((follower.position_beats() - 1.5).abs() < f64::EPSILON);
+
+        // 16th note 130 = 1 + 2 * 128
+        assert_eq!(
+            follower.accept(0, &[MIDI_SONG_POSITION_POINTER, 2, 1]),
+            Some(MidiClockEvent::SongPosition { beats: 32.5 })
+        );
+        assert_eq!(
+            follower.accept(0, &[MIDI_CLOCK_CONTINUE]),
+            Some(MidiClockEvent::Continue)
+        );
+        assert!(follower.is_running());
+        assert_eq!(follower.accept(0, &[0x90, 60, 100]), None);
+    }
+
+    #[test]
+    fn test_generator_sends_ticks_at_tempo() {
+        let mut generator = MidiClockGenerator::default();
+        let mut messages = vec![];
+        let state = MidiTransportState {
+            tempo: Some(120.0),
+            is_playing: false,
+            position_beats: 0.0,
+        };
+
+        let mut now = 0;
+        while now < 1_000_000 {
+            generator.poll(now, state, |message| messages.push(message.to_vec()));
+            now += 1_000;
+        }
+        assert_eq!(messages.len(), 48);
+        assert!(messages.iter().all(|message| message == &[MIDI_CLOCK_TICK]));
+    }
+
+    #[test]
+    fn test_generator_sends_transport() {
+        let mut generator = MidiClockGenerator::default();
+        let mut messages = vec![];
+        let mut state = MidiTransportState {
+            tempo: Some(120.0),
+            is_playing: true,
+            position_beats: 0.0,
+        };
+        generator.poll(0, state, |message| messages.push(message.to_vec()));
+        assert_eq!(
+            messages,
+            vec![vec![MIDI_CLOCK_START], vec![MIDI_CLOCK_TICK]]
+        );
+
+        messages.clear();
+        state.is_playing = false;
+        generator.poll(1_000, state, |message| messages.push(message.to_vec()));
+        assert_eq!(messages, vec![vec![MIDI_CLOCK_STOP]]);
+
+        messages.clear();
+        state.is_playing = true;
+        state.position_beats = 32.5;
+        generator.poll(2_000, state, |message| messages.push(message.to_vec()));
+        assert_eq!(
+            messages,
+            vec![
+                vec![MIDI_SONG_POSITION_POINTER, 2, 1],
+                vec![MIDI_CLOCK_CONTINUE],
+                vec![MIDI_CLOCK_TICK]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generator_without_tempo_sends_no_ticks() {
+        let mut generator = MidiClockGenerator::default();
+        let state = MidiTransportState {
+            tempo: None,
+            is_playing: false,
+            position_beats: 0.0,
+        };
+        let mut count = 0;
+        assert_eq!(generator.poll(0, state, |_| count += 1), None);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_midi_clock_thread_sends_to_output() {
+        use std::sync::{Arc, Mutex};
+
+        struct SharedOutput(Arc<Mutex<Vec<Vec<u8>>>>);
+
+        impl MidiClockOutput for SharedOutput {
+            fn send_clock(&mut self, message: &[u8]) {
+                self.0.lock().unwrap().push(message.to_vec());
+            }
+        }
+
+        let messages = Arc::new(Mutex::new(vec![]));
+        let is_running = audio_garbage_collector::make_shared(AtomicBool::new(true));
+        let thread = spawn_midi_clock_thread(
+            || MidiTransportState {
+                tempo: Some(120.0),
+                is_playing: true,
+                position_beats: 0.0,
+            },
+            SharedOutput(messages.clone()),
+            is_running.clone(),
+        )
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        is_running.store(false, Ordering::Relaxed);
+        thread.join().unwrap();
+
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages[0], vec![MIDI_CLOCK_START]);
+        assert!(messages.len() > 2);
+    }
+}
@@ -226,11 +226,16 @@ impl MidiMessageLike for MidiMessageEntry {
     fn bytes(&self) -> Option<&[u8]> {
         Some(&self.message_data)
     }
+
+    fn timestamp(&self) -> Option<u64> {
+        Some(self.timestamp)
+    }
 }
 
 /// A wrapper type to wrap messages. Messages must be 3 bytes in length (SysEx will be dropped).
 pub struct MidiMessageWrapper {
     pub message_data: [u8; 3],
+    /// Time the message was received at in micro-seconds, as reported by `midir`
     pub timestamp: u64,
 }
 
@@ -272,7 +277,7 @@ fn midi_callback(timestamp: u64, bytes: &[u8], context: &mut MidiCallbackContext
 
 #[cfg(test)]
 mod test {
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
 
     use audio_garbage_collector::make_shared;
 
@@ -318,7 +323,7 @@ mod test {
             MidiCallbackContext::new(audio_garbage_collector::handle().clone(), queue.clone());
         let bytes: [u8; 4] = [10, 20, 30, 40];
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             midi_callback(0, &bytes, &mut context);
         });
 
@@ -54,7 +54,7 @@
 //! The events are forwarded onto a lock-free queue (`atomic_queue`).
 //!
 //! On the `audio_thread` and `vst` modules, past construction methods that should be called on the audio-thread will not
-//! (de)-allocate. This is tested using the `assert_no_alloc` crate.
+//! (de)-allocate. This is tested using `audio_processor_testing_helpers::realtime`.
 //!
 //! In addition, `basedrop` / `audio_garbage_collector` are used to prevent de-allocation from happening on the
 //! audio-thread.
@@ -78,6 +78,7 @@
 //!   - This is enough to add MIDI to a standalone [`audio_processor_traits::MidiEventHandler`]
 //! * [`vst::MidiVSTConverter`] If you're implementing a host, you'll have to convert messages onto
 //!   the VST API
+//! * [`clock`] Follow an incoming MIDI clock or send one to external gear
 //!
 //! ```
 //! fn example() {
@@ -132,6 +133,8 @@
 
 /// Audio-thread handling of messages
 pub mod audio_thread;
+/// MIDI clock following & generation
+pub mod clock;
 /// Defaults
 pub mod constants;
 /// Hosting of MIDI
@@ -141,5 +144,5 @@ pub mod host;
 /// VST API conversion
 pub mod vst;
 
-#[cfg(all(test, debug_assertions))]
+#[cfg(test)]
 pub(crate) mod test_util;
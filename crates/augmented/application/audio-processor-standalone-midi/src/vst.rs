@@ -168,7 +168,7 @@ mod test {
     use audio_processor_traits::MidiMessageLike;
 
     use crate::host::MidiMessageWrapper;
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
 
     use super::*;
 
@@ -203,7 +203,7 @@ mod test {
             )),
         ];
 
-        let events = assert_no_alloc(|| converter.accept(&buffer));
+        let events = assert_realtime_safe(|| converter.accept(&buffer));
         assert_eq!(events.num_events, 2);
 
         let event = events.events[0];
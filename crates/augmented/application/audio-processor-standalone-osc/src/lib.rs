@@ -20,91 +20,25 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use std::collections::HashMap;
-use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
-use std::time::Duration;
+//! Small OSC server used by the standalone applications.
+//!
+//! * [`OscMap`] maps OSC addresses to handlers
+//! * [`OscServer`] listens on a UDP port and dispatches messages and bundles to an [`OscMap`]
+//! * [`OscSender`] sends messages back to registered clients, for example state feedback to a
+//!   control surface
 
-use rosc::{OscMessage, OscPacket};
-use thiserror::Error;
+pub use rosc;
+pub use rosc::{OscBundle, OscMessage, OscPacket, OscTime, OscType};
 
-pub type OscHandler<C> = Box<dyn Fn(&C, OscMessage) + Send>;
+pub use map::{OscHandler, OscMap, OscSourceHandler};
+pub use scheduler::timetag_deadline;
+pub use sender::OscSender;
+pub use server::{OscServer, OscServerError, OscServerOptions};
 
-pub struct OscMap<C> {
-    map: HashMap<String, OscHandler<C>>,
-}
-
-impl<C> Default for OscMap<C> {
-    fn default() -> Self {
-        Self {
-            map: HashMap::new(),
-        }
-    }
-}
-
-impl<C> OscMap<C> {
-    pub fn add(&mut self, addr: impl Into<String>, handler: OscHandler<C>) {
-        self.map.insert(addr.into(), handler);
-    }
-
-    pub fn get(&self, addr: &str) -> Option<&OscHandler<C>> {
-        self.map.get(addr)
-    }
-}
-
-#[derive(Error, Debug)]
-pub enum OscServerError {
-    #[error("IO Error, failed to open socket")]
-    IOError(#[from] std::io::Error),
-}
-
-pub struct OscServer<C> {
-    context: C,
-    map: OscMap<C>,
-}
-
-impl<C> OscServer<C> {
-    pub fn new(context: C, map: OscMap<C>) -> Self {
-        Self { context, map }
-    }
-
-    pub fn start(&self) -> Result<(), OscServerError> {
-        let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1449);
-        let sock = UdpSocket::bind(addr)?;
-        sock.set_read_timeout(Some(Duration::from_millis(500)))?;
-        let mut buf = [0u8; rosc::decoder::MTU];
-
-        log::info!("Listening...");
-        loop {
-            match sock.recv_from(&mut buf) {
-                Ok((size, addr)) => {
-                    log::info!("Received packet with size {} from: {}", size, addr);
-                    if let Ok(packet) = rosc::decoder::decode(&buf[..size]) {
-                        self.handle_packet(packet);
-                    }
-                }
-                Err(err) => {
-                    log::debug!("Failed to recv from OSC socket {}", err);
-                }
-            }
-        }
-    }
-
-    fn handle_packet(&self, packet: OscPacket) {
-        match packet {
-            OscPacket::Message(msg) => {
-                if let Some(handler) = self.map.get(&msg.addr) {
-                    handler(&self.context, msg);
-                } else {
-                    log::debug!("OSC address: {}", msg.addr);
-                    log::debug!("OSC arguments: {:?}", msg.args);
-                }
-            }
-            OscPacket::Bundle(bundle) => {
-                log::debug!("OSC Bundle: {:?}", bundle);
-            }
-        }
-    }
-}
+mod map;
+mod scheduler;
+mod sender;
+mod server;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,111 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use rosc::OscMessage;
+
+pub type OscHandler<C> = Box<dyn Fn(&C, OscMessage) + Send>;
+
+/// A handler which also receives the address of the client that sent the message. Used to reply
+/// to, or register, clients.
+pub type OscSourceHandler<C> = Box<dyn Fn(&C, OscMessage, SocketAddr) + Send>;
+
+/// Maps OSC addresses to handlers. Addresses are matched exactly.
+pub struct OscMap<C> {
+    map: HashMap<String, OscSourceHandler<C>>,
+}
+
+impl<C> Default for OscMap<C> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+}
+
+impl<C: 'static> OscMap<C> {
+    pub fn add(&mut self, addr: impl Into<String>, handler: OscHandler<C>) {
+        self.add_with_source(addr, Box::new(move |context, msg, _| handler(context, msg)));
+    }
+
+    pub fn add_with_source(&mut self, addr: impl Into<String>, handler: OscSourceHandler<C>) {
+        self.map.insert(addr.into(), handler);
+    }
+}
+
+impl<C> OscMap<C> {
+    pub fn get(&self, addr: &str) -> Option<&OscSourceHandler<C>> {
+        self.map.get(addr)
+    }
+
+    /// All registered addresses, in no particular order
+    pub fn addresses(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(|addr| addr.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_add_handlers() {
+        let mut map: OscMap<AtomicUsize> = OscMap::default();
+        map.add(
+            "/volume",
+            Box::new(|count, _msg| {
+                count.fetch_add(1, Ordering::Relaxed);
+            }),
+        );
+        map.add_with_source(
+            "/register",
+            Box::new(|count, _msg, source| {
+                count.fetch_add(source.port() as usize, Ordering::Relaxed);
+            }),
+        );
+        assert_eq!(map.len(), 2);
+        assert!(map.get("/unknown").is_none());
+
+        let count = AtomicUsize::new(0);
+        let source = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 10));
+        let msg = OscMessage {
+            addr: "/volume".to_string(),
+            args: vec![],
+        };
+        map.get("/volume").unwrap()(&count, msg.clone(), source);
+        map.get("/register").unwrap()(&count, msg, source);
+        assert_eq!(count.load(Ordering::Relaxed), 11);
+    }
+}
@@ -0,0 +1,224 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use rosc::{OscMessage, OscTime};
+
+/// Seconds between the OSC epoch (1900-01-01) and the UNIX epoch
+const UNIX_OFFSET_SECONDS: u32 = 2_208_988_800;
+/// Maximum number of messages held by the scheduler; further messages are dropped
+pub const MAX_SCHEDULED_MESSAGES: usize = 1024;
+/// Messages scheduled further than this into the future are dropped
+pub const MAX_SCHEDULE_AHEAD: Duration = Duration::from_secs(60);
+
+/// Returns when a bundle with this timetag should be dispatched, or `None` if it should be
+/// dispatched immediately.
+///
+/// The special `(0, 1)` timetag means "immediately". Timetags before the UNIX epoch can't be
+/// represented as a `SystemTime` and are also treated as immediate.
+pub fn timetag_deadline(timetag: OscTime) -> Option<SystemTime> {
+    if timetag.seconds < UNIX_OFFSET_SECONDS {
+        None
+    } else {
+        Some(timetag.into())
+    }
+}
+
+struct ScheduledMessage {
+    deadline: SystemTime,
+    sequence: usize,
+    source: SocketAddr,
+    message: OscMessage,
+}
+
+impl PartialEq for ScheduledMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline && self.sequence == other.sequence
+    }
+}
+
+impl Eq for ScheduledMessage {}
+
+impl PartialOrd for ScheduledMessage {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledMessage {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.deadline, self.sequence).cmp(&(other.deadline, other.sequence))
+    }
+}
+
+/// Holds messages from bundles with future timetags until they're due. Messages with the same
+/// deadline are dispatched in the order they were received.
+///
+/// At most [`MAX_SCHEDULED_MESSAGES`] messages are held, up to [`MAX_SCHEDULE_AHEAD`] into the
+/// future, so a misbehaving client can't grow the queue without bounds.
+#[derive(Default)]
+pub struct BundleScheduler {
+    queue: BinaryHeap<Reverse<ScheduledMessage>>,
+    sequence: usize,
+}
+
+impl BundleScheduler {
+    /// Hold `message` until `deadline`. Returns false & drops the message if the queue is full or
+    /// the deadline is too far after `now`.
+    pub fn schedule(
+        &mut self,
+        now: SystemTime,
+        deadline: SystemTime,
+        source: SocketAddr,
+        message: OscMessage,
+    ) -> bool {
+        if self.queue.len() >= MAX_SCHEDULED_MESSAGES {
+            log::warn!(
+                "OSC scheduler is full, dropping {} from {}",
+                message.addr,
+                source
+            );
+            return false;
+        }
+        if deadline > now + MAX_SCHEDULE_AHEAD {
+            log::warn!(
+                "OSC bundle is scheduled too far ahead, dropping {} from {}",
+                message.addr,
+                source
+            );
+            return false;
+        }
+
+        self.sequence += 1;
+        self.queue.push(Reverse(ScheduledMessage {
+            deadline,
+            sequence: self.sequence,
+            source,
+            message,
+        }));
+        true
+    }
+
+    /// Pop the next message whose deadline is before or at `now`
+    pub fn pop_due(&mut self, now: SystemTime) -> Option<(SocketAddr, OscMessage)> {
+        if self.next_deadline()? > now {
+            return None;
+        }
+        self.queue
+            .pop()
+            .map(|Reverse(scheduled)| (scheduled.source, scheduled.message))
+    }
+
+    pub fn next_deadline(&self) -> Option<SystemTime> {
+        self.queue
+            .peek()
+            .map(|Reverse(scheduled)| scheduled.deadline)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+    use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::time::Duration;
+
+    use super::*;
+
+    fn message(addr: &str) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args: vec![],
+        }
+    }
+
+    #[test]
+    fn test_immediate_timetag() {
+        assert_eq!(timetag_deadline(OscTime::from((0, 1))), None);
+        let now = SystemTime::now();
+        let deadline = timetag_deadline(OscTime::try_from(now).unwrap()).unwrap();
+        let difference = deadline
+            .duration_since(now)
+            .unwrap_or_else(|err| err.duration());
+        assert!(difference < Duration::from_micros(1));
+    }
+
+    #[test]
+    fn test_pop_due_in_deadline_order() {
+        let source = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1449));
+        let now = SystemTime::now();
+        let mut scheduler = BundleScheduler::default();
+        scheduler.schedule(now, now + Duration::from_secs(2), source, message("/c"));
+        scheduler.schedule(now, now + Duration::from_secs(1), source, message("/a"));
+        scheduler.schedule(now, now + Duration::from_secs(1), source, message("/b"));
+
+        assert_eq!(scheduler.len(), 3);
+        assert!(scheduler.pop_due(now).is_none());
+        assert_eq!(
+            scheduler.next_deadline(),
+            Some(now + Duration::from_secs(1))
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(scheduler.pop_due(later).unwrap().1.addr, "/a");
+        assert_eq!(scheduler.pop_due(later).unwrap().1.addr, "/b");
+        assert!(scheduler.pop_due(later).is_none());
+
+        let later = now + Duration::from_secs(3);
+        assert_eq!(scheduler.pop_due(later).unwrap().1.addr, "/c");
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_schedule_drops_messages_past_the_limits() {
+        let source = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1449));
+        let now = SystemTime::now();
+        let mut scheduler = BundleScheduler::default();
+
+        assert!(!scheduler.schedule(
+            now,
+            now + MAX_SCHEDULE_AHEAD + Duration::from_secs(1),
+            source,
+            message("/far")
+        ));
+        assert!(scheduler.is_empty());
+
+        for _ in 0..MAX_SCHEDULED_MESSAGES {
+            assert!(scheduler.schedule(now, now + Duration::from_secs(1), source, message("/a")));
+        }
+        assert!(!scheduler.schedule(now, now + Duration::from_secs(1), source, message("/b")));
+        assert_eq!(scheduler.len(), MAX_SCHEDULED_MESSAGES);
+    }
+}
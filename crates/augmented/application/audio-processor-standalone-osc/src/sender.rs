@@ -0,0 +1,146 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+use rosc::{OscMessage, OscPacket, OscType};
+
+use crate::OscServerError;
+
+/// Sends OSC packets to a set of registered clients.
+///
+/// Clones share the same socket and client list, so one clone may be handed to the OSC handlers
+/// registering clients while another sends feedback.
+#[derive(Clone)]
+pub struct OscSender {
+    socket: Arc<UdpSocket>,
+    clients: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl OscSender {
+    /// Bind a sender socket on an ephemeral port
+    pub fn bind() -> Result<Self, OscServerError> {
+        let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+        Ok(Self {
+            socket: Arc::new(socket),
+            clients: Arc::new(Mutex::new(vec![])),
+        })
+    }
+
+    /// Start sending packets to `addr`. Registering the same client twice has no effect.
+    pub fn register_client(&self, addr: SocketAddr) {
+        let mut clients = self.clients.lock().unwrap();
+        if !clients.contains(&addr) {
+            log::info!("Registered OSC client {}", addr);
+            clients.push(addr);
+        }
+    }
+
+    pub fn unregister_client(&self, addr: &SocketAddr) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|client| client != addr);
+    }
+
+    pub fn clients(&self) -> Vec<SocketAddr> {
+        self.clients.lock().unwrap().clone()
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.lock().unwrap().is_empty()
+    }
+
+    pub fn send_to(&self, addr: SocketAddr, packet: &OscPacket) -> Result<(), OscServerError> {
+        let bytes = rosc::encoder::encode(packet)?;
+        self.socket.send_to(&bytes, addr)?;
+        Ok(())
+    }
+
+    /// Send a packet to all registered clients. A client failing doesn't stop the packet from
+    /// being sent to the others; the last error is returned.
+    pub fn send(&self, packet: &OscPacket) -> Result<(), OscServerError> {
+        let bytes = rosc::encoder::encode(packet)?;
+        let mut result = Ok(());
+        for client in self.clients() {
+            if let Err(err) = self.socket.send_to(&bytes, client) {
+                result = Err(err.into());
+            }
+        }
+        result
+    }
+
+    /// Send a message to all registered clients
+    pub fn send_message(
+        &self,
+        addr: impl Into<String>,
+        args: Vec<OscType>,
+    ) -> Result<(), OscServerError> {
+        self.send(&OscPacket::Message(OscMessage {
+            addr: addr.into(),
+            args,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_register_clients() {
+        let sender = OscSender::bind().unwrap();
+        let client = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9000));
+        assert!(!sender.has_clients());
+        sender.register_client(client);
+        sender.register_client(client);
+        assert_eq!(sender.clients(), vec![client]);
+        sender.unregister_client(&client);
+        assert!(!sender.has_clients());
+    }
+
+    #[test]
+    fn test_send_message_to_clients() {
+        let receiver = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let sender = OscSender::bind().unwrap();
+        sender.register_client(receiver.local_addr().unwrap());
+
+        sender
+            .send_message("/tempo", vec![OscType::Float(120.0)])
+            .unwrap();
+
+        let mut buf = [0u8; rosc::decoder::MTU];
+        let (size, _) = receiver.recv_from(&mut buf).unwrap();
+        let packet = rosc::decoder::decode(&buf[..size]).unwrap();
+        assert_eq!(
+            packet,
+            OscPacket::Message(OscMessage {
+                addr: "/tempo".to_string(),
+                args: vec![OscType::Float(120.0)]
+            })
+        );
+    }
+}
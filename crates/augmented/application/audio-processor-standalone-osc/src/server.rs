@@ -0,0 +1,269 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, SystemTime};
+
+use rosc::{OscMessage, OscPacket};
+use thiserror::Error;
+
+use crate::scheduler::{timetag_deadline, BundleScheduler};
+use crate::OscMap;
+
+#[derive(Error, Debug)]
+pub enum OscServerError {
+    #[error("IO Error, failed to open socket")]
+    IOError(#[from] std::io::Error),
+    #[error("Failed to encode OSC packet")]
+    EncodeError(#[from] rosc::OscError),
+}
+
+pub struct OscServerOptions {
+    /// UDP port the server listens on. Defaults to 1449.
+    pub port: u16,
+    /// Maximum time the server blocks waiting for packets before dispatching scheduled bundles.
+    /// Defaults to 500ms.
+    pub read_timeout: Duration,
+}
+
+impl Default for OscServerOptions {
+    fn default() -> Self {
+        Self {
+            port: 1449,
+            read_timeout: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Listens for OSC packets and dispatches them to the handlers in an [`OscMap`].
+///
+/// Bundles are unpacked recursively. Messages in bundles with a future timetag are dispatched
+/// once it's reached, other messages are dispatched as soon as they're received.
+pub struct OscServer<C> {
+    context: C,
+    map: OscMap<C>,
+    options: OscServerOptions,
+}
+
+impl<C> OscServer<C> {
+    pub fn new(context: C, map: OscMap<C>) -> Self {
+        Self::with_options(context, map, OscServerOptions::default())
+    }
+
+    pub fn with_options(context: C, map: OscMap<C>, options: OscServerOptions) -> Self {
+        Self {
+            context,
+            map,
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &OscServerOptions {
+        &self.options
+    }
+
+    pub fn start(&self) -> Result<(), OscServerError> {
+        let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, self.options.port);
+        let sock = UdpSocket::bind(addr)?;
+        let mut buf = [0u8; rosc::decoder::MTU];
+        let mut scheduler = BundleScheduler::default();
+
+        log::info!("Listening on port {}...", self.options.port);
+        loop {
+            sock.set_read_timeout(Some(self.next_timeout(&scheduler, SystemTime::now())))?;
+            match sock.recv_from(&mut buf) {
+                Ok((size, addr)) => {
+                    log::info!("Received packet with size {} from: {}", size, addr);
+                    match rosc::decoder::decode(&buf[..size]) {
+                        Ok(packet) => self.handle_packet(
+                            &mut scheduler,
+                            addr,
+                            packet,
+                            SystemTime::now(),
+                            None,
+                        ),
+                        Err(err) => {
+                            log::warn!("Failed to decode OSC packet {}", err);
+                        }
+                    }
+                }
+                Err(err) => {
+                    log::debug!("Failed to recv from OSC socket {}", err);
+                }
+            }
+            self.dispatch_due(&mut scheduler, SystemTime::now());
+        }
+    }
+
+    fn next_timeout(&self, scheduler: &BundleScheduler, now: SystemTime) -> Duration {
+        let timeout = scheduler
+            .next_deadline()
+            .map(|deadline| {
+                deadline
+                    .duration_since(now)
+                    .unwrap_or_default()
+                    .min(self.options.read_timeout)
+            })
+            .unwrap_or(self.options.read_timeout);
+        // A zero read timeout is rejected by the socket
+        timeout.max(Duration::from_millis(1))
+    }
+
+    /// Dispatch or schedule a packet. Messages are held until the latest deadline of the bundles
+    /// enclosing them.
+    fn handle_packet(
+        &self,
+        scheduler: &mut BundleScheduler,
+        source: SocketAddr,
+        packet: OscPacket,
+        now: SystemTime,
+        deadline: Option<SystemTime>,
+    ) {
+        match packet {
+            OscPacket::Message(msg) => match deadline {
+                Some(deadline) if deadline > now => {
+                    scheduler.schedule(now, deadline, source, msg);
+                }
+                _ => self.handle_message(source, msg),
+            },
+            OscPacket::Bundle(bundle) => {
+                log::debug!("OSC Bundle: {:?}", bundle);
+                let deadline = timetag_deadline(bundle.timetag).max(deadline);
+                for packet in bundle.content {
+                    self.handle_packet(scheduler, source, packet, now, deadline);
+                }
+            }
+        }
+    }
+
+    fn dispatch_due(&self, scheduler: &mut BundleScheduler, now: SystemTime) {
+        while let Some((source, msg)) = scheduler.pop_due(now) {
+            self.handle_message(source, msg);
+        }
+    }
+
+    fn handle_message(&self, source: SocketAddr, msg: OscMessage) {
+        if let Some(handler) = self.map.get(&msg.addr) {
+            handler(&self.context, msg, source);
+        } else {
+            log::debug!("OSC address: {}", msg.addr);
+            log::debug!("OSC arguments: {:?}", msg.args);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+    use std::sync::Mutex;
+
+    use rosc::{OscBundle, OscTime};
+
+    use super::*;
+
+    fn recording_server() -> OscServer<Mutex<Vec<String>>> {
+        let mut map: OscMap<Mutex<Vec<String>>> = OscMap::default();
+        for addr in ["/a", "/b", "/c"] {
+            map.add(
+                addr,
+                Box::new(|received, msg| received.lock().unwrap().push(msg.addr)),
+            );
+        }
+        OscServer::new(Mutex::new(vec![]), map)
+    }
+
+    fn message(addr: &str) -> OscPacket {
+        OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: vec![],
+        })
+    }
+
+    fn bundle(timetag: OscTime, content: Vec<OscPacket>) -> OscPacket {
+        OscPacket::Bundle(OscBundle { timetag, content })
+    }
+
+    fn source() -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9000))
+    }
+
+    #[test]
+    fn test_default_options() {
+        let server = recording_server();
+        assert_eq!(server.options().port, 1449);
+    }
+
+    #[test]
+    fn test_handle_message() {
+        let server = recording_server();
+        let mut scheduler = BundleScheduler::default();
+        let now = SystemTime::now();
+        server.handle_packet(&mut scheduler, source(), message("/a"), now, None);
+        server.handle_packet(&mut scheduler, source(), message("/unknown"), now, None);
+        assert_eq!(*server.context.lock().unwrap(), vec!["/a"]);
+    }
+
+    #[test]
+    fn test_immediate_bundles_are_dispatched_in_order() {
+        let server = recording_server();
+        let mut scheduler = BundleScheduler::default();
+        let immediate = OscTime::from((0, 1));
+        let packet = bundle(
+            immediate,
+            vec![
+                message("/a"),
+                bundle(immediate, vec![message("/b")]),
+                message("/c"),
+            ],
+        );
+        server.handle_packet(&mut scheduler, source(), packet, SystemTime::now(), None);
+        assert!(scheduler.is_empty());
+        assert_eq!(*server.context.lock().unwrap(), vec!["/a", "/b", "/c"]);
+    }
+
+    #[test]
+    fn test_future_bundles_are_scheduled() {
+        let server = recording_server();
+        let mut scheduler = BundleScheduler::default();
+        let now = SystemTime::now();
+        let in_one_second = OscTime::try_from(now + Duration::from_secs(1)).unwrap();
+        let in_two_seconds = OscTime::try_from(now + Duration::from_secs(2)).unwrap();
+        let packet = bundle(
+            in_two_seconds,
+            vec![message("/b"), bundle(in_one_second, vec![message("/c")])],
+        );
+        server.handle_packet(&mut scheduler, source(), message("/a"), now, None);
+        server.handle_packet(&mut scheduler, source(), packet, now, None);
+        assert_eq!(scheduler.len(), 2);
+        assert_eq!(*server.context.lock().unwrap(), vec!["/a"]);
+
+        let timeout = server.next_timeout(&scheduler, now);
+        assert!(timeout <= server.options().read_timeout);
+        assert!(timeout >= Duration::from_millis(1));
+
+        server.dispatch_due(&mut scheduler, now + Duration::from_secs(1));
+        assert_eq!(*server.context.lock().unwrap(), vec!["/a"]);
+        // The nested bundle can't be dispatched before the enclosing one
+        server.dispatch_due(&mut scheduler, now + Duration::from_secs(3));
+        assert_eq!(*server.context.lock().unwrap(), vec!["/a", "/b", "/c"]);
+    }
+}
@@ -192,7 +192,8 @@ fn audio_thread_run_processor<D: DeviceTrait>(
         let errors_tx = errors_tx.clone();
 
         move || -> Result<(Option<D::Stream>, D::Stream), AudioThreadError> {
-            let buffer = ringbuf::RingBuffer::new(buffer_size * 10);
+            // Room for 5 blocks of input
+            let buffer = ringbuf::RingBuffer::new(buffer_size * num_input_channels.max(2) * 5);
             let (producer, consumer) = buffer.split();
             let input_stream = input_tuple
                 .map(|(input_device, input_config)| {
@@ -66,6 +66,20 @@ fn device_name(options: &StandaloneOptions, mode: AudioIOMode) -> Option<&String
     }
 }
 
+fn requested_channels(options: &StandaloneOptions, mode: AudioIOMode) -> Option<usize> {
+    match mode {
+        AudioIOMode::Input => options.input_channels,
+        AudioIOMode::Output => options.output_channels,
+    }
+}
+
+/// Pick the number of channels to open a device with. Falls back to stereo when no count was
+/// requested and to the most channels the device supports if it has fewer.
+fn negotiate_channels(requested: Option<usize>, max_supported_channels: u16) -> u16 {
+    let requested = requested.unwrap_or(2).min(u16::MAX as usize) as u16;
+    requested.min(max_supported_channels).max(1)
+}
+
 fn default_device<Host: HostTrait>(host: &Host, mode: AudioIOMode) -> Option<Host::Device> {
     match mode {
         AudioIOMode::Input => host.default_input_device(),
@@ -124,14 +138,12 @@ fn configure_device<Host: HostTrait>(
 
     log::debug!("Listing supported configs");
     let supported_configs = supported_configs(&device, mode)?;
-    let mut supports_stereo = false;
+    let mut max_supported_channels = 0;
     let mut supports_buffer_size = false;
     let mut supports_sample_rate = false;
     for config in supported_configs {
         log::debug!("  Supported config: {:?}", config);
-        if config.channels() > 1 {
-            supports_stereo = true;
-        }
+        max_supported_channels = max_supported_channels.max(config.channels());
         if let SupportedBufferSize::Range { min, max } = config.buffer_size() {
             let buffer_size = buffer_size as u32;
             if buffer_size >= *min && buffer_size <= *max {
@@ -147,7 +159,7 @@ fn configure_device<Host: HostTrait>(
     log::debug!("Negotiating default configuration");
     let config = default_config(&device, mode)?;
     let mut config: StreamConfig = config.into();
-    config.channels = if supports_stereo { 2 } else { 1 };
+    config.channels = negotiate_channels(requested_channels(options, mode), max_supported_channels);
     config.sample_rate = if supports_sample_rate {
         SampleRate(sample_rate as u32)
     } else {
@@ -242,6 +254,33 @@ mod test {
         assert_eq!(name.unwrap(), "output-name");
     }
 
+    #[test]
+    fn test_negotiate_channels_defaults_to_stereo() {
+        assert_eq!(negotiate_channels(None, 8), 2);
+        assert_eq!(negotiate_channels(None, 1), 1);
+        assert_eq!(negotiate_channels(None, 0), 1);
+    }
+
+    #[test]
+    fn test_negotiate_channels_opens_requested_channels() {
+        let options = StandaloneOptions {
+            output_channels: Some(8),
+            ..StandaloneOptions::default()
+        };
+        assert_eq!(
+            negotiate_channels(requested_channels(&options, AudioIOMode::Output), 16),
+            8
+        );
+        assert_eq!(
+            negotiate_channels(requested_channels(&options, AudioIOMode::Output), 4),
+            4
+        );
+        assert_eq!(
+            negotiate_channels(requested_channels(&options, AudioIOMode::Input), 4),
+            2
+        );
+    }
+
     #[test]
     fn test_list_devices_calls_host_input_devices() {
         let mut host = MockHost::default();
@@ -146,22 +146,31 @@ fn output_stream_with_context<SP: StandaloneProcessor>(context: OutputStreamFram
     };
 
     for sample_num in 0..audio_buffer.num_samples() {
-        if num_input_channels == num_output_channels {
-            for channel_num in 0..audio_buffer.num_channels() {
+        if num_input_channels == 1 && num_output_channels > 1 {
+            // Mono inputs are copied onto every output channel
+            if let Some(input_sample) = consumer.pop() {
+                for sample in 0..audio_buffer.num_channels() {
+                    let sample = &mut audio_buffer.channel_mut(sample)[sample_num];
+                    *sample = input_sample
+                }
+            } else {
+                on_under_run();
+            }
+        } else {
+            // Input channels past the number of output channels are dropped & output channels
+            // past the number of input channels are silent
+            for channel_num in 0..num_input_channels {
                 if let Some(input_sample) = consumer.pop() {
-                    audio_buffer.channel_mut(channel_num)[sample_num] = input_sample;
+                    if channel_num < num_output_channels {
+                        audio_buffer.channel_mut(channel_num)[sample_num] = input_sample;
+                    }
                 } else {
                     on_under_run();
                 }
             }
-        } else if let Some(input_sample) = consumer.pop() {
-            // This only works if num_input_channels == 1
-            for sample in 0..audio_buffer.num_channels() {
-                let sample = &mut audio_buffer.channel_mut(sample)[sample_num];
-                *sample = input_sample
+            for channel_num in num_input_channels..num_output_channels {
+                audio_buffer.channel_mut(channel_num)[sample_num] = 0.0;
             }
-        } else {
-            on_under_run();
         }
     }
 
@@ -233,4 +242,62 @@ mod test {
             [0.0, 2.0, 4.0, 6.0, 8.0, 10.0, 12.0, 14.0, 16.0, 18.0]
         )
     }
+
+    #[test]
+    fn test_tick_output_stream_with_different_channel_counts() {
+        struct MockProcessor {}
+        impl AudioProcessor for MockProcessor {
+            type SampleType = f32;
+
+            fn process(&mut self, _context: &mut AudioContext, _data: &mut AudioBuffer<f32>) {}
+        }
+
+        let buf = ringbuf::RingBuffer::new(12);
+        let (mut producer, mut consumer) = buf.split();
+        let mut processor: StandaloneAudioOnlyProcessor<MockProcessor> =
+            StandaloneAudioOnlyProcessor::new(MockProcessor {}, Default::default());
+
+        // 3 frames of 4 input channels
+        for i in 0..12 {
+            producer.push(i as f32).expect("Pushing sample failed");
+        }
+
+        // 3 frames of 2 output channels
+        let mut data = [1.0; 6];
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 3);
+        output_stream_with_context(OutputStreamFrameContext {
+            processor: &mut processor,
+            consumer: &mut consumer,
+            num_output_channels: 2,
+            num_input_channels: 4,
+            #[cfg(feature = "midi")]
+            midi_context: None,
+            data: &mut data,
+            audio_context: &mut Default::default(),
+            audio_buffer: &mut buffer,
+        });
+        assert_eq!(data, [0.0, 1.0, 4.0, 5.0, 8.0, 9.0]);
+
+        // 2 frames of 2 input channels
+        for i in 0..4 {
+            producer.push(i as f32).expect("Pushing sample failed");
+        }
+
+        // 2 frames of 4 output channels
+        let mut data = [1.0; 8];
+        buffer.resize(4, 2);
+        output_stream_with_context(OutputStreamFrameContext {
+            processor: &mut processor,
+            consumer: &mut consumer,
+            num_output_channels: 4,
+            num_input_channels: 2,
+            #[cfg(feature = "midi")]
+            midi_context: None,
+            data: &mut data,
+            audio_context: &mut Default::default(),
+            audio_buffer: &mut buffer,
+        });
+        assert_eq!(data, [0.0, 1.0, 0.0, 0.0, 2.0, 3.0, 0.0, 0.0]);
+    }
 }
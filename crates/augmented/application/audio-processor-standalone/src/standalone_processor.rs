@@ -55,6 +55,12 @@ pub struct StandaloneOptions {
     pub input_device: Option<String>,
     /// If set starting this processor will attempt to find this device and use it
     pub output_device: Option<String>,
+    /// Number of input channels to open. Defaults to stereo, devices with fewer channels will use
+    /// all their channels
+    pub input_channels: Option<usize>,
+    /// Number of output channels to open. Defaults to stereo, devices with fewer channels will use
+    /// all their channels
+    pub output_channels: Option<usize>,
     /// If set and running in a VST and the VST uses the generated GUI, this will be used
     /// to build a generic GUI over the generic audio processor handle.
     ///
@@ -68,6 +74,8 @@ impl Default for StandaloneOptions {
             accepts_input: true,
             input_device: None,
             output_device: None,
+            input_channels: None,
+            output_channels: None,
             handle: None,
         }
     }
@@ -0,0 +1,100 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Monotonic clock in micro-seconds. Peers don't share clocks, offsets between them are measured
+/// by the session.
+pub trait Clock: Send + Sync {
+    fn micros(&self) -> i64;
+}
+
+/// [`Clock`] backed by [`Instant`]. This is safe to read from the audio-thread.
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn micros(&self) -> i64 {
+        self.origin.elapsed().as_micros() as i64
+    }
+}
+
+/// [`Clock`] which only moves when told to. Used on tests.
+#[derive(Default)]
+pub struct ManualClock {
+    micros: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(micros: i64) -> Self {
+        Self {
+            micros: AtomicI64::new(micros),
+        }
+    }
+
+    pub fn set(&self, micros: i64) {
+        self.micros.store(micros, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.micros
+            .fetch_add(duration.as_micros() as i64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn micros(&self) -> i64 {
+        self.micros.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_advances() {
+        let clock = ManualClock::new(10);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(clock.micros(), 1010);
+        clock.set(5);
+        assert_eq!(clock.micros(), 5);
+    }
+
+    #[test]
+    fn test_system_clock_is_monotonic() {
+        let clock = SystemClock::default();
+        let first = clock.micros();
+        let second = clock.micros();
+        assert!(second >= first);
+    }
+}
@@ -0,0 +1,52 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Network tempo, beat & phase synchronisation between audio applications on a LAN.
+//!
+//! Each peer owns a [`TempoSyncSession`]. Sessions announce their [`Timeline`] (tempo and a
+//! beat/time origin) over a [`SessionTransport`] and merge what they hear from other peers:
+//!
+//! * Peers measure each other's clock offsets with ping/pong messages, so remote timelines are
+//!   always converted into the local [`Clock`] before being compared
+//! * When two sessions meet, every peer joins the session which started first
+//! * Within a session, the timeline with the latest revision wins, so tempo changes made on any
+//!   peer propagate to all others
+//!
+//! Only the phase within the session `quantum` is meant to be shared. Applications keep their own
+//! beat counts and use [`TempoSyncReader::aligned_beat`] to nudge them into phase.
+//!
+//! [`UdpMulticastTransport`] is the network transport, [`LocalNetwork`] is an in-process stand-in
+//! used on tests.
+
+pub use self::clock::{Clock, ManualClock, SystemClock};
+pub use self::message::{MessageError, PeerAnnouncement, SessionMessage};
+pub use self::session::{TempoSyncOptions, TempoSyncReader, TempoSyncSession, TempoSyncThread};
+pub use self::timeline::{align_beat, SharedTimeline, Timeline};
+pub use self::transport::{
+    LocalNetwork, LocalTransport, MulticastOptions, SessionTransport, UdpMulticastTransport,
+};
+
+mod clock;
+mod message;
+mod session;
+mod timeline;
+mod transport;
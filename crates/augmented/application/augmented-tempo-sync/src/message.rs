@@ -0,0 +1,244 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Wire format. Every packet starts with [`MAGIC`], followed by a message kind byte and
+//! big-endian fields.
+use thiserror::Error;
+
+use crate::Timeline;
+
+const MAGIC: &[u8; 8] = b"augsync\x01";
+
+const KIND_ALIVE: u8 = 1;
+const KIND_PING: u8 = 2;
+const KIND_PONG: u8 = 3;
+const KIND_BYE: u8 = 4;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MessageError {
+    #[error("Packet is not a tempo sync message")]
+    InvalidHeader,
+    #[error("Unknown message kind {0}")]
+    UnknownKind(u8),
+    #[error("Packet is truncated")]
+    Truncated,
+}
+
+/// Periodic announcement of a peer's session. Times are in the sender's clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerAnnouncement {
+    pub peer_id: u64,
+    pub session_id: u64,
+    pub session_started_at: i64,
+    pub revision: u64,
+    pub revision_author: u64,
+    pub timeline: Timeline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionMessage {
+    Alive(PeerAnnouncement),
+    /// Clock offset measurement request, answered with a `Pong`
+    Ping {
+        from: u64,
+        to: u64,
+        sent_at: i64,
+    },
+    Pong {
+        from: u64,
+        to: u64,
+        ping_sent_at: i64,
+        remote_time: i64,
+    },
+    /// A peer is leaving the network
+    Bye {
+        peer_id: u64,
+    },
+}
+
+impl SessionMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(64);
+        output.extend_from_slice(MAGIC);
+        match self {
+            SessionMessage::Alive(announcement) => {
+                output.push(KIND_ALIVE);
+                output.extend_from_slice(&announcement.peer_id.to_be_bytes());
+                output.extend_from_slice(&announcement.session_id.to_be_bytes());
+                output.extend_from_slice(&announcement.session_started_at.to_be_bytes());
+                output.extend_from_slice(&announcement.revision.to_be_bytes());
+                output.extend_from_slice(&announcement.revision_author.to_be_bytes());
+                output.extend_from_slice(&announcement.timeline.tempo().to_be_bytes());
+                output.extend_from_slice(&announcement.timeline.beat_origin().to_be_bytes());
+                output.extend_from_slice(&announcement.timeline.time_origin().to_be_bytes());
+            }
+            SessionMessage::Ping { from, to, sent_at } => {
+                output.push(KIND_PING);
+                output.extend_from_slice(&from.to_be_bytes());
+                output.extend_from_slice(&to.to_be_bytes());
+                output.extend_from_slice(&sent_at.to_be_bytes());
+            }
+            SessionMessage::Pong {
+                from,
+                to,
+                ping_sent_at,
+                remote_time,
+            } => {
+                output.push(KIND_PONG);
+                output.extend_from_slice(&from.to_be_bytes());
+                output.extend_from_slice(&to.to_be_bytes());
+                output.extend_from_slice(&ping_sent_at.to_be_bytes());
+                output.extend_from_slice(&remote_time.to_be_bytes());
+            }
+            SessionMessage::Bye { peer_id } => {
+                output.push(KIND_BYE);
+                output.extend_from_slice(&peer_id.to_be_bytes());
+            }
+        }
+        output
+    }
+
+    pub fn decode(packet: &[u8]) -> Result<Self, MessageError> {
+        if packet.len() < MAGIC.len() || &packet[..MAGIC.len()] != MAGIC {
+            return Err(MessageError::InvalidHeader);
+        }
+        let mut reader = Reader {
+            input: &packet[MAGIC.len()..],
+        };
+
+        let kind = reader.u8()?;
+        match kind {
+            KIND_ALIVE => Ok(SessionMessage::Alive(PeerAnnouncement {
+                peer_id: reader.u64()?,
+                session_id: reader.u64()?,
+                session_started_at: reader.i64()?,
+                revision: reader.u64()?,
+                revision_author: reader.u64()?,
+                timeline: Timeline::new(reader.f64()?, reader.f64()?, reader.i64()?),
+            })),
+            KIND_PING => Ok(SessionMessage::Ping {
+                from: reader.u64()?,
+                to: reader.u64()?,
+                sent_at: reader.i64()?,
+            }),
+            KIND_PONG => Ok(SessionMessage::Pong {
+                from: reader.u64()?,
+                to: reader.u64()?,
+                ping_sent_at: reader.i64()?,
+                remote_time: reader.i64()?,
+            }),
+            KIND_BYE => Ok(SessionMessage::Bye {
+                peer_id: reader.u64()?,
+            }),
+            other => Err(MessageError::UnknownKind(other)),
+        }
+    }
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], MessageError> {
+        if self.input.len() < N {
+            return Err(MessageError::Truncated);
+        }
+        let (bytes, rest) = self.input.split_at(N);
+        self.input = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, MessageError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, MessageError> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, MessageError> {
+        Ok(i64::from_be_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, MessageError> {
+        Ok(f64::from_be_bytes(self.take()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let messages = [
+            SessionMessage::Alive(PeerAnnouncement {
+                peer_id: 1,
+                session_id: 2,
+                session_started_at: -3,
+                revision: 4,
+                revision_author: 5,
+                timeline: Timeline::new(128.5, 2.25, 1_000),
+            }),
+            SessionMessage::Ping {
+                from: 1,
+                to: 2,
+                sent_at: 3,
+            },
+            SessionMessage::Pong {
+                from: 1,
+                to: 2,
+                ping_sent_at: 3,
+                remote_time: 4,
+            },
+            SessionMessage::Bye { peer_id: 10 },
+        ];
+
+        for message in messages {
+            let packet = message.encode();
+            assert_eq!(SessionMessage::decode(&packet), Ok(message));
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_packets() {
+        assert_eq!(
+            SessionMessage::decode(b"/looper/1/record"),
+            Err(MessageError::InvalidHeader)
+        );
+
+        let mut packet = SessionMessage::Bye { peer_id: 10 }.encode();
+        packet.pop();
+        assert_eq!(
+            SessionMessage::decode(&packet),
+            Err(MessageError::Truncated)
+        );
+
+        let mut packet = MAGIC.to_vec();
+        packet.push(99);
+        assert_eq!(
+            SessionMessage::decode(&packet),
+            Err(MessageError::UnknownKind(99))
+        );
+    }
+}
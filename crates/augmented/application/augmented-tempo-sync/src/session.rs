@@ -0,0 +1,603 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::{
+    align_beat, Clock, PeerAnnouncement, SessionMessage, SessionTransport, SharedTimeline, Timeline,
+};
+
+pub struct TempoSyncOptions {
+    /// Tempo of the session this peer starts with. Defaults to 120.
+    pub tempo: f64,
+    /// Number of beats whose phase is shared between peers. Defaults to 4.
+    pub quantum: f64,
+    /// How often this peer announces its session. Defaults to 250ms.
+    pub broadcast_interval: Duration,
+    /// Peers which haven't announced themselves in this long are forgotten. Defaults to 2s.
+    pub peer_timeout: Duration,
+}
+
+impl Default for TempoSyncOptions {
+    fn default() -> Self {
+        Self {
+            tempo: 120.0,
+            quantum: 4.0,
+            broadcast_interval: Duration::from_millis(250),
+            peer_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+struct PeerInfo {
+    /// Remote clock minus local clock, once measured
+    clock_offset: Option<i64>,
+    best_round_trip: i64,
+    last_seen: i64,
+}
+
+struct SessionState {
+    session_id: u64,
+    started_at: i64,
+    revision: u64,
+    revision_author: u64,
+    timeline: Timeline,
+    peers: HashMap<u64, PeerInfo>,
+    last_broadcast: Option<i64>,
+}
+
+/// One peer of a tempo sync session.
+///
+/// The network side is driven by [`TempoSyncSession::tick`] and [`TempoSyncSession::poll`], which
+/// [`TempoSyncSession::start`] calls on a background thread. The audio-thread should only read the
+/// session through a [`TempoSyncReader`].
+pub struct TempoSyncSession {
+    peer_id: u64,
+    options: TempoSyncOptions,
+    transport: Box<dyn SessionTransport>,
+    clock: Arc<dyn Clock>,
+    state: Mutex<SessionState>,
+    timeline: Arc<SharedTimeline>,
+}
+
+impl TempoSyncSession {
+    /// Start a new session on `transport`. It'll be merged with any older session this peer finds.
+    pub fn new(
+        transport: impl SessionTransport + 'static,
+        clock: Arc<dyn Clock>,
+        options: TempoSyncOptions,
+    ) -> Self {
+        let peer_id = random_id();
+        let now = clock.micros();
+        let timeline = Timeline::new(options.tempo, 0.0, now);
+
+        Self {
+            peer_id,
+            transport: Box::new(transport),
+            clock,
+            state: Mutex::new(SessionState {
+                session_id: peer_id,
+                started_at: now,
+                revision: 0,
+                revision_author: peer_id,
+                timeline,
+                peers: HashMap::new(),
+                last_broadcast: None,
+            }),
+            timeline: Arc::new(SharedTimeline::new(timeline)),
+            options,
+        }
+    }
+
+    pub fn peer_id(&self) -> u64 {
+        self.peer_id
+    }
+
+    pub fn session_id(&self) -> u64 {
+        self.state.lock().unwrap().session_id
+    }
+
+    pub fn quantum(&self) -> f64 {
+        self.options.quantum
+    }
+
+    /// Number of peers currently heard from, not counting this one
+    pub fn num_peers(&self) -> usize {
+        self.state.lock().unwrap().peers.len()
+    }
+
+    /// Session timeline in the local clock
+    pub fn timeline(&self) -> Timeline {
+        self.timeline.load()
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.timeline().tempo()
+    }
+
+    /// Change the session tempo. This is propagated to all peers.
+    pub fn set_tempo(&self, tempo: f64) {
+        let announcement = {
+            let mut state = self.state.lock().unwrap();
+            let now = self.clock.micros();
+            state.timeline = state.timeline.with_tempo(tempo, now);
+            state.revision += 1;
+            state.revision_author = self.peer_id;
+            self.timeline.store(state.timeline);
+            state.last_broadcast = Some(now);
+            self.announcement(&state)
+        };
+        self.send(SessionMessage::Alive(announcement));
+    }
+
+    pub fn reader(&self) -> TempoSyncReader {
+        TempoSyncReader {
+            timeline: self.timeline.clone(),
+            clock: self.clock.clone(),
+            quantum: self.options.quantum,
+        }
+    }
+
+    /// Announce this peer every `broadcast_interval`, refresh clock offsets and forget peers which
+    /// timed out.
+    pub fn tick(&self) {
+        let now = self.clock.micros();
+        let mut messages = vec![];
+        {
+            let mut state = self.state.lock().unwrap();
+            let should_broadcast = match state.last_broadcast {
+                Some(last_broadcast) => {
+                    now - last_broadcast >= self.options.broadcast_interval.as_micros() as i64
+                }
+                None => true,
+            };
+            if !should_broadcast {
+                return;
+            }
+            state.last_broadcast = Some(now);
+
+            let peer_timeout = self.options.peer_timeout.as_micros() as i64;
+            state.peers.retain(|peer_id, peer| {
+                let is_alive = now - peer.last_seen < peer_timeout;
+                if !is_alive {
+                    log::info!("Tempo sync peer {} timed-out", peer_id);
+                }
+                is_alive
+            });
+
+            messages.push(SessionMessage::Alive(self.announcement(&state)));
+            for peer_id in state.peers.keys() {
+                messages.push(SessionMessage::Ping {
+                    from: self.peer_id,
+                    to: *peer_id,
+                    sent_at: now,
+                });
+            }
+        }
+
+        for message in messages {
+            self.send(message);
+        }
+    }
+
+    /// Wait up to `timeout` for a packet and handle it. Returns whether a packet was received.
+    pub fn poll(&self, timeout: Duration) -> io::Result<bool> {
+        match self.transport.recv(timeout)? {
+            Some(packet) => {
+                self.receive(&packet);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Handle a packet from the transport
+    pub fn receive(&self, packet: &[u8]) {
+        let message = match SessionMessage::decode(packet) {
+            Ok(message) => message,
+            Err(err) => {
+                log::debug!("Ignoring tempo sync packet: {}", err);
+                return;
+            }
+        };
+        let now = self.clock.micros();
+
+        match message {
+            SessionMessage::Alive(announcement) if announcement.peer_id != self.peer_id => {
+                self.on_announcement(announcement, now);
+            }
+            SessionMessage::Ping { from, to, sent_at } if to == self.peer_id => {
+                self.send(SessionMessage::Pong {
+                    from: self.peer_id,
+                    to: from,
+                    ping_sent_at: sent_at,
+                    remote_time: now,
+                });
+            }
+            SessionMessage::Pong {
+                from,
+                to,
+                ping_sent_at,
+                remote_time,
+            } if to == self.peer_id => {
+                let round_trip = now - ping_sent_at;
+                let mut state = self.state.lock().unwrap();
+                if let Some(peer) = state.peers.get_mut(&from) {
+                    if round_trip >= 0
+                        && (peer.clock_offset.is_none() || round_trip <= peer.best_round_trip)
+                    {
+                        peer.best_round_trip = round_trip;
+                        peer.clock_offset = Some(remote_time - (ping_sent_at + round_trip / 2));
+                    }
+                }
+            }
+            SessionMessage::Bye { peer_id } => {
+                log::info!("Tempo sync peer {} left", peer_id);
+                self.state.lock().unwrap().peers.remove(&peer_id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Spawn a thread which drives this session until the returned [`TempoSyncThread`] is stopped
+    /// or dropped.
+    pub fn start(self: Arc<Self>) -> io::Result<TempoSyncThread> {
+        let is_running = Arc::new(AtomicBool::new(true));
+        let poll_timeout = self
+            .options
+            .broadcast_interval
+            .min(Duration::from_millis(50));
+
+        let handle = std::thread::Builder::new()
+            .name(String::from("tempo-sync-thread"))
+            .spawn({
+                let is_running = is_running.clone();
+                let session = self.clone();
+                move || {
+                    while is_running.load(Ordering::Relaxed) {
+                        session.tick();
+                        if let Err(err) = session.poll(poll_timeout) {
+                            log::error!("Tempo sync receive failed: {}", err);
+                            std::thread::sleep(poll_timeout);
+                        }
+                    }
+                    session.send(SessionMessage::Bye {
+                        peer_id: session.peer_id,
+                    });
+                }
+            })?;
+
+        Ok(TempoSyncThread {
+            is_running,
+            handle: Some(handle),
+        })
+    }
+
+    fn on_announcement(&self, announcement: PeerAnnouncement, now: i64) {
+        let mut state = self.state.lock().unwrap();
+        let peer = state.peers.entry(announcement.peer_id).or_insert_with(|| {
+            log::info!("Tempo sync peer {} found", announcement.peer_id);
+            PeerInfo {
+                clock_offset: None,
+                best_round_trip: 0,
+                last_seen: now,
+            }
+        });
+        peer.last_seen = now;
+
+        // Timelines can only be compared once we know the peer's clock
+        let clock_offset = match peer.clock_offset {
+            Some(clock_offset) => clock_offset,
+            None => {
+                drop(state);
+                self.send(SessionMessage::Ping {
+                    from: self.peer_id,
+                    to: announcement.peer_id,
+                    sent_at: now,
+                });
+                return;
+            }
+        };
+        let started_at = announcement.session_started_at - clock_offset;
+        let timeline = announcement.timeline.shifted(clock_offset);
+
+        let should_adopt = if announcement.session_id == state.session_id {
+            (
+                announcement.revision,
+                u64::MAX - announcement.revision_author,
+            ) > (state.revision, u64::MAX - state.revision_author)
+        } else {
+            let should_join =
+                (started_at, announcement.session_id) < (state.started_at, state.session_id);
+            if should_join {
+                log::info!(
+                    "Joining tempo sync session {} at {}bpm",
+                    announcement.session_id,
+                    timeline.tempo()
+                );
+                state.session_id = announcement.session_id;
+                state.started_at = started_at;
+            }
+            should_join
+        };
+
+        if should_adopt {
+            state.revision = announcement.revision;
+            state.revision_author = announcement.revision_author;
+            state.timeline = timeline;
+            self.timeline.store(timeline);
+        }
+    }
+
+    fn announcement(&self, state: &SessionState) -> PeerAnnouncement {
+        PeerAnnouncement {
+            peer_id: self.peer_id,
+            session_id: state.session_id,
+            session_started_at: state.started_at,
+            revision: state.revision,
+            revision_author: state.revision_author,
+            timeline: state.timeline,
+        }
+    }
+
+    fn send(&self, message: SessionMessage) {
+        if let Err(err) = self.transport.send(&message.encode()) {
+            log::warn!("Failed to send tempo sync message: {}", err);
+        }
+    }
+}
+
+fn random_id() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Running session thread. Stops and leaves the session when dropped.
+pub struct TempoSyncThread {
+    is_running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TempoSyncThread {
+    pub fn stop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for TempoSyncThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Real-time safe view of a session's timeline.
+#[derive(Clone)]
+pub struct TempoSyncReader {
+    timeline: Arc<SharedTimeline>,
+    clock: Arc<dyn Clock>,
+    quantum: f64,
+}
+
+impl TempoSyncReader {
+    pub fn tempo(&self) -> f64 {
+        self.timeline.load().tempo()
+    }
+
+    pub fn quantum(&self) -> f64 {
+        self.quantum
+    }
+
+    /// Session beat right now
+    pub fn beat_now(&self) -> f64 {
+        self.timeline.load().beat_at_time(self.clock.micros())
+    }
+
+    /// Session phase within the quantum right now
+    pub fn phase_now(&self) -> f64 {
+        self.timeline
+            .load()
+            .phase_at_time(self.clock.micros(), self.quantum)
+    }
+
+    /// The beat closest to `local_beat` which is in phase with the session. See [`align_beat`].
+    pub fn aligned_beat(&self, local_beat: f64) -> f64 {
+        align_beat(local_beat, self.beat_now(), self.quantum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{LocalNetwork, ManualClock};
+
+    use super::*;
+
+    /// Clock which is `offset` micro-seconds ahead of another
+    struct OffsetClock {
+        inner: Arc<ManualClock>,
+        offset: i64,
+    }
+
+    impl Clock for OffsetClock {
+        fn micros(&self) -> i64 {
+            self.inner.micros() + self.offset
+        }
+    }
+
+    fn session(network: &LocalNetwork, clock: Arc<dyn Clock>, tempo: f64) -> TempoSyncSession {
+        TempoSyncSession::new(
+            network.connect(),
+            clock,
+            TempoSyncOptions {
+                tempo,
+                ..TempoSyncOptions::default()
+            },
+        )
+    }
+
+    /// Run a few rounds of announcements, delivering every packet
+    fn exchange(clock: &ManualClock, sessions: &[&TempoSyncSession]) {
+        for _ in 0..4 {
+            clock.advance(Duration::from_millis(250));
+            for session in sessions {
+                session.tick();
+            }
+            loop {
+                let mut received = false;
+                for session in sessions {
+                    received |= session.poll(Duration::ZERO).unwrap();
+                }
+                if !received {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_session_has_its_own_timeline() {
+        let network = LocalNetwork::new();
+        let clock = Arc::new(ManualClock::new(0));
+        let session = session(&network, clock.clone(), 90.0);
+        assert_eq!(session.session_id(), session.peer_id());
+        assert_eq!(session.num_peers(), 0);
+        assert!((session.tempo() - 90.0).abs() < f64::EPSILON);
+
+        clock.advance(Duration::from_secs(2));
+        let reader = session.reader();
+        assert!((reader.beat_now() - 3.0).abs() < 1e-9);
+        assert!((reader.phase_now() - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_new_peer_joins_the_oldest_session() {
+        let network = LocalNetwork::new();
+        let clock = Arc::new(ManualClock::new(0));
+        let first = session(&network, clock.clone(), 120.0);
+        clock.advance(Duration::from_millis(1300));
+        let second = session(&network, clock.clone(), 90.0);
+
+        exchange(&clock, &[&first, &second]);
+
+        assert_eq!(first.num_peers(), 1);
+        assert_eq!(second.num_peers(), 1);
+        assert_eq!(second.session_id(), first.session_id());
+        assert!((second.tempo() - 120.0).abs() < f64::EPSILON);
+        assert!((first.tempo() - 120.0).abs() < f64::EPSILON);
+        assert!((first.reader().beat_now() - second.reader().beat_now()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tempo_changes_propagate_to_all_peers() {
+        let network = LocalNetwork::new();
+        let clock = Arc::new(ManualClock::new(0));
+        let first = session(&network, clock.clone(), 120.0);
+        clock.advance(Duration::from_millis(10));
+        let second = session(&network, clock.clone(), 120.0);
+        clock.advance(Duration::from_millis(10));
+        let third = session(&network, clock.clone(), 120.0);
+        exchange(&clock, &[&first, &second, &third]);
+
+        let beat_before = first.reader().beat_now();
+        third.set_tempo(100.0);
+        exchange(&clock, &[&first, &second, &third]);
+
+        for session in [&first, &second, &third] {
+            assert!((session.tempo() - 100.0).abs() < f64::EPSILON);
+        }
+        // The exchange took 1s at 100bpm, the beat didn't jump on the tempo change
+        assert!((first.reader().beat_now() - (beat_before + 1000.0 / 600.0)).abs() < 1e-6);
+        assert!((first.reader().beat_now() - third.reader().beat_now()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_peers_with_different_clocks_share_phase() {
+        let network = LocalNetwork::new();
+        let clock = Arc::new(ManualClock::new(0));
+        let first = session(&network, clock.clone(), 120.0);
+        clock.advance(Duration::from_millis(700));
+        let second = session(
+            &network,
+            Arc::new(OffsetClock {
+                inner: clock.clone(),
+                offset: 37_000_000,
+            }),
+            90.0,
+        );
+
+        exchange(&clock, &[&first, &second]);
+
+        assert!((second.tempo() - 120.0).abs() < f64::EPSILON);
+        let first_phase = first.reader().phase_now();
+        let second_phase = second.reader().phase_now();
+        assert!((first_phase - second_phase).abs() < 1e-9);
+        let aligned_beat = second.reader().aligned_beat(9.0);
+        assert!((aligned_beat - 9.0).abs() <= 2.0);
+        assert!((aligned_beat.rem_euclid(4.0) - first_phase).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_peers_are_forgotten_when_they_leave_or_time_out() {
+        let network = LocalNetwork::new();
+        let clock = Arc::new(ManualClock::new(0));
+        let first = session(&network, clock.clone(), 120.0);
+        let second = session(&network, clock.clone(), 120.0);
+        exchange(&clock, &[&first, &second]);
+        assert_eq!(first.num_peers(), 1);
+
+        clock.advance(Duration::from_secs(3));
+        first.tick();
+        assert_eq!(first.num_peers(), 0);
+
+        exchange(&clock, &[&first, &second]);
+        assert_eq!(first.num_peers(), 1);
+        second.send(SessionMessage::Bye {
+            peer_id: second.peer_id(),
+        });
+        first.poll(Duration::ZERO).unwrap();
+        assert_eq!(first.num_peers(), 0);
+    }
+
+    #[test]
+    fn test_session_thread_starts_and_stops() {
+        let network = LocalNetwork::new();
+        let clock = Arc::new(crate::SystemClock::default());
+        let session = Arc::new(session(&network, clock, 120.0));
+        let listener = network.connect();
+
+        let mut thread = session.clone().start().unwrap();
+        let packet = listener.recv(Duration::from_secs(1)).unwrap().unwrap();
+        assert!(matches!(
+            SessionMessage::decode(&packet),
+            Ok(SessionMessage::Alive(_))
+        ));
+        thread.stop();
+    }
+}
@@ -0,0 +1,212 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+/// Maps clock time (micro-seconds) onto beats, at a constant tempo.
+///
+/// `beat_origin` is the beat at `time_origin`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeline {
+    tempo: f64,
+    beat_origin: f64,
+    time_origin: i64,
+}
+
+impl Timeline {
+    pub fn new(tempo: f64, beat_origin: f64, time_origin: i64) -> Self {
+        Self {
+            tempo,
+            beat_origin,
+            time_origin,
+        }
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    pub fn beat_origin(&self) -> f64 {
+        self.beat_origin
+    }
+
+    pub fn time_origin(&self) -> i64 {
+        self.time_origin
+    }
+
+    pub fn beat_at_time(&self, time: i64) -> f64 {
+        let elapsed_secs = (time - self.time_origin) as f64 / 1_000_000.0;
+        self.beat_origin + elapsed_secs * self.tempo / 60.0
+    }
+
+    pub fn time_at_beat(&self, beat: f64) -> i64 {
+        let elapsed_secs = (beat - self.beat_origin) * 60.0 / self.tempo;
+        self.time_origin + (elapsed_secs * 1_000_000.0).round() as i64
+    }
+
+    /// Position within `quantum`, in `[0, quantum)`
+    pub fn phase_at_time(&self, time: i64, quantum: f64) -> f64 {
+        self.beat_at_time(time).rem_euclid(quantum)
+    }
+
+    /// Change the tempo at `time`, without the beat position jumping.
+    pub fn with_tempo(&self, tempo: f64, time: i64) -> Self {
+        Self {
+            tempo,
+            beat_origin: self.beat_at_time(time),
+            time_origin: time,
+        }
+    }
+
+    /// Convert a timeline into a clock which is `offset` micro-seconds behind this one's.
+    pub fn shifted(&self, offset: i64) -> Self {
+        Self {
+            time_origin: self.time_origin - offset,
+            ..*self
+        }
+    }
+}
+
+/// Returns the beat closest to `local_beat` which has the same phase within `quantum` as
+/// `target_beat`.
+///
+/// This is how peers get in phase without agreeing on beat counts. With a quantum of 4, a peer at
+/// beat 9.1 and a session at beat 2.0 align to beat 10.0.
+pub fn align_beat(local_beat: f64, target_beat: f64, quantum: f64) -> f64 {
+    if quantum <= 0.0 {
+        return target_beat;
+    }
+
+    let mut difference = (target_beat - local_beat).rem_euclid(quantum);
+    if difference > quantum / 2.0 {
+        difference -= quantum;
+    }
+    local_beat + difference
+}
+
+/// [`Timeline`] which may be written from the session thread and read from the audio-thread.
+///
+/// This is a sequence lock; reads never block or allocate. There must be a single writer.
+pub struct SharedTimeline {
+    sequence: AtomicU64,
+    tempo: AtomicU64,
+    beat_origin: AtomicU64,
+    time_origin: AtomicU64,
+}
+
+impl SharedTimeline {
+    pub fn new(timeline: Timeline) -> Self {
+        Self {
+            sequence: AtomicU64::new(0),
+            tempo: AtomicU64::new(timeline.tempo.to_bits()),
+            beat_origin: AtomicU64::new(timeline.beat_origin.to_bits()),
+            time_origin: AtomicU64::new(timeline.time_origin as u64),
+        }
+    }
+
+    pub fn store(&self, timeline: Timeline) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence.store(sequence + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.tempo
+            .store(timeline.tempo.to_bits(), Ordering::Relaxed);
+        self.beat_origin
+            .store(timeline.beat_origin.to_bits(), Ordering::Relaxed);
+        self.time_origin
+            .store(timeline.time_origin as u64, Ordering::Relaxed);
+        self.sequence.store(sequence + 2, Ordering::Release);
+    }
+
+    pub fn load(&self) -> Timeline {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let timeline = Timeline {
+                tempo: f64::from_bits(self.tempo.load(Ordering::Relaxed)),
+                beat_origin: f64::from_bits(self.beat_origin.load(Ordering::Relaxed)),
+                time_origin: self.time_origin.load(Ordering::Relaxed) as i64,
+            };
+            fence(Ordering::Acquire);
+
+            if self.sequence.load(Ordering::Relaxed) == before {
+                return timeline;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_beat_at_time() {
+        let timeline = Timeline::new(120.0, 0.0, 0);
+        assert!((timeline.beat_at_time(1_000_000) - 2.0).abs() < f64::EPSILON);
+        assert!((timeline.beat_at_time(-500_000) + 1.0).abs() < f64::EPSILON);
+        assert_eq!(timeline.time_at_beat(4.0), 2_000_000);
+    }
+
+    #[test]
+    fn test_phase_at_time() {
+        let timeline = Timeline::new(60.0, 0.0, 0);
+        assert!((timeline.phase_at_time(5_000_000, 4.0) - 1.0).abs() < f64::EPSILON);
+        assert!((timeline.phase_at_time(-1_000_000, 4.0) - 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_with_tempo_keeps_beat_continuous() {
+        let timeline = Timeline::new(120.0, 0.0, 0);
+        let changed = timeline.with_tempo(60.0, 1_000_000);
+        assert!((changed.beat_at_time(1_000_000) - 2.0).abs() < f64::EPSILON);
+        assert!((changed.beat_at_time(2_000_000) - 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_shifted_timeline_converts_clocks() {
+        // The remote clock is 10s ahead of ours
+        let remote = Timeline::new(120.0, 0.0, 10_000_000);
+        let local = remote.shifted(10_000_000);
+        assert!((local.beat_at_time(1_000_000) - remote.beat_at_time(11_000_000)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_align_beat() {
+        assert!((align_beat(9.1, 2.0, 4.0) - 10.0).abs() < 1e-9);
+        assert!((align_beat(9.9, 1.0, 4.0) - 9.0).abs() < 1e-9);
+        assert!((align_beat(0.1, 3.9, 4.0) + 0.1).abs() < 1e-9);
+        assert!((align_beat(5.0, 1.0, 4.0) - 5.0).abs() < 1e-9);
+        assert!((align_beat(5.0, 1.0, 0.0) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_shared_timeline_store_and_load() {
+        let shared = SharedTimeline::new(Timeline::new(120.0, 0.0, 0));
+        assert_eq!(shared.load(), Timeline::new(120.0, 0.0, 0));
+        shared.store(Timeline::new(90.0, 3.5, -200));
+        assert_eq!(shared.load(), Timeline::new(90.0, 3.5, -200));
+    }
+}
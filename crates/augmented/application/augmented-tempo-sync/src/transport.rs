@@ -0,0 +1,198 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// Sends packets to every other peer and receives theirs.
+pub trait SessionTransport: Send + Sync {
+    fn send(&self, packet: &[u8]) -> io::Result<()>;
+
+    /// Wait up to `timeout` for a packet. Returns `None` if nothing arrived.
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
+
+pub struct MulticastOptions {
+    /// Multicast group. Defaults to `239.255.77.77`.
+    pub group: Ipv4Addr,
+    /// Defaults to 20909.
+    pub port: u16,
+    /// Interface to join the group on. Defaults to all interfaces.
+    pub interface: Ipv4Addr,
+    /// Defaults to 1, so packets don't leave the LAN.
+    pub ttl: u32,
+}
+
+impl Default for MulticastOptions {
+    fn default() -> Self {
+        Self {
+            group: Ipv4Addr::new(239, 255, 77, 77),
+            port: 20909,
+            interface: Ipv4Addr::UNSPECIFIED,
+            ttl: 1,
+        }
+    }
+}
+
+/// [`SessionTransport`] over UDP multicast. Several instances may run on the same host.
+pub struct UdpMulticastTransport {
+    socket: UdpSocket,
+    group: SocketAddr,
+}
+
+impl UdpMulticastTransport {
+    pub fn bind(options: MulticastOptions) -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, options.port).into())?;
+        socket.join_multicast_v4(&options.group, &options.interface)?;
+        socket.set_multicast_ttl_v4(options.ttl)?;
+        socket.set_multicast_loop_v4(true)?;
+
+        Ok(Self {
+            socket: socket.into(),
+            group: SocketAddrV4::new(options.group, options.port).into(),
+        })
+    }
+}
+
+impl SessionTransport for UdpMulticastTransport {
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        self.socket.send_to(packet, self.group)?;
+        Ok(())
+    }
+
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut buffer = [0; 256];
+        match self.socket.recv_from(&mut buffer) {
+            Ok((size, _)) => Ok(Some(buffer[..size].to_vec())),
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+type LocalEndpoints = Vec<(usize, Sender<Vec<u8>>)>;
+
+/// In-process stand-in for the network. Every [`LocalTransport`] connected to it receives the
+/// packets sent by all others.
+#[derive(Clone, Default)]
+pub struct LocalNetwork {
+    endpoints: Arc<Mutex<LocalEndpoints>>,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl LocalNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connect(&self) -> LocalTransport {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = channel();
+        self.endpoints.lock().unwrap().push((id, sender));
+        LocalTransport {
+            id,
+            network: self.clone(),
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+pub struct LocalTransport {
+    id: usize,
+    network: LocalNetwork,
+    receiver: Mutex<Receiver<Vec<u8>>>,
+}
+
+impl SessionTransport for LocalTransport {
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        let endpoints = self.network.endpoints.lock().unwrap();
+        for (id, sender) in endpoints.iter() {
+            if *id != self.id {
+                let _ = sender.send(packet.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        match self.receiver.lock().unwrap().recv_timeout(timeout) {
+            Ok(packet) => Ok(Some(packet)),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => Ok(None),
+        }
+    }
+}
+
+impl Drop for LocalTransport {
+    fn drop(&mut self) {
+        if let Ok(mut endpoints) = self.network.endpoints.lock() {
+            endpoints.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_local_network_delivers_to_other_peers() {
+        let network = LocalNetwork::new();
+        let first = network.connect();
+        let second = network.connect();
+        let third = network.connect();
+
+        first.send(b"hello").unwrap();
+        assert_eq!(
+            second.recv(Duration::ZERO).unwrap(),
+            Some(b"hello".to_vec())
+        );
+        assert_eq!(third.recv(Duration::ZERO).unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(first.recv(Duration::ZERO).unwrap(), None);
+    }
+
+    #[test]
+    fn test_local_network_forgets_dropped_transports() {
+        let network = LocalNetwork::new();
+        let first = network.connect();
+        let second = network.connect();
+        drop(second);
+        first.send(b"hello").unwrap();
+        assert_eq!(network.endpoints.lock().unwrap().len(), 1);
+    }
+}
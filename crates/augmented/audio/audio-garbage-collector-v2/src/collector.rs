@@ -20,17 +20,42 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use atomic_queue;
 
-use crate::node::DropCommand;
+use std::ptr::NonNull;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::node::NodeHeader;
+use crate::sync::{Arc, AtomicPtr, AtomicUsize, Ordering};
+
+/// What to do when a value is dropped while the collector's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackPressure {
+    /// Push the value onto an unbounded lock-free overflow list, which is collected with the queue.
+    /// This never allocates or blocks, since the list is intrusive.
+    Overflow,
+    /// Drop the value on the thread that released it. This keeps memory bounded but will
+    /// de-allocate on the audio-thread.
+    DropInPlace,
+}
 
 pub struct GarbageCollectorOptions {
-    queue_capacity: usize,
+    /// Capacity of the drop queue. Defaults to 500.
+    pub queue_capacity: usize,
+    /// How often the background thread collects, when started with
+    /// [`GarbageCollector::start`]. Defaults to 100ms.
+    pub collect_interval: Duration,
+    /// Behaviour when the drop queue is full. Defaults to [`BackPressure::Overflow`].
+    pub back_pressure: BackPressure,
 }
 
 impl GarbageCollectorOptions {
     pub fn new(queue_capacity: usize) -> Self {
-        GarbageCollectorOptions { queue_capacity }
+        GarbageCollectorOptions {
+            queue_capacity,
+            ..Default::default()
+        }
     }
 }
 
@@ -38,16 +63,159 @@ impl Default for GarbageCollectorOptions {
     fn default() -> Self {
         GarbageCollectorOptions {
             queue_capacity: 500,
+            collect_interval: Duration::from_millis(100),
+            back_pressure: BackPressure::Overflow,
+        }
+    }
+}
+
+/// A snapshot of the collector's counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GarbageCollectorMetrics {
+    /// Number of values allocated through this collector which haven't been collected yet
+    pub allocated: usize,
+    /// Number of values that were released and are waiting to be collected
+    pub pending: usize,
+    /// Total number of values collected
+    pub collected: usize,
+    /// Total number of values pushed onto the overflow list because the queue was full
+    pub overflowed: usize,
+    /// Total number of values dropped in place because the queue was full
+    pub dropped_in_place: usize,
+}
+
+struct NodePtr(NonNull<NodeHeader>);
+
+// Safety: Nodes are only created for `Send` values
+unsafe impl Send for NodePtr {}
+
+struct CollectorInner {
+    queue: atomic_queue::Queue<NodePtr>,
+    overflow: AtomicPtr<NodeHeader>,
+    back_pressure: BackPressure,
+    allocated: AtomicUsize,
+    pending: AtomicUsize,
+    collected: AtomicUsize,
+    overflowed: AtomicUsize,
+    dropped_in_place: AtomicUsize,
+}
+
+impl CollectorInner {
+    fn collect(&self) -> usize {
+        let mut values_dropped = 0;
+        while let Some(NodePtr(node)) = self.queue.pop() {
+            unsafe { self.drop_node(node) };
+            values_dropped += 1;
+        }
+
+        let mut node = self.overflow.swap(std::ptr::null_mut(), Ordering::Acquire);
+        while let Some(current) = NonNull::new(node) {
+            node = unsafe { current.as_ref().next.load(Ordering::Relaxed) };
+            unsafe { self.drop_node(current) };
+            values_dropped += 1;
+        }
+
+        self.collected.fetch_add(values_dropped, Ordering::Relaxed);
+        values_dropped
+    }
+
+    unsafe fn drop_node(&self, node: NonNull<NodeHeader>) {
+        NodeHeader::drop_node(node);
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+        self.allocated.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn push_overflow(&self, node: NonNull<NodeHeader>) {
+        let mut head = self.overflow.load(Ordering::Relaxed);
+        loop {
+            unsafe { node.as_ref().next.store(head, Ordering::Relaxed) };
+            match self.overflow.compare_exchange_weak(
+                head,
+                node.as_ptr(),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
         }
     }
 }
 
-pub trait GarbageCollectorRef {
-    fn enqueue_drop(&self, drop_command: *mut DropCommand);
+impl Drop for CollectorInner {
+    fn drop(&mut self) {
+        self.collect();
+    }
+}
+
+/// A cheap to clone reference to a collector, used to create pointers and to enqueue values for
+/// collection.
+///
+/// Values released after the [`GarbageCollector`] is dropped are collected when the last handle
+/// is dropped.
+#[derive(Clone)]
+pub struct Handle {
+    inner: Arc<CollectorInner>,
+}
+
+impl Handle {
+    pub(crate) fn on_allocate(&self) {
+        self.inner.allocated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Enqueue an unreferenced node to be dropped by the collector. Never allocates or blocks.
+    pub(crate) fn enqueue(&self, node: NonNull<NodeHeader>) {
+        let inner = &self.inner;
+        inner.pending.fetch_add(1, Ordering::Relaxed);
+        if inner.queue.push(NodePtr(node)) {
+            return;
+        }
+
+        match inner.back_pressure {
+            BackPressure::Overflow => {
+                inner.overflowed.fetch_add(1, Ordering::Relaxed);
+                inner.push_overflow(node);
+            }
+            BackPressure::DropInPlace => {
+                inner.dropped_in_place.fetch_add(1, Ordering::Relaxed);
+                unsafe { inner.drop_node(node) };
+            }
+        }
+    }
+
+    /// Drop all pending values on the current thread, returning how many were dropped.
+    pub fn collect(&self) -> usize {
+        self.inner.collect()
+    }
+
+    pub fn metrics(&self) -> GarbageCollectorMetrics {
+        let inner = &self.inner;
+        GarbageCollectorMetrics {
+            allocated: inner.allocated.load(Ordering::Relaxed),
+            pending: inner.pending.load(Ordering::Relaxed),
+            collected: inner.collected.load(Ordering::Relaxed),
+            overflowed: inner.overflowed.load(Ordering::Relaxed),
+            dropped_in_place: inner.dropped_in_place.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Errors that may be emitted when stopping the GC
+#[derive(Debug, Error)]
+pub enum GarbageCollectorError {
+    /// Emitted if the GC thread panicked
+    #[error("Failed to join the GC thread")]
+    JoinError,
 }
 
+/// Owns the drop queue and optionally a background thread which collects it periodically.
+///
+/// Remaining values are collected when the collector is dropped.
 pub struct GarbageCollector {
-    queue: atomic_queue::Queue<*mut DropCommand>,
+    handle: Handle,
+    collect_interval: Duration,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
 }
 
 impl Default for GarbageCollector {
@@ -57,42 +225,93 @@ impl Default for GarbageCollector {
 }
 
 impl GarbageCollector {
+    /// Create a collector which is collected manually with [`GarbageCollector::collect`].
     pub fn new(options: GarbageCollectorOptions) -> Self {
-        GarbageCollector {
+        let inner = CollectorInner {
             queue: atomic_queue::Queue::new(options.queue_capacity),
+            overflow: AtomicPtr::new(std::ptr::null_mut()),
+            back_pressure: options.back_pressure,
+            allocated: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+            collected: AtomicUsize::new(0),
+            overflowed: AtomicUsize::new(0),
+            dropped_in_place: AtomicUsize::new(0),
+        };
+        GarbageCollector {
+            handle: Handle {
+                inner: Arc::new(inner),
+            },
+            collect_interval: options.collect_interval,
+            running: Default::default(),
+            thread: None,
         }
     }
 
-    pub fn handle(&self) -> *mut Self {
-        self as *const Self as *mut Self
+    /// Create a collector and start a background thread collecting every `collect_interval`.
+    pub fn start(options: GarbageCollectorOptions) -> Self {
+        let mut collector = Self::new(options);
+        collector
+            .running
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        let thread = {
+            let handle = collector.handle.clone();
+            let running = collector.running.clone();
+            let collect_interval = collector.collect_interval;
+            std::thread::Builder::new()
+                .name(String::from("gc-thread"))
+                .spawn(move || {
+                    while running.load(std::sync::atomic::Ordering::Relaxed) {
+                        handle.collect();
+                        std::thread::park_timeout(collect_interval);
+                    }
+                })
+                .expect("Failed to start GC thread")
+        };
+        collector.thread = Some(thread);
+        collector
+    }
+
+    /// Stop & join the collector thread, if there is one.
+    pub fn stop(&mut self) -> Result<(), GarbageCollectorError> {
+        self.running
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            thread
+                .join()
+                .map_err(|_| GarbageCollectorError::JoinError)?;
+        }
+        Ok(())
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
     }
-}
 
-impl GarbageCollectorRef for GarbageCollector {
-    fn enqueue_drop(&self, drop_command: *mut DropCommand) {
-        self.queue.push(drop_command);
+    /// Drop all pending values on the current thread, returning how many were dropped.
+    pub fn collect(&self) -> usize {
+        self.handle.collect()
+    }
+
+    pub fn metrics(&self) -> GarbageCollectorMetrics {
+        self.handle.metrics()
     }
 }
 
-impl GarbageCollector {
-    /// # Safety
-    /// Relies on drop commands pushed to its queue being valid.
-    pub unsafe fn collect(&self) -> usize {
-        let mut values_dropped = 0;
-        while let Some(drop_command) = self.queue.pop() {
-            (*drop_command).do_drop();
-            values_dropped += 1;
-        }
-        values_dropped
+impl Drop for GarbageCollector {
+    fn drop(&mut self) {
+        let _ = self.stop();
+        self.collect();
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(loom)))]
 mod test {
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
+    use std::time::Instant;
 
-    use crate::Shared;
+    use crate::{Owned, Shared};
 
     use super::*;
 
@@ -115,102 +334,145 @@ mod test {
     #[test]
     fn test_collect_when_empty() {
         let collector = GarbageCollector::default();
-        unsafe {
-            collector.collect();
-        }
+        assert_eq!(collector.collect(), 0);
     }
 
     #[test]
-    fn test_collect_with_one_entry() {
+    fn test_collect_list() {
         let collector = GarbageCollector::default();
-        let count = Arc::new(AtomicUsize::new(1));
-        let has_been_dropped = {
-            let count = count.clone();
-            move || count.load(Ordering::Relaxed) != 1
-        };
-        let value_ptr = Box::into_raw(Box::new(RefCounter::new(count)));
-        let drop_command = Box::into_raw(Box::new(DropCommand::new(value_ptr)));
+        let num_entries = 10;
+        let count = Arc::new(AtomicUsize::new(num_entries));
 
-        assert!(
-            !has_been_dropped(),
-            "Value has been dropped before collection"
-        );
-        unsafe {
-            collector.collect();
+        for _i in 0..num_entries {
+            drop(Shared::new(
+                collector.handle(),
+                RefCounter::new(count.clone()),
+            ));
         }
-        assert!(
-            !has_been_dropped(),
-            "Value has been dropped before being added to queue"
-        );
-        collector.enqueue_drop(drop_command);
-        assert!(
-            !has_been_dropped(),
+
+        assert_eq!(
+            count.load(Ordering::Relaxed),
+            num_entries,
             "Value has been dropped before collection"
         );
-        unsafe {
-            collector.collect();
-        }
-        assert!(has_been_dropped(), "Value wasn't dropped when expected");
+        assert_eq!(collector.collect(), num_entries);
+        assert_eq!(
+            count.load(Ordering::Relaxed),
+            0,
+            "Value wasn't dropped when expected"
+        );
     }
 
     #[test]
-    fn test_collect_list() {
+    fn test_metrics() {
         let collector = GarbageCollector::default();
-        let num_entries = 10;
-        let count = Arc::new(AtomicUsize::new(num_entries));
-        let has_been_dropped = {
-            let count = count.clone();
-            move || count.load(Ordering::Relaxed) == 0
-        };
+        let value = Shared::new(collector.handle(), 10);
+        let owned = Owned::new(collector.handle(), 20);
+        assert_eq!(
+            collector.metrics(),
+            GarbageCollectorMetrics {
+                allocated: 2,
+                ..Default::default()
+            }
+        );
 
-        let make_command = |count| {
-            let value_ptr = Box::into_raw(Box::new(RefCounter::new(count)));
-            Box::into_raw(Box::new(DropCommand::new(value_ptr)))
-        };
+        drop(value);
+        drop(owned);
+        assert_eq!(
+            collector.metrics(),
+            GarbageCollectorMetrics {
+                allocated: 2,
+                pending: 2,
+                ..Default::default()
+            }
+        );
 
-        for _i in 0..num_entries {
-            collector.enqueue_drop(make_command(count.clone()));
+        collector.collect();
+        assert_eq!(
+            collector.metrics(),
+            GarbageCollectorMetrics {
+                collected: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_back_pressure_overflow() {
+        let collector = GarbageCollector::new(GarbageCollectorOptions {
+            queue_capacity: 2,
+            ..Default::default()
+        });
+        let count = Arc::new(AtomicUsize::new(5));
+        for _i in 0..5 {
+            drop(Shared::new(
+                collector.handle(),
+                RefCounter::new(count.clone()),
+            ));
         }
 
-        assert!(
-            !has_been_dropped(),
-            "Value has been dropped before collection"
-        );
-        unsafe {
-            collector.collect();
+        let metrics = collector.metrics();
+        assert_eq!(metrics.pending, 5);
+        assert_eq!(metrics.overflowed, 3);
+        assert_eq!(count.load(Ordering::Relaxed), 5);
+
+        assert_eq!(collector.collect(), 5);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+        assert_eq!(collector.metrics().pending, 0);
+    }
+
+    #[test]
+    fn test_back_pressure_drop_in_place() {
+        let collector = GarbageCollector::new(GarbageCollectorOptions {
+            queue_capacity: 2,
+            back_pressure: BackPressure::DropInPlace,
+            ..Default::default()
+        });
+        let count = Arc::new(AtomicUsize::new(5));
+        for _i in 0..5 {
+            drop(Shared::new(
+                collector.handle(),
+                RefCounter::new(count.clone()),
+            ));
         }
-        assert!(has_been_dropped(), "Value wasn't dropped when expected");
+
+        let metrics = collector.metrics();
+        assert_eq!(metrics.pending, 2);
+        assert_eq!(metrics.dropped_in_place, 3);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        assert_eq!(collector.collect(), 2);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
     }
 
     #[test]
-    fn test_shared_integration_test() {
-        let collector = GarbageCollector::default();
+    fn test_background_thread_collects() {
+        let mut collector = GarbageCollector::start(GarbageCollectorOptions {
+            collect_interval: Duration::from_millis(10),
+            ..Default::default()
+        });
+        {
+            let _s1 = Shared::new(collector.handle(), 10);
+            let _s2 = Shared::new(collector.handle(), 10);
+            assert_eq!(collector.metrics().allocated, 2);
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while collector.metrics().allocated != 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(collector.metrics().allocated, 0);
+        collector.stop().unwrap();
+    }
+
+    #[test]
+    fn test_values_released_after_the_collector_is_dropped_are_collected() {
         let count = Arc::new(AtomicUsize::new(1));
-        let has_been_dropped = {
-            let count = count.clone();
-            move || count.load(Ordering::Relaxed) != 1
+        let value = {
+            let collector = GarbageCollector::default();
+            Shared::new(collector.handle(), RefCounter::new(count.clone()))
         };
-        let value = Shared::new(collector.handle(), RefCounter::new(count));
-
-        assert!(
-            !has_been_dropped(),
-            "Value has been dropped before collection"
-        );
-        unsafe {
-            collector.collect();
-        }
-        assert!(
-            !has_been_dropped(),
-            "Value has been dropped but variable is still around"
-        );
+        assert_eq!(count.load(Ordering::Relaxed), 1);
         drop(value);
-        assert!(
-            !has_been_dropped(),
-            "Value has been dropped before collection"
-        );
-        unsafe {
-            collector.collect();
-        }
-        assert!(has_been_dropped(), "Value wasn't dropped when expected");
+        assert_eq!(count.load(Ordering::Relaxed), 0);
     }
 }
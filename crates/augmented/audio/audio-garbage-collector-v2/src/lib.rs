@@ -20,12 +20,87 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use crate::collector::GarbageCollector;
-use crate::node::Node;
-use crate::shared::SharedGeneric;
+
+//! Smart pointers that get de-allocated on a background thread when dropped, for use in
+//! real-time systems.
+//!
+//! * [`Shared`] is a reference-counted pointer, [`Owned`] a uniquely owned one. When the last
+//!   reference is dropped the value is pushed onto the collector's lock-free queue instead of
+//!   being de-allocated on the current thread.
+//! * [`SharedCell`] holds a [`Shared`] value that can be read from the audio-thread and
+//!   replaced from other threads, using epoch based reclamation so that readers never block.
+//! * [`GarbageCollector`] owns the queue and drops its values, either manually with
+//!   [`GarbageCollector::collect`] or on a background thread started with
+//!   [`GarbageCollector::start`]. [`GarbageCollectorMetrics`] reports how many drops are pending.
+//! * [`BackPressure`] configures what happens when the bounded queue is full.
+//!
+//! The free functions mirror the `audio-garbage-collector` crate, which can switch to this
+//! implementation with its `v2` feature.
+//!
+//! The concurrency tests run with `loom`:
+//!
+//! ```shell
+//! RUSTFLAGS="--cfg loom" cargo test -p audio-garbage-collector-v2 --release
+//! ```
+
+pub use crate::collector::{
+    BackPressure, GarbageCollector, GarbageCollectorError, GarbageCollectorMetrics,
+    GarbageCollectorOptions, Handle,
+};
+pub use crate::owned::Owned;
+pub use crate::shared::Shared;
+pub use crate::shared_cell::SharedCell;
 
 pub mod collector;
 mod node;
-pub mod shared;
+mod owned;
+mod shared;
+mod shared_cell;
+mod sync;
+
+#[cfg(not(loom))]
+static GARBAGE_COLLECTOR: std::sync::OnceLock<GarbageCollector> = std::sync::OnceLock::new();
+
+/// Return a reference to a global GC instance, collecting on a background thread
+#[cfg(not(loom))]
+pub fn current() -> &'static GarbageCollector {
+    GARBAGE_COLLECTOR.get_or_init(|| GarbageCollector::start(GarbageCollectorOptions::default()))
+}
+
+/// Return a handle to a global GC instance
+#[cfg(not(loom))]
+pub fn handle() -> &'static Handle {
+    current().handle()
+}
+
+/// Create a new [`Shared`] value using the default global [`GarbageCollector`] instance.
+#[cfg(not(loom))]
+pub fn make_shared<T: Send + 'static>(value: T) -> Shared<T> {
+    Shared::new(handle(), value)
+}
+
+/// Create a new [`SharedCell`] value using the default global [`GarbageCollector`] instance.
+#[cfg(not(loom))]
+pub fn make_shared_cell<T: Send + 'static>(value: T) -> SharedCell<T> {
+    SharedCell::new(make_shared(value))
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_make_shared() {
+        let value = make_shared(10);
+        assert_eq!(*value, 10);
+    }
 
-pub type Shared<T> = SharedGeneric<T, GarbageCollector, Node<T>>;
+    #[test]
+    fn test_make_shared_cell() {
+        let cell = make_shared_cell(10);
+        cell.set(make_shared(22));
+        assert_eq!(*cell.get(), 22);
+        drop(cell);
+        current().collect();
+    }
+}
@@ -20,88 +20,66 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use std::ffi::c_void;
-use std::sync::atomic::AtomicUsize;
 
-pub trait NodeTrait<T> {
-    fn with_value(value: T) -> Self;
-    fn drop_command(&self) -> *mut DropCommand;
-    fn value(&self) -> *mut T;
-    fn ref_count(&self) -> &AtomicUsize;
-}
+use std::ptr::NonNull;
 
-pub struct Node<T: ?Sized> {
-    value: *mut T,
-    ref_count: AtomicUsize,
-    drop_command: *mut DropCommand,
-}
+use crate::sync::{AtomicPtr, AtomicUsize};
 
-impl<T> Node<T> {
-    pub fn new(value: *mut T) -> Self {
-        Node {
-            value,
-            ref_count: AtomicUsize::new(0),
-            drop_command: Box::into_raw(Box::new(DropCommand::new(value))),
-        }
-    }
+/// Type-erased part of a [`Node`], which is what the collector queues hold.
+#[repr(C)]
+pub(crate) struct NodeHeader {
+    pub(crate) ref_count: AtomicUsize,
+    /// Link used when the node is pushed onto the collector's overflow list
+    pub(crate) next: AtomicPtr<NodeHeader>,
+    drop: unsafe fn(NonNull<NodeHeader>),
 }
 
-impl<T> NodeTrait<T> for Node<T> {
-    fn with_value(value: T) -> Self {
-        let value_ptr = Box::into_raw(Box::new(value));
-        Self::new(value_ptr)
-    }
-
-    fn drop_command(&self) -> *mut DropCommand {
-        self.drop_command
-    }
-
-    fn value(&self) -> *mut T {
-        self.value
-    }
-
-    fn ref_count(&self) -> &AtomicUsize {
-        &self.ref_count
+impl NodeHeader {
+    /// # Safety
+    /// `node` must have been created by [`Node::allocate`] and not be referenced anymore.
+    pub(crate) unsafe fn drop_node(node: NonNull<NodeHeader>) {
+        let drop = node.as_ref().drop;
+        drop(node);
     }
 }
 
-pub struct DropCommand {
-    value: *mut c_void,
-    drop: unsafe fn(*mut c_void),
+/// A single heap allocation holding a reference-counted value.
+#[repr(C)]
+pub(crate) struct Node<T> {
+    pub(crate) header: NodeHeader,
+    pub(crate) value: T,
 }
 
-impl DropCommand {
-    pub(crate) fn new<T>(value: *mut T) -> Self {
-        DropCommand {
-            value: value as *mut c_void,
-            drop: drop_value::<T>,
-        }
-    }
-
-    pub unsafe fn do_drop(&self) {
-        (self.drop)(self.value);
+impl<T> Node<T> {
+    /// Allocate a node holding `value` with a reference count of 1.
+    pub(crate) fn allocate(value: T) -> NonNull<Node<T>> {
+        let node = Box::new(Node {
+            header: NodeHeader {
+                ref_count: AtomicUsize::new(1),
+                next: AtomicPtr::new(std::ptr::null_mut()),
+                drop: drop_node::<T>,
+            },
+            value,
+        });
+        // Safety: Box pointers are never null
+        unsafe { NonNull::new_unchecked(Box::into_raw(node)) }
     }
 }
 
-unsafe fn drop_value<T>(value: *mut c_void) {
-    let _ = Box::<T>::from_raw(value as *mut T);
+unsafe fn drop_node<T>(node: NonNull<NodeHeader>) {
+    // `Node` is `repr(C)` with the header as its first field
+    drop(Box::from_raw(node.cast::<Node<T>>().as_ptr()));
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(loom)))]
 mod test {
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::atomic::Ordering;
     use std::sync::Arc;
 
     use super::*;
 
     struct RefCounter {
-        count: Arc<AtomicUsize>,
-    }
-
-    impl RefCounter {
-        fn new(count: Arc<AtomicUsize>) -> Self {
-            RefCounter { count }
-        }
+        count: Arc<std::sync::atomic::AtomicUsize>,
     }
 
     impl Drop for RefCounter {
@@ -111,39 +89,18 @@ mod test {
     }
 
     #[test]
-    fn test_drop_command_idea() {
-        let count = Arc::new(AtomicUsize::new(1));
-        let has_been_dropped = {
-            let count = count.clone();
-            move || count.load(Ordering::Relaxed) != 1
-        };
-
-        let ref_counter = RefCounter::new(count);
-        let ref_counter = Box::into_raw(Box::new(ref_counter));
-        let ref_counter_drop_command = DropCommand::new(ref_counter);
-
-        assert!(!has_been_dropped(), "Value was dropped before expected");
+    fn test_node_allocate_and_drop() {
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(1));
+        let node = Node::allocate(RefCounter {
+            count: count.clone(),
+        });
         unsafe {
-            ref_counter_drop_command.do_drop();
+            assert_eq!(node.as_ref().header.ref_count.load(Ordering::Relaxed), 1);
         }
-        assert!(has_been_dropped(), "Value has been dropped properly");
-    }
-
-    #[test]
-    fn test_node_create() {
-        let count = Arc::new(AtomicUsize::new(1));
-        let has_been_dropped = {
-            let count = count.clone();
-            move || count.load(Ordering::Relaxed) != 1
-        };
-        let ref_counter = RefCounter::new(count);
-        let node = Node::with_value(ref_counter);
-
-        assert!(!has_been_dropped(), "Value was dropped before expected");
-        let drop_command = node.drop_command;
+        assert_eq!(count.load(Ordering::Relaxed), 1, "Value was dropped early");
         unsafe {
-            (*drop_command).do_drop();
+            NodeHeader::drop_node(node.cast());
         }
-        assert!(has_been_dropped(), "Value has been dropped properly");
+        assert_eq!(count.load(Ordering::Relaxed), 0, "Value wasn't dropped");
     }
 }
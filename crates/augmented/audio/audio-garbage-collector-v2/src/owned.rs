@@ -0,0 +1,95 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+use crate::collector::Handle;
+use crate::node::Node;
+
+/// A uniquely owned pointer which hands its value to a collector when dropped.
+pub struct Owned<T> {
+    node: NonNull<Node<T>>,
+    handle: Handle,
+    phantom: PhantomData<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for Owned<T> {}
+unsafe impl<T: Sync> Sync for Owned<T> {}
+
+impl<T: Send + 'static> Owned<T> {
+    pub fn new(handle: &Handle, value: T) -> Self {
+        handle.on_allocate();
+        Owned {
+            node: Node::allocate(value),
+            handle: handle.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &self.node.as_ref().value }
+    }
+}
+
+impl<T> DerefMut for Owned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut self.node.as_mut().value }
+    }
+}
+
+impl<T> Drop for Owned<T> {
+    fn drop(&mut self) {
+        self.handle.enqueue(self.node.cast());
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Owned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use crate::GarbageCollector;
+
+    use super::*;
+
+    #[test]
+    fn test_owned_is_collected() {
+        let collector = GarbageCollector::default();
+        let mut owned = Owned::new(collector.handle(), vec![1, 2, 3]);
+        owned.push(4);
+        assert_eq!(*owned, vec![1, 2, 3, 4]);
+        drop(owned);
+        assert_eq!(collector.metrics().pending, 1);
+        assert_eq!(collector.collect(), 1);
+    }
+}
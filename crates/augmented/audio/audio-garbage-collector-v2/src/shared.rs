@@ -20,86 +20,126 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
+
+use std::fmt;
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+use crate::collector::Handle;
+use crate::node::Node;
+use crate::sync::{fence, Ordering};
+
+/// A reference-counted pointer which hands its value to a collector instead of de-allocating it
+/// when the last reference is dropped.
+///
+/// Cloning and dropping never allocate, de-allocate or block.
+pub struct Shared<T> {
+    node: NonNull<Node<T>>,
+    handle: Handle,
+    phantom: PhantomData<Node<T>>,
+}
 
-use crate::collector::GarbageCollectorRef;
-use crate::node::NodeTrait;
+unsafe impl<T: Send + Sync> Send for Shared<T> {}
+unsafe impl<T: Send + Sync> Sync for Shared<T> {}
 
-pub struct SharedGeneric<T, Handle: GarbageCollectorRef, Node: NodeTrait<T>> {
-    inner: AtomicPtr<Node>,
-    handle: *mut Handle,
-    phantom: PhantomData<T>,
+impl<T: Send + 'static> Shared<T> {
+    pub fn new(handle: &Handle, value: T) -> Self {
+        handle.on_allocate();
+        Shared {
+            node: Node::allocate(value),
+            handle: handle.clone(),
+            phantom: PhantomData,
+        }
+    }
 }
 
-impl<T, Handle: GarbageCollectorRef, Node: NodeTrait<T>> SharedGeneric<T, Handle, Node> {
-    pub fn new(handle: *mut Handle, value: T) -> Self {
-        let inner = Box::into_raw(Box::new(Node::with_value(value)));
-        SharedGeneric {
-            inner: AtomicPtr::new(inner),
+impl<T> Shared<T> {
+    /// Take ownership of one reference to `node`.
+    pub(crate) unsafe fn from_raw(node: NonNull<Node<T>>, handle: Handle) -> Self {
+        Shared {
+            node,
             handle,
-            phantom: PhantomData::default(),
+            phantom: PhantomData,
         }
     }
+
+    /// Give up this reference without decrementing the count.
+    pub(crate) fn into_raw(this: Self) -> NonNull<Node<T>> {
+        let this = std::mem::ManuallyDrop::new(this);
+        // Safety: `this` is never dropped, so the handle is read exactly once
+        drop(unsafe { std::ptr::read(&this.handle) });
+        this.node
+    }
+
+    pub(crate) fn collector_handle(this: &Self) -> &Handle {
+        &this.handle
+    }
+
+    /// Returns true if both pointers point at the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.node == other.node
+    }
+
+    /// The number of references to the value.
+    pub fn ref_count(this: &Self) -> usize {
+        unsafe { this.node.as_ref() }
+            .header
+            .ref_count
+            .load(Ordering::Relaxed)
+    }
 }
 
-impl<T, Handle: GarbageCollectorRef, Node: NodeTrait<T>> Drop for SharedGeneric<T, Handle, Node> {
-    fn drop(&mut self) {
-        unsafe {
-            let inner = self.inner.load(Ordering::Acquire);
-            let count = (*inner).ref_count().fetch_sub(1, Ordering::Release);
-            if count <= 1 {
-                (*self.handle).enqueue_drop((*inner).drop_command());
-                let _ = Box::from_raw(inner);
-            }
-        }
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &self.node.as_ref().value }
     }
 }
 
-impl<T, Handle: GarbageCollectorRef, Node: NodeTrait<T>> Clone for SharedGeneric<T, Handle, Node> {
+impl<T> Clone for Shared<T> {
     fn clone(&self) -> Self {
-        unsafe {
-            let inner = self.inner.load(Ordering::Acquire);
-            let _ = (*inner).ref_count().fetch_add(1, Ordering::Release);
+        unsafe { self.node.as_ref() }
+            .header
+            .ref_count
+            .fetch_add(1, Ordering::Relaxed);
+        Shared {
+            node: self.node,
+            handle: self.handle.clone(),
+            phantom: PhantomData,
         }
+    }
+}
 
-        SharedGeneric {
-            inner: AtomicPtr::new(self.inner.load(Ordering::Relaxed)),
-            handle: self.handle,
-            phantom: self.phantom,
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let count = unsafe { self.node.as_ref() }
+            .header
+            .ref_count
+            .fetch_sub(1, Ordering::Release);
+        if count == 1 {
+            fence(Ordering::Acquire);
+            self.handle.enqueue(self.node.cast());
         }
     }
 }
 
-#[cfg(test)]
+impl<T: fmt::Debug> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
 mod test {
     use std::sync::atomic::AtomicUsize;
     use std::sync::Arc;
 
-    use atomic_queue;
-
-    use crate::node::DropCommand;
+    use crate::GarbageCollector;
 
     use super::*;
 
-    struct MockGarbageCollector {
-        queue: atomic_queue::Queue<*mut DropCommand>,
-    }
-
-    impl Default for MockGarbageCollector {
-        fn default() -> Self {
-            MockGarbageCollector {
-                queue: atomic_queue::Queue::new(10),
-            }
-        }
-    }
-
-    impl GarbageCollectorRef for MockGarbageCollector {
-        fn enqueue_drop(&self, drop_command: *mut DropCommand) {
-            self.queue.push(drop_command);
-        }
-    }
-
     struct RefCounter {
         count: Arc<AtomicUsize>,
     }
@@ -116,63 +156,12 @@ mod test {
         }
     }
 
-    struct MockNode<T> {
-        value: Option<*mut T>,
-        drop_command: *mut DropCommand,
-        ref_count: AtomicUsize,
-        // Testing counter when this struct is dropped
-        drop_counter: Arc<AtomicUsize>,
-    }
-
-    impl<T> MockNode<T> {
-        fn set_drop_counter(&mut self, counter: Arc<AtomicUsize>) {
-            self.drop_counter = counter;
-        }
-
-        fn set_drop_command(&mut self, drop_command: *mut DropCommand) {
-            self.drop_command = drop_command;
-        }
-    }
-
-    impl<T> Drop for MockNode<T> {
-        fn drop(&mut self) {
-            self.drop_counter.fetch_sub(1, Ordering::Relaxed);
-        }
-    }
-
-    impl<T> NodeTrait<T> for MockNode<T> {
-        fn with_value(value: T) -> Self {
-            MockNode {
-                value: Some(Box::into_raw(Box::new(value))),
-                drop_command: mock_ptr(66),
-                ref_count: AtomicUsize::new(1),
-                drop_counter: Arc::new(AtomicUsize::new(1)),
-            }
-        }
-
-        fn drop_command(&self) -> *mut DropCommand {
-            self.drop_command
-        }
-
-        fn value(&self) -> *mut T {
-            let value_ref = self.value.unwrap();
-            value_ref as *const T as *mut T
-        }
-
-        fn ref_count(&self) -> &AtomicUsize {
-            &self.ref_count
-        }
-    }
-
     #[test]
     fn test_create_shared_does_not_drop_its_contents() {
-        let mock_handle = Box::into_raw(Box::default());
+        let collector = GarbageCollector::default();
         let count = Arc::new(AtomicUsize::new(1));
-        let value = RefCounter::new(count.clone());
-        let _shared = SharedGeneric::<RefCounter, MockGarbageCollector, MockNode<RefCounter>>::new(
-            mock_handle,
-            value,
-        );
+        let _shared = Shared::new(collector.handle(), RefCounter::new(count.clone()));
+        collector.collect();
         assert_eq!(
             count.load(Ordering::Relaxed),
             1,
@@ -182,150 +171,101 @@ mod test {
 
     #[test]
     fn test_drop_shared_ref() {
-        let mock_handle = Box::into_raw(Box::default());
-        let mock_node_count = Arc::new(AtomicUsize::new(1));
-        let value_count = Arc::new(AtomicUsize::new(1));
-        let value = RefCounter::new(value_count.clone());
-
-        let shared = SharedGeneric::<RefCounter, MockGarbageCollector, MockNode<RefCounter>>::new(
-            mock_handle,
-            value,
-        );
-        set_drop_command(&shared, mock_ptr(1));
-        {
-            let inner = shared.inner.load(Ordering::Relaxed);
-            unsafe {
-                (*inner).set_drop_counter(mock_node_count.clone());
-            }
-        }
-
-        let current_count = get_node_ref_count(&shared);
-        assert_eq!(
-            current_count, 1,
-            "Shared ref count wasn't 1 though there's only 1 ref"
-        );
-        // Assert node was not dropped
-        assert_eq!(
-            mock_node_count.load(Ordering::Relaxed),
-            1,
-            "Shared dropped node memory while using it"
-        );
+        let collector = GarbageCollector::default();
+        let count = Arc::new(AtomicUsize::new(1));
+        let shared = Shared::new(collector.handle(), RefCounter::new(count.clone()));
+        assert_eq!(Shared::ref_count(&shared), 1);
 
         drop(shared);
-        // Assert node was dropped
-        assert_eq!(
-            mock_node_count.load(Ordering::Relaxed),
-            0,
-            "Shared leaked node memory"
-        );
-        // Assert value was NOT dropped
         assert_eq!(
-            value_count.load(Ordering::Relaxed),
+            count.load(Ordering::Relaxed),
             1,
             "Shared dropped the value on the current thread"
         );
+        assert_eq!(collector.metrics().pending, 1, "Value was not enqueued");
 
-        // Drop command should have been pushed into drop queue
-        let queue_len = unsafe { (*mock_handle).queue.len() };
-        assert_eq!(queue_len, 1, "Drop command was not enqueued");
-        // It was our drop command that was pushed
-        let drop_command = unsafe { (*mock_handle).queue.pop().unwrap() };
-        assert_eq!(drop_command, mock_ptr(1), "Drop command is invalid");
+        collector.collect();
+        assert_eq!(count.load(Ordering::Relaxed), 0, "Value wasn't collected");
     }
 
     #[test]
     fn test_clone() {
-        let mock_handle = Box::into_raw(Box::default());
-        let count = Arc::new(AtomicUsize::new(1));
-        let value = RefCounter::new(count);
+        let collector = GarbageCollector::default();
+        let shared1 = Shared::new(collector.handle(), 10);
+        assert_eq!(Shared::ref_count(&shared1), 1);
 
-        let shared1 = SharedGeneric::<RefCounter, MockGarbageCollector, MockNode<RefCounter>>::new(
-            mock_handle,
-            value,
-        );
-        set_drop_command(&shared1, mock_ptr(1));
-        let current_count = get_node_ref_count(&shared1);
-        assert_eq!(
-            current_count, 1,
-            "Ref count is not 1, but there's only 1 ref"
-        );
-
-        #[allow(clippy::redundant_clone)]
         let shared2 = shared1.clone();
-        let current_count = get_node_ref_count(&shared2);
-        assert_eq!(current_count, 2, "Ref count is not 2, but there are 2 refs");
+        assert_eq!(Shared::ref_count(&shared2), 2);
+        assert!(Shared::ptr_eq(&shared1, &shared2));
+        assert_eq!(*shared2, 10);
     }
 
     #[test]
-    fn test_clone_shared_does_not_drop_its_contents_when_1_ref_is_dropped() {
-        let mock_handle = Box::into_raw(Box::default());
+    fn test_clone_shared_drops_when_all_refs_are_gone() {
+        let collector = GarbageCollector::default();
         let count = Arc::new(AtomicUsize::new(1));
-        let value = RefCounter::new(count.clone());
-        let shared = SharedGeneric::<RefCounter, MockGarbageCollector, MockNode<RefCounter>>::new(
-            mock_handle,
-            value,
-        );
-        let _shared2 = shared.clone();
-        assert_eq!(
-            count.load(Ordering::Relaxed),
-            1,
-            "contents were dropped but there are still refs"
-        );
+        let shared = Shared::new(collector.handle(), RefCounter::new(count.clone()));
+        let shared2 = shared.clone();
+
         drop(shared);
+        collector.collect();
         assert_eq!(
             count.load(Ordering::Relaxed),
             1,
             "contents were dropped but there are still refs"
         );
-    }
 
-    #[test]
-    fn test_clone_shared_drops_when_all_refs_are_gone() {
-        let mock_handle = Box::into_raw(Box::default());
-        let count = Arc::new(AtomicUsize::new(1));
-        let value = RefCounter::new(count.clone());
-        {
-            let shared1 =
-                SharedGeneric::<RefCounter, MockGarbageCollector, MockNode<RefCounter>>::new(
-                    mock_handle,
-                    value,
-                );
-            set_drop_command(&shared1, mock_ptr(2));
-            let _shared2 = shared1;
-        }
+        drop(shared2);
         assert_eq!(
             count.load(Ordering::Relaxed),
             1,
-            "contents were dropped on the main thread"
+            "contents were dropped on the current thread"
         );
-
-        // Drop command should have been pushed into drop queue
-        let queue_len = unsafe { (*mock_handle).queue.len() };
-        assert_eq!(queue_len, 1, "Drop command was not enqueued");
-        // It was our drop command that was pushed
-        let drop_command = unsafe { (*mock_handle).queue.pop().unwrap() };
-        assert_eq!(drop_command, mock_ptr(2), "Drop command is invalid");
+        collector.collect();
+        assert_eq!(count.load(Ordering::Relaxed), 0);
     }
+}
 
-    fn get_node_ref_count(
-        shared: &SharedGeneric<RefCounter, MockGarbageCollector, MockNode<RefCounter>>,
-    ) -> usize {
-        let inner = shared.inner.load(Ordering::Relaxed);
+#[cfg(all(test, loom))]
+mod loom_test {
+    use loom::sync::atomic::AtomicUsize;
+    use loom::sync::Arc;
+
+    use crate::GarbageCollector;
+
+    use super::*;
 
-        unsafe { (*inner).ref_count.load(Ordering::Relaxed) }
+    struct RefCounter {
+        count: Arc<AtomicUsize>,
     }
 
-    fn set_drop_command(
-        shared: &SharedGeneric<RefCounter, MockGarbageCollector, MockNode<RefCounter>>,
-        drop_command: *mut DropCommand,
-    ) {
-        let inner = shared.inner.load(Ordering::Relaxed);
-        unsafe {
-            (*inner).set_drop_command(drop_command);
+    impl Drop for RefCounter {
+        fn drop(&mut self) {
+            self.count.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    fn mock_ptr<T>(value: usize) -> *mut T {
-        value as *mut T
+    #[test]
+    fn test_concurrent_drops_enqueue_once() {
+        loom::model(|| {
+            let collector = GarbageCollector::default();
+            let drops = Arc::new(AtomicUsize::new(0));
+            let shared = Shared::new(
+                collector.handle(),
+                RefCounter {
+                    count: drops.clone(),
+                },
+            );
+            let other = shared.clone();
+
+            let thread = loom::thread::spawn(move || drop(other));
+            drop(shared);
+            thread.join().unwrap();
+
+            assert_eq!(collector.metrics().pending, 1);
+            assert_eq!(drops.load(Ordering::Relaxed), 0);
+            collector.collect();
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
     }
 }
@@ -0,0 +1,264 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+use crate::collector::Handle;
+use crate::node::Node;
+use crate::shared::Shared;
+use crate::sync::{fence, spin_loop, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// A mutable slot holding a [`Shared`] value, which can be read from the audio-thread while
+/// being replaced from another thread.
+///
+/// Reclamation is epoch based: readers register in the slot of the current epoch while they take
+/// their reference, and writers flip the epoch after swapping the value and wait for the readers
+/// of the previous epoch to leave before releasing the old value. Reads never block, they may only
+/// retry if a writer flips the epoch while they register. Writers are serialized and wait for at
+/// most the few instructions readers spend registered.
+pub struct SharedCell<T> {
+    value: AtomicPtr<Node<T>>,
+    handle: Handle,
+    epoch: AtomicUsize,
+    readers: [AtomicUsize; 2],
+    is_writing: AtomicBool,
+    phantom: PhantomData<Shared<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for SharedCell<T> {}
+unsafe impl<T: Send + Sync> Sync for SharedCell<T> {}
+
+impl<T> SharedCell<T> {
+    pub fn new(value: Shared<T>) -> Self {
+        let handle = Shared::collector_handle(&value).clone();
+        SharedCell {
+            value: AtomicPtr::new(Shared::into_raw(value).as_ptr()),
+            handle,
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            is_writing: AtomicBool::new(false),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get a reference to the current value.
+    pub fn get(&self) -> Shared<T> {
+        let epoch = self.pin();
+        let node = self.value.load(Ordering::SeqCst);
+        // Safety: the writer which replaces `node` waits for this epoch's readers before
+        // releasing it
+        unsafe { (*node).header.ref_count.fetch_add(1, Ordering::Relaxed) };
+        self.readers[epoch & 1].fetch_sub(1, Ordering::Release);
+
+        // Safety: the pointer is never null and we just took a reference
+        unsafe { Shared::from_raw(NonNull::new_unchecked(node), self.handle.clone()) }
+    }
+
+    /// Replace the value, releasing the previous one.
+    pub fn set(&self, value: Shared<T>) {
+        drop(self.replace(value));
+    }
+
+    /// Replace the value, returning the previous one.
+    pub fn replace(&self, value: Shared<T>) -> Shared<T> {
+        while self
+            .is_writing
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+
+        let previous = self
+            .value
+            .swap(Shared::into_raw(value).as_ptr(), Ordering::SeqCst);
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst);
+        // Pairs with the fence in `pin`: either the reader sees the new epoch and retries, or
+        // this sees its registration
+        fence(Ordering::SeqCst);
+        while self.readers[epoch & 1].load(Ordering::SeqCst) != 0 {
+            spin_loop();
+        }
+
+        self.is_writing.store(false, Ordering::Release);
+        // Safety: the cell's reference is transferred to the returned value
+        unsafe { Shared::from_raw(NonNull::new_unchecked(previous), self.handle.clone()) }
+    }
+
+    /// Register as a reader in the current epoch, returning it.
+    fn pin(&self) -> usize {
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            self.readers[epoch & 1].fetch_add(1, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            if self.epoch.load(Ordering::SeqCst) == epoch {
+                return epoch;
+            }
+            self.readers[epoch & 1].fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+impl<T> Drop for SharedCell<T> {
+    fn drop(&mut self) {
+        let node = self.value.load(Ordering::Relaxed);
+        // Safety: the cell owns one reference to its value
+        drop(unsafe { Shared::from_raw(NonNull::new_unchecked(node), self.handle.clone()) });
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SharedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedCell").field(&self.get()).finish()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use crate::GarbageCollector;
+
+    use super::*;
+
+    #[test]
+    fn test_get_and_set() {
+        let collector = GarbageCollector::default();
+        let cell = SharedCell::new(Shared::new(collector.handle(), 10));
+        assert_eq!(*cell.get(), 10);
+
+        cell.set(Shared::new(collector.handle(), 22));
+        assert_eq!(*cell.get(), 22);
+        assert_eq!(collector.metrics().pending, 1);
+        collector.collect();
+        assert_eq!(collector.metrics().allocated, 1);
+    }
+
+    #[test]
+    fn test_replace_returns_previous_value() {
+        let collector = GarbageCollector::default();
+        let cell = SharedCell::new(Shared::new(collector.handle(), 10));
+        let reader = cell.get();
+        let previous = cell.replace(Shared::new(collector.handle(), 22));
+        assert!(Shared::ptr_eq(&reader, &previous));
+        assert_eq!(Shared::ref_count(&previous), 2);
+    }
+
+    #[test]
+    fn test_drop_cell_releases_value() {
+        let collector = GarbageCollector::default();
+        let cell = SharedCell::new(Shared::new(collector.handle(), 10));
+        drop(cell);
+        assert_eq!(collector.metrics().pending, 1);
+        collector.collect();
+        assert_eq!(collector.metrics().allocated, 0);
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        let collector = GarbageCollector::default();
+        let cell = std::sync::Arc::new(SharedCell::new(Shared::new(collector.handle(), 0)));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                std::thread::spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..10000 {
+                        let value = *cell.get();
+                        assert!(value >= last);
+                        last = value;
+                    }
+                })
+            })
+            .collect();
+        for i in 1..1000 {
+            cell.set(Shared::new(collector.handle(), i));
+            collector.collect();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        drop(cell);
+        collector.collect();
+        assert_eq!(collector.metrics().allocated, 0);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use loom::sync::atomic::AtomicBool;
+    use loom::sync::Arc;
+
+    use crate::GarbageCollector;
+
+    use super::*;
+
+    struct Value {
+        index: usize,
+        dropped: Arc<[AtomicBool; 2]>,
+    }
+
+    impl Drop for Value {
+        fn drop(&mut self) {
+            self.dropped[self.index].store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_get_is_never_released_by_concurrent_set() {
+        loom::model(|| {
+            let collector = GarbageCollector::default();
+            let dropped = Arc::new([AtomicBool::new(false), AtomicBool::new(false)]);
+            let make_value = |index| {
+                Shared::new(
+                    collector.handle(),
+                    Value {
+                        index,
+                        dropped: dropped.clone(),
+                    },
+                )
+            };
+            let cell = Arc::new(SharedCell::new(make_value(0)));
+
+            let reader = {
+                let cell = cell.clone();
+                loom::thread::spawn(move || {
+                    let value = cell.get();
+                    assert!(!value.dropped[value.index].load(Ordering::SeqCst));
+                    value.index
+                })
+            };
+
+            cell.set(make_value(1));
+            collector.collect();
+
+            let index = reader.join().unwrap();
+            assert!(index == 0 || index == 1);
+            assert!(!dropped[1].load(Ordering::SeqCst));
+            drop(cell);
+            collector.collect();
+            assert!(dropped[0].load(Ordering::SeqCst));
+            assert!(dropped[1].load(Ordering::SeqCst));
+        });
+    }
+}
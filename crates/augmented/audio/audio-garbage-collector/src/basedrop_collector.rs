@@ -0,0 +1,154 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::sync::{Arc, Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::Duration;
+
+use basedrop::{Collector, Handle};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_thread as thread;
+
+use crate::GarbageCollectorError;
+
+struct GarbageCollectorState {
+    running: bool,
+    collect_interval: Duration,
+}
+
+/// Wraps [`basedrop::Collector`] with a polling GC thread.
+///
+/// This drops reference counted variables on a dedicated thread to avoid deallocating from the
+/// audio thread.
+pub struct GarbageCollector {
+    collector: Arc<Mutex<Collector>>,
+    state: Arc<Mutex<GarbageCollectorState>>,
+    thread: Option<thread::JoinHandle<()>>,
+    handle: Handle,
+}
+
+impl Default for GarbageCollector {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100))
+    }
+}
+
+impl GarbageCollector {
+    /// Create the collector and start the garbage collector thread
+    pub fn new(collect_interval: Duration) -> Self {
+        let collector = Collector::new();
+        let handle = collector.handle();
+        let collector = Arc::new(Mutex::new(collector));
+
+        let state = Arc::new(Mutex::new(GarbageCollectorState {
+            running: true,
+            collect_interval,
+        }));
+
+        let thread = {
+            let collector = collector.clone();
+            let state = state.clone();
+            thread::Builder::new()
+                .name(String::from("gc-thread"))
+                .spawn(move || run_collector_loop(collector, state))
+                .expect("Failed to start GC thread")
+        };
+
+        GarbageCollector {
+            collector,
+            thread: Some(thread),
+            handle,
+            state,
+        }
+    }
+
+    /// Stop & join the collector thread.
+    pub fn stop(&mut self) -> Result<(), GarbageCollectorError> {
+        self.state
+            .lock()
+            .map(|mut state| {
+                state.running = false;
+            })
+            .map_err(|_| GarbageCollectorError::LockError)?;
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .map_err(|_| GarbageCollectorError::JoinError)?;
+        }
+        Ok(())
+    }
+
+    /// Get a handle to the collector. Does not lock.
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Force GC on the current thread & return whether it was successful.
+    /// Tries to acquire a lock on the collector.
+    #[allow(dead_code)]
+    pub fn blocking_collect(&self) -> bool {
+        self.collector
+            .lock()
+            .map(|mut collector| {
+                collector.collect();
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Gets the number of live allocations associated with the `Collector`.
+    /// Tries to acquire a lock on the collector.
+    #[allow(dead_code)]
+    pub fn blocking_alloc_count(&self) -> usize {
+        self.collector
+            .lock()
+            .map(|collector| collector.alloc_count())
+            .unwrap_or(0)
+    }
+}
+
+fn run_collector_loop(collector: Arc<Mutex<Collector>>, state: Arc<Mutex<GarbageCollectorState>>) {
+    log::info!("Garbage collector thread started");
+    loop {
+        let (collect_interval, is_running) = state
+            .lock()
+            .map(|state| (state.collect_interval, state.running))
+            .unwrap_or((Duration::default(), false));
+        if !is_running {
+            log::info!("Garbage collector thread stopping");
+            return;
+        }
+
+        let collector = collector.lock().map(|mut collector| {
+            collector.collect();
+        });
+        if collector.is_err() {
+            log::warn!("Garbage collector thread failing due to lock error");
+            return;
+        }
+
+        std::thread::sleep(collect_interval);
+    }
+}
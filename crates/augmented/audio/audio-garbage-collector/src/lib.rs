@@ -31,19 +31,27 @@
 //!
 //! If references are created and dropped very frequently this strategy is not adequate. This also
 //! adds some small overhead.
+//!
+//! # `v2` feature
+//! Enabling the `v2` feature swaps `basedrop` for `audio-garbage-collector-v2`, keeping the same
+//! API for crates that only use the types and functions exported here.
 
-use std::sync::{Arc, Mutex};
-#[cfg(not(target_arch = "wasm32"))]
-use std::thread;
-use std::time::Duration;
-
-use basedrop::Collector;
+#[cfg(feature = "v2")]
+pub use audio_garbage_collector_v2::{Handle, Owned, Shared, SharedCell};
+#[cfg(not(feature = "v2"))]
 pub use basedrop::{Handle, Owned, Shared, SharedCell};
 use lazy_static::lazy_static;
 use thiserror::Error;
 
-#[cfg(target_arch = "wasm32")]
-use wasm_thread as thread;
+#[cfg(not(feature = "v2"))]
+pub use basedrop_collector::GarbageCollector;
+#[cfg(feature = "v2")]
+pub use v2_collector::GarbageCollector;
+
+#[cfg(not(feature = "v2"))]
+mod basedrop_collector;
+#[cfg(feature = "v2")]
+mod v2_collector;
 
 lazy_static! {
     static ref GARBAGE_COLLECTOR: GarbageCollector = GarbageCollector::default();
@@ -59,13 +67,13 @@ pub fn handle() -> &'static Handle {
     GARBAGE_COLLECTOR.handle()
 }
 
-/// Create a new [`basedrop::SharedCell`] value using the default global [`GarbageCollector`]
+/// Create a new [`SharedCell`] value using the default global [`GarbageCollector`]
 /// instance.
 pub fn make_shared_cell<T: Send + 'static>(value: T) -> SharedCell<T> {
     SharedCell::new(make_shared(value))
 }
 
-/// Create a new [`basedrop::Shared`] value using the default global [`GarbageCollector`]
+/// Create a new [`Shared`] value using the default global [`GarbageCollector`]
 /// instance.
 pub fn make_shared<T: Send + 'static>(value: T) -> Shared<T> {
     Shared::new(handle(), value)
@@ -82,129 +90,9 @@ pub enum GarbageCollectorError {
     JoinError,
 }
 
-struct GarbageCollectorState {
-    running: bool,
-    collect_interval: Duration,
-}
-
-/// Wraps [`basedrop::Collector`] with a polling GC thread.
-///
-/// This drops reference counted variables on a dedicated thread to avoid deallocating from the
-/// audio thread.
-pub struct GarbageCollector {
-    collector: Arc<Mutex<Collector>>,
-    state: Arc<Mutex<GarbageCollectorState>>,
-    thread: Option<thread::JoinHandle<()>>,
-    handle: Handle,
-}
-
-impl Default for GarbageCollector {
-    fn default() -> Self {
-        Self::new(Duration::from_millis(100))
-    }
-}
-
-impl GarbageCollector {
-    /// Create the collector and start the garbage collector thread
-    pub fn new(collect_interval: Duration) -> Self {
-        let collector = Collector::new();
-        let handle = collector.handle();
-        let collector = Arc::new(Mutex::new(collector));
-
-        let state = Arc::new(Mutex::new(GarbageCollectorState {
-            running: true,
-            collect_interval,
-        }));
-
-        let thread = {
-            let collector = collector.clone();
-            let state = state.clone();
-            thread::Builder::new()
-                .name(String::from("gc-thread"))
-                .spawn(move || run_collector_loop(collector, state))
-                .expect("Failed to start GC thread")
-        };
-
-        GarbageCollector {
-            collector,
-            thread: Some(thread),
-            handle,
-            state,
-        }
-    }
-
-    /// Stop & join the collector thread.
-    pub fn stop(&mut self) -> Result<(), GarbageCollectorError> {
-        self.state
-            .lock()
-            .map(|mut state| {
-                state.running = false;
-            })
-            .map_err(|_| GarbageCollectorError::LockError)?;
-        if let Some(thread) = self.thread.take() {
-            thread
-                .join()
-                .map_err(|_| GarbageCollectorError::JoinError)?;
-        }
-        Ok(())
-    }
-
-    /// Get a handle to the collector. Does not lock.
-    pub fn handle(&self) -> &Handle {
-        &self.handle
-    }
-
-    /// Force GC on the current thread & return whether it was successful.
-    /// Tries to acquire a lock on the collector.
-    #[allow(dead_code)]
-    pub fn blocking_collect(&self) -> bool {
-        self.collector
-            .lock()
-            .map(|mut collector| {
-                collector.collect();
-                true
-            })
-            .unwrap_or(false)
-    }
-
-    /// Gets the number of live allocations associated with the `Collector`.
-    /// Tries to acquire a lock on the collector.
-    #[allow(dead_code)]
-    pub fn blocking_alloc_count(&self) -> usize {
-        self.collector
-            .lock()
-            .map(|collector| collector.alloc_count())
-            .unwrap_or(0)
-    }
-}
-
-fn run_collector_loop(collector: Arc<Mutex<Collector>>, state: Arc<Mutex<GarbageCollectorState>>) {
-    log::info!("Garbage collector thread started");
-    loop {
-        let (collect_interval, is_running) = state
-            .lock()
-            .map(|state| (state.collect_interval, state.running))
-            .unwrap_or((Duration::default(), false));
-        if !is_running {
-            log::info!("Garbage collector thread stopping");
-            return;
-        }
-
-        let collector = collector.lock().map(|mut collector| {
-            collector.collect();
-        });
-        if collector.is_err() {
-            log::warn!("Garbage collector thread failing due to lock error");
-            return;
-        }
-
-        std::thread::sleep(collect_interval);
-    }
-}
-
 #[cfg(test)]
 mod test {
-    use basedrop::*;
+    use std::time::Duration;
 
     use super::*;
 
@@ -0,0 +1,82 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::time::Duration;
+
+use audio_garbage_collector_v2::{GarbageCollectorOptions, Handle};
+
+use crate::GarbageCollectorError;
+
+/// Wraps [`audio_garbage_collector_v2::GarbageCollector`] with the same API as the `basedrop`
+/// based collector.
+pub struct GarbageCollector {
+    collector: audio_garbage_collector_v2::GarbageCollector,
+}
+
+impl Default for GarbageCollector {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100))
+    }
+}
+
+impl GarbageCollector {
+    /// Create the collector and start the garbage collector thread
+    pub fn new(collect_interval: Duration) -> Self {
+        GarbageCollector {
+            collector: audio_garbage_collector_v2::GarbageCollector::start(
+                GarbageCollectorOptions {
+                    collect_interval,
+                    ..Default::default()
+                },
+            ),
+        }
+    }
+
+    /// Stop & join the collector thread.
+    pub fn stop(&mut self) -> Result<(), GarbageCollectorError> {
+        self.collector
+            .stop()
+            .map_err(|_| GarbageCollectorError::JoinError)
+    }
+
+    /// Get a handle to the collector. Does not lock.
+    pub fn handle(&self) -> &Handle {
+        self.collector.handle()
+    }
+
+    /// Force GC on the current thread & return whether it was successful.
+    pub fn blocking_collect(&self) -> bool {
+        self.collector.collect();
+        true
+    }
+
+    /// Gets the number of live allocations associated with the collector.
+    pub fn blocking_alloc_count(&self) -> usize {
+        self.collector.metrics().allocated
+    }
+
+    /// Counters for pending and collected drops.
+    pub fn metrics(&self) -> audio_garbage_collector_v2::GarbageCollectorMetrics {
+        self.collector.metrics()
+    }
+}
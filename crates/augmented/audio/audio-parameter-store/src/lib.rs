@@ -21,26 +21,49 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use audio_processor_traits::parameters::{
+    FloatType, ParameterSpec, ParameterType as HandleParameterType, ParameterValue,
+};
 
 pub use parameter::PluginParameter;
 pub use parameter::PluginParameterLike;
+pub use smoothing::ParameterSmoother;
 
 pub mod parameter;
+pub mod smoothing;
+
+/// Number of events each of the store's queues can hold before they're drained
+pub const DEFAULT_EVENT_CAPACITY: usize = 512;
+
+/// Notification of changes which didn't originate on the UI, such as host automation, for the UI,
+/// or of gestures on the UI, for the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterEvent {
+    Changed { index: usize, value: f32 },
+    GestureBegin { index: usize },
+    GestureEnd { index: usize },
+}
 
 /// Holder of parameters
 ///
-/// Uses `RwLock` and atomics internally.
-///
-/// Modifying parameter values or adding/removing parameters will lock the store for all threads.
-/// Setting parameter values as well as reading them will not lock as long as there isn't a writer
-/// adding/removing parameters.
+/// Parameters are added while building the store, which requires exclusive access. Once the store
+/// is shared, the set of parameters is fixed, so indexes are stable and lookups are lock-free.
 ///
 /// The parameters themselves wrap an atomic value & otherwise immutable fields.
 ///
-/// I should validate that this is sound.
+/// Changes made through [`ParameterStore::set_value_from_host`] are published onto a bounded
+/// lock-free queue, which the UI drains with [`ParameterStore::pop_ui_event`]. Gestures go the other
+/// way, onto a separate queue the host side drains with [`ParameterStore::pop_host_event`], so
+/// neither side can consume the other's events. Smoothing is done on the audio thread by a
+/// [`ParameterSmoother`].
 pub struct ParameterStore {
-    inner: RwLock<ParameterStoreInner>,
+    parameters: HashMap<ParameterId, usize>,
+    entries: Vec<ParameterEntry>,
+    ui_events: EventQueue,
+    host_events: EventQueue,
 }
 
 unsafe impl Send for ParameterStore {}
@@ -54,73 +77,160 @@ impl Default for ParameterStore {
 
 impl ParameterStore {
     pub fn new() -> Self {
+        Self::with_event_capacity(DEFAULT_EVENT_CAPACITY)
+    }
+
+    pub fn with_event_capacity(capacity: usize) -> Self {
         ParameterStore {
-            inner: RwLock::new(ParameterStoreInner {
-                parameters: HashMap::new(),
-                parameter_ids: Vec::new(),
-            }),
+            parameters: HashMap::new(),
+            entries: Vec::new(),
+            ui_events: EventQueue::new(capacity),
+            host_events: EventQueue::new(capacity),
         }
     }
 
-    /// Add a parameter to the store
-    ///
-    /// # Locking
-    /// This will lock the store for writing.
-    /// Locking the store while processing audio is not desired.
+    /// Add a parameter to the store. Its index is the number of parameters added before it.
     pub fn add_parameter(&mut self, id: &str, parameter: ParameterRef) {
-        if let Ok(mut inner) = self.inner.write() {
-            inner.parameter_ids.push(id.to_string());
-            inner.parameters.insert(id.to_string(), parameter);
-        }
+        self.parameters.insert(id.to_string(), self.entries.len());
+        self.entries.push(ParameterEntry {
+            id: id.to_string(),
+            parameter,
+            in_gesture: AtomicBool::new(false),
+        });
     }
 
     /// Find a parameter by ID
-    ///
-    /// # Locking
-    /// This will block if the store is locked for writing.
     pub fn find_parameter(&self, parameter_id: &str) -> Option<ParameterRef> {
-        let inner = self.inner.read().ok()?;
-        Some(inner.parameters.get(parameter_id)?.clone())
+        let index = self.find_parameter_index(parameter_id)?;
+        Some(self.entries[index].parameter.clone())
+    }
+
+    /// Find a parameter index by ID
+    pub fn find_parameter_index(&self, parameter_id: &str) -> Option<usize> {
+        self.parameters.get(parameter_id).copied()
     }
 
     /// Find a parameter ID by index
-    ///
-    /// # Locking
-    /// This will block if the store is locked for writing.
     pub fn find_parameter_id(&self, index: i32) -> Option<String> {
-        let inner = self.inner.read().ok()?;
-        Some(inner.parameter_ids.get(index as usize)?.clone())
+        Some(self.entry(index as usize)?.id.clone())
     }
 
     /// Find a parameter by index
-    ///
-    /// # Locking
-    /// This will block if the store is locked for writing.
     pub fn find_parameter_by_index(&self, index: i32) -> Option<ParameterRef> {
-        let inner = self.inner.read().ok()?;
-        let parameter_id = inner.parameter_ids.get(index as usize)?;
-        Some(inner.parameters.get(parameter_id)?.clone())
+        Some(self.entry(index as usize)?.parameter.clone())
     }
 
     /// Get count of parameters
-    ///
-    /// # Locking
-    /// This will block if the store is locked for writing.
     pub fn get_num_parameters(&self) -> i32 {
-        let run = || -> Option<i32> {
-            let inner = self.inner.read().ok()?;
-            Some(inner.parameter_ids.len() as i32)
-        };
-        run().unwrap_or(0)
+        self.entries.len() as i32
     }
 
     /// Get a parameter value by ID
-    ///
-    /// # Locking
-    /// This will block if the store is locked for writing.
     pub fn value(&self, id: &str) -> f32 {
         self.find_parameter(id).as_ref().unwrap().value()
     }
+
+    /// Get a parameter value by index. Doesn't allocate or touch reference counts, so this is
+    /// preferred on the audio-thread.
+    pub fn value_at(&self, index: usize) -> Option<f32> {
+        Some(self.entry(index)?.parameter.value())
+    }
+
+    /// Set a parameter value & notify the UI of the change. Should be used for changes which
+    /// don't originate on the UI, such as host automation.
+    pub fn set_value_from_host(&self, index: usize, value: f32) {
+        if let Some(entry) = self.entry(index) {
+            entry.parameter.set_value(value);
+            self.ui_events
+                .push(ParameterEvent::Changed { index, value });
+        }
+    }
+
+    /// Mark the start of a UI gesture, such as dragging a knob. Returns false if the index is
+    /// invalid or a gesture was already running for this parameter.
+    pub fn begin_gesture(&self, index: usize) -> bool {
+        let started = self
+            .entry(index)
+            .map(|entry| !entry.in_gesture.swap(true, Ordering::Relaxed))
+            .unwrap_or(false);
+        if started {
+            self.host_events
+                .push(ParameterEvent::GestureBegin { index });
+        }
+        started
+    }
+
+    /// Mark the end of a UI gesture. Returns false if no gesture was running for this parameter.
+    pub fn end_gesture(&self, index: usize) -> bool {
+        let ended = self
+            .entry(index)
+            .map(|entry| entry.in_gesture.swap(false, Ordering::Relaxed))
+            .unwrap_or(false);
+        if ended {
+            self.host_events.push(ParameterEvent::GestureEnd { index });
+        }
+        ended
+    }
+
+    pub fn is_in_gesture(&self, index: usize) -> bool {
+        self.entry(index)
+            .map(|entry| entry.in_gesture.load(Ordering::Relaxed))
+            .unwrap_or(false)
+    }
+
+    /// Pop the oldest change the UI hasn't seen yet
+    pub fn pop_ui_event(&self) -> Option<ParameterEvent> {
+        self.ui_events.pop()
+    }
+
+    /// Pop the oldest UI gesture the host hasn't seen yet
+    pub fn pop_host_event(&self) -> Option<ParameterEvent> {
+        self.host_events.pop()
+    }
+
+    /// Number of changes dropped because the UI queue was full
+    pub fn dropped_ui_events(&self) -> usize {
+        self.ui_events.dropped()
+    }
+
+    /// Number of gestures dropped because the host queue was full
+    pub fn dropped_host_events(&self) -> usize {
+        self.host_events.dropped()
+    }
+
+    fn entry(&self, index: usize) -> Option<&ParameterEntry> {
+        self.entries.get(index)
+    }
+}
+
+/// Both this & [`vst::plugin::PluginParameters`] declare `get_parameter` and `set_parameter`,
+/// so the trait isn't imported here to avoid ambiguity.
+impl audio_processor_traits::parameters::AudioProcessorHandle for ParameterStore {
+    fn parameter_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn get_parameter_spec(&self, index: usize) -> ParameterSpec {
+        let (name, range) = self
+            .entry(index)
+            .map(|entry| (entry.parameter.name(), entry.parameter.value_range()))
+            .unwrap_or_else(|| ("Unknown".to_string(), (0.0, 1.0)));
+        ParameterSpec::new(
+            name,
+            HandleParameterType::Float(FloatType { range, step: None }),
+        )
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<ParameterValue> {
+        self.value_at(index).map(ParameterValue::from)
+    }
+
+    fn set_parameter(&self, index: usize, request: ParameterValue) {
+        let ParameterValue::Float { value } = request;
+        if let Some(entry) = self.entry(index) {
+            entry.parameter.set_value(value);
+        }
+    }
 }
 
 impl vst::plugin::PluginParameters for ParameterStore {
@@ -157,12 +267,9 @@ impl vst::plugin::PluginParameters for ParameterStore {
     }
 
     fn set_parameter(&self, index: i32, value: f32) {
-        let run = move || -> Option<()> {
-            let parameter = self.find_parameter_by_index(index)?;
-            parameter.set_value(value);
-            Some(())
-        };
-        run();
+        if index >= 0 {
+            self.set_value_from_host(index as usize, value);
+        }
     }
 
     fn can_be_automated(&self, index: i32) -> bool {
@@ -174,6 +281,45 @@ impl vst::plugin::PluginParameters for ParameterStore {
     }
 }
 
+/// Parameter IDs are strings
+pub type ParameterId = String;
+pub type ParameterRef = Arc<dyn PluginParameterLike>;
+
+struct ParameterEntry {
+    id: ParameterId,
+    parameter: ParameterRef,
+    in_gesture: AtomicBool,
+}
+
+/// Single-consumer event queue which counts the events it had to drop
+struct EventQueue {
+    queue: atomic_queue::Queue<ParameterEvent>,
+    dropped: AtomicUsize,
+}
+
+impl EventQueue {
+    fn new(capacity: usize) -> Self {
+        EventQueue {
+            queue: atomic_queue::Queue::new(capacity),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, event: ParameterEvent) {
+        if !self.queue.push(event) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn pop(&self) -> Option<ParameterEvent> {
+        self.queue.pop()
+    }
+
+    fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use audio_processor_testing_helpers::assert_f_eq;
@@ -181,6 +327,9 @@ mod test {
 
     use super::*;
 
+    /// Not imported as a trait, which would make `get_parameter` calls ambiguous
+    type AudioProcessorHandle = dyn audio_processor_traits::parameters::AudioProcessorHandle;
+
     #[test]
     fn test_creating_and_adding_parameters() {
         let mut parameter_store = ParameterStore::new();
@@ -228,16 +377,120 @@ mod test {
     }
 
     #[test]
-    fn test_float_is_atomic() {
-        assert!(crossbeam::atomic::AtomicCell::<f32>::is_lock_free());
+    fn test_parameter_indexes_are_stable() {
+        let mut parameter_store = ParameterStore::new();
+        for id in ["a", "b", "c"] {
+            parameter_store
+                .add_parameter(id, Arc::new(PluginParameter::builder().name(id).build()));
+        }
+
+        assert_eq!(parameter_store.find_parameter_index("b"), Some(1));
+        assert_eq!(parameter_store.find_parameter_id(2), Some("c".to_string()));
+        assert_eq!(parameter_store.find_parameter_index("d"), None);
     }
-}
 
-/// Parameter IDs are strings
-pub type ParameterId = String;
-pub type ParameterRef = Arc<dyn PluginParameterLike>;
+    #[test]
+    fn test_host_changes_are_published() {
+        let mut parameter_store = ParameterStore::new();
+        parameter_store.add_parameter("test", Arc::new(PluginParameter::builder().build()));
 
-struct ParameterStoreInner {
-    parameters: HashMap<ParameterId, ParameterRef>,
-    parameter_ids: Vec<ParameterId>,
+        parameter_store.set_parameter(0, 0.5);
+        assert_f_eq!(parameter_store.value_at(0).unwrap(), 0.5);
+        assert_eq!(
+            parameter_store.pop_ui_event(),
+            Some(ParameterEvent::Changed {
+                index: 0,
+                value: 0.5
+            })
+        );
+        assert_eq!(parameter_store.pop_ui_event(), None);
+        assert_eq!(parameter_store.pop_host_event(), None);
+
+        // Changes from the UI aren't published
+        let handle: &AudioProcessorHandle = &parameter_store;
+        handle.set_parameter(0, 0.25.into());
+        assert_f_eq!(parameter_store.value_at(0).unwrap(), 0.25);
+        assert_eq!(parameter_store.pop_ui_event(), None);
+    }
+
+    #[test]
+    fn test_full_event_queue_drops_events() {
+        let mut parameter_store = ParameterStore::with_event_capacity(2);
+        parameter_store.add_parameter("test", Arc::new(PluginParameter::builder().build()));
+        for _ in 0..3 {
+            parameter_store.set_value_from_host(0, 1.0);
+        }
+        assert_eq!(parameter_store.dropped_ui_events(), 1);
+        assert_eq!(parameter_store.dropped_host_events(), 0);
+
+        for _ in 0..2 {
+            parameter_store.begin_gesture(0);
+            parameter_store.end_gesture(0);
+        }
+        assert_eq!(parameter_store.dropped_host_events(), 2);
+        assert_eq!(parameter_store.dropped_ui_events(), 1);
+    }
+
+    #[test]
+    fn test_gestures() {
+        let mut parameter_store = ParameterStore::new();
+        parameter_store.add_parameter("test", Arc::new(PluginParameter::builder().build()));
+
+        assert!(!parameter_store.is_in_gesture(0));
+        assert!(parameter_store.begin_gesture(0));
+        assert!(!parameter_store.begin_gesture(0));
+        assert!(parameter_store.is_in_gesture(0));
+        assert!(parameter_store.end_gesture(0));
+        assert!(!parameter_store.end_gesture(0));
+        assert!(!parameter_store.begin_gesture(1));
+
+        // Gestures are only seen by the host, changes only by the UI
+        parameter_store.set_value_from_host(0, 0.5);
+        assert_eq!(
+            parameter_store.pop_ui_event(),
+            Some(ParameterEvent::Changed {
+                index: 0,
+                value: 0.5
+            })
+        );
+        assert_eq!(parameter_store.pop_ui_event(), None);
+        assert_eq!(
+            parameter_store.pop_host_event(),
+            Some(ParameterEvent::GestureBegin { index: 0 })
+        );
+        assert_eq!(
+            parameter_store.pop_host_event(),
+            Some(ParameterEvent::GestureEnd { index: 0 })
+        );
+        assert_eq!(parameter_store.pop_host_event(), None);
+    }
+
+    #[test]
+    fn test_audio_processor_handle() {
+        let mut parameter_store = ParameterStore::new();
+        parameter_store.add_parameter(
+            "test",
+            Arc::new(
+                PluginParameter::builder()
+                    .name("Rate")
+                    .value_range(0.1, 10.0)
+                    .initial_value(1.0)
+                    .build(),
+            ),
+        );
+
+        let handle: &AudioProcessorHandle = &parameter_store;
+        assert_eq!(handle.parameter_count(), 1);
+        let spec = handle.get_parameter_spec(0);
+        assert_eq!(spec.name(), "Rate");
+        assert_eq!(spec.ty().float().unwrap().range, (0.1, 10.0));
+        assert_eq!(handle.get_parameter(0), Some(1.0.into()));
+        assert_eq!(handle.get_parameter(1), None);
+        assert_eq!(handle.get_parameter_spec(1).name(), "Unknown");
+    }
+
+    #[test]
+    fn test_float_is_atomic() {
+        assert!(crossbeam::atomic::AtomicCell::<f32>::is_lock_free());
+    }
 }
@@ -0,0 +1,136 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Audio-thread smoothing of [`ParameterStore`] values.
+
+use std::time::Duration;
+
+use smooth_value::InterpolatedValue;
+
+use crate::ParameterStore;
+
+/// Smooths every parameter of a [`ParameterStore`] with an [`InterpolatedValue`].
+///
+/// The smoother is owned by the audio-thread. Call [`ParameterSmoother::update`] at the start of
+/// each block to pick up new targets, then [`ParameterSmoother::next_sample`] for each sample.
+pub struct ParameterSmoother {
+    values: Vec<InterpolatedValue>,
+    targets: Vec<f32>,
+}
+
+impl ParameterSmoother {
+    pub fn new(store: &ParameterStore, sample_rate: f32, duration: Duration) -> Self {
+        let targets: Vec<f32> = (0..store.entries.len())
+            .map(|index| store.value_at(index).unwrap_or(0.0))
+            .collect();
+        let values = targets
+            .iter()
+            .map(|target| InterpolatedValue::new(sample_rate, duration, *target))
+            .collect();
+        Self { values, targets }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        for value in &mut self.values {
+            value.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Modify the smoothing window of a single parameter
+    pub fn set_duration(&mut self, index: usize, duration: Duration) {
+        if let Some(value) = self.values.get_mut(index) {
+            value.set_duration(duration, false);
+        }
+    }
+
+    /// Read the current targets from the store. Doesn't allocate or lock.
+    pub fn update(&mut self, store: &ParameterStore) {
+        for (index, (value, target)) in self.values.iter_mut().zip(&mut self.targets).enumerate() {
+            if let Some(new_target) = store.value_at(index) {
+                if new_target != *target {
+                    *target = new_target;
+                    value.set(new_target);
+                }
+            }
+        }
+    }
+
+    /// Get the current smoothed value of a parameter and tick it
+    pub fn next_sample(&mut self, index: usize) -> f32 {
+        self.values[index].next_sample()
+    }
+
+    /// Current smoothed value of a parameter
+    pub fn get(&self, index: usize) -> f32 {
+        self.values[index].get()
+    }
+
+    /// Tick all parameters, for processors which only read values once per block
+    pub fn tick(&mut self) {
+        for value in &mut self.values {
+            value.tick();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use audio_processor_testing_helpers::assert_f_eq;
+
+    use crate::PluginParameter;
+
+    use super::*;
+
+    fn make_store() -> ParameterStore {
+        let mut store = ParameterStore::new();
+        store.add_parameter(
+            "gain",
+            Arc::new(PluginParameter::builder().initial_value(1.0).build()),
+        );
+        store
+    }
+
+    #[test]
+    fn test_starts_at_store_values() {
+        let store = make_store();
+        let smoother = ParameterSmoother::new(&store, 100.0, Duration::from_millis(100));
+        assert_f_eq!(smoother.get(0), 1.0);
+    }
+
+    #[test]
+    fn test_smooths_towards_new_values() {
+        let store = make_store();
+        let mut smoother = ParameterSmoother::new(&store, 100.0, Duration::from_millis(100));
+
+        store.set_value_from_host(0, 0.0);
+        smoother.update(&store);
+        let values: Vec<f32> = (0..12).map(|_| smoother.next_sample(0)).collect();
+
+        assert_f_eq!(values[0], 1.0);
+        assert!((values[5] - 0.5).abs() < 1e-4);
+        assert_f_eq!(values[11], 0.0);
+        assert!(values.windows(2).all(|pair| pair[1] <= pair[0]));
+    }
+}
@@ -0,0 +1,210 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Dynamic programming beat tracking.
+//!
+//! Finds the sequence of beat frames maximizing the onset strength at each beat, minus a penalty
+//! for inter-beat intervals deviating from the induced period:
+//!
+//! `C(t) = O(t) + max_{τ ∈ [t - 2p, t - p/2]} (C(τ) - tightness * ln((t - τ) / p)²)`
+//!
+//! The last beat is picked among the local maxima of `C`, then beats are read back through the
+//! stored predecessors.
+//!
+//! Reference:
+//! * Ellis, D. P. W. (2007). Beat Tracking by Dynamic Programming.
+//!   <https://www.ee.columbia.edu/~dpwe/pubs/Ellis07-beattrack.pdf>
+
+/// Track beats on an onset strength envelope, given the beat `period` in frames.
+///
+/// Returns the beat frames in ascending order.
+pub fn track_beats_with_period(values: &[f32], period: f32, tightness: f32) -> Vec<usize> {
+    if values.is_empty() || period < 1.0 {
+        return vec![];
+    }
+
+    let local_score = smooth(values, period);
+    let mut cumulative_score = vec![0.0; values.len()];
+    let mut predecessor: Vec<Option<usize>> = vec![None; values.len()];
+
+    let min_interval = (period / 2.0).round().max(1.0) as usize;
+    let max_interval = (period * 2.0).round() as usize;
+    for frame in 0..values.len() {
+        let mut best: Option<(usize, f32)> = None;
+        for interval in min_interval..=max_interval.min(frame) {
+            let previous = frame - interval;
+            let penalty = (interval as f32 / period).ln();
+            let score = cumulative_score[previous] - tightness * penalty * penalty;
+            if best
+                .map(|(_, best_score)| score > best_score)
+                .unwrap_or(true)
+            {
+                best = Some((previous, score));
+            }
+        }
+
+        cumulative_score[frame] = local_score[frame];
+        if let Some((previous, score)) = best {
+            // A chain is only extended if that's better than starting a new one here
+            if score > 0.0 {
+                cumulative_score[frame] += score;
+                predecessor[frame] = Some(previous);
+            }
+        }
+    }
+
+    let last_beat = match find_last_beat(&cumulative_score) {
+        Some(last_beat) => last_beat,
+        None => return vec![],
+    };
+    let mut beats = vec![last_beat];
+    while let Some(previous) = predecessor[*beats.last().unwrap()] {
+        beats.push(previous);
+    }
+    beats.reverse();
+
+    trim_weak_beats(&local_score, beats)
+}
+
+/// Convolve with a gaussian of standard deviation `period / 32`, spanning one period either side
+fn smooth(values: &[f32], period: f32) -> Vec<f32> {
+    let radius = period.round() as isize;
+    let deviation = period / 32.0;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|offset| {
+            let x = offset as f32 / deviation;
+            (-0.5 * x * x).exp()
+        })
+        .collect();
+
+    (0..values.len() as isize)
+        .map(|frame| {
+            kernel
+                .iter()
+                .enumerate()
+                .map(|(k, weight)| {
+                    let index = frame + k as isize - radius;
+                    if index >= 0 && (index as usize) < values.len() {
+                        weight * values[index as usize]
+                    } else {
+                        0.0
+                    }
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// The last local maximum of the cumulative score above half the median of all local maxima
+fn find_last_beat(cumulative_score: &[f32]) -> Option<usize> {
+    let maxima: Vec<usize> = (0..cumulative_score.len())
+        .filter(|&frame| {
+            let value = cumulative_score[frame];
+            let left = frame
+                .checked_sub(1)
+                .map(|previous| cumulative_score[previous])
+                .unwrap_or(f32::MIN);
+            // The score keeps rising past the last beat, so the final frame isn't a maximum
+            let right = cumulative_score.get(frame + 1).cloned().unwrap_or(f32::MAX);
+            value > 0.0 && value > left && value >= right
+        })
+        .collect();
+    if maxima.is_empty() {
+        return None;
+    }
+
+    let mut values: Vec<f32> = maxima
+        .iter()
+        .map(|&frame| cumulative_score[frame])
+        .collect();
+    values.sort_by(|a, b| a.total_cmp(b));
+    let threshold = 0.5 * values[values.len() / 2];
+    maxima
+        .into_iter()
+        .rev()
+        .find(|&frame| cumulative_score[frame] >= threshold)
+}
+
+/// Drop beats at either end with an onset strength under half the RMS of all beats
+fn trim_weak_beats(local_score: &[f32], mut beats: Vec<usize>) -> Vec<usize> {
+    let mean_square = beats
+        .iter()
+        .map(|&beat| local_score[beat].powi(2))
+        .sum::<f32>()
+        / beats.len() as f32;
+    let threshold = 0.5 * mean_square.sqrt();
+
+    while beats.last().map(|&beat| local_score[beat] < threshold) == Some(true) {
+        beats.pop();
+    }
+    let first_strong = beats
+        .iter()
+        .position(|&beat| local_score[beat] >= threshold)
+        .unwrap_or(beats.len());
+    beats.drain(..first_strong);
+    beats
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tracks_regular_impulses() {
+        let mut values = vec![0.0; 500];
+        for beat in (10..500).step_by(50) {
+            values[beat] = 1.0;
+        }
+        let beats = track_beats_with_period(&values, 50.0, 100.0);
+        assert_eq!(beats, (10..500).step_by(50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_fills_in_a_missing_onset() {
+        let mut values = vec![0.0; 500];
+        for beat in (10..500).step_by(50) {
+            values[beat] = 1.0;
+        }
+        values[210] = 0.0;
+        let beats = track_beats_with_period(&values, 50.0, 100.0);
+        assert_eq!(beats, (10..500).step_by(50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ignores_off_beat_noise() {
+        let mut values = vec![0.0; 500];
+        for beat in (10..500).step_by(50) {
+            values[beat] = 1.0;
+        }
+        values[123] = 0.5;
+        values[377] = 0.5;
+        let beats = track_beats_with_period(&values, 50.0, 100.0);
+        assert_eq!(beats, (10..500).step_by(50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_empty_envelope() {
+        assert!(track_beats_with_period(&[], 50.0, 100.0).is_empty());
+        assert!(track_beats_with_period(&[0.0; 100], 50.0, 100.0).is_empty());
+    }
+}
@@ -0,0 +1,381 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Offline tempo & beat tracking.
+//!
+//! [`track_beats`] runs three stages over a buffer:
+//!
+//! * An onset strength envelope, log-compressed spectral flux - [`onset_strength`]
+//! * Tempo induction by autocorrelation & comb filtering with a tempo prior -
+//!   [`tempo_induction`]
+//! * Beat placement by dynamic programming - [`dynamic_programming`]
+//!
+//! The tempo is then refined by fitting a line through the tracked beats, and the downbeat is
+//! guessed as the beat phase (within a bar of `beats_per_bar`) with the strongest onsets. Its
+//! confidence is how much that phase stands out over the second strongest.
+//!
+//! This is not real-time safe and is meant to run over recorded clips.
+//!
+//! ## Usage
+//! ```
+//! use audio_processor_analysis::beat_tracking::{track_beats, BeatTrackerOptions};
+//! use audio_processor_traits::AudioBuffer;
+//!
+//! // 2 seconds of clicks at 120bpm
+//! let mut samples = vec![0.0; 88200];
+//! for beat in 0..4 {
+//!     samples[beat * 22050] = 1.0;
+//! }
+//! let buffer = AudioBuffer::from_interleaved(1, &samples);
+//!
+//! if let Some(result) = track_beats(44100.0, BeatTrackerOptions::default(), &buffer) {
+//!     println!("tempo={} beats={:?}", result.tempo, result.beats);
+//! }
+//! ```
+
+use audio_processor_traits::AudioBuffer;
+
+use onset_strength::{onset_strength_envelope, OnsetStrengthEnvelope};
+use tempo_induction::{induce_tempo, TempoInductionOptions};
+
+pub mod dynamic_programming;
+pub mod onset_strength;
+pub mod tempo_induction;
+
+#[derive(Debug, Clone)]
+pub struct BeatTrackerOptions {
+    /// FFT size of the onset strength envelope. Defaults to 1024
+    pub fft_size: usize,
+    /// Samples between onset strength frames. Defaults to 256
+    pub hop_size: usize,
+    /// Seconds over which the onset strength local mean is removed. Defaults to 0.1
+    pub smoothing: f32,
+    /// Lowest tempo considered, in BPM. Defaults to 60
+    pub minimum_tempo: f32,
+    /// Highest tempo considered, in BPM. Defaults to 240
+    pub maximum_tempo: f32,
+    /// Tempo octave errors are resolved towards, in BPM. Defaults to 120
+    pub prior_tempo: f32,
+    /// Width of the tempo prior, in octaves. Defaults to 1
+    pub prior_octaves: f32,
+    /// How strictly beats follow the induced tempo. Defaults to 100
+    pub tightness: f32,
+    /// Number of beats per bar, used to find the downbeat. Defaults to 4
+    pub beats_per_bar: usize,
+}
+
+impl Default for BeatTrackerOptions {
+    fn default() -> Self {
+        Self {
+            fft_size: 1024,
+            hop_size: 256,
+            smoothing: 0.1,
+            minimum_tempo: 60.0,
+            maximum_tempo: 240.0,
+            prior_tempo: 120.0,
+            prior_octaves: 1.0,
+            tightness: 100.0,
+            beats_per_bar: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeatTrackingResult {
+    /// BPM
+    pub tempo: f32,
+    /// Periodicity of the onsets at the induced tempo, between 0 and 1
+    pub tempo_confidence: f32,
+    /// Beat positions in samples
+    pub beats: Vec<usize>,
+    /// Index into `beats` of the first downbeat, if at least a bar of beats was found
+    pub downbeat: Option<usize>,
+    /// Between 0 (every beat phase looks alike) and 1
+    pub downbeat_confidence: f32,
+}
+
+impl BeatTrackingResult {
+    /// Position in samples of the first downbeat
+    pub fn first_downbeat(&self) -> Option<usize> {
+        self.downbeat.map(|index| self.beats[index])
+    }
+}
+
+/// Track tempo, beats & downbeat over a buffer. Channels are mixed into mono. Not real-time safe.
+///
+/// Returns `None` if there are no onsets or the buffer is too short to hold two beats at
+/// `maximum_tempo`.
+pub fn track_beats(
+    sample_rate: f32,
+    options: BeatTrackerOptions,
+    data: &AudioBuffer<f32>,
+) -> Option<BeatTrackingResult> {
+    let mono: Vec<f32> = (0..data.num_samples()).map(|i| data.get_mono(i)).collect();
+    let envelope = onset_strength_envelope(
+        sample_rate,
+        options.fft_size,
+        options.hop_size,
+        options.smoothing,
+        &mono,
+    )?;
+    track_beats_in_envelope(&envelope, &options)
+}
+
+/// Track tempo, beats & downbeat over a pre-computed onset strength envelope
+pub fn track_beats_in_envelope(
+    envelope: &OnsetStrengthEnvelope,
+    options: &BeatTrackerOptions,
+) -> Option<BeatTrackingResult> {
+    let induction = induce_tempo(
+        envelope,
+        TempoInductionOptions {
+            minimum_tempo: options.minimum_tempo,
+            maximum_tempo: options.maximum_tempo,
+            prior_tempo: options.prior_tempo,
+            prior_octaves: options.prior_octaves,
+        },
+    )?;
+    let beat_frames = dynamic_programming::track_beats_with_period(
+        &envelope.values,
+        induction.period,
+        options.tightness,
+    );
+    if beat_frames.is_empty() {
+        return None;
+    }
+
+    let beat_numbers = number_beats(&beat_frames, induction.period);
+    let period = fit_period(&beat_frames, &beat_numbers).unwrap_or(induction.period);
+    let (downbeat_phase, downbeat_confidence) = find_downbeat_phase(
+        &envelope.values,
+        &beat_frames,
+        &beat_numbers,
+        options.beats_per_bar,
+    )
+    .unwrap_or((0, 0.0));
+    let downbeat = if beat_numbers.last().cloned().unwrap_or(0) + 1 >= options.beats_per_bar {
+        beat_numbers
+            .iter()
+            .position(|number| number % options.beats_per_bar == downbeat_phase)
+    } else {
+        None
+    };
+
+    Some(BeatTrackingResult {
+        tempo: envelope.frame_rate * 60.0 / period,
+        tempo_confidence: induction.confidence,
+        beats: beat_frames
+            .iter()
+            .map(|&frame| envelope.frame_to_samples(frame as f32) as usize)
+            .collect(),
+        downbeat,
+        downbeat_confidence: if downbeat.is_some() {
+            downbeat_confidence
+        } else {
+            0.0
+        },
+    })
+}
+
+/// Position of each beat on the grid, counting from the first. A skipped beat leaves a gap
+fn number_beats(beat_frames: &[usize], period: f32) -> Vec<usize> {
+    let first = beat_frames[0] as f32;
+    beat_frames
+        .iter()
+        .map(|&frame| ((frame as f32 - first) / period).round() as usize)
+        .collect()
+}
+
+/// Least squares slope of beat frame over beat number
+fn fit_period(beat_frames: &[usize], beat_numbers: &[usize]) -> Option<f32> {
+    let count = beat_frames.len() as f32;
+    let mean_number = beat_numbers.iter().sum::<usize>() as f32 / count;
+    let mean_frame = beat_frames.iter().sum::<usize>() as f32 / count;
+
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for (&frame, &number) in beat_frames.iter().zip(beat_numbers) {
+        let number = number as f32 - mean_number;
+        covariance += number * (frame as f32 - mean_frame);
+        variance += number * number;
+    }
+
+    if variance > 0.0 {
+        Some(covariance / variance)
+    } else {
+        None
+    }
+}
+
+/// Beat phase within a bar with the highest mean onset strength & how far it's ahead of the
+/// second highest, relative to its own strength
+fn find_downbeat_phase(
+    values: &[f32],
+    beat_frames: &[usize],
+    beat_numbers: &[usize],
+    beats_per_bar: usize,
+) -> Option<(usize, f32)> {
+    if beats_per_bar < 2 {
+        return None;
+    }
+
+    let mut sums = vec![0.0; beats_per_bar];
+    let mut counts = vec![0; beats_per_bar];
+    for (&frame, &number) in beat_frames.iter().zip(beat_numbers) {
+        let start = frame.saturating_sub(1);
+        let end = (frame + 2).min(values.len());
+        let accent = values[start..end].iter().cloned().fold(0.0, f32::max);
+        sums[number % beats_per_bar] += accent;
+        counts[number % beats_per_bar] += 1;
+    }
+
+    let mut strengths: Vec<(usize, f32)> = sums
+        .iter()
+        .zip(&counts)
+        .enumerate()
+        .filter(|(_, (_, &count))| count > 0)
+        .map(|(phase, (sum, &count))| (phase, sum / count as f32))
+        .collect();
+    strengths.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    let (phase, strongest) = *strengths.first()?;
+    let second = strengths
+        .get(1)
+        .map(|(_, strength)| *strength)
+        .unwrap_or(0.0);
+    let confidence = if strongest > 0.0 {
+        ((strongest - second) / strongest).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    Some((phase, confidence))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    /// Short decaying noise bursts on every beat, the first of every bar louder
+    fn click_track(tempo: f32, num_beats: usize, offset_samples: usize) -> Vec<f32> {
+        let samples_per_beat = SAMPLE_RATE * 60.0 / tempo;
+        let length = (samples_per_beat * num_beats as f32) as usize + offset_samples;
+        let mut signal = vec![0.0; length];
+        let mut state: u32 = 0x12345678;
+        for beat in 0..num_beats {
+            let start = offset_samples + (beat as f32 * samples_per_beat) as usize;
+            let gain = if beat % 4 == 0 { 1.0 } else { 0.4 };
+            for i in 0..1000 {
+                if start + i >= length {
+                    break;
+                }
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0;
+                signal[start + i] = gain * noise * (-(i as f32) / 200.0).exp();
+            }
+        }
+        signal
+    }
+
+    fn track(signal: &[f32]) -> BeatTrackingResult {
+        let buffer = AudioBuffer::from_interleaved(1, signal);
+        track_beats(SAMPLE_RATE, BeatTrackerOptions::default(), &buffer).unwrap()
+    }
+
+    #[test]
+    fn test_silence() {
+        let buffer = AudioBuffer::from_interleaved(1, &vec![0.0; 44100]);
+        assert_eq!(
+            track_beats(SAMPLE_RATE, BeatTrackerOptions::default(), &buffer),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tracks_tempo_of_click_tracks() {
+        for tempo in [85.0, 97.0, 120.0, 128.0, 140.0, 174.0] {
+            let result = track(&click_track(tempo, 16, 0));
+            assert!(
+                (result.tempo - tempo).abs() < 1.0,
+                "expected={} {:?}",
+                tempo,
+                result
+            );
+            assert!(result.tempo_confidence > 0.5, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_beats_line_up_with_clicks() {
+        let tempo = 120.0;
+        let offset = 5000;
+        let result = track(&click_track(tempo, 16, offset));
+        assert!(result.beats.len() >= 15, "{:?}", result);
+
+        let samples_per_beat = SAMPLE_RATE * 60.0 / tempo;
+        for beat in &result.beats {
+            let position = (*beat as f32 - offset as f32) / samples_per_beat;
+            let error_samples = (position - position.round()).abs() * samples_per_beat;
+            assert!(error_samples < 512.0, "beat={} {:?}", beat, result);
+        }
+    }
+
+    #[test]
+    fn test_finds_the_accented_downbeat() {
+        let tempo = 120.0;
+        let samples_per_beat = SAMPLE_RATE * 60.0 / tempo;
+        // Start on the 3rd beat of a bar, so the first downbeat is the 3rd click
+        let mut signal = click_track(tempo, 18, 0);
+        signal.drain(..(samples_per_beat * 2.0) as usize);
+        let result = track(&signal);
+
+        let first_downbeat = result.first_downbeat().unwrap() as f32;
+        assert!(
+            (first_downbeat - samples_per_beat * 2.0).abs() < 512.0,
+            "{:?}",
+            result
+        );
+        assert!(result.downbeat_confidence > 0.2, "{:?}", result);
+    }
+
+    #[test]
+    fn test_loop_which_is_not_a_power_of_two_bars() {
+        // 6 beats at 100bpm, which the length heuristic would call 3.6s / 1 bar = 66bpm
+        let result = track(&click_track(100.0, 6, 0));
+        assert!((result.tempo - 100.0).abs() < 1.0, "{:?}", result);
+        assert_eq!(result.beats.len(), 6, "{:?}", result);
+    }
+
+    #[test]
+    fn test_fit_period() {
+        let period = fit_period(&[10, 60, 160], &[0, 1, 3]).unwrap();
+        assert!((period - 50.0).abs() < 1e-4);
+        assert_eq!(fit_period(&[10], &[0]), None);
+    }
+
+    #[test]
+    fn test_number_beats_leaves_gaps() {
+        assert_eq!(number_beats(&[10, 59, 161, 210], 50.0), vec![0, 1, 3, 4]);
+    }
+}
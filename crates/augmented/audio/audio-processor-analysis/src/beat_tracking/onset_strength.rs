@@ -0,0 +1,180 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Onset strength envelope: log-compressed spectral flux, one value per hop.
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+use crate::window_functions::{make_window_vec, WindowFunctionType};
+
+/// Onset strength over time, sampled every `hop_size` samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnsetStrengthEnvelope {
+    /// Onset strength per frame, mean removed & normalized to unit standard deviation. Frame `i`
+    /// is centered on sample `i * hop_size`
+    pub values: Vec<f32>,
+    pub hop_size: usize,
+    /// Frames per second
+    pub frame_rate: f32,
+}
+
+impl OnsetStrengthEnvelope {
+    /// Position in samples of frame `frame`
+    pub fn frame_to_samples(&self, frame: f32) -> f32 {
+        frame * self.hop_size as f32
+    }
+}
+
+/// Compute the onset strength envelope of a mono signal.
+///
+/// Frames are centered on multiples of `hop_size`, zero padded at the edges, so an onset on the
+/// very first sample still shows on frame 0. Each frame's magnitudes are log compressed and the
+/// positive differences against the previous frame summed. The local mean (over ~`smoothing`
+/// seconds) is subtracted, the result half-wave rectified & scaled to unit standard deviation.
+///
+/// Returns `None` if the signal has no onsets at all (e.g. silence or DC).
+pub fn onset_strength_envelope(
+    sample_rate: f32,
+    fft_size: usize,
+    hop_size: usize,
+    smoothing: f32,
+    signal: &[f32],
+) -> Option<OnsetStrengthEnvelope> {
+    let hop_size = hop_size.max(1);
+    let num_bins = fft_size / 2 + 1;
+    let window: Vec<f32> = make_window_vec(fft_size, WindowFunctionType::Hann);
+    let window_sum: f32 = window.iter().sum();
+    let fft = FftPlanner::new().plan_fft_forward(fft_size);
+    let mut scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+    let mut buffer = vec![Complex::default(); fft_size];
+
+    let num_frames = signal.len() / hop_size + 1;
+    let mut flux = Vec::with_capacity(num_frames);
+    let mut previous = vec![0.0; num_bins];
+    let mut current = vec![0.0; num_bins];
+    for frame in 0..num_frames {
+        let center = (frame * hop_size) as isize;
+        for (i, (value, w)) in buffer.iter_mut().zip(&window).enumerate() {
+            let index = center + i as isize - (fft_size / 2) as isize;
+            let sample = if index >= 0 && (index as usize) < signal.len() {
+                signal[index as usize]
+            } else {
+                0.0
+            };
+            *value = Complex::new(sample * w, 0.0);
+        }
+        fft.process_with_scratch(&mut buffer, &mut scratch);
+
+        for (magnitude, bin) in current.iter_mut().zip(&buffer) {
+            *magnitude = (1.0 + LOG_COMPRESSION * bin.norm() / window_sum).ln();
+        }
+        let difference: f32 = current
+            .iter()
+            .zip(&previous)
+            .map(|(current, previous)| (current - previous).max(0.0))
+            .sum();
+        flux.push(difference / num_bins as f32);
+        std::mem::swap(&mut current, &mut previous);
+    }
+
+    let frame_rate = sample_rate / hop_size as f32;
+    let radius = (smoothing * frame_rate / 2.0).round() as usize;
+    let values = subtract_local_mean(&flux, radius);
+
+    let mean_square = values.iter().map(|v| v * v).sum::<f32>() / values.len() as f32;
+    if mean_square <= f32::EPSILON {
+        return None;
+    }
+    let scale = 1.0 / mean_square.sqrt();
+
+    Some(OnsetStrengthEnvelope {
+        values: values.iter().map(|v| v * scale).collect(),
+        hop_size,
+        frame_rate,
+    })
+}
+
+/// Gain applied to magnitudes before `ln(1 + x)`, relative to a full-scale sine (0.5)
+const LOG_COMPRESSION: f32 = 1000.0;
+
+/// Subtract the mean over `[i - radius, i + radius]` from each value & clamp at 0
+fn subtract_local_mean(values: &[f32], radius: usize) -> Vec<f32> {
+    let mut prefix_sum = Vec::with_capacity(values.len() + 1);
+    prefix_sum.push(0.0);
+    for value in values {
+        prefix_sum.push(prefix_sum.last().unwrap() + value);
+    }
+
+    (0..values.len())
+        .map(|i| {
+            let start = i.saturating_sub(radius);
+            let end = (i + radius + 1).min(values.len());
+            let mean = (prefix_sum[end] - prefix_sum[start]) / (end - start) as f32;
+            (values[i] - mean).max(0.0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_silence_has_no_envelope() {
+        assert_eq!(
+            onset_strength_envelope(44100.0, 1024, 256, 0.1, &[0.0; 44100]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_envelope_peaks_on_clicks() {
+        let mut signal = vec![0.0; 44100];
+        for position in [0, 11025, 22050, 33075] {
+            signal[position] = 1.0;
+        }
+        let envelope = onset_strength_envelope(44100.0, 1024, 256, 0.1, &signal).unwrap();
+        assert!((envelope.frame_rate - 44100.0 / 256.0).abs() < f32::EPSILON);
+        assert_eq!(envelope.values.len(), 44100 / 256 + 1);
+
+        for position in [0, 11025, 22050, 33075] {
+            let frame = (position as f32 / 256.0).round() as usize;
+            let start = frame.saturating_sub(4);
+            let local_max = envelope.values[start..frame + 4]
+                .iter()
+                .cloned()
+                .fold(0.0, f32::max);
+            assert!(local_max > 2.0, "position={} {:?}", position, local_max);
+        }
+        // Half way between clicks there's nothing
+        let frame = (5512.0 / 256.0) as usize;
+        assert!(envelope.values[frame] < 0.1);
+    }
+
+    #[test]
+    fn test_subtract_local_mean() {
+        let values = subtract_local_mean(&[1.0, 1.0, 4.0, 1.0, 1.0], 1);
+        assert_eq!(values, vec![0.0, 0.0, 2.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,214 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Tempo induction from an onset strength envelope.
+//!
+//! The envelope is autocorrelated, then every candidate beat period `τ` is scored by a comb
+//! filter summing the autocorrelation at `τ, 2τ, 3τ, 4τ` (peak picked within a small tolerance
+//! around each multiple). Scores are weighted by a log-gaussian prior over tempo, so octave
+//! errors resolve towards `prior_tempo`.
+
+use crate::pitch_detection::parabolic_interpolation;
+
+use super::onset_strength::OnsetStrengthEnvelope;
+
+const NUM_HARMONICS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoInductionOptions {
+    /// Lowest tempo considered, in BPM
+    pub minimum_tempo: f32,
+    /// Highest tempo considered, in BPM
+    pub maximum_tempo: f32,
+    /// Center of the tempo prior, in BPM
+    pub prior_tempo: f32,
+    /// Standard deviation of the tempo prior, in octaves
+    pub prior_octaves: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoInduction {
+    /// BPM
+    pub tempo: f32,
+    /// Beat period in envelope frames
+    pub period: f32,
+    /// Normalized autocorrelation at the picked period, between 0 and 1
+    pub confidence: f32,
+}
+
+/// Autocorrelation of `values` for lags `0..max_lag`, normalized so lag 0 is 1
+pub fn autocorrelation(values: &[f32], max_lag: usize) -> Vec<f32> {
+    let max_lag = max_lag.min(values.len());
+    let mut result: Vec<f32> = (0..max_lag)
+        .map(|lag| {
+            values
+                .iter()
+                .zip(&values[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+        })
+        .collect();
+    if let Some(energy) = result.first().cloned() {
+        if energy > 0.0 {
+            for value in result.iter_mut() {
+                *value /= energy;
+            }
+        }
+    }
+    result
+}
+
+/// Estimate the dominant tempo of `envelope`.
+///
+/// Returns `None` if the envelope is too short to hold two periods of `maximum_tempo`.
+pub fn induce_tempo(
+    envelope: &OnsetStrengthEnvelope,
+    options: TempoInductionOptions,
+) -> Option<TempoInduction> {
+    let frames_per_minute = envelope.frame_rate * 60.0;
+    let min_lag = (frames_per_minute / options.maximum_tempo).floor().max(1.0) as usize;
+    let max_lag = (frames_per_minute / options.minimum_tempo).ceil() as usize;
+    // At least two periods should fit in the envelope
+    let max_lag = max_lag.min(envelope.values.len() / 2);
+    if min_lag >= max_lag {
+        return None;
+    }
+
+    let acf = autocorrelation(&envelope.values, max_lag * NUM_HARMONICS + NUM_HARMONICS);
+    let scores: Vec<f32> = (0..=max_lag)
+        .map(|lag| {
+            if lag < min_lag {
+                return 0.0;
+            }
+            comb_filter(&acf, lag) * tempo_prior(frames_per_minute / lag as f32, &options)
+        })
+        .collect();
+
+    let (best_lag, _) = scores
+        .iter()
+        .enumerate()
+        .skip(min_lag)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let (period, _) = parabolic_interpolation(&scores, best_lag);
+
+    Some(TempoInduction {
+        tempo: frames_per_minute / period,
+        period,
+        confidence: acf[best_lag].clamp(0.0, 1.0),
+    })
+}
+
+/// Mean of the autocorrelation peaks at multiples of `lag` which fit in `acf`
+fn comb_filter(acf: &[f32], lag: usize) -> f32 {
+    let mut sum = 0.0;
+    let mut count = 0;
+    for harmonic in 1..=NUM_HARMONICS {
+        let center = lag * harmonic;
+        let spread = harmonic - 1;
+        if center + spread >= acf.len() {
+            break;
+        }
+        sum += acf[center - spread..=center + spread]
+            .iter()
+            .cloned()
+            .fold(f32::MIN, f32::max);
+        count += 1;
+    }
+
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f32
+    }
+}
+
+/// Log-gaussian weight of `tempo`, 1 at `prior_tempo`
+fn tempo_prior(tempo: f32, options: &TempoInductionOptions) -> f32 {
+    let octaves = (tempo / options.prior_tempo).log2() / options.prior_octaves;
+    (-0.5 * octaves * octaves).exp()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options() -> TempoInductionOptions {
+        TempoInductionOptions {
+            minimum_tempo: 60.0,
+            maximum_tempo: 240.0,
+            prior_tempo: 120.0,
+            prior_octaves: 1.0,
+        }
+    }
+
+    /// Impulses every `period` frames
+    fn impulse_envelope(period: f32, num_frames: usize) -> OnsetStrengthEnvelope {
+        let mut values = vec![0.0; num_frames];
+        let mut position: f32 = 0.0;
+        while (position as usize) < num_frames {
+            values[position.round() as usize % num_frames] = 1.0;
+            position += period;
+        }
+        OnsetStrengthEnvelope {
+            values,
+            hop_size: 256,
+            frame_rate: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_autocorrelation() {
+        let acf = autocorrelation(&[1.0, 0.0, 1.0, 0.0], 4);
+        assert_eq!(acf, vec![1.0, 0.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn test_tempo_prior() {
+        let options = options();
+        assert!((tempo_prior(120.0, &options) - 1.0).abs() < f32::EPSILON);
+        assert!((tempo_prior(60.0, &options) - tempo_prior(240.0, &options)).abs() < 1e-6);
+        assert!(tempo_prior(90.0, &options) > tempo_prior(60.0, &options));
+    }
+
+    #[test]
+    fn test_induce_tempo_on_impulses() {
+        // 100 frames per second, 50 frames per beat is 120bpm
+        for (period, tempo) in [(50.0, 120.0), (60.0, 100.0), (42.0, 142.857)] {
+            let envelope = impulse_envelope(period, 1000);
+            let result = induce_tempo(&envelope, options()).unwrap();
+            assert!(
+                (result.tempo - tempo).abs() < 1.0,
+                "expected={} {:?}",
+                tempo,
+                result
+            );
+            assert!(result.confidence > 0.5);
+        }
+    }
+
+    #[test]
+    fn test_induce_tempo_on_short_envelope() {
+        let envelope = impulse_envelope(50.0, 20);
+        assert_eq!(induce_tempo(&envelope, options()), None);
+    }
+}
@@ -36,6 +36,8 @@
 //! Overlap is configurable
 //!
 //! ![](https://raw.githubusercontent.com/yamadapc/augmented-audio/master/crates/augmented/audio/audio-processor-analysis/screen.png)
+//!
+//! For analysis/resynthesis with overlap-add see [`stft`].
 
 use std::sync::Arc;
 
@@ -49,6 +51,8 @@ use audio_processor_traits::{AudioContext, Float};
 
 use crate::window_functions::{make_window_vec, WindowFunctionType};
 
+pub mod stft;
+
 pub struct FftProcessorOptions {
     pub size: usize,
     pub direction: FftDirection,
@@ -0,0 +1,463 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Short-time Fourier transform analysis & resynthesis with weighted overlap-add.
+//!
+//! [`StftProcessor`] windows its input, performs a forward FFT every `hop_size` samples, hands the
+//! positive frequency bins of each frame to a [`SpectralProcessor`] and overlap-adds the inverse
+//! FFT of the result, windowed again, onto its output.
+//!
+//! The output is normalized by the overlap-add sum of `analysis_window * synthesis_window`, so
+//! with an identity spectral processor the output is the input delayed by
+//! [`StftProcessor::latency_samples`]. This is exact when the window pair satisfies the constant
+//! overlap-add (COLA) constraint for the hop size, for example:
+//!
+//! * Hann analysis & Hann synthesis, `hop_size <= fft_size / 4`
+//! * Sine analysis & Sine synthesis, `hop_size <= fft_size / 2`
+//! * Hann analysis & Rectangular synthesis, `hop_size <= fft_size / 2`
+//!
+//! [`StftProcessor::cola_error`] reports how far a configuration is from this.
+//!
+//! ## Usage
+//! A crude spectral gate, zeroing bins under a magnitude threshold:
+//! ```
+//! use audio_processor_analysis::fft_processor::stft::{StftProcessor, StftProcessorOptions};
+//! use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
+//! use rustfft::num_complex::Complex;
+//!
+//! let mut spectral_gate = StftProcessor::new(
+//!     StftProcessorOptions::default(),
+//!     |_channel: usize, bins: &mut [Complex<f32>]| {
+//!         for bin in bins.iter_mut() {
+//!             if bin.norm() < 0.1 {
+//!                 *bin = Complex::new(0.0, 0.0);
+//!             }
+//!         }
+//!     },
+//! );
+//!
+//! let mut context = AudioContext::from(AudioProcessorSettings::default());
+//! spectral_gate.prepare(&mut context);
+//! let mut buffer = AudioBuffer::empty();
+//! buffer.resize(2, 512);
+//! spectral_gate.process(&mut context, &mut buffer);
+//! ```
+
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+
+use crate::window_functions::{make_window_vec, WindowFunctionType};
+
+/// Per-frame callback of an [`StftProcessor`]
+pub trait SpectralProcessor {
+    /// Called when the [`StftProcessor`] is prepared, before any frames are processed
+    fn prepare(&mut self, _context: &mut AudioContext, _options: &StftProcessorOptions) {}
+
+    /// Modify a frame in-place. `bins` are the `fft_size / 2 + 1` non-negative frequency bins of
+    /// `channel`; negative frequencies are reconstructed from them. Must be real-time safe.
+    fn process_frame(&mut self, channel: usize, bins: &mut [Complex<f32>]);
+}
+
+impl<F> SpectralProcessor for F
+where
+    F: FnMut(usize, &mut [Complex<f32>]),
+{
+    fn process_frame(&mut self, channel: usize, bins: &mut [Complex<f32>]) {
+        self(channel, bins)
+    }
+}
+
+pub struct StftProcessorOptions {
+    /// Defaults to 2048
+    pub fft_size: usize,
+    /// Number of samples between frames. Defaults to 512 (75% overlap)
+    pub hop_size: usize,
+    /// Window applied before the forward FFT. Defaults to Hann
+    pub analysis_window: WindowFunctionType,
+    /// Window applied after the inverse FFT. Defaults to Hann
+    pub synthesis_window: WindowFunctionType,
+}
+
+impl Default for StftProcessorOptions {
+    fn default() -> Self {
+        Self {
+            fft_size: 2048,
+            hop_size: 512,
+            analysis_window: WindowFunctionType::Hann,
+            synthesis_window: WindowFunctionType::Hann,
+        }
+    }
+}
+
+/// Returns the gain that normalizes the overlap-add of `analysis * synthesis` at `hop_size` and
+/// the maximum relative deviation from constant of that sum.
+fn overlap_add_normalization(analysis: &[f32], synthesis: &[f32], hop_size: usize) -> (f32, f32) {
+    let size = analysis.len();
+    let sums: Vec<f32> = (0..hop_size)
+        .map(|n| {
+            (n..size)
+                .step_by(hop_size)
+                .map(|i| analysis[i] * synthesis[i])
+                .sum()
+        })
+        .collect();
+
+    let mean = sums.iter().sum::<f32>() / hop_size as f32;
+    if mean <= f32::EPSILON {
+        return (0.0, 1.0);
+    }
+    let max_deviation = sums
+        .iter()
+        .map(|sum| ((sum - mean) / mean).abs())
+        .fold(0.0, f32::max);
+    (1.0 / mean, max_deviation)
+}
+
+struct ChannelState {
+    input: Vec<f32>,
+    output: Vec<f32>,
+}
+
+/// STFT analysis/resynthesis processor, see the [module docs](self).
+///
+/// Every channel is processed independently. Channels past the number of output channels the
+/// processor was prepared with are left untouched.
+pub struct StftProcessor<P: SpectralProcessor> {
+    options: StftProcessorOptions,
+    spectral_processor: P,
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    analysis_window: Vec<f32>,
+    synthesis_window: Vec<f32>,
+    gain: f32,
+    cola_error: f32,
+    frame: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    channels: Vec<ChannelState>,
+    cursor: usize,
+    samples_since_frame: usize,
+}
+
+impl<P: SpectralProcessor> StftProcessor<P> {
+    pub fn new(options: StftProcessorOptions, spectral_processor: P) -> Self {
+        let fft_size = options.fft_size;
+        let hop_size = options.hop_size.clamp(1, fft_size);
+        let options = StftProcessorOptions {
+            hop_size,
+            ..options
+        };
+
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(fft_size);
+        let inverse = planner.plan_fft_inverse(fft_size);
+        let scratch_size = forward
+            .get_inplace_scratch_len()
+            .max(inverse.get_inplace_scratch_len());
+
+        let analysis_window = make_window_vec(fft_size, options.analysis_window);
+        let synthesis_window = make_window_vec(fft_size, options.synthesis_window);
+        let (normalization, cola_error) =
+            overlap_add_normalization(&analysis_window, &synthesis_window, hop_size);
+        if cola_error > 0.001 {
+            log::warn!(
+                "STFT windows do not satisfy COLA for hop_size={} fft_size={}, output will be modulated (error={})",
+                hop_size,
+                fft_size,
+                cola_error
+            );
+        }
+
+        Self {
+            options,
+            spectral_processor,
+            forward,
+            inverse,
+            analysis_window,
+            synthesis_window,
+            // rustfft doesn't normalize the inverse transform
+            gain: normalization / fft_size as f32,
+            cola_error,
+            frame: vec![Complex::new(0.0, 0.0); fft_size],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_size],
+            channels: vec![],
+            cursor: 0,
+            samples_since_frame: 0,
+        }
+    }
+
+    pub fn options(&self) -> &StftProcessorOptions {
+        &self.options
+    }
+
+    pub fn spectral_processor(&self) -> &P {
+        &self.spectral_processor
+    }
+
+    pub fn spectral_processor_mut(&mut self) -> &mut P {
+        &mut self.spectral_processor
+    }
+
+    /// Delay between input & output, in samples
+    pub fn latency_samples(&self) -> usize {
+        self.options.fft_size
+    }
+
+    /// Maximum relative deviation of the windows' overlap-add sum from a constant. 0 means the
+    /// configuration satisfies COLA & reconstruction is perfect.
+    pub fn cola_error(&self) -> f32 {
+        self.cola_error
+    }
+
+    fn process_frame(&mut self, channel: usize) {
+        let fft_size = self.options.fft_size;
+        let state = &mut self.channels[channel];
+
+        // `cursor` now points at the oldest input sample
+        for (i, (bin, window)) in self.frame.iter_mut().zip(&self.analysis_window).enumerate() {
+            let sample = state.input[(self.cursor + i) % fft_size];
+            *bin = Complex::new(sample * window, 0.0);
+        }
+
+        self.forward
+            .process_with_scratch(&mut self.frame, &mut self.scratch);
+
+        let nyquist = fft_size / 2;
+        self.spectral_processor
+            .process_frame(channel, &mut self.frame[..=nyquist]);
+
+        // Keep the spectrum hermitian so the inverse is real
+        self.frame[0].im = 0.0;
+        if fft_size.is_multiple_of(2) {
+            self.frame[nyquist].im = 0.0;
+        }
+        for bin in 1..fft_size.div_ceil(2) {
+            self.frame[fft_size - bin] = self.frame[bin].conj();
+        }
+
+        self.inverse
+            .process_with_scratch(&mut self.frame, &mut self.scratch);
+
+        for (i, (bin, window)) in self.frame.iter().zip(&self.synthesis_window).enumerate() {
+            state.output[(self.cursor + i) % fft_size] += bin.re * window * self.gain;
+        }
+    }
+}
+
+impl<P: SpectralProcessor> AudioProcessor for StftProcessor<P> {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        let fft_size = self.options.fft_size;
+        self.channels = (0..context.settings.output_channels())
+            .map(|_| ChannelState {
+                input: vec![0.0; fft_size],
+                output: vec![0.0; fft_size],
+            })
+            .collect();
+        self.cursor = 0;
+        self.samples_since_frame = 0;
+        self.spectral_processor.prepare(context, &self.options);
+    }
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        let num_channels = data.num_channels().min(self.channels.len());
+        for sample_index in 0..data.num_samples() {
+            for channel in 0..num_channels {
+                let state = &mut self.channels[channel];
+                state.input[self.cursor] = *data.get(channel, sample_index);
+                data.set(channel, sample_index, state.output[self.cursor]);
+                state.output[self.cursor] = 0.0;
+            }
+
+            self.cursor = (self.cursor + 1) % self.options.fft_size;
+            self.samples_since_frame += 1;
+            if self.samples_since_frame >= self.options.hop_size {
+                self.samples_since_frame = 0;
+                for channel in 0..num_channels {
+                    self.process_frame(channel);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
+    use std::time::Duration;
+
+    use audio_processor_testing_helpers::{rms_level, sine_buffer};
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::*;
+
+    fn identity(_channel: usize, _bins: &mut [Complex<f32>]) {}
+
+    fn run<P: SpectralProcessor>(processor: &mut StftProcessor<P>, signal: &[f32]) -> Vec<f32> {
+        let mut context = AudioContext::from(AudioProcessorSettings::new(44100.0, 1, 1, 512));
+        processor.prepare(&mut context);
+        let mut output = vec![];
+        for block in signal.chunks(512) {
+            let mut buffer = AudioBuffer::from_interleaved(1, block);
+            processor.process(&mut context, &mut buffer);
+            output.extend_from_slice(buffer.channel(0));
+        }
+        output
+    }
+
+    fn assert_reconstructs(options: StftProcessorOptions) {
+        let mut processor = StftProcessor::new(options, identity);
+        assert!(processor.cola_error() < 0.001);
+
+        let mut signal = sine_buffer(44100.0, 440.0, Duration::from_millis(200));
+        for (i, sample) in signal.iter_mut().enumerate() {
+            *sample += 0.3 * ((i * 7919) % 101) as f32 / 101.0 - 0.15;
+        }
+        let output = run(&mut processor, &signal);
+
+        let latency = processor.latency_samples();
+        for (i, sample) in output.iter().enumerate().skip(latency) {
+            assert!(
+                (sample - signal[i - latency]).abs() < 1e-4,
+                "index={} output={} input={}",
+                i,
+                sample,
+                signal[i - latency]
+            );
+        }
+    }
+
+    #[test]
+    fn test_hann_hann_reconstructs_at_75_percent_overlap() {
+        assert_reconstructs(StftProcessorOptions::default());
+    }
+
+    #[test]
+    fn test_sine_sine_reconstructs_at_50_percent_overlap() {
+        assert_reconstructs(StftProcessorOptions {
+            fft_size: 1024,
+            hop_size: 512,
+            analysis_window: WindowFunctionType::Sine,
+            synthesis_window: WindowFunctionType::Sine,
+        });
+    }
+
+    #[test]
+    fn test_hann_rectangular_reconstructs_at_50_percent_overlap() {
+        assert_reconstructs(StftProcessorOptions {
+            fft_size: 1024,
+            hop_size: 512,
+            analysis_window: WindowFunctionType::Hann,
+            synthesis_window: WindowFunctionType::Rectangular,
+        });
+    }
+
+    #[test]
+    fn test_cola_error_is_reported() {
+        let processor = StftProcessor::new(
+            StftProcessorOptions {
+                hop_size: 1024,
+                ..StftProcessorOptions::default()
+            },
+            identity,
+        );
+        assert!(processor.cola_error() > 0.1);
+    }
+
+    #[test]
+    fn test_latency_is_fft_size() {
+        let processor = StftProcessor::new(StftProcessorOptions::default(), identity);
+        assert_eq!(processor.latency_samples(), 2048);
+    }
+
+    #[test]
+    fn test_zeroing_bins_outputs_silence() {
+        let mut processor = StftProcessor::new(
+            StftProcessorOptions::default(),
+            |_, bins: &mut [Complex<f32>]| bins.fill(Complex::new(0.0, 0.0)),
+        );
+        let signal = sine_buffer(44100.0, 440.0, Duration::from_millis(200));
+        let output = run(&mut processor, &signal);
+        assert!(output.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn test_brickwall_lowpass() {
+        let cutoff_bin = (2000.0 / 44100.0 * 2048.0) as usize;
+        let lowpass = move |_channel: usize, bins: &mut [Complex<f32>]| {
+            bins[cutoff_bin..].fill(Complex::new(0.0, 0.0));
+        };
+
+        let low = sine_buffer(44100.0, 440.0, Duration::from_millis(500));
+        let output = run(
+            &mut StftProcessor::new(StftProcessorOptions::default(), lowpass),
+            &low,
+        );
+        assert!((rms_level(&output[4096..]) - rms_level(&low)).abs() < 0.01);
+
+        let high = sine_buffer(44100.0, 8000.0, Duration::from_millis(500));
+        let output = run(
+            &mut StftProcessor::new(StftProcessorOptions::default(), lowpass),
+            &high,
+        );
+        assert!(rms_level(&output[4096..]) < 0.001);
+    }
+
+    #[test]
+    fn test_channels_are_processed_independently() {
+        let mut channels_seen = vec![];
+        let mut processor = StftProcessor::new(
+            StftProcessorOptions::default(),
+            |channel: usize, bins: &mut [Complex<f32>]| {
+                channels_seen.push(channel);
+                if channel == 1 {
+                    bins.fill(Complex::new(0.0, 0.0));
+                }
+            },
+        );
+        let mut context = AudioContext::from(AudioProcessorSettings::new(44100.0, 2, 2, 512));
+        processor.prepare(&mut context);
+
+        let signal: Vec<f32> = sine_buffer(44100.0, 440.0, Duration::from_millis(200))
+            .iter()
+            .flat_map(|sample| [*sample, *sample])
+            .collect();
+        let mut buffer = AudioBuffer::from_interleaved(2, &signal);
+        processor.process(&mut context, &mut buffer);
+
+        assert!(rms_level(&buffer.channel(0)[4096..]) > 0.5);
+        assert!(buffer.channel(1).iter().all(|sample| *sample == 0.0));
+        drop(processor);
+        assert!(channels_seen.contains(&0) && channels_seen.contains(&1));
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        let processor = StftProcessor::new(StftProcessorOptions::default(), identity);
+        assert_processor_realtime_safe(processor, Default::default(), 20);
+    }
+}
@@ -25,7 +25,13 @@
 //!
 //! * **Peak detector** - [`peak_detector`]
 //! * **FFT (Windowed/Overlapped)** - [`fft_processor`]
+//! * **STFT analysis/resynthesis (overlap-add)** - [`fft_processor::stft`]
 //! * **Transient detection** (not real-time) - [`transient_detection::stft`]
+//! * **Onset detection** (real-time) - [`transient_detection::onset_detector`]
+//! * **Tempo & beat tracking** (not real-time) - [`beat_tracking`]
+//! * **Pitch detection** (YIN & McLeod) - [`pitch_detection`]
+//! * **Loudness (EBU R128 / ITU-R BS.1770) & true-peak** - [`loudness`]
+//! * **Spectral features** (centroid, flux, rolloff, MFCC, chroma...) - [`spectral_features`]
 //! * **Window functions** - [`window_functions`]
 //!
 //! ## RMS
@@ -49,6 +55,10 @@
 //!
 //! ![](https://raw.githubusercontent.com/yamadapc/augmented-audio/master/crates/augmented/audio/audio-processor-analysis/screen.png)
 //!
+//! ## STFT
+//!
+//! Analysis/resynthesis with a per-frame spectral callback and COLA-normalized overlap-add.
+//!
 //! ## Envelope follower
 //!
 //! Envelope follower implementation with adjustable attack/release times.
@@ -63,17 +73,55 @@
 //!
 //! ![](https://raw.githubusercontent.com/yamadapc/augmented-audio/master/crates/augmented/audio/audio-processor-analysis/src/transient_detection/stft.png)
 //!
+//! ## Onset detection
+//!
+//! Streaming onset detector with spectral flux, high-frequency content and complex-domain
+//! detection functions. Onsets are pushed onto a lock-free queue read from any thread.
+//!
+//! ## Tempo & beat tracking
+//!
+//! Onset strength envelope, autocorrelation/comb-filter tempo induction & dynamic programming
+//! beat tracking over a recorded buffer. Returns the tempo, a beat grid and the downbeat with a
+//! confidence value.
+//!
+//! ## Pitch detection
+//!
+//! Monophonic fundamental frequency estimation with YIN or the McLeod pitch method, with a
+//! confidence value per estimate. Readable from any thread through a handle, or run offline.
+//!
+//! ## Loudness
+//!
+//! K-weighted momentary, short-term & integrated loudness with gating, loudness range and 4x
+//! oversampled true-peak. Real-time with a shared handle, or offline over a buffer.
+//!
+//! ## Spectral features
+//!
+//! Centroid, spread, flatness, rolloff, flux, MFCCs and chroma computed per FFT frame, either in
+//! real-time or over a buffer, with helpers to summarize and compare clips.
+//!
 //! ## Window functions
 //! Several window functions are implemented and configurable.
 
+/// Offline tempo, beat & downbeat tracking
+pub mod beat_tracking;
+
 #[warn(missing_docs)]
 pub mod envelope_follower_processor;
 
 pub mod fft_processor;
 
+/// EBU R128 / ITU-R BS.1770 loudness & true-peak metering
+pub mod loudness;
+
 /// Peak detector implementation
 pub mod peak_detector;
 
+/// Monophonic pitch detection (YIN & McLeod)
+pub mod pitch_detection;
+
+/// Spectral descriptors, MFCCs & chroma
+pub mod spectral_features;
+
 /// RMS implementation suitable for GUI reacting to magnitude of the signal. Accumulates values on
 /// a circular buffer, the consumer calculates the RMS value based on it.
 pub mod running_rms_processor;
@@ -83,3 +131,6 @@ pub mod transient_detection;
 
 /// Many window functions
 pub mod window_functions;
+
+#[cfg(test)]
+mod test_allocator;
@@ -0,0 +1,192 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Fixed resolution loudness histograms used for gating. Blocks are never discarded, so integrated
+//! loudness and loudness range can be measured over any duration with constant memory and without
+//! allocating on the audio thread.
+
+/// Lowest loudness stored, also the absolute gate
+pub const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const HIGHEST_LUFS: f64 = 10.0;
+const BINS_PER_LU: f64 = 100.0;
+const NUM_BINS: usize = ((HIGHEST_LUFS - ABSOLUTE_GATE_LUFS) * BINS_PER_LU) as usize;
+
+/// Convert a K-weighted mean square energy into LUFS
+pub fn energy_to_lufs(energy: f64) -> f64 {
+    if energy <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        -0.691 + 10.0 * energy.log10()
+    }
+}
+
+pub fn lufs_to_energy(lufs: f64) -> f64 {
+    10.0_f64.powf((lufs + 0.691) / 10.0)
+}
+
+fn bin_index(lufs: f64) -> usize {
+    (((lufs - ABSOLUTE_GATE_LUFS) * BINS_PER_LU) as usize).min(NUM_BINS - 1)
+}
+
+fn bin_lufs(index: usize) -> f64 {
+    ABSOLUTE_GATE_LUFS + index as f64 / BINS_PER_LU
+}
+
+/// Histogram of block loudness values, with 0.01 LU resolution.
+pub struct LoudnessHistogram {
+    counts: Vec<u64>,
+    energies: Vec<f64>,
+}
+
+impl Default for LoudnessHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; NUM_BINS],
+            energies: vec![0.0; NUM_BINS],
+        }
+    }
+}
+
+impl LoudnessHistogram {
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+        self.energies.fill(0.0);
+    }
+
+    /// Add a block, blocks under the absolute gate are dropped
+    pub fn push(&mut self, energy: f64) {
+        let lufs = energy_to_lufs(energy);
+        if lufs < ABSOLUTE_GATE_LUFS {
+            return;
+        }
+
+        let index = bin_index(lufs);
+        self.counts[index] += 1;
+        self.energies[index] += energy;
+    }
+
+    /// Index of the first bin over the relative gate, which is `relative_gate` LU under the mean
+    /// energy of all blocks over the absolute gate
+    fn relative_gate_bin(&self, relative_gate: f64) -> Option<usize> {
+        let count: u64 = self.counts.iter().sum();
+        if count == 0 {
+            return None;
+        }
+
+        let energy: f64 = self.energies.iter().sum();
+        let threshold = energy_to_lufs(energy / count as f64) + relative_gate;
+        if threshold < ABSOLUTE_GATE_LUFS {
+            Some(0)
+        } else {
+            Some(bin_index(threshold))
+        }
+    }
+
+    /// Integrated loudness (ITU-R BS.1770-4), with a relative gate of -10 LU
+    pub fn integrated_loudness(&self) -> f64 {
+        let start_bin = match self.relative_gate_bin(-10.0) {
+            Some(start_bin) => start_bin,
+            None => return f64::NEG_INFINITY,
+        };
+
+        let count: u64 = self.counts[start_bin..].iter().sum();
+        let energy: f64 = self.energies[start_bin..].iter().sum();
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+        energy_to_lufs(energy / count as f64)
+    }
+
+    /// Loudness range (EBU Tech 3342), with a relative gate of -20 LU. The histogram must have
+    /// been filled with short-term loudness blocks.
+    pub fn loudness_range(&self) -> f64 {
+        let start_bin = match self.relative_gate_bin(-20.0) {
+            Some(start_bin) => start_bin,
+            None => return 0.0,
+        };
+
+        let count: u64 = self.counts[start_bin..].iter().sum();
+        if count == 0 {
+            return 0.0;
+        }
+
+        let percentile = |ratio: f64| -> f64 {
+            let target = ((count - 1) as f64 * ratio).round() as u64;
+            let mut seen = 0;
+            for (index, bin_count) in self.counts.iter().enumerate().skip(start_bin) {
+                seen += bin_count;
+                if seen > target {
+                    return bin_lufs(index);
+                }
+            }
+            bin_lufs(NUM_BINS - 1)
+        };
+
+        percentile(0.95) - percentile(0.1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_energy_lufs_round_trip() {
+        let lufs = energy_to_lufs(lufs_to_energy(-23.0));
+        assert!((lufs + 23.0).abs() < 1e-9);
+        assert_eq!(energy_to_lufs(0.0), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_empty_histogram() {
+        let histogram = LoudnessHistogram::default();
+        assert_eq!(histogram.integrated_loudness(), f64::NEG_INFINITY);
+        assert_eq!(histogram.loudness_range(), 0.0);
+    }
+
+    #[test]
+    fn test_integrated_loudness_applies_relative_gate() {
+        let mut histogram = LoudnessHistogram::default();
+        for _ in 0..10 {
+            histogram.push(lufs_to_energy(-20.0));
+        }
+        // Quiet blocks over the absolute gate but under the relative gate are ignored
+        for _ in 0..10 {
+            histogram.push(lufs_to_energy(-50.0));
+        }
+        // Blocks under the absolute gate are ignored
+        histogram.push(lufs_to_energy(-80.0));
+
+        assert!((histogram.integrated_loudness() + 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_loudness_range() {
+        let mut histogram = LoudnessHistogram::default();
+        for i in 0..=100 {
+            histogram.push(lufs_to_energy(-30.0 + 10.0 * i as f64 / 100.0));
+        }
+        let range = histogram.loudness_range();
+        assert!((range - 8.5).abs() < 0.1, "{}", range);
+    }
+}
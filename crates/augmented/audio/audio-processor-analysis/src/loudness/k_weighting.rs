@@ -0,0 +1,151 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! ITU-R BS.1770 "K" frequency weighting. Two cascaded biquads, a high-shelf modelling the acoustic
+//! effect of the head and a high-pass ("RLB" weighting).
+//!
+//! The standard only lists coefficients for 48kHz, these are derived from the analog prototypes so
+//! any sample rate may be used (same approach as `libebur128`).
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+/// Transposed direct form II biquad
+#[derive(Debug, Clone, Default)]
+struct Biquad {
+    coefficients: BiquadCoefficients,
+    s1: f64,
+    s2: f64,
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = self.coefficients;
+        let output = b0 * input + self.s1;
+        self.s1 = b1 * input - a1 * output + self.s2;
+        self.s2 = b2 * input - a2 * output;
+        output
+    }
+
+    fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+}
+
+/// Stage 1 coefficients, high-shelf of roughly +4dB above 1.5kHz
+pub fn pre_filter_coefficients(sample_rate: f64) -> BiquadCoefficients {
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+
+    let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+    let vh = 10.0_f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+
+    BiquadCoefficients {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    }
+}
+
+/// Stage 2 coefficients, high-pass at roughly 38Hz
+pub fn rlb_filter_coefficients(sample_rate: f64) -> BiquadCoefficients {
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+
+    let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+
+    BiquadCoefficients {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    }
+}
+
+/// K-weighting filter for a single channel
+#[derive(Debug, Clone, Default)]
+pub struct KWeightingFilter {
+    pre_filter: Biquad,
+    rlb_filter: Biquad,
+}
+
+impl KWeightingFilter {
+    pub fn new(sample_rate: f64) -> Self {
+        let mut filter = Self::default();
+        filter.set_sample_rate(sample_rate);
+        filter
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.pre_filter.coefficients = pre_filter_coefficients(sample_rate);
+        self.rlb_filter.coefficients = rlb_filter_coefficients(sample_rate);
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.pre_filter.reset();
+        self.rlb_filter.reset();
+    }
+
+    pub fn process(&mut self, sample: f64) -> f64 {
+        self.rlb_filter.process(self.pre_filter.process(sample))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-8, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn test_48khz_coefficients_match_the_standard() {
+        // Table 1 & 2 in ITU-R BS.1770-4
+        let pre_filter = pre_filter_coefficients(48000.0);
+        assert_close(pre_filter.b0, 1.53512485958697);
+        assert_close(pre_filter.b1, -2.69169618940638);
+        assert_close(pre_filter.b2, 1.19839281085285);
+        assert_close(pre_filter.a1, -1.69065929318241);
+        assert_close(pre_filter.a2, 0.73248077421585);
+
+        let rlb_filter = rlb_filter_coefficients(48000.0);
+        assert_close(rlb_filter.a1, -1.99004745483398);
+        assert_close(rlb_filter.a2, 0.99007225036621);
+    }
+}
@@ -0,0 +1,541 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Loudness metering as specified by ITU-R BS.1770-4 and EBU R128.
+//!
+//! * **Momentary loudness** - 400ms window, in LUFS
+//! * **Short-term loudness** - 3s window, in LUFS
+//! * **Integrated loudness** - Gated average since the meter was reset (absolute gate at
+//!   -70 LUFS, relative gate at -10 LU), in LUFS
+//! * **Loudness range (LRA)** - Spread between the 10th and 95th percentiles of gated short-term
+//!   loudness (EBU Tech 3342), in LU
+//! * **True-peak** - Maximum sample value of the 4x oversampled signal, in dBTP
+//!
+//! [`LoudnessMeterProcessor`] measures in real-time and publishes to a [`LoudnessMeterHandle`]
+//! every 100ms. [`analyze_loudness`] measures a whole buffer offline.
+//!
+//! ## Usage
+//! ```
+//! use audio_processor_analysis::loudness::LoudnessMeterProcessor;
+//! use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
+//!
+//! let mut loudness_meter = LoudnessMeterProcessor::default();
+//! let handle = loudness_meter.handle().clone(); // can send to another thread
+//!
+//! let mut context = AudioContext::from(AudioProcessorSettings::default());
+//! loudness_meter.prepare(&mut context);
+//! let mut buffer = AudioBuffer::empty();
+//! buffer.resize(2, 512);
+//! loudness_meter.process(&mut context, &mut buffer);
+//!
+//! println!(
+//!     "M: {} LUFS I: {} LUFS TP: {} dBTP",
+//!     handle.momentary_loudness(),
+//!     handle.integrated_loudness(),
+//!     handle.true_peak()
+//! );
+//! ```
+//!
+//! References:
+//! * <https://www.itu.int/rec/R-REC-BS.1770>
+//! * <https://tech.ebu.ch/publications/r128>
+//! * <https://tech.ebu.ch/publications/tech3342>
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_traits::{AtomicF32, AudioBuffer, AudioContext, AudioProcessor};
+
+use gating::{energy_to_lufs, LoudnessHistogram};
+use k_weighting::KWeightingFilter;
+use true_peak::{TruePeakInterpolator, TruePeakState};
+
+pub mod gating;
+pub mod k_weighting;
+pub mod true_peak;
+
+/// Loudness is accumulated in blocks of 100ms. Momentary and short-term windows are multiples of it.
+const BLOCK_DURATION_SECS: f32 = 0.1;
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// Channel weights `G` from ITU-R BS.1770. 5.0 & 5.1 layouts are assumed to be ordered
+/// L, R, C, (LFE), Ls, Rs; anything else is weighted equally.
+fn channel_weight(channel: usize, num_channels: usize) -> f64 {
+    match (num_channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) | (5, 3) | (5, 4) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Results of a loudness measurement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessAnalysis {
+    /// LUFS
+    pub integrated_loudness: f32,
+    /// LU
+    pub loudness_range: f32,
+    /// dBTP
+    pub true_peak: f32,
+    /// LUFS
+    pub maximum_momentary_loudness: f32,
+    /// LUFS
+    pub maximum_short_term_loudness: f32,
+}
+
+/// The loudness measurement state. Allocates on construction only.
+pub struct LoudnessMeter {
+    filters: Vec<KWeightingFilter>,
+    channel_weights: Vec<f64>,
+    true_peak_interpolator: TruePeakInterpolator,
+    true_peak_states: Vec<TruePeakState>,
+    block_size: usize,
+    block_cursor: usize,
+    block_energy: f64,
+    block_energies: [f64; SHORT_TERM_BLOCKS],
+    num_blocks: usize,
+    gating_histogram: LoudnessHistogram,
+    short_term_histogram: LoudnessHistogram,
+    true_peak: f64,
+    momentary_energy: f64,
+    short_term_energy: f64,
+    maximum_momentary_energy: f64,
+    maximum_short_term_energy: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        Self {
+            filters: (0..num_channels)
+                .map(|_| KWeightingFilter::new(sample_rate as f64))
+                .collect(),
+            channel_weights: (0..num_channels)
+                .map(|channel| channel_weight(channel, num_channels))
+                .collect(),
+            true_peak_interpolator: TruePeakInterpolator::default(),
+            true_peak_states: vec![TruePeakState::default(); num_channels],
+            block_size: ((sample_rate * BLOCK_DURATION_SECS) as usize).max(1),
+            block_cursor: 0,
+            block_energy: 0.0,
+            block_energies: [0.0; SHORT_TERM_BLOCKS],
+            num_blocks: 0,
+            gating_histogram: LoudnessHistogram::default(),
+            short_term_histogram: LoudnessHistogram::default(),
+            true_peak: 0.0,
+            momentary_energy: 0.0,
+            short_term_energy: 0.0,
+            maximum_momentary_energy: 0.0,
+            maximum_short_term_energy: 0.0,
+        }
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Reset all measurements
+    pub fn reset(&mut self) {
+        for filter in &mut self.filters {
+            filter.reset();
+        }
+        for state in &mut self.true_peak_states {
+            state.reset();
+        }
+        self.block_cursor = 0;
+        self.block_energy = 0.0;
+        self.block_energies = [0.0; SHORT_TERM_BLOCKS];
+        self.num_blocks = 0;
+        self.gating_histogram.reset();
+        self.short_term_histogram.reset();
+        self.true_peak = 0.0;
+        self.momentary_energy = 0.0;
+        self.short_term_energy = 0.0;
+        self.maximum_momentary_energy = 0.0;
+        self.maximum_short_term_energy = 0.0;
+    }
+
+    /// Measure a buffer. Returns true if a 100ms block was completed and measurements changed.
+    pub fn process(&mut self, data: &AudioBuffer<f32>) -> bool {
+        let num_channels = data.num_channels().min(self.num_channels());
+        let mut has_changed = false;
+
+        for sample_index in 0..data.num_samples() {
+            for channel in 0..num_channels {
+                let sample = *data.get(channel, sample_index) as f64;
+
+                let true_peak =
+                    self.true_peak_states[channel].process(&self.true_peak_interpolator, sample);
+                self.true_peak = self.true_peak.max(true_peak);
+
+                let filtered = self.filters[channel].process(sample);
+                self.block_energy += self.channel_weights[channel] * filtered * filtered;
+            }
+
+            self.block_cursor += 1;
+            if self.block_cursor >= self.block_size {
+                self.on_block();
+                has_changed = true;
+            }
+        }
+
+        has_changed
+    }
+
+    fn on_block(&mut self) {
+        self.block_energies[self.num_blocks % SHORT_TERM_BLOCKS] =
+            self.block_energy / self.block_size as f64;
+        self.num_blocks += 1;
+        self.block_cursor = 0;
+        self.block_energy = 0.0;
+
+        if self.num_blocks >= MOMENTARY_BLOCKS {
+            self.momentary_energy = self.mean_energy(MOMENTARY_BLOCKS);
+            self.maximum_momentary_energy =
+                self.maximum_momentary_energy.max(self.momentary_energy);
+            // 400ms gating blocks overlapping by 75%
+            self.gating_histogram.push(self.momentary_energy);
+        }
+
+        if self.num_blocks >= SHORT_TERM_BLOCKS {
+            self.short_term_energy = self.mean_energy(SHORT_TERM_BLOCKS);
+            self.maximum_short_term_energy =
+                self.maximum_short_term_energy.max(self.short_term_energy);
+            self.short_term_histogram.push(self.short_term_energy);
+        }
+    }
+
+    /// Mean of the last `num_blocks` block energies
+    fn mean_energy(&self, num_blocks: usize) -> f64 {
+        let sum: f64 = (0..num_blocks)
+            .map(|i| {
+                let index = (self.num_blocks - 1 - i) % SHORT_TERM_BLOCKS;
+                self.block_energies[index]
+            })
+            .sum();
+        sum / num_blocks as f64
+    }
+
+    /// Momentary loudness in LUFS, -∞ for the first 400ms
+    pub fn momentary_loudness(&self) -> f32 {
+        energy_to_lufs(self.momentary_energy) as f32
+    }
+
+    /// Short-term loudness in LUFS, -∞ for the first 3s
+    pub fn short_term_loudness(&self) -> f32 {
+        energy_to_lufs(self.short_term_energy) as f32
+    }
+
+    /// Integrated loudness in LUFS. This iterates over the gating histogram, so prefer reading it
+    /// once per block.
+    pub fn integrated_loudness(&self) -> f32 {
+        self.gating_histogram.integrated_loudness() as f32
+    }
+
+    /// Loudness range in LU
+    pub fn loudness_range(&self) -> f32 {
+        self.short_term_histogram.loudness_range() as f32
+    }
+
+    /// Maximum true-peak in dBTP
+    pub fn true_peak(&self) -> f32 {
+        if self.true_peak <= 0.0 {
+            f32::NEG_INFINITY
+        } else {
+            (20.0 * self.true_peak.log10()) as f32
+        }
+    }
+
+    pub fn maximum_momentary_loudness(&self) -> f32 {
+        energy_to_lufs(self.maximum_momentary_energy) as f32
+    }
+
+    pub fn maximum_short_term_loudness(&self) -> f32 {
+        energy_to_lufs(self.maximum_short_term_energy) as f32
+    }
+
+    pub fn analysis(&self) -> LoudnessAnalysis {
+        LoudnessAnalysis {
+            integrated_loudness: self.integrated_loudness(),
+            loudness_range: self.loudness_range(),
+            true_peak: self.true_peak(),
+            maximum_momentary_loudness: self.maximum_momentary_loudness(),
+            maximum_short_term_loudness: self.maximum_short_term_loudness(),
+        }
+    }
+}
+
+/// Handle for [`LoudnessMeterProcessor`], use this to read measurements from any thread.
+pub struct LoudnessMeterHandle {
+    momentary_loudness: AtomicF32,
+    short_term_loudness: AtomicF32,
+    integrated_loudness: AtomicF32,
+    loudness_range: AtomicF32,
+    true_peak: AtomicF32,
+    maximum_momentary_loudness: AtomicF32,
+    maximum_short_term_loudness: AtomicF32,
+    reset_requested: AtomicBool,
+}
+
+impl Default for LoudnessMeterHandle {
+    fn default() -> Self {
+        Self {
+            momentary_loudness: AtomicF32::new(f32::NEG_INFINITY),
+            short_term_loudness: AtomicF32::new(f32::NEG_INFINITY),
+            integrated_loudness: AtomicF32::new(f32::NEG_INFINITY),
+            loudness_range: AtomicF32::new(0.0),
+            true_peak: AtomicF32::new(f32::NEG_INFINITY),
+            maximum_momentary_loudness: AtomicF32::new(f32::NEG_INFINITY),
+            maximum_short_term_loudness: AtomicF32::new(f32::NEG_INFINITY),
+            reset_requested: AtomicBool::new(false),
+        }
+    }
+}
+
+impl LoudnessMeterHandle {
+    /// Momentary loudness in LUFS
+    pub fn momentary_loudness(&self) -> f32 {
+        self.momentary_loudness.get()
+    }
+
+    /// Short-term loudness in LUFS
+    pub fn short_term_loudness(&self) -> f32 {
+        self.short_term_loudness.get()
+    }
+
+    /// Integrated loudness since the last reset in LUFS
+    pub fn integrated_loudness(&self) -> f32 {
+        self.integrated_loudness.get()
+    }
+
+    /// Loudness range since the last reset in LU
+    pub fn loudness_range(&self) -> f32 {
+        self.loudness_range.get()
+    }
+
+    /// Maximum true-peak since the last reset in dBTP
+    pub fn true_peak(&self) -> f32 {
+        self.true_peak.get()
+    }
+
+    pub fn maximum_momentary_loudness(&self) -> f32 {
+        self.maximum_momentary_loudness.get()
+    }
+
+    pub fn maximum_short_term_loudness(&self) -> f32 {
+        self.maximum_short_term_loudness.get()
+    }
+
+    /// Request that all measurements are reset, this is applied on the next `process` call
+    pub fn reset(&self) {
+        self.reset_requested.store(true, Ordering::Relaxed);
+    }
+
+    fn publish(&self, meter: &LoudnessMeter) {
+        self.momentary_loudness.set(meter.momentary_loudness());
+        self.short_term_loudness.set(meter.short_term_loudness());
+        self.integrated_loudness.set(meter.integrated_loudness());
+        self.loudness_range.set(meter.loudness_range());
+        self.true_peak.set(meter.true_peak());
+        self.maximum_momentary_loudness
+            .set(meter.maximum_momentary_loudness());
+        self.maximum_short_term_loudness
+            .set(meter.maximum_short_term_loudness());
+    }
+}
+
+/// Real-time loudness meter. The buffer is passed through unchanged.
+///
+/// Measurements are published onto the handle every 100ms.
+pub struct LoudnessMeterProcessor {
+    meter: LoudnessMeter,
+    handle: Shared<LoudnessMeterHandle>,
+}
+
+impl Default for LoudnessMeterProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoudnessMeterProcessor {
+    pub fn new() -> Self {
+        Self {
+            meter: LoudnessMeter::new(44100.0, 2),
+            handle: make_shared(LoudnessMeterHandle::default()),
+        }
+    }
+
+    /// Get a reference to the `basedrop::Shared` handle of this processor
+    pub fn handle(&self) -> &Shared<LoudnessMeterHandle> {
+        &self.handle
+    }
+}
+
+impl AudioProcessor for LoudnessMeterProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        let settings = context.settings;
+        self.meter = LoudnessMeter::new(settings.sample_rate(), settings.output_channels());
+        self.handle.publish(&self.meter);
+    }
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        if self.handle.reset_requested.swap(false, Ordering::Relaxed) {
+            self.meter.reset();
+            self.handle.publish(&self.meter);
+        }
+
+        if self.meter.process(data) {
+            self.handle.publish(&self.meter);
+        }
+    }
+}
+
+/// Measure the loudness of a whole buffer. Not real-time safe.
+pub fn analyze_loudness(sample_rate: f32, data: &AudioBuffer<f32>) -> LoudnessAnalysis {
+    let mut meter = LoudnessMeter::new(sample_rate, data.num_channels());
+    meter.process(data);
+    meter.analysis()
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
+    use std::time::Duration;
+
+    use audio_processor_testing_helpers::sine_buffer;
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::*;
+
+    /// Stereo 1kHz sine with both channels at `level_db` dBFS
+    fn stereo_sine(level_db: f32, duration: Duration) -> Vec<f32> {
+        let amplitude = 10.0_f32.powf(level_db / 20.0);
+        let mono = sine_buffer(48000.0, 1000.0, duration);
+        mono.iter()
+            .flat_map(|sample| [sample * amplitude, sample * amplitude])
+            .collect()
+    }
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() <= tolerance,
+            "{} != {} (±{})",
+            value,
+            expected,
+            tolerance
+        );
+    }
+
+    #[test]
+    fn test_channel_weights() {
+        assert_eq!(channel_weight(0, 2), 1.0);
+        assert_eq!(channel_weight(3, 6), 0.0);
+        assert_eq!(channel_weight(4, 6), 1.41);
+        assert_eq!(channel_weight(3, 5), 1.41);
+    }
+
+    #[test]
+    fn test_stereo_sine_at_minus_23_dbfs_reads_minus_23_lufs() {
+        // EBU Tech 3341, test case 1
+        let signal = stereo_sine(-23.0, Duration::from_secs(20));
+        let buffer = AudioBuffer::from_interleaved(2, &signal);
+        let mut meter = LoudnessMeter::new(48000.0, 2);
+        meter.process(&buffer);
+
+        assert_close(meter.momentary_loudness(), -23.0, 0.1);
+        assert_close(meter.short_term_loudness(), -23.0, 0.1);
+        assert_close(meter.integrated_loudness(), -23.0, 0.1);
+        assert_close(meter.true_peak(), -23.0, 0.1);
+    }
+
+    #[test]
+    fn test_integrated_loudness_gates_quiet_sections() {
+        // EBU Tech 3341, test case 3
+        let mut signal = stereo_sine(-36.0, Duration::from_secs(10));
+        signal.extend(stereo_sine(-23.0, Duration::from_secs(60)));
+        signal.extend(stereo_sine(-36.0, Duration::from_secs(10)));
+        let buffer = AudioBuffer::from_interleaved(2, &signal);
+
+        let analysis = analyze_loudness(48000.0, &buffer);
+        assert_close(analysis.integrated_loudness, -23.0, 0.1);
+        assert_close(analysis.maximum_momentary_loudness, -23.0, 0.1);
+    }
+
+    #[test]
+    fn test_loudness_range() {
+        // EBU Tech 3342, test case 1
+        let mut signal = stereo_sine(-20.0, Duration::from_secs(20));
+        signal.extend(stereo_sine(-30.0, Duration::from_secs(20)));
+        let buffer = AudioBuffer::from_interleaved(2, &signal);
+
+        let analysis = analyze_loudness(48000.0, &buffer);
+        assert_close(analysis.loudness_range, 10.0, 1.0);
+    }
+
+    #[test]
+    fn test_silence() {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 48000);
+        let analysis = analyze_loudness(48000.0, &buffer);
+        assert_eq!(analysis.integrated_loudness, f32::NEG_INFINITY);
+        assert_eq!(analysis.true_peak, f32::NEG_INFINITY);
+        assert_eq!(analysis.loudness_range, 0.0);
+    }
+
+    #[test]
+    fn test_processor_publishes_to_handle_and_resets() {
+        let mut processor = LoudnessMeterProcessor::default();
+        let handle = processor.handle().clone();
+        let mut context = AudioContext::from(AudioProcessorSettings::new(48000.0, 2, 2, 512));
+        processor.prepare(&mut context);
+        assert_eq!(handle.momentary_loudness(), f32::NEG_INFINITY);
+
+        let signal = stereo_sine(-23.0, Duration::from_secs(1));
+        for block in signal.chunks(1024) {
+            let mut buffer = AudioBuffer::from_interleaved(2, block);
+            processor.process(&mut context, &mut buffer);
+            assert_eq!(
+                buffer.channel(0),
+                &block.iter().step_by(2).copied().collect::<Vec<_>>()[..]
+            );
+        }
+        assert_close(handle.momentary_loudness(), -23.0, 0.1);
+        assert_close(handle.integrated_loudness(), -23.0, 0.1);
+        assert_close(handle.true_peak(), -23.0, 0.1);
+
+        handle.reset();
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 512);
+        processor.process(&mut context, &mut buffer);
+        assert_eq!(handle.integrated_loudness(), f32::NEG_INFINITY);
+        assert_eq!(handle.true_peak(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        assert_processor_realtime_safe(LoudnessMeterProcessor::default(), Default::default(), 20);
+    }
+}
@@ -0,0 +1,122 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! True-peak estimation through 4x oversampling, as described in ITU-R BS.1770-4 Annex 2.
+
+use audio_processor_traits::num::traits::FloatConst;
+
+use crate::window_functions::{make_window_vec, WindowFunctionType};
+
+pub const OVERSAMPLING_FACTOR: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Polyphase windowed-sinc interpolator shared by every channel
+pub struct TruePeakInterpolator {
+    /// `phases[phase][tap]`
+    phases: Vec<[f64; TAPS_PER_PHASE]>,
+}
+
+impl Default for TruePeakInterpolator {
+    fn default() -> Self {
+        let num_taps = OVERSAMPLING_FACTOR * TAPS_PER_PHASE;
+        let window: Vec<f64> = make_window_vec(num_taps, WindowFunctionType::Hann);
+        let center = (num_taps - 1) as f64 / 2.0;
+
+        let mut phases = vec![[0.0; TAPS_PER_PHASE]; OVERSAMPLING_FACTOR];
+        for (phase_index, phase) in phases.iter_mut().enumerate() {
+            for (tap_index, tap) in phase.iter_mut().enumerate() {
+                let n = tap_index * OVERSAMPLING_FACTOR + phase_index;
+                let x = (n as f64 - center) / OVERSAMPLING_FACTOR as f64;
+                let sinc = if x.abs() < f64::EPSILON {
+                    1.0
+                } else {
+                    (f64::PI() * x).sin() / (f64::PI() * x)
+                };
+                *tap = sinc * window[n];
+            }
+
+            // Unity DC gain on every phase
+            let sum: f64 = phase.iter().sum();
+            for tap in phase.iter_mut() {
+                *tap /= sum;
+            }
+        }
+
+        Self { phases }
+    }
+}
+
+/// Per-channel history for [`TruePeakInterpolator`]
+#[derive(Debug, Clone, Default)]
+pub struct TruePeakState {
+    history: [f64; TAPS_PER_PHASE],
+    cursor: usize,
+}
+
+impl TruePeakState {
+    pub fn reset(&mut self) {
+        self.history = [0.0; TAPS_PER_PHASE];
+        self.cursor = 0;
+    }
+
+    /// Push a sample and return the highest absolute value of its oversampled interpolation
+    pub fn process(&mut self, interpolator: &TruePeakInterpolator, sample: f64) -> f64 {
+        self.history[self.cursor] = sample;
+        self.cursor = (self.cursor + 1) % TAPS_PER_PHASE;
+
+        let mut peak: f64 = 0.0;
+        for phase in &interpolator.phases {
+            let mut value = 0.0;
+            for (tap_index, tap) in phase.iter().enumerate() {
+                // Newest sample multiplies the first tap
+                let history_index = (self.cursor + TAPS_PER_PHASE - 1 - tap_index) % TAPS_PER_PHASE;
+                value += tap * self.history[history_index];
+            }
+            peak = peak.max(value.abs());
+        }
+        peak
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_true_peak_finds_inter_sample_peak() {
+        let interpolator = TruePeakInterpolator::default();
+        let mut state = TruePeakState::default();
+
+        // A sine at a quarter of the sample rate with a 45º phase has samples at 0.707
+        let mut sample_peak: f64 = 0.0;
+        let mut true_peak: f64 = 0.0;
+        for i in 0..1000 {
+            let sample = (f64::PI() / 2.0 * i as f64 + f64::PI() / 4.0).sin();
+            sample_peak = sample_peak.max(sample.abs());
+            true_peak = true_peak.max(state.process(&interpolator, sample));
+        }
+
+        assert!((sample_peak - std::f64::consts::FRAC_1_SQRT_2).abs() < 0.001);
+        assert!((true_peak - 1.0).abs() < 0.05, "{}", true_peak);
+    }
+}
@@ -0,0 +1,141 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+/// FFT based autocorrelation over a fixed size window. Buffers are allocated on construction so
+/// [`Autocorrelation::process`] is real-time safe.
+///
+/// Both YIN & McLeod are based on the autocorrelation `r(τ)` and on the sum of squares of the two
+/// overlapping segments at each lag, `m(τ)`:
+///
+/// * `r(τ) = Σ x(j) * x(j + τ)` for `j` in `0..N - τ`
+/// * `m(τ) = Σ x(j)² + x(j + τ)²` for `j` in `0..N - τ`
+pub struct Autocorrelation {
+    size: usize,
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    fft_buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    correlation: Vec<f32>,
+    squared_sums: Vec<f32>,
+}
+
+impl Autocorrelation {
+    pub fn new(size: usize) -> Self {
+        // Zero-pad to twice the window so the circular correlation equals the linear one
+        let fft_size = size * 2;
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(fft_size);
+        let inverse = planner.plan_fft_inverse(fft_size);
+        let scratch_size = forward
+            .get_inplace_scratch_len()
+            .max(inverse.get_inplace_scratch_len());
+
+        Self {
+            size,
+            forward,
+            inverse,
+            fft_buffer: vec![0.0.into(); fft_size],
+            scratch: vec![0.0.into(); scratch_size],
+            correlation: vec![0.0; size],
+            squared_sums: vec![0.0; size],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// `r(τ)` for the last processed window
+    pub fn correlation(&self) -> &[f32] {
+        &self.correlation
+    }
+
+    /// `m(τ)` for the last processed window
+    pub fn squared_sums(&self) -> &[f32] {
+        &self.squared_sums
+    }
+
+    /// Calculate `r(τ)` and `m(τ)` for `samples`, which must be `size` long.
+    pub fn process(&mut self, samples: &[f32]) {
+        assert_eq!(samples.len(), self.size);
+
+        for (target, sample) in self.fft_buffer.iter_mut().zip(samples) {
+            *target = Complex::new(*sample, 0.0);
+        }
+        for target in self.fft_buffer.iter_mut().skip(self.size) {
+            *target = 0.0.into();
+        }
+
+        self.forward
+            .process_with_scratch(&mut self.fft_buffer, &mut self.scratch);
+        for bin in self.fft_buffer.iter_mut() {
+            *bin = Complex::new(bin.norm_sqr(), 0.0);
+        }
+        self.inverse
+            .process_with_scratch(&mut self.fft_buffer, &mut self.scratch);
+
+        let normalization = 1.0 / self.fft_buffer.len() as f32;
+        for (target, bin) in self.correlation.iter_mut().zip(&self.fft_buffer) {
+            *target = bin.re * normalization;
+        }
+
+        // m(0) = 2 * r(0); every increase in lag drops one sample from each end
+        let mut squared_sum = 2.0 * self.correlation[0];
+        for tau in 0..self.size {
+            self.squared_sums[tau] = squared_sum.max(0.0);
+            let head = samples[tau];
+            let tail = samples[self.size - 1 - tau];
+            squared_sum -= head * head + tail * tail;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_autocorrelation_matches_naive_implementation() {
+        let samples: Vec<f32> = (0..64)
+            .map(|i| ((i * 7) % 13) as f32 / 13.0 - 0.5)
+            .collect();
+        let mut autocorrelation = Autocorrelation::new(samples.len());
+        autocorrelation.process(&samples);
+
+        for tau in 0..samples.len() {
+            let mut r = 0.0;
+            let mut m = 0.0;
+            for j in 0..samples.len() - tau {
+                r += samples[j] * samples[j + tau];
+                m += samples[j] * samples[j] + samples[j + tau] * samples[j + tau];
+            }
+            assert!((autocorrelation.correlation()[tau] - r).abs() < 0.001);
+            assert!((autocorrelation.squared_sums()[tau] - m).abs() < 0.001);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! McLeod Pitch Method (MPM) fundamental frequency estimator.
+//!
+//! Reference:
+//! * McLeod, P., & Wyvill, G. (2005). A smarter way to find pitch.
+//!   <https://www.cs.otago.ac.nz/graphics/Geoff/tartini/papers/A_Smarter_Way_to_Find_Pitch.pdf>
+
+use super::autocorrelation::Autocorrelation;
+use super::{parabolic_interpolation, PitchEstimate};
+
+pub struct McLeod {
+    cutoff: f32,
+    normalized_square_difference: Vec<f32>,
+}
+
+impl McLeod {
+    /// Create a MPM estimator for windows of `size` samples.
+    ///
+    /// * cutoff: `k` in the paper. The first key maximum higher than `cutoff` times the highest key
+    ///   maximum is picked. 0.8-0.95 are typical.
+    pub fn new(size: usize, cutoff: f32) -> Self {
+        Self {
+            cutoff,
+            normalized_square_difference: vec![0.0; size],
+        }
+    }
+
+    /// Estimate the period from an autocorrelation that's already been processed, searching lags
+    /// in `min_tau..=max_tau`.
+    pub fn estimate(
+        &mut self,
+        autocorrelation: &Autocorrelation,
+        sample_rate: f32,
+        min_tau: usize,
+        max_tau: usize,
+    ) -> Option<PitchEstimate> {
+        let correlation = autocorrelation.correlation();
+        let squared_sums = autocorrelation.squared_sums();
+        let max_tau = max_tau.min(correlation.len() - 2);
+        if min_tau < 1 || min_tau >= max_tau {
+            return None;
+        }
+
+        let nsdf = &mut self.normalized_square_difference;
+        for tau in 0..=max_tau + 1 {
+            nsdf[tau] = if squared_sums[tau] > 0.0 {
+                2.0 * correlation[tau] / squared_sums[tau]
+            } else {
+                0.0
+            };
+        }
+
+        // Key maxima are the highest points between each positively sloped zero crossing and the
+        // following negatively sloped zero crossing. The peak at lag 0 is skipped.
+        let mut tau = 1;
+        while tau <= max_tau && nsdf[tau] > 0.0 {
+            tau += 1;
+        }
+
+        let mut highest_key_maximum: f32 = 0.0;
+        let mut current_maximum: Option<usize> = None;
+        for tau in tau..=max_tau {
+            if nsdf[tau] > 0.0 {
+                if current_maximum.map(|m| nsdf[tau] > nsdf[m]).unwrap_or(true) {
+                    current_maximum = Some(tau);
+                }
+            } else if let Some(maximum) = current_maximum.take() {
+                if maximum >= min_tau {
+                    highest_key_maximum = highest_key_maximum.max(nsdf[maximum]);
+                }
+            }
+        }
+        if let Some(maximum) = current_maximum {
+            if maximum >= min_tau {
+                highest_key_maximum = highest_key_maximum.max(nsdf[maximum]);
+            }
+        }
+        if highest_key_maximum <= 0.0 {
+            return None;
+        }
+
+        // Second pass, pick the first key maximum over the threshold
+        let threshold = self.cutoff * highest_key_maximum;
+        let mut current_maximum: Option<usize> = None;
+        let mut picked = None;
+        for tau in tau..=max_tau + 1 {
+            if tau <= max_tau && nsdf[tau] > 0.0 {
+                if current_maximum.map(|m| nsdf[tau] > nsdf[m]).unwrap_or(true) {
+                    current_maximum = Some(tau);
+                }
+            } else if let Some(maximum) = current_maximum.take() {
+                if maximum >= min_tau && nsdf[maximum] >= threshold {
+                    picked = Some(maximum);
+                    break;
+                }
+            }
+        }
+
+        let (period, clarity) = parabolic_interpolation(nsdf, picked?);
+        if period <= 0.0 {
+            return None;
+        }
+
+        Some(PitchEstimate {
+            frequency: sample_rate / period,
+            confidence: clarity.clamp(0.0, 1.0),
+        })
+    }
+}
@@ -0,0 +1,504 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Monophonic pitch (fundamental frequency) detection.
+//!
+//! Two estimators are implemented, both over an FFT based autocorrelation:
+//!
+//! * [`PitchDetectionAlgorithm::Yin`] - [`yin::Yin`]
+//! * [`PitchDetectionAlgorithm::McLeod`] - [`mcleod::McLeod`]
+//!
+//! Every estimate has a `confidence` between 0 and 1; for YIN this is `1 - d'(τ)`, for MPM it's
+//! the "clarity" of the picked key maximum.
+//!
+//! [`PitchDetectorProcessor`] runs in real-time and publishes its last estimate to a
+//! [`PitchDetectorHandle`] which may be read from any thread (e.g. to draw a tuner).
+//! [`detect_pitch`] runs the same analysis offline over a buffer.
+//!
+//! ## Usage
+//! ```
+//! use audio_processor_analysis::pitch_detection::PitchDetectorProcessor;
+//! use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
+//!
+//! let mut pitch_detector = PitchDetectorProcessor::default();
+//! let handle = pitch_detector.handle().clone(); // can send to another thread
+//!
+//! let mut context = AudioContext::from(AudioProcessorSettings::default());
+//! pitch_detector.prepare(&mut context);
+//! let mut buffer = AudioBuffer::empty();
+//! buffer.resize(2, 512);
+//! pitch_detector.process(&mut context, &mut buffer);
+//!
+//! if let Some(estimate) = handle.estimate() {
+//!     println!("{}Hz confidence={}", estimate.frequency, estimate.confidence);
+//! }
+//! ```
+
+use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_traits::{AtomicF32, AudioBuffer, AudioContext, AudioProcessor};
+
+use autocorrelation::Autocorrelation;
+use mcleod::McLeod;
+use yin::Yin;
+
+pub mod autocorrelation;
+pub mod mcleod;
+pub mod yin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PitchDetectionAlgorithm {
+    Yin,
+    McLeod,
+}
+
+#[derive(Debug, Clone)]
+pub struct PitchDetectorOptions {
+    /// Defaults to [`PitchDetectionAlgorithm::Yin`]
+    pub algorithm: PitchDetectionAlgorithm,
+    /// Number of samples analysed per estimate. Must be at least twice the period of
+    /// `minimum_frequency`. Defaults to 2048
+    pub window_size: usize,
+    /// Number of samples between estimates. Defaults to 512
+    pub hop_size: usize,
+    /// Lowest frequency searched for, in Hz. Defaults to 60Hz
+    pub minimum_frequency: f32,
+    /// Highest frequency searched for, in Hz. Defaults to 2kHz
+    pub maximum_frequency: f32,
+    /// YIN absolute threshold or MPM `k` cutoff. Defaults to 0.15 for YIN; use ~0.9 for MPM
+    pub threshold: f32,
+    /// Windows with RMS lower than this aren't analysed. Defaults to 0.001 (-60dB)
+    pub silence_threshold: f32,
+}
+
+impl Default for PitchDetectorOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: PitchDetectionAlgorithm::Yin,
+            window_size: 2048,
+            hop_size: 512,
+            minimum_frequency: 60.0,
+            maximum_frequency: 2000.0,
+            threshold: 0.15,
+            silence_threshold: 0.001,
+        }
+    }
+}
+
+impl PitchDetectorOptions {
+    /// Default options for the McLeod pitch method
+    pub fn mcleod() -> Self {
+        Self {
+            algorithm: PitchDetectionAlgorithm::McLeod,
+            threshold: 0.9,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchEstimate {
+    /// Fundamental frequency in Hz
+    pub frequency: f32,
+    /// Confidence of this estimate, between 0 and 1
+    pub confidence: f32,
+}
+
+/// Find the vertex of the parabola through `values[index - 1..=index + 1]`.
+///
+/// Returns the fractional index & interpolated value.
+pub(crate) fn parabolic_interpolation(values: &[f32], index: usize) -> (f32, f32) {
+    if index < 1 || index + 1 >= values.len() {
+        return (index as f32, values[index]);
+    }
+
+    let left = values[index - 1];
+    let center = values[index];
+    let right = values[index + 1];
+    let denominator = left - 2.0 * center + right;
+    if denominator.abs() < f32::EPSILON {
+        return (index as f32, center);
+    }
+
+    let offset = 0.5 * (left - right) / denominator;
+    let value = center - 0.25 * (left - right) * offset;
+    (index as f32 + offset, value)
+}
+
+enum Estimator {
+    Yin(Yin),
+    McLeod(McLeod),
+}
+
+/// Pitch estimator over single windows of audio. Real-time safe after construction.
+pub struct PitchDetector {
+    options: PitchDetectorOptions,
+    autocorrelation: Autocorrelation,
+    estimator: Estimator,
+}
+
+impl PitchDetector {
+    pub fn new(options: PitchDetectorOptions) -> Self {
+        let autocorrelation = Autocorrelation::new(options.window_size);
+        let estimator = match options.algorithm {
+            PitchDetectionAlgorithm::Yin => {
+                Estimator::Yin(Yin::new(options.window_size, options.threshold))
+            }
+            PitchDetectionAlgorithm::McLeod => {
+                Estimator::McLeod(McLeod::new(options.window_size, options.threshold))
+            }
+        };
+
+        Self {
+            options,
+            autocorrelation,
+            estimator,
+        }
+    }
+
+    pub fn options(&self) -> &PitchDetectorOptions {
+        &self.options
+    }
+
+    /// Estimate the pitch of `samples`, which must be `window_size` long.
+    ///
+    /// Returns `None` on silence or if no periodicity was found.
+    pub fn estimate(&mut self, sample_rate: f32, samples: &[f32]) -> Option<PitchEstimate> {
+        self.autocorrelation.process(samples);
+
+        let mean_square = self.autocorrelation.correlation()[0] / samples.len() as f32;
+        if mean_square.sqrt() < self.options.silence_threshold {
+            return None;
+        }
+
+        let min_tau = (sample_rate / self.options.maximum_frequency)
+            .floor()
+            .max(1.0) as usize;
+        let max_tau = (sample_rate / self.options.minimum_frequency).ceil() as usize;
+        // At least two periods should fit in the window
+        let max_tau = max_tau.min(self.options.window_size / 2);
+
+        match &mut self.estimator {
+            Estimator::Yin(yin) => {
+                yin.estimate(&self.autocorrelation, sample_rate, min_tau, max_tau)
+            }
+            Estimator::McLeod(mcleod) => {
+                mcleod.estimate(&self.autocorrelation, sample_rate, min_tau, max_tau)
+            }
+        }
+    }
+}
+
+/// Handle for [`PitchDetectorProcessor`], use this to read the current estimate from any thread.
+pub struct PitchDetectorHandle {
+    frequency: AtomicF32,
+    confidence: AtomicF32,
+}
+
+impl PitchDetectorHandle {
+    /// Last estimated frequency in Hz, 0.0 if the last window was silent or unpitched
+    pub fn frequency(&self) -> f32 {
+        self.frequency.get()
+    }
+
+    /// Confidence of the last estimate, between 0 and 1
+    pub fn confidence(&self) -> f32 {
+        self.confidence.get()
+    }
+
+    /// Last estimate, if there's one
+    pub fn estimate(&self) -> Option<PitchEstimate> {
+        let frequency = self.frequency();
+        if frequency <= 0.0 {
+            return None;
+        }
+
+        Some(PitchEstimate {
+            frequency,
+            confidence: self.confidence(),
+        })
+    }
+}
+
+/// Real-time pitch tracker. Input channels are summed into mono; the buffer is passed through
+/// unchanged.
+///
+/// An estimate is made every `hop_size` samples over the last `window_size` samples.
+pub struct PitchDetectorProcessor {
+    detector: PitchDetector,
+    handle: Shared<PitchDetectorHandle>,
+    input_buffer: Vec<f32>,
+    analysis_buffer: Vec<f32>,
+    cursor: usize,
+    samples_since_estimate: usize,
+    sample_rate: f32,
+}
+
+impl Default for PitchDetectorProcessor {
+    fn default() -> Self {
+        Self::new(PitchDetectorOptions::default())
+    }
+}
+
+impl PitchDetectorProcessor {
+    pub fn new(options: PitchDetectorOptions) -> Self {
+        let window_size = options.window_size;
+        Self {
+            detector: PitchDetector::new(options),
+            handle: make_shared(PitchDetectorHandle {
+                frequency: AtomicF32::new(0.0),
+                confidence: AtomicF32::new(0.0),
+            }),
+            input_buffer: vec![0.0; window_size],
+            analysis_buffer: vec![0.0; window_size],
+            cursor: 0,
+            samples_since_estimate: 0,
+            sample_rate: 44100.0,
+        }
+    }
+
+    /// Get a reference to the `basedrop::Shared` handle of this processor
+    pub fn handle(&self) -> &Shared<PitchDetectorHandle> {
+        &self.handle
+    }
+
+    fn perform_estimate(&mut self) {
+        // Unroll the circular buffer, oldest sample first
+        let (newest, oldest) = self.input_buffer.split_at(self.cursor);
+        self.analysis_buffer[..oldest.len()].copy_from_slice(oldest);
+        self.analysis_buffer[oldest.len()..].copy_from_slice(newest);
+
+        let estimate = self
+            .detector
+            .estimate(self.sample_rate, &self.analysis_buffer);
+        let estimate = estimate.unwrap_or(PitchEstimate {
+            frequency: 0.0,
+            confidence: 0.0,
+        });
+        self.handle.frequency.set(estimate.frequency);
+        self.handle.confidence.set(estimate.confidence);
+    }
+}
+
+impl AudioProcessor for PitchDetectorProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.sample_rate = context.settings.sample_rate();
+        self.input_buffer.fill(0.0);
+        self.cursor = 0;
+        self.samples_since_estimate = 0;
+    }
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        for sample_index in 0..data.num_samples() {
+            self.input_buffer[self.cursor] = downmix(data, sample_index);
+            self.cursor = (self.cursor + 1) % self.input_buffer.len();
+            self.samples_since_estimate += 1;
+
+            if self.samples_since_estimate >= self.detector.options().hop_size {
+                self.samples_since_estimate = 0;
+                self.perform_estimate();
+            }
+        }
+    }
+}
+
+/// Sum all channels of a frame into mono, shared by the real-time and offline detectors so both
+/// see the same signal
+#[inline]
+fn downmix(data: &AudioBuffer<f32>, sample_index: usize) -> f32 {
+    let mut sample = 0.0;
+    for channel in 0..data.num_channels() {
+        sample += *data.get(channel, sample_index);
+    }
+    sample
+}
+
+/// An estimate for the window starting at `position_samples`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchFrame {
+    pub position_samples: usize,
+    pub estimate: Option<PitchEstimate>,
+}
+
+/// Estimate the pitch of every `hop_size` window of a buffer. Channels are summed into mono. Not
+/// real-time safe.
+pub fn detect_pitch(
+    sample_rate: f32,
+    options: PitchDetectorOptions,
+    data: &AudioBuffer<f32>,
+) -> Vec<PitchFrame> {
+    let window_size = options.window_size;
+    let hop_size = options.hop_size.max(1);
+    let mut detector = PitchDetector::new(options);
+
+    let mono: Vec<f32> = (0..data.num_samples()).map(|i| downmix(data, i)).collect();
+    let mut frames = vec![];
+    let mut position_samples = 0;
+    while position_samples + window_size <= mono.len() {
+        let window = &mono[position_samples..position_samples + window_size];
+        frames.push(PitchFrame {
+            position_samples,
+            estimate: detector.estimate(sample_rate, window),
+        });
+        position_samples += hop_size;
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
+    use std::time::Duration;
+
+    use audio_processor_testing_helpers::{oscillator_buffer, saw_generator, sine_buffer};
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::*;
+
+    fn assert_estimate(estimate: Option<PitchEstimate>, frequency: f32) {
+        let estimate = estimate.expect("Expected a pitch estimate");
+        assert!(
+            (estimate.frequency - frequency).abs() < frequency * 0.01,
+            "Estimated {:?} expected {}Hz",
+            estimate,
+            frequency
+        );
+        assert!(estimate.confidence > 0.8, "Low confidence {:?}", estimate);
+    }
+
+    #[test]
+    fn test_parabolic_interpolation() {
+        let (index, value) = parabolic_interpolation(&[1.0, 0.0, 1.0], 1);
+        assert!((index - 1.0).abs() < f32::EPSILON);
+        assert!(value.abs() < f32::EPSILON);
+        let (index, _) = parabolic_interpolation(&[2.0, 0.0, 1.0], 1);
+        assert!(index > 1.0);
+    }
+
+    #[test]
+    fn test_yin_detects_sine() {
+        for frequency in [82.41, 220.0, 440.0, 1000.0] {
+            let signal = sine_buffer(44100.0, frequency, Duration::from_millis(100));
+            let mut detector = PitchDetector::new(PitchDetectorOptions::default());
+            assert_estimate(detector.estimate(44100.0, &signal[0..2048]), frequency);
+        }
+    }
+
+    #[test]
+    fn test_mcleod_detects_sine() {
+        for frequency in [82.41, 220.0, 440.0, 1000.0] {
+            let signal = sine_buffer(44100.0, frequency, Duration::from_millis(100));
+            let mut detector = PitchDetector::new(PitchDetectorOptions::mcleod());
+            assert_estimate(detector.estimate(44100.0, &signal[0..2048]), frequency);
+        }
+    }
+
+    #[test]
+    fn test_detectors_find_fundamental_of_sawtooth() {
+        let signal = oscillator_buffer(44100.0, 110.0, Duration::from_millis(100), saw_generator);
+        let mut yin = PitchDetector::new(PitchDetectorOptions::default());
+        assert_estimate(yin.estimate(44100.0, &signal[0..2048]), 110.0);
+        let mut mcleod = PitchDetector::new(PitchDetectorOptions::mcleod());
+        assert_estimate(mcleod.estimate(44100.0, &signal[0..2048]), 110.0);
+    }
+
+    #[test]
+    fn test_silence_has_no_estimate() {
+        let signal = vec![0.0; 2048];
+        let mut detector = PitchDetector::new(PitchDetectorOptions::default());
+        assert_eq!(detector.estimate(44100.0, &signal), None);
+        let mut detector = PitchDetector::new(PitchDetectorOptions::mcleod());
+        assert_eq!(detector.estimate(44100.0, &signal), None);
+    }
+
+    #[test]
+    fn test_processor_publishes_estimate_to_handle() {
+        let mut processor = PitchDetectorProcessor::default();
+        let handle = processor.handle().clone();
+        assert_eq!(handle.estimate(), None);
+
+        let settings = AudioProcessorSettings::new(44100.0, 1, 1, 512);
+        let mut context = AudioContext::from(settings);
+        processor.prepare(&mut context);
+        let signal = sine_buffer(44100.0, 440.0, Duration::from_millis(200));
+        let mut buffer = AudioBuffer::from_interleaved(1, &signal);
+        processor.process(&mut context, &mut buffer);
+
+        assert_estimate(handle.estimate(), 440.0);
+        assert_eq!(buffer.channel(0), &signal[..]);
+
+        let mut silence = AudioBuffer::empty();
+        silence.resize(1, 4096);
+        processor.process(&mut context, &mut silence);
+        assert_eq!(handle.estimate(), None);
+        assert_eq!(handle.confidence(), 0.0);
+    }
+
+    #[test]
+    fn test_detect_pitch_offline() {
+        let mut signal = sine_buffer(44100.0, 220.0, Duration::from_millis(500));
+        signal.extend(sine_buffer(44100.0, 330.0, Duration::from_millis(500)));
+        let buffer = AudioBuffer::from_interleaved(1, &signal);
+
+        let frames = detect_pitch(44100.0, PitchDetectorOptions::default(), &buffer);
+        assert_eq!(frames[1].position_samples, 512);
+        assert_estimate(frames[2].estimate, 220.0);
+        assert_estimate(frames[frames.len() - 2].estimate, 330.0);
+    }
+
+    #[test]
+    fn test_offline_and_processor_downmix_match() {
+        let options = PitchDetectorOptions::default();
+        let signal = sine_buffer(44100.0, 220.0, Duration::from_millis(200));
+        let quiet: Vec<f32> = signal.iter().map(|s| s * 0.25).collect();
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, signal.len());
+        buffer.channel_mut(0).copy_from_slice(&signal);
+        buffer.channel_mut(1).copy_from_slice(&quiet);
+
+        let frames = detect_pitch(44100.0, options.clone(), &buffer);
+        let last_window_end = frames.last().unwrap().position_samples + options.window_size;
+
+        let mut processor = PitchDetectorProcessor::new(options.clone());
+        let handle = processor.handle().clone();
+        let mut context = AudioContext::from(AudioProcessorSettings::new(44100.0, 2, 2, 512));
+        processor.prepare(&mut context);
+        let mut input = AudioBuffer::empty();
+        input.resize(2, last_window_end);
+        for channel in 0..2 {
+            input
+                .channel_mut(channel)
+                .copy_from_slice(&buffer.channel(channel)[..last_window_end]);
+        }
+        processor.process(&mut context, &mut input);
+
+        assert_eq!(handle.estimate(), frames.last().unwrap().estimate);
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        assert_processor_realtime_safe(PitchDetectorProcessor::default(), Default::default(), 20);
+    }
+}
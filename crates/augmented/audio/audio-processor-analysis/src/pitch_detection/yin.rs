@@ -0,0 +1,115 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! YIN fundamental frequency estimator.
+//!
+//! Reference:
+//! * de Cheveigné, A., & Kawahara, H. (2002). YIN, a fundamental frequency estimator for speech
+//!   and music. <http://audition.ens.fr/adc/pdf/2002_JASA_YIN.pdf>
+
+use super::autocorrelation::Autocorrelation;
+use super::{parabolic_interpolation, PitchEstimate};
+
+pub struct Yin {
+    threshold: f32,
+    cumulative_mean_normalized_difference: Vec<f32>,
+}
+
+impl Yin {
+    /// Create a YIN estimator for windows of `size` samples.
+    ///
+    /// * threshold: Absolute threshold on the cumulative mean normalized difference (step 4 in the
+    ///   paper). Lower values are stricter. 0.1-0.2 are typical.
+    pub fn new(size: usize, threshold: f32) -> Self {
+        Self {
+            threshold,
+            cumulative_mean_normalized_difference: vec![1.0; size],
+        }
+    }
+
+    /// Estimate the period from an autocorrelation that's already been processed, searching lags
+    /// in `min_tau..=max_tau`.
+    pub fn estimate(
+        &mut self,
+        autocorrelation: &Autocorrelation,
+        sample_rate: f32,
+        min_tau: usize,
+        max_tau: usize,
+    ) -> Option<PitchEstimate> {
+        let correlation = autocorrelation.correlation();
+        let squared_sums = autocorrelation.squared_sums();
+        let max_tau = max_tau.min(correlation.len() - 2);
+        if min_tau < 1 || min_tau >= max_tau {
+            return None;
+        }
+
+        // Steps 2 & 3 - difference function and its cumulative mean normalization
+        let cmnd = &mut self.cumulative_mean_normalized_difference;
+        cmnd[0] = 1.0;
+        let mut running_sum = 0.0;
+        for tau in 1..=max_tau + 1 {
+            let difference = (squared_sums[tau] - 2.0 * correlation[tau]).max(0.0);
+            running_sum += difference;
+            cmnd[tau] = if running_sum > 0.0 {
+                difference * tau as f32 / running_sum
+            } else {
+                1.0
+            };
+        }
+
+        // Step 4 - first dip under the threshold, followed to its local minimum
+        let mut best_tau = None;
+        let mut tau = min_tau;
+        while tau <= max_tau {
+            if cmnd[tau] < self.threshold {
+                while tau < max_tau && cmnd[tau + 1] < cmnd[tau] {
+                    tau += 1;
+                }
+                best_tau = Some(tau);
+                break;
+            }
+            tau += 1;
+        }
+        // No dip under the threshold; take the global minimum, it'll have low confidence
+        let best_tau = best_tau.unwrap_or_else(|| {
+            (min_tau..=max_tau)
+                .min_by(|t1, t2| {
+                    cmnd[*t1]
+                        .partial_cmp(&cmnd[*t2])
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap_or(min_tau)
+        });
+
+        // Step 5 - parabolic interpolation
+        let (period, value) = parabolic_interpolation(cmnd, best_tau);
+        if period <= 0.0 {
+            return None;
+        }
+
+        Some(PitchEstimate {
+            frequency: sample_rate / period,
+            confidence: (1.0 - value).clamp(0.0, 1.0),
+        })
+    }
+}
@@ -0,0 +1,111 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Chroma (pitch class profile) vectors.
+//!
+//! Each FFT bin within the configured range is assigned to its nearest pitch class in equal
+//! temperament; chroma is the normalized sum of the power in each class. Index 0 is C.
+
+pub const NUM_PITCH_CLASSES: usize = 12;
+
+/// Maps FFT bins onto pitch classes
+pub struct ChromaFilterbank {
+    start_bin: usize,
+    pitch_classes: Vec<usize>,
+}
+
+impl ChromaFilterbank {
+    /// * tuning_frequency: Frequency of A4, usually 440Hz
+    pub fn new(
+        fft_size: usize,
+        sample_rate: f32,
+        tuning_frequency: f32,
+        minimum_frequency: f32,
+        maximum_frequency: f32,
+    ) -> Self {
+        let num_bins = fft_size / 2 + 1;
+        let bin_width = sample_rate / fft_size as f32;
+        let start_bin = ((minimum_frequency / bin_width).ceil() as usize).max(1);
+        let end_bin = ((maximum_frequency / bin_width).floor() as usize).min(num_bins - 1);
+
+        let pitch_classes = (start_bin..=end_bin)
+            .map(|bin| {
+                let frequency = bin as f32 * bin_width;
+                let midi_note = 69.0 + 12.0 * (frequency / tuning_frequency).log2();
+                (midi_note.round() as i32).rem_euclid(NUM_PITCH_CLASSES as i32) as usize
+            })
+            .collect();
+
+        Self {
+            start_bin,
+            pitch_classes,
+        }
+    }
+
+    /// Write the chroma of a power spectrum onto `output`, normalized so the loudest pitch class
+    /// is 1. Silence yields all zeros.
+    pub fn apply(&self, power: &[f32], output: &mut [f32; NUM_PITCH_CLASSES]) {
+        output.fill(0.0);
+        for (pitch_class, power) in self
+            .pitch_classes
+            .iter()
+            .zip(power.iter().skip(self.start_bin))
+        {
+            output[*pitch_class] += power;
+        }
+
+        let maximum = output.iter().copied().fold(0.0, f32::max);
+        if maximum > f32::EPSILON {
+            for value in output.iter_mut() {
+                *value /= maximum;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a4_maps_to_a() {
+        let fft_size = 4096;
+        let sample_rate = 44100.0;
+        let filterbank = ChromaFilterbank::new(fft_size, sample_rate, 440.0, 50.0, 5000.0);
+        let mut power = vec![0.0; fft_size / 2 + 1];
+        power[(440.0 / sample_rate * fft_size as f32).round() as usize] = 1.0;
+
+        let mut chroma = [0.0; NUM_PITCH_CLASSES];
+        filterbank.apply(&power, &mut chroma);
+        assert_eq!(chroma[9], 1.0);
+        assert_eq!(chroma.iter().sum::<f32>(), 1.0);
+    }
+
+    #[test]
+    fn test_silence_is_zero() {
+        let filterbank = ChromaFilterbank::new(2048, 44100.0, 440.0, 50.0, 5000.0);
+        let mut chroma = [1.0; NUM_PITCH_CLASSES];
+        filterbank.apply(&[0.0; 1025], &mut chroma);
+        assert!(chroma.iter().all(|value| *value == 0.0));
+    }
+}
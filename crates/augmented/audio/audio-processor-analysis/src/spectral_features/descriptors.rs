@@ -0,0 +1,165 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Scalar descriptors of a magnitude spectrum.
+//!
+//! All functions take the magnitudes of the positive frequency bins (`0..=N/2`) of an N sized FFT
+//! and `bin_width` (`sample_rate / N`) in Hz. An empty or silent spectrum yields `0.0`.
+
+/// Amplitude-weighted mean frequency, in Hz. Correlates with perceived "brightness".
+pub fn spectral_centroid(magnitudes: &[f32], bin_width: f32) -> f32 {
+    let total: f32 = magnitudes.iter().sum();
+    if total <= f32::EPSILON {
+        return 0.0;
+    }
+
+    let weighted: f32 = magnitudes
+        .iter()
+        .enumerate()
+        .map(|(bin, magnitude)| bin as f32 * bin_width * magnitude)
+        .sum();
+    weighted / total
+}
+
+/// Amplitude-weighted standard deviation around `centroid`, in Hz.
+pub fn spectral_spread(magnitudes: &[f32], bin_width: f32, centroid: f32) -> f32 {
+    let total: f32 = magnitudes.iter().sum();
+    if total <= f32::EPSILON {
+        return 0.0;
+    }
+
+    let variance: f32 = magnitudes
+        .iter()
+        .enumerate()
+        .map(|(bin, magnitude)| {
+            let delta = bin as f32 * bin_width - centroid;
+            delta * delta * magnitude
+        })
+        .sum::<f32>()
+        / total;
+    variance.sqrt()
+}
+
+/// Ratio between the geometric and arithmetic means of the power spectrum, between 0 and 1.
+///
+/// Close to 1 for noise and close to 0 for tonal signals.
+pub fn spectral_flatness(magnitudes: &[f32]) -> f32 {
+    if magnitudes.is_empty() {
+        return 0.0;
+    }
+
+    let len = magnitudes.len() as f32;
+    let arithmetic_mean = magnitudes.iter().map(|m| m * m).sum::<f32>() / len;
+    if arithmetic_mean <= f32::EPSILON {
+        return 0.0;
+    }
+
+    let log_mean = magnitudes.iter().map(|m| (m * m + 1e-20).ln()).sum::<f32>() / len;
+    (log_mean.exp() / arithmetic_mean).min(1.0)
+}
+
+/// Frequency below which `ratio` (e.g. 0.85) of the spectrum's energy is contained, in Hz.
+pub fn spectral_rolloff(magnitudes: &[f32], bin_width: f32, ratio: f32) -> f32 {
+    let total: f32 = magnitudes.iter().map(|m| m * m).sum();
+    if total <= f32::EPSILON {
+        return 0.0;
+    }
+
+    let threshold = total * ratio;
+    let mut cumulative = 0.0;
+    for (bin, magnitude) in magnitudes.iter().enumerate() {
+        cumulative += magnitude * magnitude;
+        if cumulative >= threshold {
+            return bin as f32 * bin_width;
+        }
+    }
+    (magnitudes.len() - 1) as f32 * bin_width
+}
+
+/// Sum of the (half-wave rectified) increase in magnitude of each bin since the previous frame.
+pub fn spectral_flux(previous_magnitudes: &[f32], magnitudes: &[f32]) -> f32 {
+    previous_magnitudes
+        .iter()
+        .zip(magnitudes)
+        .map(|(previous, current)| (current - previous).max(0.0))
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::assert_f_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_centroid_and_spread_of_a_single_bin() {
+        let mut magnitudes = vec![0.0; 100];
+        magnitudes[10] = 1.0;
+        let centroid = spectral_centroid(&magnitudes, 10.0);
+        assert_f_eq!(centroid, 100.0);
+        assert_f_eq!(spectral_spread(&magnitudes, 10.0, centroid), 0.0);
+    }
+
+    #[test]
+    fn test_centroid_and_spread_of_two_bins() {
+        let mut magnitudes = vec![0.0; 100];
+        magnitudes[10] = 1.0;
+        magnitudes[30] = 1.0;
+        let centroid = spectral_centroid(&magnitudes, 10.0);
+        assert_f_eq!(centroid, 200.0);
+        assert_f_eq!(spectral_spread(&magnitudes, 10.0, centroid), 100.0);
+    }
+
+    #[test]
+    fn test_flatness() {
+        let flat = vec![1.0; 100];
+        assert!((spectral_flatness(&flat) - 1.0).abs() < 0.001);
+
+        let mut peak = vec![0.0; 100];
+        peak[10] = 1.0;
+        assert!(spectral_flatness(&peak) < 0.001);
+    }
+
+    #[test]
+    fn test_rolloff() {
+        let magnitudes = vec![1.0; 100];
+        let rolloff = spectral_rolloff(&magnitudes, 1.0, 0.85);
+        assert_f_eq!(rolloff, 84.0);
+    }
+
+    #[test]
+    fn test_flux_only_counts_increases() {
+        let previous = [1.0, 1.0, 1.0];
+        assert_f_eq!(spectral_flux(&previous, &previous), 0.0);
+        assert_f_eq!(spectral_flux(&previous, &[2.0, 0.0, 1.5]), 1.5);
+    }
+
+    #[test]
+    fn test_silence() {
+        let magnitudes = vec![0.0; 100];
+        assert_f_eq!(spectral_centroid(&magnitudes, 1.0), 0.0);
+        assert_f_eq!(spectral_spread(&magnitudes, 1.0, 0.0), 0.0);
+        assert_f_eq!(spectral_flatness(&magnitudes), 0.0);
+        assert_f_eq!(spectral_rolloff(&magnitudes, 1.0, 0.85), 0.0);
+    }
+}
@@ -0,0 +1,210 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Mel filterbank & MFCCs.
+//!
+//! Uses the HTK mel scale, `2595 * log10(1 + f / 700)`, with triangular filters of unit peak
+//! evenly spaced in mel. MFCCs are the orthonormal DCT-II of the natural log of the filterbank
+//! energies.
+
+/// Convert a frequency in Hz to mels
+pub fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+/// Convert mels to a frequency in Hz
+pub fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10.0_f32.powf(mel / 2595.0) - 1.0)
+}
+
+struct MelFilter {
+    start_bin: usize,
+    weights: Vec<f32>,
+}
+
+/// Triangular filters over the positive FFT bins
+pub struct MelFilterbank {
+    filters: Vec<MelFilter>,
+}
+
+impl MelFilterbank {
+    /// * fft_size: Size of the FFT whose bins will be filtered
+    /// * sample_rate: Sample rate of the analysed signal
+    /// * num_bands: Number of mel bands
+    /// * minimum_frequency/maximum_frequency: Edges of the first & last filters, in Hz
+    pub fn new(
+        fft_size: usize,
+        sample_rate: f32,
+        num_bands: usize,
+        minimum_frequency: f32,
+        maximum_frequency: f32,
+    ) -> Self {
+        let num_bins = fft_size / 2 + 1;
+        let bin_width = sample_rate / fft_size as f32;
+        let maximum_frequency = maximum_frequency.min(sample_rate / 2.0);
+        let min_mel = hz_to_mel(minimum_frequency);
+        let max_mel = hz_to_mel(maximum_frequency);
+
+        // num_bands + 2 edges; filter `i` goes from edge `i` to `i + 2`, peaking on `i + 1`
+        let edges: Vec<f32> = (0..num_bands + 2)
+            .map(|i| mel_to_hz(min_mel + (max_mel - min_mel) * i as f32 / (num_bands + 1) as f32))
+            .collect();
+
+        let filters = edges
+            .windows(3)
+            .map(|edges| {
+                let (left, center, right) = (edges[0], edges[1], edges[2]);
+                let start_bin = (left / bin_width).ceil() as usize;
+                let end_bin = ((right / bin_width).floor() as usize).min(num_bins - 1);
+                let weights = (start_bin..=end_bin)
+                    .map(|bin| {
+                        let frequency = bin as f32 * bin_width;
+                        if frequency <= center {
+                            (frequency - left) / (center - left)
+                        } else {
+                            (right - frequency) / (right - center)
+                        }
+                        .max(0.0)
+                    })
+                    .collect();
+                MelFilter { start_bin, weights }
+            })
+            .collect();
+
+        Self { filters }
+    }
+
+    pub fn num_bands(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Write the energy of each band of a power spectrum onto `output`
+    pub fn apply(&self, power: &[f32], output: &mut [f32]) {
+        for (filter, output) in self.filters.iter().zip(output.iter_mut()) {
+            *output = filter
+                .weights
+                .iter()
+                .zip(power.iter().skip(filter.start_bin))
+                .map(|(weight, power)| weight * power)
+                .sum();
+        }
+    }
+}
+
+/// Orthonormal DCT-II with a pre-computed basis, keeping the first `num_coefficients`.
+pub struct Dct {
+    input_size: usize,
+    basis: Vec<f32>,
+}
+
+impl Dct {
+    pub fn new(input_size: usize, num_coefficients: usize) -> Self {
+        let n = input_size as f32;
+        let mut basis = Vec::with_capacity(input_size * num_coefficients);
+        for k in 0..num_coefficients {
+            let scale = if k == 0 {
+                (1.0 / n).sqrt()
+            } else {
+                (2.0 / n).sqrt()
+            };
+            for i in 0..input_size {
+                let angle = std::f32::consts::PI / n * (i as f32 + 0.5) * k as f32;
+                basis.push(scale * angle.cos());
+            }
+        }
+
+        Self { input_size, basis }
+    }
+
+    pub fn process(&self, input: &[f32], output: &mut [f32]) {
+        for (row, output) in self.basis.chunks(self.input_size).zip(output.iter_mut()) {
+            *output = row.iter().zip(input).map(|(b, x)| b * x).sum();
+        }
+    }
+}
+
+/// Compute MFCCs from mel band energies. `log_energies` is used as scratch space.
+pub fn mfcc(dct: &Dct, mel_energies: &[f32], log_energies: &mut [f32], output: &mut [f32]) {
+    for (log_energy, energy) in log_energies.iter_mut().zip(mel_energies) {
+        *log_energy = (energy + 1e-10).ln();
+    }
+    dct.process(log_energies, output);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mel_round_trip() {
+        for hz in [0.0, 100.0, 440.0, 1000.0, 8000.0] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 0.01);
+        }
+        assert!((hz_to_mel(1000.0) - 1000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_filterbank_covers_range() {
+        let filterbank = MelFilterbank::new(2048, 44100.0, 40, 20.0, 8000.0);
+        assert_eq!(filterbank.num_bands(), 40);
+
+        let power = vec![1.0; 1025];
+        let mut output = vec![0.0; 40];
+        filterbank.apply(&power, &mut output);
+        // Filters get wider as frequency increases
+        assert!(output.iter().all(|energy| *energy > 0.0));
+        assert!(output[39] > output[0]);
+    }
+
+    #[test]
+    fn test_filterbank_peaks_on_its_band() {
+        let filterbank = MelFilterbank::new(2048, 44100.0, 40, 20.0, 8000.0);
+        let mut power = vec![0.0; 1025];
+        // ~1kHz
+        power[46] = 1.0;
+        let mut output = vec![0.0; 40];
+        filterbank.apply(&power, &mut output);
+
+        let loudest = output
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(band, _)| band)
+            .unwrap();
+        let center = mel_to_hz(
+            hz_to_mel(20.0) + (hz_to_mel(8000.0) - hz_to_mel(20.0)) * (loudest + 1) as f32 / 41.0,
+        );
+        assert!((center - 1000.0).abs() < 100.0, "{}", center);
+    }
+
+    #[test]
+    fn test_dct_of_constant_only_has_dc() {
+        let dct = Dct::new(8, 4);
+        let mut output = vec![0.0; 4];
+        dct.process(&[1.0; 8], &mut output);
+        assert!((output[0] - 8.0_f32.sqrt()).abs() < 1e-5);
+        for coefficient in &output[1..] {
+            assert!(coefficient.abs() < 1e-5);
+        }
+    }
+}
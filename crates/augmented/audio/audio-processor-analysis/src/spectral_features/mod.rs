@@ -0,0 +1,562 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Spectral feature extraction.
+//!
+//! Per FFT frame descriptors:
+//!
+//! * **Centroid** & **spread** - [`descriptors::spectral_centroid`], [`descriptors::spectral_spread`]
+//! * **Flatness** - [`descriptors::spectral_flatness`]
+//! * **Rolloff** - [`descriptors::spectral_rolloff`]
+//! * **Flux** - [`descriptors::spectral_flux`]
+//! * **MFCCs** over a mel filterbank - [`mel`]
+//! * **Chroma** vectors - [`chroma`]
+//!
+//! [`SpectralFeatureExtractor`] computes features from the bins of an
+//! [`FftProcessor`](crate::fft_processor::FftProcessor). [`SpectralFeaturesProcessor`] wraps both
+//! and publishes the last frame onto a [`SpectralFeaturesHandle`], while
+//! [`extract_spectral_features`] runs over a whole buffer. [`SpectralFeatures::mean`] and
+//! [`SpectralFeatures::mfcc_distance`] summarize and compare clips.
+//!
+//! ## Usage
+//! ```
+//! use audio_processor_analysis::spectral_features::SpectralFeaturesProcessor;
+//! use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
+//!
+//! let mut spectral_features = SpectralFeaturesProcessor::default();
+//! let handle = spectral_features.handle().clone(); // can send to another thread
+//!
+//! let mut context = AudioContext::from(AudioProcessorSettings::default());
+//! spectral_features.prepare(&mut context);
+//! let mut buffer = AudioBuffer::empty();
+//! buffer.resize(2, 512);
+//! spectral_features.process(&mut context, &mut buffer);
+//!
+//! println!("centroid={}Hz mfcc={:?}", handle.centroid(), handle.mfcc());
+//! ```
+
+use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_traits::simple_processor::MonoAudioProcessor;
+use audio_processor_traits::{AtomicF32, AudioBuffer, AudioContext, AudioProcessor};
+use rustfft::num_complex::Complex;
+
+use crate::fft_processor::{FftDirection, FftProcessor, FftProcessorOptions};
+use crate::window_functions::WindowFunctionType;
+use chroma::{ChromaFilterbank, NUM_PITCH_CLASSES};
+use mel::{Dct, MelFilterbank};
+
+pub mod chroma;
+pub mod descriptors;
+pub mod mel;
+
+pub struct SpectralFeaturesOptions {
+    /// Defaults to 2048
+    pub fft_size: usize,
+    /// Defaults to 0.75
+    pub overlap_ratio: f32,
+    /// Defaults to Hann
+    pub window_function: WindowFunctionType,
+    /// Number of mel bands MFCCs are computed over. Defaults to 40
+    pub num_mel_bands: usize,
+    /// Number of MFCCs kept, including the 0th. Defaults to 13
+    pub num_mfcc: usize,
+    /// Lowest frequency of the mel & chroma filterbanks. Defaults to 20Hz
+    pub minimum_frequency: f32,
+    /// Highest frequency of the mel & chroma filterbanks, clamped to nyquist. Defaults to 8kHz
+    pub maximum_frequency: f32,
+    /// Energy ratio for the rolloff frequency. Defaults to 0.85
+    pub rolloff_ratio: f32,
+    /// Frequency of A4 for chroma. Defaults to 440Hz
+    pub tuning_frequency: f32,
+}
+
+impl Default for SpectralFeaturesOptions {
+    fn default() -> Self {
+        Self {
+            fft_size: 2048,
+            overlap_ratio: 0.75,
+            window_function: WindowFunctionType::Hann,
+            num_mel_bands: 40,
+            num_mfcc: 13,
+            minimum_frequency: 20.0,
+            maximum_frequency: 8000.0,
+            rolloff_ratio: 0.85,
+            tuning_frequency: 440.0,
+        }
+    }
+}
+
+/// Features of a single frame, or the mean over many
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SpectralFeatures {
+    /// Hz
+    pub centroid: f32,
+    /// Hz
+    pub spread: f32,
+    /// Between 0 (tonal) and 1 (noise)
+    pub flatness: f32,
+    /// Hz
+    pub rolloff: f32,
+    pub flux: f32,
+    pub mfcc: Vec<f32>,
+    /// Pitch class energies, C first, loudest is 1
+    pub chroma: [f32; NUM_PITCH_CLASSES],
+}
+
+impl SpectralFeatures {
+    fn with_num_mfcc(num_mfcc: usize) -> Self {
+        Self {
+            mfcc: vec![0.0; num_mfcc],
+            ..Self::default()
+        }
+    }
+
+    /// Mean of every feature over `frames`. Returns `None` if `frames` is empty.
+    pub fn mean(frames: &[SpectralFeatures]) -> Option<SpectralFeatures> {
+        let first = frames.first()?;
+        let mut result = SpectralFeatures::with_num_mfcc(first.mfcc.len());
+        for frame in frames {
+            result.centroid += frame.centroid;
+            result.spread += frame.spread;
+            result.flatness += frame.flatness;
+            result.rolloff += frame.rolloff;
+            result.flux += frame.flux;
+            for (total, value) in result.mfcc.iter_mut().zip(&frame.mfcc) {
+                *total += value;
+            }
+            for (total, value) in result.chroma.iter_mut().zip(&frame.chroma) {
+                *total += value;
+            }
+        }
+
+        let len = frames.len() as f32;
+        result.centroid /= len;
+        result.spread /= len;
+        result.flatness /= len;
+        result.rolloff /= len;
+        result.flux /= len;
+        result.mfcc.iter_mut().for_each(|value| *value /= len);
+        result.chroma.iter_mut().for_each(|value| *value /= len);
+        Some(result)
+    }
+
+    /// Euclidean distance between MFCC vectors, ignoring the 0th coefficient (overall level).
+    ///
+    /// A simple timbral similarity measure; 0 means identical.
+    pub fn mfcc_distance(&self, other: &SpectralFeatures) -> f32 {
+        self.mfcc
+            .iter()
+            .zip(&other.mfcc)
+            .skip(1)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+/// Computes [`SpectralFeatures`] from FFT frames. Real-time safe after construction.
+pub struct SpectralFeatureExtractor {
+    bin_width: f32,
+    rolloff_ratio: f32,
+    magnitudes: Vec<f32>,
+    previous_magnitudes: Vec<f32>,
+    power: Vec<f32>,
+    mel_filterbank: MelFilterbank,
+    mel_energies: Vec<f32>,
+    log_mel_energies: Vec<f32>,
+    dct: Dct,
+    chroma_filterbank: ChromaFilterbank,
+    features: SpectralFeatures,
+}
+
+impl SpectralFeatureExtractor {
+    pub fn new(sample_rate: f32, options: &SpectralFeaturesOptions) -> Self {
+        let num_bins = options.fft_size / 2 + 1;
+        let maximum_frequency = options.maximum_frequency.min(sample_rate / 2.0);
+
+        Self {
+            bin_width: sample_rate / options.fft_size as f32,
+            rolloff_ratio: options.rolloff_ratio,
+            magnitudes: vec![0.0; num_bins],
+            previous_magnitudes: vec![0.0; num_bins],
+            power: vec![0.0; num_bins],
+            mel_filterbank: MelFilterbank::new(
+                options.fft_size,
+                sample_rate,
+                options.num_mel_bands,
+                options.minimum_frequency,
+                maximum_frequency,
+            ),
+            mel_energies: vec![0.0; options.num_mel_bands],
+            log_mel_energies: vec![0.0; options.num_mel_bands],
+            dct: Dct::new(options.num_mel_bands, options.num_mfcc),
+            chroma_filterbank: ChromaFilterbank::new(
+                options.fft_size,
+                sample_rate,
+                options.tuning_frequency,
+                options.minimum_frequency,
+                maximum_frequency,
+            ),
+            features: SpectralFeatures::with_num_mfcc(options.num_mfcc),
+        }
+    }
+
+    /// Features of the last frame
+    pub fn features(&self) -> &SpectralFeatures {
+        &self.features
+    }
+
+    /// Forget the previous frame, used for flux
+    pub fn reset(&mut self) {
+        self.previous_magnitudes.fill(0.0);
+    }
+
+    /// Compute features from the bins of a forward FFT. Only the positive frequencies are read.
+    pub fn process_frame(&mut self, frame: &[Complex<f32>]) -> &SpectralFeatures {
+        std::mem::swap(&mut self.magnitudes, &mut self.previous_magnitudes);
+        // Normalize so a full-scale sine with a Hann window peaks at ~0.5
+        let scale = 2.0 / frame.len() as f32;
+        for ((magnitude, power), complex) in self
+            .magnitudes
+            .iter_mut()
+            .zip(self.power.iter_mut())
+            .zip(frame)
+        {
+            *magnitude = complex.norm() * scale;
+            *power = *magnitude * *magnitude;
+        }
+
+        let features = &mut self.features;
+        features.centroid = descriptors::spectral_centroid(&self.magnitudes, self.bin_width);
+        features.spread =
+            descriptors::spectral_spread(&self.magnitudes, self.bin_width, features.centroid);
+        features.flatness = descriptors::spectral_flatness(&self.magnitudes);
+        features.rolloff =
+            descriptors::spectral_rolloff(&self.magnitudes, self.bin_width, self.rolloff_ratio);
+        features.flux = descriptors::spectral_flux(&self.previous_magnitudes, &self.magnitudes);
+
+        self.mel_filterbank
+            .apply(&self.power, &mut self.mel_energies);
+        mel::mfcc(
+            &self.dct,
+            &self.mel_energies,
+            &mut self.log_mel_energies,
+            &mut features.mfcc,
+        );
+        self.chroma_filterbank
+            .apply(&self.power, &mut features.chroma);
+
+        &self.features
+    }
+}
+
+/// Handle for [`SpectralFeaturesProcessor`], use this to read the last frame's features from any
+/// thread.
+pub struct SpectralFeaturesHandle {
+    centroid: AtomicF32,
+    spread: AtomicF32,
+    flatness: AtomicF32,
+    rolloff: AtomicF32,
+    flux: AtomicF32,
+    mfcc: Vec<AtomicF32>,
+    chroma: [AtomicF32; NUM_PITCH_CLASSES],
+}
+
+impl SpectralFeaturesHandle {
+    fn new(num_mfcc: usize) -> Self {
+        Self {
+            centroid: AtomicF32::new(0.0),
+            spread: AtomicF32::new(0.0),
+            flatness: AtomicF32::new(0.0),
+            rolloff: AtomicF32::new(0.0),
+            flux: AtomicF32::new(0.0),
+            mfcc: (0..num_mfcc).map(|_| AtomicF32::new(0.0)).collect(),
+            chroma: std::array::from_fn(|_| AtomicF32::new(0.0)),
+        }
+    }
+
+    pub fn centroid(&self) -> f32 {
+        self.centroid.get()
+    }
+
+    pub fn spread(&self) -> f32 {
+        self.spread.get()
+    }
+
+    pub fn flatness(&self) -> f32 {
+        self.flatness.get()
+    }
+
+    pub fn rolloff(&self) -> f32 {
+        self.rolloff.get()
+    }
+
+    pub fn flux(&self) -> f32 {
+        self.flux.get()
+    }
+
+    /// Allocates; do not call from the audio-thread
+    pub fn mfcc(&self) -> Vec<f32> {
+        self.mfcc.iter().map(|value| value.get()).collect()
+    }
+
+    pub fn chroma(&self) -> [f32; NUM_PITCH_CLASSES] {
+        std::array::from_fn(|i| self.chroma[i].get())
+    }
+
+    /// Snapshot of the last frame. Values are read one by one, so they may span two frames.
+    pub fn features(&self) -> SpectralFeatures {
+        SpectralFeatures {
+            centroid: self.centroid(),
+            spread: self.spread(),
+            flatness: self.flatness(),
+            rolloff: self.rolloff(),
+            flux: self.flux(),
+            mfcc: self.mfcc(),
+            chroma: self.chroma(),
+        }
+    }
+
+    fn publish(&self, features: &SpectralFeatures) {
+        self.centroid.set(features.centroid);
+        self.spread.set(features.spread);
+        self.flatness.set(features.flatness);
+        self.rolloff.set(features.rolloff);
+        self.flux.set(features.flux);
+        for (target, value) in self.mfcc.iter().zip(&features.mfcc) {
+            target.set(*value);
+        }
+        for (target, value) in self.chroma.iter().zip(&features.chroma) {
+            target.set(*value);
+        }
+    }
+}
+
+/// Real-time spectral feature extraction. Input channels are summed into mono; the buffer is
+/// passed through unchanged.
+///
+/// Features are published onto the handle on every FFT frame (every `fft_size * (1 -
+/// overlap_ratio)` samples).
+pub struct SpectralFeaturesProcessor {
+    options: SpectralFeaturesOptions,
+    fft: FftProcessor,
+    extractor: SpectralFeatureExtractor,
+    handle: Shared<SpectralFeaturesHandle>,
+}
+
+impl Default for SpectralFeaturesProcessor {
+    fn default() -> Self {
+        Self::new(SpectralFeaturesOptions::default())
+    }
+}
+
+impl SpectralFeaturesProcessor {
+    pub fn new(options: SpectralFeaturesOptions) -> Self {
+        Self {
+            fft: make_fft(&options),
+            extractor: SpectralFeatureExtractor::new(44100.0, &options),
+            handle: make_shared(SpectralFeaturesHandle::new(options.num_mfcc)),
+            options,
+        }
+    }
+
+    /// Get a reference to the `basedrop::Shared` handle of this processor
+    pub fn handle(&self) -> &Shared<SpectralFeaturesHandle> {
+        &self.handle
+    }
+}
+
+impl AudioProcessor for SpectralFeaturesProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.fft = make_fft(&self.options);
+        self.extractor =
+            SpectralFeatureExtractor::new(context.settings.sample_rate(), &self.options);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        for sample_index in 0..data.num_samples() {
+            let mut sample = 0.0;
+            for channel in 0..data.num_channels() {
+                sample += *data.get(channel, sample_index);
+            }
+
+            self.fft.m_process(context, sample);
+            if self.fft.has_changed() {
+                let features = self.extractor.process_frame(self.fft.buffer());
+                self.handle.publish(features);
+            }
+        }
+    }
+}
+
+fn make_fft(options: &SpectralFeaturesOptions) -> FftProcessor {
+    FftProcessor::new(FftProcessorOptions {
+        size: options.fft_size,
+        direction: FftDirection::Forward,
+        overlap_ratio: options.overlap_ratio,
+        window_function: options.window_function,
+    })
+}
+
+/// Extract features for every FFT frame of a buffer. Channels are summed into mono. Not real-time
+/// safe.
+pub fn extract_spectral_features(
+    sample_rate: f32,
+    options: SpectralFeaturesOptions,
+    data: &AudioBuffer<f32>,
+) -> Vec<SpectralFeatures> {
+    let mut context = AudioContext::default();
+    context.settings.sample_rate = sample_rate;
+    let mut fft = make_fft(&options);
+    let mut extractor = SpectralFeatureExtractor::new(sample_rate, &options);
+
+    let mut frames = vec![];
+    for sample_index in 0..data.num_samples() {
+        let sample: f32 = (0..data.num_channels())
+            .map(|channel| *data.get(channel, sample_index))
+            .sum();
+        fft.m_process(&mut context, sample);
+        if fft.has_changed() {
+            frames.push(extractor.process_frame(fft.buffer()).clone());
+        }
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
+    use std::time::Duration;
+
+    use audio_processor_testing_helpers::sine_buffer;
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::*;
+
+    /// Deterministic white-ish noise
+    fn noise_buffer(length: usize) -> Vec<f32> {
+        let mut state: u32 = 0x12345678;
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn mean_features(signal: &[f32]) -> SpectralFeatures {
+        let buffer = AudioBuffer::from_interleaved(1, signal);
+        let frames =
+            extract_spectral_features(44100.0, SpectralFeaturesOptions::default(), &buffer);
+        // Skip the first frames, which include the initial silence
+        SpectralFeatures::mean(&frames[4..]).unwrap()
+    }
+
+    #[test]
+    fn test_sine_features() {
+        let signal = sine_buffer(44100.0, 1000.0, Duration::from_millis(500));
+        let features = mean_features(&signal);
+        assert!((features.centroid - 1000.0).abs() < 50.0, "{:?}", features);
+        assert!((features.rolloff - 1000.0).abs() < 50.0, "{:?}", features);
+        assert!(features.flatness < 0.01, "{:?}", features);
+        // ~1kHz is between B & C, 1046Hz would be C6
+        let loudest = features
+            .chroma
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(pitch_class, _)| pitch_class)
+            .unwrap();
+        assert!(loudest == 11 || loudest == 0, "{:?}", features.chroma);
+    }
+
+    #[test]
+    fn test_noise_is_flatter_and_brighter_than_sine() {
+        let sine = mean_features(&sine_buffer(44100.0, 440.0, Duration::from_millis(500)));
+        let noise = mean_features(&noise_buffer(22050));
+        assert!(noise.flatness > 0.3, "{:?}", noise);
+        assert!(noise.flatness > sine.flatness * 10.0);
+        assert!(noise.centroid > sine.centroid * 10.0);
+        assert!(noise.spread > sine.spread);
+    }
+
+    #[test]
+    fn test_mfcc_distance() {
+        let sine1 = mean_features(&sine_buffer(44100.0, 440.0, Duration::from_millis(500)));
+        let sine2 = mean_features(&sine_buffer(44100.0, 445.0, Duration::from_millis(500)));
+        let noise = mean_features(&noise_buffer(22050));
+        assert_eq!(sine1.mfcc.len(), 13);
+        assert_eq!(sine1.mfcc_distance(&sine1), 0.0);
+        assert!(sine1.mfcc_distance(&sine2) < sine1.mfcc_distance(&noise));
+    }
+
+    #[test]
+    fn test_flux_is_zero_for_a_steady_signal_and_rises_on_change() {
+        let mut signal = sine_buffer(44100.0, 440.0, Duration::from_millis(500));
+        signal.extend(noise_buffer(22050));
+        let buffer = AudioBuffer::from_interleaved(1, &signal);
+        let frames =
+            extract_spectral_features(44100.0, SpectralFeaturesOptions::default(), &buffer);
+
+        let steady_flux = frames[8].flux;
+        let change_flux = frames.iter().map(|frame| frame.flux).fold(0.0, f32::max);
+        assert!(steady_flux < 0.01, "{}", steady_flux);
+        assert!(change_flux > steady_flux * 100.0);
+    }
+
+    #[test]
+    fn test_mean_of_nothing_is_none() {
+        assert_eq!(SpectralFeatures::mean(&[]), None);
+    }
+
+    #[test]
+    fn test_processor_publishes_features() {
+        let mut processor = SpectralFeaturesProcessor::default();
+        let handle = processor.handle().clone();
+        let mut context = AudioContext::from(AudioProcessorSettings::new(44100.0, 1, 1, 512));
+        processor.prepare(&mut context);
+
+        let signal = sine_buffer(44100.0, 1000.0, Duration::from_millis(200));
+        for block in signal.chunks(512) {
+            let mut buffer = AudioBuffer::from_interleaved(1, block);
+            processor.process(&mut context, &mut buffer);
+            assert_eq!(buffer.channel(0), block);
+        }
+
+        assert!((handle.centroid() - 1000.0).abs() < 50.0);
+        assert_eq!(handle.mfcc().len(), 13);
+        assert_eq!(handle.features().centroid, handle.centroid());
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        assert_processor_realtime_safe(
+            SpectralFeaturesProcessor::default(),
+            Default::default(),
+            20,
+        );
+    }
+}
@@ -0,0 +1,573 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Real-time onset detection.
+//!
+//! Unlike [`crate::transient_detection::stft::find_transients`], which runs offline over a whole
+//! buffer, [`OnsetDetectorProcessor`] consumes audio block by block and emits [`OnsetEvent`]s
+//! through a lock-free queue on its [`OnsetDetectorHandle`].
+//!
+//! The processor performs windowed & overlapped FFTs (see [`crate::fft_processor`]) and reduces
+//! each frame into a single "onset detection function" value. Three functions are available:
+//!
+//! * [`OnsetDetectionFunction::SpectralFlux`] - Sum of positive magnitude differences between
+//!   consecutive frames
+//! * [`OnsetDetectionFunction::HighFrequencyContent`] - Magnitudes weighted by their bin index,
+//!   good for percussive material
+//! * [`OnsetDetectionFunction::ComplexDomain`] - Distance between each bin and its prediction from
+//!   the previous two frames (magnitude & phase), good for soft/tonal onsets
+//!
+//! Peaks on this function are picked against an adaptive threshold (the median of the last
+//! `threshold_window` values times `threshold_multiplier`, plus `threshold_offset`).
+//!
+//! Reference:
+//! * <https://www.eecs.qmul.ac.uk/~simond/pub/2005/ieee_tsap05.pdf>
+//!
+//! ## Usage
+//! ```
+//! use audio_processor_analysis::transient_detection::onset_detector::OnsetDetectorProcessor;
+//! use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
+//!
+//! let mut onset_detector = OnsetDetectorProcessor::default();
+//! let handle = onset_detector.handle().clone(); // can send to another thread
+//!
+//! let mut context = AudioContext::from(AudioProcessorSettings::default());
+//! onset_detector.prepare(&mut context);
+//! let mut buffer = AudioBuffer::empty();
+//! buffer.resize(2, 512);
+//! onset_detector.process(&mut context, &mut buffer);
+//!
+//! while let Some(onset) = handle.pop() {
+//!     println!("Onset at {} strength={}", onset.position_samples, onset.strength);
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use atomic_queue::Queue;
+use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_traits::simple_processor::MonoAudioProcessor;
+use audio_processor_traits::{AtomicF32, AudioBuffer, AudioContext, AudioProcessor};
+use rustfft::num_complex::Complex;
+
+use crate::fft_processor::{FftDirection, FftProcessor, FftProcessorOptions};
+use crate::window_functions::WindowFunctionType;
+
+/// The function used to reduce each FFT frame into a single onset strength value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnsetDetectionFunction {
+    SpectralFlux,
+    HighFrequencyContent,
+    ComplexDomain,
+}
+
+#[derive(Debug, Clone)]
+pub struct OnsetDetectorOptions {
+    /// Size of the FFT windows, defaults to 1024
+    pub fft_size: usize,
+    /// If 0.75 is provided, 3/4 of the windows will overlap. Defaults to 3/4
+    pub fft_overlap_ratio: f32,
+    /// Defaults to [`OnsetDetectionFunction::SpectralFlux`]
+    pub detection_function: OnsetDetectionFunction,
+    /// How many past detection function values are considered for the adaptive threshold.
+    ///
+    /// Defaults to 16 frames, roughly 90ms at 44.1kHz with the default FFT size
+    pub threshold_window: usize,
+    /// Multiplier over the median of the threshold window. Higher nºs decrease sensitivity.
+    ///
+    /// Defaults to 1.5
+    pub threshold_multiplier: f32,
+    /// Constant added to the adaptive threshold, so silence/noise floor doesn't trigger onsets.
+    ///
+    /// Defaults to 0.01
+    pub threshold_offset: f32,
+    /// Onsets closer than this to the previous onset are dropped. Defaults to 50ms
+    pub minimum_interval: Duration,
+    /// Capacity of the event queue. Events are dropped if the consumer doesn't keep up.
+    ///
+    /// Defaults to 100
+    pub queue_capacity: usize,
+}
+
+impl Default for OnsetDetectorOptions {
+    fn default() -> Self {
+        Self {
+            fft_size: 1024,
+            fft_overlap_ratio: 0.75,
+            detection_function: OnsetDetectionFunction::SpectralFlux,
+            threshold_window: 16,
+            threshold_multiplier: 1.5,
+            threshold_offset: 0.01,
+            minimum_interval: Duration::from_millis(50),
+            queue_capacity: 100,
+        }
+    }
+}
+
+/// An onset detected by [`OnsetDetectorProcessor`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnsetEvent {
+    /// Position of the onset, in samples since the processor was prepared
+    pub position_samples: u64,
+    /// Value of the detection function at this onset
+    pub strength: f32,
+}
+
+/// Handle for [`OnsetDetectorProcessor`], use this to consume onsets from any thread.
+pub struct OnsetDetectorHandle {
+    queue: Queue<OnsetEvent>,
+    detection_function_value: AtomicF32,
+    position_samples: AtomicU64,
+    dropped_events: AtomicUsize,
+}
+
+impl OnsetDetectorHandle {
+    fn new(queue_capacity: usize) -> Self {
+        Self {
+            queue: Queue::new(queue_capacity),
+            detection_function_value: AtomicF32::new(0.0),
+            position_samples: AtomicU64::new(0),
+            dropped_events: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pop the oldest onset that hasn't been consumed yet
+    pub fn pop(&self) -> Option<OnsetEvent> {
+        self.queue.pop()
+    }
+
+    /// The last value of the onset detection function
+    pub fn detection_function_value(&self) -> f32 {
+        self.detection_function_value.get()
+    }
+
+    /// How many samples the processor has consumed since it was prepared
+    pub fn position_samples(&self) -> u64 {
+        self.position_samples.load(Ordering::Relaxed)
+    }
+
+    /// Number of onsets dropped because the queue was full
+    pub fn dropped_events(&self) -> usize {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+}
+
+/// Streaming onset detector. Real-time safe after `prepare`.
+///
+/// Input channels are summed into mono; the buffer is passed through unchanged.
+///
+/// Onsets are reported with 1 FFT hop of latency, since a peak is only known once the following
+/// frame is lower. The position reported is the center of the window the peak was found in.
+pub struct OnsetDetectorProcessor {
+    options: OnsetDetectorOptions,
+    handle: Shared<OnsetDetectorHandle>,
+    fft: FftProcessor,
+    state: DetectionFunctionState,
+    threshold: AdaptiveThreshold,
+    position_samples: u64,
+    minimum_interval_samples: u64,
+    last_onset_position: Option<u64>,
+    previous_values: [f32; 2],
+}
+
+impl Default for OnsetDetectorProcessor {
+    fn default() -> Self {
+        Self::new(OnsetDetectorOptions::default())
+    }
+}
+
+impl OnsetDetectorProcessor {
+    /// Create a new processor with options. This will allocate the FFT buffers and the event queue.
+    pub fn new(options: OnsetDetectorOptions) -> Self {
+        let fft = FftProcessor::new(FftProcessorOptions {
+            size: options.fft_size,
+            direction: FftDirection::Forward,
+            overlap_ratio: options.fft_overlap_ratio,
+            window_function: WindowFunctionType::Hann,
+        });
+        let handle = make_shared(OnsetDetectorHandle::new(options.queue_capacity));
+        let state = DetectionFunctionState::new(options.fft_size / 2);
+        let threshold = AdaptiveThreshold::new(options.threshold_window.max(1));
+
+        Self {
+            options,
+            handle,
+            fft,
+            state,
+            threshold,
+            position_samples: 0,
+            minimum_interval_samples: 0,
+            last_onset_position: None,
+            previous_values: [0.0; 2],
+        }
+    }
+
+    /// Get a reference to the `basedrop::Shared` handle of this processor
+    pub fn handle(&self) -> &Shared<OnsetDetectorHandle> {
+        &self.handle
+    }
+
+    /// Latency, in samples, between an onset happening and it being reported
+    pub fn latency_samples(&self) -> usize {
+        self.options.fft_size / 2 + self.fft.step_len()
+    }
+
+    fn on_frame(&mut self) {
+        let value = self
+            .state
+            .accept_frame(self.options.detection_function, self.fft.buffer());
+        self.handle.detection_function_value.set(value);
+
+        // The previous frame is a peak if it's higher than both its neighbours and higher than
+        // the threshold of frames preceding it
+        let [before_candidate, candidate] = self.previous_values;
+        let threshold = self.threshold.value() * self.options.threshold_multiplier
+            + self.options.threshold_offset;
+        let is_peak = candidate > before_candidate && candidate >= value && candidate > threshold;
+
+        if is_peak {
+            let hop = self.fft.step_len() as u64;
+            let candidate_center = self
+                .position_samples
+                .saturating_sub(hop + self.options.fft_size as u64 / 2);
+            self.emit_onset(candidate_center, candidate);
+        }
+
+        self.threshold.push(candidate);
+        self.previous_values = [candidate, value];
+    }
+
+    fn emit_onset(&mut self, position_samples: u64, strength: f32) {
+        if let Some(last_onset_position) = self.last_onset_position {
+            if position_samples < last_onset_position + self.minimum_interval_samples {
+                return;
+            }
+        }
+
+        self.last_onset_position = Some(position_samples);
+        let event = OnsetEvent {
+            position_samples,
+            strength,
+        };
+        if !self.handle.queue.push(event) {
+            self.handle.dropped_events.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl AudioProcessor for OnsetDetectorProcessor {
+    type SampleType = f32;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.minimum_interval_samples =
+            (context.settings.sample_rate() * self.options.minimum_interval.as_secs_f32()) as u64;
+        self.position_samples = 0;
+        self.last_onset_position = None;
+        self.previous_values = [0.0; 2];
+        self.state.reset();
+        self.threshold.reset();
+        self.handle.position_samples.store(0, Ordering::Relaxed);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        for sample_index in 0..data.num_samples() {
+            let mut sample = 0.0;
+            for channel in 0..data.num_channels() {
+                sample += *data.get(channel, sample_index);
+            }
+
+            self.fft.m_process(context, sample);
+            self.position_samples += 1;
+
+            if self.fft.has_changed() {
+                self.on_frame();
+            }
+        }
+
+        self.handle
+            .position_samples
+            .store(self.position_samples, Ordering::Relaxed);
+    }
+}
+
+/// Run the onset detector over a buffer, collecting the onsets found. Not real-time safe.
+pub fn detect_onsets(
+    context: &mut AudioContext,
+    options: OnsetDetectorOptions,
+    data: &mut AudioBuffer<f32>,
+) -> Vec<OnsetEvent> {
+    let mut processor = OnsetDetectorProcessor::new(options);
+    processor.prepare(context);
+
+    let mut onsets = vec![];
+    let block_size = context.settings.block_size().max(1);
+    let mut block = AudioBuffer::empty();
+    let mut cursor = 0;
+    while cursor < data.num_samples() {
+        let block_len = block_size.min(data.num_samples() - cursor);
+        block.resize(data.num_channels(), block_len);
+        for channel in 0..data.num_channels() {
+            block
+                .channel_mut(channel)
+                .copy_from_slice(&data.channel(channel)[cursor..cursor + block_len]);
+        }
+
+        processor.process(context, &mut block);
+        while let Some(onset) = processor.handle.pop() {
+            onsets.push(onset);
+        }
+        cursor += block_len;
+    }
+
+    onsets
+}
+
+/// Previous frame state required by the detection functions; pre-allocated so the processor
+/// doesn't allocate after construction.
+struct DetectionFunctionState {
+    previous_magnitudes: Vec<f32>,
+    previous_phases: Vec<f32>,
+    previous_previous_phases: Vec<f32>,
+}
+
+impl DetectionFunctionState {
+    fn new(num_bins: usize) -> Self {
+        Self {
+            previous_magnitudes: vec![0.0; num_bins],
+            previous_phases: vec![0.0; num_bins],
+            previous_previous_phases: vec![0.0; num_bins],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.previous_magnitudes.fill(0.0);
+        self.previous_phases.fill(0.0);
+        self.previous_previous_phases.fill(0.0);
+    }
+
+    fn accept_frame(&mut self, function: OnsetDetectionFunction, frame: &[Complex<f32>]) -> f32 {
+        let num_bins = self.previous_magnitudes.len();
+        // Normalize so a full-scale sine with a Hann window peaks at ~0.5
+        let scale = 2.0 / frame.len() as f32;
+        let mut value = 0.0;
+
+        for (bin, complex) in frame.iter().take(num_bins).enumerate() {
+            let magnitude = complex.norm() * scale;
+            let phase = complex.arg();
+
+            value += match function {
+                OnsetDetectionFunction::SpectralFlux => {
+                    (magnitude - self.previous_magnitudes[bin]).max(0.0)
+                }
+                OnsetDetectionFunction::HighFrequencyContent => {
+                    magnitude * magnitude * bin as f32 / num_bins as f32
+                }
+                OnsetDetectionFunction::ComplexDomain => {
+                    let predicted_phase =
+                        2.0 * self.previous_phases[bin] - self.previous_previous_phases[bin];
+                    let predicted =
+                        Complex::from_polar(self.previous_magnitudes[bin], predicted_phase);
+                    let current = Complex::from_polar(magnitude, phase);
+                    // Only consider rising energy; decays aren't onsets
+                    if magnitude >= self.previous_magnitudes[bin] {
+                        (current - predicted).norm()
+                    } else {
+                        0.0
+                    }
+                }
+            };
+
+            self.previous_magnitudes[bin] = magnitude;
+            self.previous_previous_phases[bin] = self.previous_phases[bin];
+            self.previous_phases[bin] = phase;
+        }
+
+        value
+    }
+}
+
+/// Median of the last N detection function values, computed with pre-allocated buffers.
+struct AdaptiveThreshold {
+    values: Vec<f32>,
+    scratch: Vec<f32>,
+    cursor: usize,
+    len: usize,
+}
+
+impl AdaptiveThreshold {
+    fn new(size: usize) -> Self {
+        Self {
+            values: vec![0.0; size],
+            scratch: vec![0.0; size],
+            cursor: 0,
+            len: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cursor = 0;
+        self.len = 0;
+    }
+
+    fn push(&mut self, value: f32) {
+        self.values[self.cursor] = value;
+        self.cursor = (self.cursor + 1) % self.values.len();
+        self.len = (self.len + 1).min(self.values.len());
+    }
+
+    fn value(&mut self) -> f32 {
+        if self.len == 0 {
+            return 0.0;
+        }
+
+        let scratch = &mut self.scratch[0..self.len];
+        scratch.copy_from_slice(&self.values[0..self.len]);
+        scratch.sort_unstable_by(|f1, f2| f1.partial_cmp(f2).unwrap_or(std::cmp::Ordering::Equal));
+        scratch[self.len / 2]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::assert_f_eq;
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::*;
+
+    /// Silence with short bursts of noise-like content every `interval` samples
+    fn make_clicks(length: usize, interval: usize) -> AudioBuffer<f32> {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, length);
+        let mut seed: u32 = 12345;
+        for burst_start in (interval..length).step_by(interval) {
+            for i in burst_start..(burst_start + 2000).min(length) {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let noise = (seed >> 16) as f32 / 32768.0 - 1.0;
+                let decay = 1.0 - (i - burst_start) as f32 / 2000.0;
+                buffer.set(0, i, noise * decay);
+            }
+        }
+        buffer
+    }
+
+    fn assert_onsets_match(onsets: &[OnsetEvent], interval: usize, count: usize) {
+        assert_eq!(onsets.len(), count, "{:?}", onsets);
+        for (i, onset) in onsets.iter().enumerate() {
+            let expected = ((i + 1) * interval) as i64;
+            let error = (onset.position_samples as i64 - expected).abs();
+            assert!(error < 1024, "Onset {:?} too far from {}", onset, expected);
+        }
+    }
+
+    #[test]
+    fn test_silence_has_no_onsets() {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 44100);
+        let onsets = detect_onsets(&mut context, Default::default(), &mut buffer);
+        assert!(onsets.is_empty());
+    }
+
+    #[test]
+    fn test_spectral_flux_detects_clicks() {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        let mut buffer = make_clicks(44100 * 2, 11025);
+        let onsets = detect_onsets(&mut context, Default::default(), &mut buffer);
+        assert_onsets_match(&onsets, 11025, 7);
+    }
+
+    #[test]
+    fn test_high_frequency_content_detects_clicks() {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        let mut buffer = make_clicks(44100 * 2, 11025);
+        let options = OnsetDetectorOptions {
+            detection_function: OnsetDetectionFunction::HighFrequencyContent,
+            ..Default::default()
+        };
+        let onsets = detect_onsets(&mut context, options, &mut buffer);
+        assert_onsets_match(&onsets, 11025, 7);
+    }
+
+    #[test]
+    fn test_complex_domain_detects_clicks() {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        let mut buffer = make_clicks(44100 * 2, 11025);
+        let options = OnsetDetectorOptions {
+            detection_function: OnsetDetectionFunction::ComplexDomain,
+            ..Default::default()
+        };
+        let onsets = detect_onsets(&mut context, options, &mut buffer);
+        assert_onsets_match(&onsets, 11025, 7);
+    }
+
+    #[test]
+    fn test_minimum_interval_drops_close_onsets() {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        let mut buffer = make_clicks(44100 * 2, 11025);
+        let options = OnsetDetectorOptions {
+            minimum_interval: Duration::from_millis(400),
+            ..Default::default()
+        };
+        let onsets = detect_onsets(&mut context, options, &mut buffer);
+        assert_eq!(onsets.len(), 4);
+    }
+
+    #[test]
+    fn test_full_queue_counts_dropped_events() {
+        let mut context = AudioContext::from(AudioProcessorSettings::default());
+        let mut processor = OnsetDetectorProcessor::new(OnsetDetectorOptions {
+            queue_capacity: 2,
+            ..Default::default()
+        });
+        processor.prepare(&mut context);
+        let mut buffer = make_clicks(44100 * 2, 11025);
+        processor.process(&mut context, &mut buffer);
+
+        let handle = processor.handle();
+        assert_eq!(handle.position_samples(), 44100 * 2);
+        assert_eq!(handle.dropped_events(), 5);
+        assert!(handle.pop().is_some());
+        assert!(handle.pop().is_some());
+        assert!(handle.pop().is_none());
+    }
+
+    #[test]
+    fn test_adaptive_threshold_is_median() {
+        let mut threshold = AdaptiveThreshold::new(3);
+        assert_f_eq!(threshold.value(), 0.0);
+        threshold.push(3.0);
+        threshold.push(1.0);
+        threshold.push(2.0);
+        assert_f_eq!(threshold.value(), 2.0);
+        threshold.push(10.0);
+        threshold.push(10.0);
+        assert_f_eq!(threshold.value(), 10.0);
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        assert_processor_realtime_safe(OnsetDetectorProcessor::default(), Default::default(), 20);
+    }
+}
@@ -70,3 +70,78 @@ pub fn build_markers(
         .map(|position_samples| AudioFileMarker { position_samples })
         .collect()
 }
+
+/// Write markers as `cue ` points onto an existing WAV file, replacing its markers. Loop points
+/// are kept.
+#[cfg(feature = "audio-processor-file")]
+pub fn write_markers(
+    path: &str,
+    markers: &[AudioFileMarker],
+) -> Result<(), audio_processor_file::file_io::AudioFileError> {
+    use audio_processor_file::file_io;
+
+    let metadata = file_io::read_audio_file_metadata(path)?;
+    let markers: Vec<file_io::AudioFileMarker> = markers
+        .iter()
+        .enumerate()
+        .map(|(index, marker)| file_io::AudioFileMarker {
+            id: index as u32 + 1,
+            position_samples: marker.position_samples as u64,
+            label: None,
+        })
+        .collect();
+    file_io::write_wav_markers(path, &markers, &metadata.loops)
+}
+
+/// Read markers from an audio file, sorted by position
+#[cfg(feature = "audio-processor-file")]
+pub fn read_markers(
+    path: &str,
+) -> Result<Vec<AudioFileMarker>, audio_processor_file::file_io::AudioFileError> {
+    let metadata = audio_processor_file::file_io::read_audio_file_metadata(path)?;
+    let mut markers: Vec<AudioFileMarker> = metadata
+        .markers
+        .iter()
+        .map(|marker| AudioFileMarker {
+            position_samples: marker.position_samples as usize,
+        })
+        .collect();
+    markers.sort_by_key(|marker| marker.position_samples);
+    Ok(markers)
+}
+
+#[cfg(all(test, feature = "audio-processor-file"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_markers_round_trip() {
+        let tempdir = tempdir::TempDir::new("markers").unwrap();
+        let path = tempdir.path().join("markers.wav");
+        let path = path.to_str().unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for _ in 0..1000 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let markers = vec![
+            AudioFileMarker {
+                position_samples: 10,
+            },
+            AudioFileMarker {
+                position_samples: 500,
+            },
+        ];
+        write_markers(path, &markers).unwrap();
+        let result = read_markers(path).unwrap();
+        let positions: Vec<usize> = result.iter().map(|m| m.position_samples).collect();
+        assert_eq!(positions, vec![10, 500]);
+    }
+}
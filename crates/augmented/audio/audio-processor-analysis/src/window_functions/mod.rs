@@ -26,6 +26,7 @@ use audio_processor_traits::Float;
 
 pub type WindowFunction<F> = fn(n: F, size: F) -> F;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WindowFunctionType {
     Hann,
     BlackmanHarris,
@@ -37,6 +37,8 @@ use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, Float};
 pub use generic_handle::BitCrusherHandleRef;
 
 mod generic_handle;
+#[cfg(test)]
+mod test_allocator;
 
 pub type BitCrusherHandle = BitCrusherHandleImpl<f32>;
 
@@ -179,6 +181,7 @@ where
 mod test {
     use std::time::Duration;
 
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
     use audio_processor_testing_helpers::sine_buffer;
 
     use audio_processor_traits::AudioProcessorSettings;
@@ -190,6 +193,13 @@ mod test {
         let _processor = BitCrusherProcessor::default();
     }
 
+    #[test]
+    fn test_process_is_realtime_safe() {
+        let processor = BitCrusherProcessor::default();
+        processor.handle().set_bit_rate(11025.0);
+        assert_processor_realtime_safe(processor, Default::default(), 10);
+    }
+
     #[test]
     fn test_step_size_is_1_on_passthrough() {
         let settings = AudioProcessorSettings::default();
@@ -0,0 +1,86 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+use audio_garbage_collector::Shared;
+use audio_processor_traits::parameters::{
+    AudioProcessorHandle, FloatType, ParameterSpec, ParameterType, ParameterValue,
+};
+
+use crate::handle::CompressorHandle;
+use crate::FloatT;
+
+pub struct GenericHandle(pub Shared<CompressorHandle>);
+
+impl AudioProcessorHandle for GenericHandle {
+    fn name(&self) -> String {
+        "Compressor".to_string()
+    }
+
+    fn parameter_count(&self) -> usize {
+        6
+    }
+
+    fn get_parameter_spec(&self, index: usize) -> ParameterSpec {
+        let specs: [(&str, (f32, f32)); 6] = [
+            ("Threshold", (-60.0, 0.0)),
+            ("Ratio", (1.0, 20.0)),
+            ("Attack", (0.1, 100.0)),
+            ("Release", (1.0, 1000.0)),
+            ("Make-up gain", (0.0, 24.0)),
+            ("Knee", (0.0, 24.0)),
+        ];
+        let (name, range) = specs[index];
+        ParameterSpec::new(
+            name.into(),
+            ParameterType::Float(FloatType { range, step: None }),
+        )
+    }
+
+    // `FloatT` is `f64` when `augmented_audio_volume` is built with its `f64` feature
+    #[allow(clippy::unnecessary_cast)]
+    fn get_parameter(&self, index: usize) -> Option<ParameterValue> {
+        let value = match index {
+            0 => self.0.threshold(),
+            1 => self.0.ratio(),
+            2 => self.0.attack_ms(),
+            3 => self.0.release_ms(),
+            4 => self.0.make_up_gain(),
+            5 => self.0.knee_width(),
+            _ => return None,
+        };
+        Some((value as f32).into())
+    }
+
+    fn set_parameter(&self, index: usize, request: ParameterValue) {
+        let ParameterValue::Float { value } = request;
+        let value = value as FloatT;
+        match index {
+            0 => self.0.set_threshold(value),
+            1 => self.0.set_ratio(value),
+            2 => self.0.set_attack_ms(value),
+            3 => self.0.set_release_ms(value),
+            4 => self.0.set_make_up_gain(value),
+            5 => self.0.set_knee_width(value),
+            _ => {}
+        }
+    }
+}
@@ -27,10 +27,18 @@
 //! * [Digital Dynamic Range Compressor Design — A Tutorial and Analysis](https://www.eecs.qmul.ac.uk/~josh/documents/2012/GiannoulisMassbergReiss-dynamicrangecompression-JAES2012.pdf)
 
 use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_traits::parameters::{
+    make_handle_ref, AudioProcessorHandleProvider, AudioProcessorHandleRef,
+};
 use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
 use augmented_audio_volume::db_to_amplitude;
+use generic_handle::GenericHandle;
 use handle::CompressorHandle;
 
+mod generic_handle;
+#[cfg(test)]
+mod test_allocator;
+
 type FloatT = augmented_audio_volume::Float;
 
 mod handle {
@@ -123,6 +131,14 @@ mod handle {
         pub fn knee_width(&self) -> FloatT {
             self.knee_width_db.get()
         }
+
+        pub fn attack_ms(&self) -> FloatT {
+            self.attack_ms.get()
+        }
+
+        pub fn release_ms(&self) -> FloatT {
+            self.release_ms.get()
+        }
     }
 }
 
@@ -150,6 +166,12 @@ impl CompressorProcessor {
     }
 }
 
+impl AudioProcessorHandleProvider for CompressorProcessor {
+    fn generic_handle(&self) -> AudioProcessorHandleRef {
+        make_handle_ref(GenericHandle(self.handle.clone()))
+    }
+}
+
 impl AudioProcessor for CompressorProcessor {
     type SampleType = FloatT;
 
@@ -223,6 +245,7 @@ mod test {
     use audio_processor_testing_helpers::charts::{
         draw_multi_vec_charts, draw_vec_chart, BLUE, RED,
     };
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
     use audio_processor_testing_helpers::relative_path;
 
     use audio_processor_file::AudioFileProcessor;
@@ -243,6 +266,23 @@ mod test {
         let _ = CompressorProcessor::new();
     }
 
+    #[test]
+    fn test_generic_handle_sets_parameters() {
+        let processor = CompressorProcessor::new();
+        let handle = processor.generic_handle();
+        assert_eq!(handle.name(), "Compressor");
+        assert_eq!(handle.parameter_count(), 6);
+
+        handle.set_parameter(1, 4.0.into());
+        assert_eq!(processor.handle().ratio(), 4.0);
+        assert_eq!(handle.get_parameter(1), Some(4.0.into()));
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        assert_processor_realtime_safe(CompressorProcessor::new(), Default::default(), 10);
+    }
+
     #[test]
     fn test_knee_widths() {
         let amp = db_to_amplitude(0.1, 1.0);
@@ -0,0 +1,344 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Audio file metadata, markers & loop points.
+//!
+//! Codec parameters & tags are read through [`symphonia`]. Markers (`cue ` & `LIST`/`adtl`), loop
+//! points (`smpl`) and ACID tempo/root-note tags (`acid`) are read from the RIFF chunks of WAV
+//! files.
+
+use std::path::Path;
+use std::time::Duration;
+
+use symphonia::core::audio::Channels;
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+use symphonia::core::probe::ProbeResult;
+
+use super::riff;
+use super::{default_read_audio_file, AudioFileError};
+
+/// A named position in an audio file. Stored as a `cue ` point on WAV files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioFileMarker {
+    /// Unique identifier of this marker within its file
+    pub id: u32,
+    /// Position in frames
+    pub position_samples: u64,
+    pub label: Option<String>,
+}
+
+/// A loop region. Stored on the `smpl` chunk of WAV files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioFileLoop {
+    /// First frame of the loop
+    pub start: u64,
+    /// Frame after the last frame of the loop
+    pub end: u64,
+    /// Number of times the loop should play, `0` means it loops forever
+    pub play_count: u32,
+}
+
+/// Contents of an ACID `acid` chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcidInfo {
+    pub tempo: f32,
+    /// MIDI note number
+    pub root_note: Option<u8>,
+    pub num_beats: u32,
+    /// Numerator & denominator
+    pub meter: (u16, u16),
+    pub one_shot: bool,
+}
+
+/// A tag read from ID3, Vorbis comments, RIFF `INFO` or any other container supported by
+/// [`symphonia`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioFileTag {
+    pub key: String,
+    pub std_key: Option<StandardTagKey>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AudioFileMetadata {
+    pub sample_rate: Option<u32>,
+    /// Length of the file in frames
+    pub num_frames: Option<u64>,
+    pub num_channels: usize,
+    pub channel_layout: Option<Channels>,
+    /// Bit depth of decoded samples, not available for lossy formats
+    pub bits_per_sample: Option<u32>,
+    pub markers: Vec<AudioFileMarker>,
+    pub loops: Vec<AudioFileLoop>,
+    /// Unity note of the `smpl` chunk
+    pub sampler_root_note: Option<u8>,
+    pub acid: Option<AcidInfo>,
+    pub tags: Vec<AudioFileTag>,
+}
+
+impl AudioFileMetadata {
+    pub fn duration(&self) -> Option<Duration> {
+        let num_frames = self.num_frames?;
+        let sample_rate = self.sample_rate.filter(|sample_rate| *sample_rate > 0)?;
+        Some(Duration::from_secs_f64(
+            num_frames as f64 / sample_rate as f64,
+        ))
+    }
+
+    /// Tempo from the ACID chunk, falling back to `BPM`/`TBPM` tags
+    pub fn tempo(&self) -> Option<f32> {
+        self.acid
+            .as_ref()
+            .map(|acid| acid.tempo)
+            .filter(|tempo| *tempo > 0.0)
+            .or_else(|| {
+                self.tag(StandardTagKey::Bpm)
+                    .and_then(|bpm| bpm.trim().parse().ok())
+            })
+    }
+
+    /// Root note from the ACID chunk, falling back to the `smpl` unity note
+    pub fn root_note(&self) -> Option<u8> {
+        self.acid
+            .as_ref()
+            .and_then(|acid| acid.root_note)
+            .or(self.sampler_root_note)
+    }
+
+    /// First value for a standard tag
+    pub fn tag(&self, key: StandardTagKey) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.as_str())
+    }
+}
+
+/// Read metadata from the file at `path`.
+///
+/// This doesn't decode the audio, but WAV files are read twice, once by [`symphonia`] and once to
+/// find metadata chunks.
+pub fn read_audio_file_metadata(path: &str) -> Result<AudioFileMetadata, AudioFileError> {
+    let mut audio_file = default_read_audio_file(path)?;
+    let mut metadata = read_probe_metadata(&mut audio_file);
+
+    if let Some(chunks) = riff::read_wav_chunks(Path::new(path))? {
+        metadata.markers = chunks.markers;
+        metadata.loops = chunks.loops;
+        metadata.sampler_root_note = chunks.root_note;
+        metadata.acid = chunks.acid;
+    }
+
+    Ok(metadata)
+}
+
+/// Read codec parameters & tags from an already open file
+pub fn read_probe_metadata(audio_file: &mut ProbeResult) -> AudioFileMetadata {
+    let mut metadata = AudioFileMetadata::default();
+
+    if let Some(track) = audio_file.format.default_track() {
+        let params = &track.codec_params;
+        metadata.sample_rate = params.sample_rate;
+        metadata.num_frames = params.n_frames;
+        metadata.channel_layout = params.channels;
+        metadata.num_channels = params.channels.map(|c| c.count()).unwrap_or(0);
+        metadata.bits_per_sample = params.bits_per_sample;
+    }
+
+    // Tags found before the container (e.g. ID3v2) come first
+    if let Some(revision) = audio_file.metadata.get().as_ref().and_then(|m| m.current()) {
+        push_tags(&mut metadata.tags, revision);
+    }
+    if let Some(revision) = audio_file.format.metadata().current() {
+        push_tags(&mut metadata.tags, revision);
+    }
+
+    metadata
+}
+
+/// Write markers & loop points onto an existing WAV file, replacing the ones it has
+pub fn write_wav_markers(
+    path: &str,
+    markers: &[AudioFileMarker],
+    loops: &[AudioFileLoop],
+) -> Result<(), AudioFileError> {
+    let path = Path::new(path);
+    let existing = riff::read_wav_chunks(path)?.ok_or(AudioFileError::OpenStreamError)?;
+    let sample_rate = existing.sample_rate.unwrap_or(44100);
+
+    let mut chunks = vec![];
+    if !markers.is_empty() {
+        chunks.push((*b"cue ", riff::cue_chunk(markers)));
+    }
+    if let Some(adtl) = riff::adtl_chunk(markers) {
+        chunks.push((*b"LIST", adtl));
+    }
+    if !loops.is_empty() || existing.root_note.is_some() {
+        let root_note = existing.root_note.unwrap_or(60);
+        chunks.push((*b"smpl", riff::smpl_chunk(loops, sample_rate, root_note)));
+    }
+
+    riff::append_chunks(path, &chunks, &[*b"cue ", *b"smpl"])?;
+    Ok(())
+}
+
+fn push_tags(tags: &mut Vec<AudioFileTag>, revision: &MetadataRevision) {
+    tags.extend(revision.tags().iter().map(|tag| AudioFileTag {
+        key: tag.key.clone(),
+        std_key: tag.std_key,
+        value: tag.value.to_string(),
+    }));
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use symphonia::core::audio::Channels;
+    use tempdir::TempDir;
+
+    use audio_processor_traits::{AudioBuffer, AudioProcessorSettings};
+
+    use crate::{OutputAudioFileProcessor, OutputFileSettings, RecordingMetadata};
+
+    use super::*;
+
+    fn write_file(tempdir: &TempDir, name: &str, metadata: RecordingMetadata) -> PathBuf {
+        let path = tempdir.path().join(name);
+        let mut settings = OutputFileSettings::new(path.to_str().unwrap());
+        settings.metadata = Some(metadata);
+        let mut processor = OutputAudioFileProcessor::new(Default::default(), settings);
+        processor.prepare(AudioProcessorSettings::new(48000.0, 2, 2, 512));
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 96000);
+        processor.process(&mut buffer).unwrap();
+        processor.finalize().unwrap();
+        path
+    }
+
+    fn markers() -> Vec<AudioFileMarker> {
+        vec![
+            AudioFileMarker {
+                id: 1,
+                position_samples: 0,
+                label: Some(String::from("Start")),
+            },
+            AudioFileMarker {
+                id: 2,
+                position_samples: 24000,
+                label: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_read_wav_metadata() {
+        let tempdir = TempDir::new("metadata").unwrap();
+        let loops = vec![AudioFileLoop {
+            start: 0,
+            end: 96000,
+            play_count: 0,
+        }];
+        let path = write_file(
+            &tempdir,
+            "test.wav",
+            RecordingMetadata {
+                tempo: Some(120.0),
+                markers: markers(),
+                loops: loops.clone(),
+                ..RecordingMetadata::default()
+            },
+        );
+
+        let metadata = read_audio_file_metadata(path.to_str().unwrap()).unwrap();
+        assert_eq!(metadata.sample_rate, Some(48000));
+        assert_eq!(metadata.num_frames, Some(96000));
+        assert_eq!(metadata.duration(), Some(Duration::from_secs(2)));
+        assert_eq!(metadata.num_channels, 2);
+        assert_eq!(
+            metadata.channel_layout,
+            Some(Channels::FRONT_LEFT | Channels::FRONT_RIGHT)
+        );
+        assert_eq!(metadata.bits_per_sample, Some(32));
+        assert_eq!(metadata.markers, markers());
+        assert_eq!(metadata.loops, loops);
+        assert_eq!(metadata.tempo(), Some(120.0));
+        assert_eq!(metadata.acid.as_ref().unwrap().num_beats, 4);
+        assert_eq!(metadata.root_note(), Some(60));
+    }
+
+    #[test]
+    fn test_write_wav_markers_replaces_existing_markers() {
+        let tempdir = TempDir::new("metadata").unwrap();
+        let path = write_file(
+            &tempdir,
+            "test.wav",
+            RecordingMetadata {
+                markers: markers(),
+                ..RecordingMetadata::default()
+            },
+        );
+        let path = path.to_str().unwrap();
+
+        let new_markers = vec![AudioFileMarker {
+            id: 7,
+            position_samples: 100,
+            label: Some(String::from("Chorus")),
+        }];
+        let new_loops = vec![AudioFileLoop {
+            start: 100,
+            end: 200,
+            play_count: 2,
+        }];
+        write_wav_markers(path, &new_markers, &new_loops).unwrap();
+
+        let metadata = read_audio_file_metadata(path).unwrap();
+        assert_eq!(metadata.markers, new_markers);
+        assert_eq!(metadata.loops, new_loops);
+        // Audio is still readable
+        assert_eq!(metadata.num_frames, Some(96000));
+        let mut audio_file = crate::file_io::default_read_audio_file(path).unwrap();
+        crate::file_io::read_file_contents(&mut audio_file).unwrap();
+    }
+
+    #[test]
+    fn test_read_flac_tags() {
+        let tempdir = TempDir::new("metadata").unwrap();
+        let path = write_file(
+            &tempdir,
+            "test.flac",
+            RecordingMetadata {
+                description: String::from("A take"),
+                tempo: Some(98.5),
+                ..RecordingMetadata::default()
+            },
+        );
+
+        let metadata = read_audio_file_metadata(path.to_str().unwrap()).unwrap();
+        assert_eq!(metadata.bits_per_sample, Some(24));
+        assert_eq!(metadata.num_frames, Some(96000));
+        assert_eq!(metadata.tempo(), Some(98.5));
+        assert_eq!(metadata.tag(StandardTagKey::Description), Some("A take"));
+        assert!(metadata.markers.is_empty());
+    }
+}
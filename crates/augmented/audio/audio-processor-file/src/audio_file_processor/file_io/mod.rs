@@ -36,12 +36,20 @@ use symphonia::default::get_probe;
 use audio_processor_traits::AudioBuffer;
 use augmented_audio_metrics as metrics;
 
-use crate::file_io::sample_rate_converter::BLOCK_SIZE;
+use crate::file_io::sample_rate_converter::{
+    make_converter, SampleRateConverter, SampleRateConverterOptions, BLOCK_SIZE,
+};
 
 pub use self::audio_file_error::AudioFileError;
+pub use self::metadata::{
+    read_audio_file_metadata, read_probe_metadata, write_wav_markers, AcidInfo, AudioFileLoop,
+    AudioFileMarker, AudioFileMetadata, AudioFileTag,
+};
 
 mod audio_file_error;
-mod sample_rate_converter;
+mod metadata;
+pub(crate) mod riff;
+pub mod sample_rate_converter;
 #[cfg(test)]
 mod test;
 
@@ -238,27 +246,25 @@ where
 pub struct ConvertedFileContentsStream<'a> {
     audio_file_stream: FileFramesStream<FileContentsStream<'a>>,
     output_rate: f32,
-    decoder: Option<sample_rate_converter::Decoder>,
+    converter: Option<Box<dyn SampleRateConverter>>,
 }
 
 impl<'a> ConvertedFileContentsStream<'a> {
-    fn get_decoder(
+    fn get_converter(
         &mut self,
         from_rate: u32,
         channels: usize,
-    ) -> Option<&mut sample_rate_converter::Decoder> {
-        if self.decoder.is_none() {
-            self.decoder = Some(
-                sample_rate_converter::make_decoder(from_rate, self.output_rate as u32, channels)
-                    .unwrap(),
-            );
+    ) -> Option<&mut Box<dyn SampleRateConverter>> {
+        if self.converter.is_none() {
+            let options = SampleRateConverterOptions {
+                num_channels: channels,
+                ..Default::default()
+            };
+            self.converter =
+                Some(make_converter(from_rate, self.output_rate as u32, &options).unwrap());
         }
 
-        if let Some(decoder) = &mut self.decoder {
-            Some(decoder)
-        } else {
-            None
-        }
+        self.converter.as_mut()
     }
 }
 
@@ -269,20 +275,28 @@ impl<'a> Iterator for ConvertedFileContentsStream<'a> {
         let (channels, chunk_size) = self.audio_file_stream.next()?;
         let rate = self.audio_file_stream.rate;
 
-        let decoder = self.get_decoder(rate, channels.len())?;
+        let converter = self.get_converter(rate, channels.len())?;
 
         assert_eq!(channels.len(), 2);
         assert_eq!(channels[0].len(), BLOCK_SIZE);
+        let mut chunk_result = AudioBuffer::empty();
+        chunk_result.resize(channels.len(), converter.max_output_frames(BLOCK_SIZE));
+        let output_frames = converter
+            .process(&AudioBuffer::new(channels), &mut chunk_result)
+            .ok()?;
+        for channel in chunk_result.channels_mut() {
+            channel.truncate(output_frames);
+        }
+
         // Resize channels so that the trailing smaller block is resized
-        let mut chunk_result = sample_rate_converter::process(decoder, &channels).ok()?;
         let expected_chunk_size = (chunk_size as f32 * self.output_rate / rate as f32) as usize;
         if chunk_size != BLOCK_SIZE {
-            for channel in chunk_result.iter_mut() {
+            for channel in chunk_result.channels_mut() {
                 channel.resize(expected_chunk_size, 0.0);
             }
         }
 
-        Some(AudioBuffer::new(chunk_result))
+        Some(chunk_result)
     }
 }
 
@@ -293,23 +307,20 @@ pub fn convert_audio_file_stream_sample_rate(
     ConvertedFileContentsStream {
         audio_file_stream: FileFramesStream::new(audio_file_stream, BLOCK_SIZE),
         output_rate,
-        decoder: None,
+        converter: None,
     }
 }
 
-#[cfg(feature = "samplerate")]
 pub fn convert_audio_file_sample_rate(
     audio_file_contents: &SymphoniaAudioBuffer<f32>,
     output_rate: f32,
     channel_number: usize,
 ) -> Vec<f32> {
     let audio_file_channel = audio_file_contents.chan(channel_number);
-    let input_rate = audio_file_contents.spec().rate as f32;
-    let audio_file_duration = audio_file_channel.len() as f32 / input_rate;
-
-    let output_size = (audio_file_duration * output_rate).ceil() as usize;
-    let mut channel = Vec::new();
-    channel.resize(output_size, 0.0);
+    let input_rate = audio_file_contents.spec().rate;
+    if input_rate == output_rate as u32 {
+        return audio_file_channel.to_vec();
+    }
 
     // Convert sample rate from audio file to in-memory
     log::info!(
@@ -318,14 +329,23 @@ pub fn convert_audio_file_sample_rate(
         input_rate,
         output_rate
     );
-    augmented_convert_sample_rate::convert_sample_rate(
-        input_rate,
-        audio_file_channel,
-        output_rate,
-        channel.as_mut_slice(),
-    );
-
-    channel
+    let options = SampleRateConverterOptions {
+        num_channels: 1,
+        ..Default::default()
+    };
+    let result =
+        make_converter(input_rate, output_rate as u32, &options).and_then(|mut converter| {
+            let input = AudioBuffer::new(vec![audio_file_channel.to_vec()]);
+            sample_rate_converter::convert_buffer(converter.as_mut(), &input)
+        });
+
+    match result {
+        Ok(buffer) => buffer.channels()[0].clone(),
+        Err(err) => {
+            log::error!("Failed to convert sample rate {}", err);
+            vec![]
+        }
+    }
 }
 
 /// buffers must be non-empty and stereo
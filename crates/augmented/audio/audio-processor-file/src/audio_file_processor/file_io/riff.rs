@@ -0,0 +1,419 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Reading & writing RIFF/WAVE chunks which symphonia doesn't expose.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::metadata::{AcidInfo, AudioFileLoop, AudioFileMarker};
+
+/// Chunks larger than this aren't metadata, they won't be read onto memory
+const MAX_METADATA_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+const ACID_ONE_SHOT: u32 = 0x01;
+const ACID_ROOT_NOTE_SET: u32 = 0x02;
+
+/// Metadata chunks found in a WAV file
+#[derive(Debug, Default)]
+pub(crate) struct WavChunks {
+    pub(crate) sample_rate: Option<u32>,
+    pub(crate) markers: Vec<AudioFileMarker>,
+    pub(crate) loops: Vec<AudioFileLoop>,
+    pub(crate) root_note: Option<u8>,
+    pub(crate) acid: Option<AcidInfo>,
+}
+
+/// Returns `None` if the file isn't RIFF/WAVE
+pub(crate) fn read_wav_chunks(path: &Path) -> io::Result<Option<WavChunks>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if !is_riff_wave(&mut reader)? {
+        return Ok(None);
+    }
+
+    let mut chunks = WavChunks::default();
+    let mut labels: Vec<(u32, String)> = vec![];
+    while let Some((id, size)) = read_chunk_header(&mut reader)? {
+        let is_metadata = matches!(&id, b"fmt " | b"cue " | b"LIST" | b"smpl" | b"acid");
+        if !is_metadata || size > MAX_METADATA_CHUNK_SIZE {
+            skip_chunk(&mut reader, size)?;
+            continue;
+        }
+
+        let mut contents = vec![0; size as usize];
+        reader.read_exact(&mut contents)?;
+        if size % 2 != 0 {
+            reader.seek(SeekFrom::Current(1))?;
+        }
+
+        match &id {
+            b"fmt " if contents.len() >= 8 => chunks.sample_rate = Some(read_u32(&contents, 4)),
+            b"cue " => chunks.markers = parse_cue(&contents),
+            b"LIST" if contents.starts_with(b"adtl") => labels = parse_adtl(&contents[4..]),
+            b"smpl" => {
+                let (root_note, loops) = parse_smpl(&contents);
+                chunks.root_note = root_note;
+                chunks.loops = loops;
+            }
+            b"acid" => chunks.acid = parse_acid(&contents),
+            _ => {}
+        }
+    }
+
+    for marker in &mut chunks.markers {
+        marker.label = labels
+            .iter()
+            .find(|(id, _)| *id == marker.id)
+            .map(|(_, label)| label.clone());
+    }
+
+    Ok(Some(chunks))
+}
+
+/// Append chunks to a finalized WAV file and update the RIFF header.
+///
+/// RIFF readers must skip unknown chunks, so these may come after the `data` chunk, which avoids
+/// re-writing the whole file. Existing chunks matching `replace` are renamed to `JUNK` so readers
+/// ignore them; `LIST` only matches if it's of the same list type.
+pub(crate) fn append_chunks(
+    path: &Path,
+    chunks: &[([u8; 4], Vec<u8>)],
+    replace: &[[u8; 4]],
+) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    if !is_riff_wave(&mut file)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a RIFF/WAVE file",
+        ));
+    }
+
+    while let Some((id, size)) = read_chunk_header(&mut file)? {
+        let chunk_start = file.stream_position()?;
+        let should_replace = if &id == b"LIST" {
+            let mut list_type = [0; 4];
+            file.read_exact(&mut list_type)?;
+            chunks
+                .iter()
+                .any(|(new_id, contents)| new_id == b"LIST" && contents.starts_with(&list_type))
+        } else {
+            replace.contains(&id)
+        };
+        if should_replace {
+            file.seek(SeekFrom::Start(chunk_start - 8))?;
+            file.write_all(b"JUNK")?;
+        }
+        file.seek(SeekFrom::Start(chunk_start))?;
+        skip_chunk(&mut file, size)?;
+    }
+
+    let mut end = file.seek(SeekFrom::End(0))?;
+    // Chunks start on even offsets
+    if end % 2 != 0 {
+        file.write_all(&[0])?;
+        end += 1;
+    }
+
+    for (id, contents) in chunks {
+        file.write_all(id)?;
+        file.write_all(&(contents.len() as u32).to_le_bytes())?;
+        file.write_all(contents)?;
+        end += 8 + contents.len() as u64;
+        if contents.len() % 2 != 0 {
+            file.write_all(&[0])?;
+            end += 1;
+        }
+    }
+
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&((end - 8) as u32).to_le_bytes())?;
+    file.flush()
+}
+
+pub(crate) fn cue_chunk(markers: &[AudioFileMarker]) -> Vec<u8> {
+    let mut chunk = vec![];
+    chunk.extend_from_slice(&(markers.len() as u32).to_le_bytes());
+    for marker in markers {
+        chunk.extend_from_slice(&marker.id.to_le_bytes());
+        // Play order position
+        chunk.extend_from_slice(&(marker.position_samples as u32).to_le_bytes());
+        chunk.extend_from_slice(b"data");
+        // Chunk & block start, zero for uncompressed files with a single data chunk
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend_from_slice(&(marker.position_samples as u32).to_le_bytes());
+    }
+    chunk
+}
+
+/// `LIST` chunk with `labl` sub-chunks for labelled markers
+pub(crate) fn adtl_chunk(markers: &[AudioFileMarker]) -> Option<Vec<u8>> {
+    let mut chunk = b"adtl".to_vec();
+    for marker in markers {
+        if let Some(label) = &marker.label {
+            let size = 4 + label.len() + 1;
+            chunk.extend_from_slice(b"labl");
+            chunk.extend_from_slice(&(size as u32).to_le_bytes());
+            chunk.extend_from_slice(&marker.id.to_le_bytes());
+            chunk.extend_from_slice(label.as_bytes());
+            chunk.push(0);
+            if size % 2 != 0 {
+                chunk.push(0);
+            }
+        }
+    }
+    if chunk.len() > 4 {
+        Some(chunk)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn smpl_chunk(loops: &[AudioFileLoop], sample_rate: u32, root_note: u8) -> Vec<u8> {
+    let mut chunk = vec![];
+    // Manufacturer & product
+    chunk.extend_from_slice(&0u32.to_le_bytes());
+    chunk.extend_from_slice(&0u32.to_le_bytes());
+    let sample_period = 1_000_000_000 / sample_rate.max(1);
+    chunk.extend_from_slice(&sample_period.to_le_bytes());
+    chunk.extend_from_slice(&(root_note as u32).to_le_bytes());
+    // Pitch fraction, SMPTE format & offset
+    chunk.extend_from_slice(&[0; 12]);
+    chunk.extend_from_slice(&(loops.len() as u32).to_le_bytes());
+    // Sampler data
+    chunk.extend_from_slice(&0u32.to_le_bytes());
+    for (index, sample_loop) in loops.iter().enumerate() {
+        chunk.extend_from_slice(&(index as u32).to_le_bytes());
+        // Forward loop
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend_from_slice(&(sample_loop.start as u32).to_le_bytes());
+        // The end sample is played
+        let end = sample_loop.end.saturating_sub(1).max(sample_loop.start);
+        chunk.extend_from_slice(&(end as u32).to_le_bytes());
+        // Fraction
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend_from_slice(&sample_loop.play_count.to_le_bytes());
+    }
+    chunk
+}
+
+pub(crate) fn acid_chunk(acid: &AcidInfo) -> Vec<u8> {
+    let mut flags = 0;
+    if acid.one_shot {
+        flags |= ACID_ONE_SHOT;
+    }
+    if acid.root_note.is_some() {
+        flags |= ACID_ROOT_NOTE_SET;
+    }
+
+    let mut chunk = vec![];
+    chunk.extend_from_slice(&flags.to_le_bytes());
+    chunk.extend_from_slice(&(acid.root_note.unwrap_or(60) as u16).to_le_bytes());
+    // Unknown fields, these are the values ACID writes
+    chunk.extend_from_slice(&0x8000u16.to_le_bytes());
+    chunk.extend_from_slice(&0f32.to_le_bytes());
+    chunk.extend_from_slice(&acid.num_beats.to_le_bytes());
+    chunk.extend_from_slice(&acid.meter.1.to_le_bytes());
+    chunk.extend_from_slice(&acid.meter.0.to_le_bytes());
+    chunk.extend_from_slice(&acid.tempo.to_le_bytes());
+    chunk
+}
+
+fn parse_cue(contents: &[u8]) -> Vec<AudioFileMarker> {
+    if contents.len() < 4 {
+        return vec![];
+    }
+    let num_points = read_u32(contents, 0) as usize;
+    contents[4..]
+        .chunks_exact(24)
+        .take(num_points)
+        .map(|point| AudioFileMarker {
+            id: read_u32(point, 0),
+            position_samples: read_u32(point, 20) as u64,
+            label: None,
+        })
+        .collect()
+}
+
+fn parse_adtl(mut contents: &[u8]) -> Vec<(u32, String)> {
+    let mut labels = vec![];
+    while contents.len() >= 8 {
+        let size = read_u32(contents, 4) as usize;
+        let end = (8 + size).min(contents.len());
+        let body = &contents[8..end];
+        if (&contents[0..4] == b"labl" || &contents[0..4] == b"note") && body.len() >= 4 {
+            let text = &body[4..];
+            let text = &text[..text.iter().position(|c| *c == 0).unwrap_or(text.len())];
+            if &contents[0..4] == b"labl" {
+                labels.push((read_u32(body, 0), String::from_utf8_lossy(text).to_string()));
+            }
+        }
+        contents = &contents[(end + size % 2).min(contents.len())..];
+    }
+    labels
+}
+
+fn parse_smpl(contents: &[u8]) -> (Option<u8>, Vec<AudioFileLoop>) {
+    if contents.len() < 36 {
+        return (None, vec![]);
+    }
+    let root_note = u8::try_from(read_u32(contents, 12)).ok();
+    let num_loops = read_u32(contents, 28) as usize;
+    let loops = contents[36..]
+        .chunks_exact(24)
+        .take(num_loops)
+        .map(|sample_loop| AudioFileLoop {
+            start: read_u32(sample_loop, 8) as u64,
+            end: read_u32(sample_loop, 12) as u64 + 1,
+            play_count: read_u32(sample_loop, 20),
+        })
+        .collect();
+    (root_note, loops)
+}
+
+fn parse_acid(contents: &[u8]) -> Option<AcidInfo> {
+    if contents.len() < 24 {
+        return None;
+    }
+    let flags = read_u32(contents, 0);
+    Some(AcidInfo {
+        tempo: f32::from_le_bytes(contents[20..24].try_into().ok()?),
+        root_note: if flags & ACID_ROOT_NOTE_SET != 0 {
+            u8::try_from(read_u16(contents, 4)).ok()
+        } else {
+            None
+        },
+        num_beats: read_u32(contents, 12),
+        meter: (read_u16(contents, 18), read_u16(contents, 16)),
+        one_shot: flags & ACID_ONE_SHOT != 0,
+    })
+}
+
+fn is_riff_wave<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let mut header = [0; 12];
+    reader.seek(SeekFrom::Start(0))?;
+    match reader.read_exact(&mut header) {
+        Ok(()) => Ok(&header[0..4] == b"RIFF" && &header[8..12] == b"WAVE"),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns `None` at the end of the file
+fn read_chunk_header<R: Read>(reader: &mut R) -> io::Result<Option<([u8; 4], u32)>> {
+    let mut header = [0; 8];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {
+            let mut id = [0; 4];
+            id.copy_from_slice(&header[0..4]);
+            Ok(Some((id, read_u32(&header, 4))))
+        }
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn skip_chunk<R: Seek>(reader: &mut R, size: u32) -> io::Result<()> {
+    reader.seek(SeekFrom::Current(size as i64 + size as i64 % 2))?;
+    Ok(())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn markers() -> Vec<AudioFileMarker> {
+        vec![
+            AudioFileMarker {
+                id: 1,
+                position_samples: 100,
+                label: Some(String::from("Verse")),
+            },
+            AudioFileMarker {
+                id: 2,
+                position_samples: 2000,
+                label: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_cue_and_labels_round_trip() {
+        let mut markers = markers();
+        let parsed = parse_cue(&cue_chunk(&markers));
+        let labels = parse_adtl(&adtl_chunk(&markers).unwrap()[4..]);
+        assert_eq!(labels, vec![(1, String::from("Verse"))]);
+        assert_eq!(parsed.len(), 2);
+        markers[0].label = None;
+        assert_eq!(parsed, markers);
+    }
+
+    #[test]
+    fn test_adtl_chunk_is_empty_without_labels() {
+        let mut markers = markers();
+        markers[0].label = None;
+        assert!(adtl_chunk(&markers).is_none());
+    }
+
+    #[test]
+    fn test_smpl_round_trip() {
+        let loops = vec![AudioFileLoop {
+            start: 10,
+            end: 1000,
+            play_count: 0,
+        }];
+        let chunk = smpl_chunk(&loops, 44100, 64);
+        assert_eq!(chunk.len(), 36 + 24);
+        assert_eq!(read_u32(&chunk, 8), 22675);
+        assert_eq!(read_u32(&chunk, 36 + 12), 999);
+        assert_eq!(parse_smpl(&chunk), (Some(64), loops));
+    }
+
+    #[test]
+    fn test_acid_round_trip() {
+        let acid = AcidInfo {
+            tempo: 128.0,
+            root_note: Some(57),
+            num_beats: 16,
+            meter: (4, 4),
+            one_shot: false,
+        };
+        let chunk = acid_chunk(&acid);
+        assert_eq!(chunk.len(), 24);
+        assert_eq!(parse_acid(&chunk), Some(acid));
+    }
+}
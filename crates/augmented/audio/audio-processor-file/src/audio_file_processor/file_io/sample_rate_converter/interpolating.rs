@@ -0,0 +1,298 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use audio_processor_traits::AudioBuffer;
+
+use super::{
+    validate_buffers, validate_ratio, ConverterQuality, SampleRateConverter,
+    SampleRateConverterError, SampleRateConverterOptions,
+};
+
+/// Half the number of taps of the windowed-sinc kernel
+const SINC_HALF_LENGTH: usize = 16;
+/// Number of fractional positions the sinc kernel is tabulated at
+const SINC_OVERSAMPLING: usize = 256;
+
+/// Sample rate converter implemented in this crate. It doesn't allocate while processing & the
+/// ratio may change on any block.
+///
+/// * [`ConverterQuality::Fast`] interpolates linearly
+/// * [`ConverterQuality::Balanced`] uses 4-point cubic Hermite interpolation
+/// * [`ConverterQuality::Best`] uses a 32-tap Blackman windowed-sinc kernel, with its cut-off set
+///   below the lowest output Nyquist frequency the ratio may reach
+pub struct InterpolatingConverter {
+    quality: ConverterQuality,
+    initial_ratio: f64,
+    ratio: f64,
+    max_ratio_change: f64,
+    max_block_size: usize,
+    /// Input frames needed before & after the interpolated position
+    frames_before: usize,
+    frames_after: usize,
+    /// `SINC_OVERSAMPLING + 1` kernels of `2 * SINC_HALF_LENGTH` taps
+    sinc_table: Vec<f32>,
+    /// Input which hasn't been fully used yet, per channel
+    history: Vec<Vec<f32>>,
+    /// Fractional index onto `history` of the next output frame
+    position: f64,
+}
+
+impl InterpolatingConverter {
+    pub fn new(ratio: f64, options: &SampleRateConverterOptions) -> Self {
+        let (frames_before, frames_after) = match options.quality {
+            ConverterQuality::Fast => (0, 1),
+            ConverterQuality::Balanced => (1, 2),
+            ConverterQuality::Best => (SINC_HALF_LENGTH - 1, SINC_HALF_LENGTH),
+        };
+        let sinc_table = if options.quality == ConverterQuality::Best {
+            let min_ratio = ratio / options.max_ratio_change.max(1.0);
+            build_sinc_table(min_ratio.min(1.0) * 0.95)
+        } else {
+            vec![]
+        };
+        let history_size = frames_before + frames_after + options.max_block_size + 1;
+
+        let mut converter = Self {
+            quality: options.quality,
+            initial_ratio: ratio,
+            ratio,
+            max_ratio_change: options.max_ratio_change.max(1.0),
+            max_block_size: options.max_block_size,
+            frames_before,
+            frames_after,
+            sinc_table,
+            history: (0..options.num_channels)
+                .map(|_| Vec::with_capacity(history_size))
+                .collect(),
+            position: 0.0,
+        };
+        converter.reset();
+        converter
+    }
+
+    fn interpolate(&self, samples: &[f32], index: usize, fraction: f32) -> f32 {
+        match self.quality {
+            ConverterQuality::Fast => {
+                samples[index] + (samples[index + 1] - samples[index]) * fraction
+            }
+            ConverterQuality::Balanced => {
+                let (y0, y1, y2, y3) = (
+                    samples[index - 1],
+                    samples[index],
+                    samples[index + 1],
+                    samples[index + 2],
+                );
+                let c1 = 0.5 * (y2 - y0);
+                let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+                let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+                ((c3 * fraction + c2) * fraction + c1) * fraction + y1
+            }
+            ConverterQuality::Best => {
+                let taps = 2 * SINC_HALF_LENGTH;
+                let table_position = fraction * SINC_OVERSAMPLING as f32;
+                let table_index = (table_position as usize).min(SINC_OVERSAMPLING - 1);
+                let table_fraction = table_position - table_index as f32;
+                let kernel = &self.sinc_table[table_index * taps..(table_index + 2) * taps];
+                let samples = &samples[index + 1 - SINC_HALF_LENGTH..index + 1 + SINC_HALF_LENGTH];
+
+                let mut result = 0.0;
+                for (tap, sample) in samples.iter().enumerate() {
+                    let weight = kernel[tap] + (kernel[taps + tap] - kernel[tap]) * table_fraction;
+                    result += sample * weight;
+                }
+                result
+            }
+        }
+    }
+}
+
+impl SampleRateConverter for InterpolatingConverter {
+    fn num_channels(&self) -> usize {
+        self.history.len()
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.max_block_size
+    }
+
+    fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    fn set_ratio(&mut self, ratio: f64) -> Result<(), SampleRateConverterError> {
+        validate_ratio(self.initial_ratio, ratio, self.max_ratio_change)?;
+        self.ratio = ratio;
+        Ok(())
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        let max_ratio = self.initial_ratio * self.max_ratio_change;
+        let buffered_frames = self.frames_before + self.frames_after + 1;
+        ((input_frames + buffered_frames) as f64 * max_ratio).ceil() as usize + 1
+    }
+
+    fn process(
+        &mut self,
+        input: &AudioBuffer<f32>,
+        output: &mut AudioBuffer<f32>,
+    ) -> Result<usize, SampleRateConverterError> {
+        validate_buffers(self, input, output)?;
+
+        for (history, channel) in self.history.iter_mut().zip(input.channels()) {
+            history.extend_from_slice(channel);
+        }
+
+        let num_frames = self.history.first().map(|h| h.len()).unwrap_or(0);
+        let step = 1.0 / self.ratio;
+        let mut output_frames = 0;
+        loop {
+            let index = self.position as usize;
+            if index + self.frames_after >= num_frames {
+                break;
+            }
+
+            let fraction = (self.position - index as f64) as f32;
+            for (channel, history) in self.history.iter().enumerate() {
+                output.channel_mut(channel)[output_frames] =
+                    self.interpolate(history, index, fraction);
+            }
+            output_frames += 1;
+            self.position += step;
+        }
+
+        let consumed = (self.position as usize)
+            .saturating_sub(self.frames_before)
+            .min(num_frames);
+        for history in &mut self.history {
+            history.drain(..consumed);
+        }
+        self.position -= consumed as f64;
+
+        Ok(output_frames)
+    }
+
+    fn reset(&mut self) {
+        // Input is preceded by silence, so the first output frame lines up with the first input
+        // frame
+        for history in &mut self.history {
+            history.clear();
+            history.resize(self.frames_before, 0.0);
+        }
+        self.position = self.frames_before as f64;
+    }
+}
+
+/// Tabulates the kernel for each fractional position. `cutoff` is relative to the input Nyquist
+/// frequency.
+fn build_sinc_table(cutoff: f64) -> Vec<f32> {
+    let taps = 2 * SINC_HALF_LENGTH;
+    let mut table = Vec::with_capacity((SINC_OVERSAMPLING + 1) * taps);
+    for phase in 0..=SINC_OVERSAMPLING {
+        let fraction = phase as f64 / SINC_OVERSAMPLING as f64;
+        let kernel_start = table.len();
+        for tap in 0..taps {
+            // Distance between the interpolated position & this tap
+            let t = tap as f64 - (SINC_HALF_LENGTH - 1) as f64 - fraction;
+            let x = t / SINC_HALF_LENGTH as f64;
+            let window = if x.abs() >= 1.0 {
+                0.0
+            } else {
+                0.42 + 0.5 * (std::f64::consts::PI * x).cos()
+                    + 0.08 * (2.0 * std::f64::consts::PI * x).cos()
+            };
+            table.push((cutoff * sinc(cutoff * t) * window) as f32);
+        }
+
+        // Normalize so DC passes with unity gain
+        let sum: f32 = table[kernel_start..].iter().sum();
+        for weight in &mut table[kernel_start..] {
+            *weight /= sum;
+        }
+    }
+    table
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        let x = std::f64::consts::PI * x;
+        x.sin() / x
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_output_lines_up_with_input() {
+        for quality in [
+            ConverterQuality::Fast,
+            ConverterQuality::Balanced,
+            ConverterQuality::Best,
+        ] {
+            let options = SampleRateConverterOptions {
+                quality,
+                num_channels: 1,
+                ..Default::default()
+            };
+            let mut converter = InterpolatingConverter::new(2.0, &options);
+            let mut input = AudioBuffer::empty();
+            input.resize(1, 100);
+            for frame in 0..100 {
+                input.set(0, frame, frame as f32 / 100.0);
+            }
+            let mut output = AudioBuffer::empty();
+            output.resize(1, converter.max_output_frames(100));
+
+            let num_frames = converter.process(&input, &mut output).unwrap();
+            assert!(num_frames > 150, "{:?}", quality);
+            // Output frame 2n is input frame n, away from the start
+            for frame in (40..num_frames).step_by(2) {
+                let expected = (frame / 2) as f32 / 100.0;
+                assert!(
+                    (output.get(0, frame) - expected).abs() < 0.01,
+                    "{:?} frame={} output={} expected={}",
+                    quality,
+                    frame,
+                    output.get(0, frame),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sinc_table_has_unity_gain() {
+        let table = build_sinc_table(0.9);
+        for kernel in table.chunks(2 * SINC_HALF_LENGTH) {
+            let sum: f32 = kernel.iter().sum();
+            assert!((sum - 1.0).abs() < 1e-5);
+        }
+    }
+}
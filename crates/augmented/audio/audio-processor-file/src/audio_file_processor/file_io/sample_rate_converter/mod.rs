@@ -21,15 +21,457 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-pub const BLOCK_SIZE: usize = 1024;
+//! Sample rate conversion with runtime selectable backends.
+//!
+//! [`SampleRateConverter`] converts audio block-by-block. Converters are created with
+//! [`make_converter`], which picks a backend & [`ConverterQuality`] preset from
+//! [`SampleRateConverterOptions`]:
+//!
+//! * [`ConverterBackend::Interpolating`] is implemented in this crate, it's always available and
+//!   never allocates while processing
+//! * [`ConverterBackend::Rubato`] uses [`rubato`], with the `rubato` feature
+//! * [`ConverterBackend::Samplerate`] uses `libsamplerate`, with the `samplerate` feature
+//!
+//! Buffers are pre-allocated on creation, so the interpolating & rubato backends may be used on
+//! the audio-thread. Setting [`SampleRateConverterOptions::max_ratio_change`] enables
+//! [`SampleRateConverter::set_ratio`], which changes the conversion ratio while running for
+//! vari-speed playback.
+//!
+//! ```
+//! use audio_processor_file::file_io::sample_rate_converter::{
+//!     make_converter, SampleRateConverterOptions,
+//! };
+//! use audio_processor_traits::AudioBuffer;
+//!
+//! let mut converter = make_converter(44100, 48000, &SampleRateConverterOptions::default()).unwrap();
+//! let mut input = AudioBuffer::empty();
+//! input.resize(2, 512);
+//! let mut output = AudioBuffer::empty();
+//! output.resize(2, converter.max_output_frames(512));
+//!
+//! // On the audio-thread
+//! let num_frames = converter.process(&input, &mut output).unwrap();
+//! assert!(num_frames <= output.num_samples());
+//! ```
+
+use thiserror::Error;
+
+use audio_processor_traits::AudioBuffer;
 
-#[cfg(all(feature = "rubato", not(feature = "samplerate")))]
-pub use rubato_impl::*;
+pub use interpolating::InterpolatingConverter;
+#[cfg(feature = "rubato")]
+pub use rubato_impl::RubatoConverter;
 #[cfg(feature = "samplerate")]
-pub use samplerate_impl::*;
+pub use samplerate_impl::SamplerateConverter;
 
+mod interpolating;
 #[cfg(feature = "rubato")]
-#[allow(unused)]
-pub mod rubato_impl;
+mod rubato_impl;
 #[cfg(feature = "samplerate")]
 mod samplerate_impl;
+
+/// Block size used when converting files
+pub const BLOCK_SIZE: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum SampleRateConverterError {
+    #[error("Invalid sample rate conversion ratio {0}")]
+    InvalidRatio(f64),
+    #[error("Block of {0} frames is larger than the maximum block size")]
+    BlockTooLarge(usize),
+    #[error("Expected {expected} channels, got {actual}")]
+    ChannelMismatch { expected: usize, actual: usize },
+    #[error("Output buffer can't fit {0} frames")]
+    OutputTooSmall(usize),
+    #[error("Sample rate converter failed: {0}")]
+    BackendError(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConverterBackend {
+    /// Linear, cubic or windowed-sinc interpolation, depending on the quality
+    Interpolating,
+    #[cfg(feature = "rubato")]
+    Rubato,
+    /// `libsamplerate`. Its bindings allocate output buffers, so this isn't real-time safe
+    #[cfg(feature = "samplerate")]
+    Samplerate,
+}
+
+impl Default for ConverterBackend {
+    /// The best backend available with the enabled features
+    fn default() -> Self {
+        #[cfg(feature = "samplerate")]
+        return ConverterBackend::Samplerate;
+        #[cfg(all(feature = "rubato", not(feature = "samplerate")))]
+        return ConverterBackend::Rubato;
+        #[cfg(not(any(feature = "rubato", feature = "samplerate")))]
+        return ConverterBackend::Interpolating;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConverterQuality {
+    /// Lowest CPU usage, audible aliasing. Good for scrubbing & previews
+    Fast,
+    Balanced,
+    #[default]
+    Best,
+}
+
+#[derive(Debug, Clone)]
+pub struct SampleRateConverterOptions {
+    /// Defaults to the best backend available, see [`ConverterBackend::default`]
+    pub backend: ConverterBackend,
+    /// Defaults to [`ConverterQuality::Best`]
+    pub quality: ConverterQuality,
+    /// Defaults to 2
+    pub num_channels: usize,
+    /// Maximum number of frames passed onto each [`SampleRateConverter::process`] call. Defaults
+    /// to [`BLOCK_SIZE`]
+    pub max_block_size: usize,
+    /// How far [`SampleRateConverter::set_ratio`] may move the ratio away from the initial
+    /// ratio, as a factor. `2.0` allows anything from half to double the initial ratio. Defaults
+    /// to `1.0`, which means the ratio is fixed
+    pub max_ratio_change: f64,
+}
+
+impl Default for SampleRateConverterOptions {
+    fn default() -> Self {
+        Self {
+            backend: ConverterBackend::default(),
+            quality: ConverterQuality::default(),
+            num_channels: 2,
+            max_block_size: BLOCK_SIZE,
+            max_ratio_change: 1.0,
+        }
+    }
+}
+
+/// Block-by-block sample rate conversion.
+///
+/// The ratio is the output rate divided by the input rate.
+pub trait SampleRateConverter: Send {
+    fn num_channels(&self) -> usize;
+
+    fn max_block_size(&self) -> usize;
+
+    fn ratio(&self) -> f64;
+
+    /// Change the conversion ratio, for vari-speed playback. The ratio must be within
+    /// [`SampleRateConverterOptions::max_ratio_change`] of the initial ratio.
+    fn set_ratio(&mut self, ratio: f64) -> Result<(), SampleRateConverterError>;
+
+    /// Delay of the output signal, in output frames
+    fn latency(&self) -> usize;
+
+    /// Upper bound of how many frames [`SampleRateConverter::process`] writes for a block of
+    /// `input_frames`, at any allowed ratio
+    fn max_output_frames(&self, input_frames: usize) -> usize;
+
+    /// Convert all frames in `input` onto the start of `output`. Returns the number of frames
+    /// written.
+    ///
+    /// Converters buffer input internally, so the number of output frames varies from block to
+    /// block. `output` must have at least [`SampleRateConverter::max_output_frames`] frames.
+    fn process(
+        &mut self,
+        input: &AudioBuffer<f32>,
+        output: &mut AudioBuffer<f32>,
+    ) -> Result<usize, SampleRateConverterError>;
+
+    /// Clear buffered input, e.g. after seeking. This may allocate.
+    fn reset(&mut self);
+}
+
+pub fn make_converter(
+    input_rate: u32,
+    output_rate: u32,
+    options: &SampleRateConverterOptions,
+) -> Result<Box<dyn SampleRateConverter>, SampleRateConverterError> {
+    let ratio = output_rate as f64 / input_rate as f64;
+    if input_rate == 0 || output_rate == 0 || options.max_ratio_change < 1.0 {
+        return Err(SampleRateConverterError::InvalidRatio(ratio));
+    }
+
+    Ok(match options.backend {
+        ConverterBackend::Interpolating => Box::new(InterpolatingConverter::new(ratio, options)),
+        #[cfg(feature = "rubato")]
+        ConverterBackend::Rubato => {
+            Box::new(RubatoConverter::new(input_rate, output_rate, options)?)
+        }
+        #[cfg(feature = "samplerate")]
+        ConverterBackend::Samplerate => {
+            Box::new(SamplerateConverter::new(input_rate, output_rate, options)?)
+        }
+    })
+}
+
+/// Convert a whole buffer, compensating for the converter's latency. This allocates, so it's
+/// meant for offline use.
+pub fn convert_buffer(
+    converter: &mut dyn SampleRateConverter,
+    input: &AudioBuffer<f32>,
+) -> Result<AudioBuffer<f32>, SampleRateConverterError> {
+    let num_channels = converter.num_channels();
+    let block_size = converter.max_block_size();
+    // Rounding errors shouldn't add a frame
+    let expected_frames = (input.num_samples() as f64 * converter.ratio() - 1e-6)
+        .ceil()
+        .max(0.0) as usize;
+    let latency = converter.latency();
+
+    let mut result: Vec<Vec<f32>> =
+        vec![Vec::with_capacity(expected_frames + latency); num_channels];
+    let mut block = AudioBuffer::empty();
+    let mut output = AudioBuffer::empty();
+    output.resize(num_channels, converter.max_output_frames(block_size));
+
+    let mut cursor = 0;
+    // Silence is fed after the input until the converter's buffers are flushed
+    let mut silent_blocks = 0;
+    while result[0].len() < expected_frames + latency {
+        let num_frames = block_size.min(input.num_samples().saturating_sub(cursor));
+        if num_frames == 0 {
+            silent_blocks += 1;
+            if silent_blocks
+                > 1 + (latency + converter.max_output_frames(0)) / block_size.max(1) + 8
+            {
+                break;
+            }
+            block.resize(num_channels, block_size);
+            for channel in block.channels_mut() {
+                channel.fill(0.0);
+            }
+        } else {
+            block.resize(num_channels, num_frames);
+            for (channel, samples) in block.channels_mut().iter_mut().enumerate() {
+                samples.copy_from_slice(&input.channel(channel)[cursor..cursor + num_frames]);
+            }
+            cursor += num_frames;
+        }
+
+        let frames = converter.process(&block, &mut output)?;
+        for (target, source) in result.iter_mut().zip(output.channels()) {
+            target.extend_from_slice(&source[..frames]);
+        }
+    }
+
+    for channel in &mut result {
+        channel.drain(..latency.min(channel.len()));
+        channel.resize(expected_frames, 0.0);
+    }
+    Ok(AudioBuffer::new(result))
+}
+
+fn validate_buffers(
+    converter: &dyn SampleRateConverter,
+    input: &AudioBuffer<f32>,
+    output: &AudioBuffer<f32>,
+) -> Result<(), SampleRateConverterError> {
+    for num_channels in [input.num_channels(), output.num_channels()] {
+        if num_channels != converter.num_channels() {
+            return Err(SampleRateConverterError::ChannelMismatch {
+                expected: converter.num_channels(),
+                actual: num_channels,
+            });
+        }
+    }
+    if input.num_samples() > converter.max_block_size() {
+        return Err(SampleRateConverterError::BlockTooLarge(input.num_samples()));
+    }
+    let max_output_frames = converter.max_output_frames(input.num_samples());
+    if output.num_samples() < max_output_frames {
+        return Err(SampleRateConverterError::OutputTooSmall(max_output_frames));
+    }
+    Ok(())
+}
+
+/// Checks `ratio` is within `max_ratio_change` of `initial_ratio`
+fn validate_ratio(
+    initial_ratio: f64,
+    ratio: f64,
+    max_ratio_change: f64,
+) -> Result<(), SampleRateConverterError> {
+    let relative = ratio / initial_ratio;
+    // Allow for rounding when setting the initial ratio back
+    let tolerance = 1e-9;
+    if ratio.is_finite()
+        && relative >= 1.0 / max_ratio_change - tolerance
+        && relative <= max_ratio_change + tolerance
+    {
+        Ok(())
+    } else {
+        Err(SampleRateConverterError::InvalidRatio(ratio))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine(num_frames: usize, sample_rate: f32, frequency: f32) -> AudioBuffer<f32> {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, num_frames);
+        for frame in 0..num_frames {
+            let value = (frame as f32 / sample_rate * frequency * std::f32::consts::TAU).sin();
+            buffer.set(0, frame, value);
+            buffer.set(1, frame, value);
+        }
+        buffer
+    }
+
+    fn backends() -> Vec<ConverterBackend> {
+        vec![
+            ConverterBackend::Interpolating,
+            #[cfg(feature = "rubato")]
+            ConverterBackend::Rubato,
+            #[cfg(feature = "samplerate")]
+            ConverterBackend::Samplerate,
+        ]
+    }
+
+    #[test]
+    fn test_convert_buffer_on_every_backend() {
+        for backend in backends() {
+            for quality in [
+                ConverterQuality::Fast,
+                ConverterQuality::Balanced,
+                ConverterQuality::Best,
+            ] {
+                let options = SampleRateConverterOptions {
+                    backend,
+                    quality,
+                    ..Default::default()
+                };
+                let mut converter = make_converter(44100, 48000, &options).unwrap();
+                let input = sine(44100, 44100.0, 440.0);
+                let output = convert_buffer(converter.as_mut(), &input).unwrap();
+                assert_eq!(output.num_samples(), 48000);
+
+                let expected = sine(48000, 48000.0, 440.0);
+                // Skip the start, where converters ramp-up
+                let error = output.channel(0)[1000..47000]
+                    .iter()
+                    .zip(&expected.channel(0)[1000..47000])
+                    .map(|(sample, expected)| (sample - expected).abs())
+                    .fold(0.0, f32::max);
+                assert!(
+                    error < 0.05,
+                    "{:?} {:?} max error {}",
+                    backend,
+                    quality,
+                    error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_accepts_any_block_size() {
+        for backend in backends() {
+            let options = SampleRateConverterOptions {
+                backend,
+                ..Default::default()
+            };
+            let mut converter = make_converter(48000, 44100, &options).unwrap();
+            let mut output = AudioBuffer::empty();
+            output.resize(2, converter.max_output_frames(BLOCK_SIZE));
+
+            let mut total_frames = 0;
+            for block_size in [1, 7, 512, 1024, 300, 1000].iter().cycle().take(100) {
+                let mut input = AudioBuffer::empty();
+                input.resize(2, *block_size);
+                total_frames += converter.process(&input, &mut output).unwrap();
+            }
+            let input_frames: usize = [1, 7, 512, 1024, 300, 1000].iter().sum::<usize>() * 100 / 6;
+            let expected = input_frames as f64 * 44100.0 / 48000.0;
+            // Output lags behind by the converter's buffering
+            assert!(total_frames as f64 <= expected + 1.0, "{:?}", backend);
+            assert!(
+                total_frames as f64 > expected - 2.0 * BLOCK_SIZE as f64,
+                "{:?}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_process_validates_buffers() {
+        let mut converter =
+            make_converter(44100, 48000, &SampleRateConverterOptions::default()).unwrap();
+        let mut input = AudioBuffer::empty();
+        let mut output = AudioBuffer::empty();
+        input.resize(2, BLOCK_SIZE + 1);
+        output.resize(2, converter.max_output_frames(BLOCK_SIZE + 1));
+        assert!(matches!(
+            converter.process(&input, &mut output),
+            Err(SampleRateConverterError::BlockTooLarge(_))
+        ));
+        input.resize(1, 10);
+        assert!(matches!(
+            converter.process(&input, &mut output),
+            Err(SampleRateConverterError::ChannelMismatch { .. })
+        ));
+        input.resize(2, 10);
+        output.resize(2, 1);
+        assert!(matches!(
+            converter.process(&input, &mut output),
+            Err(SampleRateConverterError::OutputTooSmall(_))
+        ));
+    }
+
+    #[test]
+    fn test_variable_ratio() {
+        for backend in backends() {
+            let options = SampleRateConverterOptions {
+                backend,
+                max_ratio_change: 2.0,
+                quality: ConverterQuality::Balanced,
+                ..Default::default()
+            };
+            let mut converter = make_converter(44100, 44100, &options).unwrap();
+            assert!(converter.set_ratio(0.4).is_err());
+            assert!(converter.set_ratio(2.5).is_err());
+
+            let mut input = AudioBuffer::empty();
+            input.resize(2, 256);
+            let mut output = AudioBuffer::empty();
+            output.resize(2, converter.max_output_frames(256));
+
+            for ratio in [2.0, 0.5] {
+                converter.set_ratio(ratio).unwrap();
+                assert_eq!(converter.ratio(), ratio);
+                let mut total_frames = 0;
+                // Let buffered input at the previous ratio out
+                for _ in 0..20 {
+                    converter.process(&input, &mut output).unwrap();
+                }
+                for _ in 0..100 {
+                    total_frames += converter.process(&input, &mut output).unwrap();
+                }
+                let expected = 100.0 * 256.0 * ratio;
+                assert!(
+                    (total_frames as f64 - expected).abs() < 0.02 * expected,
+                    "{:?} ratio={} frames={}",
+                    backend,
+                    ratio,
+                    total_frames
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixed_ratio_converters_reject_ratio_changes() {
+        for backend in backends() {
+            let options = SampleRateConverterOptions {
+                backend,
+                ..Default::default()
+            };
+            let mut converter = make_converter(44100, 48000, &options).unwrap();
+            assert!(converter.set_ratio(48000.0 / 44100.0).is_ok());
+            assert!(converter.set_ratio(1.0).is_err());
+        }
+    }
+}
@@ -21,33 +21,238 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use rubato::Resampler;
+use rubato::{
+    FftFixedIn, InterpolationParameters, InterpolationType, SincFixedIn, VecResampler,
+    WindowFunction,
+};
 
-use super::BLOCK_SIZE;
+use audio_processor_traits::AudioBuffer;
 
-pub type Decoder = rubato::FftFixedIn<f32>;
-pub type DecoderError = rubato::ResampleError;
-pub type DecoderCreateError = rubato::ResamplerConstructionError;
+use super::{
+    validate_buffers, validate_ratio, ConverterQuality, SampleRateConverter,
+    SampleRateConverterError, SampleRateConverterOptions,
+};
 
-pub fn make_decoder(
+const FFT_SUB_CHUNKS: usize = 2;
+
+/// [`rubato`] backed converter.
+///
+/// Fixed ratio conversion at [`ConverterQuality::Best`] uses the FFT resampler, everything else
+/// uses windowed-sinc interpolation, with a longer kernel at higher qualities. Input is collected
+/// into chunks of `max_block_size` frames before being converted.
+pub struct RubatoConverter {
+    resampler: Box<dyn VecResampler<f32>>,
     input_rate: u32,
     output_rate: u32,
-    channels: usize,
-) -> Result<Decoder, DecoderCreateError> {
-    Decoder::new(
-        input_rate as usize,
-        output_rate as usize,
-        BLOCK_SIZE,
-        2,
-        channels,
+    options: SampleRateConverterOptions,
+    initial_ratio: f64,
+    ratio: f64,
+    /// Delay of the resampler in input frames
+    input_latency: usize,
+    /// Most frames the resampler outputs for a chunk
+    max_chunk_output_frames: usize,
+    input_buffer: Vec<Vec<f32>>,
+    output_buffer: Vec<Vec<f32>>,
+}
+
+impl RubatoConverter {
+    pub fn new(
+        input_rate: u32,
+        output_rate: u32,
+        options: &SampleRateConverterOptions,
+    ) -> Result<Self, SampleRateConverterError> {
+        let (resampler, input_latency) = make_resampler(input_rate, output_rate, options)?;
+        let input_buffer = resampler.input_buffer_allocate();
+        let max_chunk_output_frames = if Self::is_fixed_ratio(options) {
+            fft_output_frames_max(input_rate, output_rate, options.max_block_size)
+        } else {
+            resampler.output_frames_max()
+        };
+        let output_buffer = vec![Vec::with_capacity(max_chunk_output_frames); options.num_channels];
+        let ratio = output_rate as f64 / input_rate as f64;
+
+        Ok(Self {
+            resampler,
+            input_rate,
+            output_rate,
+            options: options.clone(),
+            initial_ratio: ratio,
+            ratio,
+            input_latency,
+            max_chunk_output_frames,
+            input_buffer,
+            output_buffer,
+        })
+    }
+
+    fn is_fixed_ratio(options: &SampleRateConverterOptions) -> bool {
+        options.max_ratio_change <= 1.0 && options.quality == ConverterQuality::Best
+    }
+}
+
+fn make_resampler(
+    input_rate: u32,
+    output_rate: u32,
+    options: &SampleRateConverterOptions,
+) -> Result<(Box<dyn VecResampler<f32>>, usize), SampleRateConverterError> {
+    let backend_error = |err: rubato::ResamplerConstructionError| {
+        SampleRateConverterError::BackendError(err.to_string())
+    };
+
+    if RubatoConverter::is_fixed_ratio(options) {
+        let resampler = FftFixedIn::new(
+            input_rate as usize,
+            output_rate as usize,
+            options.max_block_size,
+            FFT_SUB_CHUNKS,
+            options.num_channels,
+        )
+        .map_err(backend_error)?;
+        // Output is delayed by half an FFT
+        let (fft_size_in, _) = fft_sizes(input_rate, output_rate, options.max_block_size);
+        return Ok((Box::new(resampler), fft_size_in / 2));
+    }
+
+    let (sinc_len, oversampling_factor, interpolation) = match options.quality {
+        ConverterQuality::Fast => (64, 64, InterpolationType::Linear),
+        ConverterQuality::Balanced => (128, 128, InterpolationType::Cubic),
+        ConverterQuality::Best => (256, 256, InterpolationType::Cubic),
+    };
+    let parameters = InterpolationParameters {
+        sinc_len,
+        f_cutoff: 0.95,
+        oversampling_factor,
+        interpolation,
+        window: WindowFunction::BlackmanHarris2,
+    };
+    let resampler = SincFixedIn::new(
+        output_rate as f64 / input_rate as f64,
+        options.max_ratio_change.max(1.0),
+        parameters,
+        options.max_block_size,
+        options.num_channels,
+    )
+    .map_err(backend_error)?;
+    // The sinc resampler compensates for its own delay
+    Ok((Box::new(resampler), 0))
+}
+
+/// `FftFixedIn` keeps partial FFT blocks between chunks, so a chunk may output more than
+/// [`VecResampler::output_frames_max`]
+fn fft_output_frames_max(input_rate: u32, output_rate: u32, chunk_size: usize) -> usize {
+    let (fft_size_in, fft_size_out) = fft_sizes(input_rate, output_rate, chunk_size);
+    chunk_size.div_ceil(fft_size_in) * fft_size_out
+}
+
+/// Input & output FFT sizes, this mirrors how `FftFixedIn` picks them
+fn fft_sizes(input_rate: u32, output_rate: u32, chunk_size: usize) -> (usize, usize) {
+    let (input_rate, output_rate) = (input_rate as usize, output_rate as usize);
+    let gcd = gcd(input_rate, output_rate);
+    let fft_chunks = ((chunk_size / FFT_SUB_CHUNKS) as f32 / (input_rate / gcd) as f32).ceil();
+    (
+        fft_chunks as usize * input_rate / gcd,
+        fft_chunks as usize * output_rate / gcd,
     )
 }
 
-pub fn process<T: AsRef<[f32]>>(
-    decoder: &mut Decoder,
-    block: &[T],
-) -> Result<Vec<Vec<f32>>, DecoderError> {
-    decoder.process(block, None)
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl SampleRateConverter for RubatoConverter {
+    fn num_channels(&self) -> usize {
+        self.options.num_channels
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.options.max_block_size
+    }
+
+    fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    fn set_ratio(&mut self, ratio: f64) -> Result<(), SampleRateConverterError> {
+        validate_ratio(self.initial_ratio, ratio, self.options.max_ratio_change)?;
+        if Self::is_fixed_ratio(&self.options) {
+            return Ok(());
+        }
+        self.resampler
+            .set_resample_ratio(ratio)
+            .map_err(|_| SampleRateConverterError::InvalidRatio(ratio))?;
+        self.ratio = ratio;
+        Ok(())
+    }
+
+    fn latency(&self) -> usize {
+        (self.input_latency as f64 * self.ratio).round() as usize
+    }
+
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        let buffered_frames = self.input_buffer.first().map(|b| b.len()).unwrap_or(0);
+        let num_chunks = (buffered_frames + input_frames) / self.resampler.input_frames_next();
+        num_chunks.max(1) * self.max_chunk_output_frames
+    }
+
+    fn process(
+        &mut self,
+        input: &AudioBuffer<f32>,
+        output: &mut AudioBuffer<f32>,
+    ) -> Result<usize, SampleRateConverterError> {
+        validate_buffers(self, input, output)?;
+
+        let chunk_size = self.resampler.input_frames_next();
+        let mut input_cursor = 0;
+        let mut output_frames = 0;
+        while input_cursor < input.num_samples() {
+            let buffered_frames = self.input_buffer[0].len();
+            let num_frames = (chunk_size - buffered_frames).min(input.num_samples() - input_cursor);
+            for (buffer, channel) in self.input_buffer.iter_mut().zip(input.channels()) {
+                buffer.extend_from_slice(&channel[input_cursor..input_cursor + num_frames]);
+            }
+            input_cursor += num_frames;
+
+            if self.input_buffer[0].len() < chunk_size {
+                break;
+            }
+
+            self.resampler
+                .process_into_buffer(&self.input_buffer, &mut self.output_buffer, None)
+                .map_err(|err| SampleRateConverterError::BackendError(err.to_string()))?;
+            for buffer in &mut self.input_buffer {
+                buffer.clear();
+            }
+
+            let chunk_frames = self.output_buffer[0].len();
+            for (channel, buffer) in self.output_buffer.iter().enumerate() {
+                output.channel_mut(channel)[output_frames..output_frames + chunk_frames]
+                    .copy_from_slice(buffer);
+            }
+            output_frames += chunk_frames;
+        }
+
+        Ok(output_frames)
+    }
+
+    fn reset(&mut self) {
+        for buffer in &mut self.input_buffer {
+            buffer.clear();
+        }
+        // rubato resamplers can't be reset, a new one is created instead
+        match make_resampler(self.input_rate, self.output_rate, &self.options) {
+            Ok((resampler, _)) => {
+                self.resampler = resampler;
+                if self.ratio != self.initial_ratio {
+                    let _ = self.resampler.set_resample_ratio(self.ratio);
+                }
+            }
+            Err(err) => log::error!("Failed to reset sample rate converter {}", err),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -56,16 +261,48 @@ mod test {
 
     #[test]
     fn test_pulse() {
-        let mut block1 = vec![];
-        block1.resize(1, [0.0; 1024]);
-        block1[0][0] = 1.0;
-        let mut decoder = make_decoder(44100, 44100, 1).unwrap();
-        let result = process(&mut decoder, &block1).unwrap();
-        let (index, _) = result[0]
+        let options = SampleRateConverterOptions {
+            num_channels: 1,
+            ..Default::default()
+        };
+        let mut converter = RubatoConverter::new(44100, 44100, &options).unwrap();
+        let mut input = AudioBuffer::empty();
+        input.resize(1, 1024);
+        input.set(0, 0, 1.0);
+        let mut output = AudioBuffer::empty();
+        output.resize(1, converter.max_output_frames(1024));
+
+        let num_frames = converter.process(&input, &mut output).unwrap();
+        let (index, _) = output.channel(0)[..num_frames]
             .iter()
             .enumerate()
             .find(|(_, x)| (*x).abs() > 0.2)
             .unwrap();
         assert_eq!(index, 256);
+        assert_eq!(converter.latency(), 256);
+    }
+
+    #[test]
+    fn test_sinc_has_no_latency() {
+        let options = SampleRateConverterOptions {
+            num_channels: 1,
+            quality: ConverterQuality::Balanced,
+            ..Default::default()
+        };
+        let mut converter = RubatoConverter::new(44100, 88200, &options).unwrap();
+        let mut input = AudioBuffer::empty();
+        input.resize(1, 1024);
+        input.set(0, 0, 1.0);
+        let mut output = AudioBuffer::empty();
+        output.resize(1, converter.max_output_frames(1024));
+
+        let num_frames = converter.process(&input, &mut output).unwrap();
+        let (index, _) = output.channel(0)[..num_frames]
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.abs().partial_cmp(&b.abs()).unwrap())
+            .unwrap();
+        assert_eq!(converter.latency(), 0);
+        assert!(index <= 1);
     }
 }
@@ -21,46 +21,124 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-pub type Decoder = samplerate::Samplerate;
-pub type DecoderError = samplerate::Error;
+use samplerate::{ConverterType, Samplerate};
 
-pub fn make_decoder(
+use audio_processor_traits::AudioBuffer;
+
+use super::{
+    validate_buffers, validate_ratio, ConverterQuality, SampleRateConverter,
+    SampleRateConverterError, SampleRateConverterOptions,
+};
+
+/// `libsamplerate` backed converter.
+///
+/// The bindings allocate an output buffer on each call, so this isn't real-time safe. Ratio
+/// changes are rounded to an integer output rate.
+pub struct SamplerateConverter {
+    converter: Samplerate,
     input_rate: u32,
-    output_rate: u32,
-    channels: usize,
-) -> Result<Decoder, DecoderError> {
-    samplerate::Samplerate::new(
-        samplerate::ConverterType::SincBestQuality,
-        input_rate,
-        output_rate,
-        channels,
-    )
+    num_channels: usize,
+    max_block_size: usize,
+    initial_ratio: f64,
+    max_ratio_change: f64,
+    interleaved: Vec<f32>,
 }
 
-pub fn process<T: AsRef<[f32]>>(
-    decoder: &mut Decoder,
-    block: &[T],
-) -> Result<Vec<Vec<f32>>, DecoderError> {
-    let num_channels = block.len();
-    let num_samples = block[0].as_ref().len();
-
-    let mut interleaved_buffer = vec![];
-    interleaved_buffer.resize(num_channels * num_samples, 0.0);
-    for sample in 0..num_samples {
-        #[allow(clippy::needless_range_loop)]
-        for channel in 0..num_channels {
-            let index = sample * num_channels + channel;
-            interleaved_buffer[index] = block[channel].as_ref()[sample];
-        }
+// SAFETY: The `libsamplerate` state is only reachable through this struct & not tied to a thread,
+// so moving it to another thread is fine. It's not `Sync`.
+unsafe impl Send for SamplerateConverter {}
+
+impl SamplerateConverter {
+    pub fn new(
+        input_rate: u32,
+        output_rate: u32,
+        options: &SampleRateConverterOptions,
+    ) -> Result<Self, SampleRateConverterError> {
+        let converter_type = match options.quality {
+            ConverterQuality::Fast => ConverterType::SincFastest,
+            ConverterQuality::Balanced => ConverterType::SincMediumQuality,
+            ConverterQuality::Best => ConverterType::SincBestQuality,
+        };
+        let converter = Samplerate::new(
+            converter_type,
+            input_rate,
+            output_rate,
+            options.num_channels,
+        )
+        .map_err(|err| SampleRateConverterError::BackendError(err.to_string()))?;
+
+        Ok(Self {
+            converter,
+            input_rate,
+            num_channels: options.num_channels,
+            max_block_size: options.max_block_size,
+            initial_ratio: output_rate as f64 / input_rate as f64,
+            max_ratio_change: options.max_ratio_change.max(1.0),
+            interleaved: Vec::with_capacity(options.max_block_size * options.num_channels),
+        })
     }
+}
 
-    let result = decoder.process(&interleaved_buffer)?;
+impl SampleRateConverter for SamplerateConverter {
+    fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.max_block_size
+    }
+
+    fn ratio(&self) -> f64 {
+        self.converter.ratio()
+    }
+
+    fn set_ratio(&mut self, ratio: f64) -> Result<(), SampleRateConverterError> {
+        validate_ratio(self.initial_ratio, ratio, self.max_ratio_change)?;
+        let output_rate = (self.input_rate as f64 * ratio).round() as u32;
+        self.converter.set_to_rate(output_rate);
+        Ok(())
+    }
 
-    let mut deinterleaved_buffer = vec![];
-    deinterleaved_buffer.resize(num_channels, vec![]);
-    for sample in 0..result.len() {
-        let channel = sample % num_channels;
-        deinterleaved_buffer[channel].push(result[sample]);
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn max_output_frames(&self, input_frames: usize) -> usize {
+        let max_ratio = self.initial_ratio * self.max_ratio_change;
+        (input_frames as f64 * max_ratio).ceil() as usize + 1
+    }
+
+    fn process(
+        &mut self,
+        input: &AudioBuffer<f32>,
+        output: &mut AudioBuffer<f32>,
+    ) -> Result<usize, SampleRateConverterError> {
+        validate_buffers(self, input, output)?;
+
+        self.interleaved.clear();
+        for frame in 0..input.num_samples() {
+            for channel in input.channels() {
+                self.interleaved.push(channel[frame]);
+            }
+        }
+
+        let result = self
+            .converter
+            .process(&self.interleaved)
+            .map_err(|err| SampleRateConverterError::BackendError(err.to_string()))?;
+
+        let output_frames = result.len() / self.num_channels;
+        for (frame, samples) in result.chunks_exact(self.num_channels).enumerate() {
+            for (channel, sample) in samples.iter().enumerate() {
+                output.set(channel, frame, *sample);
+            }
+        }
+        Ok(output_frames)
+    }
+
+    fn reset(&mut self) {
+        if let Err(err) = self.converter.reset() {
+            log::error!("Failed to reset sample rate converter {}", err);
+        }
     }
-    Ok(deinterleaved_buffer)
 }
@@ -28,8 +28,12 @@ use symphonia::core::probe::ProbeResult;
 use audio_garbage_collector::{Handle, Shared};
 use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
 use file_io::{AudioFileError, FileContentsStream};
+use streaming::StreamingAudioFile;
+
+pub use streaming::StreamingOptions;
 
 pub mod file_io;
+mod streaming;
 
 pub struct InMemoryAudioFile {
     audio_file: ProbeResult,
@@ -46,7 +50,6 @@ impl InMemoryAudioFile {
 
     /// Eagerly read the file onto memory, do sample rate conversion into the target
     /// AudioProcessorSettings and return a VecAudioBuffer containing the file's contents.
-    #[cfg(feature = "samplerate")]
     pub fn read_into_vec_audio_buffer(
         &mut self,
         settings: &AudioProcessorSettings,
@@ -78,6 +81,27 @@ pub struct AudioFileProcessorHandle {
     audio_file_cursor: AtomicUsize,
     is_playing: AtomicBool,
     should_loop: AtomicBool,
+    seek_generation: AtomicUsize,
+    seek_target: AtomicUsize,
+    /// Loop end of 0 means there are no loop points
+    loop_start: AtomicUsize,
+    loop_end: AtomicUsize,
+    underruns: AtomicUsize,
+}
+
+impl Default for AudioFileProcessorHandle {
+    fn default() -> Self {
+        Self {
+            audio_file_cursor: AtomicUsize::new(0),
+            is_playing: AtomicBool::new(true),
+            should_loop: AtomicBool::new(true),
+            seek_generation: AtomicUsize::new(0),
+            seek_target: AtomicUsize::new(0),
+            loop_start: AtomicUsize::new(0),
+            loop_end: AtomicUsize::new(0),
+            underruns: AtomicUsize::new(0),
+        }
+    }
 }
 
 impl AudioFileProcessorHandle {
@@ -94,7 +118,50 @@ impl AudioFileProcessorHandle {
     /// Stop playback and go back to the start of the file
     pub fn stop(&self) {
         self.is_playing.store(false, Ordering::Relaxed);
-        self.audio_file_cursor.store(0, Ordering::Relaxed);
+        self.seek(0);
+    }
+
+    /// Move the playhead to `position`, in samples at the output sample rate
+    pub fn seek(&self, position: usize) {
+        self.audio_file_cursor.store(position, Ordering::Relaxed);
+        self.seek_target.store(position, Ordering::Relaxed);
+        self.seek_generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Current playhead position, in samples at the output sample rate
+    pub fn position_samples(&self) -> usize {
+        self.audio_file_cursor.load(Ordering::Relaxed)
+    }
+
+    /// Loop between `start` and `end` (exclusive) when looping is on, instead of the whole file
+    pub fn set_loop_points(&self, start: usize, end: usize) {
+        if end <= start {
+            self.clear_loop_points();
+            return;
+        }
+        self.loop_start.store(start, Ordering::Relaxed);
+        self.loop_end.store(end, Ordering::Relaxed);
+    }
+
+    /// Loop the whole file
+    pub fn clear_loop_points(&self) {
+        self.loop_end.store(0, Ordering::Relaxed);
+        self.loop_start.store(0, Ordering::Relaxed);
+    }
+
+    pub fn loop_points(&self) -> Option<(usize, usize)> {
+        let end = self.loop_end.load(Ordering::Relaxed);
+        let start = self.loop_start.load(Ordering::Relaxed);
+        if end > start {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    /// Number of times a streaming processor ran out of decoded audio
+    pub fn underruns(&self) -> usize {
+        self.underruns.load(Ordering::Relaxed)
     }
 
     /// Whether the file is being played back
@@ -111,9 +178,18 @@ impl AudioFileProcessorHandle {
     }
 }
 
+enum AudioFileSource {
+    InMemory(InMemoryAudioFile),
+    Streaming(StreamingAudioFile),
+}
+
 /// An audio processor which plays a file in loop
+///
+/// By default the file is decoded onto memory on `prepare`. Processors created with
+/// [`AudioFileProcessor::new_streaming`] instead read the file from disk on a background thread
+/// while playing.
 pub struct AudioFileProcessor {
-    audio_file_settings: InMemoryAudioFile,
+    source: AudioFileSource,
     audio_settings: AudioProcessorSettings,
     buffer: Vec<Vec<f32>>,
     handle: Shared<AudioFileProcessorHandle>,
@@ -134,26 +210,45 @@ impl AudioFileProcessor {
         audio_file_settings: InMemoryAudioFile,
         audio_settings: AudioProcessorSettings,
     ) -> Self {
-        let handle = Shared::new(
-            gc_handle,
-            AudioFileProcessorHandle {
-                audio_file_cursor: AtomicUsize::new(0),
-                is_playing: AtomicBool::new(true),
-                should_loop: AtomicBool::new(true),
-            },
-        );
+        let handle = Shared::new(gc_handle, AudioFileProcessorHandle::default());
 
         AudioFileProcessor {
-            audio_file_settings,
+            source: AudioFileSource::InMemory(audio_file_settings),
             audio_settings,
             buffer: Vec::new(),
             handle,
         }
     }
 
-    /// Unsafe get buffer for offline rendering
+    /// Create a processor which streams the file from disk rather than loading it onto memory.
+    ///
+    /// Decoding happens on a background thread started on `prepare`, `options` controls how far
+    /// ahead of the playhead it reads. [`AudioFileProcessor::buffer`] will be empty for these
+    /// processors.
+    pub fn new_streaming(
+        gc_handle: &Handle,
+        audio_file_settings: InMemoryAudioFile,
+        audio_settings: AudioProcessorSettings,
+        options: StreamingOptions,
+    ) -> Self {
+        let handle = Shared::new(gc_handle, AudioFileProcessorHandle::default());
+
+        AudioFileProcessor {
+            source: AudioFileSource::Streaming(StreamingAudioFile::new(
+                audio_file_settings.audio_file,
+                options,
+            )),
+            audio_settings,
+            buffer: Vec::new(),
+            handle,
+        }
+    }
+
+    /// Number of samples in the file, at the output sample rate
     pub fn num_samples(&self) -> usize {
-        if self.buffer.is_empty() {
+        if let AudioFileSource::Streaming(streaming) = &self.source {
+            streaming.num_frames()
+        } else if self.buffer.is_empty() {
             0
         } else {
             self.buffer[0].len()
@@ -215,13 +310,24 @@ impl AudioProcessor for AudioFileProcessor {
 
     /// Prepares for playback
     ///
-    /// Note: Currently this will load the audio file on the audio-thread.
-    /// It'd be an interesting exercise to perform this on a background thread.
+    /// Note: Currently this will load the audio file on the audio-thread, unless the processor is
+    /// streaming.
     fn prepare(&mut self, context: &mut AudioContext) {
         let audio_settings = context.settings;
         log::info!("Preparing for audio file playback");
         self.audio_settings = audio_settings;
 
+        let audio_file_settings = match &mut self.source {
+            AudioFileSource::InMemory(audio_file_settings) => audio_file_settings,
+            AudioFileSource::Streaming(streaming) => {
+                log::info!("Starting audio file reader thread");
+                if let Err(err) = streaming.prepare(audio_settings, &self.handle) {
+                    log::error!("Failed to start streaming input file {}", err);
+                }
+                return;
+            }
+        };
+
         self.buffer.clear();
         self.buffer.reserve(self.audio_settings.output_channels());
 
@@ -229,7 +335,7 @@ impl AudioProcessor for AudioFileProcessor {
         log::info!("Reading audio file onto memory");
 
         let mut run = || -> Result<(), file_io::AudioFileError> {
-            let input_stream = FileContentsStream::new(&mut self.audio_file_settings.audio_file)?;
+            let input_stream = FileContentsStream::new(&mut audio_file_settings.audio_file)?;
             let converted_stream = file_io::convert_audio_file_stream_sample_rate(
                 input_stream,
                 audio_settings.sample_rate(),
@@ -268,13 +374,22 @@ impl AudioProcessor for AudioFileProcessor {
     fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
         let is_playing = self.handle.is_playing.load(Ordering::Relaxed);
 
-        if !is_playing {
+        if let AudioFileSource::Streaming(streaming) = &mut self.source {
+            streaming.process(&self.handle, data);
+            return;
+        }
+
+        if !is_playing || self.buffer.is_empty() {
             return;
         }
 
         let should_loop = self.handle.should_loop();
+        let loop_points = self.handle.loop_points();
         let start_cursor = self.handle.audio_file_cursor.load(Ordering::Relaxed);
         let mut audio_file_cursor = start_cursor;
+        if audio_file_cursor >= self.buffer[0].len() {
+            audio_file_cursor = 0;
+        }
 
         for sample_num in 0..data.num_samples() {
             for channel_index in 0..data.num_channels() {
@@ -284,6 +399,11 @@ impl AudioProcessor for AudioFileProcessor {
             }
 
             audio_file_cursor += 1;
+            if let (true, Some((start, end))) = (should_loop, loop_points) {
+                if audio_file_cursor >= end {
+                    audio_file_cursor = start.min(self.buffer[0].len() - 1);
+                }
+            }
             if audio_file_cursor >= self.buffer[0].len() {
                 audio_file_cursor = 0;
 
@@ -306,6 +426,7 @@ impl AudioProcessor for AudioFileProcessor {
 #[cfg(test)]
 mod test {
     use audio_garbage_collector::GarbageCollector;
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
 
     use super::*;
 
@@ -382,4 +503,17 @@ mod test {
             audio_processor_testing_helpers::rms_level(sample_buffer.channel(0)) > f32::EPSILON
         );
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        let (garbage_collector, audio_file_settings) = setup();
+
+        let audio_file_processor = AudioFileProcessor::new(
+            garbage_collector.handle(),
+            audio_file_settings,
+            Default::default(),
+        );
+        audio_file_processor.play();
+        assert_processor_realtime_safe(audio_file_processor, Default::default(), 10);
+    }
 }
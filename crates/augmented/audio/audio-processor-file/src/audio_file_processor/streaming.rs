@@ -0,0 +1,864 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Streaming playback from disk.
+//!
+//! A background reader thread decodes (and sample rate converts) the file ahead of the playhead
+//! into a lock-free ring buffer, which the audio-thread consumes. Memory use is bounded by
+//! [`StreamingOptions::buffer_duration`] rather than the file's length.
+//!
+//! Seeking is a handshake between both threads: the audio-thread requests a seek and stops
+//! reading; the reader seeks and reports how many samples were left in the ring buffer from before
+//! the seek, which the audio-thread then discards. Output is silent until then.
+//!
+//! Loop points are applied by the reader thread, so changing them while playing takes effect after
+//! the audio which is already buffered.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use augmented_streams::ringbuf::{Producer, RingBuffer};
+use augmented_streams::ConsumerActor;
+use symphonia::core::audio::Signal;
+use symphonia::core::codecs::Decoder;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{SeekMode, SeekTo};
+use symphonia::core::probe::ProbeResult;
+
+use audio_garbage_collector::Shared;
+use audio_processor_traits::{AudioBuffer, AudioProcessorSettings};
+
+use super::file_io::sample_rate_converter::{
+    self, SampleRateConverter, SampleRateConverterOptions,
+};
+use super::file_io::{self, AudioFileError};
+use super::AudioFileProcessorHandle;
+
+pub struct StreamingOptions {
+    /// How much audio is decoded ahead of the playhead. Defaults to 2s
+    pub buffer_duration: Duration,
+    /// How long the reader thread sleeps for when the buffer is full. Defaults to 5ms
+    pub poll_interval: Duration,
+}
+
+impl Default for StreamingOptions {
+    fn default() -> Self {
+        Self {
+            buffer_duration: Duration::from_secs(2),
+            poll_interval: Duration::from_millis(5),
+        }
+    }
+}
+
+/// State shared between the audio-thread & reader thread
+#[derive(Default)]
+struct StreamingState {
+    requested_generation: AtomicUsize,
+    acknowledged_generation: AtomicUsize,
+    seek_target: AtomicUsize,
+    stale_samples: AtomicUsize,
+    /// Set by the reader once the end of the file was pushed and it isn't looping
+    finished: AtomicBool,
+    /// Estimated length of the file at the output sample rate
+    num_frames: AtomicUsize,
+    shutdown: AtomicBool,
+}
+
+/// Audio-thread side of streaming playback
+pub(crate) struct StreamingAudioFile {
+    options: StreamingOptions,
+    audio_file: Option<ProbeResult>,
+    reader_thread: Option<JoinHandle<ProbeResult>>,
+    state: Arc<StreamingState>,
+    consumer: Option<ConsumerActor<f32>>,
+    scratch: Vec<f32>,
+    num_channels: usize,
+    handle_seek_generation: usize,
+    generation: usize,
+    is_seeking: bool,
+}
+
+impl StreamingAudioFile {
+    pub(crate) fn new(audio_file: ProbeResult, options: StreamingOptions) -> Self {
+        Self {
+            options,
+            audio_file: Some(audio_file),
+            reader_thread: None,
+            state: Arc::new(StreamingState::default()),
+            consumer: None,
+            scratch: vec![],
+            num_channels: 0,
+            handle_seek_generation: 0,
+            generation: 0,
+            is_seeking: false,
+        }
+    }
+
+    /// Estimated length of the file at the output sample rate
+    pub(crate) fn num_frames(&self) -> usize {
+        self.state.num_frames.load(Ordering::Relaxed)
+    }
+
+    /// Number of frames decoded ahead of the playhead
+    #[cfg(test)]
+    fn buffered_frames(&self) -> usize {
+        if self.is_seeking || self.num_channels == 0 {
+            return 0;
+        }
+        self.consumer
+            .as_ref()
+            .map(|consumer| consumer.len() / self.num_channels)
+            .unwrap_or(0)
+    }
+
+    /// Stops the reader thread, if running, and takes back the file
+    fn stop_reader_thread(&mut self) {
+        self.state.shutdown.store(true, Ordering::Relaxed);
+        if let Some(reader_thread) = self.reader_thread.take() {
+            match reader_thread.join() {
+                Ok(audio_file) => self.audio_file = Some(audio_file),
+                Err(_) => log::error!("Audio file reader thread panicked"),
+            }
+        }
+        self.consumer = None;
+    }
+
+    /// Start a reader thread for these settings. Not real-time safe.
+    pub(crate) fn prepare(
+        &mut self,
+        settings: AudioProcessorSettings,
+        handle: &Shared<AudioFileProcessorHandle>,
+    ) -> Result<(), AudioFileError> {
+        self.stop_reader_thread();
+        let audio_file = self
+            .audio_file
+            .take()
+            .ok_or(AudioFileError::OpenStreamError)?;
+
+        let num_channels = settings.output_channels();
+        let output_rate = settings.sample_rate() as u32;
+        let file = OpenFile::new(audio_file, output_rate)?;
+
+        let capacity = (self.options.buffer_duration.as_secs_f32() * settings.sample_rate())
+            as usize
+            * num_channels;
+        let (producer, consumer) = RingBuffer::new(capacity.max(num_channels)).split();
+
+        self.state = Arc::new(StreamingState::default());
+        self.state
+            .num_frames
+            .store(file.num_frames, Ordering::Relaxed);
+        self.consumer = Some(ConsumerActor::new(consumer));
+        self.scratch = vec![0.0; settings.block_size().max(1) * num_channels];
+        self.num_channels = num_channels;
+        self.generation = 0;
+        self.is_seeking = false;
+        // Start from wherever the handle's cursor is
+        self.handle_seek_generation = handle.seek_generation.load(Ordering::Acquire);
+        self.request_seek(handle.audio_file_cursor.load(Ordering::Relaxed));
+
+        let state = self.state.clone();
+        let handle = handle.clone();
+        let poll_interval = self.options.poll_interval;
+        let reader_thread = std::thread::Builder::new()
+            .name(String::from("audio-file-reader"))
+            .spawn(move || {
+                // The sample rate converter might not be `Send`, so the reader is created here
+                let reader = FileReader::new(file, output_rate, num_channels);
+                run_reader(reader, producer, state, handle, poll_interval)
+            })?;
+        self.reader_thread = Some(reader_thread);
+
+        Ok(())
+    }
+
+    fn request_seek(&mut self, position: usize) {
+        self.generation += 1;
+        self.is_seeking = true;
+        self.state.seek_target.store(position, Ordering::Relaxed);
+        self.state
+            .requested_generation
+            .store(self.generation, Ordering::Release);
+    }
+
+    /// Add the next `data.num_samples()` frames onto `data`. Real-time safe.
+    pub(crate) fn process(
+        &mut self,
+        handle: &AudioFileProcessorHandle,
+        data: &mut AudioBuffer<f32>,
+    ) {
+        let handle_seek_generation = handle.seek_generation.load(Ordering::Acquire);
+        if handle_seek_generation != self.handle_seek_generation {
+            self.handle_seek_generation = handle_seek_generation;
+            self.request_seek(handle.seek_target.load(Ordering::Relaxed));
+        }
+
+        let consumer = match &mut self.consumer {
+            Some(consumer) => consumer,
+            None => return,
+        };
+
+        if self.is_seeking {
+            if self.state.acknowledged_generation.load(Ordering::Acquire) != self.generation {
+                return;
+            }
+            consumer.discard(self.state.stale_samples.load(Ordering::Relaxed));
+            self.is_seeking = false;
+        }
+
+        if !handle.is_playing() {
+            return;
+        }
+
+        let num_channels = self.num_channels;
+        let output_channels = data.num_channels().min(num_channels);
+        let frames_per_chunk = self.scratch.len() / num_channels;
+        let start_position = handle.audio_file_cursor.load(Ordering::Relaxed);
+        let mut position = start_position;
+        let loop_points = handle.loop_points();
+        let num_frames = self.state.num_frames.load(Ordering::Relaxed);
+
+        let mut frame_index = 0;
+        while frame_index < data.num_samples() {
+            let chunk_frames = frames_per_chunk.min(data.num_samples() - frame_index);
+            let wanted = chunk_frames * num_channels;
+            let popped = consumer.pop_slice(&mut self.scratch[..wanted]);
+            let popped_frames = popped / num_channels;
+
+            for frame in 0..popped_frames {
+                for channel in 0..output_channels {
+                    data.channel_mut(channel)[frame_index + frame] +=
+                        self.scratch[frame * num_channels + channel];
+                }
+
+                position += 1;
+                match loop_points {
+                    Some((start, end)) if handle.should_loop() && position >= end => {
+                        position = start
+                    }
+                    _ if handle.should_loop() && num_frames > 0 && position >= num_frames => {
+                        position = loop_points.map(|(start, _)| start).unwrap_or(0)
+                    }
+                    _ => {}
+                }
+            }
+            frame_index += popped_frames;
+
+            if popped < wanted {
+                if self.state.finished.load(Ordering::Acquire) && consumer.is_empty() {
+                    handle.stop();
+                    return;
+                }
+                handle.underruns.fetch_add(1, Ordering::Relaxed);
+                break;
+            }
+        }
+
+        let _ = handle.audio_file_cursor.compare_exchange(
+            start_position,
+            position,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+}
+
+impl Drop for StreamingAudioFile {
+    fn drop(&mut self) {
+        // Don't join, this might be the audio-thread; the reader exits on its next poll
+        self.state.shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+fn run_reader(
+    mut reader: FileReader,
+    mut producer: Producer<f32>,
+    state: Arc<StreamingState>,
+    handle: Shared<AudioFileProcessorHandle>,
+    poll_interval: Duration,
+) -> ProbeResult {
+    let mut acknowledged_generation = 0;
+    let mut is_finished = false;
+
+    while !state.shutdown.load(Ordering::Relaxed) {
+        let requested_generation = state.requested_generation.load(Ordering::Acquire);
+        if requested_generation != acknowledged_generation {
+            // The audio-thread stopped reading before requesting, so this won't change
+            let stale_samples = producer.len();
+            reader.clear_pending();
+            if let Err(err) = reader.seek(state.seek_target.load(Ordering::Relaxed)) {
+                log::error!("Failed to seek audio file {}", err);
+            }
+            is_finished = false;
+            state.finished.store(false, Ordering::Relaxed);
+            state.stale_samples.store(stale_samples, Ordering::Relaxed);
+            state
+                .acknowledged_generation
+                .store(requested_generation, Ordering::Release);
+            acknowledged_generation = requested_generation;
+        }
+
+        if !reader.push_pending(&mut producer) || is_finished {
+            std::thread::sleep(poll_interval);
+            continue;
+        }
+
+        let has_more = reader.decode_next();
+        let should_loop = handle.should_loop();
+        let loop_points = handle.loop_points();
+
+        if let (true, Some((start, end))) = (should_loop, loop_points) {
+            if reader.truncate_at(end) {
+                if let Err(err) = reader.seek(start) {
+                    log::error!("Failed to seek audio file {}", err);
+                }
+                continue;
+            }
+        }
+
+        if !has_more {
+            if should_loop {
+                let start = loop_points.map(|(start, _)| start).unwrap_or(0);
+                if let Err(err) = reader.seek(start) {
+                    log::error!("Failed to seek audio file {}", err);
+                    is_finished = true;
+                }
+            } else {
+                is_finished = true;
+            }
+        }
+
+        if is_finished && reader.push_pending(&mut producer) {
+            state.finished.store(true, Ordering::Release);
+        }
+    }
+
+    reader.into_audio_file()
+}
+
+/// A file with its decoder, before being handed to the reader thread
+struct OpenFile {
+    audio_file: ProbeResult,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    file_rate: u32,
+    /// Estimated length at the output sample rate
+    num_frames: usize,
+}
+
+impl OpenFile {
+    fn new(audio_file: ProbeResult, output_rate: u32) -> Result<Self, AudioFileError> {
+        let track = audio_file
+            .format
+            .default_track()
+            .ok_or(AudioFileError::OpenStreamError)?;
+        let decoder =
+            symphonia::default::get_codecs().make(&track.codec_params, &Default::default())?;
+        let track_id = track.id;
+        let file_rate = track.codec_params.sample_rate.unwrap_or(output_rate);
+        let num_frames = track
+            .codec_params
+            .n_frames
+            .map(|n_frames| (n_frames as f64 * output_rate as f64 / file_rate as f64) as usize)
+            .unwrap_or(0);
+
+        Ok(Self {
+            audio_file,
+            decoder,
+            track_id,
+            file_rate,
+            num_frames,
+        })
+    }
+}
+
+/// Reader-thread side of streaming playback. Decodes & converts the file into interleaved frames
+/// at the output sample rate.
+struct FileReader {
+    audio_file: ProbeResult,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    file_rate: u32,
+    output_rate: u32,
+    num_channels: usize,
+    converter: Option<Box<dyn SampleRateConverter>>,
+    converter_input: AudioBuffer<f32>,
+    converter_output: AudioBuffer<f32>,
+    /// File frames fed to the converter & output frames produced since the last seek
+    converter_frames_in: usize,
+    converter_frames_out: usize,
+    skip_file_frames: usize,
+    skip_output_frames: usize,
+    pending: Vec<f32>,
+    pending_cursor: usize,
+    /// Output position of the frame after the last pending frame
+    position: usize,
+}
+
+impl FileReader {
+    fn new(file: OpenFile, output_rate: u32, num_channels: usize) -> Self {
+        Self {
+            audio_file: file.audio_file,
+            decoder: file.decoder,
+            track_id: file.track_id,
+            file_rate: file.file_rate,
+            output_rate,
+            num_channels,
+            converter: None,
+            converter_input: AudioBuffer::new(
+                (0..num_channels)
+                    .map(|_| Vec::with_capacity(sample_rate_converter::BLOCK_SIZE))
+                    .collect(),
+            ),
+            converter_output: AudioBuffer::empty(),
+            converter_frames_in: 0,
+            converter_frames_out: 0,
+            skip_file_frames: 0,
+            skip_output_frames: 0,
+            pending: vec![],
+            pending_cursor: 0,
+            position: 0,
+        }
+    }
+
+    fn into_audio_file(self) -> ProbeResult {
+        self.audio_file
+    }
+
+    fn clear_pending(&mut self) {
+        self.pending.clear();
+        self.pending_cursor = 0;
+    }
+
+    /// Push as many pending samples as fit. Returns true if all pending samples were pushed.
+    fn push_pending(&mut self, producer: &mut Producer<f32>) -> bool {
+        self.pending_cursor += producer.push_slice(&self.pending[self.pending_cursor..]);
+        if self.pending_cursor < self.pending.len() {
+            return false;
+        }
+        self.clear_pending();
+        true
+    }
+
+    /// Drop pending frames at or after `end`. Returns true if `end` was reached.
+    fn truncate_at(&mut self, end: usize) -> bool {
+        if self.position < end {
+            return false;
+        }
+
+        let pending_frames = (self.pending.len() - self.pending_cursor) / self.num_channels;
+        let excess = (self.position - end).min(pending_frames);
+        self.pending
+            .truncate(self.pending.len() - excess * self.num_channels);
+        self.position -= excess;
+        true
+    }
+
+    /// Seek to a position at the output sample rate. Pending frames are kept.
+    fn seek(&mut self, position: usize) -> Result<(), AudioFileError> {
+        let file_position =
+            (position as f64 * self.file_rate as f64 / self.output_rate as f64).round() as u64;
+        let seeked_to = self.audio_file.format.seek(
+            SeekMode::Accurate,
+            SeekTo::TimeStamp {
+                ts: file_position,
+                track_id: self.track_id,
+            },
+        )?;
+        self.decoder.reset();
+
+        self.skip_file_frames = seeked_to.required_ts.saturating_sub(seeked_to.actual_ts) as usize;
+        self.position = position;
+        self.converter = None;
+        for channel in self.converter_input.channels_mut() {
+            channel.clear();
+        }
+        self.converter_frames_in = 0;
+        self.converter_frames_out = 0;
+        self.skip_output_frames = 0;
+        if self.file_rate != self.output_rate {
+            let options = SampleRateConverterOptions {
+                num_channels: self.num_channels,
+                ..Default::default()
+            };
+            let converter =
+                sample_rate_converter::make_converter(self.file_rate, self.output_rate, &options)
+                    .map_err(|_| AudioFileError::OpenStreamError)?;
+            self.skip_output_frames = converter.latency();
+            self.converter_output.resize(
+                self.num_channels,
+                converter.max_output_frames(sample_rate_converter::BLOCK_SIZE),
+            );
+            self.converter = Some(converter);
+        }
+
+        Ok(())
+    }
+
+    /// Decode the next packet onto the pending buffer. Returns false at the end of the file.
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let packet = match self.audio_file.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err))
+                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    self.flush_converter();
+                    return false;
+                }
+                Err(err) => {
+                    log::error!("Failed to read audio file packet {}", err);
+                    self.flush_converter();
+                    return false;
+                }
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => file_io::convert_audio_buffer_sample_type(decoded),
+                Err(SymphoniaError::DecodeError(err)) => {
+                    log::warn!("Skipping corrupt audio file packet {}", err);
+                    continue;
+                }
+                Err(err) => {
+                    log::error!("Failed to decode audio file {}", err);
+                    return false;
+                }
+            };
+
+            let file_channels = decoded.spec().channels.count();
+            let skip = self.skip_file_frames.min(decoded.frames());
+            self.skip_file_frames -= skip;
+
+            for frame in skip..decoded.frames() {
+                for channel in 0..self.num_channels {
+                    // Mono files are played on every channel
+                    let sample = decoded.chan(channel.min(file_channels - 1))[frame];
+                    match self.converter {
+                        Some(_) => self.converter_input.channels_mut()[channel].push(sample),
+                        None => self.pending.push(sample),
+                    }
+                }
+
+                if self.converter.is_none() {
+                    self.position += 1;
+                } else if self.converter_input.num_samples() == sample_rate_converter::BLOCK_SIZE {
+                    self.convert_block(sample_rate_converter::BLOCK_SIZE);
+                }
+            }
+
+            return true;
+        }
+    }
+
+    /// Convert the input block, of which `file_frames` are from the file rather than padding
+    fn convert_block(&mut self, file_frames: usize) {
+        let converter = match &mut self.converter {
+            Some(converter) => converter,
+            None => return,
+        };
+
+        self.converter_frames_in += file_frames;
+        let output_frames =
+            match converter.process(&self.converter_input, &mut self.converter_output) {
+                Ok(output_frames) => output_frames,
+                Err(err) => {
+                    log::error!("Failed to convert sample rate {}", err);
+                    0
+                }
+            };
+        for channel in self.converter_input.channels_mut() {
+            channel.clear();
+        }
+
+        // At the end of the file, the output is cut at the expected length
+        let expected_frames = (self.converter_frames_in as f64 * self.output_rate as f64
+            / self.file_rate as f64)
+            .ceil() as usize;
+        for frame in 0..output_frames {
+            if self.skip_output_frames > 0 {
+                self.skip_output_frames -= 1;
+                continue;
+            }
+            if self.converter_frames_out >= expected_frames {
+                break;
+            }
+
+            for channel in self.converter_output.channels() {
+                self.pending.push(channel[frame]);
+            }
+            self.converter_frames_out += 1;
+            self.position += 1;
+        }
+    }
+
+    /// Convert the remaining partial block & the converter's latency
+    fn flush_converter(&mut self) {
+        let latency = match &self.converter {
+            Some(converter) => converter.latency(),
+            None => return,
+        };
+
+        let mut file_frames = self.converter_input.num_samples();
+        loop {
+            let frames_out = self.converter_frames_out;
+            for channel in self.converter_input.channels_mut() {
+                channel.resize(sample_rate_converter::BLOCK_SIZE, 0.0);
+            }
+            self.convert_block(file_frames);
+            file_frames = 0;
+            if latency == 0 || self.converter_frames_out == frames_out {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use std::time::Instant;
+
+    use audio_garbage_collector::make_shared;
+    use hound::WavSpec;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    /// Stereo file where frame `i` has the value `(i % 1000) / 1000`
+    fn create_ramp_file(tempdir: &TempDir, sample_rate: u32, num_frames: usize) -> PathBuf {
+        let file_path = tempdir.path().join("ramp.wav");
+        let mut writer = hound::WavWriter::create(
+            &file_path,
+            WavSpec {
+                channels: 2,
+                sample_rate,
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            },
+        )
+        .unwrap();
+        for frame in 0..num_frames {
+            let value = ramp_value(frame);
+            writer.write_sample(value).unwrap();
+            writer.write_sample(-value).unwrap();
+        }
+        writer.finalize().unwrap();
+        file_path
+    }
+
+    fn ramp_value(frame: usize) -> f32 {
+        (frame % 1000) as f32 / 1000.0
+    }
+
+    fn setup(
+        path: &Path,
+        options: StreamingOptions,
+        should_loop: bool,
+    ) -> (StreamingAudioFile, Shared<AudioFileProcessorHandle>) {
+        let audio_file = file_io::default_read_audio_file(path.to_str().unwrap()).unwrap();
+        let mut streaming = StreamingAudioFile::new(audio_file, options);
+        let handle = make_shared(AudioFileProcessorHandle::default());
+        handle.set_should_loop(should_loop);
+        streaming
+            .prepare(AudioProcessorSettings::new(44100.0, 2, 2, 512), &handle)
+            .unwrap();
+        (streaming, handle)
+    }
+
+    /// Process empty buffers until the reader has caught up
+    fn wait_for_buffer(
+        streaming: &mut StreamingAudioFile,
+        handle: &AudioFileProcessorHandle,
+        frames: usize,
+    ) {
+        let start = Instant::now();
+        let is_playing = handle.is_playing();
+        handle.pause();
+        // Process at least once so pending seeks are picked-up
+        loop {
+            let mut buffer = AudioBuffer::empty();
+            buffer.resize(2, 1);
+            streaming.process(handle, &mut buffer);
+            if streaming.buffered_frames() >= frames {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed-out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        if is_playing {
+            handle.play();
+        }
+    }
+
+    fn render(
+        streaming: &mut StreamingAudioFile,
+        handle: &AudioFileProcessorHandle,
+        frames: usize,
+    ) -> AudioBuffer<f32> {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, frames);
+        streaming.process(handle, &mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn test_streams_file_contents() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 44100, 10000);
+        let (mut streaming, handle) = setup(&path, StreamingOptions::default(), true);
+        assert_eq!(streaming.num_frames(), 10000);
+
+        wait_for_buffer(&mut streaming, &handle, 2048);
+        let buffer = render(&mut streaming, &handle, 2048);
+        for frame in 0..2048 {
+            assert_eq!(buffer.channel(0)[frame], ramp_value(frame));
+            assert_eq!(buffer.channel(1)[frame], -ramp_value(frame));
+        }
+        assert_eq!(handle.position_samples(), 2048);
+        assert_eq!(handle.underruns(), 0);
+    }
+
+    #[test]
+    fn test_seek() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 44100, 10000);
+        let (mut streaming, handle) = setup(&path, StreamingOptions::default(), true);
+        wait_for_buffer(&mut streaming, &handle, 512);
+        render(&mut streaming, &handle, 512);
+
+        handle.seek(5123);
+        wait_for_buffer(&mut streaming, &handle, 512);
+        let buffer = render(&mut streaming, &handle, 512);
+        for frame in 0..512 {
+            assert_eq!(buffer.channel(0)[frame], ramp_value(5123 + frame));
+        }
+        assert_eq!(handle.position_samples(), 5123 + 512);
+    }
+
+    #[test]
+    fn test_loop_points() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 44100, 10000);
+        let (mut streaming, handle) = setup(&path, StreamingOptions::default(), true);
+        handle.set_loop_points(100, 300);
+        handle.seek(100);
+        wait_for_buffer(&mut streaming, &handle, 1000);
+
+        let buffer = render(&mut streaming, &handle, 1000);
+        for frame in 0..1000 {
+            assert_eq!(buffer.channel(0)[frame], ramp_value(100 + frame % 200));
+        }
+        assert_eq!(handle.position_samples(), 100);
+    }
+
+    #[test]
+    fn test_loops_whole_file() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 44100, 1500);
+        let (mut streaming, handle) = setup(&path, StreamingOptions::default(), true);
+        wait_for_buffer(&mut streaming, &handle, 4000);
+
+        let buffer = render(&mut streaming, &handle, 4000);
+        for frame in 0..4000 {
+            assert_eq!(buffer.channel(0)[frame], ramp_value(frame % 1500));
+        }
+    }
+
+    #[test]
+    fn test_stops_at_the_end_when_not_looping() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 44100, 1500);
+        let (mut streaming, handle) = setup(&path, StreamingOptions::default(), false);
+        wait_for_buffer(&mut streaming, &handle, 1500);
+        // Wait for the reader to notice the end of the file
+        std::thread::sleep(Duration::from_millis(50));
+
+        let buffer = render(&mut streaming, &handle, 2000);
+        assert_eq!(buffer.channel(0)[1499], ramp_value(1499));
+        assert!(buffer.channel(0)[1500..].iter().all(|s| *s == 0.0));
+        assert!(!handle.is_playing());
+        assert_eq!(handle.position_samples(), 0);
+        assert_eq!(handle.underruns(), 0);
+    }
+
+    #[test]
+    fn test_underruns_are_counted() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 44100, 10000);
+        let (mut streaming, handle) = setup(
+            &path,
+            StreamingOptions {
+                buffer_duration: Duration::from_millis(5),
+                ..StreamingOptions::default()
+            },
+            true,
+        );
+        wait_for_buffer(&mut streaming, &handle, 100);
+
+        // The buffer holds ~220 frames
+        render(&mut streaming, &handle, 512);
+        assert_eq!(handle.underruns(), 1);
+    }
+
+    #[test]
+    fn test_converts_sample_rate() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 22050, 22050);
+        let (mut streaming, handle) = setup(&path, StreamingOptions::default(), false);
+        assert_eq!(streaming.num_frames(), 44100);
+        wait_for_buffer(&mut streaming, &handle, 40000);
+        std::thread::sleep(Duration::from_millis(50));
+
+        let buffer = render(&mut streaming, &handle, 50000);
+        let last_frame = buffer
+            .channel(0)
+            .iter()
+            .rposition(|sample| *sample != 0.0)
+            .unwrap();
+        assert!((last_frame as i32 - 44100).abs() < 10, "{}", last_frame);
+        // Frame 2400 at 44.1kHz is frame 1200 of the file
+        assert!((buffer.channel(0)[2400] - ramp_value(1200)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_can_be_prepared_again() {
+        let tempdir = TempDir::new("streaming").unwrap();
+        let path = create_ramp_file(&tempdir, 44100, 10000);
+        let (mut streaming, handle) = setup(&path, StreamingOptions::default(), true);
+        wait_for_buffer(&mut streaming, &handle, 512);
+        render(&mut streaming, &handle, 512);
+
+        streaming
+            .prepare(AudioProcessorSettings::new(44100.0, 2, 2, 512), &handle)
+            .unwrap();
+        wait_for_buffer(&mut streaming, &handle, 512);
+        let buffer = render(&mut streaming, &handle, 512);
+        assert_eq!(buffer.channel(0)[0], ramp_value(512));
+    }
+}
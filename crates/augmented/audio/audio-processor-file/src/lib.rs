@@ -23,17 +23,40 @@
 
 //! [`audio_processor_traits::AudioProcessor`] implementations for audio file playback & writing.
 //!
-//! Currently two processors are provided:
+//! Currently three processors are provided:
 //!
 //! * [`AudioFileProcessor`] is an input file processor, its `prepare` method will *load the whole
 //!   file onto memory*. Both `wav` and `mp3` are supported via [`symphonia`]
-//!   - If streaming is a requirement this won't work
-//! * [`OutputAudioFileProcessor`] writes `wav` files
+//!   - If streaming is a requirement, [`AudioFileProcessor::new_streaming`] will instead read the
+//!     file from disk on a background thread, see [`StreamingOptions`]
+//! * [`OutputAudioFileProcessor`] writes `wav` & `flac` files on the calling thread, for offline
+//!   rendering
+//! * [`AudioFileRecorder`] records takes from the audio-thread, files are written on a background
+//!   thread
+//!
+//! Both can write 16/24/32-bit integer or float samples, with optional dithering, and embed
+//! [`RecordingMetadata`] as BWF/iXML chunks or FLAC Vorbis comments.
+//!
+//! [`file_io::read_audio_file_metadata`] reads duration, channel layout, bit depth, tags, and WAV
+//! markers, loop points & ACID tempo. [`file_io::write_wav_markers`] writes markers & loops back.
+//!
+//! Sample rate conversion goes through [`file_io::sample_rate_converter::SampleRateConverter`],
+//! which may also be used directly for real-time or vari-speed conversion.
+//!
+//! [`peaks`] builds multi-resolution waveform overviews on a background thread, cached onto
+//! sidecar `.peaks` files.
 
 pub use audio_file_processor::{
-    file_io, AudioFileProcessor, AudioFileProcessorHandle, InMemoryAudioFile,
+    file_io, AudioFileProcessor, AudioFileProcessorHandle, InMemoryAudioFile, StreamingOptions,
+};
+pub use output_file_processor::{
+    AudioFileRecorder, AudioFileRecorderHandle, DitherMode, OutputAudioFileProcessor,
+    OutputFileError, OutputFileFormat, OutputFileSettings, OutputSampleFormat, RecorderOptions,
+    RecorderState, RecordingMetadata,
 };
-pub use output_file_processor::{OutputAudioFileProcessor, OutputFileSettings};
 
 mod audio_file_processor;
 mod output_file_processor;
+pub mod peaks;
+#[cfg(test)]
+mod test_allocator;
@@ -0,0 +1,137 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+/// How samples are dithered when quantizing to an integer sample format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DitherMode {
+    /// Round to the nearest value, quantization error will correlate with the signal
+    None,
+    /// Add triangular probability density noise of 2 LSB peak-to-peak before rounding
+    #[default]
+    Triangular,
+    /// Triangular dither with first-order error feedback, which moves the noise towards high
+    /// frequencies where it's less audible
+    NoiseShaped,
+}
+
+/// Quantizes a single channel. Keeps the noise generator & error feedback state.
+pub(crate) struct Ditherer {
+    mode: DitherMode,
+    rng_state: u32,
+    error: f64,
+}
+
+impl Ditherer {
+    pub(crate) fn new(mode: DitherMode, seed: u32) -> Self {
+        Self {
+            mode,
+            // xorshift state must be non-zero
+            rng_state: seed.wrapping_mul(0x9E37_79B9) | 1,
+            error: 0.0,
+        }
+    }
+
+    /// Quantize a sample in the `-1.0..1.0` range into a signed `bits` integer
+    pub(crate) fn quantize(&mut self, sample: f32, bits: u32) -> i32 {
+        let scale = (1u64 << (bits - 1)) as f64;
+        let min = -scale;
+        let max = scale - 1.0;
+        let value = sample as f64 * scale;
+
+        let quantized = match self.mode {
+            DitherMode::None => value.round(),
+            DitherMode::Triangular => (value + self.triangular_noise()).round(),
+            DitherMode::NoiseShaped => {
+                let target = value - self.error;
+                let quantized = (target + self.triangular_noise()).round().clamp(min, max);
+                // Clipping would otherwise feed back into the next samples indefinitely
+                self.error = (quantized - target).clamp(-1.5, 1.5);
+                quantized
+            }
+        };
+
+        quantized.clamp(min, max) as i32
+    }
+
+    /// Sum of two uniform values, in LSB
+    fn triangular_noise(&mut self) -> f64 {
+        self.uniform() + self.uniform() - 1.0
+    }
+
+    fn uniform(&mut self) -> f64 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+        x as f64 / u32::MAX as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_no_dither_rounds() {
+        let mut ditherer = Ditherer::new(DitherMode::None, 0);
+        assert_eq!(ditherer.quantize(0.5, 16), 16384);
+        assert_eq!(ditherer.quantize(1.0, 16), 32767);
+        assert_eq!(ditherer.quantize(-1.0, 16), -32768);
+        assert_eq!(ditherer.quantize(0.3 / 32768.0, 16), 0);
+    }
+
+    #[test]
+    fn test_triangular_dither_preserves_low_level_signals() {
+        let mut ditherer = Ditherer::new(DitherMode::Triangular, 0);
+        let sample = 0.3 / 32768.0;
+        let num_samples = 100_000;
+        let sum: i64 = (0..num_samples)
+            .map(|_| ditherer.quantize(sample, 16) as i64)
+            .sum();
+        let mean = sum as f64 / num_samples as f64;
+        assert!((mean - 0.3).abs() < 0.02, "mean={}", mean);
+    }
+
+    #[test]
+    fn test_noise_shaping_keeps_low_frequency_error_bounded() {
+        // With first-order error feedback the accumulated error telescopes, so the error's DC
+        // component is bounded instead of growing like a random walk.
+        let mut shaped = Ditherer::new(DitherMode::NoiseShaped, 0);
+        let mut triangular = Ditherer::new(DitherMode::Triangular, 0);
+        let mut shaped_error = 0.0;
+        let mut triangular_error = 0.0;
+        let mut max_shaped_error: f64 = 0.0;
+        let mut max_triangular_error: f64 = 0.0;
+        for i in 0..100_000 {
+            let sample = (i as f32 * 0.001).sin() * 0.5;
+            let value = sample as f64 * 32768.0;
+            shaped_error += shaped.quantize(sample, 16) as f64 - value;
+            triangular_error += triangular.quantize(sample, 16) as f64 - value;
+            max_shaped_error = max_shaped_error.max(shaped_error.abs());
+            max_triangular_error = max_triangular_error.max(triangular_error.abs());
+        }
+        assert!(max_shaped_error <= 1.5, "{}", max_shaped_error);
+        assert!(max_triangular_error > 10.0, "{}", max_triangular_error);
+    }
+}
@@ -0,0 +1,595 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Minimal FLAC encoder.
+//!
+//! Blocks are encoded with whichever of the constant, verbatim or fixed polynomial predictor
+//! sub-frames is smallest, and residuals are Rice coded with a searched partition order. This
+//! compresses worse than libFLAC's LPC encoder but is simple and fast enough to run while
+//! recording.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_PARTITION_ORDER: u32 = 8;
+/// Rice parameters are written with 5 bits (RICE2), 31 is reserved for escapes
+const MAX_RICE_PARAMETER: u32 = 30;
+
+pub(crate) struct FlacWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    num_channels: usize,
+    bits_per_sample: u32,
+    has_comments: bool,
+    block: Vec<Vec<i32>>,
+    channel: usize,
+    frame_number: u64,
+    total_samples: u64,
+    min_frame_size: usize,
+    max_frame_size: usize,
+    streaminfo_position: u64,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// Writes the stream header. `comments` are written as a `VORBIS_COMMENT` block
+    pub(crate) fn new(
+        mut writer: W,
+        sample_rate: u32,
+        num_channels: usize,
+        bits_per_sample: u32,
+        comments: &[(String, String)],
+    ) -> io::Result<Self> {
+        if !(1..=8).contains(&num_channels) || !(4..=24).contains(&bits_per_sample) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC supports 1-8 channels and 4-24 bits per sample",
+            ));
+        }
+
+        writer.write_all(b"fLaC")?;
+        let streaminfo_position = writer.stream_position()?;
+        let mut flac_writer = Self {
+            writer,
+            sample_rate,
+            num_channels,
+            bits_per_sample,
+            has_comments: !comments.is_empty(),
+            block: vec![Vec::with_capacity(BLOCK_SIZE); num_channels],
+            channel: 0,
+            frame_number: 0,
+            total_samples: 0,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            streaminfo_position,
+        };
+        flac_writer.write_streaminfo()?;
+        if flac_writer.has_comments {
+            flac_writer.write_vorbis_comment(comments)?;
+        }
+
+        Ok(flac_writer)
+    }
+
+    /// Write the next interleaved sample
+    pub(crate) fn write_sample(&mut self, sample: i32) -> io::Result<()> {
+        self.block[self.channel].push(sample);
+        self.channel += 1;
+        if self.channel == self.num_channels {
+            self.channel = 0;
+            if self.block[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the last partial block & fill-in the stream length
+    pub(crate) fn finalize(mut self) -> io::Result<()> {
+        // Drop samples of an incomplete frame
+        let num_frames = self.block.iter().map(|channel| channel.len()).min();
+        for channel in &mut self.block {
+            channel.truncate(num_frames.unwrap_or(0));
+        }
+        if num_frames.unwrap_or(0) > 0 {
+            self.write_frame()?;
+        }
+
+        let end = self.writer.stream_position()?;
+        self.writer
+            .seek(SeekFrom::Start(self.streaminfo_position))?;
+        self.write_streaminfo()?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()
+    }
+
+    fn write_streaminfo(&mut self) -> io::Result<()> {
+        let mut bits = BitWriter::default();
+        bits.write(if self.has_comments { 0 } else { 1 }, 1);
+        bits.write(0, 7);
+        bits.write(34, 24);
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(self.min_frame_size as u64, 24);
+        bits.write(self.max_frame_size as u64, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(self.num_channels as u64 - 1, 3);
+        bits.write(self.bits_per_sample as u64 - 1, 5);
+        bits.write(self.total_samples >> 32, 4);
+        bits.write(self.total_samples, 32);
+        // MD5 signature, zero means it wasn't computed
+        for _ in 0..4 {
+            bits.write(0, 32);
+        }
+        self.writer.write_all(&bits.into_bytes())
+    }
+
+    fn write_vorbis_comment(&mut self, comments: &[(String, String)]) -> io::Result<()> {
+        let vendor = b"augmented-audio";
+        let mut body = vec![];
+        body.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        body.extend_from_slice(vendor);
+        body.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for (key, value) in comments {
+            let comment = format!("{}={}", key, value);
+            body.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            body.extend_from_slice(comment.as_bytes());
+        }
+
+        let mut header = BitWriter::default();
+        header.write(1, 1);
+        header.write(4, 7);
+        header.write(body.len() as u64, 24);
+        self.writer.write_all(&header.into_bytes())?;
+        self.writer.write_all(&body)
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let block_size = self.block[0].len();
+        let mut bits = BitWriter::default();
+
+        // Sync code & fixed block size strategy
+        bits.write(0b11_1111_1111_1110, 14);
+        bits.write(0, 2);
+        // Block size is written as 16 bits after the frame number
+        bits.write(0b0111, 4);
+        bits.write(sample_rate_code(self.sample_rate), 4);
+        // Independent channels
+        bits.write(self.num_channels as u64 - 1, 4);
+        bits.write(sample_size_code(self.bits_per_sample), 3);
+        bits.write(0, 1);
+        write_utf8_number(&mut bits, self.frame_number);
+        bits.write(block_size as u64 - 1, 16);
+        let header_crc = crc8(bits.bytes());
+        bits.write(header_crc as u64, 8);
+
+        for channel in &self.block {
+            write_subframe(&mut bits, channel, self.bits_per_sample);
+        }
+
+        bits.align();
+        let frame_crc = crc16(bits.bytes());
+        bits.write(frame_crc as u64, 16);
+
+        let frame = bits.into_bytes();
+        self.writer.write_all(&frame)?;
+
+        self.min_frame_size = if self.frame_number == 0 {
+            frame.len()
+        } else {
+            self.min_frame_size.min(frame.len())
+        };
+        self.max_frame_size = self.max_frame_size.max(frame.len());
+        self.frame_number += 1;
+        self.total_samples += block_size as u64;
+        for channel in &mut self.block {
+            channel.clear();
+        }
+
+        Ok(())
+    }
+}
+
+fn sample_rate_code(sample_rate: u32) -> u64 {
+    match sample_rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        8000 => 0b0100,
+        16000 => 0b0101,
+        22050 => 0b0110,
+        24000 => 0b0111,
+        32000 => 0b1000,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        // Read from STREAMINFO
+        _ => 0b0000,
+    }
+}
+
+fn sample_size_code(bits_per_sample: u32) -> u64 {
+    match bits_per_sample {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        // Read from STREAMINFO
+        _ => 0b000,
+    }
+}
+
+/// Frame numbers are written with the UTF-8 variable length encoding, extended to 36 bits
+fn write_utf8_number(bits: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        bits.write(value, 8);
+        return;
+    }
+
+    let num_bytes = match value {
+        0..=0x7FF => 2,
+        0x800..=0xFFFF => 3,
+        0x1_0000..=0x1F_FFFF => 4,
+        0x20_0000..=0x3FF_FFFF => 5,
+        0x400_0000..=0x7FFF_FFFF => 6,
+        _ => 7,
+    };
+    let continuation_bits = 6 * (num_bytes - 1);
+    let prefix = (0xFF00u64 >> num_bytes) & 0xFF;
+    bits.write(prefix | (value >> continuation_bits), 8);
+    for byte in (0..num_bytes - 1).rev() {
+        bits.write(0x80 | ((value >> (6 * byte)) & 0x3F), 8);
+    }
+}
+
+fn write_subframe(bits: &mut BitWriter, samples: &[i32], bits_per_sample: u32) {
+    if samples.iter().all(|sample| *sample == samples[0]) {
+        bits.write(0b0000_0000, 8);
+        bits.write_signed(samples[0] as i64, bits_per_sample);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let residuals = fixed_residuals(samples, order);
+            let coding = RiceCoding::find(&residuals, samples.len(), order);
+            let size = order as u64 * bits_per_sample as u64 + coding.size;
+            (order, residuals, coding, size)
+        })
+        .min_by_key(|(_, _, _, size)| *size);
+
+    match best {
+        Some((order, residuals, coding, size)) if size < verbatim_bits => {
+            bits.write(0b0001_0000 | ((order as u64) << 1), 8);
+            for sample in &samples[..order] {
+                bits.write_signed(*sample as i64, bits_per_sample);
+            }
+            coding.write(bits, &residuals);
+        }
+        _ => {
+            bits.write(0b0000_0010, 8);
+            for sample in samples {
+                bits.write_signed(*sample as i64, bits_per_sample);
+            }
+        }
+    }
+}
+
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let x = |offset: usize| samples[i - offset] as i64;
+            match order {
+                0 => x(0),
+                1 => x(0) - x(1),
+                2 => x(0) - 2 * x(1) + x(2),
+                3 => x(0) - 3 * x(1) + 3 * x(2) - x(3),
+                _ => x(0) - 4 * x(1) + 6 * x(2) - 4 * x(3) + x(4),
+            }
+        })
+        .collect()
+}
+
+fn zigzag(residual: i64) -> u64 {
+    ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+/// Partitioned Rice coding of a sub-frame's residuals
+struct RiceCoding {
+    block_size: usize,
+    predictor_order: usize,
+    partition_order: u32,
+    parameters: Vec<u32>,
+    size: u64,
+}
+
+impl RiceCoding {
+    fn find(residuals: &[i64], block_size: usize, predictor_order: usize) -> Self {
+        let mut best: Option<RiceCoding> = None;
+        for partition_order in 0..=MAX_PARTITION_ORDER {
+            let num_partitions = 1 << partition_order;
+            if block_size.trailing_zeros() < partition_order
+                || block_size / num_partitions <= predictor_order
+            {
+                break;
+            }
+
+            let mut parameters = Vec::with_capacity(num_partitions);
+            // Coding method & partition order
+            let mut size = 2 + 4;
+            for partition in partitions(residuals, block_size, predictor_order, partition_order) {
+                let (parameter, partition_size) = best_rice_parameter(partition);
+                parameters.push(parameter);
+                size += 5 + partition_size;
+            }
+
+            if best.as_ref().map(|best| size < best.size).unwrap_or(true) {
+                best = Some(RiceCoding {
+                    block_size,
+                    predictor_order,
+                    partition_order,
+                    parameters,
+                    size,
+                });
+            }
+        }
+
+        best.expect("Partition order 0 is always valid")
+    }
+
+    fn write(&self, bits: &mut BitWriter, residuals: &[i64]) {
+        // RICE2 coding method (5 bit parameters)
+        bits.write(0b01, 2);
+        bits.write(self.partition_order as u64, 4);
+        let partitions = partitions(
+            residuals,
+            self.block_size,
+            self.predictor_order,
+            self.partition_order,
+        );
+        for (partition, parameter) in partitions.zip(&self.parameters) {
+            bits.write(*parameter as u64, 5);
+            for residual in partition {
+                let value = zigzag(*residual);
+                bits.write_unary(value >> parameter);
+                bits.write(value, *parameter);
+            }
+        }
+    }
+}
+
+/// The first partition is shorter by the predictor order, since warm-up samples aren't coded
+fn partitions(
+    residuals: &[i64],
+    block_size: usize,
+    predictor_order: usize,
+    partition_order: u32,
+) -> impl Iterator<Item = &[i64]> {
+    let partition_size = block_size >> partition_order;
+    let num_partitions = 1 << partition_order;
+    (0..num_partitions).map(move |partition| {
+        let start = (partition * partition_size).saturating_sub(predictor_order);
+        let end = (partition + 1) * partition_size - predictor_order;
+        &residuals[start..end]
+    })
+}
+
+/// Returns the Rice parameter with the smallest encoding & its size in bits
+fn best_rice_parameter(partition: &[i64]) -> (u32, u64) {
+    (0..=MAX_RICE_PARAMETER)
+        .map(|parameter| {
+            let size = partition
+                .iter()
+                .map(|residual| (zigzag(*residual) >> parameter) + 1 + parameter as u64)
+                .sum();
+            (parameter, size)
+        })
+        .min_by_key(|(_, size)| *size)
+        .unwrap_or((0, 0))
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    num_bits: u32,
+}
+
+impl BitWriter {
+    /// Write the low `num_bits` of `value`, most significant bit first
+    fn write(&mut self, value: u64, num_bits: u32) {
+        debug_assert!(num_bits <= 32);
+        if num_bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << num_bits) | (value & ((1 << num_bits) - 1));
+        self.num_bits += num_bits;
+        while self.num_bits >= 8 {
+            self.num_bits -= 8;
+            self.bytes.push((self.accumulator >> self.num_bits) as u8);
+        }
+        self.accumulator &= (1 << self.num_bits) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, num_bits: u32) {
+        self.write(value as u64, num_bits);
+    }
+
+    /// Write `value` zeros followed by a one
+    fn write_unary(&mut self, mut value: u64) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+
+    /// Pad with zeros up to the next byte
+    fn align(&mut self) {
+        if self.num_bits > 0 {
+            self.write(0, 8 - self.num_bits);
+        }
+    }
+
+    /// Complete bytes written so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+/// CRC-8 with polynomial `x^8 + x^2 + x + 1`
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 with polynomial `x^16 + x^15 + x^2 + 1`
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    use symphonia::core::audio::Signal;
+    use tempdir::TempDir;
+
+    use crate::file_io;
+
+    use super::*;
+
+    /// Encode `channels` & decode them back with symphonia. `read_file_contents` only supports
+    /// stereo files
+    fn round_trip(channels: &[Vec<i32>], bits_per_sample: u32) -> Vec<Vec<i32>> {
+        let tempdir = TempDir::new("flac").unwrap();
+        let path = tempdir.path().join("test.flac");
+        let file = BufWriter::new(File::create(&path).unwrap());
+        let mut writer = FlacWriter::new(
+            file,
+            44100,
+            channels.len(),
+            bits_per_sample,
+            &[(String::from("BPM"), String::from("120"))],
+        )
+        .unwrap();
+        for frame in 0..channels[0].len() {
+            for channel in channels {
+                writer.write_sample(channel[frame]).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+
+        let mut audio_file = file_io::default_read_audio_file(path.to_str().unwrap()).unwrap();
+        let track = audio_file.format.default_track().unwrap();
+        assert_eq!(track.codec_params.n_frames, Some(channels[0].len() as u64));
+        let contents = file_io::read_file_contents(&mut audio_file).unwrap();
+        let scale = (1 << (bits_per_sample - 1)) as f32;
+        (0..contents.spec().channels.count())
+            .map(|channel| {
+                contents
+                    .chan(channel)
+                    .iter()
+                    .map(|sample| (sample * scale).round() as i32)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_16_bits() {
+        let left: Vec<i32> = (0..10000)
+            .map(|i| ((i as f32 * 0.01).sin() * 20000.0) as i32)
+            .collect();
+        let right: Vec<i32> = (0..10000).map(|i| (i * 7919 % 65536) - 32768).collect();
+        let channels = vec![left, right];
+        assert_eq!(round_trip(&channels, 16), channels);
+    }
+
+    #[test]
+    fn test_round_trip_24_bits() {
+        let channel: Vec<i32> = (0..5000)
+            .map(|i| ((i as f32 * 0.003).sin() * 8_000_000.0) as i32)
+            .collect();
+        let channels = vec![channel.clone(), channel];
+        assert_eq!(round_trip(&channels, 24), channels);
+    }
+
+    #[test]
+    fn test_round_trip_constant_and_short_blocks() {
+        let mut channel = vec![0; BLOCK_SIZE];
+        channel.extend([1, -1, 2]);
+        let channels = vec![channel, vec![5; BLOCK_SIZE + 3]];
+        assert_eq!(round_trip(&channels, 16), channels);
+    }
+
+    #[test]
+    fn test_fixed_predictor_compresses_smooth_signals() {
+        let samples: Vec<i32> = (0..BLOCK_SIZE as i32).map(|i| i * 3).collect();
+        let mut bits = BitWriter::default();
+        write_subframe(&mut bits, &samples, 16);
+        assert!(bits.into_bytes().len() < BLOCK_SIZE * 2 / 4);
+    }
+
+    #[test]
+    fn test_utf8_number() {
+        let encode = |value| {
+            let mut bits = BitWriter::default();
+            write_utf8_number(&mut bits, value);
+            bits.into_bytes()
+        };
+        assert_eq!(encode(0x24), vec![0x24]);
+        // Same as "¢" & "€" in UTF-8
+        assert_eq!(encode(0xA2), vec![0xC2, 0xA2]);
+        assert_eq!(encode(0x20AC), vec![0xE2, 0x82, 0xAC]);
+    }
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+    }
+}
@@ -0,0 +1,258 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Broadcast WAVE (`bext`) & `iXML` chunks, and the equivalent FLAC Vorbis comments.
+
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Local, Timelike};
+
+use crate::file_io::riff;
+use crate::file_io::{AcidInfo, AudioFileLoop, AudioFileMarker};
+
+/// Metadata written into recorded files
+#[derive(Debug, Clone)]
+pub struct RecordingMetadata {
+    /// Free text description. Defaults to an empty string
+    pub description: String,
+    /// Name of the application or person which recorded the file. Defaults to "augmented-audio"
+    pub originator: String,
+    /// When the recording started. Defaults to now
+    pub origination_time: DateTime<Local>,
+    /// Position of the first sample, in samples since midnight. Defaults to the origination time
+    pub time_reference: Option<u64>,
+    /// Tempo of the recording in BPM, if known. Defaults to `None`
+    pub tempo: Option<f32>,
+    /// Markers written as `cue ` points. Only supported on WAV files. Defaults to none
+    pub markers: Vec<AudioFileMarker>,
+    /// Loop points written on a `smpl` chunk. Only supported on WAV files. Defaults to none
+    pub loops: Vec<AudioFileLoop>,
+}
+
+impl Default for RecordingMetadata {
+    fn default() -> Self {
+        Self {
+            description: String::new(),
+            originator: String::from("augmented-audio"),
+            origination_time: Local::now(),
+            time_reference: None,
+            tempo: None,
+            markers: vec![],
+            loops: vec![],
+        }
+    }
+}
+
+impl RecordingMetadata {
+    pub fn time_reference(&self, sample_rate: u32) -> u64 {
+        self.time_reference.unwrap_or_else(|| {
+            let time = self.origination_time.time();
+            let seconds = time.num_seconds_from_midnight() as f64
+                + time.nanosecond() as f64 / 1_000_000_000.0;
+            (seconds * sample_rate as f64) as u64
+        })
+    }
+
+    /// Vorbis comments used for FLAC files
+    pub(crate) fn vorbis_comments(&self, sample_rate: u32) -> Vec<(String, String)> {
+        let mut comments = vec![
+            (
+                String::from("DATE"),
+                self.origination_time.format("%Y-%m-%d").to_string(),
+            ),
+            (String::from("ENCODED_BY"), self.originator.clone()),
+            (
+                String::from("TIME_REFERENCE"),
+                self.time_reference(sample_rate).to_string(),
+            ),
+        ];
+        if !self.description.is_empty() {
+            comments.push((String::from("DESCRIPTION"), self.description.clone()));
+        }
+        if let Some(tempo) = self.tempo {
+            comments.push((String::from("BPM"), format!("{:.2}", tempo)));
+        }
+        comments
+    }
+
+    /// Contents of a version 1 `bext` chunk
+    fn bext_chunk(&self, sample_rate: u32) -> Vec<u8> {
+        let mut chunk = Vec::with_capacity(602);
+        write_fixed_string(&mut chunk, &self.description, 256);
+        write_fixed_string(&mut chunk, &self.originator, 32);
+        // Originator reference
+        write_fixed_string(&mut chunk, "", 32);
+        write_fixed_string(
+            &mut chunk,
+            &self.origination_time.format("%Y-%m-%d").to_string(),
+            10,
+        );
+        write_fixed_string(
+            &mut chunk,
+            &self.origination_time.format("%H:%M:%S").to_string(),
+            8,
+        );
+        let time_reference = self.time_reference(sample_rate);
+        chunk.extend_from_slice(&(time_reference as u32).to_le_bytes());
+        chunk.extend_from_slice(&((time_reference >> 32) as u32).to_le_bytes());
+        chunk.extend_from_slice(&1u16.to_le_bytes());
+        // UMID & reserved
+        chunk.resize(chunk.len() + 64 + 190, 0);
+        chunk
+    }
+
+    /// Contents of an `iXML` chunk. There's no standard tempo field, so it goes into `USER`
+    fn ixml_chunk(&self, sample_rate: u32) -> Vec<u8> {
+        let time_reference = self.time_reference(sample_rate);
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<BWFXML>\n");
+        xml += "<IXML_VERSION>1.61</IXML_VERSION>\n";
+        xml += &format!("<NOTE>{}</NOTE>\n", escape_xml(&self.description));
+        xml += "<SPEED>\n";
+        xml += &format!("<FILE_SAMPLE_RATE>{}</FILE_SAMPLE_RATE>\n", sample_rate);
+        xml += &format!(
+            "<TIMESTAMP_SAMPLE_RATE>{}</TIMESTAMP_SAMPLE_RATE>\n",
+            sample_rate
+        );
+        xml += &format!(
+            "<TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_HI>{}</TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_HI>\n",
+            time_reference >> 32
+        );
+        xml += &format!(
+            "<TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_LO>{}</TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_LO>\n",
+            time_reference & 0xFFFF_FFFF
+        );
+        xml += "</SPEED>\n";
+        if let Some(tempo) = self.tempo {
+            xml += &format!("<USER>TEMPO={:.2}</USER>\n", tempo);
+        }
+        xml += "</BWFXML>\n";
+        xml.into_bytes()
+    }
+}
+
+/// Append `bext`, `iXML` and, if set, `acid`, `cue ` & `smpl` chunks to a finalized WAV file
+pub(crate) fn append_wav_chunks(
+    path: &Path,
+    metadata: &RecordingMetadata,
+    sample_rate: u32,
+    num_frames: u64,
+) -> io::Result<()> {
+    let mut chunks = vec![
+        (*b"bext", metadata.bext_chunk(sample_rate)),
+        (*b"iXML", metadata.ixml_chunk(sample_rate)),
+    ];
+    if let Some(tempo) = metadata.tempo {
+        let num_beats = num_frames as f32 / sample_rate as f32 * tempo / 60.0;
+        let acid = AcidInfo {
+            tempo,
+            root_note: None,
+            num_beats: num_beats.round() as u32,
+            meter: (4, 4),
+            one_shot: false,
+        };
+        chunks.push((*b"acid", riff::acid_chunk(&acid)));
+    }
+    if !metadata.markers.is_empty() {
+        chunks.push((*b"cue ", riff::cue_chunk(&metadata.markers)));
+    }
+    if let Some(adtl) = riff::adtl_chunk(&metadata.markers) {
+        chunks.push((*b"LIST", adtl));
+    }
+    if !metadata.loops.is_empty() {
+        chunks.push((*b"smpl", riff::smpl_chunk(&metadata.loops, sample_rate, 60)));
+    }
+    riff::append_chunks(path, &chunks, &[])
+}
+
+/// Writes `value` truncated or zero padded to `len` bytes
+fn write_fixed_string(output: &mut Vec<u8>, value: &str, len: usize) {
+    let bytes = value.as_bytes();
+    let bytes = &bytes[..bytes.len().min(len)];
+    output.extend_from_slice(bytes);
+    output.resize(output.len() + len - bytes.len(), 0);
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn metadata() -> RecordingMetadata {
+        RecordingMetadata {
+            description: String::from("Take <1>"),
+            origination_time: Local.with_ymd_and_hms(2022, 3, 4, 1, 0, 0).unwrap(),
+            tempo: Some(120.0),
+            ..RecordingMetadata::default()
+        }
+    }
+
+    #[test]
+    fn test_time_reference_defaults_to_origination_time() {
+        assert_eq!(metadata().time_reference(48000), 3600 * 48000);
+        let metadata = RecordingMetadata {
+            time_reference: Some(10),
+            ..metadata()
+        };
+        assert_eq!(metadata.time_reference(48000), 10);
+    }
+
+    #[test]
+    fn test_bext_chunk() {
+        let chunk = metadata().bext_chunk(48000);
+        assert_eq!(chunk.len(), 602);
+        assert_eq!(&chunk[0..8], b"Take <1>");
+        assert_eq!(&chunk[256..271], b"augmented-audio");
+        assert_eq!(&chunk[320..330], b"2022-03-04");
+        assert_eq!(&chunk[330..338], b"01:00:00");
+        let time_reference = u32::from_le_bytes(chunk[338..342].try_into().unwrap());
+        assert_eq!(time_reference, 3600 * 48000);
+        assert_eq!(u16::from_le_bytes(chunk[346..348].try_into().unwrap()), 1);
+    }
+
+    #[test]
+    fn test_ixml_chunk() {
+        let chunk = String::from_utf8(metadata().ixml_chunk(48000)).unwrap();
+        assert!(chunk.contains("<NOTE>Take &lt;1&gt;</NOTE>"));
+        assert!(chunk.contains("<USER>TEMPO=120.00</USER>"));
+        assert!(chunk.contains(
+            "<TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_LO>172800000</TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_LO>"
+        ));
+    }
+
+    #[test]
+    fn test_vorbis_comments() {
+        let comments = metadata().vorbis_comments(48000);
+        assert!(comments.contains(&(String::from("BPM"), String::from("120.00"))));
+        assert!(comments.contains(&(String::from("DATE"), String::from("2022-03-04"))));
+    }
+}
@@ -0,0 +1,379 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Writing audio files.
+//!
+//! [`OutputAudioFileProcessor`] writes on the calling thread, for offline rendering.
+//! [`AudioFileRecorder`] hands blocks over to a writer thread, so it's safe to use on the
+//! audio-thread.
+
+use std::io;
+
+use thiserror::Error;
+
+use audio_processor_traits::{AudioBuffer, AudioProcessorSettings};
+
+pub use dither::DitherMode;
+pub use metadata::RecordingMetadata;
+pub use recorder::{AudioFileRecorder, AudioFileRecorderHandle, RecorderOptions, RecorderState};
+
+use writer::AudioFileWriter;
+
+mod dither;
+mod flac;
+mod metadata;
+mod recorder;
+mod writer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFileFormat {
+    #[default]
+    Wav,
+    /// Only supports 16 and 24-bit integer samples
+    Flac,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputSampleFormat {
+    Int16,
+    Int24,
+    Int32,
+    #[default]
+    Float32,
+}
+
+impl OutputSampleFormat {
+    pub fn bits_per_sample(&self) -> u32 {
+        match self {
+            OutputSampleFormat::Int16 => 16,
+            OutputSampleFormat::Int24 => 24,
+            OutputSampleFormat::Int32 | OutputSampleFormat::Float32 => 32,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum OutputFileError {
+    #[error("Failed to write WAV file")]
+    WavError(#[from] hound::Error),
+    #[error("Failed to write output file")]
+    IoError(#[from] io::Error),
+    #[error("{0:?} files don't support {1:?} samples")]
+    UnsupportedSampleFormat(OutputFileFormat, OutputSampleFormat),
+    #[error("Output file isn't open, `prepare` must be called first")]
+    NotPrepared,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputFileSettings {
+    audio_file_path: String,
+    /// Defaults to FLAC for paths ending in `.flac` and WAV otherwise
+    pub format: OutputFileFormat,
+    /// Defaults to 24-bit for FLAC and 32-bit float for WAV
+    pub sample_format: OutputSampleFormat,
+    /// Dithering used when writing integer samples. Defaults to [`DitherMode::Triangular`]
+    pub dither: DitherMode,
+    /// Written as BWF & iXML chunks into WAV files and Vorbis comments into FLAC files. Defaults
+    /// to `None`
+    pub metadata: Option<RecordingMetadata>,
+}
+
+impl OutputFileSettings {
+    pub fn new(audio_file_path: &str) -> Self {
+        let is_flac = audio_file_path.to_lowercase().ends_with(".flac");
+        Self {
+            audio_file_path: audio_file_path.to_string(),
+            format: if is_flac {
+                OutputFileFormat::Flac
+            } else {
+                OutputFileFormat::Wav
+            },
+            sample_format: if is_flac {
+                OutputSampleFormat::Int24
+            } else {
+                OutputSampleFormat::Float32
+            },
+            dither: DitherMode::default(),
+            metadata: None,
+        }
+    }
+
+    pub fn audio_file_path(&self) -> &str {
+        &self.audio_file_path
+    }
+}
+
+/// Writes buffers into a file on the calling thread. This isn't real-time safe, use
+/// [`AudioFileRecorder`] for recording on the audio-thread.
+///
+/// The file is finalized on [`OutputAudioFileProcessor::finalize`] or when the processor is
+/// dropped.
+pub struct OutputAudioFileProcessor {
+    audio_settings: AudioProcessorSettings,
+    output_file_settings: OutputFileSettings,
+    writer: Option<AudioFileWriter>,
+    prepare_error: Option<OutputFileError>,
+}
+
+impl OutputAudioFileProcessor {
+    pub fn from_path(audio_settings: AudioProcessorSettings, audio_file_path: &str) -> Self {
+        let output_file_settings = OutputFileSettings::new(audio_file_path);
+        Self::new(audio_settings, output_file_settings)
+    }
+
+    pub fn new(
+        audio_settings: AudioProcessorSettings,
+        output_file_settings: OutputFileSettings,
+    ) -> Self {
+        OutputAudioFileProcessor {
+            audio_settings,
+            output_file_settings,
+            writer: None,
+            prepare_error: None,
+        }
+    }
+}
+
+impl OutputAudioFileProcessor {
+    pub fn prepare(&mut self, settings: AudioProcessorSettings) {
+        self.audio_settings = settings;
+        let sample_rate = settings.sample_rate() as u32;
+        log::info!(
+            "{:?} file will be written with sample rate: {}",
+            self.output_file_settings.format,
+            sample_rate
+        );
+        self.finalize_writer();
+        self.prepare_error = None;
+        match AudioFileWriter::create(
+            &self.output_file_settings,
+            sample_rate,
+            settings.output_channels(),
+        ) {
+            Ok(writer) => self.writer = Some(writer),
+            Err(err) => {
+                log::error!("Failed to create output file {}", err);
+                self.prepare_error = Some(err);
+            }
+        }
+    }
+
+    /// Write a buffer into the file. If the file couldn't be created on `prepare`, the first call
+    /// returns that error and further calls return [`OutputFileError::NotPrepared`]
+    pub fn process(&mut self, data: &mut AudioBuffer<f32>) -> Result<(), OutputFileError> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.write_buffer(data),
+            None => Err(self
+                .prepare_error
+                .take()
+                .unwrap_or(OutputFileError::NotPrepared)),
+        }
+    }
+
+    /// Complete the file's headers. Further calls to `process` will fail until `prepare` is called
+    pub fn finalize(&mut self) -> Result<(), OutputFileError> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize(),
+            None => Ok(()),
+        }
+    }
+
+    fn finalize_writer(&mut self) {
+        if let Err(err) = self.finalize() {
+            log::error!("Failed to finalize output file {}", err);
+        }
+    }
+}
+
+impl Drop for OutputAudioFileProcessor {
+    fn drop(&mut self) {
+        self.finalize_writer();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use symphonia::core::audio::Signal;
+    use tempdir::TempDir;
+
+    use crate::file_io;
+
+    use super::*;
+
+    fn sine_buffer(num_samples: usize) -> AudioBuffer<f32> {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, num_samples);
+        for sample in 0..num_samples {
+            let value = (sample as f32 * 0.01).sin() * 0.5;
+            buffer.set(0, sample, value);
+            buffer.set(1, sample, -value);
+        }
+        buffer
+    }
+
+    fn write_file(settings: OutputFileSettings, buffer: &mut AudioBuffer<f32>) {
+        let mut processor = OutputAudioFileProcessor::new(Default::default(), settings);
+        processor.prepare(AudioProcessorSettings::new(48000.0, 2, 2, 512));
+        processor.process(buffer).unwrap();
+        processor.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_writes_integer_wav_files() {
+        let tempdir = TempDir::new("output_file_processor").unwrap();
+        let mut buffer = sine_buffer(1000);
+
+        for (sample_format, tolerance) in [
+            (OutputSampleFormat::Int16, 2.0 / 32768.0),
+            (OutputSampleFormat::Int24, 1e-6),
+            (OutputSampleFormat::Int32, 1e-6),
+            (OutputSampleFormat::Float32, 0.0),
+        ] {
+            let path = tempdir.path().join(format!("{:?}.wav", sample_format));
+            let mut settings = OutputFileSettings::new(path.to_str().unwrap());
+            settings.sample_format = sample_format;
+            write_file(settings, &mut buffer);
+
+            let reader = hound::WavReader::open(&path).unwrap();
+            let spec = reader.spec();
+            assert_eq!(spec.bits_per_sample as u32, sample_format.bits_per_sample());
+            assert_eq!(spec.sample_rate, 48000);
+            assert_eq!(spec.channels, 2);
+            assert_eq!(reader.duration(), 1000);
+
+            let mut audio_file = file_io::default_read_audio_file(path.to_str().unwrap()).unwrap();
+            let contents = file_io::read_file_contents(&mut audio_file).unwrap();
+            for (sample, expected) in contents.chan(1).iter().zip(buffer.channel(1)) {
+                assert!((sample - expected).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_writes_flac_files() {
+        let tempdir = TempDir::new("output_file_processor").unwrap();
+        let path = tempdir.path().join("output.flac");
+        let mut settings = OutputFileSettings::new(path.to_str().unwrap());
+        assert_eq!(settings.format, OutputFileFormat::Flac);
+        settings.metadata = Some(RecordingMetadata {
+            tempo: Some(128.0),
+            ..RecordingMetadata::default()
+        });
+        let mut buffer = sine_buffer(10000);
+        write_file(settings, &mut buffer);
+
+        let mut audio_file = file_io::default_read_audio_file(path.to_str().unwrap()).unwrap();
+        let contents = file_io::read_file_contents(&mut audio_file).unwrap();
+        assert_eq!(contents.frames(), 10000);
+        for (sample, expected) in contents.chan(0).iter().zip(buffer.channel(0)) {
+            assert!((sample - expected).abs() <= 2.0 / 8_388_608.0);
+        }
+
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.windows(10).any(|window| window == b"BPM=128.00"));
+    }
+
+    #[test]
+    fn test_flac_rejects_float_samples() {
+        let tempdir = TempDir::new("output_file_processor").unwrap();
+        let path = tempdir.path().join("output.flac");
+        let mut settings = OutputFileSettings::new(path.to_str().unwrap());
+        settings.sample_format = OutputSampleFormat::Float32;
+        let mut processor = OutputAudioFileProcessor::new(Default::default(), settings);
+        processor.prepare(AudioProcessorSettings::default());
+        assert!(matches!(
+            processor.process(&mut sine_buffer(10)),
+            Err(OutputFileError::UnsupportedSampleFormat(
+                OutputFileFormat::Flac,
+                OutputSampleFormat::Float32
+            ))
+        ));
+        assert!(matches!(
+            processor.process(&mut sine_buffer(10)),
+            Err(OutputFileError::NotPrepared)
+        ));
+    }
+
+    #[test]
+    fn test_writes_broadcast_wave_chunks() {
+        let tempdir = TempDir::new("output_file_processor").unwrap();
+        let path = tempdir.path().join("output.wav");
+        let mut settings = OutputFileSettings::new(path.to_str().unwrap());
+        settings.sample_format = OutputSampleFormat::Int24;
+        settings.metadata = Some(RecordingMetadata {
+            description: String::from("Live take"),
+            time_reference: Some(1234),
+            tempo: Some(90.0),
+            ..RecordingMetadata::default()
+        });
+        // Odd number of 24-bit mono samples makes an odd-sized data chunk
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, 11);
+        let mut processor = OutputAudioFileProcessor::new(Default::default(), settings);
+        processor.prepare(AudioProcessorSettings::new(48000.0, 1, 1, 512));
+        processor.process(&mut buffer).unwrap();
+        processor.finalize().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let riff_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        assert_eq!(riff_size, bytes.len() - 8);
+
+        let mut chunks = vec![];
+        let mut offset = 12;
+        while offset < bytes.len() {
+            let id = String::from_utf8_lossy(&bytes[offset..offset + 4]).to_string();
+            let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap());
+            chunks.push((id, offset + 8, size as usize));
+            offset += 8 + size as usize + size as usize % 2;
+        }
+        assert_eq!(offset, bytes.len());
+
+        let (_, bext_offset, _) = chunks.iter().find(|(id, _, _)| id == "bext").unwrap();
+        assert_eq!(&bytes[*bext_offset..*bext_offset + 9], b"Live take");
+        let time_reference = &bytes[bext_offset + 338..bext_offset + 342];
+        assert_eq!(u32::from_le_bytes(time_reference.try_into().unwrap()), 1234);
+
+        let (_, ixml_offset, ixml_size) = chunks.iter().find(|(id, _, _)| id == "iXML").unwrap();
+        let ixml = String::from_utf8_lossy(&bytes[*ixml_offset..ixml_offset + ixml_size]);
+        assert!(ixml.contains("TEMPO=90.00"));
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 11);
+    }
+
+    #[test]
+    fn test_file_is_finalized_on_drop() {
+        let tempdir = TempDir::new("output_file_processor").unwrap();
+        let path = tempdir.path().join("output.wav");
+        {
+            let mut processor =
+                OutputAudioFileProcessor::from_path(Default::default(), path.to_str().unwrap());
+            processor.prepare(AudioProcessorSettings::default());
+            processor.process(&mut sine_buffer(100)).unwrap();
+        }
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 100);
+    }
+}
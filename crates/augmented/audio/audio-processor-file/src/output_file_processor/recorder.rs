@@ -0,0 +1,540 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Real-time safe recording.
+//!
+//! The audio-thread interleaves blocks into a lock-free ring buffer, which a writer thread drains
+//! into the file. Opening, encoding and finalizing files all happen on the writer thread.
+//!
+//! Each take goes through `Idle -> Opening -> Recording -> Stopping -> Idle`. While stopping, the
+//! audio-thread acknowledges it won't push more samples, so the writer can drain everything that
+//! was recorded before finalizing the file.
+
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use augmented_streams::ringbuf::{Consumer, Producer, RingBuffer};
+
+use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+
+use super::writer::AudioFileWriter;
+use super::{OutputFileError, OutputFileSettings};
+
+const IDLE: u8 = 0;
+const OPENING: u8 = 1;
+const RECORDING: u8 = 2;
+/// Stop was requested
+const STOPPING: u8 = 3;
+/// The audio-thread acknowledged the stop request
+const STOPPED: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecorderState {
+    /// Ready to start a take
+    Idle,
+    /// The file is being created, recording will start on the next block
+    Opening,
+    Recording,
+    /// The file is being finalized
+    Stopping,
+}
+
+pub struct RecorderOptions {
+    /// How much audio can be waiting to be written before samples are dropped. Defaults to 2s
+    pub buffer_duration: Duration,
+    /// How long the writer thread sleeps for when there's nothing to write. Defaults to 5ms
+    pub poll_interval: Duration,
+}
+
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        Self {
+            buffer_duration: Duration::from_secs(2),
+            poll_interval: Duration::from_millis(5),
+        }
+    }
+}
+
+pub struct AudioFileRecorderHandle {
+    state: AtomicU8,
+    next_take: Mutex<Option<OutputFileSettings>>,
+    last_error: Mutex<Option<OutputFileError>>,
+    recorded_frames: AtomicUsize,
+    dropped_frames: AtomicUsize,
+}
+
+impl Default for AudioFileRecorderHandle {
+    fn default() -> Self {
+        Self {
+            state: AtomicU8::new(IDLE),
+            next_take: Mutex::new(None),
+            last_error: Mutex::new(None),
+            recorded_frames: AtomicUsize::new(0),
+            dropped_frames: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl AudioFileRecorderHandle {
+    /// Start recording a take into a new file. Returns false if a take is in progress.
+    ///
+    /// Not real-time safe.
+    pub fn start(&self, settings: OutputFileSettings) -> bool {
+        let mut next_take = self.next_take.lock().unwrap();
+        *next_take = Some(settings);
+        if self
+            .state
+            .compare_exchange(IDLE, OPENING, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            *next_take = None;
+            return false;
+        }
+        true
+    }
+
+    /// Stop recording. The file is finalized on the writer thread once the audio-thread has
+    /// processed another block, wait for [`RecorderState::Idle`] before reading it.
+    pub fn stop(&self) {
+        let _ =
+            self.state
+                .compare_exchange(RECORDING, STOPPING, Ordering::AcqRel, Ordering::Relaxed);
+        // If the file is being created, the writer thread will finalize it empty
+        let _ = self
+            .state
+            .compare_exchange(OPENING, STOPPED, Ordering::AcqRel, Ordering::Relaxed);
+    }
+
+    pub fn state(&self) -> RecorderState {
+        match self.state.load(Ordering::Acquire) {
+            IDLE => RecorderState::Idle,
+            OPENING => RecorderState::Opening,
+            RECORDING => RecorderState::Recording,
+            _ => RecorderState::Stopping,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state() == RecorderState::Recording
+    }
+
+    /// Frames recorded on the current or last take
+    pub fn recorded_frames(&self) -> usize {
+        self.recorded_frames.load(Ordering::Relaxed)
+    }
+
+    /// Frames which were dropped on the current or last take because the writer thread couldn't
+    /// keep up
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// The last error creating, writing or finalizing a file
+    pub fn take_error(&self) -> Option<OutputFileError> {
+        self.last_error.lock().unwrap().take()
+    }
+
+    fn set_error(&self, error: OutputFileError) {
+        log::error!("Failed to record audio file {}", error);
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+}
+
+/// Records its input into audio files, passing it through unchanged. Control takes with the
+/// [`AudioFileRecorderHandle`].
+pub struct AudioFileRecorder {
+    options: RecorderOptions,
+    handle: Shared<AudioFileRecorderHandle>,
+    producer: Option<Producer<f32>>,
+    scratch: Vec<f32>,
+    num_channels: usize,
+    writer_thread: Option<JoinHandle<()>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl Default for AudioFileRecorder {
+    fn default() -> Self {
+        Self::new(RecorderOptions::default())
+    }
+}
+
+impl AudioFileRecorder {
+    pub fn new(options: RecorderOptions) -> Self {
+        Self {
+            options,
+            handle: make_shared(AudioFileRecorderHandle::default()),
+            producer: None,
+            scratch: vec![],
+            num_channels: 0,
+            writer_thread: None,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn handle(&self) -> &Shared<AudioFileRecorderHandle> {
+        &self.handle
+    }
+
+    /// Stops the writer thread, if running. Takes in progress are finalized.
+    fn stop_writer_thread(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(writer_thread) = self.writer_thread.take() {
+            if writer_thread.join().is_err() {
+                log::error!("Audio file writer thread panicked");
+            }
+        }
+        self.producer = None;
+    }
+}
+
+impl AudioProcessor for AudioFileRecorder {
+    type SampleType = f32;
+
+    /// Starts the writer thread. Not real-time safe.
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.stop_writer_thread();
+
+        let settings = context.settings;
+        let num_channels = settings.output_channels();
+        let capacity = (self.options.buffer_duration.as_secs_f32() * settings.sample_rate())
+            as usize
+            * num_channels;
+        let (producer, consumer) = RingBuffer::new(capacity.max(num_channels)).split();
+        self.producer = Some(producer);
+        self.scratch = vec![0.0; settings.block_size().max(1) * num_channels];
+        self.num_channels = num_channels;
+        self.shutdown = Arc::new(AtomicBool::new(false));
+
+        let writer = WriterThread {
+            handle: self.handle.clone(),
+            consumer,
+            shutdown: self.shutdown.clone(),
+            sample_rate: settings.sample_rate() as u32,
+            num_channels,
+            poll_interval: self.options.poll_interval,
+        };
+        match std::thread::Builder::new()
+            .name(String::from("audio-file-writer"))
+            .spawn(move || writer.run())
+        {
+            Ok(writer_thread) => self.writer_thread = Some(writer_thread),
+            Err(err) => self.handle.set_error(err.into()),
+        }
+    }
+
+    fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        let producer = match &mut self.producer {
+            Some(producer) => producer,
+            None => return,
+        };
+
+        match self.handle.state.load(Ordering::Acquire) {
+            RECORDING => {}
+            STOPPING => {
+                self.handle.state.store(STOPPED, Ordering::Release);
+                return;
+            }
+            _ => return,
+        }
+
+        let num_channels = self.num_channels;
+        let frames_per_chunk = self.scratch.len() / num_channels;
+        let mut frame_index = 0;
+        while frame_index < data.num_samples() {
+            let chunk_frames = frames_per_chunk.min(data.num_samples() - frame_index);
+            // Only push whole frames so the writer stays in sync with the channels
+            let free_frames = producer.remaining() / num_channels;
+            let pushed_frames = chunk_frames.min(free_frames);
+
+            for frame in 0..pushed_frames {
+                for channel in 0..num_channels {
+                    self.scratch[frame * num_channels + channel] = if channel < data.num_channels()
+                    {
+                        data.channel(channel)[frame_index + frame]
+                    } else {
+                        0.0
+                    };
+                }
+            }
+            producer.push_slice(&self.scratch[..pushed_frames * num_channels]);
+
+            self.handle
+                .recorded_frames
+                .fetch_add(pushed_frames, Ordering::Relaxed);
+            if pushed_frames < chunk_frames {
+                self.handle
+                    .dropped_frames
+                    .fetch_add(chunk_frames - pushed_frames, Ordering::Relaxed);
+            }
+            frame_index += chunk_frames;
+        }
+    }
+}
+
+impl Drop for AudioFileRecorder {
+    fn drop(&mut self) {
+        // Don't join, this might be the audio-thread; the writer finalizes on its next poll
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+struct WriterThread {
+    handle: Shared<AudioFileRecorderHandle>,
+    consumer: Consumer<f32>,
+    shutdown: Arc<AtomicBool>,
+    sample_rate: u32,
+    num_channels: usize,
+    poll_interval: Duration,
+}
+
+impl WriterThread {
+    fn run(mut self) {
+        let mut writer: Option<AudioFileWriter> = None;
+        let mut buffer = vec![0.0; 4096 * self.num_channels];
+
+        loop {
+            let shutdown = self.shutdown.load(Ordering::Relaxed);
+            let state = self.handle.state.load(Ordering::Acquire);
+
+            if state == OPENING {
+                self.open(&mut writer);
+            }
+
+            let mut written = 0;
+            loop {
+                let popped = self.consumer.pop_slice(&mut buffer);
+                if popped == 0 {
+                    break;
+                }
+                written += popped;
+                if let Some(file_writer) = &mut writer {
+                    if let Err(err) = file_writer.write_interleaved(&buffer[..popped]) {
+                        self.handle.set_error(err);
+                        writer = None;
+                    }
+                }
+            }
+
+            // Everything pushed before the audio-thread acknowledged the stop was drained above
+            if state == STOPPED || shutdown {
+                if let Some(file_writer) = writer.take() {
+                    if let Err(err) = file_writer.finalize() {
+                        self.handle.set_error(err);
+                    }
+                }
+                if state == STOPPED || (shutdown && state != IDLE) {
+                    self.handle.state.store(IDLE, Ordering::Release);
+                }
+            }
+
+            if shutdown {
+                break;
+            }
+            if written == 0 {
+                std::thread::sleep(self.poll_interval);
+            }
+        }
+    }
+
+    fn open(&self, writer: &mut Option<AudioFileWriter>) {
+        let settings = match self.handle.next_take.lock().unwrap().take() {
+            Some(settings) => settings,
+            None => return,
+        };
+
+        match AudioFileWriter::create(&settings, self.sample_rate, self.num_channels) {
+            Ok(file_writer) => {
+                *writer = Some(file_writer);
+                self.handle.recorded_frames.store(0, Ordering::Relaxed);
+                self.handle.dropped_frames.store(0, Ordering::Relaxed);
+                // Fails if the take was stopped while opening, the writer thread will finalize it
+                let _ = self.handle.state.compare_exchange(
+                    OPENING,
+                    RECORDING,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                );
+            }
+            Err(err) => {
+                self.handle.set_error(err);
+                self.handle.state.store(IDLE, Ordering::Release);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
+    use audio_processor_traits::AudioProcessorSettings;
+    use tempdir::TempDir;
+
+    use super::super::OutputSampleFormat;
+    use super::*;
+
+    fn wait_for_state(handle: &AudioFileRecorderHandle, state: RecorderState) {
+        let start = Instant::now();
+        while handle.state() != state {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed-out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn ramp_block(start: usize, num_samples: usize) -> AudioBuffer<f32> {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, num_samples);
+        for sample in 0..num_samples {
+            let value = ((start + sample) % 100) as f32 / 100.0;
+            buffer.set(0, sample, value);
+            buffer.set(1, sample, -value);
+        }
+        buffer
+    }
+
+    fn setup() -> (AudioFileRecorder, AudioContext) {
+        let mut recorder = AudioFileRecorder::default();
+        let mut context = AudioContext::from(AudioProcessorSettings::new(44100.0, 2, 2, 512));
+        recorder.prepare(&mut context);
+        (recorder, context)
+    }
+
+    #[test]
+    fn test_records_takes() {
+        let tempdir = TempDir::new("recorder").unwrap();
+        let (mut recorder, mut context) = setup();
+        let handle = recorder.handle().clone();
+
+        for take in 0..2 {
+            let path = tempdir.path().join(format!("take-{}.wav", take));
+            assert!(handle.start(OutputFileSettings::new(path.to_str().unwrap())));
+            wait_for_state(&handle, RecorderState::Recording);
+
+            for block in 0..10 {
+                let mut buffer = ramp_block(block * 512, 512);
+                recorder.process(&mut context, &mut buffer);
+                // Input is passed through
+                assert_eq!(buffer.channel(0), ramp_block(block * 512, 512).channel(0));
+            }
+            handle.stop();
+            recorder.process(&mut context, &mut ramp_block(0, 512));
+            wait_for_state(&handle, RecorderState::Idle);
+            assert_eq!(handle.recorded_frames(), 5120);
+            assert_eq!(handle.dropped_frames(), 0);
+
+            let mut reader = hound::WavReader::open(&path).unwrap();
+            assert_eq!(reader.duration(), 5120);
+            let samples: Vec<f32> = reader.samples().map(|sample| sample.unwrap()).collect();
+            let expected = ramp_block(0, 5120);
+            for frame in 0..5120 {
+                assert_eq!(samples[frame * 2], expected.channel(0)[frame]);
+                assert_eq!(samples[frame * 2 + 1], expected.channel(1)[frame]);
+            }
+        }
+        assert!(handle.take_error().is_none());
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        let tempdir = TempDir::new("recorder").unwrap();
+        let (mut recorder, mut context) = setup();
+        let handle = recorder.handle().clone();
+        let path = tempdir.path().join("take.wav");
+        handle.start(OutputFileSettings::new(path.to_str().unwrap()));
+        wait_for_state(&handle, RecorderState::Recording);
+
+        let mut buffer = ramp_block(0, 512);
+        for _ in 0..10 {
+            assert_realtime_safe(|| recorder.process(&mut context, &mut buffer));
+        }
+        handle.stop();
+        assert_realtime_safe(|| recorder.process(&mut context, &mut buffer));
+        wait_for_state(&handle, RecorderState::Idle);
+        assert_eq!(handle.recorded_frames(), 5120);
+    }
+
+    #[test]
+    fn test_start_is_ignored_while_recording() {
+        let tempdir = TempDir::new("recorder").unwrap();
+        let (recorder, _context) = setup();
+        let handle = recorder.handle().clone();
+        let path = tempdir.path().join("take.wav");
+        assert!(handle.start(OutputFileSettings::new(path.to_str().unwrap())));
+        assert!(!handle.start(OutputFileSettings::new(path.to_str().unwrap())));
+    }
+
+    #[test]
+    fn test_dropped_frames_are_counted() {
+        let tempdir = TempDir::new("recorder").unwrap();
+        let mut recorder = AudioFileRecorder::new(RecorderOptions {
+            buffer_duration: Duration::from_millis(10),
+            // The writer won't drain the buffer during the test
+            poll_interval: Duration::from_secs(1),
+        });
+        let mut context = AudioContext::from(AudioProcessorSettings::new(44100.0, 2, 2, 512));
+        recorder.prepare(&mut context);
+        let handle = recorder.handle().clone();
+        let path = tempdir.path().join("take.wav");
+        handle.start(OutputFileSettings::new(path.to_str().unwrap()));
+        wait_for_state(&handle, RecorderState::Recording);
+
+        recorder.process(&mut context, &mut ramp_block(0, 512));
+        // The buffer holds 441 frames
+        assert_eq!(handle.recorded_frames(), 441);
+        assert_eq!(handle.dropped_frames(), 71);
+    }
+
+    #[test]
+    fn test_take_is_finalized_when_dropped() {
+        let tempdir = TempDir::new("recorder").unwrap();
+        let path = tempdir.path().join("take.flac");
+        let handle = {
+            let (mut recorder, mut context) = setup();
+            let handle = recorder.handle().clone();
+            let mut settings = OutputFileSettings::new(path.to_str().unwrap());
+            settings.sample_format = OutputSampleFormat::Int16;
+            handle.start(settings);
+            wait_for_state(&handle, RecorderState::Recording);
+            recorder.process(&mut context, &mut ramp_block(0, 512));
+            handle
+        };
+        wait_for_state(&handle, RecorderState::Idle);
+
+        let audio_file = crate::file_io::default_read_audio_file(path.to_str().unwrap()).unwrap();
+        let track = audio_file.format.default_track().unwrap();
+        assert_eq!(track.codec_params.n_frames, Some(512));
+    }
+
+    #[test]
+    fn test_open_errors_are_reported() {
+        let (recorder, _context) = setup();
+        let handle = recorder.handle().clone();
+        assert!(handle.start(OutputFileSettings::new("/non-existing-directory/take.wav")));
+        wait_for_state(&handle, RecorderState::Idle);
+        assert!(handle.take_error().is_some());
+    }
+}
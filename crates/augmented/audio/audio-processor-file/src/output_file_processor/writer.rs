@@ -0,0 +1,176 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use audio_processor_traits::AudioBuffer;
+
+use super::dither::Ditherer;
+use super::flac::FlacWriter;
+use super::metadata::{self, RecordingMetadata};
+use super::{OutputFileError, OutputFileFormat, OutputFileSettings, OutputSampleFormat};
+
+enum Encoder {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(FlacWriter<BufWriter<File>>),
+}
+
+/// Encodes interleaved samples into a file with the given [`OutputFileSettings`]
+pub(crate) struct AudioFileWriter {
+    path: PathBuf,
+    encoder: Encoder,
+    sample_format: OutputSampleFormat,
+    ditherers: Vec<Ditherer>,
+    channel: usize,
+    sample_rate: u32,
+    metadata: Option<RecordingMetadata>,
+}
+
+impl AudioFileWriter {
+    pub(crate) fn create(
+        settings: &OutputFileSettings,
+        sample_rate: u32,
+        num_channels: usize,
+    ) -> Result<Self, OutputFileError> {
+        let path = PathBuf::from(settings.audio_file_path());
+        let sample_format = settings.sample_format;
+        let encoder = match settings.format {
+            OutputFileFormat::Wav => {
+                let spec = hound::WavSpec {
+                    channels: num_channels as u16,
+                    sample_rate,
+                    bits_per_sample: sample_format.bits_per_sample() as u16,
+                    sample_format: if sample_format == OutputSampleFormat::Float32 {
+                        hound::SampleFormat::Float
+                    } else {
+                        hound::SampleFormat::Int
+                    },
+                };
+                Encoder::Wav(hound::WavWriter::create(&path, spec)?)
+            }
+            OutputFileFormat::Flac => {
+                if !matches!(
+                    sample_format,
+                    OutputSampleFormat::Int16 | OutputSampleFormat::Int24
+                ) {
+                    return Err(OutputFileError::UnsupportedSampleFormat(
+                        settings.format,
+                        sample_format,
+                    ));
+                }
+                let comments = settings
+                    .metadata
+                    .as_ref()
+                    .map(|metadata| metadata.vorbis_comments(sample_rate))
+                    .unwrap_or_default();
+                let file = BufWriter::new(File::create(&path)?);
+                Encoder::Flac(FlacWriter::new(
+                    file,
+                    sample_rate,
+                    num_channels,
+                    sample_format.bits_per_sample(),
+                    &comments,
+                )?)
+            }
+        };
+
+        Ok(Self {
+            path,
+            encoder,
+            sample_format,
+            ditherers: (0..num_channels)
+                .map(|channel| Ditherer::new(settings.dither, channel as u32))
+                .collect(),
+            channel: 0,
+            sample_rate,
+            metadata: settings.metadata.clone(),
+        })
+    }
+
+    pub(crate) fn num_channels(&self) -> usize {
+        self.ditherers.len()
+    }
+
+    /// Write the next interleaved sample
+    pub(crate) fn write_sample(&mut self, sample: f32) -> Result<(), OutputFileError> {
+        let bits = self.sample_format.bits_per_sample();
+        let channel = self.channel;
+        self.channel = (channel + 1) % self.ditherers.len();
+        let ditherer = &mut self.ditherers[channel];
+
+        match (&mut self.encoder, self.sample_format) {
+            (Encoder::Wav(writer), OutputSampleFormat::Float32) => writer.write_sample(sample)?,
+            (Encoder::Wav(writer), OutputSampleFormat::Int16) => {
+                writer.write_sample(ditherer.quantize(sample, bits) as i16)?
+            }
+            (Encoder::Wav(writer), _) => writer.write_sample(ditherer.quantize(sample, bits))?,
+            (Encoder::Flac(writer), _) => writer.write_sample(ditherer.quantize(sample, bits))?,
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn write_interleaved(&mut self, samples: &[f32]) -> Result<(), OutputFileError> {
+        for sample in samples {
+            self.write_sample(*sample)?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffer's channels, up to the file's channel count. Missing channels are silent.
+    pub(crate) fn write_buffer(&mut self, data: &AudioBuffer<f32>) -> Result<(), OutputFileError> {
+        for sample_num in 0..data.num_samples() {
+            for channel_num in 0..self.num_channels() {
+                let sample = if channel_num < data.num_channels() {
+                    *data.get(channel_num, sample_num)
+                } else {
+                    0.0
+                };
+                self.write_sample(sample)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush & complete headers. The file is incomplete until this is called.
+    pub(crate) fn finalize(self) -> Result<(), OutputFileError> {
+        match self.encoder {
+            Encoder::Wav(writer) => {
+                let num_frames = writer.duration() as u64;
+                writer.finalize()?;
+                if let Some(metadata) = &self.metadata {
+                    metadata::append_wav_chunks(
+                        &self.path,
+                        metadata,
+                        self.sample_rate,
+                        num_frames,
+                    )?;
+                }
+            }
+            Encoder::Flac(writer) => writer.finalize()?,
+        }
+        Ok(())
+    }
+}
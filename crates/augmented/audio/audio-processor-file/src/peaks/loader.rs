@@ -0,0 +1,244 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+
+use symphonia::core::audio::Signal;
+
+use audio_processor_traits::AtomicF32;
+
+use crate::file_io::{default_read_audio_file, AudioFileError, FileContentsStream};
+
+use super::peak_file::{read_peak_file, sidecar_path, write_peak_file, PeakFileError};
+use super::{PeakPyramid, PeakPyramidBuilder, PeakPyramidOptions};
+
+/// Builds a [`PeakPyramid`] for an audio file on a background thread.
+///
+/// Poll [`PeakPyramidLoader::poll`] from the GUI thread & draw [`PeakPyramidLoader::progress`]
+/// in the meantime. Dropping the loader cancels generation.
+pub struct PeakPyramidLoader {
+    progress: Arc<AtomicF32>,
+    cancelled: Arc<AtomicBool>,
+    receiver: Receiver<Result<PeakPyramid, PeakFileError>>,
+}
+
+impl PeakPyramidLoader {
+    /// Decode the file & build its pyramid, ignoring any sidecar file
+    pub fn build(path: impl AsRef<Path>, options: PeakPyramidOptions) -> Self {
+        Self::spawn(path.as_ref().to_path_buf(), options, false)
+    }
+
+    /// Read the pyramid from the file's sidecar peak file. If it's missing or stale the pyramid
+    /// is built from the audio file and the sidecar file is written.
+    pub fn load_or_build(path: impl AsRef<Path>, options: PeakPyramidOptions) -> Self {
+        Self::spawn(path.as_ref().to_path_buf(), options, true)
+    }
+
+    fn spawn(path: PathBuf, options: PeakPyramidOptions, use_sidecar: bool) -> Self {
+        let progress = Arc::new(AtomicF32::new(0.0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = channel();
+
+        let thread_progress = progress.clone();
+        let thread_cancelled = cancelled.clone();
+        let spawn_result = std::thread::Builder::new()
+            .name(String::from("peak-pyramid-builder"))
+            .spawn(move || {
+                let result = if use_sidecar {
+                    load_or_build(&path, options, &thread_progress, &thread_cancelled)
+                } else {
+                    build(&path, options, &thread_progress, &thread_cancelled)
+                };
+                thread_progress.set(1.0);
+                let _ = sender.send(result);
+            });
+
+        if let Err(err) = spawn_result {
+            let (sender, failed_receiver) = channel();
+            let _ = sender.send(Err(PeakFileError::Io(err)));
+            return Self {
+                progress,
+                cancelled,
+                receiver: failed_receiver,
+            };
+        }
+
+        Self {
+            progress,
+            cancelled,
+            receiver,
+        }
+    }
+
+    /// Progress from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.progress.get()
+    }
+
+    /// Returns the result once it's ready. Only returns `Some` once.
+    pub fn poll(&self) -> Option<Result<PeakPyramid, PeakFileError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Block until the result is ready
+    pub fn wait(self) -> Result<PeakPyramid, PeakFileError> {
+        self.receiver
+            .recv()
+            .unwrap_or(Err(PeakFileError::Cancelled))
+    }
+}
+
+impl Drop for PeakPyramidLoader {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn load_or_build(
+    path: &Path,
+    options: PeakPyramidOptions,
+    progress: &AtomicF32,
+    cancelled: &AtomicBool,
+) -> Result<PeakPyramid, PeakFileError> {
+    let peak_file_path = sidecar_path(path);
+    match read_peak_file(&peak_file_path, path) {
+        Ok(pyramid) => return Ok(pyramid),
+        Err(PeakFileError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => log::info!(
+            "Rebuilding peak file path={:?} reason={}",
+            peak_file_path,
+            err
+        ),
+    }
+
+    let pyramid = build(path, options, progress, cancelled)?;
+    if let Err(err) = write_peak_file(&peak_file_path, path, &pyramid) {
+        log::warn!(
+            "Failed to write peak file path={:?} err={}",
+            peak_file_path,
+            err
+        );
+    }
+    Ok(pyramid)
+}
+
+fn build(
+    path: &Path,
+    options: PeakPyramidOptions,
+    progress: &AtomicF32,
+    cancelled: &AtomicBool,
+) -> Result<PeakPyramid, PeakFileError> {
+    let path = path.to_str().ok_or(AudioFileError::OpenStreamError)?;
+    let mut probe_result = default_read_audio_file(path)?;
+    let stream = FileContentsStream::new(&mut probe_result)?;
+    let total_frames = stream.len();
+
+    let mut builder = None;
+    for buffer in stream {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(PeakFileError::Cancelled);
+        }
+
+        let num_channels = buffer.spec().channels.count();
+        let builder =
+            builder.get_or_insert_with(|| PeakPyramidBuilder::new(num_channels, options.clone()));
+        for frame in 0..buffer.frames() {
+            builder.push_frame(|channel| buffer.chan(channel.min(num_channels - 1))[frame]);
+        }
+
+        if total_frames > 0 {
+            progress.set((builder.num_frames() as f32 / total_frames as f32).min(1.0));
+        }
+    }
+
+    builder
+        .map(PeakPyramidBuilder::build)
+        .ok_or(PeakFileError::AudioFile(AudioFileError::EmptyFileError))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_sine_file(path: &Path, num_frames: usize) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for frame in 0..num_frames {
+            let value = (frame as f32 / 44100.0 * 440.0 * std::f32::consts::TAU).sin() * 0.5;
+            writer.write_sample(value).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_build_from_file() {
+        let dir = tempdir::TempDir::new("peak_loader").unwrap();
+        let path = dir.path().join("sine.wav");
+        write_sine_file(&path, 44100);
+
+        let loader = PeakPyramidLoader::build(&path, PeakPyramidOptions::default());
+        let pyramid = loader.wait().unwrap();
+        assert_eq!(pyramid.num_frames(), 44100);
+        assert_eq!(pyramid.num_channels(), 1);
+        let peak = pyramid.peaks(0, 0..44100, 1)[0];
+        assert!((peak.max - 0.5).abs() < 0.01);
+        assert!((peak.min + 0.5).abs() < 0.01);
+        assert!((peak.rms - 0.5 / 2.0_f32.sqrt()).abs() < 0.01);
+        assert!(!sidecar_path(&path).exists());
+    }
+
+    #[test]
+    fn test_load_or_build_writes_sidecar() {
+        let dir = tempdir::TempDir::new("peak_loader").unwrap();
+        let path = dir.path().join("sine.wav");
+        write_sine_file(&path, 10000);
+
+        let built = PeakPyramidLoader::load_or_build(&path, PeakPyramidOptions::default())
+            .wait()
+            .unwrap();
+        assert!(sidecar_path(&path).exists());
+
+        let loaded = PeakPyramidLoader::load_or_build(&path, PeakPyramidOptions::default())
+            .wait()
+            .unwrap();
+        assert_eq!(built, loaded);
+    }
+
+    #[test]
+    fn test_missing_file_fails() {
+        let loader = PeakPyramidLoader::build("/does/not/exist.wav", Default::default());
+        assert!(loader.wait().is_err());
+    }
+}
@@ -0,0 +1,375 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Multi-resolution waveform overviews.
+//!
+//! A [`PeakPyramid`] holds min/max/RMS [`Peak`]s for blocks of samples at several resolutions.
+//! Drawing a waveform at any zoom level only reads the coarsest level that still has a peak per
+//! pixel, see [`PeakPyramid::peaks`], so the cost of a query depends on the number of pixels
+//! rather than the length of the file.
+//!
+//! [`PeakPyramidLoader`] builds pyramids on a background thread. Pyramids can be written onto a
+//! sidecar `.peaks` file next to the audio file & reloaded from it, see
+//! [`PeakPyramidLoader::load_or_build`].
+
+use std::ops::Range;
+
+use audio_processor_traits::AudioBuffer;
+
+pub use loader::PeakPyramidLoader;
+pub use peak_file::{read_peak_file, sidecar_path, write_peak_file, PeakFileError};
+
+mod loader;
+mod peak_file;
+
+/// Summary of a block of samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub min: f32,
+    pub max: f32,
+    pub rms: f32,
+}
+
+impl Default for Peak {
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: 0.0,
+            rms: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PeakPyramidOptions {
+    /// Number of samples summarised by each peak of the finest level. Defaults to 256
+    pub samples_per_peak: usize,
+    /// How many peaks of a level are merged into each peak of the next level. Defaults to 4
+    pub level_factor: usize,
+}
+
+impl Default for PeakPyramidOptions {
+    fn default() -> Self {
+        Self {
+            samples_per_peak: 256,
+            level_factor: 4,
+        }
+    }
+}
+
+/// Peaks at a single resolution
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakLevel {
+    samples_per_peak: usize,
+    /// Peaks per channel
+    channels: Vec<Vec<Peak>>,
+}
+
+impl PeakLevel {
+    pub fn samples_per_peak(&self) -> usize {
+        self.samples_per_peak
+    }
+
+    pub fn channel(&self, channel: usize) -> &[Peak] {
+        &self.channels[channel]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakPyramid {
+    num_frames: usize,
+    levels: Vec<PeakLevel>,
+}
+
+impl PeakPyramid {
+    pub fn from_buffer(buffer: &AudioBuffer<f32>, options: PeakPyramidOptions) -> Self {
+        let mut builder = PeakPyramidBuilder::new(buffer.num_channels(), options);
+        builder.push(buffer);
+        builder.build()
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.levels
+            .first()
+            .map(|level| level.channels.len())
+            .unwrap_or(0)
+    }
+
+    /// Levels, from the finest to the coarsest
+    pub fn levels(&self) -> &[PeakLevel] {
+        &self.levels
+    }
+
+    /// Peaks for `range` of frames, split into `num_pixels` columns
+    pub fn peaks(&self, channel: usize, range: Range<usize>, num_pixels: usize) -> Vec<Peak> {
+        let mut result = vec![Peak::default(); num_pixels];
+        self.peaks_into(channel, range, &mut result);
+        result
+    }
+
+    /// Same as [`PeakPyramid::peaks`], writing a column onto each element of `output`.
+    ///
+    /// When zoomed in past the finest level, columns repeat the peak they fall into. Columns
+    /// past the end of the file are silent.
+    pub fn peaks_into(&self, channel: usize, range: Range<usize>, output: &mut [Peak]) {
+        if output.is_empty() {
+            return;
+        }
+        let level = self.level_for(range.len() as f64 / output.len() as f64);
+        let level = match level {
+            Some(level) => level,
+            None => {
+                output.fill(Peak::default());
+                return;
+            }
+        };
+
+        let peaks = level.channel(channel);
+        let samples_per_pixel = range.len() as f64 / output.len() as f64;
+        for (pixel, result) in output.iter_mut().enumerate() {
+            let start = range.start + (pixel as f64 * samples_per_pixel) as usize;
+            let end = range.start + ((pixel + 1) as f64 * samples_per_pixel) as usize;
+            let first = start / level.samples_per_peak;
+            let last = (end.max(start + 1)).div_ceil(level.samples_per_peak);
+            *result = merge_peaks(&peaks[first.min(peaks.len())..last.min(peaks.len())]);
+        }
+    }
+
+    /// Coarsest level with at most one peak per `samples_per_pixel`
+    fn level_for(&self, samples_per_pixel: f64) -> Option<&PeakLevel> {
+        self.levels
+            .iter()
+            .rev()
+            .find(|level| level.samples_per_peak as f64 <= samples_per_pixel)
+            .or_else(|| self.levels.first())
+    }
+}
+
+/// Builds a [`PeakPyramid`] from blocks of audio
+pub struct PeakPyramidBuilder {
+    options: PeakPyramidOptions,
+    num_frames: usize,
+    peaks: Vec<Vec<Peak>>,
+    /// Accumulated (min, max, sum of squares) of the current block, per channel
+    current: Vec<(f32, f32, f32)>,
+    current_frames: usize,
+}
+
+impl PeakPyramidBuilder {
+    pub fn new(num_channels: usize, options: PeakPyramidOptions) -> Self {
+        Self {
+            options: PeakPyramidOptions {
+                samples_per_peak: options.samples_per_peak.max(1),
+                level_factor: options.level_factor.max(2),
+            },
+            num_frames: 0,
+            peaks: vec![vec![]; num_channels],
+            current: vec![(f32::MAX, f32::MIN, 0.0); num_channels],
+            current_frames: 0,
+        }
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    pub fn push(&mut self, buffer: &AudioBuffer<f32>) {
+        for frame in 0..buffer.num_samples() {
+            self.push_frame(|channel| *buffer.get(channel, frame));
+        }
+    }
+
+    /// Push a frame. Mono sources may return the same sample for every channel.
+    pub fn push_frame(&mut self, sample: impl Fn(usize) -> f32) {
+        for (channel, (min, max, sum_squares)) in self.current.iter_mut().enumerate() {
+            let sample = sample(channel);
+            *min = min.min(sample);
+            *max = max.max(sample);
+            *sum_squares += sample * sample;
+        }
+        self.current_frames += 1;
+        self.num_frames += 1;
+        if self.current_frames == self.options.samples_per_peak {
+            self.flush_current();
+        }
+    }
+
+    pub fn build(mut self) -> PeakPyramid {
+        if self.current_frames > 0 {
+            self.flush_current();
+        }
+
+        let mut levels = vec![PeakLevel {
+            samples_per_peak: self.options.samples_per_peak,
+            channels: self.peaks,
+        }];
+        loop {
+            let previous = &levels[levels.len() - 1];
+            if previous.channels.first().map(|c| c.len()).unwrap_or(0) <= 1 {
+                break;
+            }
+            let factor = self.options.level_factor;
+            levels.push(PeakLevel {
+                samples_per_peak: previous.samples_per_peak * factor,
+                channels: previous
+                    .channels
+                    .iter()
+                    .map(|peaks| peaks.chunks(factor).map(merge_peaks).collect())
+                    .collect(),
+            });
+        }
+
+        PeakPyramid {
+            num_frames: self.num_frames,
+            levels,
+        }
+    }
+
+    fn flush_current(&mut self) {
+        let num_frames = self.current_frames as f32;
+        for (peaks, current) in self.peaks.iter_mut().zip(&mut self.current) {
+            let (min, max, sum_squares) = *current;
+            peaks.push(Peak {
+                min,
+                max,
+                rms: (sum_squares / num_frames).sqrt(),
+            });
+            *current = (f32::MAX, f32::MIN, 0.0);
+        }
+        self.current_frames = 0;
+    }
+}
+
+/// Blocks are assumed to be of the same length, which only isn't true for the last block
+fn merge_peaks(peaks: &[Peak]) -> Peak {
+    if peaks.is_empty() {
+        return Peak::default();
+    }
+
+    let mut result = Peak {
+        min: f32::MAX,
+        max: f32::MIN,
+        rms: 0.0,
+    };
+    for peak in peaks {
+        result.min = result.min.min(peak.min);
+        result.max = result.max.max(peak.max);
+        result.rms += peak.rms * peak.rms;
+    }
+    result.rms = (result.rms / peaks.len() as f32).sqrt();
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ramp(num_frames: usize) -> AudioBuffer<f32> {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, num_frames);
+        for frame in 0..num_frames {
+            let value = frame as f32 / num_frames as f32;
+            buffer.set(0, frame, value);
+            buffer.set(1, frame, -value);
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_build_levels() {
+        let pyramid = PeakPyramid::from_buffer(&ramp(10000), PeakPyramidOptions::default());
+        assert_eq!(pyramid.num_frames(), 10000);
+        assert_eq!(pyramid.num_channels(), 2);
+
+        let sizes: Vec<(usize, usize)> = pyramid
+            .levels()
+            .iter()
+            .map(|level| (level.samples_per_peak(), level.channel(0).len()))
+            .collect();
+        assert_eq!(sizes, vec![(256, 40), (1024, 10), (4096, 3), (16384, 1)]);
+
+        let top = pyramid.levels().last().unwrap().channel(1)[0];
+        assert_eq!(top.max, 0.0);
+        assert!((top.min + 0.9999).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_rms() {
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, 1024);
+        for frame in 0..1024 {
+            buffer.set(0, frame, if frame % 2 == 0 { 0.5 } else { -0.5 });
+        }
+        let pyramid = PeakPyramid::from_buffer(&buffer, PeakPyramidOptions::default());
+        for level in pyramid.levels() {
+            for peak in level.channel(0) {
+                assert!((peak.rms - 0.5).abs() < 1e-6);
+                assert_eq!((peak.min, peak.max), (-0.5, 0.5));
+            }
+        }
+    }
+
+    #[test]
+    fn test_peaks_query_matches_samples() {
+        let buffer = ramp(100_000);
+        let pyramid = PeakPyramid::from_buffer(&buffer, PeakPyramidOptions::default());
+
+        for (range, num_pixels) in [(0..100_000, 100), (5000..25000, 10), (0..100_000, 1)] {
+            let peaks = pyramid.peaks(0, range.clone(), num_pixels);
+            assert_eq!(peaks.len(), num_pixels);
+            let samples_per_pixel = range.len() / num_pixels;
+            for (pixel, peak) in peaks.iter().enumerate() {
+                let start = range.start + pixel * samples_per_pixel;
+                let end = start + samples_per_pixel;
+                let expected_max = buffer.channel(0)[end - 1];
+                let expected_min = buffer.channel(0)[start];
+                // Peaks are rounded out to the block boundaries of the level used
+                assert!(peak.max >= expected_max);
+                assert!(peak.min <= expected_min);
+                assert!(peak.max - expected_max < 0.05);
+                assert!(expected_min - peak.min < 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn test_peaks_query_picks_coarsest_level() {
+        let pyramid = PeakPyramid::from_buffer(&ramp(100_000), PeakPyramidOptions::default());
+        assert_eq!(pyramid.level_for(5000.0).unwrap().samples_per_peak(), 4096);
+        assert_eq!(pyramid.level_for(300.0).unwrap().samples_per_peak(), 256);
+        // Zoomed in further than the finest level
+        assert_eq!(pyramid.level_for(10.0).unwrap().samples_per_peak(), 256);
+    }
+
+    #[test]
+    fn test_peaks_past_the_end_are_silent() {
+        let pyramid = PeakPyramid::from_buffer(&ramp(1000), PeakPyramidOptions::default());
+        let peaks = pyramid.peaks(0, 0..4000, 4);
+        assert!(peaks[0].max > 0.9);
+        assert_eq!(peaks[3], Peak::default());
+    }
+}
@@ -0,0 +1,256 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Sidecar peak files.
+//!
+//! Files are little-endian and start with a header recording the size & modification time of
+//! the audio file they were generated from, so stale files are detected & rebuilt:
+//!
+//! ```text
+//! "AAPK" | version: u32 | source size: u64 | source mtime (ms): u64
+//! num channels: u32 | num frames: u64 | num levels: u32
+//! levels: (samples per peak: u32 | num peaks: u64 | channels: (min: f32 | max: f32 | rms: f32)*)*
+//! ```
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use thiserror::Error;
+
+use crate::file_io::AudioFileError;
+
+use super::{Peak, PeakLevel, PeakPyramid};
+
+const MAGIC: &[u8; 4] = b"AAPK";
+const VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum PeakFileError {
+    #[error("Failed to read or write peak file")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid peak file: {0}")]
+    InvalidFormat(&'static str),
+    #[error("Peak file is out of date with its audio file")]
+    Stale,
+    #[error("Failed to read audio file")]
+    AudioFile(#[from] AudioFileError),
+    #[error("Peak generation was cancelled")]
+    Cancelled,
+}
+
+/// `<audio file path>.peaks`
+pub fn sidecar_path(audio_file_path: &Path) -> PathBuf {
+    let mut path = audio_file_path.as_os_str().to_owned();
+    path.push(".peaks");
+    PathBuf::from(path)
+}
+
+/// Size & modification time of the audio file
+#[derive(Debug, PartialEq)]
+struct SourceStamp {
+    size: u64,
+    modified_ms: u64,
+}
+
+impl SourceStamp {
+    fn read(audio_file_path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(audio_file_path)?;
+        let modified_ms = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0);
+        Ok(Self {
+            size: metadata.len(),
+            modified_ms,
+        })
+    }
+}
+
+/// Write `pyramid` onto `path`, stamped with the current state of `audio_file_path`
+pub fn write_peak_file(
+    path: &Path,
+    audio_file_path: &Path,
+    pyramid: &PeakPyramid,
+) -> Result<(), PeakFileError> {
+    let stamp = SourceStamp::read(audio_file_path)?;
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&stamp.size.to_le_bytes())?;
+    writer.write_all(&stamp.modified_ms.to_le_bytes())?;
+    writer.write_all(&(pyramid.num_channels() as u32).to_le_bytes())?;
+    writer.write_all(&(pyramid.num_frames as u64).to_le_bytes())?;
+    writer.write_all(&(pyramid.levels.len() as u32).to_le_bytes())?;
+
+    for level in &pyramid.levels {
+        let num_peaks = level.channels.first().map(|c| c.len()).unwrap_or(0);
+        writer.write_all(&(level.samples_per_peak as u32).to_le_bytes())?;
+        writer.write_all(&(num_peaks as u64).to_le_bytes())?;
+        for peaks in &level.channels {
+            for peak in peaks {
+                writer.write_all(&peak.min.to_le_bytes())?;
+                writer.write_all(&peak.max.to_le_bytes())?;
+                writer.write_all(&peak.rms.to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a peak file, failing with [`PeakFileError::Stale`] if `audio_file_path` changed since it
+/// was written
+pub fn read_peak_file(path: &Path, audio_file_path: &Path) -> Result<PeakPyramid, PeakFileError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(PeakFileError::InvalidFormat("missing magic bytes"));
+    }
+    if read_u32(&mut reader)? != VERSION {
+        return Err(PeakFileError::InvalidFormat("unsupported version"));
+    }
+
+    let stamp = SourceStamp {
+        size: read_u64(&mut reader)?,
+        modified_ms: read_u64(&mut reader)?,
+    };
+    if stamp != SourceStamp::read(audio_file_path)? {
+        return Err(PeakFileError::Stale);
+    }
+
+    let num_channels = read_u32(&mut reader)? as usize;
+    let num_frames = read_u64(&mut reader)? as usize;
+    let num_levels = read_u32(&mut reader)? as usize;
+    // Corrupt counts shouldn't cause huge allocations, peaks are read one by one instead
+    let file_len = std::fs::metadata(path)?.len() as usize;
+
+    let mut levels = Vec::new();
+    for _ in 0..num_levels {
+        let samples_per_peak = read_u32(&mut reader)? as usize;
+        let num_peaks = read_u64(&mut reader)? as usize;
+        if samples_per_peak == 0 || num_peaks.saturating_mul(num_channels * 12) > file_len {
+            return Err(PeakFileError::InvalidFormat("invalid level header"));
+        }
+
+        let mut channels = Vec::with_capacity(num_channels);
+        for _ in 0..num_channels {
+            let mut peaks = Vec::with_capacity(num_peaks);
+            for _ in 0..num_peaks {
+                peaks.push(Peak {
+                    min: read_f32(&mut reader)?,
+                    max: read_f32(&mut reader)?,
+                    rms: read_f32(&mut reader)?,
+                });
+            }
+            channels.push(peaks);
+        }
+        levels.push(PeakLevel {
+            samples_per_peak,
+            channels,
+        });
+    }
+
+    if levels.is_empty() {
+        return Err(PeakFileError::InvalidFormat("no levels"));
+    }
+
+    Ok(PeakPyramid { num_frames, levels })
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> std::io::Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use audio_processor_traits::AudioBuffer;
+
+    use super::super::PeakPyramidOptions;
+    use super::*;
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("/tmp/file.wav")),
+            PathBuf::from("/tmp/file.wav.peaks")
+        );
+    }
+
+    #[test]
+    fn test_write_and_read_peak_file() {
+        let dir = tempdir::TempDir::new("peak_file").unwrap();
+        let audio_path = dir.path().join("audio.wav");
+        std::fs::write(&audio_path, b"not really audio").unwrap();
+
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(2, 5000);
+        for frame in 0..5000 {
+            buffer.set(0, frame, (frame as f32 * 0.01).sin());
+            buffer.set(1, frame, (frame as f32 * 0.02).cos());
+        }
+        let pyramid = PeakPyramid::from_buffer(&buffer, PeakPyramidOptions::default());
+
+        let peak_path = sidecar_path(&audio_path);
+        write_peak_file(&peak_path, &audio_path, &pyramid).unwrap();
+        let result = read_peak_file(&peak_path, &audio_path).unwrap();
+        assert_eq!(result, pyramid);
+
+        std::fs::write(&audio_path, b"a different, longer file").unwrap();
+        assert!(matches!(
+            read_peak_file(&peak_path, &audio_path),
+            Err(PeakFileError::Stale)
+        ));
+    }
+
+    #[test]
+    fn test_read_invalid_file() {
+        let dir = tempdir::TempDir::new("peak_file").unwrap();
+        let path = dir.path().join("file.peaks");
+        std::fs::write(&path, b"RIFF1234").unwrap();
+        assert!(matches!(
+            read_peak_file(&path, &path),
+            Err(PeakFileError::InvalidFormat(_))
+        ));
+    }
+}
@@ -23,10 +23,15 @@
 
 //! WIP - Draft of a version of https://github.com/RustAudio/dsp-chain which will work with the
 //! `audio-processor-traits` crate (support for abstract `AudioBuffer` / `AudioProcessor`s).
+//!
+//! Every node is timed on each block. Per-node percentiles, xruns & Chrome traces are available
+//! through [`AudioProcessorGraphHandleImpl::profiler`], see
+//! [`augmented_audio_metrics::profiling`].
 
 use std::cell::UnsafeCell;
 use std::collections::HashMap;
 use std::ops::Deref;
+use std::time::Instant;
 
 use daggy::Walker;
 use thiserror::Error;
@@ -36,6 +41,7 @@ use audio_processor_traits::simple_processor::MonoAudioProcessor;
 use audio_processor_traits::{
     AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings, NoopAudioProcessor,
 };
+use augmented_audio_metrics::profiling::{AudioProcessorProfilerHandle, ProcessorTimingHandle};
 use augmented_oscillator::Oscillator;
 
 #[cfg(test)]
@@ -69,13 +75,33 @@ pub struct AudioProcessorGraphHandleImpl<P> {
     audio_processor_settings: SharedCell<Option<AudioProcessorSettings>>,
     processors: SharedCell<HashMap<NodeIndex, Shared<ProcessorCell<NodeType<P>>>>>,
     buffers: SharedCell<HashMap<ConnectionIndex, Shared<BufferCell<AudioBuffer<f32>>>>>,
+    timings: SharedCell<HashMap<NodeIndex, Shared<ProcessorTimingHandle>>>,
+    profiler: Shared<AudioProcessorProfilerHandle>,
 }
 
 impl<P: Send + 'static + AudioProcessor> AudioProcessorGraphHandleImpl<P> {
-    pub fn add_node(&self, mut processor: NodeType<P>) -> NodeIndex {
+    pub fn add_node(&self, processor: NodeType<P>) -> NodeIndex {
+        self.add_node_with_name(None, processor)
+    }
+
+    /// Add a node which will show up with `name` on profiling data, otherwise nodes are named by
+    /// their index
+    pub fn add_named_node(&self, name: &str, processor: NodeType<P>) -> NodeIndex {
+        self.add_node_with_name(Some(name), processor)
+    }
+
+    fn add_node_with_name(&self, name: Option<&str>, mut processor: NodeType<P>) -> NodeIndex {
         let mut processors = self.processors.get().deref().clone();
+        let mut timings = self.timings.get().deref().clone();
         let mut dag = self.dag.get().deref().clone();
         let index = dag.add_node(());
+        let timing = match name {
+            Some(name) => self.profiler.register_processor(name),
+            None => self
+                .profiler
+                .register_processor(&format!("Node {}", index.index())),
+        };
+        timings.insert(index, timing);
 
         if let Some(settings) = self.audio_processor_settings.get().deref() {
             let mut context = AudioContext::from(*settings);
@@ -90,6 +116,7 @@ impl<P: Send + 'static + AudioProcessor> AudioProcessorGraphHandleImpl<P> {
         processors.insert(index, processor_ref);
 
         self.processors.set(make_shared(processors));
+        self.timings.set(make_shared(timings));
         self.dag.set(make_shared(dag));
         index
     }
@@ -130,6 +157,35 @@ impl<P: Send + 'static + AudioProcessor> AudioProcessorGraphHandleImpl<P> {
         self.dag.set(make_shared(dag));
     }
 
+    /// Disconnect `node`, drop its processor & stop timing it.
+    ///
+    /// The index stays allocated as an empty node, so other `NodeIndex`s remain valid.
+    pub fn remove_node(&self, node: NodeIndex) {
+        let mut dag = self.dag.get().deref().clone();
+        // Removing an edge may re-number the others, so look them up one at a time
+        while let Some(edge) = dag
+            .graph()
+            .first_edge(node, daggy::petgraph::Direction::Outgoing)
+            .or_else(|| {
+                dag.graph()
+                    .first_edge(node, daggy::petgraph::Direction::Incoming)
+            })
+        {
+            dag.remove_edge(edge);
+        }
+
+        let mut processors = self.processors.get().deref().clone();
+        processors.remove(&node);
+        let mut timings = self.timings.get().deref().clone();
+        if let Some(timing) = timings.remove(&node) {
+            self.profiler.unregister_processor(timing.id());
+        }
+
+        self.processors.set(make_shared(processors));
+        self.timings.set(make_shared(timings));
+        self.dag.set(make_shared(dag));
+    }
+
     pub fn input(&self) -> NodeIndex {
         self.input_node
     }
@@ -137,6 +193,15 @@ impl<P: Send + 'static + AudioProcessor> AudioProcessorGraphHandleImpl<P> {
     pub fn output(&self) -> NodeIndex {
         self.output_node
     }
+
+    /// Timing of whole blocks & of every node
+    pub fn profiler(&self) -> &Shared<AudioProcessorProfilerHandle> {
+        &self.profiler
+    }
+
+    pub fn node_timing(&self, node: NodeIndex) -> Option<Shared<ProcessorTimingHandle>> {
+        self.timings.get().get(&node).cloned()
+    }
 }
 
 #[derive(Debug, Error)]
@@ -188,6 +253,8 @@ impl<P: Send + 'static + AudioProcessor> AudioProcessorGraphImpl<P> {
                 audio_processor_settings: make_shared_cell(None),
                 processors: make_shared_cell(HashMap::new()),
                 buffers: make_shared_cell(HashMap::new()),
+                timings: make_shared_cell(HashMap::new()),
+                profiler: make_shared(AudioProcessorProfilerHandle::default()),
             }),
             temporary_buffer: AudioBuffer::empty(),
         }
@@ -218,6 +285,10 @@ impl<P: Send + 'static + AudioProcessor> AudioProcessorGraphImpl<P> {
         self.handle.add_node(processor)
     }
 
+    pub fn add_named_node(&mut self, name: &str, processor: NodeType<P>) -> NodeIndex {
+        self.handle.add_named_node(name, processor)
+    }
+
     pub fn add_connection(
         &mut self,
         source: NodeIndex,
@@ -238,6 +309,7 @@ impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for AudioProcessorGraph
         self.handle
             .audio_processor_settings
             .set(make_shared(Some(settings)));
+        self.handle.profiler.prepare(settings);
         let buffers = self.handle.buffers.get();
         for buffer_ref in buffers.values() {
             let buffer = buffer_ref.deref().0.get();
@@ -275,6 +347,7 @@ impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for AudioProcessorGraph
     }
 
     fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        let block_start = Instant::now();
         let num_channels = data.num_channels();
         let num_samples = data.num_samples();
         // TODO: this is bad, but I'm not sure how to handle variable size buffers (maybe process multiple times)
@@ -287,6 +360,7 @@ impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for AudioProcessorGraph
         let buffers = handle.buffers.get();
         let process_order = handle.process_order.get();
         let process_order = process_order.deref();
+        let timings = handle.timings.get();
 
         // Push inputs in
         let mut outputs = dag.children(self.input_node);
@@ -334,6 +408,7 @@ impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for AudioProcessorGraph
                 let processor = processor_ref.deref().0.get();
                 let processor = unsafe { &mut *processor };
 
+                let start = Instant::now();
                 match processor {
                     NodeType::Simple(processor) => {
                         processor.process(context, &mut self.temporary_buffer);
@@ -343,6 +418,11 @@ impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for AudioProcessorGraph
                     }
                     NodeType::None => {}
                 }
+                if let Some(timing) = timings.get(&node_index) {
+                    handle
+                        .profiler
+                        .record_processor(timing, start, Instant::now());
+                }
             }
 
             let mut outputs = dag.children(node_index);
@@ -377,6 +457,10 @@ impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for AudioProcessorGraph
                 data.add(unsafe { &*buffer });
             }
         }
+
+        handle
+            .profiler
+            .record_block(block_start, Instant::now(), num_samples);
     }
 }
 
@@ -384,7 +468,7 @@ impl<P: AudioProcessor<SampleType = f32>> AudioProcessor for AudioProcessorGraph
 mod test {
     use std::time::Duration;
 
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
     use audio_processor_testing_helpers::{assert_f_eq, test_level_equivalence};
     use audio_processor_testing_helpers::{rms_level, sine_buffer};
 
@@ -409,6 +493,24 @@ mod test {
         ))));
     }
 
+    #[test]
+    fn test_process_is_realtime_safe() {
+        let mut graph = AudioProcessorGraph::default();
+        let gain = graph.add_node(NodeType::Simple(Box::new(MonoCopyProcessor::new(
+            GainProcessor::default(),
+        ))));
+        let pan = graph.add_node(NodeType::Simple(Box::new(PanProcessor::default())));
+        graph.add_connection(graph.input(), gain).unwrap();
+        graph.add_connection(gain, pan).unwrap();
+        graph.add_connection(pan, graph.output()).unwrap();
+
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            graph,
+            Default::default(),
+            10,
+        );
+    }
+
     #[test]
     fn test_create_graph_and_add_2_nodes() {
         let mut graph = AudioProcessorGraph::default();
@@ -449,7 +551,7 @@ mod test {
         let mut process_buffer = empty_buffer.clone();
         graph.prepare(&mut context);
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut process_buffer);
         });
 
@@ -477,7 +579,7 @@ mod test {
         let mut graph = AudioProcessorGraph::default();
         graph.add_connection(graph.input(), graph.output()).unwrap();
         graph.prepare(&mut context);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut buffer);
         });
         assert_f_eq!(*buffer.get(0, 0), 1.0);
@@ -520,7 +622,7 @@ mod test {
         graph.add_connection(graph.input(), sum_10_node).unwrap();
         graph.add_connection(sum_10_node, graph.output()).unwrap();
         graph.prepare(&mut context);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut buffer);
         });
         assert_f_eq!(*buffer.get(0, 0), 11.0);
@@ -570,7 +672,7 @@ mod test {
         graph.add_connection(node1, node2).unwrap();
         graph.add_connection(node2, graph.output()).unwrap();
         graph.prepare(&mut context);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut buffer);
         });
         assert_f_eq!(*buffer.get(0, 0), 100.0);
@@ -579,6 +681,104 @@ mod test {
         assert_f_eq!(*buffer.get(0, 3), 400.0);
     }
 
+    #[test]
+    fn test_remove_node_from_series_graph() {
+        let mut context = AudioContext::default();
+        context.settings.input_channels = 1;
+        context.settings.output_channels = 1;
+        context.settings.block_size = 4;
+
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, 4);
+        buffer.set(0, 0, 1.0);
+
+        struct Mult10Node {}
+        impl MonoAudioProcessor for Mult10Node {
+            type SampleType = f32;
+            fn m_process(
+                &mut self,
+                _context: &mut AudioContext,
+                sample: Self::SampleType,
+            ) -> Self::SampleType {
+                sample * 10.0
+            }
+        }
+
+        let mut graph = AudioProcessorGraph::default();
+        let node1 = graph.add_node(NodeType::Simple(Box::new(MonoCopyProcessor::new(
+            Mult10Node {},
+        ))));
+        let node2 = graph.add_node(NodeType::Simple(Box::new(MonoCopyProcessor::new(
+            Mult10Node {},
+        ))));
+        graph.add_connection(graph.input(), node1).unwrap();
+        graph.add_connection(node1, node2).unwrap();
+        graph.add_connection(node2, graph.output()).unwrap();
+        graph.prepare(&mut context);
+
+        let profiled_nodes = graph.handle().profiler().processors().len();
+        graph.handle().remove_node(node1);
+        assert!(graph.handle().node_timing(node1).is_none());
+        assert_eq!(
+            graph.handle().profiler().processors().len(),
+            profiled_nodes - 1
+        );
+        graph.add_connection(graph.input(), node2).unwrap();
+        graph.process(&mut context, &mut buffer);
+        assert_f_eq!(*buffer.get(0, 0), 10.0);
+    }
+
+    #[test]
+    fn test_nodes_are_profiled() {
+        let mut context = AudioContext::default();
+        context.settings.input_channels = 1;
+        context.settings.output_channels = 1;
+        context.settings.block_size = 4;
+        let mut buffer = AudioBuffer::empty();
+        buffer.resize(1, 4);
+
+        struct SleepNode {}
+        impl MonoAudioProcessor for SleepNode {
+            type SampleType = f32;
+            fn m_process(
+                &mut self,
+                _context: &mut AudioContext,
+                sample: Self::SampleType,
+            ) -> Self::SampleType {
+                std::thread::sleep(Duration::from_millis(1));
+                sample
+            }
+        }
+
+        let mut graph = AudioProcessorGraph::default();
+        let gain = graph.add_named_node(
+            "Gain",
+            NodeType::Simple(Box::new(MonoCopyProcessor::new(GainProcessor::default()))),
+        );
+        let sleep = graph.add_node(NodeType::Simple(Box::new(MonoCopyProcessor::new(
+            SleepNode {},
+        ))));
+        graph.add_connection(graph.input(), gain).unwrap();
+        graph.add_connection(gain, sleep).unwrap();
+        graph.add_connection(sleep, graph.output()).unwrap();
+        graph.prepare(&mut context);
+        for _ in 0..3 {
+            graph.process(&mut context, &mut buffer);
+        }
+
+        let handle = graph.handle();
+        let gain_timing = handle.node_timing(gain).unwrap();
+        let sleep_timing = handle.node_timing(sleep).unwrap();
+        assert_eq!(gain_timing.name(), "Gain");
+        assert_eq!(sleep_timing.name(), format!("Node {}", sleep.index()));
+        assert_eq!(gain_timing.histogram().count(), 3);
+        assert!(sleep_timing.histogram().percentile(50.0) >= Duration::from_millis(4));
+        assert!(gain_timing.histogram().max() < sleep_timing.histogram().max());
+        assert_eq!(handle.profiler().block_histogram().count(), 3);
+        // 4 samples at 44.1kHz have a ~90us budget, sleeping misses it
+        assert_eq!(handle.profiler().xrun_count(), 3);
+    }
+
     #[test]
     fn test_buffer_in_series() {
         let settings = AudioProcessorSettings::default();
@@ -612,7 +812,7 @@ mod test {
         graph.add_connection(node1, node2).unwrap();
         graph.add_connection(node2, graph.output()).unwrap();
         graph.prepare(&mut context);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut buffer);
         });
         assert_f_eq!(*buffer.get(0, 0), 100.0);
@@ -658,7 +858,7 @@ mod test {
         graph.add_connection(node2, graph.output()).unwrap();
         graph.prepare(&mut context);
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut buffer);
         });
 
@@ -710,7 +910,7 @@ mod test {
         graph.add_connection(node_b1, node_b2).unwrap();
         graph.add_connection(node_b2, graph.output()).unwrap();
         graph.prepare(&mut context);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut buffer);
         });
         assert_f_eq!(*buffer.get(0, 0), 200.0);
@@ -760,7 +960,7 @@ mod test {
             graph.add_connection(current_idx, graph.output()).unwrap();
         }
         graph.prepare(&mut context);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut buffer);
         });
         assert_f_eq!(*buffer.get(0, 0), 10000.0);
@@ -788,7 +988,7 @@ mod test {
 
         let mut process_buffer = sine_buffer.clone();
         graph.prepare(&mut context);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut process_buffer);
         });
 
@@ -827,7 +1027,7 @@ mod test {
         let _oscillator_idx = graph.add_node(NodeType::Simple(Box::new(MonoCopyProcessor::new(
             oscillator,
         ))));
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut process_buffer);
         });
 
@@ -867,7 +1067,7 @@ mod test {
         graph
             .add_connection(graph.input_node, oscillator_idx)
             .unwrap();
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut process_buffer);
         });
 
@@ -928,7 +1128,7 @@ mod test {
 
         let mut process_buffer = AudioBuffer::empty();
         process_buffer.resize(1, 3);
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut process_buffer);
         });
         let output = process_buffer.channel(0).to_vec();
@@ -971,7 +1171,7 @@ mod test {
         graph
             .add_connection(oscillator_idx, graph.output_node)
             .unwrap();
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             graph.process(&mut context, &mut process_buffer);
         });
 
@@ -34,6 +34,8 @@ use audio_garbage_collector::{make_shared, Shared};
 use audio_processor_traits::{AtomicF32, AudioBuffer, AudioContext, AudioProcessor};
 
 use self::constants::{build_envelope, DEFAULT_SAMPLE_RATE, DEFAULT_TEMPO};
+#[cfg(feature = "tempo-sync")]
+pub use self::playhead::TempoSyncMetronomePlayhead;
 pub use self::playhead::{DefaultMetronomePlayhead, MetronomePlayhead};
 use self::sound::MetronomeSoundSine;
 pub use self::sound::{MetronomeSound, MetronomeSoundType};
@@ -41,6 +43,8 @@ pub use self::sound::{MetronomeSound, MetronomeSoundType};
 mod constants;
 mod playhead;
 mod sound;
+#[cfg(test)]
+mod test_allocator;
 
 /// Public metronome API
 pub struct MetronomeProcessorHandle {
@@ -221,3 +225,17 @@ impl<P: MetronomePlayhead> MetronomeProcessor<P> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use audio_processor_testing_helpers::realtime::assert_processor_realtime_safe;
+
+    use super::*;
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        let processor = MetronomeProcessor::default();
+        processor.handle().set_tempo(240.0);
+        assert_processor_realtime_safe(processor, Default::default(), 100);
+    }
+}
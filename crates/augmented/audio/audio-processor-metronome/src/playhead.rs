@@ -100,6 +100,84 @@ impl MetronomePlayhead for DefaultMetronomePlayhead {
     }
 }
 
+/// Follows the tempo & phase of a network tempo sync session.
+///
+/// Tempo set on the metronome handle is ignored, the session tempo is used instead. Every 10ms
+/// worth of samples the beat position is nudged into phase with the session (see
+/// [`augmented_tempo_sync::align_beat`]).
+#[cfg(feature = "tempo-sync")]
+pub struct TempoSyncMetronomePlayhead {
+    inner: DefaultMetronomePlayhead,
+    reader: augmented_tempo_sync::TempoSyncReader,
+    samples_since_sync: u32,
+    sync_interval: u32,
+}
+
+#[cfg(feature = "tempo-sync")]
+impl TempoSyncMetronomePlayhead {
+    /// Beat drift which is tolerated before the position is corrected
+    const TOLERANCE_BEATS: f64 = 0.01;
+
+    pub fn new(reader: augmented_tempo_sync::TempoSyncReader) -> Self {
+        let mut inner = DefaultMetronomePlayhead::default();
+        inner.set_tempo(reader.tempo() as f32);
+        Self {
+            inner,
+            reader,
+            samples_since_sync: 0,
+            sync_interval: (DEFAULT_SAMPLE_RATE / 100.0) as u32,
+        }
+    }
+
+    fn sync(&mut self) {
+        self.samples_since_sync = 0;
+        self.inner.set_tempo(self.reader.tempo() as f32);
+        let position_beats = self.inner.position_beats();
+        let mut aligned_beats = self.reader.aligned_beat(position_beats);
+        // The play-head can't go before the start, use the next bar instead
+        if aligned_beats < 0.0 {
+            aligned_beats += self.reader.quantum();
+        }
+        if (aligned_beats - position_beats).abs() > Self::TOLERANCE_BEATS {
+            self.inner.playhead.set_position_beats(aligned_beats);
+        }
+    }
+}
+
+#[cfg(feature = "tempo-sync")]
+impl MetronomePlayhead for TempoSyncMetronomePlayhead {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.samples_since_sync = self.sync_interval;
+    }
+
+    fn set_tempo(&mut self, _tempo: f32) {
+        self.inner.set_tempo(self.reader.tempo() as f32);
+    }
+
+    fn prepare(&mut self, settings: &AudioProcessorSettings, _tempo: f32) {
+        self.inner.prepare(settings, self.reader.tempo() as f32);
+        self.sync_interval = ((settings.sample_rate() / 100.0) as u32).max(1);
+        self.samples_since_sync = self.sync_interval;
+    }
+
+    fn accept_samples(&mut self, samples: u32) {
+        self.samples_since_sync += samples;
+        if self.samples_since_sync >= self.sync_interval {
+            self.sync();
+        }
+        self.inner.accept_samples(samples);
+    }
+
+    fn tempo(&self) -> Option<f32> {
+        Some(self.reader.tempo() as f32)
+    }
+
+    fn position_beats(&self) -> f64 {
+        self.inner.position_beats()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::{DefaultMetronomePlayhead, MetronomePlayhead};
@@ -147,4 +225,35 @@ mod test {
         playhead.accept_samples(4410);
         assert_f_eq!(playhead.position_beats(), 0.2);
     }
+
+    #[cfg(feature = "tempo-sync")]
+    #[test]
+    fn test_tempo_sync_playhead_follows_the_session() {
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        use augmented_tempo_sync::{LocalNetwork, ManualClock, TempoSyncOptions, TempoSyncSession};
+
+        use super::TempoSyncMetronomePlayhead;
+
+        let clock = Arc::new(ManualClock::new(0));
+        let session = TempoSyncSession::new(
+            LocalNetwork::new().connect(),
+            clock.clone(),
+            TempoSyncOptions {
+                tempo: 60.0,
+                ..TempoSyncOptions::default()
+            },
+        );
+        clock.advance(Duration::from_millis(1500));
+
+        let mut playhead = TempoSyncMetronomePlayhead::new(session.reader());
+        let settings = AudioProcessorSettings::new(1000.0, 2, 2, 512);
+        playhead.prepare(&settings, 120.0);
+        playhead.set_tempo(120.0);
+        assert_eq!(playhead.tempo(), Some(60.0));
+
+        playhead.accept_samples(1);
+        assert!((playhead.position_beats() - 1.501).abs() < 1e-6);
+    }
 }
@@ -20,45 +20,46 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use basedrop::Shared;
+use audio_garbage_collector::Shared;
+use audio_processor_traits::parameters::{
+    AudioProcessorHandle, FloatType, ParameterSpec, ParameterType, ParameterValue,
+};
 
-use audio_processor_standalone_osc::{OscMap, OscServer};
+use crate::MultiChannelPitchShifterProcessorHandle;
 
-use crate::audio::multi_track_looper::parameters::LooperId;
-use crate::MultiTrackLooperHandle;
+pub struct GenericHandle(pub Shared<MultiChannelPitchShifterProcessorHandle>);
 
-pub fn setup_osc_server(handle: Shared<MultiTrackLooperHandle>) {
-    let mut osc_map: OscMap<Shared<MultiTrackLooperHandle>> = OscMap::default();
-    osc_map.add(
-        "/looper/record",
-        Box::new(|handle, _msg| {
-            log::info!("Toggle recording");
-            handle.start_recording(LooperId(0))
-        }),
-    );
+impl AudioProcessorHandle for GenericHandle {
+    fn name(&self) -> String {
+        "Pitch shifter".to_string()
+    }
 
-    osc_map.add(
-        "/looper/play",
-        Box::new(|handle, _msg| {
-            log::info!("Toggle playback");
-            handle.toggle_playback(LooperId(0))
-        }),
-    );
+    fn parameter_count(&self) -> usize {
+        1
+    }
 
-    osc_map.add(
-        "/looper/clear",
-        Box::new(|handle, _msg| {
-            log::info!("Clear");
-            handle.clear(LooperId(0));
-        }),
-    );
+    fn get_parameter_spec(&self, _index: usize) -> ParameterSpec {
+        ParameterSpec::new(
+            "Ratio".into(),
+            ParameterType::Float(FloatType {
+                range: (0.25, 4.0),
+                step: None,
+            }),
+        )
+    }
 
-    let osc_server = OscServer::new(handle, osc_map);
-    let _ = std::thread::Builder::new()
-        .name(String::from("looper_osc_server"))
-        .spawn(move || {
-            if let Err(err) = osc_server.start() {
-                log::error!("OscServer has exited with {}", err);
-            }
-        });
+    fn get_parameter(&self, index: usize) -> Option<ParameterValue> {
+        if index == 0 {
+            Some(self.0.ratio().into())
+        } else {
+            None
+        }
+    }
+
+    fn set_parameter(&self, index: usize, request: ParameterValue) {
+        let ParameterValue::Float { value } = request;
+        if index == 0 {
+            self.0.set_ratio(value);
+        }
+    }
 }
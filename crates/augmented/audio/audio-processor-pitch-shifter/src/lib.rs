@@ -36,10 +36,16 @@ use audio_garbage_collector::{make_shared, Shared};
 use audio_processor_analysis::fft_processor::{FftDirection, FftProcessor, FftProcessorOptions};
 use audio_processor_analysis::window_functions::{make_hann_vec, WindowFunctionType};
 use audio_processor_traits::num::Complex;
+use audio_processor_traits::parameters::{
+    make_handle_ref, AudioProcessorHandleProvider, AudioProcessorHandleRef,
+};
 use audio_processor_traits::simple_processor::MonoAudioProcessor;
 use audio_processor_traits::{AtomicF32, AudioBuffer, AudioContext, AudioProcessor, Zero};
 
-#[cfg(all(test, debug_assertions))]
+use generic_handle::GenericHandle;
+
+mod generic_handle;
+#[cfg(test)]
 mod test_allocator;
 
 fn make_vec(size: usize) -> Vec<f32> {
@@ -321,6 +327,10 @@ impl MultiChannelPitchShifterProcessorHandle {
     pub fn set_ratio(&self, ratio: f32) {
         self.ratio.set(ratio);
     }
+
+    pub fn ratio(&self) -> f32 {
+        self.ratio.get()
+    }
 }
 
 pub struct MultiChannelPitchShifterProcessor {
@@ -341,6 +351,12 @@ impl MultiChannelPitchShifterProcessor {
     }
 }
 
+impl AudioProcessorHandleProvider for MultiChannelPitchShifterProcessor {
+    fn generic_handle(&self) -> AudioProcessorHandleRef {
+        make_handle_ref(GenericHandle(self.handle.clone()))
+    }
+}
+
 impl Default for MultiChannelPitchShifterProcessor {
     fn default() -> Self {
         Self {
@@ -426,7 +442,7 @@ impl MonoAudioProcessor for PitchShifterProcessor {
 
 #[cfg(test)]
 mod test {
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
     use audio_processor_testing_helpers::{relative_path, rms_level};
 
     use audio_processor_file::{AudioFileProcessor, OutputAudioFileProcessor};
@@ -472,7 +488,7 @@ mod test {
         let mut context = AudioContext::default();
         pitch_shifter.prepare(&mut context);
 
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             pitch_shifter.process(&mut context, &mut input);
         });
 
@@ -497,4 +513,13 @@ mod test {
             .process(&mut stereo)
             .expect("Failed to write samples to wave file");
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            MultiChannelPitchShifterProcessor::default(),
+            Default::default(),
+            10,
+        );
+    }
 }
@@ -20,7 +20,4 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use assert_no_alloc::AllocDisabler;
-
-#[global_allocator]
-static A: AllocDisabler = AllocDisabler;
+audio_processor_testing_helpers::install_realtime_allocator!();
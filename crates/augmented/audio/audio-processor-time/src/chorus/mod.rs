@@ -20,13 +20,66 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
+use audio_garbage_collector::{make_shared, Shared};
+use audio_processor_traits::parameters::{
+    make_handle_ref, AudioProcessorHandleProvider, AudioProcessorHandleRef,
+};
 use audio_processor_traits::simple_processor::MonoAudioProcessor;
-use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor};
+use audio_processor_traits::{AtomicF32, AudioBuffer, AudioContext, AudioProcessor};
 use augmented_oscillator::Oscillator;
+use generic_handle::GenericHandle;
 
 use crate::MonoDelayProcessor;
 
+mod generic_handle;
+
+pub struct ChorusHandle {
+    rate: AtomicF32,
+    depth_secs: AtomicF32,
+    mix: AtomicF32,
+}
+
+impl Default for ChorusHandle {
+    fn default() -> Self {
+        Self {
+            rate: AtomicF32::new(3.0),
+            depth_secs: AtomicF32::new(0.001),
+            mix: AtomicF32::new(0.4),
+        }
+    }
+}
+
+impl ChorusHandle {
+    /// Modulation rate in Hz. Defaults to 3Hz
+    pub fn rate(&self) -> f32 {
+        self.rate.get()
+    }
+
+    pub fn set_rate(&self, value: f32) {
+        self.rate.set(value);
+    }
+
+    /// How far the delay time swings, in seconds. Defaults to 1ms
+    pub fn depth_secs(&self) -> f32 {
+        self.depth_secs.get()
+    }
+
+    pub fn set_depth_secs(&self, value: f32) {
+        self.depth_secs.set(value);
+    }
+
+    /// Level of the modulated signal added to the input. Defaults to 0.4
+    pub fn mix(&self) -> f32 {
+        self.mix.get()
+    }
+
+    pub fn set_mix(&self, value: f32) {
+        self.mix.set(value);
+    }
+}
+
 pub struct ChorusProcessor {
+    handle: Shared<ChorusHandle>,
     mono_delay_processor: Vec<MonoDelayProcessor<f32>>,
     oscillator: Oscillator<f32>,
 }
@@ -34,12 +87,25 @@ pub struct ChorusProcessor {
 impl Default for ChorusProcessor {
     fn default() -> Self {
         Self {
+            handle: make_shared(ChorusHandle::default()),
             mono_delay_processor: vec![],
             oscillator: Oscillator::sine(44100.0),
         }
     }
 }
 
+impl ChorusProcessor {
+    pub fn handle(&self) -> &Shared<ChorusHandle> {
+        &self.handle
+    }
+}
+
+impl AudioProcessorHandleProvider for ChorusProcessor {
+    fn generic_handle(&self) -> AudioProcessorHandleRef {
+        make_handle_ref(GenericHandle(self.handle.clone()))
+    }
+}
+
 impl AudioProcessor for ChorusProcessor {
     type SampleType = f32;
 
@@ -56,18 +122,47 @@ impl AudioProcessor for ChorusProcessor {
 
         self.oscillator
             .set_sample_rate(context.settings.sample_rate());
-        self.oscillator.set_frequency(3.0);
+        self.oscillator.set_frequency(self.handle.rate());
     }
 
     fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        self.oscillator.set_frequency(self.handle.rate());
+        let depth_secs = self.handle.depth_secs();
+        let mix = self.handle.mix();
+
         for frame_num in 0..data.num_samples() {
             let time = self.oscillator.next_sample();
 
             for (channel_num, delay) in self.mono_delay_processor.iter_mut().enumerate() {
                 let sample = &mut data.channels_mut()[channel_num][frame_num];
-                delay.handle().set_delay_time_secs(0.02 + time * 0.001);
-                *sample = *sample + 0.4 * delay.m_process(context, *sample)
+                delay.handle().set_delay_time_secs(0.02 + time * depth_secs);
+                *sample = *sample + mix * delay.m_process(context, *sample)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generic_handle_sets_parameters() {
+        let processor = ChorusProcessor::default();
+        let handle = processor.generic_handle();
+        assert_eq!(handle.name(), "Chorus");
+        handle.set_parameter(0, 5.0.into());
+        handle.set_parameter(2, 0.8.into());
+        assert_eq!(processor.handle().rate(), 5.0);
+        assert_eq!(processor.handle().mix(), 0.8);
+    }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            ChorusProcessor::default(),
+            Default::default(),
+            10,
+        );
+    }
+}
@@ -212,3 +212,19 @@ impl<Sample: Float + From<f32>> MonoAudioProcessor for MonoDelayProcessor<Sample
         delay_output
     }
 }
+
+#[cfg(test)]
+mod test {
+    use audio_processor_traits::simple_processor::MonoCopyProcessor;
+
+    use super::*;
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            MonoCopyProcessor::new(MonoDelayProcessor::<f32>::default()),
+            Default::default(),
+            10,
+        );
+    }
+}
@@ -314,3 +314,17 @@ impl AudioProcessor for FreeverbProcessor {
         // }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            FreeverbProcessor::default(),
+            Default::default(),
+            10,
+        );
+    }
+}
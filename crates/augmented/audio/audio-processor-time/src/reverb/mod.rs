@@ -29,5 +29,5 @@ pub mod mod_reverb;
 mod tuning;
 mod utils;
 
-#[cfg(all(test, debug_assertions))]
+#[cfg(test)]
 mod test_allocator;
@@ -28,6 +28,10 @@ use crate::mod_reverb::ModReverbHandle;
 pub struct GenericHandle(pub Shared<ModReverbHandle>);
 
 impl AudioProcessorHandle for GenericHandle {
+    fn name(&self) -> String {
+        "Mod reverb".to_string()
+    }
+
     fn parameter_count(&self) -> usize {
         0
     }
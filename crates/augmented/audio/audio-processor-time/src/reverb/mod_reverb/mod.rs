@@ -286,7 +286,7 @@ where
 
 #[cfg(test)]
 mod test {
-    use assert_no_alloc::assert_no_alloc;
+    use audio_processor_testing_helpers::realtime::assert_realtime_safe;
     use audio_processor_traits::AudioProcessorSettings;
 
     use super::*;
@@ -301,8 +301,17 @@ mod test {
         diffuser.prepare(&mut context);
 
         let mut frame = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
-        assert_no_alloc(|| {
+        assert_realtime_safe(|| {
             diffuser.process(&mut context, &mut frame);
         });
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            ModReverbProcessor::default(),
+            Default::default(),
+            10,
+        );
+    }
 }
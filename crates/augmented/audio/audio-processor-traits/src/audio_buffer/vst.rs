@@ -27,6 +27,7 @@ use super::AudioBuffer;
 
 pub struct VSTBufferHandler<SampleType> {
     buffer: AudioBuffer<SampleType>,
+    num_channels: usize,
 }
 
 impl<SampleType: Float> Default for VSTBufferHandler<SampleType> {
@@ -37,13 +38,24 @@ impl<SampleType: Float> Default for VSTBufferHandler<SampleType> {
 
 impl<SampleType: Float> VSTBufferHandler<SampleType> {
     pub fn new() -> Self {
+        Self::new_with_num_channels(2)
+    }
+
+    /// Handler for plugins with more than the stereo inputs/outputs, such as multi-output plugins.
+    /// Channels the host doesn't provide inputs for are silent.
+    pub fn new_with_num_channels(num_channels: usize) -> Self {
         Self {
             buffer: AudioBuffer::empty(),
+            num_channels,
         }
     }
 
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
     pub fn set_block_size(&mut self, block_size: usize) {
-        self.buffer.resize(2, block_size);
+        self.buffer.resize(self.num_channels, block_size);
     }
 
     pub fn with_buffer<F>(&mut self, buffer: &mut vst::buffer::AudioBuffer<SampleType>, f: F)
@@ -52,20 +64,26 @@ impl<SampleType: Float> VSTBufferHandler<SampleType> {
     {
         let num_samples = buffer.samples();
         let (inputs, mut outputs) = buffer.split();
-        self.buffer.resize(2, num_samples);
+        self.buffer.resize(self.num_channels, num_samples);
         {
-            for (channel, input) in inputs.into_iter().take(2).enumerate() {
+            let num_inputs = inputs.len().min(self.num_channels);
+            for (channel, input) in inputs.into_iter().take(num_inputs).enumerate() {
                 let buffer_slice = self.buffer.channel_mut(channel);
                 for (index, sample) in input.iter().enumerate() {
                     buffer_slice[index] = *sample;
                 }
             }
+            for channel in num_inputs..self.num_channels {
+                for sample in self.buffer.channel_mut(channel) {
+                    *sample = SampleType::zero();
+                }
+            }
         }
 
         f(&mut self.buffer);
 
         {
-            for (channel, output) in outputs.into_iter().take(2).enumerate() {
+            for (channel, output) in outputs.into_iter().take(self.num_channels).enumerate() {
                 let buffer_slice = self.buffer.channel(channel);
                 for (index, sample) in output.iter_mut().enumerate() {
                     *sample = buffer_slice[index];
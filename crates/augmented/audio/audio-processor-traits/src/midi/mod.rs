@@ -30,6 +30,10 @@ pub mod vst;
 pub trait MidiMessageLike {
     fn is_midi(&self) -> bool;
     fn bytes(&self) -> Option<&[u8]>;
+    /// Time the message was received at in micro-seconds, if the host provides it
+    fn timestamp(&self) -> Option<u64> {
+        None
+    }
 }
 
 /// A MIDI event processor
@@ -135,4 +135,13 @@ mod test {
             assert_f_eq!(*sample, 0.8);
         }
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            simple_processor::MonoCopyProcessor::new(GainProcessor::<f32>::default()),
+            Default::default(),
+            10,
+        );
+    }
 }
@@ -41,5 +41,5 @@ pub mod pan;
 /// Convert mono signals to stereo
 pub mod stereo;
 
-#[cfg(all(test, debug_assertions))]
+#[cfg(test)]
 mod test_allocator;
@@ -115,4 +115,13 @@ mod test {
 
         mono.process(&mut context, &mut input);
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            StereoToMonoProcessor::<f32>::default(),
+            Default::default(),
+            10,
+        );
+    }
 }
@@ -103,16 +103,27 @@ impl<SampleType: Float + rand::distributions::uniform::SampleUniform> MonoAudioP
 
 #[cfg(test)]
 mod test {
+    use audio_processor_traits::simple_processor::MonoCopyProcessor;
+
     use super::*;
 
     #[test]
     fn test_no_alloc() {
         let mut context = AudioContext::default();
         let mut processor = WhiteNoiseProcessor::default();
-        assert_no_alloc::assert_no_alloc(|| {
+        audio_processor_testing_helpers::realtime::assert_realtime_safe(|| {
             for i in 0..10 {
                 processor.m_process(&mut context, i as f32);
             }
         })
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            MonoCopyProcessor::new(WhiteNoiseProcessor::<f32>::default()),
+            Default::default(),
+            10,
+        );
+    }
 }
@@ -147,4 +147,13 @@ mod test {
             assert_f_eq!(left, 0.0);
         }
     }
+
+    #[test]
+    fn test_process_is_realtime_safe() {
+        audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+            PanProcessor::<f32>::default(),
+            Default::default(),
+            10,
+        );
+    }
 }
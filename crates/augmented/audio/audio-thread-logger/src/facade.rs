@@ -0,0 +1,56 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::{output, AudioThreadLogger, OUTPUT};
+
+/// Install a global [`log`] logger which queues records onto the [`AudioThreadLogger`], making
+/// `log::info!` and friends audio-thread safe. `inner` receives the records on the background
+/// thread.
+///
+/// Records are formatted into fixed-size buffers, so long messages are truncated.
+pub fn init_log_facade(inner: Box<dyn Log>, max_level: LevelFilter) -> Result<(), SetLoggerError> {
+    // The background thread must write onto `inner`, as the global logger will be the facade
+    let _ = OUTPUT.set(inner);
+    AudioThreadLogger::init();
+    log::set_boxed_logger(Box::new(AudioThreadLogFacade))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+struct AudioThreadLogFacade;
+
+impl Log for AudioThreadLogFacade {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        AudioThreadLogger::handle().log_fmt(record.level(), record.target(), *record.args());
+    }
+
+    fn flush(&self) {
+        output().flush();
+    }
+}
@@ -20,7 +20,32 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
-use std::sync::atomic::{AtomicBool, Ordering};
+
+//! Logging from the audio-thread.
+//!
+//! Messages are formatted into fixed-size buffers & pushed onto a lock-free queue, so logging
+//! doesn't allocate or lock. A background thread forwards them onto the [`log`] logger, tagged
+//! with the sample time of the handle & the wall time at which they were logged.
+//!
+//! ```
+//! use augmented_audio_thread_logger::{audio_thread_warn, AudioThreadLogger};
+//!
+//! // Spawns the background thread, should be called before starting audio
+//! AudioThreadLogger::init();
+//!
+//! // On the audio-thread
+//! let handle = AudioThreadLogger::handle();
+//! handle.advance_sample_time(512);
+//! audio_thread_warn!("clipping gain={:.2}", 1.5);
+//! ```
+//!
+//! With the `log-facade` feature, [`init_log_facade`] installs a [`log::Log`] implementation
+//! which goes through the same queue, so existing `log::warn!` calls become audio-thread safe.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
 
 use basedrop::Shared;
 use lazy_static::lazy_static;
@@ -30,44 +55,118 @@ use audio_garbage_collector::make_shared;
 
 use augmented_longbackoff::LongBackoff;
 
+#[cfg(feature = "log-facade")]
+pub use facade::init_log_facade;
+pub use log::Level;
+pub use message::{LogMessage, MessageBuffer, MESSAGE_CAPACITY, TARGET_CAPACITY};
+
+#[cfg(feature = "log-facade")]
+mod facade;
+mod message;
+
+/// Default number of messages which may be queued before messages are dropped
+pub const DEFAULT_CAPACITY: usize = 1024;
+
 lazy_static! {
     static ref AUDIO_THREAD_LOGGER: AudioThreadLogger = AudioThreadLogger::new();
 }
 
-#[allow(unused)]
-struct LogMessage {
-    level: log::Level,
-    message: &'static str,
+/// Where the background thread writes messages onto. Defaults to the global [`log::logger`].
+static OUTPUT: OnceLock<Box<dyn log::Log>> = OnceLock::new();
+
+fn output() -> &'static dyn log::Log {
+    match OUTPUT.get() {
+        Some(output) => output.as_ref(),
+        None => log::logger(),
+    }
 }
 
-#[allow(unused)]
 pub struct AudioThreadLoggerHandle {
     queue: Queue<LogMessage>,
+    sample_time: AtomicU64,
+    dropped_messages: AtomicUsize,
 }
 
 impl AudioThreadLoggerHandle {
-    #[allow(unused)]
+    fn new(capacity: usize) -> Self {
+        Self {
+            queue: Queue::new(capacity),
+            sample_time: AtomicU64::new(0),
+            dropped_messages: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn error(&self, message: &'static str) {
+        self.log(Level::Error, message);
+    }
+
+    pub fn warn(&self, message: &'static str) {
+        self.log(Level::Warn, message);
+    }
+
     pub fn info(&self, message: &'static str) {
-        self.queue.push(LogMessage {
-            level: log::Level::Info,
-            message,
-        });
+        self.log(Level::Info, message);
+    }
+
+    pub fn debug(&self, message: &'static str) {
+        self.log(Level::Debug, message);
+    }
+
+    pub fn trace(&self, message: &'static str) {
+        self.log(Level::Trace, message);
+    }
+
+    pub fn log(&self, level: Level, message: &'static str) {
+        self.log_fmt(level, "audio_thread", format_args!("{}", message));
+    }
+
+    /// Format `args` into a fixed-size buffer & queue the message. Doesn't allocate, unless one
+    /// of the arguments allocates on its `Display` implementation. Numbers & strings don't.
+    pub fn log_fmt(&self, level: Level, target: &str, args: fmt::Arguments) {
+        if level > log::max_level() {
+            return;
+        }
+
+        let message = LogMessage::new(level, target, args, self.sample_time());
+        if !self.queue.push(message) {
+            self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Sample time attached to messages, processors should update it on each block
+    pub fn set_sample_time(&self, sample_time: u64) {
+        self.sample_time.store(sample_time, Ordering::Relaxed);
+    }
+
+    pub fn advance_sample_time(&self, num_samples: u64) {
+        self.sample_time.fetch_add(num_samples, Ordering::Relaxed);
+    }
+
+    pub fn sample_time(&self) -> u64 {
+        self.sample_time.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages dropped because the queue was full
+    pub fn dropped_messages(&self) -> usize {
+        self.dropped_messages.load(Ordering::Relaxed)
     }
 }
 
 pub struct AudioThreadLogger {
     is_running: Shared<AtomicBool>,
-    #[allow(unused)]
     handle: Shared<AudioThreadLoggerHandle>,
 }
 
 impl AudioThreadLogger {
-    #[allow(unused)]
     fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create a logger with its own queue & background thread. Most uses should go through the
+    /// global [`AudioThreadLogger::handle`] instead.
+    pub fn with_capacity(capacity: usize) -> Self {
         let is_running = make_shared(AtomicBool::new(true));
-        let handle = make_shared(AudioThreadLoggerHandle {
-            queue: Queue::new(100),
-        });
+        let handle = make_shared(AudioThreadLoggerHandle::new(capacity));
         {
             let is_running = is_running.clone();
             let handle = handle.clone();
@@ -80,13 +179,27 @@ impl AudioThreadLogger {
         Self { is_running, handle }
     }
 
-    #[allow(unused)]
     fn run(is_running: Shared<AtomicBool>, handle: Shared<AudioThreadLoggerHandle>) {
-        log::info!("Starting audio-thread-logger");
+        let start = Instant::now();
         let mut long_backoff = LongBackoff::new();
+        let mut dropped_messages = 0;
+        log_record(Level::Info, format_args!("Starting audio-thread-logger"));
+
         while is_running.load(Ordering::Relaxed) {
+            let current_dropped_messages = handle.dropped_messages();
+            if current_dropped_messages != dropped_messages {
+                log_record(
+                    Level::Warn,
+                    format_args!(
+                        "audio-thread-logger dropped {} messages",
+                        current_dropped_messages - dropped_messages
+                    ),
+                );
+                dropped_messages = current_dropped_messages;
+            }
+
             if let Some(message) = handle.queue.pop() {
-                log::log!(message.level, "{}", message.message);
+                Self::write_message(&message, start);
                 long_backoff.reset();
             } else {
                 long_backoff.snooze();
@@ -94,10 +207,37 @@ impl AudioThreadLogger {
         }
     }
 
-    #[allow(unused)]
+    fn write_message(message: &LogMessage, start: Instant) {
+        let wall_time = message.instant.saturating_duration_since(start);
+        output().log(
+            &log::Record::builder()
+                .level(message.level)
+                .target(message.target.as_str())
+                .args(format_args!(
+                    "[sample={} t={:.6}s] {}",
+                    message.sample_time,
+                    wall_time.as_secs_f64(),
+                    message.message
+                ))
+                .build(),
+        );
+    }
+
+    /// Start the global logger. Otherwise it's started on the first call to
+    /// [`AudioThreadLogger::handle`], which allocates & spawns a thread, so this should be called
+    /// before starting audio.
+    pub fn init() {
+        lazy_static::initialize(&AUDIO_THREAD_LOGGER);
+    }
+
+    /// Handle to the global logger
     pub fn handle() -> &'static Shared<AudioThreadLoggerHandle> {
         &AUDIO_THREAD_LOGGER.handle
     }
+
+    pub fn logger_handle(&self) -> &Shared<AudioThreadLoggerHandle> {
+        &self.handle
+    }
 }
 
 impl Drop for AudioThreadLogger {
@@ -106,6 +246,54 @@ impl Drop for AudioThreadLogger {
     }
 }
 
+fn log_record(level: Level, args: fmt::Arguments) {
+    output().log(
+        &log::Record::builder()
+            .level(level)
+            .target("audio_thread_logger")
+            .args(args)
+            .build(),
+    );
+}
+
+/// Log a formatted message from the audio-thread onto the global logger, see
+/// [`AudioThreadLoggerHandle::log_fmt`]
+#[macro_export]
+macro_rules! audio_thread_log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::AudioThreadLogger::handle().log_fmt(
+            $level,
+            module_path!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+#[macro_export]
+macro_rules! audio_thread_error {
+    ($($arg:tt)+) => { $crate::audio_thread_log!($crate::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! audio_thread_warn {
+    ($($arg:tt)+) => { $crate::audio_thread_log!($crate::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! audio_thread_info {
+    ($($arg:tt)+) => { $crate::audio_thread_log!($crate::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! audio_thread_debug {
+    ($($arg:tt)+) => { $crate::audio_thread_log!($crate::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! audio_thread_trace {
+    ($($arg:tt)+) => { $crate::audio_thread_log!($crate::Level::Trace, $($arg)+) };
+}
+
 #[cfg(test)]
 mod test {
     use std::time::Duration;
@@ -135,4 +323,46 @@ mod test {
         logger.info("Global logger");
         std::thread::sleep(Duration::from_millis(20));
     }
+
+    #[test]
+    fn test_log_with_macros() {
+        wisual_logger::init_from_env();
+        AudioThreadLogger::init();
+        audio_thread_warn!("value={}", 10);
+        audio_thread_log!(Level::Info, "sample={:.3}", 0.25);
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_handle_queues_levels_and_sample_time() {
+        // Other tests may lower this to the env logger's level, so only warnings & errors are used
+        log::set_max_level(log::LevelFilter::Trace);
+        let handle = AudioThreadLoggerHandle::new(10);
+        handle.set_sample_time(100);
+        handle.error("error");
+        handle.advance_sample_time(50);
+        handle.log_fmt(Level::Warn, "target", format_args!("frame={}", 3));
+
+        let message = handle.queue.pop().unwrap();
+        assert_eq!(message.level, Level::Error);
+        assert_eq!(message.message.as_str(), "error");
+        assert_eq!(message.sample_time, 100);
+
+        let message = handle.queue.pop().unwrap();
+        assert_eq!(message.level, Level::Warn);
+        assert_eq!(message.target.as_str(), "target");
+        assert_eq!(message.message.as_str(), "frame=3");
+        assert_eq!(message.sample_time, 150);
+        assert!(message.instant.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_handle_counts_dropped_messages() {
+        log::set_max_level(log::LevelFilter::Trace);
+        let handle = AudioThreadLoggerHandle::new(2);
+        for _ in 0..5 {
+            handle.warn("message");
+        }
+        assert_eq!(handle.dropped_messages(), 3);
+    }
 }
@@ -0,0 +1,165 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::fmt::{self, Write};
+use std::time::Instant;
+
+/// Maximum length of a formatted message, longer messages are truncated
+pub const MESSAGE_CAPACITY: usize = 256;
+/// Maximum length of a message target
+pub const TARGET_CAPACITY: usize = 64;
+
+/// Fixed-size string buffer. Writes past its capacity are truncated rather than allocating.
+#[derive(Clone)]
+pub struct MessageBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> Default for MessageBuffer<N> {
+    fn default() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+}
+
+impl<const N: usize> MessageBuffer<N> {
+    pub fn as_str(&self) -> &str {
+        // Only whole UTF-8 characters are written
+        std::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+}
+
+impl<const N: usize> Write for MessageBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = N - self.len;
+        let mut end = s.len().min(available);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        if end < s.len() {
+            self.truncated = true;
+        }
+        // Truncation isn't an error, so formatting the remaining arguments is harmless
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for MessageBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())?;
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Debug for MessageBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// A message logged from the audio-thread
+pub struct LogMessage {
+    pub level: log::Level,
+    pub target: MessageBuffer<TARGET_CAPACITY>,
+    pub message: MessageBuffer<MESSAGE_CAPACITY>,
+    /// Sample time of the handle when the message was logged
+    pub sample_time: u64,
+    /// Wall time when the message was logged
+    pub instant: Instant,
+}
+
+impl LogMessage {
+    pub fn new(level: log::Level, target: &str, args: fmt::Arguments, sample_time: u64) -> Self {
+        let mut message = Self {
+            level,
+            target: MessageBuffer::default(),
+            message: MessageBuffer::default(),
+            sample_time,
+            instant: Instant::now(),
+        };
+        let _ = message.target.write_str(target);
+        let _ = message.message.write_fmt(args);
+        message
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_numbers() {
+        let mut buffer = MessageBuffer::<64>::default();
+        write!(buffer, "gain={:.2} frame={}", 0.5_f32, 1024).unwrap();
+        assert_eq!(buffer.as_str(), "gain=0.50 frame=1024");
+        assert!(!buffer.is_truncated());
+    }
+
+    #[test]
+    fn test_truncates_long_messages() {
+        let mut buffer = MessageBuffer::<8>::default();
+        buffer.write_str("hello world").unwrap();
+        assert_eq!(buffer.as_str(), "hello wo");
+        assert!(buffer.is_truncated());
+        assert_eq!(buffer.to_string(), "hello wo...");
+    }
+
+    #[test]
+    fn test_truncates_on_char_boundary() {
+        let mut buffer = MessageBuffer::<4>::default();
+        write!(buffer, "abcé").unwrap();
+        assert_eq!(buffer.as_str(), "abc");
+    }
+
+    #[test]
+    fn test_message_from_arguments() {
+        let message = LogMessage::new(
+            log::Level::Warn,
+            "looper",
+            format_args!("xrun at {}", 512),
+            1000,
+        );
+        assert_eq!(message.target.as_str(), "looper");
+        assert_eq!(message.message.as_str(), "xrun at 512");
+        assert_eq!(message.sample_time, 1000);
+    }
+}
@@ -0,0 +1,68 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+//! Runs in its own binary, as it installs the global `log` logger.
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use augmented_audio_thread_logger::{init_log_facade, AudioThreadLogger};
+
+struct CapturingLogger {
+    messages: &'static Mutex<Vec<String>>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.messages.lock().unwrap().push(format!(
+            "{} {} {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn test_log_macros_go_through_queue() {
+    let messages: &'static Mutex<Vec<String>> = Box::leak(Box::new(Mutex::new(vec![])));
+    init_log_facade(Box::new(CapturingLogger { messages }), LevelFilter::Info).unwrap();
+
+    AudioThreadLogger::handle().set_sample_time(128);
+    log::warn!("buffer size={}", 64);
+    std::thread::sleep(Duration::from_millis(200));
+
+    let messages = messages.lock().unwrap();
+    let message = messages
+        .iter()
+        .find(|message| message.contains("buffer size=64"))
+        .expect("Message wasn't forwarded");
+    assert!(message.starts_with("WARN "));
+    assert!(message.contains("[sample=128 t="));
+}
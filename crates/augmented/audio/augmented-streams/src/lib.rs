@@ -20,6 +20,8 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
+pub use ringbuf;
+
 pub trait ProducerProcedure {
     type Item;
 
@@ -66,10 +68,40 @@ where
 }
 
 pub struct ConsumerActor<T> {
-    #[allow(dead_code)]
     rx: ringbuf::Consumer<T>,
 }
 
+impl<T> ConsumerActor<T> {
+    pub fn new(rx: ringbuf::Consumer<T>) -> Self {
+        Self { rx }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.rx.pop()
+    }
+
+    /// Drop up to `count` items, returns the number of items dropped
+    pub fn discard(&mut self, count: usize) -> usize {
+        self.rx.discard(count)
+    }
+
+    /// Number of items available
+    pub fn len(&self) -> usize {
+        self.rx.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rx.is_empty()
+    }
+}
+
+impl<T: Copy> ConsumerActor<T> {
+    /// Pop items onto `output`, returns the number of items popped
+    pub fn pop_slice(&mut self, output: &mut [T]) -> usize {
+        self.rx.pop_slice(output)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -84,4 +116,20 @@ mod test {
         let value = rx.pop().unwrap();
         assert_eq!(value, 10);
     }
+
+    #[test]
+    fn test_consumer_actor() {
+        let (mut tx, rx) = ringbuf::RingBuffer::new(10).split();
+        let mut consumer = ConsumerActor::new(rx);
+        assert!(consumer.is_empty());
+
+        tx.push_slice(&[1, 2, 3, 4]);
+        assert_eq!(consumer.len(), 4);
+        assert_eq!(consumer.discard(1), 1);
+        let mut output = [0; 2];
+        assert_eq!(consumer.pop_slice(&mut output), 2);
+        assert_eq!(output, [2, 3]);
+        assert_eq!(consumer.pop(), Some(4));
+        assert_eq!(consumer.pop(), None);
+    }
 }
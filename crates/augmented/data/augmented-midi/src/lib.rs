@@ -81,3 +81,6 @@ pub use types::*;
 mod parser;
 mod serializer;
 mod types;
+
+#[cfg(test)]
+audio_processor_testing_helpers::install_realtime_allocator!();
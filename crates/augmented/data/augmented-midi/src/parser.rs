@@ -540,20 +540,49 @@ mod test {
         let input_path = format!("{}/bach_846.mid", env!("CARGO_MANIFEST_DIR"));
         let file_contents = std::fs::read(input_path).unwrap();
         // let file_contents: Vec<u8> = file_contents.into_iter().take(8000).collect();
-        let (_rest, _midi_stream) = assert_no_alloc::assert_no_alloc(|| {
-            parse_midi_file::<String, Vec<u8>>(&file_contents).unwrap()
-        });
+        let (_rest, _midi_stream) = parse_midi_file::<String, Vec<u8>>(&file_contents).unwrap();
     }
 
     #[test]
     fn test_parse_midi_file_smoke_test_no_alloc() {
+        use audio_processor_testing_helpers::realtime::{check_realtime_safe, ViolationKind};
+
         let input_path = format!("{}/bach_846.mid", env!("CARGO_MANIFEST_DIR"));
         let file_contents = std::fs::read(input_path).unwrap();
-        // let file_contents: Vec<u8> = file_contents.into_iter().take(8000).collect();
-        let (_rest, _midi_stream) = assert_no_alloc::assert_no_alloc(|| {
-            parse_midi_file::<&str, &[u8]>(&file_contents).unwrap()
+        let (_rest, midi_file) = parse_midi_file::<&str, &[u8]>(&file_contents).unwrap();
+
+        // Only the chunk list and each track's event list are allocated, events borrow from the
+        // input
+        let violations = check_realtime_safe(|| {
+            parse_midi_file::<&str, &[u8]>(&file_contents).unwrap();
+        })
+        .unwrap_err();
+        let num_tracks = midi_file
+            .chunks()
+            .iter()
+            .filter(|chunk| matches!(chunk, MIDIFileChunk::Track { .. }))
+            .count();
+        assert_eq!(violations.count, 2 * (num_tracks + 1));
+        assert!(violations.violations.iter().all(|violation| matches!(
+            violation.kind,
+            ViolationKind::Allocation { .. } | ViolationKind::Deallocation { .. }
+        )));
+    }
+
+    #[test]
+    fn test_parse_midi_event_is_realtime_safe() {
+        let messages: [&[u8]; 4] = [
+            &[0x90, 0x3C, 0x7F],
+            &[0x80, 0x3C, 0x00],
+            &[0xB0, 0x01, 0x40],
+            &[0xE3, 0x54, 0x39],
+        ];
+        let mut state = ParserState::default();
+        audio_processor_testing_helpers::realtime::assert_realtime_safe(|| {
+            for message in messages {
+                parse_midi_event::<&[u8]>(message, &mut state).unwrap();
+            }
         });
-        // println!("{:?}", midi_stream);
     }
 
     #[test]
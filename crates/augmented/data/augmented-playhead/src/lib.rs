@@ -143,6 +143,23 @@ impl PlayHead {
         self.accept_samples(0);
     }
 
+    /// Move the play-head to `beats`. Requires a tempo to be set, otherwise only the beat position
+    /// is updated.
+    pub fn set_position_beats(&self, beats: f64) {
+        self.position_beats.set(beats);
+        if let Some(tempo) = self.options.tempo.inner() {
+            let seconds = beats * 60.0 / tempo as f64;
+            self.position_us
+                .store((seconds * 1_000_000.0) as u64, Ordering::Relaxed);
+            if let Some(ticks_per_quarter_note) = self.options.ticks_per_quarter_note.inner() {
+                self.position_ticks.store(
+                    (beats * ticks_per_quarter_note as f64) as u32,
+                    Ordering::Relaxed,
+                );
+            }
+        }
+    }
+
     pub fn set_tempo(&self, tempo: f32) {
         self.options.tempo.set(Some(tempo));
     }
@@ -232,6 +249,20 @@ mod test {
         assert!((play_head.position_beats() - 384.0).abs() < f64::EPSILON);
     }
 
+    #[test]
+    fn test_set_position_beats() {
+        let options = PlayHeadOptions::new(Some(44100.0), Some(120.0), Some(32));
+        let play_head = PlayHead::new(options);
+        play_head.set_position_beats(3.0);
+        assert!((play_head.position_beats() - 3.0).abs() < f64::EPSILON);
+        assert!((play_head.position_seconds() - 1.5).abs() < f32::EPSILON);
+        assert_eq!(play_head.position_ticks(), 96);
+
+        // 1 beat at 120bpm
+        play_head.accept_samples(22050);
+        assert!((play_head.position_beats() - 4.0).abs() < 1e-6);
+    }
+
     // #[test]
     // fn test_accept_samples_loop() {
     //     let inverse_sample_rate = 1.0 / 44100.0;
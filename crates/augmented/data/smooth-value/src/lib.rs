@@ -109,7 +109,12 @@ impl InterpolatedValue {
             self.current_value += interpolation_state.tick_increment;
 
             // Reset internal state & don't let the value exceed the target.
-            if self.current_value >= interpolation_state.target {
+            let reached_target = if interpolation_state.tick_increment >= 0.0 {
+                self.current_value >= interpolation_state.target
+            } else {
+                self.current_value <= interpolation_state.target
+            };
+            if reached_target {
                 self.current_value = interpolation_state.target;
                 self.interpolation_state = None;
             }
@@ -228,11 +233,19 @@ mod tests {
         // Go back to 0
         value.set(0.0);
 
-        // Tick the value another 22.05k times, the current value should be 0.
+        // Moving down is also smoothed, half-way through the value should be 25.
         for _ in 0..22050 {
             value.tick();
         }
+        assert!((value.get() - 25.0).abs() < 0.01);
+
+        // Tick the value another 22.05k times (plus some slack for accumulated float error), the
+        // current value should be 0.
+        for _ in 0..22100 {
+            value.tick();
+        }
         assert_approx_equals(value.get(), 0.0);
+        assert!(value.get() >= 0.0);
     }
 
     fn assert_approx_equals(value: f32, target: f32) {
@@ -28,6 +28,11 @@ use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
+use audio_processor_analysis::loudness::analyze_loudness;
+use audio_processor_analysis::spectral_features::{
+    extract_spectral_features, SpectralFeatures, SpectralFeaturesOptions,
+};
+use audio_processor_traits::AudioBuffer;
 use clap::Parser;
 use dasp::Signal;
 use hound::{SampleFormat, WavReader, WavSpec};
@@ -35,7 +40,9 @@ use num_complex::Complex;
 use rayon::prelude::*;
 use rustfft::FftPlanner;
 
-use crate::model::{Args, AudioMetadata, AudioSimilarityResult, Commands, CompareResults};
+use crate::model::{
+    Args, AudioMetadata, AudioSimilarityResult, Commands, CompareResults, Loudness, SpectralSummary,
+};
 use crate::server::start_server;
 
 mod logger;
@@ -180,7 +187,7 @@ fn run_compare(targets: Vec<String>) -> (Vec<String>, Arc<CompareResults>) {
         })
         .collect::<Vec<_>>();
 
-    let metadatas = readers
+    let mut metadatas = readers
         .iter()
         .map(|(path, reader)| AudioMetadata {
             path: path.clone(),
@@ -196,6 +203,8 @@ fn run_compare(targets: Vec<String>) -> (Vec<String>, Arc<CompareResults>) {
             )
             .as_secs_f32(),
             spec: reader.spec().into(),
+            loudness: None,
+            spectral: None,
         })
         .collect::<Vec<_>>();
     log::info!("Read metadatas: {:#?}", metadatas);
@@ -212,6 +221,26 @@ fn run_compare(targets: Vec<String>) -> (Vec<String>, Arc<CompareResults>) {
         .collect::<Vec<(String, Vec<[f32; 2]>)>>();
     log::debug!("Read files.");
 
+    for (metadata, (_, file)) in metadatas.iter_mut().zip(files.iter()) {
+        let loudness = compute_loudness(metadata.spec.sample_rate as f32, file);
+        log::info!("Loudness file={} {:?}", metadata.path, loudness);
+        metadata.loudness = Some(loudness);
+    }
+
+    let spectral_features = metadatas
+        .iter_mut()
+        .zip(files.iter())
+        .map(|(metadata, (_, file))| {
+            let features = compute_spectral_features(metadata.spec.sample_rate as f32, file);
+            metadata.spectral = Some(SpectralSummary {
+                centroid: features.centroid,
+                flatness: features.flatness,
+                rolloff: features.rolloff,
+            });
+            features
+        })
+        .collect::<Vec<_>>();
+
     let image_paths = files
         .iter()
         .map(|(name, file)| draw_audio_file(name, file))
@@ -226,6 +255,7 @@ fn run_compare(targets: Vec<String>) -> (Vec<String>, Arc<CompareResults>) {
                     compute_cross_correlation_similarity(file1, file2);
                 let spectral_similarity = compute_spectral_similarity(file1, file2);
                 let delta_magnitude = compute_delta_magnitude(file1, file2);
+                let mfcc_distance = spectral_features[i].mfcc_distance(&spectral_features[j]);
                 let deltas: Vec<f32> = file1
                     .iter()
                     .zip(file2.iter())
@@ -240,12 +270,13 @@ fn run_compare(targets: Vec<String>) -> (Vec<String>, Arc<CompareResults>) {
                 );
 
                 log::info!(
-                    "Similarity between file1={} file2={} cross_correlation_similarity={} spectral_similarity={} delta_magnitude={}",
+                    "Similarity between file1={} file2={} cross_correlation_similarity={} spectral_similarity={} delta_magnitude={} mfcc_distance={}",
                     name1,
                     name2,
                     cross_correlation_similarity,
                     spectral_similarity,
-                    delta_magnitude
+                    delta_magnitude,
+                    mfcc_distance
                 );
                 similarities.push(AudioSimilarityResult {
                     file1: name1.clone(),
@@ -253,6 +284,7 @@ fn run_compare(targets: Vec<String>) -> (Vec<String>, Arc<CompareResults>) {
                     cross_correlation_similarity: cross_correlation_similarity.max(0.0),
                     spectral_similarity: spectral_similarity.max(0.0),
                     delta_magnitude: delta_magnitude.max(0.0),
+                    mfcc_distance,
                 });
             }
         }
@@ -277,6 +309,29 @@ fn draw_audio_file(name: &str, file: &[[f32; 2]]) -> String {
     format!("{}--{}.png", name, "audio")
 }
 
+/// Silent files measure -inf, which JSON can't represent, so levels are clamped to this floor
+const LOUDNESS_FLOOR_DB: f32 = -144.0;
+
+fn compute_loudness(sample_rate: f32, file: &[[f32; 2]]) -> Loudness {
+    let samples: Vec<f32> = file.iter().flat_map(|frame| *frame).collect();
+    let buffer = AudioBuffer::from_interleaved(2, &samples);
+    let analysis = analyze_loudness(sample_rate, &buffer);
+    Loudness {
+        integrated_loudness: analysis.integrated_loudness.max(LOUDNESS_FLOOR_DB),
+        loudness_range: analysis.loudness_range.max(0.0),
+        true_peak: analysis.true_peak.max(LOUDNESS_FLOOR_DB),
+    }
+}
+
+/// Mean spectral features over the whole file
+fn compute_spectral_features(sample_rate: f32, file: &[[f32; 2]]) -> SpectralFeatures {
+    let samples: Vec<f32> = file.iter().flat_map(|frame| *frame).collect();
+    let buffer = AudioBuffer::from_interleaved(2, &samples);
+    let frames =
+        extract_spectral_features(sample_rate, SpectralFeaturesOptions::default(), &buffer);
+    SpectralFeatures::mean(&frames).unwrap_or_default()
+}
+
 fn read_file(reader: WavReader<BufReader<File>>) -> impl Signal<Frame = [f32; 2]> {
     let spec = reader.spec();
     let duration = reader.duration();
@@ -476,6 +531,15 @@ mod test {
         assert_eq!(similarity, 1.0);
     }
 
+    #[test]
+    fn test_loudness_of_silence_is_clamped() {
+        let silence = vec![[0.0, 0.0]; 44100];
+        let loudness = compute_loudness(44100.0, &silence);
+        assert_eq!(loudness.integrated_loudness, LOUDNESS_FLOOR_DB);
+        assert_eq!(loudness.loudness_range, 0.0);
+        assert_eq!(loudness.true_peak, LOUDNESS_FLOOR_DB);
+    }
+
     #[test]
     fn test_identical_signals() {
         let signal1 = vec![0.5, 0.2, 0.8, 0.3];
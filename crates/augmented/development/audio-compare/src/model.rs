@@ -85,6 +85,28 @@ impl From<WavSpec> for Spec {
     }
 }
 
+/// EBU R128 loudness measurements of a file
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Loudness {
+    /// LUFS
+    pub integrated_loudness: f32,
+    /// LU
+    pub loudness_range: f32,
+    /// dBTP
+    pub true_peak: f32,
+}
+
+/// Mean spectral descriptors of a file
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SpectralSummary {
+    /// Hz
+    pub centroid: f32,
+    /// Between 0 (tonal) and 1 (noise)
+    pub flatness: f32,
+    /// Hz
+    pub rolloff: f32,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct AudioMetadata {
     pub path: String,
@@ -92,6 +114,10 @@ pub struct AudioMetadata {
     pub duration_samples: u32,
     pub duration_seconds: f32,
     pub spec: Spec,
+    /// Filled-in once the file has been read
+    pub loudness: Option<Loudness>,
+    /// Filled-in once the file has been read
+    pub spectral: Option<SpectralSummary>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -101,6 +127,8 @@ pub struct AudioSimilarityResult {
     pub cross_correlation_similarity: f32,
     pub spectral_similarity: f32,
     pub delta_magnitude: f32,
+    /// Distance between mean MFCCs, 0 means the same timbre
+    pub mfcc_distance: f32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -97,6 +97,12 @@ table td img {
                       <th>Sample rate</th>
                       <th>Channels</th>
                       <th>Bits per sample</th>
+                      <th>Integrated loudness</th>
+                      <th>Loudness range</th>
+                      <th>True peak</th>
+                      <th>Spectral centroid</th>
+                      <th>Spectral flatness</th>
+                      <th>Spectral rolloff</th>
                       <th>Image</th>
                  </tr>
                 </thead>
@@ -108,6 +114,24 @@ table td img {
                             <td>{{ metadata.spec.sample_rate }}Hz</td>
                             <td>{{ metadata.spec.channels }}</td>
                             <td>{{ metadata.spec.bits_per_sample }}</td>
+                            {% if metadata.loudness %}
+                            <td>{{ metadata.loudness.integrated_loudness }} LUFS</td>
+                            <td>{{ metadata.loudness.loudness_range }} LU</td>
+                            <td>{{ metadata.loudness.true_peak }} dBTP</td>
+                            {% else %}
+                            <td></td>
+                            <td></td>
+                            <td></td>
+                            {% endif %}
+                            {% if metadata.spectral %}
+                            <td>{{ metadata.spectral.centroid | round(precision=1) }}Hz</td>
+                            <td>{{ metadata.spectral.flatness | round(precision=4) }}</td>
+                            <td>{{ metadata.spectral.rolloff | round(precision=1) }}Hz</td>
+                            {% else %}
+                            <td></td>
+                            <td></td>
+                            <td></td>
+                            {% endif %}
                             <td>
                                 <img src="/images/{{ metadata.filename }}--audio.png" />
                             </td>
@@ -125,6 +149,7 @@ table td img {
                     <th>Cross-correlation Similarity</th>
                     <th>Spectral similarity</th>
                     <th>Delta magnitude</th>
+                    <th>MFCC distance</th>
                 </tr>
             </thead>
             <tbody>
@@ -135,6 +160,7 @@ table td img {
                         <td>{{ similarity.cross_correlation_similarity | round(precision=4) }}</td>
                         <td>{{ similarity.spectral_similarity | round(precision=4) }}</td>
                         <td>{{ similarity.delta_magnitude | round(precision=4) }}</td>
+                        <td>{{ similarity.mfcc_distance | round(precision=4) }}</td>
                     </tr>
                 {% endfor %}
             </tbody>
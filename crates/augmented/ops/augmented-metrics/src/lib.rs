@@ -24,9 +24,12 @@
 //!
 //! * [`audio_processor_metrics`] exposes structs/functions for tracking CPU usage on the
 //!   audio-thread
+//! * [`profiling`] times individual processors with percentiles, detects xruns & exports Chrome
+//!   traces
 use std::time::Instant;
 
 pub mod audio_processor_metrics;
+pub mod profiling;
 
 /// Log duration of a function
 pub fn time<T>(label: &str, body: impl FnOnce() -> T) -> T {
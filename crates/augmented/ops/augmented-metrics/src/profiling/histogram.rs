@@ -0,0 +1,213 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Number of buckets per power of two
+const SUB_BUCKETS: usize = 4;
+const SUB_BUCKET_BITS: u32 = 2;
+/// Durations up to 2^40ns (~18 minutes) are bucketed, longer durations go on the last bucket
+const MAX_EXPONENT: usize = 40;
+const NUM_BUCKETS: usize = MAX_EXPONENT * SUB_BUCKETS;
+
+/// Histogram of durations which may be recorded from the audio-thread.
+///
+/// Buckets are logarithmic, with 4 buckets per power of two, so percentiles are within 25% of the
+/// recorded values. Recording is a few relaxed atomic operations & never allocates.
+pub struct DurationHistogram {
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl Default for DurationHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0..NUM_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+impl DurationHistogram {
+    pub fn record(&self, duration: Duration) {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        self.buckets[bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn mean(&self) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed) / count)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Upper bound of the bucket containing the `percentile` (0-100) of recorded durations
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+
+        let target = ((percentile / 100.0).clamp(0.0, 1.0) * count as f64)
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= target {
+                return Duration::from_nanos(bucket_upper_bound(index).min(self.max_nanos()));
+            }
+        }
+        self.max()
+    }
+
+    pub fn stats(&self) -> TimingStats {
+        TimingStats {
+            count: self.count(),
+            mean: self.mean(),
+            max: self.max(),
+            p50: self.percentile(50.0),
+            p90: self.percentile(90.0),
+            p99: self.percentile(99.0),
+        }
+    }
+
+    /// Clear all measurements. Measurements recorded concurrently may be partially cleared.
+    pub fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.sum_nanos.store(0, Ordering::Relaxed);
+        self.max_nanos.store(0, Ordering::Relaxed);
+    }
+
+    fn max_nanos(&self) -> u64 {
+        self.max_nanos.load(Ordering::Relaxed)
+    }
+}
+
+/// Summary of a [`DurationHistogram`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TimingStats {
+    pub count: u64,
+    pub mean: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let exponent = (63 - nanos.leading_zeros()) as usize;
+    if exponent > MAX_EXPONENT {
+        return NUM_BUCKETS - 1;
+    }
+    let sub_bucket = ((nanos >> (exponent as u32 - SUB_BUCKET_BITS)) as usize) & (SUB_BUCKETS - 1);
+    // Values below SUB_BUCKETS have their own buckets, so exponents start at 2 from index 4
+    (exponent - 1) * SUB_BUCKETS + sub_bucket
+}
+
+/// Largest value which falls into a bucket
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let exponent = (index / SUB_BUCKETS + 1) as u32;
+    let sub_bucket = (index % SUB_BUCKETS) as u64;
+    let lower_bound = (SUB_BUCKETS as u64 + sub_bucket) << (exponent - SUB_BUCKET_BITS);
+    lower_bound + (1 << (exponent - SUB_BUCKET_BITS)) - 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bucket_bounds_contain_values() {
+        for nanos in (0..100_000).chain([1 << 30, (1 << 40) - 1]) {
+            let index = bucket_index(nanos);
+            assert!(bucket_upper_bound(index) >= nanos, "{}", nanos);
+            if index > 0 {
+                assert!(bucket_upper_bound(index - 1) < nanos, "{}", nanos);
+            }
+        }
+        assert_eq!(bucket_index(u64::MAX), NUM_BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_empty_histogram() {
+        let histogram = DurationHistogram::default();
+        assert_eq!(histogram.stats(), TimingStats::default());
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let histogram = DurationHistogram::default();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        let stats = histogram.stats();
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.max, Duration::from_micros(100));
+        assert_eq!(stats.mean, Duration::from_nanos(50_500));
+
+        let within = |value: Duration, expected: u64| {
+            let expected = Duration::from_micros(expected);
+            assert!(value >= expected && value.as_secs_f64() <= expected.as_secs_f64() * 1.25);
+        };
+        within(stats.p50, 50);
+        within(stats.p90, 90);
+        within(stats.p99, 99);
+    }
+
+    #[test]
+    fn test_reset() {
+        let histogram = DurationHistogram::default();
+        histogram.record(Duration::from_millis(1));
+        histogram.reset();
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.percentile(50.0), Duration::ZERO);
+    }
+}
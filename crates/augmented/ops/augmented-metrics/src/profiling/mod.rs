@@ -0,0 +1,370 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Per-processor timing, xrun detection & tracing.
+//!
+//! [`AudioProcessorProfilerHandle`] is shared between the audio-thread & the UI. Processors are
+//! registered from a non real-time thread with
+//! [`AudioProcessorProfilerHandle::register_processor`], which returns a
+//! [`ProcessorTimingHandle`] that is then timed on the audio-thread. Each timing has a
+//! [`DurationHistogram`] from which percentiles can be read.
+//!
+//! Whole blocks are timed with [`AudioProcessorProfilerHandle::record_block`]. Blocks which take
+//! longer than the time available for their size at the current sample-rate are counted as
+//! xruns & published as [`XrunEvent`]s.
+//!
+//! While tracing is enabled, every measurement is also pushed onto a queue which
+//! [`TraceCollector`] drains & writes as a Chrome trace, which can be opened on
+//! `chrome://tracing` or <https://ui.perfetto.dev>.
+//!
+//! ```rust
+//! use std::time::Instant;
+//! use augmented_audio_metrics::profiling::AudioProcessorProfilerHandle;
+//!
+//! let profiler = AudioProcessorProfilerHandle::default();
+//! let timing = profiler.register_processor("Gain");
+//!
+//! // On the audio-thread
+//! let block_start = Instant::now();
+//! let start = Instant::now();
+//! // process gain
+//! profiler.record_processor(&timing, start, Instant::now());
+//! profiler.record_block(block_start, Instant::now(), 512);
+//!
+//! // On the UI
+//! let stats = timing.histogram().stats();
+//! println!("Gain p99={:?} xruns={}", stats.p99, profiler.xrun_count());
+//! ```
+
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use atomic_queue::Queue;
+use audio_garbage_collector::{make_shared, make_shared_cell, Shared, SharedCell};
+use audio_processor_traits::AudioProcessorSettings;
+use augmented_atomics::AtomicF32;
+
+pub use histogram::{DurationHistogram, TimingStats};
+pub use trace::TraceCollector;
+
+mod histogram;
+mod trace;
+
+/// Number of trace events which may be queued before they're dropped
+const TRACE_CAPACITY: usize = 16384;
+/// Number of xrun events which may be queued before they're dropped
+const XRUN_CAPACITY: usize = 128;
+
+/// Timing of a single processor
+pub struct ProcessorTimingHandle {
+    id: usize,
+    name: String,
+    histogram: DurationHistogram,
+    last_nanos: AtomicU64,
+}
+
+impl ProcessorTimingHandle {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn histogram(&self) -> &DurationHistogram {
+        &self.histogram
+    }
+
+    pub fn last_duration(&self) -> Duration {
+        Duration::from_nanos(self.last_nanos.load(Ordering::Relaxed))
+    }
+
+    fn record(&self, duration: Duration) {
+        self.histogram.record(duration);
+        self.last_nanos
+            .store(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// A block which took longer to process than the time available
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XrunEvent {
+    /// Time since the profiler was created
+    pub time: Duration,
+    /// Time the block took to process
+    pub duration: Duration,
+    /// Time available to process the block
+    pub budget: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceEventKind {
+    /// A whole block
+    Block,
+    /// A processor, identified by [`ProcessorTimingHandle::id`]
+    Processor(usize),
+    Xrun,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceEvent {
+    pub kind: TraceEventKind,
+    /// Time since the profiler was created
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+pub struct AudioProcessorProfilerHandle {
+    start: Instant,
+    sample_rate: AtomicF32,
+    block: DurationHistogram,
+    xrun_count: AtomicU64,
+    xruns: Queue<XrunEvent>,
+    processors: SharedCell<Vec<Shared<ProcessorTimingHandle>>>,
+    next_id: AtomicUsize,
+    is_tracing: AtomicBool,
+    trace: Queue<TraceEvent>,
+    dropped_trace_events: AtomicU64,
+}
+
+impl Default for AudioProcessorProfilerHandle {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            sample_rate: AtomicF32::new(0.0),
+            block: DurationHistogram::default(),
+            xrun_count: AtomicU64::new(0),
+            xruns: Queue::new(XRUN_CAPACITY),
+            processors: make_shared_cell(Vec::new()),
+            next_id: AtomicUsize::new(0),
+            is_tracing: AtomicBool::new(false),
+            trace: Queue::new(TRACE_CAPACITY),
+            dropped_trace_events: AtomicU64::new(0),
+        }
+    }
+}
+
+impl AudioProcessorProfilerHandle {
+    pub fn prepare(&self, settings: AudioProcessorSettings) {
+        self.sample_rate.set(settings.sample_rate());
+    }
+
+    /// Register a processor to be timed. Allocates, so this shouldn't be called on the
+    /// audio-thread.
+    pub fn register_processor(&self, name: &str) -> Shared<ProcessorTimingHandle> {
+        let timing = make_shared(ProcessorTimingHandle {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            name: name.to_string(),
+            histogram: DurationHistogram::default(),
+            last_nanos: AtomicU64::new(0),
+        });
+        let mut processors = self.processors.get().deref().clone();
+        processors.push(timing.clone());
+        self.processors.set(make_shared(processors));
+        timing
+    }
+
+    /// Stop timing a processor, so it's no longer listed in [`Self::processors`]. Allocates, so
+    /// this shouldn't be called on the audio-thread.
+    pub fn unregister_processor(&self, id: usize) {
+        let mut processors = self.processors.get().deref().clone();
+        processors.retain(|timing| timing.id() != id);
+        self.processors.set(make_shared(processors));
+    }
+
+    /// Registered processors, in order of registration
+    pub fn processors(&self) -> Vec<Shared<ProcessorTimingHandle>> {
+        self.processors.get().deref().clone()
+    }
+
+    /// Timing of whole blocks
+    pub fn block_histogram(&self) -> &DurationHistogram {
+        &self.block
+    }
+
+    pub fn xrun_count(&self) -> u64 {
+        self.xrun_count.load(Ordering::Relaxed)
+    }
+
+    /// Pop the oldest xrun which wasn't popped yet. Only the last few xruns are kept.
+    pub fn pop_xrun(&self) -> Option<XrunEvent> {
+        self.xruns.pop()
+    }
+
+    /// Start or stop pushing trace events. Events are dropped unless a [`TraceCollector`] is
+    /// draining them.
+    pub fn set_tracing(&self, is_tracing: bool) {
+        self.is_tracing.store(is_tracing, Ordering::Relaxed);
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.is_tracing.load(Ordering::Relaxed)
+    }
+
+    /// Number of trace events dropped because the queue was full
+    pub fn dropped_trace_events(&self) -> u64 {
+        self.dropped_trace_events.load(Ordering::Relaxed)
+    }
+
+    /// Clear histograms & the xrun count
+    pub fn reset(&self) {
+        self.block.reset();
+        self.xrun_count.store(0, Ordering::Relaxed);
+        while self.xruns.pop().is_some() {}
+        for processor in self.processors.get().iter() {
+            processor.histogram.reset();
+        }
+    }
+
+    /// Record the time a processor took. Real-time safe.
+    pub fn record_processor(&self, timing: &ProcessorTimingHandle, start: Instant, end: Instant) {
+        let duration = end.saturating_duration_since(start);
+        timing.record(duration);
+        self.push_trace_event(TraceEventKind::Processor(timing.id), start, duration);
+    }
+
+    /// Record the time a block of `num_samples` took & check whether it missed its deadline.
+    /// Real-time safe.
+    pub fn record_block(&self, start: Instant, end: Instant, num_samples: usize) {
+        let duration = end.saturating_duration_since(start);
+        self.block.record(duration);
+        self.push_trace_event(TraceEventKind::Block, start, duration);
+
+        let sample_rate = self.sample_rate.get();
+        if sample_rate <= 0.0 {
+            return;
+        }
+        let budget = Duration::from_secs_f64(num_samples as f64 / sample_rate as f64);
+        if duration > budget {
+            self.xrun_count.fetch_add(1, Ordering::Relaxed);
+            let _ = self.xruns.push(XrunEvent {
+                time: start.saturating_duration_since(self.start),
+                duration,
+                budget,
+            });
+            self.push_trace_event(TraceEventKind::Xrun, start, duration);
+        }
+    }
+
+    fn push_trace_event(&self, kind: TraceEventKind, start: Instant, duration: Duration) {
+        if !self.is_tracing() {
+            return;
+        }
+        let event = TraceEvent {
+            kind,
+            start: start.saturating_duration_since(self.start),
+            duration,
+        };
+        if !self.trace.push(event) {
+            self.dropped_trace_events.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn pop_trace_event(&self) -> Option<TraceEvent> {
+        self.trace.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_profiler(sample_rate: f32) -> AudioProcessorProfilerHandle {
+        let profiler = AudioProcessorProfilerHandle::default();
+        profiler.prepare(AudioProcessorSettings {
+            sample_rate,
+            ..AudioProcessorSettings::default()
+        });
+        profiler
+    }
+
+    #[test]
+    fn test_register_and_record_processors() {
+        let profiler = make_profiler(44100.0);
+        let gain = profiler.register_processor("Gain");
+        let pan = profiler.register_processor("Pan");
+        assert_eq!((gain.id(), pan.id()), (0, 1));
+
+        let start = Instant::now();
+        profiler.record_processor(&gain, start, start + Duration::from_micros(10));
+        profiler.record_processor(&gain, start, start + Duration::from_micros(20));
+
+        assert_eq!(gain.histogram().count(), 2);
+        assert_eq!(gain.last_duration(), Duration::from_micros(20));
+        assert_eq!(pan.histogram().count(), 0);
+
+        let names: Vec<String> = profiler
+            .processors()
+            .iter()
+            .map(|processor| processor.name().to_string())
+            .collect();
+        assert_eq!(names, vec!["Gain", "Pan"]);
+
+        profiler.unregister_processor(gain.id());
+        let ids: Vec<usize> = profiler
+            .processors()
+            .iter()
+            .map(|processor| processor.id())
+            .collect();
+        assert_eq!(ids, vec![pan.id()]);
+    }
+
+    #[test]
+    fn test_xruns_are_detected() {
+        // 100 samples at 1kHz gives a 100ms budget
+        let profiler = make_profiler(1000.0);
+        let start = Instant::now();
+        profiler.record_block(start, start + Duration::from_millis(50), 100);
+        assert_eq!(profiler.xrun_count(), 0);
+
+        profiler.record_block(start, start + Duration::from_millis(150), 100);
+        assert_eq!(profiler.xrun_count(), 1);
+        let xrun = profiler.pop_xrun().unwrap();
+        assert_eq!(xrun.duration, Duration::from_millis(150));
+        assert_eq!(xrun.budget, Duration::from_millis(100));
+        assert!(profiler.pop_xrun().is_none());
+        assert_eq!(profiler.block_histogram().count(), 2);
+
+        profiler.reset();
+        assert_eq!(profiler.xrun_count(), 0);
+        assert_eq!(profiler.block_histogram().count(), 0);
+    }
+
+    #[test]
+    fn test_trace_events_are_only_pushed_while_tracing() {
+        let profiler = make_profiler(44100.0);
+        let timing = profiler.register_processor("Gain");
+        let start = Instant::now();
+
+        profiler.record_processor(&timing, start, start);
+        assert!(profiler.pop_trace_event().is_none());
+
+        profiler.set_tracing(true);
+        profiler.record_processor(&timing, start, start + Duration::from_micros(5));
+        let event = profiler.pop_trace_event().unwrap();
+        assert_eq!(event.kind, TraceEventKind::Processor(0));
+        assert_eq!(event.duration, Duration::from_micros(5));
+    }
+}
@@ -0,0 +1,188 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use audio_garbage_collector::Shared;
+
+use super::{AudioProcessorProfilerHandle, TraceEvent, TraceEventKind};
+
+/// Drains trace events from an [`AudioProcessorProfilerHandle`] on a non real-time thread &
+/// writes them in the Chrome trace event format.
+///
+/// [`TraceCollector::poll`] should be called often enough for the profiler's queue not to fill up.
+pub struct TraceCollector {
+    handle: Shared<AudioProcessorProfilerHandle>,
+    events: Vec<TraceEvent>,
+}
+
+impl TraceCollector {
+    /// Create a collector & start tracing
+    pub fn new(handle: Shared<AudioProcessorProfilerHandle>) -> Self {
+        handle.set_tracing(true);
+        Self {
+            handle,
+            events: Vec::new(),
+        }
+    }
+
+    /// Move queued events onto the collector
+    pub fn poll(&mut self) {
+        while let Some(event) = self.handle.pop_trace_event() {
+            self.events.push(event);
+        }
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// Stop tracing, collecting any remaining events
+    pub fn stop(&mut self) {
+        self.handle.set_tracing(false);
+        self.poll();
+    }
+
+    /// Write collected events as a Chrome trace JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_chrome_trace(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Write collected events in the Chrome trace event format. Blocks & processors are
+    /// "complete" events, xruns are instant events.
+    pub fn write_chrome_trace(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let names: HashMap<usize, String> = self
+            .handle
+            .processors()
+            .iter()
+            .map(|processor| (processor.id(), processor.name().to_string()))
+            .collect();
+
+        writeln!(writer, "{{\"traceEvents\":[")?;
+        for (index, event) in self.events.iter().enumerate() {
+            let separator = if index + 1 < self.events.len() {
+                ","
+            } else {
+                ""
+            };
+            let ts = event.start.as_secs_f64() * 1_000_000.0;
+            let dur = event.duration.as_secs_f64() * 1_000_000.0;
+            match event.kind {
+                TraceEventKind::Block => writeln!(
+                    writer,
+                    r#"{{"name":"process","cat":"block","ph":"X","ts":{:.3},"dur":{:.3},"pid":0,"tid":0}}{}"#,
+                    ts, dur, separator
+                )?,
+                TraceEventKind::Processor(id) => {
+                    let name = names
+                        .get(&id)
+                        .map(|name| name.as_str())
+                        .unwrap_or("unknown");
+                    writeln!(
+                        writer,
+                        r#"{{"name":"{}","cat":"processor","ph":"X","ts":{:.3},"dur":{:.3},"pid":0,"tid":0,"args":{{"id":{}}}}}{}"#,
+                        escape_json(name),
+                        ts,
+                        dur,
+                        id,
+                        separator
+                    )?
+                }
+                TraceEventKind::Xrun => writeln!(
+                    writer,
+                    r#"{{"name":"xrun","cat":"xrun","ph":"i","s":"g","ts":{:.3},"pid":0,"tid":0,"args":{{"duration_us":{:.3}}}}}{}"#,
+                    ts, dur, separator
+                )?,
+            }
+        }
+        writeln!(writer, "],\"displayTimeUnit\":\"ns\"}}")?;
+        Ok(())
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use audio_garbage_collector::make_shared;
+    use audio_processor_traits::AudioProcessorSettings;
+
+    use super::*;
+
+    #[test]
+    fn test_escape_json() {
+        assert_eq!(escape_json(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+        assert_eq!(escape_json("\n"), "\\u000a");
+    }
+
+    #[test]
+    fn test_write_chrome_trace() {
+        let handle = make_shared(AudioProcessorProfilerHandle::default());
+        handle.prepare(AudioProcessorSettings {
+            sample_rate: 1000.0,
+            ..AudioProcessorSettings::default()
+        });
+        let timing = handle.register_processor("Delay \"1\"");
+
+        let mut collector = TraceCollector::new(handle.clone());
+        let start = Instant::now();
+        handle.record_processor(&timing, start, start + Duration::from_micros(5));
+        // 1 sample at 1kHz has a 1ms budget
+        handle.record_block(start, start + Duration::from_millis(2), 1);
+        collector.stop();
+        handle.record_block(start, start, 1);
+        collector.poll();
+        assert_eq!(collector.events().len(), 3);
+
+        let mut output = Vec::new();
+        collector.write_chrome_trace(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("{\"traceEvents\":["));
+        assert!(output.contains(r#""name":"Delay \"1\"","cat":"processor","ph":"X""#));
+        assert!(output.contains(r#""dur":5.000"#));
+        assert!(output.contains(r#""name":"process","cat":"block""#));
+        assert!(output.contains(r#""name":"xrun","cat":"xrun","ph":"i""#));
+        assert!(output.trim_end().ends_with("],\"displayTimeUnit\":\"ns\"}"));
+        // The last event has no trailing comma
+        assert!(!output.contains("},\n]"));
+    }
+}
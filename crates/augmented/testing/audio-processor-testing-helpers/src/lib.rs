@@ -29,12 +29,14 @@
 //! * Calculating RMS - `rms_level`
 //! * Generating sine buffers - `sine_buffer`
 //! * Generating frequency response charts - `charts`
+//! * Failing tests on allocations, locks or blocking calls in `process` - `realtime`
 
 pub use generators::*;
 pub use util::rms_level;
 
 pub mod charts;
 mod generators;
+pub mod realtime;
 mod util;
 
 /// Compare two floats are equal using `f32::EPSILON`
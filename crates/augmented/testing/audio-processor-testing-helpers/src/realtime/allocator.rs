@@ -0,0 +1,85 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{report, ViolationKind};
+
+static IS_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true once the [`RealtimeAllocator`] has served an allocation.
+pub(crate) fn is_installed() -> bool {
+    IS_INSTALLED.load(Ordering::Relaxed)
+}
+
+/// A global allocator which forwards to the system allocator and reports allocations and
+/// de-allocations made inside real-time sections.
+///
+/// Install it with [`install_realtime_allocator!`](crate::install_realtime_allocator).
+pub struct RealtimeAllocator;
+
+unsafe impl GlobalAlloc for RealtimeAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        IS_INSTALLED.store(true, Ordering::Relaxed);
+        report(ViolationKind::Allocation {
+            size: layout.size(),
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        report(ViolationKind::Deallocation {
+            size: layout.size(),
+        });
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        IS_INSTALLED.store(true, Ordering::Relaxed);
+        report(ViolationKind::Allocation {
+            size: layout.size(),
+        });
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        report(ViolationKind::Reallocation { size: new_size });
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Install the [`RealtimeAllocator`] as the global allocator, along with the libc hooks when the
+/// `realtime-hooks` feature is enabled.
+///
+/// Call this once in a test crate, usually from a `#[cfg(test)]` module.
+#[macro_export]
+macro_rules! install_realtime_allocator {
+    () => {
+        #[global_allocator]
+        static REALTIME_ALLOCATOR: $crate::realtime::RealtimeAllocator =
+            $crate::realtime::RealtimeAllocator;
+
+        $crate::__install_realtime_hooks!();
+    };
+}
@@ -0,0 +1,66 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// `std::sync` locks, condition variables and thread parking wait on futexes through the variadic
+// `syscall`, which can't be defined in stable Rust, so it's interposed here.
+//
+// The callbacks are defined in the test executable by `install_realtime_allocator!`, which also
+// references `audio_processor_testing_helpers_futex_hook` so this object gets linked.
+
+#define _GNU_SOURCE
+#include <linux/futex.h>
+#include <stdarg.h>
+#include <sys/syscall.h>
+
+typedef long (*syscall_fn)(long, ...);
+
+extern void audio_processor_testing_helpers_report_futex_wait(void);
+extern syscall_fn audio_processor_testing_helpers_resolve_syscall(void);
+
+void audio_processor_testing_helpers_futex_hook(void) {}
+
+static int is_futex_wait(long op) {
+  long command = op & ~(long)(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+  return command == FUTEX_WAIT || command == FUTEX_WAIT_BITSET ||
+         command == FUTEX_LOCK_PI;
+}
+
+long syscall(long number, ...) {
+  // Like glibc, always forward six arguments; extra ones are ignored by the kernel
+  va_list args;
+  va_start(args, number);
+  long a1 = va_arg(args, long);
+  long a2 = va_arg(args, long);
+  long a3 = va_arg(args, long);
+  long a4 = va_arg(args, long);
+  long a5 = va_arg(args, long);
+  long a6 = va_arg(args, long);
+  va_end(args);
+
+  if (number == SYS_futex && is_futex_wait(a2)) {
+    audio_processor_testing_helpers_report_futex_wait();
+  }
+
+  syscall_fn real = audio_processor_testing_helpers_resolve_syscall();
+  return real(number, a1, a2, a3, a4, a5, a6);
+}
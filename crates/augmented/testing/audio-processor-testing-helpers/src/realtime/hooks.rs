@@ -0,0 +1,165 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Interposes libc functions which lock or block, so calls made from Rust code inside a
+//! real-time section are reported.
+//!
+//! The hooks are defined in the test executable by
+//! [`install_realtime_allocator!`](crate::install_realtime_allocator), so only crates which run
+//! the harness and enable the `realtime-hooks` feature on their dev-dependency get them.
+//! Definitions in the executable take precedence over the ones in the dynamically linked libc,
+//! so every call made from Rust code (including `std`) goes through these functions, which
+//! report the violation and forward to the real implementation found with `dlsym(RTLD_NEXT)`.
+//! Calls made from inside libc itself aren't seen.
+//!
+//! `std::sync` primitives wait on futexes through the variadic `syscall`, which can't be defined
+//! in stable Rust, so it's interposed by a C shim (`futex_hook.c`) that reports futex waits
+//! through a callback defined here.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub use libc;
+use libc::c_char;
+
+use super::{permit_non_realtime, report, ViolationKind};
+
+/// Find the next definition of `name`, which must be nul terminated, caching it in `cache`.
+///
+/// # Safety
+/// `name` must be the name of a libc function.
+pub unsafe fn resolve(cache: &AtomicUsize, name: &'static str) -> usize {
+    let address = cache.load(Ordering::Relaxed);
+    if address != 0 {
+        return address;
+    }
+
+    // dlsym may allocate
+    let address = permit_non_realtime(|| {
+        libc::dlsym(libc::RTLD_NEXT, name.as_ptr() as *const c_char) as usize
+    });
+    if address == 0 {
+        libc::abort();
+    }
+    cache.store(address, Ordering::Relaxed);
+    address
+}
+
+/// Record a violation made through a hook
+pub fn report_hook(kind: ViolationKind) {
+    report(kind);
+}
+
+extern "C" {
+    /// Defined in the same object as the `syscall` shim, referencing it links the shim.
+    pub fn audio_processor_testing_helpers_futex_hook();
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __realtime_hook {
+    ($kind:ident, $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty) => {
+        /// # Safety
+        /// Forwards to the libc function of the same name.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name($($arg: $ty),*) -> $ret {
+            static REAL: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+            $crate::realtime::hooks::report_hook($crate::realtime::ViolationKind::$kind {
+                function: stringify!($name),
+            });
+            let real: unsafe extern "C" fn($($ty),*) -> $ret = ::std::mem::transmute(
+                $crate::realtime::hooks::resolve(&REAL, concat!(stringify!($name), "\0")),
+            );
+            real($($arg),*)
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __install_realtime_hooks {
+    () => {
+        $crate::__realtime_hook!(Lock, pthread_mutex_lock(
+            mutex: *mut $crate::realtime::hooks::libc::pthread_mutex_t
+        ) -> $crate::realtime::hooks::libc::c_int);
+        $crate::__realtime_hook!(Lock, pthread_rwlock_rdlock(
+            lock: *mut $crate::realtime::hooks::libc::pthread_rwlock_t
+        ) -> $crate::realtime::hooks::libc::c_int);
+        $crate::__realtime_hook!(Lock, pthread_rwlock_wrlock(
+            lock: *mut $crate::realtime::hooks::libc::pthread_rwlock_t
+        ) -> $crate::realtime::hooks::libc::c_int);
+        $crate::__realtime_hook!(Lock, pthread_cond_wait(
+            cond: *mut $crate::realtime::hooks::libc::pthread_cond_t,
+            mutex: *mut $crate::realtime::hooks::libc::pthread_mutex_t
+        ) -> $crate::realtime::hooks::libc::c_int);
+        $crate::__realtime_hook!(Lock, pthread_cond_timedwait(
+            cond: *mut $crate::realtime::hooks::libc::pthread_cond_t,
+            mutex: *mut $crate::realtime::hooks::libc::pthread_mutex_t,
+            time: *const $crate::realtime::hooks::libc::timespec
+        ) -> $crate::realtime::hooks::libc::c_int);
+        $crate::__realtime_hook!(BlockingCall, nanosleep(
+            request: *const $crate::realtime::hooks::libc::timespec,
+            remaining: *mut $crate::realtime::hooks::libc::timespec
+        ) -> $crate::realtime::hooks::libc::c_int);
+        $crate::__realtime_hook!(BlockingCall, clock_nanosleep(
+            clock: $crate::realtime::hooks::libc::clockid_t,
+            flags: $crate::realtime::hooks::libc::c_int,
+            request: *const $crate::realtime::hooks::libc::timespec,
+            remaining: *mut $crate::realtime::hooks::libc::timespec
+        ) -> $crate::realtime::hooks::libc::c_int);
+        $crate::__realtime_hook!(BlockingCall, read(
+            fd: $crate::realtime::hooks::libc::c_int,
+            buffer: *mut $crate::realtime::hooks::libc::c_void,
+            count: $crate::realtime::hooks::libc::size_t
+        ) -> $crate::realtime::hooks::libc::ssize_t);
+        $crate::__realtime_hook!(BlockingCall, write(
+            fd: $crate::realtime::hooks::libc::c_int,
+            buffer: *const $crate::realtime::hooks::libc::c_void,
+            count: $crate::realtime::hooks::libc::size_t
+        ) -> $crate::realtime::hooks::libc::ssize_t);
+        $crate::__realtime_hook!(BlockingCall, fsync(
+            fd: $crate::realtime::hooks::libc::c_int
+        ) -> $crate::realtime::hooks::libc::c_int);
+
+        /// Called by the `syscall` shim when a thread waits on a futex
+        #[no_mangle]
+        pub extern "C" fn audio_processor_testing_helpers_report_futex_wait() {
+            $crate::realtime::hooks::report_hook($crate::realtime::ViolationKind::Lock {
+                function: "futex",
+            });
+        }
+
+        /// Called by the `syscall` shim to find the libc implementation
+        ///
+        /// # Safety
+        /// Resolves a libc function.
+        #[no_mangle]
+        pub unsafe extern "C" fn audio_processor_testing_helpers_resolve_syscall() -> usize {
+            static REAL: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+            $crate::realtime::hooks::resolve(&REAL, "syscall\0")
+        }
+
+        #[used]
+        static REALTIME_FUTEX_HOOK: unsafe extern "C" fn() =
+            $crate::realtime::hooks::audio_processor_testing_helpers_futex_hook;
+    };
+}
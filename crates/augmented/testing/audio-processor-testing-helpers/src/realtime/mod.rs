@@ -0,0 +1,451 @@
+// Augmented Audio: Audio libraries and applications
+// Copyright (c) 2022 Pedro Tacla Yamada
+//
+// The MIT License (MIT)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Real-time safety checks for audio processors.
+//!
+//! Code running on the audio thread must not allocate, free memory, take locks or perform
+//! blocking system calls. This module lets tests fail when any of those happen inside a
+//! "real-time section", with a backtrace pointing at the offending call.
+//!
+//! * Heap allocation, re-allocation and de-allocation are detected by the
+//!   [`RealtimeAllocator`], which must be installed as the test binary's global allocator with
+//!   [`install_realtime_allocator!`](crate::install_realtime_allocator).
+//! * On Linux, when a test crate enables the `realtime-hooks` feature on its dev-dependency,
+//!   calls into `pthread` mutexes, read/write locks and condition variables, sleeps,
+//!   `read`/`write`/`fsync` and `futex` waits are also detected. Uncontended `std::sync::Mutex`
+//!   locks never reach the kernel, so they can only be caught when the lock is contended.
+//!
+//! Checking panics when the allocator isn't installed, on every build profile, so a test can't
+//! pass without being checked.
+//!
+//! ```ignore
+//! #[cfg(test)]
+//! audio_processor_testing_helpers::install_realtime_allocator!();
+//!
+//! #[test]
+//! fn test_processor_is_realtime_safe() {
+//!     let processor = MyProcessor::default();
+//!     audio_processor_testing_helpers::realtime::assert_processor_realtime_safe(
+//!         processor,
+//!         Default::default(),
+//!         10,
+//!     );
+//! }
+//! ```
+
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+use audio_processor_traits::{AudioBuffer, AudioContext, AudioProcessor, AudioProcessorSettings};
+
+pub use allocator::RealtimeAllocator;
+
+mod allocator;
+#[cfg(all(
+    feature = "realtime-hooks",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+#[doc(hidden)]
+pub mod hooks;
+
+#[cfg(not(all(
+    feature = "realtime-hooks",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __install_realtime_hooks {
+    () => {};
+}
+
+/// Only the first violations in a section capture a backtrace, the rest are only counted.
+const MAX_RECORDED_VIOLATIONS: usize = 32;
+
+thread_local! {
+    static IS_REALTIME: Cell<bool> = const { Cell::new(false) };
+    static VIOLATION_COUNT: Cell<usize> = const { Cell::new(0) };
+    static VIOLATIONS: RefCell<Vec<Violation>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The kind of non real-time safe operation that was detected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// Memory was allocated, with the requested size in bytes
+    Allocation { size: usize },
+    /// Memory was re-allocated, with the new size in bytes
+    Reallocation { size: usize },
+    /// Memory was freed, with the size of the freed block in bytes
+    Deallocation { size: usize },
+    /// A lock was taken through the named function
+    Lock { function: &'static str },
+    /// A potentially blocking system call was made through the named function
+    BlockingCall { function: &'static str },
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViolationKind::Allocation { size } => write!(f, "allocation of {} bytes", size),
+            ViolationKind::Reallocation { size } => write!(f, "re-allocation to {} bytes", size),
+            ViolationKind::Deallocation { size } => write!(f, "de-allocation of {} bytes", size),
+            ViolationKind::Lock { function } => write!(f, "lock taken with `{}`", function),
+            ViolationKind::BlockingCall { function } => {
+                write!(f, "blocking call to `{}`", function)
+            }
+        }
+    }
+}
+
+/// A non real-time safe operation and where it happened
+#[derive(Debug)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub backtrace: Backtrace,
+}
+
+/// All the violations found while running a real-time section
+#[derive(Debug)]
+pub struct RealtimeViolations {
+    /// The first violations found, with their backtraces
+    pub violations: Vec<Violation>,
+    /// The total number of violations, including the ones that weren't recorded
+    pub count: usize,
+}
+
+impl fmt::Display for RealtimeViolations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} real-time safety violation(s) found inside a real-time section",
+            self.count
+        )?;
+        for (index, violation) in self.violations.iter().enumerate() {
+            writeln!(
+                f,
+                "\n#{} {}:\n{}",
+                index, violation.kind, violation.backtrace
+            )?;
+        }
+        if self.count > self.violations.len() {
+            writeln!(f, "\n... and {} more", self.count - self.violations.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RealtimeViolations {}
+
+/// Returns true if the current thread is inside a real-time section.
+pub fn is_realtime() -> bool {
+    IS_REALTIME.with(|is_realtime| is_realtime.get())
+}
+
+/// Record a violation if the current thread is inside a real-time section.
+///
+/// Checks are disabled while recording so that capturing the backtrace can itself allocate.
+pub(crate) fn report(kind: ViolationKind) {
+    if !is_realtime() {
+        return;
+    }
+
+    permit_non_realtime(|| {
+        let count = VIOLATION_COUNT.with(|count| {
+            count.set(count.get() + 1);
+            count.get()
+        });
+        if count <= MAX_RECORDED_VIOLATIONS {
+            let violation = Violation {
+                kind,
+                backtrace: Backtrace::force_capture(),
+            };
+            VIOLATIONS.with(|violations| violations.borrow_mut().push(violation));
+        }
+    });
+}
+
+/// Sets the real-time flag for the current thread and restores it when dropped, so the flag is
+/// reset even if the section panics.
+struct RealtimeFlagGuard {
+    previous: bool,
+}
+
+impl RealtimeFlagGuard {
+    fn new(is_realtime: bool) -> Self {
+        let previous = IS_REALTIME.with(|flag| flag.replace(is_realtime));
+        Self { previous }
+    }
+}
+
+impl Drop for RealtimeFlagGuard {
+    fn drop(&mut self) {
+        IS_REALTIME.with(|flag| flag.set(self.previous));
+    }
+}
+
+/// Run `f` with real-time checks disabled, for example to log from inside a real-time section.
+pub fn permit_non_realtime<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = RealtimeFlagGuard::new(false);
+    f()
+}
+
+/// Run `f` as a real-time section, returning its result or the violations that happened while it
+/// ran on the current thread.
+///
+/// Panics if the [`RealtimeAllocator`] isn't installed, since allocations would go unnoticed.
+pub fn check_realtime_safe<R>(f: impl FnOnce() -> R) -> Result<R, RealtimeViolations> {
+    assert!(
+        allocator::is_installed(),
+        "RealtimeAllocator is not the global allocator, \
+         call `audio_processor_testing_helpers::install_realtime_allocator!()` in the test crate"
+    );
+
+    // Make sure the thread-local storage is initialized before entering the section
+    let previous_violations = VIOLATIONS.with(|violations| violations.take());
+    let previous_count = VIOLATION_COUNT.with(|count| count.replace(0));
+
+    let result = {
+        let _guard = RealtimeFlagGuard::new(true);
+        f()
+    };
+
+    let violations = VIOLATIONS.with(|violations| violations.replace(previous_violations));
+    let count = VIOLATION_COUNT.with(|count| count.replace(previous_count));
+
+    if count == 0 {
+        Ok(result)
+    } else {
+        Err(RealtimeViolations { violations, count })
+    }
+}
+
+/// Run `f` as a real-time section and panic with a report of every violation if it wasn't
+/// real-time safe.
+pub fn assert_realtime_safe<R>(f: impl FnOnce() -> R) -> R {
+    match check_realtime_safe(f) {
+        Ok(result) => result,
+        Err(violations) => panic!("{}", violations),
+    }
+}
+
+/// Wraps an [`AudioProcessor`] so that every `process` call is checked to be real-time safe.
+///
+/// `prepare` is allowed to allocate.
+pub struct RealtimeSafeProcessor<P> {
+    processor: P,
+}
+
+impl<P> RealtimeSafeProcessor<P> {
+    pub fn new(processor: P) -> Self {
+        Self { processor }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.processor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.processor
+    }
+
+    pub fn into_inner(self) -> P {
+        self.processor
+    }
+}
+
+impl<P: AudioProcessor> AudioProcessor for RealtimeSafeProcessor<P> {
+    type SampleType = P::SampleType;
+
+    fn prepare(&mut self, context: &mut AudioContext) {
+        self.processor.prepare(context);
+    }
+
+    fn process(&mut self, context: &mut AudioContext, data: &mut AudioBuffer<Self::SampleType>) {
+        let processor = &mut self.processor;
+        assert_realtime_safe(|| processor.process(context, data));
+    }
+}
+
+/// Prepare `processor` with `settings` and process `num_blocks` blocks of a 440Hz sine wave,
+/// panicking if any `process` call isn't real-time safe.
+pub fn assert_processor_realtime_safe<P>(
+    processor: P,
+    settings: AudioProcessorSettings,
+    num_blocks: usize,
+) -> P
+where
+    P: AudioProcessor<SampleType = f32>,
+{
+    let mut processor = RealtimeSafeProcessor::new(processor);
+    let mut context = AudioContext::from(settings);
+    processor.prepare(&mut context);
+
+    let num_channels = settings.input_channels.max(settings.output_channels);
+    let mut buffer = AudioBuffer::empty();
+    buffer.resize(num_channels, settings.block_size);
+    let mut phase = 0.0_f32;
+    let step = 440.0 * 2.0 * std::f32::consts::PI / settings.sample_rate;
+
+    for _ in 0..num_blocks {
+        for sample in 0..settings.block_size {
+            let value = phase.sin();
+            phase = (phase + step) % (2.0 * std::f32::consts::PI);
+            for channel in 0..num_channels {
+                buffer.set(channel, sample, value);
+            }
+        }
+        processor.process(&mut context, &mut buffer);
+    }
+
+    processor.into_inner()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    crate::install_realtime_allocator!();
+
+    struct GainProcessor;
+
+    impl AudioProcessor for GainProcessor {
+        type SampleType = f32;
+
+        fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+            for sample in data.slice_mut() {
+                *sample *= 0.5;
+            }
+        }
+    }
+
+    struct AllocatingProcessor;
+
+    impl AudioProcessor for AllocatingProcessor {
+        type SampleType = f32;
+
+        fn process(&mut self, _context: &mut AudioContext, data: &mut AudioBuffer<f32>) {
+            let copy = data.channel(0).to_vec();
+            data.channel_mut(0).copy_from_slice(&copy);
+        }
+    }
+
+    #[test]
+    fn test_check_realtime_safe_passes_for_safe_code() {
+        let mut values = [1.0, 2.0, 3.0];
+        let result = check_realtime_safe(|| {
+            values.iter_mut().for_each(|value| *value *= 2.0);
+            values.iter().sum::<f32>()
+        });
+        assert_eq!(result.unwrap(), 12.0);
+    }
+
+    #[test]
+    fn test_check_realtime_safe_reports_allocations() {
+        let result = check_realtime_safe(|| {
+            let v: Vec<u8> = Vec::with_capacity(10);
+            drop(v);
+        });
+        let violations = result.unwrap_err();
+        assert_eq!(violations.count, 2);
+        assert_eq!(
+            violations.violations[0].kind,
+            ViolationKind::Allocation { size: 10 }
+        );
+        assert_eq!(
+            violations.violations[1].kind,
+            ViolationKind::Deallocation { size: 10 }
+        );
+        assert!(violations.to_string().contains("allocation of 10 bytes"));
+    }
+
+    #[test]
+    fn test_check_realtime_safe_caps_recorded_violations() {
+        let result = check_realtime_safe(|| {
+            for _ in 0..100 {
+                drop(std::hint::black_box(Box::new(1)));
+            }
+        });
+        let violations = result.unwrap_err();
+        assert_eq!(violations.count, 200);
+        assert_eq!(violations.violations.len(), MAX_RECORDED_VIOLATIONS);
+    }
+
+    #[test]
+    fn test_permit_non_realtime() {
+        let result = check_realtime_safe(|| permit_non_realtime(|| vec![1, 2, 3].len()));
+        assert_eq!(result.unwrap(), 3);
+        assert!(!is_realtime());
+    }
+
+    #[test]
+    fn test_assert_processor_realtime_safe() {
+        assert_processor_realtime_safe(GainProcessor, Default::default(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "real-time safety violation")]
+    fn test_assert_processor_realtime_safe_panics_on_allocation() {
+        assert_processor_realtime_safe(AllocatingProcessor, Default::default(), 1);
+    }
+
+    #[cfg(all(
+        feature = "realtime-hooks",
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn test_check_realtime_safe_reports_blocking_calls() {
+        let result = check_realtime_safe(|| {
+            std::thread::sleep(std::time::Duration::from_micros(1));
+        });
+        let violations = result.unwrap_err();
+        assert!(violations
+            .violations
+            .iter()
+            .any(|violation| matches!(violation.kind, ViolationKind::BlockingCall { .. })));
+    }
+
+    #[cfg(all(
+        feature = "realtime-hooks",
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn test_check_realtime_safe_reports_condvar_waits() {
+        let mutex = std::sync::Mutex::new(());
+        let condvar = std::sync::Condvar::new();
+        let guard = mutex.lock().unwrap();
+        let result = check_realtime_safe(|| {
+            let (_guard, _) = condvar
+                .wait_timeout(guard, std::time::Duration::from_micros(1))
+                .unwrap();
+        });
+        let violations = result.unwrap_err();
+        assert!(violations
+            .violations
+            .iter()
+            .any(|violation| violation.kind == ViolationKind::Lock { function: "futex" }));
+    }
+}